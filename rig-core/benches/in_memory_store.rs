@@ -0,0 +1,123 @@
+//! Benchmark of the cosine search of the in-memory vector store, whose norms of the stored
+//! embeddings are computed at insert time, against a naive search recomputing them on every
+//! query, and of its selection of the top n documents against a full sort of the scores.
+//!
+//! Run with `cargo bench -p rig-core --bench in_memory_store`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rig::{
+    embeddings::{distance::DistanceMetric, Embedding, EmbeddingError, EmbeddingModel},
+    vector_store::{in_memory_store::InMemoryVectorStore, VectorStoreIndex},
+    OneOrMany,
+};
+
+const NDIMS: usize = 1536;
+const DOCUMENTS: usize = 5_000;
+
+/// Deterministic pseudo-random vector of the `seed`th document
+fn vector(seed: usize) -> Vec<f64> {
+    (0..NDIMS)
+        .map(|i| (((seed * NDIMS + i) * 2_654_435_761) % 1_000) as f64 / 1_000.0 - 0.5)
+        .collect()
+}
+
+/// Embedding model embedding every query as the same vector
+#[derive(Clone)]
+struct Model;
+
+impl EmbeddingModel for Model {
+    const MAX_DOCUMENTS: usize = 1;
+
+    fn ndims(&self) -> usize {
+        NDIMS
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        Ok(texts
+            .into_iter()
+            .map(|document| Embedding {
+                document,
+                vec: vector(DOCUMENTS),
+            })
+            .collect())
+    }
+}
+
+fn bench_top_n(c: &mut Criterion) {
+    let documents = (0..DOCUMENTS)
+        .map(|i| {
+            let embedding = Embedding {
+                document: format!("doc{i}"),
+                vec: vector(i),
+            };
+            (format!("doc{i}"), i, OneOrMany::one(embedding))
+        })
+        .collect::<Vec<_>>();
+    let index = InMemoryVectorStore::from_documents_with_ids(documents.clone()).index(Model);
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("cosine_top_10");
+
+    group.bench_function("cached_norms", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(index.top_n_ids(black_box("flurbo"), 10))
+                .unwrap()
+        })
+    });
+
+    group.bench_function("recomputed_norms", |b| {
+        let query = Embedding {
+            document: "flurbo".to_string(),
+            vec: vector(DOCUMENTS),
+        };
+        b.iter(|| {
+            let mut scores = documents
+                .iter()
+                .map(|(id, _, embeddings)| {
+                    let score = embeddings
+                        .iter()
+                        .map(|embedding| DistanceMetric::Cosine.similarity(&query, embedding))
+                        .fold(f64::MIN, f64::max);
+                    (score, id.clone())
+                })
+                .collect::<Vec<_>>();
+            scores.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+            scores.truncate(10);
+            black_box(scores)
+        })
+    });
+
+    group.finish();
+
+    // Bounded heap keeping the top n documents during the scan, against a full sort of the
+    // scores of every document
+    let mut group = c.benchmark_group("cosine_top_n_selection");
+    for n in [1, 10, 100] {
+        group.bench_function(format!("bounded_heap_{n}"), |b| {
+            b.iter(|| {
+                runtime
+                    .block_on(index.top_n_ids(black_box("flurbo"), n))
+                    .unwrap()
+            })
+        });
+
+        group.bench_function(format!("full_sort_{n}"), |b| {
+            b.iter(|| {
+                let mut scores = runtime
+                    .block_on(index.top_n_ids(black_box("flurbo"), DOCUMENTS))
+                    .unwrap();
+                scores.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+                scores.truncate(n);
+                black_box(scores)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_top_n);
+criterion_main!(benches);
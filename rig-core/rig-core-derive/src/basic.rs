@@ -1,8 +1,8 @@
 use syn::{parse_quote, Attribute, DataStruct, Meta};
 
-use crate::EMBED;
+use crate::{custom::CustomAttributeParser, EMBED};
 
-/// Finds and returns fields with simple `#[embed]` attribute tags only.
+/// Finds and returns fields with simple `#[embed]` (or `#[embed(weight = N)]`) attribute tags only.
 pub(crate) fn basic_embed_fields(data_struct: &DataStruct) -> impl Iterator<Item = &syn::Field> {
     data_struct.fields.iter().filter(|field| {
         field.attrs.iter().any(|attribute| match attribute {
@@ -10,6 +10,10 @@ pub(crate) fn basic_embed_fields(data_struct: &DataStruct) -> impl Iterator<Item
                 meta: Meta::Path(path),
                 ..
             } => path.is_ident(EMBED),
+            Attribute {
+                meta: Meta::List(list),
+                ..
+            } => list.path.is_ident(EMBED) && matches!(attribute.is_custom(), Ok(false)),
             _ => false,
         })
     })
@@ -4,6 +4,7 @@ use syn::{meta::ParseNestedMeta, ExprPath};
 use crate::EMBED;
 
 const EMBED_WITH: &str = "embed_with";
+const WEIGHT: &str = "weight";
 
 /// Finds and returns fields with #[embed(embed_with = "...")] attribute tags only.
 /// Also returns the "..." part of the tag (ie. the custom function).
@@ -30,7 +31,34 @@ pub(crate) fn custom_embed_fields(
         .collect::<Result<Vec<_>, _>>()
 }
 
-trait CustomAttributeParser {
+/// Returns the number of times the texts of a field tagged with `#[embed(weight = N)]` (or
+/// `#[embed(embed_with = "...", weight = N)]`) are embedded, 1 by default.
+pub(crate) fn embed_weight(field: &syn::Field) -> syn::Result<usize> {
+    let mut weight = 1;
+    for attribute in &field.attrs {
+        if !attribute.path().is_ident(EMBED) || !matches!(attribute.meta, syn::Meta::List(_)) {
+            continue;
+        }
+        attribute.parse_nested_meta(|meta| {
+            if !meta.path.is_ident(WEIGHT) {
+                meta.value()?.parse::<syn::Expr>()?;
+                return Ok(());
+            }
+            let lit = meta.value()?.parse::<syn::LitInt>()?;
+            weight = lit.base10_parse::<usize>()?;
+            if weight == 0 {
+                return Err(syn::Error::new_spanned(
+                    lit,
+                    format!("expected {} attribute to be at least 1", WEIGHT),
+                ));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(weight)
+}
+
+pub(crate) trait CustomAttributeParser {
     // Determine if field is tagged with an #[embed(embed_with = "...")] attribute.
     fn is_custom(&self) -> syn::Result<bool>;
 
@@ -56,11 +84,15 @@ impl CustomAttributeParser for syn::Attribute {
             return Ok(false);
         }
 
+        let mut custom = false;
         self.parse_nested_meta(|meta| {
             // Parse the meta attribute as an expression. Need this to compile.
             meta.value()?.parse::<syn::Expr>()?;
 
             if meta.path.is_ident(EMBED_WITH) {
+                custom = true;
+                Ok(())
+            } else if meta.path.is_ident(WEIGHT) {
                 Ok(())
             } else {
                 let path = meta.path.to_token_stream().to_string().replace(' ', "");
@@ -71,7 +103,7 @@ impl CustomAttributeParser for syn::Attribute {
             }
         })?;
 
-        Ok(true)
+        Ok(custom)
     }
 
     fn expand_tag(&self) -> syn::Result<syn::ExprPath> {
@@ -110,12 +142,18 @@ impl CustomAttributeParser for syn::Attribute {
 
         let mut custom_func_path = None;
 
-        self.parse_nested_meta(|meta| match function_path(&meta) {
-            Ok(path) => {
-                custom_func_path = Some(path);
-                Ok(())
+        self.parse_nested_meta(|meta| {
+            if !meta.path.is_ident(EMBED_WITH) {
+                meta.value()?.parse::<syn::Expr>()?;
+                return Ok(());
+            }
+            match function_path(&meta) {
+                Ok(path) => {
+                    custom_func_path = Some(path);
+                    Ok(())
+                }
+                Err(e) => Err(e),
             }
-            Err(e) => Err(e),
         })?;
 
         Ok(custom_func_path.unwrap())
@@ -4,7 +4,7 @@ use syn::DataStruct;
 
 use crate::{
     basic::{add_struct_bounds, basic_embed_fields},
-    custom::custom_embed_fields,
+    custom::{custom_embed_fields, embed_weight},
 };
 
 pub(crate) fn expand_derive_embedding(input: &mut syn::DeriveInput) -> syn::Result<TokenStream> {
@@ -14,7 +14,7 @@ pub(crate) fn expand_derive_embedding(input: &mut syn::DeriveInput) -> syn::Resu
 
     let target_stream = match data {
         syn::Data::Struct(data_struct) => {
-            let (basic_targets, basic_target_size) = data_struct.basic(generics);
+            let (basic_targets, basic_target_size) = data_struct.basic(generics)?;
             let (custom_targets, custom_target_size) = data_struct.custom()?;
 
             // If there are no fields tagged with `#[embed]` or `#[embed(embed_with = "...")]`, return an empty TokenStream.
@@ -58,14 +58,14 @@ pub(crate) fn expand_derive_embedding(input: &mut syn::DeriveInput) -> syn::Resu
 
 trait StructParser {
     // Handles fields tagged with `#[embed]`
-    fn basic(&self, generics: &mut syn::Generics) -> (TokenStream, usize);
+    fn basic(&self, generics: &mut syn::Generics) -> syn::Result<(TokenStream, usize)>;
 
     // Handles fields tagged with `#[embed(embed_with = "...")]`
     fn custom(&self) -> syn::Result<(TokenStream, usize)>;
 }
 
 impl StructParser for DataStruct {
-    fn basic(&self, generics: &mut syn::Generics) -> (TokenStream, usize) {
+    fn basic(&self, generics: &mut syn::Generics) -> syn::Result<(TokenStream, usize)> {
         let embed_targets = basic_embed_fields(self)
             // Iterate over every field tagged with `#[embed]`
             .map(|field| {
@@ -73,18 +73,21 @@ impl StructParser for DataStruct {
 
                 let field_name = &field.ident;
 
-                quote! {
-                    self.#field_name
-                }
+                Ok(repeat(
+                    quote! {
+                        self.#field_name.embed(embedder)?;
+                    },
+                    embed_weight(field)?,
+                ))
             })
-            .collect::<Vec<_>>();
+            .collect::<syn::Result<Vec<_>>>()?;
 
-        (
+        Ok((
             quote! {
-                #(#embed_targets.embed(embedder)?;)*
+                #(#embed_targets)*
             },
             embed_targets.len(),
-        )
+        ))
     }
 
     fn custom(&self) -> syn::Result<(TokenStream, usize)> {
@@ -94,11 +97,14 @@ impl StructParser for DataStruct {
             .map(|(field, custom_func_path)| {
                 let field_name = &field.ident;
 
-                quote! {
-                    #custom_func_path(embedder, self.#field_name.clone())?;
-                }
+                Ok(repeat(
+                    quote! {
+                        #custom_func_path(embedder, self.#field_name.clone())?;
+                    },
+                    embed_weight(field)?,
+                ))
             })
-            .collect::<Vec<_>>();
+            .collect::<syn::Result<Vec<_>>>()?;
 
         Ok((
             quote! {
@@ -108,3 +114,16 @@ impl StructParser for DataStruct {
         ))
     }
 }
+
+/// Repeats the embedding of a field `weight` times (for fields tagged with `weight = N`)
+fn repeat(embed_target: TokenStream, weight: usize) -> TokenStream {
+    if weight == 1 {
+        return embed_target;
+    }
+
+    quote! {
+        for _ in 0..#weight {
+            #embed_target
+        }
+    }
+}
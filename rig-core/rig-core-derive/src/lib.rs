@@ -1,13 +1,24 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
-use syn::{parse_macro_input, DeriveInput};
+use syn::{parse_macro_input, DeriveInput, ItemImpl};
 
 mod basic;
 mod custom;
 mod embed;
+mod tools;
 
 pub(crate) const EMBED: &str = "embed";
+pub(crate) const TOOL: &str = "tool";
 
+/// Implements `Embed` for a struct, embedding its fields tagged with `#[embed]` (which must
+/// implement `Embed`) or `#[embed(embed_with = "...")]` (embedded with the given function).
+///
+/// Fields can be weighted with `#[embed(weight = N)]` (e.g.: `#[embed(embed_with = "...",
+/// weight = 2)]`), embedding their texts `N` times so that they influence the embeddings of the
+/// document more. This is an approximation: each text is embedded separately, so repeated texts
+/// only count more where the embeddings of the document are combined (e.g.: averaged), not when
+/// documents are ranked by their best matching embedding.
+///
 /// References:
 /// <https://doc.rust-lang.org/book/ch19-06-macros.html#how-to-write-a-custom-derive-macro>
 /// <https://doc.rust-lang.org/reference/procedural-macros.html>
@@ -19,3 +30,16 @@ pub fn derive_embedding_trait(item: TokenStream) -> TokenStream {
         .unwrap_or_else(syn::Error::into_compile_error)
         .into()
 }
+
+/// Turns the methods of an impl block tagged with `#[tool]` into tools, which can be collected
+/// in a `ToolSet` with the generated `toolset` method.
+/// The name of each tool is the name of its method, its description is the doc comment of the
+/// method and the JSON schema of its parameters is generated from the method signature.
+#[proc_macro_attribute]
+pub fn tools(_args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(item as ItemImpl);
+
+    tools::expand_tools(&mut input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
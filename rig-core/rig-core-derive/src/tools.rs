@@ -0,0 +1,424 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    spanned::Spanned, Attribute, Expr, ExprLit, FnArg, GenericArgument, ImplItem, ImplItemFn, Lit,
+    Meta, Pat, PathArguments, ReturnType, Type,
+};
+
+use crate::TOOL;
+
+pub(crate) fn expand_tools(input: &mut syn::ItemImpl) -> syn::Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "#[tools] does not support generic impl blocks",
+        ));
+    }
+    if let Some((_, path, _)) = &input.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "#[tools] should only be used on inherent impl blocks",
+        ));
+    }
+
+    let self_ty = &input.self_ty;
+    let self_name = match &**self_ty {
+        Type::Path(type_path) => match type_path.path.segments.last() {
+            Some(segment) => segment.ident.clone(),
+            None => return Err(syn::Error::new_spanned(self_ty, "Unsupported type")),
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                self_ty,
+                "#[tools] should only be used on impl blocks of named types",
+            ))
+        }
+    };
+
+    let mut tools = vec![];
+    for item in input.items.iter_mut() {
+        if let ImplItem::Fn(method) = item {
+            let len = method.attrs.len();
+            // Remove the `#[tool]` attributes, which are not real attributes
+            method.attrs.retain(|attr| !is_tool_attribute(attr));
+            if method.attrs.len() != len {
+                tools.push(ToolMethod::parse(method)?);
+            }
+        }
+    }
+
+    if tools.is_empty() {
+        return Err(syn::Error::new_spanned(
+            self_ty,
+            "Add at least one method tagged with #[tool].",
+        ));
+    }
+
+    let tool_structs = tools
+        .iter()
+        .map(|tool| tool.expand(&self_name, self_ty))
+        .collect::<Vec<_>>();
+    let tool_idents = tools
+        .iter()
+        .map(|tool| tool.struct_ident(&self_name))
+        .collect::<Vec<_>>();
+
+    let gen = quote! {
+        #input
+
+        #(#tool_structs)*
+
+        impl #self_ty {
+            /// Consume `self` and create a [ToolSet](rig::tool::ToolSet) holding one tool for
+            /// each method tagged with `#[tool]`.
+            pub fn toolset(self) -> rig::tool::ToolSet
+            where
+                Self: Send + Sync + 'static,
+            {
+                let this = ::std::sync::Arc::new(self);
+                rig::tool::ToolSet::builder()
+                    #(.static_tool(#tool_idents(this.clone())))*
+                    .build()
+            }
+        }
+    };
+
+    Ok(gen)
+}
+
+fn is_tool_attribute(attr: &Attribute) -> bool {
+    matches!(&attr.meta, Meta::Path(path) if path.is_ident(TOOL))
+}
+
+/// A method tagged with `#[tool]`
+struct ToolMethod {
+    ident: syn::Ident,
+    description: String,
+    is_async: bool,
+    params: Vec<ToolParam>,
+    output: Type,
+    error: Type,
+}
+
+/// A parameter of a method tagged with `#[tool]`
+struct ToolParam {
+    ident: syn::Ident,
+    /// Owned type of the parameter, used to deserialize it
+    ty: Type,
+    /// How the parameter is passed to the method
+    passing: Passing,
+}
+
+enum Passing {
+    /// `T`
+    Value,
+    /// `&T`
+    Ref,
+    /// `Option<&T>`, where `T` derefs from its owned type (e.g.: `str` from `String`)
+    OptionDeref,
+    /// `Option<&T>`
+    OptionRef,
+}
+
+impl ToolMethod {
+    fn parse(method: &ImplItemFn) -> syn::Result<Self> {
+        let signature = &method.sig;
+
+        if !signature.generics.params.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &signature.generics,
+                "Tool methods cannot be generic",
+            ));
+        }
+
+        let mut inputs = signature.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(receiver))
+                if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    signature,
+                    "Tool methods should take `&self` as their first parameter",
+                ))
+            }
+        }
+
+        let params = inputs
+            .map(|input| match input {
+                FnArg::Typed(pat_type) => match &*pat_type.pat {
+                    Pat::Ident(pat_ident) => {
+                        let (ty, passing) = match &*pat_type.ty {
+                            Type::Reference(reference) => {
+                                (owned_type(&reference.elem), Passing::Ref)
+                            }
+                            ty => match option_inner(ty) {
+                                Some(Type::Reference(reference)) => {
+                                    let owned = owned_type(&reference.elem);
+                                    let passing = if is_unsized(&reference.elem) {
+                                        Passing::OptionDeref
+                                    } else {
+                                        Passing::OptionRef
+                                    };
+                                    (syn::parse_quote!(Option<#owned>), passing)
+                                }
+                                _ => (ty.clone(), Passing::Value),
+                            },
+                        };
+                        Ok(ToolParam {
+                            ident: pat_ident.ident.clone(),
+                            ty,
+                            passing,
+                        })
+                    }
+                    pat => Err(syn::Error::new_spanned(
+                        pat,
+                        "Tool method parameters should be identifiers",
+                    )),
+                },
+                FnArg::Receiver(receiver) => {
+                    Err(syn::Error::new_spanned(receiver, "Unexpected receiver"))
+                }
+            })
+            .collect::<syn::Result<Vec<_>>>()?;
+
+        let (output, error) = match &signature.output {
+            ReturnType::Type(_, ty) => result_types(ty),
+            ReturnType::Default => None,
+        }
+        .ok_or_else(|| {
+            syn::Error::new(
+                signature.output.span(),
+                "Tool methods should return a `Result<T, E>`",
+            )
+        })?;
+
+        Ok(Self {
+            ident: signature.ident.clone(),
+            description: doc_comment(&method.attrs),
+            is_async: signature.asyncness.is_some(),
+            params,
+            output,
+            error,
+        })
+    }
+
+    fn struct_ident(&self, self_name: &syn::Ident) -> syn::Ident {
+        format_ident!("{}{}Tool", self_name, pascal_case(&self.ident.to_string()))
+    }
+
+    fn expand(&self, self_name: &syn::Ident, self_ty: &Type) -> TokenStream {
+        let struct_ident = self.struct_ident(self_name);
+        let args_ident = format_ident!("{}Args", struct_ident);
+        let method = &self.ident;
+        let name = method.to_string();
+        let description = &self.description;
+        let output = &self.output;
+        let error = &self.error;
+
+        let fields = self.params.iter().map(|param| {
+            let ident = &param.ident;
+            let ty = &param.ty;
+            quote! { #ident: #ty }
+        });
+        let args = self.params.iter().map(|param| {
+            let ident = &param.ident;
+            match param.passing {
+                Passing::Value => quote! { args.#ident },
+                Passing::Ref => quote! { &args.#ident },
+                Passing::OptionDeref => quote! { args.#ident.as_deref() },
+                Passing::OptionRef => quote! { args.#ident.as_ref() },
+            }
+        });
+        let properties = self.params.iter().map(|param| {
+            let name = param.ident.to_string();
+            let schema = json_schema(&param.ty);
+            quote! { #name: #schema }
+        });
+        let required = self
+            .params
+            .iter()
+            .filter(|param| option_inner(&param.ty).is_none())
+            .map(|param| param.ident.to_string());
+        let call = if self.is_async {
+            quote! { self.0.#method(#(#args),*).await }
+        } else {
+            quote! { self.0.#method(#(#args),*) }
+        };
+
+        quote! {
+            #[derive(rig::__private::serde::Deserialize)]
+            #[serde(crate = "rig::__private::serde")]
+            #[doc(hidden)]
+            struct #args_ident {
+                #(#fields),*
+            }
+
+            #[doc(hidden)]
+            struct #struct_ident(::std::sync::Arc<#self_ty>);
+
+            impl rig::tool::Tool for #struct_ident {
+                const NAME: &'static str = #name;
+
+                type Error = #error;
+                type Args = #args_ident;
+                type Output = #output;
+
+                async fn definition(&self, _prompt: String) -> rig::completion::ToolDefinition {
+                    rig::completion::ToolDefinition {
+                        name: #name.to_string(),
+                        description: #description.to_string(),
+                        parameters: rig::__private::serde_json::json!({
+                            "type": "object",
+                            "properties": {
+                                #(#properties),*
+                            },
+                            "required": [#(#required),*]
+                        }),
+                    }
+                }
+
+                async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+                    #call
+                }
+            }
+        }
+    }
+}
+
+/// Joins the lines of the doc comment of an item, trimming the leading space of each line.
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(name_value) if name_value.path.is_ident("doc") => {
+                match &name_value.value {
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(line),
+                        ..
+                    }) => Some(line.value().trim().to_string()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the owned type of a type taken by reference (e.g.: `String` for `str`).
+fn owned_type(ty: &Type) -> Type {
+    match ty {
+        Type::Path(type_path) if type_path.path.is_ident("str") => syn::parse_quote!(String),
+        Type::Slice(slice) => {
+            let elem = &slice.elem;
+            syn::parse_quote!(Vec<#elem>)
+        }
+        ty => ty.clone(),
+    }
+}
+
+/// Whether `ty` is an unsized type replaced by [owned_type].
+fn is_unsized(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path.path.is_ident("str"),
+        Type::Slice(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns the generic arguments of `ty` if its last path segment is named `name`.
+fn generic_args<'a>(ty: &'a Type, name: &str) -> Option<Vec<&'a Type>> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != name {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => Some(
+            arguments
+                .args
+                .iter()
+                .filter_map(|arg| match arg {
+                    GenericArgument::Type(ty) => Some(ty),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn option_inner(ty: &Type) -> Option<&Type> {
+    generic_args(ty, "Option").and_then(|args| args.first().copied())
+}
+
+fn result_types(ty: &Type) -> Option<(Type, Type)> {
+    match generic_args(ty, "Result")?.as_slice() {
+        [output, error] => Some(((*output).clone(), (*error).clone())),
+        _ => None,
+    }
+}
+
+/// Generates the JSON schema of a parameter type. Types which are not primitives, strings,
+/// options or collections are described as objects.
+fn json_schema(ty: &Type) -> TokenStream {
+    if let Some(inner) = option_inner(ty) {
+        return json_schema(inner);
+    }
+
+    match ty {
+        Type::Reference(reference) => return json_schema(&reference.elem),
+        Type::Slice(slice) => return array_schema(&slice.elem),
+        Type::Array(array) => return array_schema(&array.elem),
+        _ => (),
+    }
+
+    for collection in ["Vec", "VecDeque", "HashSet", "BTreeSet"] {
+        if let Some(elem) = generic_args(ty, collection).and_then(|args| args.first().copied()) {
+            return array_schema(elem);
+        }
+    }
+
+    let ident = match ty {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    };
+
+    let json_type = match ident.as_deref() {
+        Some(
+            "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+            | "usize",
+        ) => "integer",
+        Some("f32" | "f64") => "number",
+        Some("bool") => "boolean",
+        Some("String" | "str" | "char") => "string",
+        _ => "object",
+    };
+
+    quote! { { "type": #json_type } }
+}
+
+fn array_schema(elem: &Type) -> TokenStream {
+    let items = json_schema(elem);
+    quote! { { "type": "array", "items": #items } }
+}
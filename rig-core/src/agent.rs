@@ -77,7 +77,7 @@
 //! let embedding_model = openai.embedding_model(openai::TEXT_EMBEDDING_ADA_002);
 //!
 //! // Create vector store, compute embeddings and load them in the store
-//! let mut vector_store = InMemoryVectorStore::default();
+//! let vector_store = InMemoryVectorStore::default();
 //!
 //! let embeddings = EmbeddingsBuilder::new(embedding_model.clone())
 //!     .simple_document("doc0", "Definition of a *flurbo*: A flurbo is a green alien that lives on cold planets")
@@ -106,22 +106,43 @@
 //! let response = agent.prompt("What does \"glarb-glarb\" mean?").await
 //!     .expect("Failed to prompt the agent");
 //! ```
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, PoisonError, RwLock},
+    time::Instant,
+};
 
-use futures::{stream, StreamExt, TryStreamExt};
+use chrono::{DateTime, FixedOffset, Utc};
+use futures::{
+    channel::mpsc,
+    future::{self, BoxFuture},
+    stream, FutureExt, Stream, StreamExt, TryStreamExt,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
 
 use crate::{
     completion::{
-        Chat, Completion, CompletionError, CompletionModel, CompletionRequestBuilder,
-        CompletionResponse, Document, Message, ModelChoice, Prompt, PromptError,
+        CallStats, Chat, Completion, CompletionError, CompletionModel, CompletionRequest,
+        CompletionRequestBuilder, Document, Image, Message, ModelChoice, NativeTool, Prompt,
+        PromptError, PromptWithStats, ReasoningStripper, RetryPolicy, StrippedAnswer,
+        ToolDefinition, Usage,
     },
-    tool::{Tool, ToolSet},
+    conversation::{ConversationStore, ConversationStoreDyn, InMemoryConversationStore},
+    extractor::{ExtractionError, ExtractionStream, PartialExtraction},
+    json_enforcer, json_utils,
+    streaming::{StreamEvent, StreamingCompletionModel, StreamingResult},
+    tool::{Source, SyncFuture, Tool, ToolError, ToolProgress, ToolSet, ToolSetError},
+    truncation::{TruncationPolicy, CHARS_PER_TOKEN},
     vector_store::{VectorStoreError, VectorStoreIndexDyn},
 };
 
 /// Struct reprensenting an LLM agent. An agent is an LLM model combined with a preamble
 /// (i.e.: system prompt) and a static set of context documents and tools.
-/// All context documents and tools are always provided to the agent when prompted.
+/// All context documents and tools are always provided to the agent when prompted. Tools can
+/// also be added and removed at runtime (see [Agent::add_tool]).
 ///
 /// # Example
 /// ```
@@ -146,8 +167,6 @@ pub struct Agent<M: CompletionModel> {
     preamble: String,
     /// Context documents always available to the agent
     static_context: Vec<Document>,
-    /// Tools that are always available to the agent (identified by their name)
-    static_tools: Vec<String>,
     /// Temperature of the model
     temperature: Option<f64>,
     /// Maximum number of tokens for the completion
@@ -156,272 +175,4286 @@ pub struct Agent<M: CompletionModel> {
     additional_params: Option<serde_json::Value>,
     /// List of vector store, with the sample number
     dynamic_context: Vec<(usize, Box<dyn VectorStoreIndexDyn>)>,
+    /// Custom retrievers of dynamic context (see [AgentBuilder::dynamic_context_fn])
+    context_retrievers: Vec<ContextRetriever>,
     /// Dynamic tools
     dynamic_tools: Vec<(usize, Box<dyn VectorStoreIndexDyn>)>,
-    /// Actual tool implementations
-    pub tools: ToolSet,
+    /// Tools of the agent, editable at runtime (see [Agent::add_tool]), copied on write so
+    /// that each call keeps a snapshot of the tools taken when it started
+    tools: RwLock<Arc<AgentTools>>,
+    /// Maximum number of completion requests sent per prompt (see [AgentBuilder::max_turns])
+    max_turns: usize,
+    /// Maximum number of calls of the tools with a quota per run (see
+    /// [AgentBuilder::tool_quota])
+    tool_quotas: HashMap<String, usize>,
+    /// Condition evaluated after each tool call to end the tool loop early
+    stop_condition: Option<StopCondition>,
+    /// Maximum number of tokens (prompt and completion) of each completion request
+    token_budget: Option<u64>,
+    /// Policy for truncating oversized context documents
+    truncation: Option<TruncationPolicy>,
+    /// Maximum number of tokens of the preamble and static context of each request
+    preamble_budget: Option<u64>,
+    /// Maximum number of tokens of the retrieved context of each request
+    retrieved_context_budget: Option<u64>,
+    /// Current date/time appended to the preamble of each request
+    date_injection: Option<DateInjection>,
+    /// Number of variants of the prompt generated to retrieve the dynamic context
+    query_expansion: Option<usize>,
+    /// Store of the conversation histories (see [Agent::prompt_with_history])
+    conversation_store: Box<dyn ConversationStoreDyn>,
+    /// Policy for re-requesting empty completions (see [AgentBuilder::retry_empty_responses])
+    empty_response_retry: RetryPolicy,
+    /// Instruction appended to the prompt when re-requesting an empty completion
+    empty_response_nudge: Option<String>,
+    /// Extractor of the final answer of the samples (see [Agent::prompt_self_consistent])
+    answer_extractor: AnswerExtractor,
+    /// Temperature of the samples (see [Agent::prompt_self_consistent])
+    self_consistency_temperature: Option<f64>,
+    /// Transforms applied to the final answer, in order
+    output_transforms: Vec<OutputTransform>,
+    /// Tools executed by the provider (see [AgentBuilder::native_tool])
+    native_tools: Vec<NativeTool>,
+    /// Stripper of the chain-of-thought of the answers (see [AgentBuilder::strip_reasoning])
+    reasoning_stripper: Option<ReasoningStripper>,
 }
 
-impl<M: CompletionModel> Completion<M> for Agent<M> {
-    async fn completion(
-        &self,
-        prompt: &str,
-        chat_history: Vec<Message>,
-    ) -> Result<CompletionRequestBuilder<M>, CompletionError> {
-        let dynamic_context = stream::iter(self.dynamic_context.iter())
-            .then(|(num_sample, index)| async {
-                Ok::<_, VectorStoreError>(
-                    index
-                        .top_n(prompt, *num_sample)
-                        .await?
-                        .into_iter()
-                        .map(|(_, id, doc)| {
-                            // Pretty print the document if possible for better readability
-                            let text = serde_json::to_string_pretty(&doc)
-                                .unwrap_or_else(|_| doc.to_string());
-
-                            Document {
-                                id,
-                                text,
-                                additional_props: HashMap::new(),
-                            }
-                        })
-                        .collect::<Vec<_>>(),
-                )
-            })
-            .try_fold(vec![], |mut acc, docs| async {
-                acc.extend(docs);
-                Ok(acc)
-            })
-            .await
-            .map_err(|e| CompletionError::RequestError(Box::new(e)))?;
+/// Default instruction appended to the prompt when re-requesting an empty completion (see
+/// [AgentBuilder::empty_response_nudge])
+pub const EMPTY_RESPONSE_NUDGE: &str =
+    "Your previous answer was empty. Be concise, but answer with a non-empty response.";
 
-        let dynamic_tools = stream::iter(self.dynamic_tools.iter())
-            .then(|(num_sample, index)| async {
-                Ok::<_, VectorStoreError>(
-                    index
-                        .top_n_ids(prompt, *num_sample)
-                        .await?
-                        .into_iter()
-                        .map(|(_, id)| id)
-                        .collect::<Vec<_>>(),
-                )
-            })
-            .try_fold(vec![], |mut acc, docs| async {
-                for doc in docs {
-                    if let Some(tool) = self.tools.get(&doc) {
-                        acc.push(tool.definition(prompt.into()).await)
-                    } else {
-                        tracing::warn!("Tool implementation not found in toolset: {}", doc);
-                    }
-                }
-                Ok(acc)
-            })
-            .await
-            .map_err(|e| CompletionError::RequestError(Box::new(e)))?;
+/// Callback evaluated on the conversation so far (see [AgentBuilder::with_stop_condition])
+type StopCondition = Box<dyn Fn(&[Message]) -> bool + Send + Sync>;
 
-        let static_tools = stream::iter(self.static_tools.iter())
-            .filter_map(|toolname| async move {
-                if let Some(tool) = self.tools.get(toolname) {
-                    Some(tool.definition(prompt.into()).await)
-                } else {
-                    tracing::warn!("Tool implementation not found in toolset: {}", toolname);
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .await;
+/// Extractor of the final answer of a response (see [AgentBuilder::answer_extractor])
+type AnswerExtractor = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
 
-        Ok(self
-            .model
-            .completion_request(prompt)
-            .preamble(self.preamble.clone())
-            .messages(chat_history)
-            .documents([self.static_context.clone(), dynamic_context].concat())
-            .tools([static_tools.clone(), dynamic_tools].concat())
-            .temperature_opt(self.temperature)
-            .max_tokens_opt(self.max_tokens)
-            .additional_params_opt(self.additional_params.clone()))
-    }
-}
+/// Transform of the final answer of an agent (see [AgentBuilder::output_transform])
+type OutputTransform =
+    Box<dyn Fn(String) -> Result<String, Box<dyn std::error::Error + Send + Sync>> + Send + Sync>;
 
-impl<M: CompletionModel> Prompt for Agent<M> {
-    async fn prompt(&self, prompt: &str) -> Result<String, PromptError> {
-        self.chat(prompt, vec![]).await
-    }
+/// Output transform extracting the content of the first fenced code block (i.e.: between
+/// ` ``` ` lines, without the language tag) of the answer of an agent (see
+/// [AgentBuilder::output_transform]). Fails if the answer has no code block.
+pub fn extract_code_block(
+    answer: String,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let start = answer.find("```").ok_or("No code block in the answer")?;
+    let block = &answer[start + 3..];
+    // Skip the language tag (e.g.: ```rust)
+    let block = block.split_once('\n').map_or("", |(_, block)| block);
+    let end = block.find("```").unwrap_or(block.len());
+    Ok(block[..end].trim_end().to_string())
 }
 
-impl<M: CompletionModel> Prompt for &Agent<M> {
-    async fn prompt(&self, prompt: &str) -> Result<String, PromptError> {
-        self.chat(prompt, vec![]).await
-    }
+/// Default temperature of the samples of [Agent::prompt_self_consistent], unless the agent has
+/// a higher temperature (see [AgentBuilder::self_consistency_temperature])
+pub const SELF_CONSISTENCY_TEMPERATURE: f64 = 0.8;
+
+/// Default extractor of the final answer of a response (see [AgentBuilder::answer_extractor]):
+/// the text following the last `Answer:` (case insensitive) if any, otherwise the last
+/// non-empty line of the response, trimmed and without a trailing period.
+pub fn default_answer_extractor(response: &str) -> Option<String> {
+    let lowercase = response.to_ascii_lowercase();
+    let answer = match lowercase.rfind("answer:") {
+        Some(start) => response[start + "answer:".len()..].lines().next()?,
+        None => response
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())?,
+    };
+
+    let answer = answer.trim().trim_end_matches('.').trim();
+    (!answer.is_empty()).then(|| answer.to_string())
 }
 
-impl<M: CompletionModel> Chat for Agent<M> {
-    async fn chat(&self, prompt: &str, chat_history: Vec<Message>) -> Result<String, PromptError> {
-        match self.completion(prompt, chat_history).await?.send().await? {
-            CompletionResponse {
-                choice: ModelChoice::Message(msg),
-                ..
-            } => Ok(msg),
-            CompletionResponse {
-                choice: ModelChoice::ToolCall(toolname, args),
-                ..
-            } => Ok(self.tools.call(&toolname, args.to_string()).await?),
-        }
+/// Stream of the events of a streaming prompt of an agent (see [Agent::stream_prompt])
+pub type AgentStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<StreamEvent, PromptError>> + Send + 'a>>;
+
+/// Sum of two token usages
+fn add_usage(total: Usage, usage: Usage) -> Usage {
+    Usage {
+        prompt_tokens: total.prompt_tokens + usage.prompt_tokens,
+        completion_tokens: total.completion_tokens + usage.completion_tokens,
+        total_tokens: total.total_tokens + usage.total_tokens,
+        cached_tokens: total.cached_tokens + usage.cached_tokens,
+        cache_creation_tokens: total.cache_creation_tokens + usage.cache_creation_tokens,
     }
 }
 
-/// A builder for creating an agent
+/// Document returned by a custom retriever (see [AgentBuilder::dynamic_context_fn])
+pub type RetrievedDoc = Document;
+
+/// Custom retriever of dynamic context (see [AgentBuilder::dynamic_context_fn])
+type ContextRetriever = Box<dyn Fn(String) -> BoxFuture<'static, Vec<RetrievedDoc>> + Send + Sync>;
+
+/// Clock returning the current time (see [DateInjection::clock])
+type Clock = Box<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+/// Configuration of the current date/time appended to the preamble of each request sent by an
+/// agent (see [AgentBuilder::inject_date]), so that the model can answer questions relative to
+/// the current date.
 ///
 /// # Example
-/// ```
-/// use rig::{providers::openai, agent::AgentBuilder};
-///
-/// let openai = openai::Client::from_env();
+/// ```rust
+/// use chrono::FixedOffset;
+/// use rig::agent::DateInjection;
 ///
-/// let gpt4o = openai.completion_model("gpt-4o");
-///
-/// // Configure the agent
-/// let agent = AgentBuilder::new(model)
-///     .preamble("System prompt")
-///     .context("Context document 1")
-///     .context("Context document 2")
-///     .tool(tool1)
-///     .tool(tool2)
-///     .temperature(0.8)
-///     .additional_params(json!({"foo": "bar"}))
+/// let agent = openai.agent(openai::GPT_4O)
+///     .preamble("You are a travel assistant.")
+///     .inject_date(
+///         DateInjection::new()
+///             .timezone(FixedOffset::east_opt(2 * 3600).unwrap())
+///             .format("%A %-d %B %Y, %H:%M"),
+///     )
 ///     .build();
 /// ```
-pub struct AgentBuilder<M: CompletionModel> {
-    /// Completion model (e.g.: OpenAI's gpt-3.5-turbo-1106, Cohere's command-r)
-    model: M,
-    /// System prompt
-    preamble: Option<String>,
-    /// Context documents always available to the agent
-    static_context: Vec<Document>,
-    /// Tools that are always available to the agent (by name)
-    static_tools: Vec<String>,
-    /// Additional parameters to be passed to the model
-    additional_params: Option<serde_json::Value>,
-    /// Maximum number of tokens for the completion
-    max_tokens: Option<u64>,
-    /// List of vector store, with the sample number
-    dynamic_context: Vec<(usize, Box<dyn VectorStoreIndexDyn>)>,
-    /// Dynamic tools
-    dynamic_tools: Vec<(usize, Box<dyn VectorStoreIndexDyn>)>,
-    /// Temperature of the model
-    temperature: Option<f64>,
-    /// Actual tool implementations
-    tools: ToolSet,
+pub struct DateInjection {
+    /// Format of the date/time (see [chrono::format::strftime])
+    format: String,
+    /// Timezone of the date/time
+    timezone: FixedOffset,
+    /// Clock returning the current time
+    clock: Clock,
 }
 
-impl<M: CompletionModel> AgentBuilder<M> {
-    pub fn new(model: M) -> Self {
+impl Default for DateInjection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DateInjection {
+    /// Inject the current UTC date/time from the system clock, formatted as
+    /// `2024-11-28 14:30 +00:00`.
+    pub fn new() -> Self {
         Self {
-            model,
-            preamble: None,
-            static_context: vec![],
-            static_tools: vec![],
-            temperature: None,
-            max_tokens: None,
-            additional_params: None,
-            dynamic_context: vec![],
-            dynamic_tools: vec![],
-            tools: ToolSet::default(),
+            format: "%Y-%m-%d %H:%M %:z".to_string(),
+            timezone: FixedOffset::east_opt(0).expect("UTC offset should be valid"),
+            clock: Box::new(Utc::now),
         }
     }
 
-    /// Set the system prompt
-    pub fn preamble(mut self, preamble: &str) -> Self {
-        self.preamble = Some(preamble.into());
+    /// Set the format of the date/time (see [chrono::format::strftime] for the syntax).
+    pub fn format(mut self, format: &str) -> Self {
+        self.format = format.to_string();
         self
     }
 
-    /// Append to the preamble of the agent
-    pub fn append_preamble(mut self, doc: &str) -> Self {
-        self.preamble = Some(format!(
-            "{}\n{}",
-            self.preamble.unwrap_or_else(|| "".into()),
-            doc
-        ));
+    /// Set the timezone in which the date/time is expressed (UTC by default).
+    pub fn timezone(mut self, timezone: FixedOffset) -> Self {
+        self.timezone = timezone;
         self
     }
 
-    /// Add a static context document to the agent
-    pub fn context(mut self, doc: &str) -> Self {
-        self.static_context.push(Document {
-            id: format!("static_doc_{}", self.static_context.len()),
-            text: doc.into(),
-            additional_props: HashMap::new(),
-        });
+    /// Set the clock returning the current time, e.g.: a fixed time for deterministic tests.
+    pub fn clock(mut self, clock: impl Fn() -> DateTime<Utc> + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
         self
     }
 
-    /// Add a static tool to the agent
-    pub fn tool(mut self, tool: impl Tool + 'static) -> Self {
-        let toolname = tool.name();
-        self.tools.add_tool(tool);
-        self.static_tools.push(toolname);
-        self
+    /// Render the current date/time as appended to the preamble.
+    fn render(&self) -> String {
+        format!(
+            "Current date and time: {}",
+            (self.clock)()
+                .with_timezone(&self.timezone)
+                .format(&self.format)
+        )
     }
+}
 
-    /// Add some dynamic context to the agent. On each prompt, `sample` documents from the
-    /// dynamic context will be inserted in the request.
-    pub fn dynamic_context(
-        mut self,
-        sample: usize,
-        dynamic_context: impl VectorStoreIndexDyn + 'static,
-    ) -> Self {
-        self.dynamic_context
-            .push((sample, Box::new(dynamic_context)));
-        self
+/// A step of the tool loop of an agent (see [Agent::prompt_traced]), i.e.: one completion
+/// request and the tool call it resulted in, if any.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    /// Prompt sent to the model
+    pub prompt: String,
+    /// Choice of the model, i.e.: either a message or a tool call
+    pub choice: ModelChoice,
+    /// Output of the tool call, if the model called a tool
+    pub tool_result: Option<String>,
+    /// Maximum number of completion tokens of the request
+    pub max_tokens: Option<u64>,
+    /// Token usage of the request, if reported by the provider
+    pub usage: Option<Usage>,
+    /// Number of retries of the request (see [CompletionModel::retries] and
+    /// [AgentBuilder::retry_empty_responses])
+    pub retries: usize,
+    /// Sources of the output of the tool call (see [Tool::sources])
+    pub sources: Vec<Source>,
+    /// Context dropped from the request to fit the context budgets of the agent (see
+    /// [AgentBuilder::preamble_budget] and [AgentBuilder::retrieved_context_budget])
+    pub dropped_context: DroppedContext,
+    /// Chain-of-thought of the response, as reported by the model (see
+    /// [CompletionModel::reasoning]) or stripped from the answer (see
+    /// [AgentBuilder::strip_reasoning])
+    pub reasoning: Option<String>,
+}
+
+/// Context dropped from completion requests to fit the context budgets of an agent (see
+/// [AgentBuilder::preamble_budget] and [AgentBuilder::retrieved_context_budget]).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DroppedContext {
+    /// Ids of the dropped context documents
+    pub documents: Vec<String>,
+    /// Estimated number of tokens dropped, of the dropped documents and of the truncated
+    /// preamble
+    pub tokens: u64,
+}
+
+impl DroppedContext {
+    /// Whether no context was dropped
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty() && self.tokens == 0
     }
 
-    /// Add some dynamic tools to the agent. On each prompt, `sample` tools from the
-    /// dynamic toolset will be inserted in the request.
-    pub fn dynamic_tools(
-        mut self,
-        sample: usize,
-        dynamic_tools: impl VectorStoreIndexDyn + 'static,
-        toolset: ToolSet,
-    ) -> Self {
-        self.dynamic_tools.push((sample, Box::new(dynamic_tools)));
-        self.tools.add_tools(toolset);
+    /// Add the context dropped from another request, the documents being deduplicated
+    fn merge(mut self, other: &DroppedContext) -> Self {
+        for id in &other.documents {
+            if !self.documents.contains(id) {
+                self.documents.push(id.clone());
+            }
+        }
+        self.tokens += other.tokens;
         self
     }
+}
 
-    /// Set the temperature of the model
-    pub fn temperature(mut self, temperature: f64) -> Self {
-        self.temperature = Some(temperature);
-        self
+/// Estimated number of tokens of `text`
+fn estimated_tokens(text: &str) -> u64 {
+    text.len().div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+/// Keep the leading `documents` fitting in `budget` (estimated) tokens, recording the others in
+/// `dropped`
+fn fit_documents(
+    documents: Vec<Document>,
+    mut budget: u64,
+    dropped: &mut DroppedContext,
+) -> Vec<Document> {
+    let mut kept = vec![];
+    let mut exhausted = false;
+    for document in documents {
+        let tokens = estimated_tokens(&document.to_string());
+        exhausted = exhausted || tokens > budget;
+        if exhausted {
+            dropped.tokens += tokens;
+            dropped.documents.push(document.id);
+        } else {
+            budget -= tokens;
+            kept.push(document);
+        }
     }
+    kept
+}
 
-    /// Set the maximum number of tokens for the completion
-    pub fn max_tokens(mut self, max_tokens: u64) -> Self {
-        self.max_tokens = Some(max_tokens);
-        self
+/// State of a tool loop paused by a tool needing input from the user (see
+/// [PromptError::NeedsInput]), to be resumed with [Agent::resume] once the input is provided.
+#[derive(Clone, Debug)]
+pub struct PendingInput {
+    /// Prompt of the tool for the user (e.g.: "Delete `notes.txt`?")
+    pub prompt: String,
+    /// Name of the tool needing input
+    pub toolname: String,
+    /// Arguments of the tool call needing input
+    pub args: serde_json::Value,
+    /// Conversation so far, ending with the tool call
+    pub chat_history: Vec<Message>,
+    /// Tools available to the paused tool loop
+    filter: ToolFilter,
+}
+
+/// Per-call restriction of the tools available to an agent (see [Agent::allowed_tools])
+#[derive(Clone, Debug, Default)]
+struct ToolFilter {
+    /// Names of the only tools available, if restricted
+    allowed: Option<Vec<String>>,
+    /// Names of the tools that are not available
+    denied: Vec<String>,
+    /// Snapshot of the tools of the agent taken when the call started (see
+    /// [Agent::with_snapshot]), so that editing the tools does not affect calls in progress
+    tools: Option<Arc<AgentTools>>,
+}
+
+/// Tools of an agent (see [Agent::add_tool])
+#[derive(Clone, Default)]
+struct AgentTools {
+    /// Actual tool implementations
+    toolset: ToolSet,
+    /// Tools that are always available to the agent (identified by their name)
+    static_tools: Vec<String>,
+}
+
+impl std::fmt::Debug for AgentTools {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentTools")
+            .field("static_tools", &self.static_tools)
+            .finish_non_exhaustive()
     }
+}
 
-    /// Set additional parameters to be passed to the model
-    pub fn additional_params(mut self, params: serde_json::Value) -> Self {
-        self.additional_params = Some(params);
-        self
+impl ToolFilter {
+    fn allows(&self, toolname: &str) -> bool {
+        self.allowed
+            .as_ref()
+            .map_or(true, |allowed| allowed.iter().any(|name| name == toolname))
+            && !self.denied.iter().any(|name| name == toolname)
+    }
+}
+
+/// Majority vote of the answers of several samples of a prompt (see
+/// [Agent::prompt_self_consistent]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vote {
+    /// Most voted answer. Ties are broken in favor of the answer sampled first.
+    pub answer: String,
+    /// Answers with their number of votes, most voted first
+    pub distribution: Vec<(String, usize)>,
+    /// Number of samples from which no answer could be extracted (e.g.: tool calls)
+    pub abstentions: usize,
+}
+
+/// Token usage of a prompt compared to the token budget of the agent
+/// (see [Agent::prompt_with_usage]).
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageReport {
+    /// Token budget of each completion request
+    pub budget: Option<u64>,
+    /// Total token usage of the completion requests of the prompt (i.e.: summed over all the
+    /// turns of the tool loop, including the cached tokens), if reported by the provider
+    pub usage: Option<Usage>,
+    /// Whether the completion of a request reached its `max_tokens` (e.g.: as derived from the
+    /// token budget), meaning that the completion was likely truncated
+    pub truncated: bool,
+    /// Context dropped from the completion requests of the prompt to fit the context budgets
+    /// of the agent (the tokens being summed over the requests)
+    pub dropped_context: DroppedContext,
+}
+
+impl<M: CompletionModel> Agent<M> {
+    /// Assemble the full completion request that [Prompt::prompt] would send for `prompt`
+    /// (preamble, retrieved context, tool definitions, etc.) without calling the provider.
+    ///
+    /// Note: dynamic context and tools are still retrieved from their vector stores (and the
+    /// prompt is expanded, see [AgentBuilder::query_expansion]).
+    ///
+    /// # Example
+    /// ```rust
+    /// let request = agent.dry_run("What is a flurbo?").await?;
+    /// println!("Sending {} documents and {} tools", request.documents.len(), request.tools.len());
+    /// ```
+    pub async fn dry_run(&self, prompt: &str) -> Result<CompletionRequest, CompletionError> {
+        Ok(self.completion(prompt, vec![]).await?.build())
     }
 
-    /// Build the agent
-    pub fn build(self) -> Agent<M> {
-        Agent {
-            model: self.model,
-            preamble: self.preamble.unwrap_or_default(),
-            static_context: self.static_context,
-            static_tools: self.static_tools,
-            temperature: self.temperature,
-            max_tokens: self.max_tokens,
-            additional_params: self.additional_params,
-            dynamic_context: self.dynamic_context,
-            dynamic_tools: self.dynamic_tools,
-            tools: self.tools,
+    /// Add a static tool to the running agent (see [AgentBuilder::tool]), replacing the tool
+    /// with the same name, if any. The tool is available from the next call of the agent:
+    /// calls in progress keep using the tools of the agent when they started.
+    ///
+    /// # Example
+    /// ```rust
+    /// let agent = openai.agent(openai::GPT_4O).build();
+    ///
+    /// // Tools of a plugin loaded at runtime
+    /// agent.add_tool(plugin.search_tool());
+    /// let answer = agent.prompt("Search for flurbos").await?;
+    ///
+    /// agent.remove_tool("search");
+    /// ```
+    pub fn add_tool(&self, tool: impl Tool + 'static) {
+        let toolname = tool.name();
+        let mut tools = self.tools.write().unwrap_or_else(PoisonError::into_inner);
+        let tools = Arc::make_mut(&mut tools);
+        tools.toolset.add_tool(tool);
+        if !tools.static_tools.contains(&toolname) {
+            tools.static_tools.push(toolname);
+        }
+    }
+
+    /// Remove the tool `toolname` from the running agent, returning whether the agent had the
+    /// tool. As with [Agent::add_tool], calls in progress are not affected.
+    pub fn remove_tool(&self, toolname: &str) -> bool {
+        let mut tools = self.tools.write().unwrap_or_else(PoisonError::into_inner);
+        let tools = Arc::make_mut(&mut tools);
+        tools.static_tools.retain(|name| name != toolname);
+        tools.toolset.remove_tool(toolname)
+    }
+
+    /// The current tools of the agent, including its dynamic tools (see
+    /// [AgentBuilder::dynamic_tools])
+    pub fn tools(&self) -> ToolSet {
+        self.snapshot().toolset.clone()
+    }
+
+    /// Snapshot of the current tools of the agent
+    fn snapshot(&self) -> Arc<AgentTools> {
+        self.tools
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Tools of the call restricted by `filter`: its snapshot if taken (see
+    /// [Agent::with_snapshot]), otherwise the current tools of the agent
+    fn call_tools(&self, filter: &ToolFilter) -> Arc<AgentTools> {
+        filter.tools.clone().unwrap_or_else(|| self.snapshot())
+    }
+
+    /// `filter` holding the snapshot of the tools used for the whole call, taken now unless
+    /// `filter` already holds one (e.g.: when resuming a paused call)
+    fn with_snapshot(&self, filter: &ToolFilter) -> ToolFilter {
+        ToolFilter {
+            tools: Some(self.call_tools(filter)),
+            ..filter.clone()
+        }
+    }
+
+    /// The preamble of the agent, followed by the current date/time if the agent injects it
+    /// (see [AgentBuilder::inject_date]).
+    fn preamble_at_request_time(&self) -> String {
+        match &self.date_injection {
+            Some(injection) if self.preamble.is_empty() => injection.render(),
+            Some(injection) => format!("{}\n\n{}", self.preamble, injection.render()),
+            None => self.preamble.clone(),
         }
     }
+
+    /// Resume a tool loop paused by a tool needing input from the user (see
+    /// [PromptError::NeedsInput]) with the `input` of the user. The input is sent to the model
+    /// as the result of the paused tool call, e.g.: for the model to call the tool again with
+    /// the confirmation. The resumed loop gets a new budget of
+    /// [max_turns](AgentBuilder::max_turns) requests.
+    ///
+    /// # Example
+    /// ```rust
+    /// let response = match agent.prompt("Delete notes.txt").await {
+    ///     Err(PromptError::NeedsInput(pending)) => {
+    ///         println!("{}", pending.prompt);
+    ///         agent.resume(*pending, &read_user_input()).await?
+    ///     }
+    ///     response => response?,
+    /// };
+    /// ```
+    pub async fn resume(&self, pending: PendingInput, input: &str) -> Result<String, PromptError> {
+        let prompt = format!(
+            "Result of tool `{}`: The tool needs input from the user: {}\nUser input: {input}",
+            pending.toolname, pending.prompt
+        );
+        self.run_with_images(&prompt, pending.chat_history, vec![], None, &pending.filter)
+            .await
+    }
+
+    /// Prompt the agent as part of the conversation `session_id`: the history of the session
+    /// is loaded from the conversation store of the agent (see
+    /// [AgentBuilder::conversation_store]) and sent as the chat history, then the prompt and
+    /// the response are appended to the history, which is saved back to the store.
+    ///
+    /// Note: the history is not saved if the prompt fails. Concurrent prompts to the same
+    /// session may overwrite each other's messages.
+    ///
+    /// # Example
+    /// ```rust
+    /// agent.prompt_with_history("user-42", "My name is Bob.").await?;
+    /// let response = agent.prompt_with_history("user-42", "What is my name?").await?;
+    /// ```
+    pub async fn prompt_with_history(
+        &self,
+        session_id: &str,
+        prompt: &str,
+    ) -> Result<String, PromptError> {
+        let mut chat_history = self.conversation_store.load(session_id).await?;
+        let response = self.chat(prompt, chat_history.clone()).await?;
+
+        chat_history.push(Message {
+            role: "user".into(),
+            content: prompt.into(),
+        });
+        chat_history.push(Message {
+            role: "assistant".into(),
+            content: response.clone(),
+        });
+        self.conversation_store
+            .save(session_id, chat_history)
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Same as [Prompt::prompt] but attaches the image at `path` to the prompt, for models
+    /// supporting images. The MIME type of the image is detected from its content and the
+    /// request fails if the file is not an image. Only the first request of the tool loop
+    /// contains the image. The image is read asynchronously with the `tokio-fs` feature, and
+    /// with blocking IO otherwise (see [Image::from_path]).
+    ///
+    /// # Example
+    /// ```rust
+    /// let description = agent.prompt_with_image("Describe this", "photo.jpg").await?;
+    /// ```
+    pub async fn prompt_with_image(
+        &self,
+        prompt: &str,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<String, PromptError> {
+        #[cfg(feature = "tokio-fs")]
+        let image = Image::from_path_async(path).await?;
+        #[cfg(not(feature = "tokio-fs"))]
+        let image = Image::from_path(path)?;
+        self.run_with_images(prompt, vec![], vec![image], None, &ToolFilter::default())
+            .await
+    }
+
+    /// Restrict the tools available to the agent to `tools` for the requests made through the
+    /// returned [ToolRestrictedAgent] only. An empty list means that no tools are available.
+    ///
+    /// # Example
+    /// ```rust
+    /// let response = agent
+    ///     .allowed_tools(&["add"])
+    ///     .prompt("What is 1 + 2?")
+    ///     .await?;
+    /// ```
+    pub fn allowed_tools(&self, tools: &[&str]) -> ToolRestrictedAgent<'_, M> {
+        ToolRestrictedAgent::new(self).allowed_tools(tools)
+    }
+
+    /// Make `tools` unavailable to the agent for the requests made through the returned
+    /// [ToolRestrictedAgent] only.
+    ///
+    /// # Example
+    /// ```rust
+    /// let response = agent
+    ///     .denied_tools(&["delete_file"])
+    ///     .prompt("Clean up the tmp directory")
+    ///     .await?;
+    /// ```
+    pub fn denied_tools(&self, tools: &[&str]) -> ToolRestrictedAgent<'_, M> {
+        ToolRestrictedAgent::new(self).denied_tools(tools)
+    }
+
+    /// Same as [Prompt::prompt] but also returns the trace of the tool loop, i.e.: every
+    /// model choice and tool result, in order.
+    ///
+    /// # Example
+    /// ```rust
+    /// let (answer, steps) = agent.prompt_traced("What is 1 + 2?").await?;
+    /// for step in steps {
+    ///     println!("{:?} -> {:?}", step.choice, step.tool_result);
+    /// }
+    /// ```
+    pub async fn prompt_traced(&self, prompt: &str) -> Result<(String, Vec<Step>), PromptError> {
+        let mut steps = vec![];
+        let answer = self
+            .run(prompt, vec![], Some(&mut steps), &ToolFilter::default())
+            .await?;
+        Ok((answer, steps))
+    }
+
+    /// Same as [Prompt::prompt] but also returns the token usage of the prompt compared to the
+    /// token budget of the agent (see [AgentBuilder::with_token_budget]).
+    ///
+    /// # Example
+    /// ```rust
+    /// let agent = openai.agent(openai::GPT_4O).with_token_budget(1000).build();
+    ///
+    /// let (answer, report) = agent.prompt_with_usage("What is a flurbo?").await?;
+    /// if report.truncated {
+    ///     println!("The answer was truncated by the token budget: {:?}", report.usage);
+    /// }
+    /// ```
+    pub async fn prompt_with_usage(
+        &self,
+        prompt: &str,
+    ) -> Result<(String, UsageReport), PromptError> {
+        let mut steps = vec![];
+        let answer = self
+            .run(prompt, vec![], Some(&mut steps), &ToolFilter::default())
+            .await?;
+
+        let usage = steps.iter().filter_map(|step| step.usage).reduce(add_usage);
+        let dropped_context = steps
+            .iter()
+            .fold(DroppedContext::default(), |dropped, step| {
+                dropped.merge(&step.dropped_context)
+            });
+
+        let truncated = steps
+            .iter()
+            .any(|step| match (step.usage, step.max_tokens) {
+                (Some(usage), Some(max_tokens)) => usage.completion_tokens >= max_tokens,
+                _ => false,
+            });
+
+        Ok((
+            answer,
+            UsageReport {
+                budget: self.token_budget,
+                usage,
+                truncated,
+                dropped_context,
+            },
+        ))
+    }
+
+    /// Same as [Prompt::prompt] but also returns the chain-of-thought of the final answer, as
+    /// reported by the model (see [CompletionModel::reasoning]) or stripped from the answer
+    /// (see [AgentBuilder::strip_reasoning]).
+    ///
+    /// # Example
+    /// ```rust
+    /// let answer = agent.prompt_with_reasoning("What is a flurbo?").await?;
+    /// tracing::info!("Reasoning: {:?}", answer.reasoning());
+    /// println!("{}", answer.answer());
+    /// ```
+    pub async fn prompt_with_reasoning(&self, prompt: &str) -> Result<StrippedAnswer, PromptError> {
+        let mut steps = vec![];
+        let answer = self
+            .run(prompt, vec![], Some(&mut steps), &ToolFilter::default())
+            .await?;
+        let reasoning = steps.last().and_then(|step| step.reasoning.clone());
+        Ok(StrippedAnswer::from(answer).with_reasoning(reasoning))
+    }
+
+    /// Same as [Prompt::prompt] but also returns the citations of the answer, i.e.: the sources
+    /// of the results of the tools called by the agent (see [Tool::sources]), in order and
+    /// deduplicated by id.
+    ///
+    /// # Example
+    /// ```rust
+    /// let (answer, citations) = agent.prompt_with_citations("What is a flurbo?").await?;
+    /// for source in citations {
+    ///     println!("{}: {:?}", source.id, source.url);
+    /// }
+    /// ```
+    pub async fn prompt_with_citations(
+        &self,
+        prompt: &str,
+    ) -> Result<(String, Vec<Source>), PromptError> {
+        let mut steps = vec![];
+        let answer = self
+            .run(prompt, vec![], Some(&mut steps), &ToolFilter::default())
+            .await?;
+
+        let mut citations: Vec<Source> = vec![];
+        for source in steps.into_iter().flat_map(|step| step.sources) {
+            if !citations.iter().any(|citation| citation.id == source.id) {
+                citations.push(source);
+            }
+        }
+        Ok((answer, citations))
+    }
+
+    /// Prompt the model `samples` times (at least once) with a higher temperature (see
+    /// [AgentBuilder::self_consistency_temperature]) and return the majority vote of the final
+    /// answers extracted from the responses (see [AgentBuilder::answer_extractor]), along with
+    /// the distribution of the votes.
+    ///
+    /// Each sample is a single completion request: tool calls are not executed and abstain from
+    /// the vote, as do the responses from which no answer can be extracted. Fails with
+    /// [PromptError::NoAnswer] if no sample has an answer. The samples share the same request:
+    /// its context is retrieved once, and the samples are requested concurrently.
+    ///
+    /// The token budget of the agent (see [AgentBuilder::with_token_budget]) bounds the total
+    /// tokens of the samples: each sample counts for the estimated tokens of the prompt and the
+    /// `max_tokens` of the request (capped to the budget), and only the samples fitting in the
+    /// budget are requested (at least one). Set the `max_tokens` of the agent (see
+    /// [AgentBuilder::max_tokens]) for several samples to fit.
+    ///
+    /// # Example
+    /// ```rust
+    /// let vote = agent
+    ///     .prompt_self_consistent("How many r's are in strawberry? End with `Answer: <n>`", 5)
+    ///     .await?;
+    /// println!("{} ({:?})", vote.answer, vote.distribution);
+    /// ```
+    pub async fn prompt_self_consistent(
+        &self,
+        prompt: &str,
+        samples: usize,
+    ) -> Result<Vote, PromptError> {
+        let temperature = self.self_consistency_temperature.unwrap_or_else(|| {
+            self.temperature
+                .map_or(SELF_CONSISTENCY_TEMPERATURE, |temperature| {
+                    temperature.max(SELF_CONSISTENCY_TEMPERATURE)
+                })
+        });
+
+        // The context is retrieved once for all the samples, which are requested concurrently
+        let mut request = self
+            .completion(prompt, vec![])
+            .await?
+            .temperature(temperature)
+            .build();
+        self.apply_token_budget(&mut request)?;
+
+        let mut samples = samples.max(1);
+        if let (Some(budget), Some(max_tokens)) = (self.token_budget, request.max_tokens) {
+            let sample_tokens = (request.estimated_prompt_tokens() + max_tokens).max(1);
+            let fitting = (budget / sample_tokens).max(1) as usize;
+            if fitting < samples {
+                tracing::warn!(target: "rig",
+                    "Only {fitting} of the {samples} samples (~{sample_tokens} tokens each) fit in \
+                    the token budget of {budget} tokens"
+                );
+                samples = fitting;
+            }
+        }
+        let responses =
+            future::join_all((0..samples).map(|_| self.model.completion(request.clone()))).await;
+
+        let mut distribution: Vec<(String, usize)> = vec![];
+        let mut abstentions = 0;
+        for response in responses {
+            let response = response?;
+            let answer = match response.choice {
+                ModelChoice::Message(msg) => (self.answer_extractor)(&msg),
+                ModelChoice::ToolCall(..) => None,
+            };
+            match answer {
+                Some(answer) => match distribution.iter_mut().find(|(a, _)| *a == answer) {
+                    Some((_, votes)) => *votes += 1,
+                    None => distribution.push((answer, 1)),
+                },
+                None => abstentions += 1,
+            }
+        }
+
+        // Stable sort: ties keep the order of the samples first giving the answers
+        distribution.sort_by(|(_, a), (_, b)| b.cmp(a));
+        let answer = distribution
+            .first()
+            .map(|(answer, _)| answer.clone())
+            .ok_or(PromptError::NoAnswer(abstentions))?;
+
+        Ok(Vote {
+            answer,
+            distribution,
+            abstentions,
+        })
+    }
+
+    /// Run the tool loop, recording its steps in `trace` if provided. Only the tools allowed
+    /// by `filter` are available.
+    async fn run(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+        trace: Option<&mut Vec<Step>>,
+        filter: &ToolFilter,
+    ) -> Result<String, PromptError> {
+        self.run_with_images(prompt, chat_history, vec![], trace, filter)
+            .await
+    }
+
+    /// Same as [Agent::run], with `images` attached to the first request
+    async fn run_with_images(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+        images: Vec<Image>,
+        trace: Option<&mut Vec<Step>>,
+        filter: &ToolFilter,
+    ) -> Result<String, PromptError> {
+        let answer = self
+            .tool_loop(prompt, chat_history, images, trace, filter)
+            .await?;
+        self.transform_output(answer)
+    }
+
+    /// Apply the output transforms of the agent to its final `answer`, in order
+    fn transform_output(&self, answer: String) -> Result<String, PromptError> {
+        self.output_transforms
+            .iter()
+            .try_fold(answer, |answer, transform| transform(answer))
+            .map_err(PromptError::OutputTransformError)
+    }
+
+    /// Count a call of `toolname` in `calls` (the calls of the current run), returning the
+    /// result sent to the model instead of calling the tool if its quota (see
+    /// [AgentBuilder::tool_quota]) is exhausted
+    fn exhausted_tool(&self, toolname: &str, calls: &mut HashMap<String, usize>) -> Option<String> {
+        let quota = *self.tool_quotas.get(toolname)?;
+        let calls = calls.entry(toolname.to_string()).or_default();
+        if *calls >= quota {
+            tracing::info!(target: "rig", "Quota of tool `{toolname}` exhausted ({quota} calls)");
+            return Some(format!(
+                "The tool `{toolname}` is exhausted: it can be called at most {quota} times and \
+                cannot be called anymore. Answer with the information you already have."
+            ));
+        }
+        *calls += 1;
+        None
+    }
+
+    /// Tool loop of [Agent::run_with_images], returning the final answer of the model (or the
+    /// output of the last tool called) before its transforms
+    async fn tool_loop(
+        &self,
+        prompt: &str,
+        mut chat_history: Vec<Message>,
+        mut images: Vec<Image>,
+        mut trace: Option<&mut Vec<Step>>,
+        filter: &ToolFilter,
+    ) -> Result<String, PromptError> {
+        let filter = &self.with_snapshot(filter);
+        let variants = self.query_variants(prompt).await?;
+        let mut prompt = prompt.to_string();
+        let mut tool_calls = HashMap::new();
+
+        for turn in 1..=self.max_turns {
+            let turn_images = std::mem::take(&mut images);
+            let (request, dropped_context) = self
+                .budgeted_completion(&prompt, chat_history.clone(), &variants, filter)
+                .await?;
+            let mut request = request.images(turn_images).build();
+            self.apply_token_budget(&mut request)?;
+
+            let max_tokens = request.max_tokens;
+            let retried = self.retried_request(&request);
+            let mut response = self.model.completion(request).await?;
+
+            // Re-request empty completions, nudging the model towards a non-empty answer
+            let mut empty_retries = 0;
+            if let Some(retried) = &retried {
+                while matches!(&response.choice, ModelChoice::Message(msg) if msg.trim().is_empty())
+                    && empty_retries < self.empty_response_retry.max_retries
+                {
+                    let retry = self.empty_response_retry(retried, empty_retries).await;
+                    empty_retries += 1;
+                    response = self.model.completion(retry).await?;
+                }
+            }
+
+            let usage = M::usage(&response.raw_response);
+            let retries = M::retries(&response.raw_response) + empty_retries;
+            let reasoning = M::reasoning(&response.raw_response);
+            let choice = response.choice;
+
+            let (toolname, args) = match choice {
+                ModelChoice::Message(msg) => {
+                    let answer = match &self.reasoning_stripper {
+                        Some(stripper) => stripper.strip(&msg),
+                        None => StrippedAnswer::from(msg.clone()),
+                    }
+                    .with_reasoning(reasoning);
+                    if let Some(reasoning) = answer.reasoning() {
+                        tracing::debug!(target: "rig", "Chain-of-thought of the answer: {reasoning}");
+                    }
+
+                    if let Some(trace) = trace.as_mut() {
+                        trace.push(Step {
+                            prompt,
+                            choice: ModelChoice::Message(msg),
+                            tool_result: None,
+                            max_tokens,
+                            usage,
+                            retries,
+                            sources: vec![],
+                            dropped_context,
+                            reasoning: answer.reasoning().map(str::to_string),
+                        });
+                    }
+                    return Ok(answer.into_answer());
+                }
+                ModelChoice::ToolCall(toolname, args) => (toolname, args),
+            };
+
+            if !filter.allows(&toolname) {
+                return Err(ToolSetError::ToolNotFoundError(toolname).into());
+            }
+            let result = match self.exhausted_tool(&toolname, &mut tool_calls) {
+                Some(exhausted) => Ok((exhausted, vec![])),
+                None => {
+                    self.call_tools(filter)
+                        .toolset
+                        .call_with_sources(&toolname, args.to_string())
+                        .await
+                }
+            };
+            let (output, sources) = match result {
+                Ok(result) => result,
+                Err(ToolSetError::ToolCallError(ToolError::NeedsInput(input_prompt))) => {
+                    push_tool_call(&mut chat_history, prompt, &toolname, &args);
+                    return Err(PromptError::NeedsInput(Box::new(PendingInput {
+                        prompt: input_prompt,
+                        toolname,
+                        args,
+                        chat_history,
+                        filter: filter.clone(),
+                    })));
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if let Some(trace) = trace.as_mut() {
+                trace.push(Step {
+                    prompt: prompt.clone(),
+                    choice: ModelChoice::ToolCall(toolname.clone(), args.clone()),
+                    tool_result: Some(output.clone()),
+                    max_tokens,
+                    usage,
+                    retries,
+                    sources,
+                    dropped_context,
+                    reasoning,
+                });
+            }
+
+            if turn == self.max_turns {
+                return Ok(output);
+            }
+
+            // Feed the tool call and its result back to the model
+            push_tool_call(&mut chat_history, prompt, &toolname, &args);
+            prompt = format!("Result of tool `{toolname}`: {output}");
+
+            if self.stops(&chat_history, &prompt) {
+                return Ok(output);
+            }
+        }
+
+        unreachable!("max_turns is at least 1")
+    }
+
+    /// Cap the `max_tokens` of `request` to the remainder of the token budget of the agent
+    /// (see [AgentBuilder::with_token_budget]), failing if the prompt alone exceeds it
+    fn apply_token_budget(&self, request: &mut CompletionRequest) -> Result<(), PromptError> {
+        let Some(budget) = self.token_budget else {
+            return Ok(());
+        };
+
+        let prompt_tokens = request.estimated_prompt_tokens();
+        if prompt_tokens >= budget {
+            return Err(PromptError::BudgetExceeded {
+                budget,
+                prompt_tokens,
+            });
+        }
+
+        let remaining = budget - prompt_tokens;
+        request.max_tokens = Some(
+            request
+                .max_tokens
+                .map_or(remaining, |max_tokens| max_tokens.min(remaining)),
+        );
+        Ok(())
+    }
+
+    /// Copy of `request` to re-send if its completion is empty, if empty completions are
+    /// re-requested (see [AgentBuilder::retry_empty_responses])
+    fn retried_request(&self, request: &CompletionRequest) -> Option<CompletionRequest> {
+        (self.empty_response_retry.max_retries > 0).then(|| request.clone())
+    }
+
+    /// Wait for the backoff of the `retries`-th re-request of an empty completion of `request`
+    /// (see [AgentBuilder::retry_empty_responses]), returning the request to re-send: `request`
+    /// as built for the first attempt (i.e.: without retrieving its context and tools again),
+    /// with the nudge appended to its prompt and without its idempotency key.
+    async fn empty_response_retry(
+        &self,
+        request: &CompletionRequest,
+        retries: usize,
+    ) -> CompletionRequest {
+        tracing::warn!(target: "rig",
+            "Empty completion, retrying ({}/{})",
+            retries + 1,
+            self.empty_response_retry.max_retries
+        );
+        futures_timer::Delay::new(self.empty_response_retry.delay(retries)).await;
+
+        let mut retry = request.clone();
+        if let Some(nudge) = &self.empty_response_nudge {
+            retry.prompt = format!("{}\n\n{nudge}", request.prompt);
+        }
+        // The retry is a distinct request, which the provider must not deduplicate
+        retry.idempotency_key = None;
+        retry
+    }
+
+    /// Whether the stop condition of the agent (see [AgentBuilder::with_stop_condition]) is met
+    /// by the conversation `chat_history` followed by the next `prompt` (the tool result)
+    fn stops(&self, chat_history: &[Message], prompt: &str) -> bool {
+        let Some(stop_condition) = &self.stop_condition else {
+            return false;
+        };
+
+        let messages = [
+            chat_history,
+            &[Message {
+                role: "user".into(),
+                content: prompt.to_string(),
+            }],
+        ]
+        .concat();
+        stop_condition(&messages)
+    }
+
+    /// The variants of the top-level `prompt` of a call generated by the model (see
+    /// [AgentBuilder::query_expansion]), used along with the prompt of each request of the call
+    /// to retrieve the dynamic context and tools. The expansion request is sent once per call:
+    /// the variants are reused by the requests of the tool loop and their retries.
+    async fn query_variants(&self, prompt: &str) -> Result<Vec<String>, CompletionError> {
+        let mut queries = vec![prompt.to_string()];
+        let variants = match self.query_expansion {
+            Some(variants)
+                if variants > 0
+                    && !(self.dynamic_context.is_empty()
+                        && self.context_retrievers.is_empty()
+                        && self.dynamic_tools.is_empty()) =>
+            {
+                variants
+            }
+            _ => return Ok(vec![]),
+        };
+
+        let response = self
+            .model
+            .completion_request(prompt)
+            .preamble(format!(
+                "Rewrite the search query of the user into {variants} alternative queries which \
+                would help retrieve relevant documents (e.g.: using synonyms, expanding \
+                abbreviations or adding context). Answer with one query per line, without \
+                numbering or any other text."
+            ))
+            .temperature_opt(self.temperature)
+            .send()
+            .await?;
+
+        let ModelChoice::Message(text) = response.choice else {
+            tracing::warn!(target: "rig", "Query expansion did not return a message");
+            return Ok(vec![]);
+        };
+        for line in text.lines() {
+            // Strip list markers (e.g.: "1.", "-") the model may add anyway
+            let variant = line
+                .trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .trim_start_matches(['.', ')', '-', '*'])
+                .trim();
+            if queries.len() > variants {
+                break;
+            }
+            if !variant.is_empty() && !queries.iter().any(|query| query == variant) {
+                queries.push(variant.to_string());
+            }
+        }
+
+        Ok(queries.split_off(1))
+    }
+
+    /// Build the completion request of `prompt` with only the tools allowed by `filter`,
+    /// retrieving the dynamic context and tools with `prompt` and the `variants` of the prompt of
+    /// the call (see [Agent::query_variants])
+    async fn filtered_completion(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+        variants: &[String],
+        filter: &ToolFilter,
+    ) -> Result<CompletionRequestBuilder<M>, CompletionError> {
+        let (request, _) = self
+            .budgeted_completion(prompt, chat_history, variants, filter)
+            .await?;
+        Ok(request)
+    }
+
+    /// Same as [Agent::filtered_completion], also returning the context dropped from the
+    /// request to fit the context budgets of the agent
+    async fn budgeted_completion(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+        variants: &[String],
+        filter: &ToolFilter,
+    ) -> Result<(CompletionRequestBuilder<M>, DroppedContext), CompletionError> {
+        let mut queries = vec![prompt.to_string()];
+        queries.extend(
+            variants
+                .iter()
+                .filter(|variant| *variant != prompt)
+                .cloned(),
+        );
+        let snapshot = self.call_tools(filter);
+        let tools = &snapshot;
+
+        let dynamic_context = stream::iter(self.dynamic_context.iter())
+            .then(|(num_sample, index)| async {
+                let mut docs = Vec::<Document>::new();
+                for query in &queries {
+                    for (_, id, doc) in index.top_n(query, *num_sample).await? {
+                        if docs.iter().any(|document| document.id == id) {
+                            continue;
+                        }
+
+                        // Pretty print the document if possible for better readability
+                        let text =
+                            serde_json::to_string_pretty(&doc).unwrap_or_else(|_| doc.to_string());
+
+                        docs.push(Document {
+                            id,
+                            text,
+                            additional_props: HashMap::new(),
+                        });
+                    }
+                }
+                Ok::<_, VectorStoreError>(docs)
+            })
+            .try_fold(vec![], |mut acc, docs| async {
+                acc.extend(docs);
+                Ok(acc)
+            })
+            .await
+            .map_err(|e| CompletionError::RequestError(Box::new(e)))?;
+
+        let mut static_context = self.static_context.clone();
+        let mut retrieved_context = dynamic_context;
+        for retriever in &self.context_retrievers {
+            let mut docs = Vec::<Document>::new();
+            for retrieved in
+                future::join_all(queries.iter().map(|query| retriever(query.clone()))).await
+            {
+                for doc in retrieved {
+                    if !docs.iter().any(|document| document.id == doc.id) {
+                        docs.push(doc);
+                    }
+                }
+            }
+            retrieved_context.extend(docs);
+        }
+
+        if let Some(policy) = &self.truncation {
+            static_context
+                .iter_mut()
+                .chain(retrieved_context.iter_mut())
+                .for_each(|document| document.text = policy.truncate(&document.text).into_owned());
+        }
+
+        let mut preamble = self.preamble_at_request_time();
+        let mut dropped = DroppedContext::default();
+        if let Some(budget) = self.preamble_budget {
+            let tokens = estimated_tokens(&preamble);
+            if tokens > budget {
+                preamble = TruncationPolicy::new(budget as usize)
+                    .truncate(&preamble)
+                    .into_owned();
+                dropped.tokens += tokens - estimated_tokens(&preamble);
+            }
+            let remaining = budget.saturating_sub(estimated_tokens(&preamble));
+            static_context = fit_documents(static_context, remaining, &mut dropped);
+        }
+        if let Some(budget) = self.retrieved_context_budget {
+            retrieved_context = fit_documents(retrieved_context, budget, &mut dropped);
+        }
+        if !dropped.is_empty() {
+            tracing::debug!(target: "rig",
+                "Dropped {} context documents ({} tokens) to fit the context budgets",
+                dropped.documents.len(),
+                dropped.tokens
+            );
+        }
+        let documents = [static_context, retrieved_context].concat();
+
+        let dynamic_tools = stream::iter(self.dynamic_tools.iter())
+            .then(|(num_sample, index)| async {
+                let mut ids = Vec::<String>::new();
+                for query in &queries {
+                    for (_, id) in index.top_n_ids(query, *num_sample).await? {
+                        if !ids.contains(&id) {
+                            ids.push(id);
+                        }
+                    }
+                }
+                Ok::<_, VectorStoreError>(ids)
+            })
+            .try_fold(vec![], |mut acc, docs| async {
+                for doc in docs.into_iter().filter(|doc| filter.allows(doc)) {
+                    if let Some(tool) = tools.toolset.get(&doc) {
+                        acc.push(tool.definition(prompt.into()).await)
+                    } else {
+                        tracing::warn!("Tool implementation not found in toolset: {}", doc);
+                    }
+                }
+                Ok(acc)
+            })
+            .await
+            .map_err(|e| CompletionError::RequestError(Box::new(e)))?;
+
+        let static_tools = stream::iter(
+            tools
+                .static_tools
+                .iter()
+                .filter(|toolname| filter.allows(toolname)),
+        )
+        .filter_map(|toolname| async move {
+            if let Some(tool) = tools.toolset.get(toolname) {
+                Some(tool.definition(prompt.into()).await)
+            } else {
+                tracing::warn!("Tool implementation not found in toolset: {}", toolname);
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+        let request = self
+            .model
+            .completion_request(prompt)
+            .preamble(preamble)
+            .messages(chat_history)
+            .documents(documents)
+            .tools([static_tools.clone(), dynamic_tools].concat())
+            .temperature_opt(self.temperature)
+            .max_tokens_opt(self.max_tokens)
+            .additional_params_opt(self.additional_params.clone())
+            .native_tools(self.native_tools.clone());
+        Ok((request, dropped))
+    }
+}
+
+impl<M: CompletionModel> Completion<M> for Agent<M> {
+    async fn completion(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+    ) -> Result<CompletionRequestBuilder<M>, CompletionError> {
+        let variants = self.query_variants(prompt).await?;
+        self.filtered_completion(prompt, chat_history, &variants, &ToolFilter::default())
+            .await
+    }
+}
+
+impl<M: StreamingCompletionModel> Agent<M> {
+    /// Run the tool loop of the agent on `input` and stream its final answer as a JSON `T`,
+    /// yielding [PartialExtraction::Partial] values as the answer is received (dropping the
+    /// parts of the answer which cannot be parsed yet) and ending with the
+    /// [PartialExtraction::Complete] value parsed from the full answer.
+    ///
+    /// The steps of the tool loop are not streamed: they are only sent (and their tools called)
+    /// until the model answers with text. They follow the same rules as [Prompt::prompt] (token
+    /// budget, empty answers, stop condition and tools needing input, see
+    /// [Agent::stream_prompt]): in particular, the output of the last tool call is parsed as the
+    /// answer if the loop ends without one. Fields of `T` which may be missing from a partial
+    /// answer should be optional (or have a default value), otherwise no partial value is
+    /// yielded until they are received.
+    ///
+    /// # Example
+    /// ```rust
+    /// use futures::StreamExt;
+    /// use rig::extractor::PartialExtraction;
+    ///
+    /// let mut stream = agent.extract_stream::<Person>("Who founded Flurbo Corp?").await?;
+    /// while let Some(extraction) = stream.next().await {
+    ///     match extraction? {
+    ///         PartialExtraction::Partial(person) => println!("So far: {person:?}"),
+    ///         PartialExtraction::Complete(person) => println!("Done: {person:?}"),
+    ///     }
+    /// }
+    /// ```
+    pub async fn extract_stream<T: DeserializeOwned + Send + 'static>(
+        &self,
+        input: &str,
+    ) -> Result<ExtractionStream<T>, ExtractionError> {
+        self.check_streaming()?;
+        let filter = self.with_snapshot(&ToolFilter::default());
+        let variants = self
+            .query_variants(input)
+            .await
+            .map_err(PromptError::from)?;
+        let mut prompt = input.to_string();
+        let mut chat_history = vec![];
+        let mut tool_calls = HashMap::new();
+
+        for turn in 1..=self.max_turns {
+            let mut request = self
+                .filtered_completion(&prompt, chat_history.clone(), &variants, &filter)
+                .await
+                .map_err(PromptError::from)?
+                .build();
+            self.apply_token_budget(&mut request)?;
+            let retried = self.retried_request(&request);
+            let mut empty_retries = 0;
+            let (toolname, args) = loop {
+                let mut stream = self
+                    .model
+                    .stream(request)
+                    .await
+                    .map_err(PromptError::from)?;
+
+                // Wait for the model to either answer or call a tool
+                let mut tool_call = None;
+                while let Some(event) = stream.next().await {
+                    match event.map_err(PromptError::from)? {
+                        StreamEvent::Delta(text) if !text.trim().is_empty() => {
+                            return Ok(partial_extractions(text, stream));
+                        }
+                        StreamEvent::ToolCall { name, arguments } => {
+                            tool_call = Some((name, arguments));
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+
+                match (tool_call, &retried) {
+                    (Some(tool_call), _) => break tool_call,
+                    (None, Some(retried))
+                        if empty_retries < self.empty_response_retry.max_retries =>
+                    {
+                        request = self.empty_response_retry(retried, empty_retries).await;
+                        empty_retries += 1;
+                    }
+                    (None, _) => return Err(ExtractionError::NoData),
+                }
+            };
+
+            let result = match self.exhausted_tool(&toolname, &mut tool_calls) {
+                Some(exhausted) => Ok(exhausted),
+                None => {
+                    self.call_tools(&filter)
+                        .toolset
+                        .call(&toolname, args.to_string())
+                        .await
+                }
+            };
+            let output = match result {
+                Ok(output) => output,
+                Err(ToolSetError::ToolCallError(ToolError::NeedsInput(input_prompt))) => {
+                    push_tool_call(&mut chat_history, prompt, &toolname, &args);
+                    return Err(PromptError::NeedsInput(Box::new(PendingInput {
+                        prompt: input_prompt,
+                        toolname,
+                        args,
+                        chat_history,
+                        filter,
+                    }))
+                    .into());
+                }
+                Err(e) => return Err(PromptError::from(e).into()),
+            };
+
+            // Feed the tool call and its result back to the model
+            push_tool_call(&mut chat_history, prompt, &toolname, &args);
+            prompt = format!("Result of tool `{toolname}`: {output}");
+
+            // As with [Prompt::prompt], the output of the last tool call is the answer
+            if turn == self.max_turns || self.stops(&chat_history, &prompt) {
+                return Ok(partial_extractions(
+                    output,
+                    Box::pin(stream::empty::<Result<StreamEvent, CompletionError>>()),
+                ));
+            }
+        }
+
+        unreachable!("max_turns is at least 1")
+    }
+
+    /// Run the tool loop of the agent on `prompt`, streaming the responses of the model: the
+    /// deltas of the answer, the tool calls (followed by the progress of the tools, see
+    /// [StreamEvent::ToolProgress]) and a final [StreamEvent::Done] event with the total token
+    /// usage of the requests, if reported by the provider. As with [Prompt::prompt], the output
+    /// of the last tool call is the answer (streamed as a single delta) if the loop reaches
+    /// [max_turns](AgentBuilder::max_turns).
+    ///
+    /// Only the first tool call of each response is executed.
+    ///
+    /// The requests are built and checked as with [Prompt::prompt]: the token budget (see
+    /// [AgentBuilder::with_token_budget]) and the stop condition (see
+    /// [AgentBuilder::with_stop_condition]) apply, empty answers are re-requested (see
+    /// [AgentBuilder::retry_empty_responses]) and a tool needing input ends the stream with a
+    /// [PromptError::NeedsInput] error, which can be resumed with [Agent::resume]. However, the
+    /// deltas are not transformed (see [AgentBuilder::output_transform]), and agents stripping
+    /// the chain-of-thought of their answers (see [AgentBuilder::strip_reasoning]) fail with a
+    /// [PromptError::UnsupportedOption] error, as the chain-of-thought can only be told apart
+    /// from the answer once the answer is complete.
+    ///
+    /// # Example
+    /// ```rust
+    /// use futures::StreamExt;
+    /// use rig::streaming::StreamEvent;
+    ///
+    /// let mut stream = supervisor.stream_prompt("What is a flurbo?");
+    /// while let Some(event) = stream.next().await {
+    ///     match event? {
+    ///         StreamEvent::Delta(delta) => print!("{delta}"),
+    ///         StreamEvent::ToolProgress { tool, delta } => println!("[{tool}] {delta}"),
+    ///         _ => (),
+    ///     }
+    /// }
+    /// ```
+    pub fn stream_prompt<'a>(&'a self, prompt: &str) -> AgentStream<'a> {
+        self.stream(prompt, false)
+    }
+
+    /// Same as [Agent::stream_prompt], but the model is instructed to cite the context
+    /// documents and the sources of the tool results (see [Tool::sources]) its answer is based
+    /// on with their id in square brackets (e.g.: `[doc1]`). The markers are resolved to their
+    /// sources as the answer is streamed: each cited source is emitted once, as a
+    /// [StreamEvent::Citation] following the delta completing its first marker. Ids which do
+    /// not match any source are logged as a warning at the end of the stream.
+    ///
+    /// # Example
+    /// ```rust
+    /// use futures::StreamExt;
+    /// use rig::streaming::StreamEvent;
+    ///
+    /// let mut stream = agent.stream_prompt_with_citations("What is a flurbo?");
+    /// while let Some(event) = stream.next().await {
+    ///     match event? {
+    ///         StreamEvent::Delta(delta) => print!("{delta}"),
+    ///         StreamEvent::Citation(source) => println!("\n[{}]: {:?}", source.id, source.url),
+    ///         _ => (),
+    ///     }
+    /// }
+    /// ```
+    pub fn stream_prompt_with_citations<'a>(&'a self, prompt: &str) -> AgentStream<'a> {
+        self.stream(prompt, true)
+    }
+
+    fn stream<'a>(&'a self, prompt: &str, cite: bool) -> AgentStream<'a> {
+        let (events, receiver) = mpsc::unbounded();
+        let prompt = prompt.to_string();
+        let tool_loop = async move {
+            if let Err(err) = self.stream_tool_loop(prompt, cite, &events).await {
+                let _ = events.unbounded_send(Err(err));
+            }
+        };
+
+        // The events of the tool loop, including the progress reported by the tools while they
+        // are being called, are sent through the channel
+        Box::pin(
+            stream::select(receiver.map(Some), tool_loop.into_stream().map(|()| None))
+                .filter_map(future::ready),
+        )
+    }
+
+    /// Tool loop of [Agent::stream_prompt], sending its events to `events` and resolving the
+    /// citations of the answer if `cite` (see [Agent::stream_prompt_with_citations])
+    async fn stream_tool_loop(
+        &self,
+        mut prompt: String,
+        cite: bool,
+        events: &mpsc::UnboundedSender<Result<StreamEvent, PromptError>>,
+    ) -> Result<(), PromptError> {
+        let send = |event: StreamEvent| {
+            let _ = events.unbounded_send(Ok(event));
+        };
+        // Send a delta of the answer, followed by the sources it cites
+        let send_delta = |delta: String, citations: &mut Option<CitationResolver>| {
+            let cited = citations
+                .as_mut()
+                .map(|citations| citations.push(&delta))
+                .unwrap_or_default();
+            send(StreamEvent::Delta(delta));
+            cited
+                .into_iter()
+                .for_each(|source| send(StreamEvent::Citation(source)));
+        };
+        self.check_streaming()?;
+        let filter = self.with_snapshot(&ToolFilter::default());
+        let variants = self.query_variants(&prompt).await?;
+        let mut chat_history = vec![];
+        let mut usage: Option<Usage> = None;
+        let mut tool_calls = HashMap::new();
+        let mut citations = cite.then(CitationResolver::default);
+
+        for turn in 1..=self.max_turns {
+            let mut request = self
+                .filtered_completion(&prompt, chat_history.clone(), &variants, &filter)
+                .await?;
+            if cite {
+                request = request.system(CITATION_INSTRUCTION.to_string());
+            }
+            let mut request = request.build();
+            self.apply_token_budget(&mut request)?;
+            if let Some(citations) = &mut citations {
+                citations.add_sources(request.documents.iter().map(document_source));
+            }
+            let retried = self.retried_request(&request);
+            let mut empty_retries = 0;
+            let tool_call = loop {
+                let mut stream = self.model.stream(request).await?;
+
+                let mut tool_call = None;
+                let mut answered = false;
+                // Whitespace deltas are held back until the answer is known to be non-empty,
+                // so that the deltas of empty answers are not streamed before their retries
+                let mut blank = String::new();
+                while let Some(event) = stream.next().await {
+                    match event? {
+                        StreamEvent::Done {
+                            usage: Some(request_usage),
+                        } => {
+                            usage =
+                                Some(usage.map_or(request_usage, |total| {
+                                    add_usage(total, request_usage)
+                                }));
+                        }
+                        StreamEvent::Done { usage: None } => (),
+                        StreamEvent::ToolCall { name, arguments } if tool_call.is_none() => {
+                            send(StreamEvent::ToolCall {
+                                name: name.clone(),
+                                arguments: arguments.clone(),
+                            });
+                            tool_call = Some((name, arguments));
+                        }
+                        StreamEvent::ToolCall { name, .. } => {
+                            tracing::warn!(target: "rig",
+                                "Ignoring call to tool `{name}` following another tool call"
+                            );
+                        }
+                        StreamEvent::Delta(delta) if answered => send_delta(delta, &mut citations),
+                        StreamEvent::Delta(delta) if delta.trim().is_empty() => {
+                            blank.push_str(&delta);
+                        }
+                        StreamEvent::Delta(delta) => {
+                            answered = true;
+                            send_delta(std::mem::take(&mut blank) + &delta, &mut citations);
+                        }
+                        event => send(event),
+                    }
+                }
+
+                // Re-request empty answers, as with [Prompt::prompt]
+                match &retried {
+                    Some(retried)
+                        if tool_call.is_none()
+                            && !answered
+                            && empty_retries < self.empty_response_retry.max_retries =>
+                    {
+                        request = self.empty_response_retry(retried, empty_retries).await;
+                        empty_retries += 1;
+                    }
+                    _ => {
+                        if !blank.is_empty() {
+                            send_delta(blank, &mut citations);
+                        }
+                        break tool_call;
+                    }
+                }
+            };
+
+            let Some((toolname, args)) = tool_call else {
+                citations.iter().for_each(CitationResolver::finish);
+                send(StreamEvent::Done { usage });
+                return Ok(());
+            };
+
+            let progress = {
+                let events = events.clone();
+                let tool = toolname.clone();
+                ToolProgress::new(move |delta| {
+                    let _ = events.unbounded_send(Ok(StreamEvent::ToolProgress {
+                        tool: tool.clone(),
+                        delta,
+                    }));
+                })
+            };
+            let result = match self.exhausted_tool(&toolname, &mut tool_calls) {
+                Some(exhausted) => Ok((exhausted, vec![])),
+                None => {
+                    self.call_tools(&filter)
+                        .toolset
+                        .call_with_progress(&toolname, args.to_string(), progress)
+                        .await
+                }
+            };
+            let (output, sources) = match result {
+                Ok(result) => result,
+                Err(ToolSetError::ToolCallError(ToolError::NeedsInput(input_prompt))) => {
+                    push_tool_call(&mut chat_history, prompt, &toolname, &args);
+                    return Err(PromptError::NeedsInput(Box::new(PendingInput {
+                        prompt: input_prompt,
+                        toolname,
+                        args,
+                        chat_history,
+                        filter,
+                    })));
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(citations) = &mut citations {
+                citations.add_sources(sources);
+            }
+
+            // Feed the tool call and its result back to the model
+            push_tool_call(&mut chat_history, prompt, &toolname, &args);
+            prompt = format!("Result of tool `{toolname}`: {output}");
+
+            if turn == self.max_turns || self.stops(&chat_history, &prompt) {
+                send_delta(output, &mut citations);
+                citations.iter().for_each(CitationResolver::finish);
+                send(StreamEvent::Done { usage });
+                return Ok(());
+            }
+        }
+
+        unreachable!("max_turns is at least 1")
+    }
+
+    /// Fail the streaming paths of the agent if it has an option they do not support
+    fn check_streaming(&self) -> Result<(), PromptError> {
+        // The chain-of-thought may only be known once the whole answer is received (e.g.: the
+        // text before a closing delimiter without opening delimiter)
+        if self.reasoning_stripper.is_some() {
+            return Err(PromptError::UnsupportedOption(
+                "the chain-of-thought of streamed answers cannot be stripped (see \
+                AgentBuilder::strip_reasoning)"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Add the call of `toolname` with `args` by the model in response to `prompt` to the
+/// conversation `chat_history`
+fn push_tool_call(
+    chat_history: &mut Vec<Message>,
+    prompt: String,
+    toolname: &str,
+    args: &serde_json::Value,
+) {
+    chat_history.push(Message {
+        role: "user".into(),
+        content: prompt,
+    });
+    chat_history.push(Message {
+        role: "assistant".into(),
+        content: format!("Calling tool `{toolname}` with arguments: {args}"),
+    });
+}
+
+/// Instruction sent to the model by [Agent::stream_prompt_with_citations]
+const CITATION_INSTRUCTION: &str = "\
+    Cite the context documents and the tool results your answer is based on with their id in \
+    square brackets, right after the statements they support (e.g.: [doc1] or [doc1, doc2]).";
+
+/// Maximum length of a citation marker (i.e.: of the text following an unclosed `[`) of a
+/// streamed answer, beyond which the `[` is not considered the start of a marker
+const MAX_CITATION_MARKER_LEN: usize = 256;
+
+/// Source of a context document, with the `title` and `url` of its metadata if any
+fn document_source(document: &Document) -> Source {
+    let mut source = Source::new(&document.id);
+    source.title = document.additional_props.get("title").cloned();
+    source.url = document.additional_props.get("url").cloned();
+    source
+}
+
+/// Resolver of the citation markers of a streamed answer (see
+/// [Agent::stream_prompt_with_citations])
+#[derive(Default)]
+struct CitationResolver {
+    /// Sources which can be cited, deduplicated by id
+    sources: Vec<Source>,
+    /// Ids of the sources cited so far
+    cited: Vec<String>,
+    /// Cited ids which do not match any source
+    unresolved: Vec<String>,
+    /// Text of the answer from the last unclosed `[`, whose marker is not fully received yet
+    pending: String,
+}
+
+impl CitationResolver {
+    fn add_sources(&mut self, sources: impl IntoIterator<Item = Source>) {
+        for source in sources {
+            if !self.sources.iter().any(|known| known.id == source.id) {
+                self.sources.push(source);
+            }
+        }
+    }
+
+    /// Resolve the markers completed by `delta` of the answer, returning the sources cited
+    /// for the first time
+    fn push(&mut self, delta: &str) -> Vec<Source> {
+        let text = std::mem::take(&mut self.pending) + delta;
+        let mut rest = text.as_str();
+        let mut cited = vec![];
+
+        while let Some(start) = rest.find('[') {
+            let marker = &rest[start + 1..];
+            let Some(end) = marker.find(']') else {
+                if marker.len() <= MAX_CITATION_MARKER_LEN {
+                    self.pending = rest[start..].to_string();
+                }
+                break;
+            };
+
+            // The content of nested brackets (e.g.: `[[doc1]]`) is the marker
+            let ids = marker[..end].rsplit('[').next().unwrap_or_default();
+            cited.extend(ids.split(',').filter_map(|id| self.resolve(id.trim())));
+            rest = &marker[end + 1..];
+        }
+
+        cited
+    }
+
+    fn resolve(&mut self, id: &str) -> Option<Source> {
+        // Bracketed text which is not an id (e.g.: the text of a markdown link)
+        if id.is_empty()
+            || id.contains(char::is_whitespace)
+            || self.cited.iter().any(|cited| cited == id)
+        {
+            return None;
+        }
+
+        match self.sources.iter().find(|source| source.id == id) {
+            Some(source) => {
+                self.cited.push(id.to_string());
+                Some(source.clone())
+            }
+            None => {
+                if !self.unresolved.iter().any(|unresolved| unresolved == id) {
+                    self.unresolved.push(id.to_string());
+                }
+                None
+            }
+        }
+    }
+
+    /// Warn about the cited ids which do not match any source, at the end of the stream
+    fn finish(&self) {
+        if !self.unresolved.is_empty() {
+            tracing::warn!(target: "rig",
+                "The streamed answer cites unknown sources: {}",
+                self.unresolved.join(", ")
+            );
+        }
+    }
+}
+
+/// Parse the text streamed by `events` (starting with `text`) as a JSON `T` (see
+/// [Agent::extract_stream]).
+fn partial_extractions<T: DeserializeOwned + Send + 'static>(
+    text: String,
+    events: StreamingResult,
+) -> ExtractionStream<T> {
+    struct State {
+        stream: StreamingResult,
+        text: String,
+        pending: Option<String>,
+        /// Last partial value yielded
+        last: Option<serde_json::Value>,
+        ended: bool,
+    }
+
+    Box::pin(stream::unfold(
+        State {
+            stream: events,
+            text: String::new(),
+            pending: Some(text),
+            last: None,
+            ended: false,
+        },
+        |mut state| async move {
+            loop {
+                if state.ended {
+                    return None;
+                }
+
+                let delta = match state.pending.take() {
+                    Some(delta) => delta,
+                    None => match state.stream.next().await {
+                        Some(Ok(StreamEvent::Delta(delta))) => delta,
+                        Some(Ok(StreamEvent::ToolCall { name, .. })) => {
+                            tracing::warn!(target: "rig",
+                                "Ignoring call to tool `{name}` in the answer of a streaming extraction"
+                            );
+                            continue;
+                        }
+                        Some(Ok(StreamEvent::ToolProgress { .. })) => continue,
+                        Some(Ok(StreamEvent::Done { .. })) | None => {
+                            state.ended = true;
+                            let json = json_enforcer::strip_code_fences(&state.text);
+                            let extraction = serde_json::from_str(json)
+                                .map(PartialExtraction::Complete)
+                                .map_err(ExtractionError::from);
+                            return Some((extraction, state));
+                        }
+                        Some(Err(err)) => {
+                            state.ended = true;
+                            return Some((Err(PromptError::from(err).into()), state));
+                        }
+                    },
+                };
+
+                state.text.push_str(&delta);
+                if let Some(value) = json_utils::parse_partial_json(&state.text) {
+                    if state.last.as_ref() != Some(&value) {
+                        if let Ok(partial) = T::deserialize(&value) {
+                            state.last = Some(value);
+                            return Some((Ok(PartialExtraction::Partial(partial)), state));
+                        }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+impl<M: CompletionModel> Prompt for Agent<M> {
+    async fn prompt(&self, prompt: &str) -> Result<String, PromptError> {
+        self.chat(prompt, vec![]).await
+    }
+}
+
+impl<M: CompletionModel> Prompt for &Agent<M> {
+    async fn prompt(&self, prompt: &str) -> Result<String, PromptError> {
+        self.chat(prompt, vec![]).await
+    }
+}
+
+impl<M: CompletionModel> PromptWithStats for Agent<M> {
+    async fn prompt_with_stats(&self, prompt: &str) -> Result<(String, CallStats), PromptError> {
+        let start = Instant::now();
+        let mut steps = vec![];
+        let answer = self
+            .run(prompt, vec![], Some(&mut steps), &ToolFilter::default())
+            .await?;
+
+        Ok((
+            answer,
+            CallStats {
+                latency: start.elapsed(),
+                retries: steps.iter().map(|step| step.retries).sum(),
+                cached: false,
+            },
+        ))
+    }
+}
+
+impl<M: CompletionModel> Chat for Agent<M> {
+    async fn chat(&self, prompt: &str, chat_history: Vec<Message>) -> Result<String, PromptError> {
+        self.run(prompt, chat_history, None, &ToolFilter::default())
+            .await
+    }
+}
+
+/// An agent whose available tools are restricted for the requests made through it, created
+/// with [Agent::allowed_tools] or [Agent::denied_tools]. The agent itself is left unchanged.
+pub struct ToolRestrictedAgent<'a, M: CompletionModel> {
+    agent: &'a Agent<M>,
+    filter: ToolFilter,
+}
+
+impl<'a, M: CompletionModel> ToolRestrictedAgent<'a, M> {
+    fn new(agent: &'a Agent<M>) -> Self {
+        Self {
+            agent,
+            filter: ToolFilter::default(),
+        }
+    }
+
+    /// Restrict the available tools to `tools` (replacing any previous allow-list).
+    /// An empty list means that no tools are available.
+    pub fn allowed_tools(mut self, tools: &[&str]) -> Self {
+        self.filter.allowed = Some(tools.iter().map(|tool| tool.to_string()).collect());
+        self
+    }
+
+    /// Make `tools` unavailable (in addition to any previously denied tools)
+    pub fn denied_tools(mut self, tools: &[&str]) -> Self {
+        self.filter
+            .denied
+            .extend(tools.iter().map(|tool| tool.to_string()));
+        self
+    }
+}
+
+impl<M: CompletionModel> Completion<M> for ToolRestrictedAgent<'_, M> {
+    async fn completion(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+    ) -> Result<CompletionRequestBuilder<M>, CompletionError> {
+        let variants = self.agent.query_variants(prompt).await?;
+        self.agent
+            .filtered_completion(prompt, chat_history, &variants, &self.filter)
+            .await
+    }
+}
+
+impl<M: CompletionModel> Prompt for ToolRestrictedAgent<'_, M> {
+    async fn prompt(&self, prompt: &str) -> Result<String, PromptError> {
+        self.chat(prompt, vec![]).await
+    }
+}
+
+impl<M: CompletionModel> Chat for ToolRestrictedAgent<'_, M> {
+    async fn chat(&self, prompt: &str, chat_history: Vec<Message>) -> Result<String, PromptError> {
+        self.agent
+            .run(prompt, chat_history, None, &self.filter)
+            .await
+    }
+}
+
+/// A builder for creating an agent
+///
+/// # Example
+/// ```
+/// use rig::{providers::openai, agent::AgentBuilder};
+///
+/// let openai = openai::Client::from_env();
+///
+/// let gpt4o = openai.completion_model("gpt-4o");
+///
+/// // Configure the agent
+/// let agent = AgentBuilder::new(model)
+///     .preamble("System prompt")
+///     .context("Context document 1")
+///     .context("Context document 2")
+///     .tool(tool1)
+///     .tool(tool2)
+///     .temperature(0.8)
+///     .additional_params(json!({"foo": "bar"}))
+///     .build();
+/// ```
+pub struct AgentBuilder<M: CompletionModel> {
+    /// Completion model (e.g.: OpenAI's gpt-3.5-turbo-1106, Cohere's command-r)
+    model: M,
+    /// System prompt
+    preamble: Option<String>,
+    /// Context documents always available to the agent
+    static_context: Vec<Document>,
+    /// Tools that are always available to the agent (by name)
+    static_tools: Vec<String>,
+    /// Additional parameters to be passed to the model
+    additional_params: Option<serde_json::Value>,
+    /// Maximum number of tokens for the completion
+    max_tokens: Option<u64>,
+    /// List of vector store, with the sample number
+    dynamic_context: Vec<(usize, Box<dyn VectorStoreIndexDyn>)>,
+    /// Custom retrievers of dynamic context
+    context_retrievers: Vec<ContextRetriever>,
+    /// Dynamic tools
+    dynamic_tools: Vec<(usize, Box<dyn VectorStoreIndexDyn>)>,
+    /// Temperature of the model
+    temperature: Option<f64>,
+    /// Actual tool implementations
+    tools: ToolSet,
+    /// Maximum number of completion requests sent per prompt
+    max_turns: usize,
+    /// Maximum number of calls of the tools with a quota per run
+    tool_quotas: HashMap<String, usize>,
+    /// Condition evaluated after each tool call to end the tool loop early
+    stop_condition: Option<StopCondition>,
+    /// Maximum number of tokens (prompt and completion) of each completion request
+    token_budget: Option<u64>,
+    /// Policy for truncating oversized context documents
+    truncation: Option<TruncationPolicy>,
+    /// Maximum number of tokens of the preamble and static context of each request
+    preamble_budget: Option<u64>,
+    /// Maximum number of tokens of the retrieved context of each request
+    retrieved_context_budget: Option<u64>,
+    /// Current date/time appended to the preamble of each request
+    date_injection: Option<DateInjection>,
+    /// Number of variants of the prompt generated to retrieve the dynamic context
+    query_expansion: Option<usize>,
+    /// Store of the conversation histories (see [Agent::prompt_with_history])
+    conversation_store: Box<dyn ConversationStoreDyn>,
+    /// Policy for re-requesting empty completions (see [AgentBuilder::retry_empty_responses])
+    empty_response_retry: RetryPolicy,
+    /// Instruction appended to the prompt when re-requesting an empty completion
+    empty_response_nudge: Option<String>,
+    /// Extractor of the final answer of the samples of a self-consistent prompt
+    answer_extractor: AnswerExtractor,
+    /// Temperature of the samples of a self-consistent prompt
+    self_consistency_temperature: Option<f64>,
+    /// Transforms applied to the final answer (see [AgentBuilder::output_transform])
+    output_transforms: Vec<OutputTransform>,
+    native_tools: Vec<NativeTool>,
+    /// Stripper of the chain-of-thought of the answers
+    reasoning_stripper: Option<ReasoningStripper>,
+}
+
+impl<M: CompletionModel> AgentBuilder<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            preamble: None,
+            static_context: vec![],
+            static_tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            additional_params: None,
+            dynamic_context: vec![],
+            context_retrievers: vec![],
+            dynamic_tools: vec![],
+            tools: ToolSet::default(),
+            max_turns: 1,
+            tool_quotas: HashMap::new(),
+            stop_condition: None,
+            token_budget: None,
+            truncation: None,
+            preamble_budget: None,
+            retrieved_context_budget: None,
+            date_injection: None,
+            query_expansion: None,
+            conversation_store: Box::new(InMemoryConversationStore::new()),
+            empty_response_retry: RetryPolicy::default(),
+            empty_response_nudge: None,
+            answer_extractor: Box::new(default_answer_extractor),
+            output_transforms: vec![],
+            native_tools: vec![],
+            self_consistency_temperature: None,
+            reasoning_stripper: None,
+        }
+    }
+
+    /// Set the system prompt
+    pub fn preamble(mut self, preamble: &str) -> Self {
+        self.preamble = Some(preamble.into());
+        self
+    }
+
+    /// Append to the preamble of the agent
+    pub fn append_preamble(mut self, doc: &str) -> Self {
+        self.preamble = Some(format!(
+            "{}\n{}",
+            self.preamble.unwrap_or_else(|| "".into()),
+            doc
+        ));
+        self
+    }
+
+    /// Add a static context document to the agent
+    pub fn context(mut self, doc: &str) -> Self {
+        self.static_context.push(Document {
+            id: format!("static_doc_{}", self.static_context.len()),
+            text: doc.into(),
+            additional_props: HashMap::new(),
+        });
+        self
+    }
+
+    /// Add a static tool to the agent
+    pub fn tool(mut self, tool: impl Tool + 'static) -> Self {
+        let toolname = tool.name();
+        self.tools.add_tool(tool);
+        self.static_tools.push(toolname);
+        self
+    }
+
+    /// Add some dynamic context to the agent. On each prompt, `sample` documents from the
+    /// dynamic context will be inserted in the request.
+    pub fn dynamic_context(
+        mut self,
+        sample: usize,
+        dynamic_context: impl VectorStoreIndexDyn + 'static,
+    ) -> Self {
+        self.dynamic_context
+            .push((sample, Box::new(dynamic_context)));
+        self
+    }
+
+    /// Add some dynamic context to the agent retrieved by a custom retriever, e.g.: a search
+    /// service that is not a vector store. On each prompt, `retriever` is called with the
+    /// prompt and all the documents it returns are inserted in the request.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::agent::RetrievedDoc;
+    ///
+    /// let agent = openai.agent(openai::GPT_4O)
+    ///     .dynamic_context_fn(|prompt: String| async move {
+    ///         search_service.search(&prompt).await
+    ///             .into_iter()
+    ///             .map(|hit| RetrievedDoc { id: hit.id, text: hit.text, additional_props: HashMap::new() })
+    ///             .collect()
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn dynamic_context_fn<F, Fut>(mut self, retriever: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Vec<RetrievedDoc>> + Send + 'static,
+    {
+        self.context_retrievers
+            .push(Box::new(move |prompt| Box::pin(retriever(prompt))));
+        self
+    }
+
+    /// Add some dynamic tools to the agent. On each prompt, `sample` tools from the
+    /// dynamic toolset will be inserted in the request.
+    pub fn dynamic_tools(
+        mut self,
+        sample: usize,
+        dynamic_tools: impl VectorStoreIndexDyn + 'static,
+        toolset: ToolSet,
+    ) -> Self {
+        self.dynamic_tools.push((sample, Box::new(dynamic_tools)));
+        self.tools.add_tools(toolset);
+        self
+    }
+
+    /// Set the temperature of the model
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the maximum number of tokens for the completion
+    pub fn max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Set additional parameters to be passed to the model
+    pub fn additional_params(mut self, params: serde_json::Value) -> Self {
+        self.additional_params = Some(params);
+        self
+    }
+
+    /// Set the maximum number of completion requests sent to the model per prompt (1 by default).
+    ///
+    /// With the default of 1, the output of a tool called by the model is directly returned.
+    /// With higher values, the agent runs a tool loop: the tool call and its result are added to
+    /// the conversation and sent back to the model, until the model answers with a message, the
+    /// stop condition (see [AgentBuilder::with_stop_condition]) is met or `max_turns` requests
+    /// have been sent. In the last two cases, the output of the last tool call is returned.
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns.max(1);
+        self
+    }
+
+    /// Limit the number of times the tool `toolname` can be called within a single run of the
+    /// tool loop (see [AgentBuilder::max_turns]), e.g.: to cap the cost of a web search tool.
+    /// Once the quota is reached, the tool is not executed anymore: its result tells the model
+    /// that the tool is exhausted, so that it answers with what it already has.
+    ///
+    /// # Example
+    /// ```rust
+    /// let agent = AgentBuilder::new(model)
+    ///     .tool(WebSearch)
+    ///     .max_turns(10)
+    ///     .tool_quota("web_search", 3)
+    ///     .build();
+    /// ```
+    pub fn tool_quota(mut self, toolname: &str, max_calls: usize) -> Self {
+        self.tool_quotas.insert(toolname.to_string(), max_calls);
+        self
+    }
+
+    /// Set a condition evaluated on the conversation after each tool call of the tool loop
+    /// (see [AgentBuilder::max_turns]). The last message of the conversation holds the result
+    /// of the tool call. When the condition returns `true`, the loop ends and the output of
+    /// the tool call is returned.
+    ///
+    /// # Example
+    /// ```rust
+    /// // Stop as soon as the model calls the `submit` tool
+    /// let agent = AgentBuilder::new(model)
+    ///     .tool(Submit)
+    ///     .max_turns(10)
+    ///     .with_stop_condition(|messages| {
+    ///         messages
+    ///             .iter()
+    ///             .any(|message| message.content.starts_with("Calling tool `submit`"))
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_stop_condition(
+        mut self,
+        stop_condition: impl Fn(&[Message]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.stop_condition = Some(Box::new(stop_condition));
+        self
+    }
+
+    /// Set the maximum number of tokens (prompt and completion) of each completion request sent
+    /// by the agent. The size of the prompt is estimated (see
+    /// [CompletionRequest::estimated_prompt_tokens]) and the `max_tokens` of the request is set
+    /// to the remainder of the budget (or kept if lower). If the prompt alone exceeds the
+    /// budget, a [PromptError::BudgetExceeded] error is returned instead.
+    pub fn with_token_budget(mut self, max_total: u64) -> Self {
+        self.token_budget = Some(max_total);
+        self
+    }
+
+    /// Truncate the context documents (static, dynamic or retrieved) longer than the token
+    /// limit of `policy` before sending them to the model.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::truncation::{TruncationPolicy, TruncationStrategy};
+    ///
+    /// let agent = openai.agent(openai::GPT_4O)
+    ///     .dynamic_context(3, index)
+    ///     .truncate_documents(TruncationPolicy::new(1000).strategy(TruncationStrategy::MiddleOut))
+    ///     .build();
+    /// ```
+    pub fn truncate_documents(mut self, policy: TruncationPolicy) -> Self {
+        self.truncation = Some(policy);
+        self
+    }
+
+    /// Set the maximum number of (estimated) tokens of the static part of each request, i.e.:
+    /// the preamble and the static context documents (see [AgentBuilder::context]). The
+    /// static context documents which do not fit in the budget left by the preamble are
+    /// dropped, in order, and the preamble itself is truncated if it exceeds the budget.
+    /// The conversation history is never trimmed.
+    pub fn preamble_budget(mut self, max_tokens: u64) -> Self {
+        self.preamble_budget = Some(max_tokens);
+        self
+    }
+
+    /// Set the maximum number of (estimated) tokens of the context retrieved for each request
+    /// (see [AgentBuilder::dynamic_context] and [AgentBuilder::dynamic_context_fn]), separately
+    /// from the budget of the preamble (see [AgentBuilder::preamble_budget]). The retrieved
+    /// documents are kept in the order they were retrieved until the budget is exhausted, the
+    /// others being dropped (see [Step::dropped_context] and [UsageReport::dropped_context]).
+    /// The conversation history is never trimmed.
+    ///
+    /// # Example
+    /// ```rust
+    /// let agent = openai.agent(openai::GPT_4O)
+    ///     .preamble("You are a dictionary assistant.")
+    ///     .dynamic_context(10, index)
+    ///     .retrieved_context_budget(2000)
+    ///     .build();
+    /// ```
+    pub fn retrieved_context_budget(mut self, max_tokens: u64) -> Self {
+        self.retrieved_context_budget = Some(max_tokens);
+        self
+    }
+
+    /// Append the current date/time to the preamble of each request sent by the agent,
+    /// rendered at request time according to `injection` (see [DateInjection]).
+    pub fn inject_date(mut self, injection: DateInjection) -> Self {
+        self.date_injection = Some(injection);
+        self
+    }
+
+    /// Expand the prompt into `variants` alternative queries before retrieving the dynamic
+    /// context and tools (see [AgentBuilder::dynamic_context], [AgentBuilder::dynamic_context_fn]
+    /// and [AgentBuilder::dynamic_tools]), which helps short or vague prompts retrieve relevant
+    /// documents. The variants are generated by the model of the agent with an additional
+    /// completion request per prompt, and reused by every request sent for the prompt (i.e.:
+    /// the turns of the tool loop and the retries of empty answers). The documents and tools
+    /// retrieved for the prompt and each of its variants are merged, keeping the first of each
+    /// id.
+    ///
+    /// # Example
+    /// ```rust
+    /// let agent = openai.agent(openai::GPT_4O)
+    ///     .dynamic_context(2, index)
+    ///     .query_expansion(3)
+    ///     .build();
+    /// ```
+    pub fn query_expansion(mut self, variants: usize) -> Self {
+        self.query_expansion = Some(variants);
+        self
+    }
+
+    /// Set the store of the conversation histories of [Agent::prompt_with_history] (an
+    /// [InMemoryConversationStore] by default), e.g.: a
+    /// [FileConversationStore](crate::conversation::FileConversationStore) to persist the
+    /// conversations across process restarts.
+    pub fn conversation_store(mut self, store: impl ConversationStore + 'static) -> Self {
+        self.conversation_store = Box::new(store);
+        self
+    }
+
+    /// Re-request completions which are empty (or whitespace only) up to
+    /// `retry_policy.max_retries` times, waiting for the backoff of the policy between
+    /// requests. By default, empty completions are returned as is. The retries are counted in
+    /// the [Step::retries] of the trace of the agent.
+    ///
+    /// The retries re-send the request of the empty completion as built (i.e.: its context and
+    /// tools are not retrieved again). When streaming, whitespace deltas are held back until
+    /// the answer is known to be non-empty, so that the deltas of an empty answer are not
+    /// streamed before its retry.
+    ///
+    /// # Example
+    /// ```rust
+    /// let agent = openai.agent(openai::GPT_4O)
+    ///     .retry_empty_responses(RetryPolicy::new(2).backoff(Duration::from_millis(100)))
+    ///     .empty_response_nudge(EMPTY_RESPONSE_NUDGE)
+    ///     .build();
+    /// ```
+    pub fn retry_empty_responses(mut self, retry_policy: RetryPolicy) -> Self {
+        self.empty_response_retry = retry_policy;
+        self
+    }
+
+    /// Append `nudge` to the prompt when re-requesting an empty completion (see
+    /// [AgentBuilder::retry_empty_responses]), e.g.: [EMPTY_RESPONSE_NUDGE]. By default, the
+    /// prompt is sent unchanged.
+    pub fn empty_response_nudge(mut self, nudge: &str) -> Self {
+        self.empty_response_nudge = Some(nudge.to_string());
+        self
+    }
+
+    /// Set the extractor of the final answer of the samples of
+    /// [Agent::prompt_self_consistent], returning `None` if a response has no answer. By
+    /// default, [default_answer_extractor] is used.
+    ///
+    /// # Example
+    /// ```rust
+    /// let agent = openai.agent(openai::GPT_4O)
+    ///     .answer_extractor(|response| {
+    ///         response.split("####").nth(1).map(|answer| answer.trim().to_string())
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn answer_extractor(
+        mut self,
+        extractor: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.answer_extractor = Box::new(extractor);
+        self
+    }
+
+    /// Set the temperature of the samples of [Agent::prompt_self_consistent]. By default, the
+    /// temperature of the agent is used if above [SELF_CONSISTENCY_TEMPERATURE].
+    pub fn self_consistency_temperature(mut self, temperature: f64) -> Self {
+        self.self_consistency_temperature = Some(temperature);
+        self
+    }
+
+    /// Register a transform of the final answer of the agent (e.g.: trimming it, stripping its
+    /// markdown or [extracting its code block](extract_code_block)). The transforms are applied
+    /// in the order they are registered, once the tool loop has produced the final answer (i.e.:
+    /// after the checks of the completions such as content filtering and the re-requesting of
+    /// empty answers). A failing transform fails the prompt with
+    /// [PromptError::OutputTransformError].
+    ///
+    /// The deltas of streaming prompts (see [Agent::stream_prompt]) are not transformed.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::agent::extract_code_block;
+    ///
+    /// let agent = openai.agent(openai::GPT_4O)
+    ///     .preamble("Answer with a single Python snippet.")
+    ///     .output_transform(extract_code_block)
+    ///     .output_transform(|answer| Ok(answer.replace('\t', "    ")))
+    ///     .build();
+    /// ```
+    pub fn output_transform(
+        mut self,
+        transform: impl Fn(String) -> Result<String, Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.output_transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Enable a tool executed by the provider (e.g.: [NativeTool::WebSearch]) in the requests of
+    /// the agent. Its calls happen within the requests, without going through the tool loop of
+    /// the agent.
+    pub fn native_tool(mut self, tool: NativeTool) -> Self {
+        self.native_tools.push(tool);
+        self
+    }
+
+    /// Strip the chain-of-thought of reasoning models from the answers of the agent with
+    /// `stripper` (e.g.: `<think>...</think>` blocks, see [ReasoningStripper]), so that only the
+    /// final answer is returned. The chain-of-thought is kept in the steps of the tool loop
+    /// (see [Step::reasoning] and [Agent::prompt_with_reasoning]) and logged.
+    ///
+    /// Streaming prompts (see [Agent::stream_prompt] and [Agent::extract_stream]) are not
+    /// supported by agents stripping their chain-of-thought: they fail with a
+    /// [PromptError::UnsupportedOption] error.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::completion::ReasoningStripper;
+    ///
+    /// let agent = client.agent("deepseek-r1").strip_reasoning(ReasoningStripper::default()).build();
+    /// ```
+    pub fn strip_reasoning(mut self, stripper: ReasoningStripper) -> Self {
+        self.reasoning_stripper = Some(stripper);
+        self
+    }
+
+    /// Build the agent
+    pub fn build(self) -> Agent<M> {
+        Agent {
+            model: self.model,
+            preamble: self.preamble.unwrap_or_default(),
+            static_context: self.static_context,
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            additional_params: self.additional_params,
+            dynamic_context: self.dynamic_context,
+            context_retrievers: self.context_retrievers,
+            dynamic_tools: self.dynamic_tools,
+            tools: RwLock::new(Arc::new(AgentTools {
+                toolset: self.tools,
+                static_tools: self.static_tools,
+            })),
+            max_turns: self.max_turns,
+            tool_quotas: self.tool_quotas,
+            stop_condition: self.stop_condition,
+            token_budget: self.token_budget,
+            truncation: self.truncation,
+            preamble_budget: self.preamble_budget,
+            retrieved_context_budget: self.retrieved_context_budget,
+            date_injection: self.date_injection,
+            query_expansion: self.query_expansion,
+            conversation_store: self.conversation_store,
+            empty_response_retry: self.empty_response_retry,
+            empty_response_nudge: self.empty_response_nudge,
+            answer_extractor: self.answer_extractor,
+            self_consistency_temperature: self.self_consistency_temperature,
+            output_transforms: self.output_transforms,
+            native_tools: self.native_tools,
+            reasoning_stripper: self.reasoning_stripper,
+        }
+    }
+}
+
+/// Arguments of an [AgentTool] call.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AgentToolArgs {
+    /// The prompt forwarded to the wrapped agent
+    pub input: String,
+}
+
+/// Adaptor that exposes an [Agent] as a [Tool], allowing specialized sub-agents to be
+/// composed as tools of a supervisor agent.
+///
+/// The tool description should describe the sub-agent's purpose so that the supervisor
+/// knows when to delegate to it. When called, the `input` argument is forwarded to the
+/// sub-agent using [Prompt::prompt] and its response is returned as the tool output.
+///
+/// With [AgentTool::streaming], the sub-agent streams its response instead when called by a
+/// streaming supervisor (see [Agent::stream_prompt]), its deltas being forwarded as
+/// [StreamEvent::ToolProgress] events of the stream of the supervisor.
+///
+/// # Example
+/// ```rust
+/// use rig::{agent::AgentTool, providers::openai};
+///
+/// let openai = openai::Client::from_env();
+///
+/// let translator = openai.agent("gpt-4o")
+///     .preamble("You translate any input text into english.")
+///     .build();
+///
+/// let supervisor = openai.agent("gpt-4o")
+///     .preamble("You answer questions asked in any language.")
+///     .tool(AgentTool::new(
+///         "translator",
+///         "Translate the input text into english.",
+///         translator,
+///     ))
+///     .build();
+/// ```
+pub struct AgentTool<M: CompletionModel> {
+    name: String,
+    description: String,
+    agent: Arc<Agent<M>>,
+    /// Streaming prompt of the agent, forwarding its deltas (see [AgentTool::streaming])
+    stream: Option<SubAgentStream>,
+}
+
+/// Streaming prompt of the agent of an [AgentTool], reporting its deltas as progress
+type SubAgentStream = Box<
+    dyn Fn(String, ToolProgress) -> BoxFuture<'static, Result<String, PromptError>> + Send + Sync,
+>;
+
+impl<M: CompletionModel> AgentTool<M> {
+    /// Wrap `agent` in a tool named `name`. `description` is used as the tool description.
+    pub fn new(name: &str, description: &str, agent: Agent<M>) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            agent: Arc::new(agent),
+            stream: None,
+        }
+    }
+}
+
+impl<M: StreamingCompletionModel + 'static> AgentTool<M> {
+    /// Stream the response of the sub-agent (see [Agent::stream_prompt]) when the tool is
+    /// called by a streaming supervisor, reporting the deltas of the response (and the progress
+    /// of the tools of the sub-agent) as the progress of the call (see [ToolProgress]). The
+    /// output of the tool is the full response, as when called by non-streaming supervisors.
+    ///
+    /// # Example
+    /// ```rust
+    /// let supervisor = openai.agent("gpt-4o")
+    ///     .tool(AgentTool::new("researcher", "Answers research questions.", researcher).streaming())
+    ///     .build();
+    ///
+    /// // The deltas of the researcher are streamed as `StreamEvent::ToolProgress` events
+    /// let stream = supervisor.stream_prompt("What is a flurbo?");
+    /// ```
+    pub fn streaming(mut self) -> Self {
+        let agent = self.agent.clone();
+        self.stream = Some(Box::new(move |input, progress| {
+            let agent = agent.clone();
+            Box::pin(async move {
+                let mut stream = agent.stream_prompt(&input);
+                let mut response = String::new();
+                while let Some(event) = stream.next().await {
+                    match event? {
+                        StreamEvent::Delta(delta) => {
+                            response.push_str(&delta);
+                            progress.send(delta);
+                        }
+                        StreamEvent::ToolProgress { delta, .. } => progress.send(delta),
+                        _ => (),
+                    }
+                }
+                Ok(response)
+            })
+        }));
+        self
+    }
+}
+
+impl<M: CompletionModel> Tool for AgentTool<M> {
+    const NAME: &'static str = "agent";
+
+    type Error = PromptError;
+    type Args = AgentToolArgs;
+    type Output = String;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "input": {
+                        "type": "string",
+                        "description": "The prompt to send to the agent"
+                    }
+                },
+                "required": ["input"]
+            }),
+        }
+    }
+
+    fn call(
+        &self,
+        args: Self::Args,
+    ) -> impl Future<Output = Result<Self::Output, Self::Error>> + Send + Sync {
+        // The completion requests of the agent are only `Send`
+        SyncFuture::new(async move { self.agent.prompt(&args.input).await })
+    }
+
+    async fn call_with_progress(
+        &self,
+        args: Self::Args,
+        progress: ToolProgress,
+    ) -> Result<Self::Output, Self::Error> {
+        match &self.stream {
+            Some(stream) if !progress.is_ignored() => stream(args.input, progress).await,
+            _ => self.call(args).await,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        completion::CompletionResponse,
+        tool::{ToolExample, MAX_TOOL_EXAMPLE_LEN},
+        vector_store::VectorStoreIndex,
+    };
+
+    /// Mock completion model that replays the given choices (one per request) and echoes
+    /// the prompt once they are exhausted. Every request received is recorded.
+    #[derive(Clone, Default)]
+    pub(crate) struct MockCompletionModel {
+        choices: Arc<Mutex<VecDeque<ModelChoice>>>,
+        pub(crate) requests: Arc<Mutex<Vec<CompletionRequest>>>,
+    }
+
+    impl MockCompletionModel {
+        pub(crate) fn new(choices: Vec<ModelChoice>) -> Self {
+            Self {
+                choices: Arc::new(Mutex::new(choices.into())),
+                requests: Arc::new(Mutex::new(vec![])),
+            }
+        }
+    }
+
+    impl CompletionModel for MockCompletionModel {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            let choice = self.choices.lock().unwrap().pop_front().unwrap_or_else(|| {
+                ModelChoice::Message(format!("Mock response: {}", request.prompt))
+            });
+
+            self.requests.lock().unwrap().push(request);
+
+            Ok(CompletionResponse {
+                choice,
+                raw_response: (),
+            })
+        }
+    }
+
+    /// Streams the replayed choices: messages are split into one delta per word
+    impl StreamingCompletionModel for MockCompletionModel {
+        async fn stream(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<StreamingResult, CompletionError> {
+            let events = match self.completion(request).await?.choice {
+                ModelChoice::Message(message) => message
+                    .split_inclusive(' ')
+                    .map(|delta| StreamEvent::Delta(delta.to_string()))
+                    .collect::<Vec<_>>(),
+                ModelChoice::ToolCall(name, arguments) => {
+                    vec![StreamEvent::ToolCall { name, arguments }]
+                }
+            };
+
+            Ok(Box::pin(stream::iter(
+                events
+                    .into_iter()
+                    .chain(std::iter::once(StreamEvent::Done { usage: None }))
+                    .map(Ok),
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_tool() {
+        let researcher = AgentBuilder::new(MockCompletionModel::default())
+            .preamble("You are a researcher.")
+            .build();
+
+        let supervisor_model = MockCompletionModel::new(vec![ModelChoice::ToolCall(
+            "researcher".to_string(),
+            json!({ "input": "What is a flurbo?" }),
+        )]);
+
+        let supervisor = AgentBuilder::new(supervisor_model.clone())
+            .tool(AgentTool::new(
+                "researcher",
+                "Answers research questions.",
+                researcher,
+            ))
+            .build();
+
+        let response = supervisor.prompt("Ask the researcher").await.unwrap();
+        assert_eq!(response, "\"Mock response: What is a flurbo?\"");
+
+        let requests = supervisor_model.requests.lock().unwrap();
+        assert_eq!(requests[0].tools.len(), 1);
+        assert_eq!(requests[0].tools[0].name, "researcher");
+        assert_eq!(
+            requests[0].tools[0].description,
+            "Answers research questions."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_agent_tool_streaming() {
+        let researcher = || {
+            AgentBuilder::new(MockCompletionModel::new(vec![ModelChoice::Message(
+                "Flurbos are green aliens.".into(),
+            )]))
+            .build()
+        };
+        let supervisor_model = || {
+            MockCompletionModel::new(vec![
+                ModelChoice::ToolCall("researcher".into(), json!({ "input": "Flurbos?" })),
+                ModelChoice::Message("They are aliens.".into()),
+            ])
+        };
+
+        let model = supervisor_model();
+        let supervisor = AgentBuilder::new(model.clone())
+            .tool(
+                AgentTool::new("researcher", "Answers research questions.", researcher())
+                    .streaming(),
+            )
+            .max_turns(2)
+            .build();
+
+        let events = supervisor
+            .stream_prompt("What is a flurbo?")
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        let progress = |delta: &str| StreamEvent::ToolProgress {
+            tool: "researcher".into(),
+            delta: delta.into(),
+        };
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::ToolCall {
+                    name: "researcher".into(),
+                    arguments: json!({ "input": "Flurbos?" }),
+                },
+                progress("Flurbos "),
+                progress("are "),
+                progress("green "),
+                progress("aliens."),
+                StreamEvent::Delta("They ".into()),
+                StreamEvent::Delta("are ".into()),
+                StreamEvent::Delta("aliens.".into()),
+                StreamEvent::Done { usage: None },
+            ]
+        );
+
+        // The full response of the sub-agent is the output of the tool
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(
+            requests[1].prompt,
+            "Result of tool `researcher`: \"Flurbos are green aliens.\""
+        );
+        drop(requests);
+
+        // Non-streaming supervisors get the same output
+        let supervisor = AgentBuilder::new(supervisor_model())
+            .tool(
+                AgentTool::new("researcher", "Answers research questions.", researcher())
+                    .streaming(),
+            )
+            .build();
+        assert_eq!(
+            supervisor.prompt("What is a flurbo?").await.unwrap(),
+            "\"Flurbos are green aliens.\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_agent_tool_error() {
+        // The sub-agent calls a tool it doesn't have, which fails the sub-agent prompt
+        let researcher = AgentBuilder::new(MockCompletionModel::new(vec![ModelChoice::ToolCall(
+            "missing".to_string(),
+            json!({}),
+        )]))
+        .build();
+
+        let supervisor = AgentBuilder::new(MockCompletionModel::new(vec![ModelChoice::ToolCall(
+            "researcher".to_string(),
+            json!({ "input": "What is a flurbo?" }),
+        )]))
+        .tool(AgentTool::new(
+            "researcher",
+            "Answers research questions.",
+            researcher,
+        ))
+        .build();
+
+        let result = supervisor.prompt("Ask the researcher").await;
+        assert!(matches!(
+            result,
+            Err(PromptError::ToolError(ToolSetError::ToolCallError(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_inject_date() {
+        use chrono::{FixedOffset, TimeZone, Utc};
+
+        let model = MockCompletionModel::default();
+        let agent = AgentBuilder::new(model.clone())
+            .preamble("You are a helpful assistant.")
+            .inject_date(
+                DateInjection::new()
+                    .timezone(FixedOffset::east_opt(2 * 3600).unwrap())
+                    .format("%A %-d %B %Y, %H:%M")
+                    .clock(|| Utc.with_ymd_and_hms(2024, 11, 28, 22, 30, 0).unwrap()),
+            )
+            .build();
+
+        agent.prompt("What day is it?").await.unwrap();
+        assert_eq!(
+            model.requests.lock().unwrap()[0].preamble.as_deref(),
+            Some("You are a helpful assistant.\n\nCurrent date and time: Friday 29 November 2024, 00:30")
+        );
+
+        // Without preamble
+        let agent = AgentBuilder::new(model.clone())
+            .inject_date(DateInjection::new().clock(|| Utc.timestamp_opt(0, 0).unwrap()))
+            .build();
+
+        let request = agent.dry_run("What day is it?").await.unwrap();
+        assert_eq!(
+            request.preamble.as_deref(),
+            Some("Current date and time: 1970-01-01 00:00 +00:00")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let model = MockCompletionModel::default();
+
+        let agent = AgentBuilder::new(model.clone())
+            .preamble("You are a helpful assistant.")
+            .context("Flurbos are a made up currency.")
+            .tool(AgentTool::new(
+                "researcher",
+                "Answers research questions.",
+                AgentBuilder::new(MockCompletionModel::default()).build(),
+            ))
+            .temperature(0.5)
+            .max_tokens(256)
+            .build();
+
+        let dry_run = agent.dry_run("What is a flurbo?").await.unwrap();
+        assert!(model.requests.lock().unwrap().is_empty());
+
+        agent.prompt("What is a flurbo?").await.unwrap();
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let sent = &requests[0];
+
+        assert_eq!(dry_run.prompt, sent.prompt);
+        assert_eq!(dry_run.preamble, sent.preamble);
+        assert_eq!(dry_run.temperature, sent.temperature);
+        assert_eq!(dry_run.max_tokens, sent.max_tokens);
+        assert_eq!(dry_run.additional_params, sent.additional_params);
+        assert_eq!(
+            serde_json::to_value(&dry_run.chat_history).unwrap(),
+            serde_json::to_value(&sent.chat_history).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&dry_run.documents).unwrap(),
+            serde_json::to_value(&sent.documents).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&dry_run.tools).unwrap(),
+            serde_json::to_value(&sent.tools).unwrap()
+        );
+        assert_eq!(dry_run.documents.len(), 1);
+        assert_eq!(dry_run.tools.len(), 1);
+    }
+
+    #[derive(Deserialize)]
+    struct OperationArgs {
+        x: i32,
+        y: i32,
+    }
+
+    struct Adder;
+
+    impl Tool for Adder {
+        const NAME: &'static str = "add";
+
+        type Error = std::convert::Infallible;
+        type Args = OperationArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Add x and y together".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "x": { "type": "number" },
+                        "y": { "type": "number" }
+                    }
+                }),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x + args.y)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::Message("The answer is 3".to_string()),
+        ]);
+
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .max_turns(5)
+            .build();
+
+        let response = agent.prompt("What is 1 + 2?").await.unwrap();
+        assert_eq!(response, "The answer is 3");
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].prompt, "Result of tool `add`: 3");
+        assert_eq!(requests[1].chat_history.len(), 2);
+        assert_eq!(requests[1].chat_history[0].content, "What is 1 + 2?");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_tools() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::Message("The answer is 3".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model.clone()).max_turns(5).build();
+
+        // The tool is not available before being added
+        assert!(matches!(
+            agent.prompt("What is 1 + 2?").await,
+            Err(PromptError::ToolError(ToolSetError::ToolNotFoundError(name))) if name == "add"
+        ));
+
+        agent.add_tool(Adder);
+        assert_eq!(
+            agent.prompt("What is 1 + 2?").await.unwrap(),
+            "The answer is 3"
+        );
+
+        {
+            let requests = model.requests.lock().unwrap();
+            assert!(requests[0].tools.is_empty());
+            assert_eq!(requests[1].tools.len(), 1);
+            assert_eq!(requests[1].tools[0].name, "add");
+            assert_eq!(requests[2].prompt, "Result of tool `add`: 3");
+        }
+
+        assert!(agent.remove_tool("add"));
+        assert!(!agent.remove_tool("add"));
+        assert!(agent
+            .dry_run("What is 1 + 2?")
+            .await
+            .unwrap()
+            .tools
+            .is_empty());
+        assert!(!agent.tools().contains("add"));
+    }
+
+    /// Tool waiting to be released before returning x
+    struct Gate {
+        started: Arc<tokio::sync::Notify>,
+        released: Arc<tokio::sync::Notify>,
+    }
+
+    impl Tool for Gate {
+        const NAME: &'static str = "gate";
+
+        type Error = std::convert::Infallible;
+        type Args = OperationArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Wait before returning x".to_string(),
+                parameters: json!({ "type": "object" }),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            self.started.notify_one();
+            self.released.notified().await;
+            Ok(args.x)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runtime_tools_snapshot() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("gate".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::Message("The answer is 3".to_string()),
+        ]);
+        let (started, released) = (
+            Arc::new(tokio::sync::Notify::new()),
+            Arc::new(tokio::sync::Notify::new()),
+        );
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .tool(Gate {
+                started: started.clone(),
+                released: released.clone(),
+            })
+            .max_turns(5)
+            .build();
+
+        // The tool removed while the prompt is in progress remains available to the prompt
+        let (answer, ()) = tokio::join!(agent.prompt("What is 1 + 2?"), async {
+            started.notified().await;
+            assert!(agent.remove_tool("add"));
+            released.notify_one();
+        });
+        assert_eq!(answer.unwrap(), "The answer is 3");
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests[1].tools.len(), 2);
+        assert_eq!(requests[2].prompt, "Result of tool `add`: 3");
+    }
+
+    #[derive(Deserialize)]
+    struct DeleteArgs {
+        path: String,
+        #[serde(default)]
+        confirmed: bool,
+    }
+
+    /// Tool asking for confirmation before deleting a file
+    struct Deleter;
+
+    impl Tool for Deleter {
+        const NAME: &'static str = "delete";
+
+        type Error = ToolError;
+        type Args = DeleteArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Delete a file, once confirmed by the user".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string" },
+                        "confirmed": { "type": "boolean" }
+                    }
+                }),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            if args.confirmed {
+                Ok(format!("Deleted {}", args.path))
+            } else {
+                Err(ToolError::NeedsInput(format!("Delete {}?", args.path)))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_needs_input() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("delete".to_string(), json!({ "path": "notes.txt" })),
+            ModelChoice::ToolCall(
+                "delete".to_string(),
+                json!({ "path": "notes.txt", "confirmed": true }),
+            ),
+            ModelChoice::Message("notes.txt was deleted".to_string()),
+        ]);
+
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Deleter)
+            .max_turns(5)
+            .build();
+
+        let pending = match agent.prompt("Delete notes.txt").await {
+            Err(PromptError::NeedsInput(pending)) => pending,
+            result => panic!("Expected the tool to need input, got {result:?}"),
+        };
+        assert_eq!(pending.prompt, "Delete notes.txt?");
+        assert_eq!(pending.toolname, "delete");
+        assert_eq!(pending.args, json!({ "path": "notes.txt" }));
+        assert_eq!(pending.chat_history.len(), 2);
+        assert_eq!(model.requests.lock().unwrap().len(), 1);
+
+        let response = agent.resume(*pending, "yes").await.unwrap();
+        assert_eq!(response, "notes.txt was deleted");
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[1].prompt,
+            "Result of tool `delete`: The tool needs input from the user: Delete notes.txt?\nUser input: yes"
+        );
+        assert_eq!(requests[1].chat_history[0].content, "Delete notes.txt");
+        assert_eq!(
+            requests[2].prompt,
+            "Result of tool `delete`: \"Deleted notes.txt\""
+        );
+        assert_eq!(requests[2].chat_history.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_stop_condition() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::ToolCall("researcher".to_string(), json!({ "input": "Check 3" })),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 3, "y": 3 })),
+            ModelChoice::Message("Done".to_string()),
+        ]);
+
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .tool(AgentTool::new(
+                "researcher",
+                "Answers research questions.",
+                AgentBuilder::new(MockCompletionModel::default()).build(),
+            ))
+            .max_turns(10)
+            .with_stop_condition(|messages| {
+                messages
+                    .iter()
+                    .any(|message| message.content.starts_with("Calling tool `researcher`"))
+            })
+            .build();
+
+        let response = agent.prompt("What is 1 + 2?").await.unwrap();
+        assert_eq!(response, "\"Mock response: Check 3\"");
+        assert_eq!(model.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_max_turns() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 3, "y": 3 })),
+            ModelChoice::Message("Done".to_string()),
+        ]);
+
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .max_turns(2)
+            .build();
+
+        let response = agent.prompt("What is 1 + 2 + 3?").await.unwrap();
+        assert_eq!(response, "6");
+        assert_eq!(model.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tool_quota() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 3, "y": 3 })),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 6, "y": 4 })),
+            ModelChoice::Message("The answer is 6".to_string()),
+        ]);
+
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .max_turns(5)
+            .tool_quota("add", 2)
+            .build();
+
+        let (answer, steps) = agent.prompt_traced("What is 1 + 2 + 3?").await.unwrap();
+        assert_eq!(answer, "The answer is 6");
+
+        let results = steps
+            .iter()
+            .map(|step| step.tool_result.clone())
+            .collect::<Vec<_>>();
+        let exhausted = "The tool `add` is exhausted: it can be called at most 2 times and \
+            cannot be called anymore. Answer with the information you already have.";
+        assert_eq!(
+            results,
+            vec![
+                Some("3".to_string()),
+                Some("6".to_string()),
+                Some(exhausted.to_string()),
+                None
+            ]
+        );
+
+        // The model is informed that the tool is exhausted
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(
+            requests[3].prompt,
+            format!("Result of tool `add`: {exhausted}")
+        );
+
+        // Quotas are per run
+        drop(requests);
+        model
+            .choices
+            .lock()
+            .unwrap()
+            .push_back(ModelChoice::ToolCall(
+                "add".to_string(),
+                json!({ "x": 1, "y": 1 }),
+            ));
+        let (_, steps) = agent.prompt_traced("What is 1 + 1?").await.unwrap();
+        assert_eq!(steps[0].tool_result, Some("2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_traced() {
+        let agent = AgentBuilder::new(MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::Message("The answer is 3".to_string()),
+        ]))
+        .tool(Adder)
+        .max_turns(5)
+        .build();
+
+        let (answer, steps) = agent.prompt_traced("What is 1 + 2?").await.unwrap();
+        assert_eq!(answer, "The answer is 3");
+        assert_eq!(
+            steps,
+            vec![
+                Step {
+                    prompt: "What is 1 + 2?".to_string(),
+                    choice: ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+                    tool_result: Some("3".to_string()),
+                    max_tokens: None,
+                    usage: None,
+                    retries: 0,
+                    sources: vec![],
+                    dropped_context: DroppedContext::default(),
+                    reasoning: None,
+                },
+                Step {
+                    prompt: "Result of tool `add`: 3".to_string(),
+                    choice: ModelChoice::Message("The answer is 3".to_string()),
+                    tool_result: None,
+                    max_tokens: None,
+                    usage: None,
+                    retries: 0,
+                    sources: vec![],
+                    dropped_context: DroppedContext::default(),
+                    reasoning: None,
+                },
+            ]
+        );
+    }
+
+    #[derive(Deserialize)]
+    struct SearchArgs {
+        query: String,
+    }
+
+    #[derive(Serialize)]
+    struct SearchResult {
+        url: String,
+        snippet: String,
+    }
+
+    /// Tool citing the pages it finds as sources
+    struct Search;
+
+    impl Tool for Search {
+        const NAME: &'static str = "search";
+
+        type Error = std::convert::Infallible;
+        type Args = SearchArgs;
+        type Output = Vec<SearchResult>;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Search the web".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string" }
+                    }
+                }),
+            }
+        }
+
+        fn sources(&self, output: &Self::Output) -> Vec<Source> {
+            output
+                .iter()
+                .map(|result| {
+                    Source::new(&result.url)
+                        .url(&result.url)
+                        .snippet(&result.snippet)
+                })
+                .collect()
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(vec![
+                SearchResult {
+                    url: format!("https://flurbo.wiki/{}", args.query),
+                    snippet: "A flurbo is a green alien currency".to_string(),
+                },
+                SearchResult {
+                    url: "https://glarb.wiki/flurbo".to_string(),
+                    snippet: "Flurbos are traded for glarbs".to_string(),
+                },
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_with_citations() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("search".to_string(), json!({ "query": "flurbo" })),
+            ModelChoice::ToolCall("search".to_string(), json!({ "query": "flurbos" })),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::Message("A flurbo is a green alien currency".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Search)
+            .tool(Adder)
+            .max_turns(5)
+            .build();
+
+        let (answer, citations) = agent
+            .prompt_with_citations("What is a flurbo?")
+            .await
+            .unwrap();
+        assert_eq!(answer, "A flurbo is a green alien currency");
+
+        // Sources are cited once, in the order of the tool calls
+        let urls = citations
+            .iter()
+            .map(|source| source.url.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                "https://flurbo.wiki/flurbo",
+                "https://glarb.wiki/flurbo",
+                "https://flurbo.wiki/flurbos",
+            ]
+        );
+        assert_eq!(
+            citations[0].snippet.as_deref(),
+            Some("A flurbo is a green alien currency")
+        );
+
+        // The tool results fed back to the model are unchanged
+        let requests = model.requests.lock().unwrap();
+        assert!(requests[1]
+            .prompt
+            .starts_with("Result of tool `search`: [{\"url\":\"https://flurbo.wiki/flurbo\""));
+        assert_eq!(requests[3].prompt, "Result of tool `add`: 3");
+    }
+
+    #[tokio::test]
+    async fn test_stream_prompt_with_citations() {
+        let model = MockCompletionModel::new(vec![ModelChoice::Message(
+            "Flurbos are green [static_doc_1]. They are currency [static_doc_0, static_doc_1] \
+            [unknown]."
+                .to_string(),
+        )]);
+        let agent = AgentBuilder::new(model.clone())
+            .context("Flurbos are a currency.")
+            .context("Flurbos are green.")
+            .build();
+
+        let events = agent
+            .stream_prompt_with_citations("What is a flurbo?")
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        // Each source is cited once, following the delta completing its first marker
+        let delta = |delta: &str| StreamEvent::Delta(delta.into());
+        let citation = |id: &str| StreamEvent::Citation(Source::new(id));
+        assert_eq!(
+            events,
+            vec![
+                delta("Flurbos "),
+                delta("are "),
+                delta("green "),
+                delta("[static_doc_1]. "),
+                citation("static_doc_1"),
+                delta("They "),
+                delta("are "),
+                delta("currency "),
+                delta("[static_doc_0, "),
+                delta("static_doc_1] "),
+                citation("static_doc_0"),
+                delta("[unknown]."),
+                StreamEvent::Done { usage: None },
+            ]
+        );
+
+        let requests = model.requests.lock().unwrap();
+        assert!(requests[0]
+            .system
+            .contains(&CITATION_INSTRUCTION.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_self_consistent() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message("3 + 4 = 7, so 7 - 2 = 5.\nAnswer: 5".into()),
+            ModelChoice::Message("Let's see: 3 + 4 is 8.\nANSWER: 6.".into()),
+            ModelChoice::Message("Answer: 5".into()),
+        ]);
+        let agent = AgentBuilder::new(model.clone()).temperature(0.2).build();
+
+        let vote = agent
+            .prompt_self_consistent("What is 3 + 4 - 2?", 3)
+            .await
+            .unwrap();
+        assert_eq!(vote.answer, "5");
+        assert_eq!(
+            vote.distribution,
+            vec![("5".to_string(), 2), ("6".to_string(), 1)]
+        );
+        assert_eq!(vote.abstentions, 0);
+
+        // Sampled at a higher temperature than the agent's
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests
+            .iter()
+            .all(|request| request.temperature == Some(SELF_CONSISTENCY_TEMPERATURE)));
+        drop(requests);
+
+        // The context is retrieved once for all the samples
+        let retrievals = Arc::new(Mutex::new(0));
+        let agent = AgentBuilder::new(MockCompletionModel::default())
+            .dynamic_context_fn({
+                let retrievals = retrievals.clone();
+                move |_| {
+                    *retrievals.lock().unwrap() += 1;
+                    async { vec![] }
+                }
+            })
+            .build();
+        agent
+            .prompt_self_consistent("What is 3 + 4 - 2?", 3)
+            .await
+            .unwrap();
+        assert_eq!(*retrievals.lock().unwrap(), 1);
+
+        // Only the samples fitting in the token budget are requested
+        let model = MockCompletionModel::default();
+        let agent = AgentBuilder::new(model.clone())
+            .max_tokens(10)
+            .with_token_budget(100)
+            .build();
+        agent
+            .prompt_self_consistent("What is 3 + 4 - 2?", 20)
+            .await
+            .unwrap();
+        let requests = model.requests.lock().unwrap();
+        let sample_tokens = requests[0].estimated_prompt_tokens() + 10;
+        assert_eq!(requests.len() as u64, 100 / sample_tokens);
+        assert!(requests.len() < 20);
+        drop(requests);
+
+        // Without `max_tokens`, a single sample fits the budget
+        let model = MockCompletionModel::default();
+        let agent = AgentBuilder::new(model.clone())
+            .with_token_budget(100)
+            .build();
+        agent
+            .prompt_self_consistent("What is 3 + 4 - 2?", 5)
+            .await
+            .unwrap();
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].max_tokens.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_prompt_self_consistent_extractor() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".into(), json!({"x": 1, "y": 2})),
+            ModelChoice::Message("I don't know".into()),
+        ]);
+        let agent = AgentBuilder::new(model.clone())
+            .answer_extractor(|response| {
+                response
+                    .split("####")
+                    .nth(1)
+                    .map(|answer| answer.trim().to_string())
+            })
+            .self_consistency_temperature(1.0)
+            .build();
+
+        let result = agent.prompt_self_consistent("What is 1 + 2?", 2).await;
+        assert!(matches!(result, Err(PromptError::NoAnswer(2))));
+
+        assert_eq!(
+            default_answer_extractor("The answer is\n\n42.\n"),
+            Some("42".into())
+        );
+        assert_eq!(default_answer_extractor("  \n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_output_transforms() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message(
+                "Here you go:\n```python\nprint('flurbo')\n```\nAnything else?".into(),
+            ),
+            ModelChoice::Message("No code here".into()),
+        ]);
+        let agent = AgentBuilder::new(model)
+            .output_transform(extract_code_block)
+            .output_transform(|answer| Ok(answer.to_uppercase()))
+            .build();
+
+        assert_eq!(
+            agent.prompt("Print flurbo").await.unwrap(),
+            "PRINT('FLURBO')"
+        );
+        assert!(matches!(
+            agent.prompt("Print glarb").await,
+            Err(PromptError::OutputTransformError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_token_budget() {
+        let model = MockCompletionModel::default();
+        let agent = AgentBuilder::new(model.clone())
+            .preamble("You are a helpful assistant.")
+            .with_token_budget(100)
+            .build();
+
+        let (_, report) = agent.prompt_with_usage("What is a flurbo?").await.unwrap();
+        assert_eq!(report.budget, Some(100));
+        assert_eq!(report.usage, None);
+        assert!(!report.truncated);
+
+        let requests = model.requests.lock().unwrap();
+        let prompt_tokens = requests[0].estimated_prompt_tokens();
+        assert!(prompt_tokens > 0);
+        assert_eq!(requests[0].max_tokens, Some(100 - prompt_tokens));
+        drop(requests);
+
+        // A lower `max_tokens` is kept
+        let agent = AgentBuilder::new(model.clone())
+            .max_tokens(10)
+            .with_token_budget(100)
+            .build();
+        agent.prompt("What is a flurbo?").await.unwrap();
+        assert_eq!(model.requests.lock().unwrap()[1].max_tokens, Some(10));
+
+        let agent = AgentBuilder::new(model)
+            .context(&"Flurbos are a made up currency. ".repeat(20))
+            .with_token_budget(100)
+            .build();
+        let result = agent.prompt("What is a flurbo?").await;
+        assert!(matches!(
+            result,
+            Err(PromptError::BudgetExceeded { budget: 100, .. })
+        ));
+    }
+
+    /// Mock completion model replaying the choices of a [MockCompletionModel], each request
+    /// reporting the usage of the same index
+    #[derive(Clone)]
+    struct MeteredModel {
+        model: MockCompletionModel,
+        usages: Vec<Usage>,
+    }
+
+    impl CompletionModel for MeteredModel {
+        type Response = Usage;
+
+        fn usage(response: &Usage) -> Option<Usage> {
+            Some(*response)
+        }
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<Usage>, CompletionError> {
+            let index = self.model.requests.lock().unwrap().len();
+            let choice = self.model.completion(request).await?.choice;
+            Ok(CompletionResponse {
+                choice,
+                raw_response: self.usages[index],
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_total_usage() {
+        let model = MeteredModel {
+            model: MockCompletionModel::new(vec![
+                ModelChoice::ToolCall("add".to_string(), json!({ "x": 2, "y": 3 })),
+                ModelChoice::Message("5".to_string()),
+            ]),
+            usages: vec![
+                Usage {
+                    prompt_tokens: 100,
+                    completion_tokens: 10,
+                    total_tokens: 110,
+                    cached_tokens: 0,
+                    cache_creation_tokens: 80,
+                },
+                Usage {
+                    prompt_tokens: 120,
+                    completion_tokens: 2,
+                    total_tokens: 122,
+                    cached_tokens: 80,
+                    cache_creation_tokens: 0,
+                },
+            ],
+        };
+        let agent = AgentBuilder::new(model.clone()).tool(Adder).build();
+
+        // The usage of both requests of the tool loop is summed
+        let (answer, report) = agent.prompt_with_usage("What is 2 + 3?").await.unwrap();
+        assert_eq!(answer, "5");
+        assert_eq!(model.model.requests.lock().unwrap().len(), 2);
+        assert_eq!(
+            report.usage,
+            Some(Usage {
+                prompt_tokens: 220,
+                completion_tokens: 12,
+                total_tokens: 232,
+                cached_tokens: 80,
+                cache_creation_tokens: 80,
+            })
+        );
+    }
+
+    struct Subtractor;
+
+    impl Tool for Subtractor {
+        const NAME: &'static str = "subtract";
+
+        type Error = std::convert::Infallible;
+        type Args = OperationArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Subtract y from x".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "x": { "type": "number" },
+                        "y": { "type": "number" }
+                    }
+                }),
+            }
+        }
+
+        fn examples(&self) -> Vec<ToolExample> {
+            vec![
+                ToolExample::new(json!({ "x": 3, "y": 2 }), json!(1)),
+                ToolExample::new(
+                    json!({ "x": "x".repeat(MAX_TOOL_EXAMPLE_LEN), "y": 2 }),
+                    json!(0),
+                ),
+                ToolExample::new(json!({ "x": 2, "y": 3 }), json!(-1)),
+            ]
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x - args.y)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_examples() {
+        let agent = AgentBuilder::new(MockCompletionModel::new(vec![]))
+            .tool(Adder)
+            .tool(Subtractor)
+            .build();
+
+        let request = agent
+            .completion("What is 3 - 2?", vec![])
+            .await
+            .unwrap()
+            .build();
+        let description = |name: &str| {
+            request
+                .tools
+                .iter()
+                .find(|tool| tool.name == name)
+                .map(|tool| tool.description.clone())
+                .unwrap()
+        };
+
+        // Oversized examples are left out
+        assert_eq!(
+            description("subtract"),
+            "Subtract y from x\n\n\
+            Examples:\n\
+            - Input: {\"x\":3,\"y\":2}\n  Output: 1\n\
+            - Input: {\"x\":2,\"y\":3}\n  Output: -1"
+        );
+        assert_eq!(description("add"), "Add x and y together");
+    }
+
+    #[tokio::test]
+    async fn test_allowed_tools() {
+        let model = MockCompletionModel::new(vec![ModelChoice::ToolCall(
+            "subtract".to_string(),
+            json!({ "x": 3, "y": 2 }),
+        )]);
+
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .tool(Subtractor)
+            .build();
+
+        let tool_names = |request: &CompletionRequest| {
+            request
+                .tools
+                .iter()
+                .map(|tool| tool.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let request = agent
+            .allowed_tools(&["add"])
+            .completion("What is 1 + 2?", vec![])
+            .await
+            .unwrap()
+            .build();
+        assert_eq!(tool_names(&request), vec!["add"]);
+
+        let request = agent
+            .denied_tools(&["add"])
+            .completion("What is 3 - 2?", vec![])
+            .await
+            .unwrap()
+            .build();
+        assert_eq!(tool_names(&request), vec!["subtract"]);
+
+        // An empty allow-list means no tools
+        let request = agent
+            .allowed_tools(&[])
+            .completion("What is 1 + 2?", vec![])
+            .await
+            .unwrap()
+            .build();
+        assert!(request.tools.is_empty());
+
+        // Tools that are not allowed cannot be called either
+        let result = agent.allowed_tools(&["add"]).prompt("What is 3 - 2?").await;
+        assert!(matches!(
+            result,
+            Err(PromptError::ToolError(ToolSetError::ToolNotFoundError(_)))
+        ));
+        assert_eq!(tool_names(&model.requests.lock().unwrap()[0]), vec!["add"]);
+
+        // The agent itself is unchanged
+        assert_eq!(
+            agent.dry_run("What is 1 + 2?").await.unwrap().tools.len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prompt_with_image() {
+        use base64::{prelude::BASE64_STANDARD, Engine};
+
+        let model = MockCompletionModel::new(vec![]);
+        let agent = AgentBuilder::new(model.clone()).build();
+
+        agent
+            .prompt_with_image("Describe this", "tests/data/pixel.png")
+            .await
+            .unwrap();
+
+        let bytes = std::fs::read("tests/data/pixel.png").unwrap();
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests[0].prompt, "Describe this");
+        assert_eq!(
+            requests[0].images,
+            vec![Image {
+                media_type: "image/png".to_string(),
+                data: BASE64_STANDARD.encode(bytes),
+            }]
+        );
+        drop(requests);
+
+        // Files that are not images are rejected before sending the request
+        let result = agent
+            .prompt_with_image("Describe this", "tests/data/dummy.pdf")
+            .await;
+        assert!(matches!(
+            result,
+            Err(PromptError::CompletionError(CompletionError::RequestError(
+                _
+            )))
+        ));
+        assert_eq!(model.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_context_fn() {
+        let model = MockCompletionModel::new(vec![]);
+        let agent = AgentBuilder::new(model.clone())
+            .context("Flurbos are a made up currency.")
+            .dynamic_context_fn(|prompt: String| async move {
+                vec![RetrievedDoc {
+                    id: "search_0".to_string(),
+                    text: format!("Search result for: {prompt}"),
+                    additional_props: HashMap::new(),
+                }]
+            })
+            .build();
+
+        agent.prompt("What is a flurbo?").await.unwrap();
+
+        let requests = model.requests.lock().unwrap();
+        let documents = &requests[0].documents;
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[1].id, "search_0");
+        assert!(requests[0]
+            .prompt_with_context()
+            .contains("<file id: search_0>\nSearch result for: What is a flurbo?\n</file>"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_empty_responses() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message(" \n".to_string()),
+            ModelChoice::Message("A flurbo is a made up currency.".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model.clone())
+            .retry_empty_responses(RetryPolicy::new(2).backoff(Duration::ZERO))
+            .empty_response_nudge(EMPTY_RESPONSE_NUDGE)
+            .build();
+
+        let (answer, trace) = agent.prompt_traced("What is a flurbo?").await.unwrap();
+        assert_eq!(answer, "A flurbo is a made up currency.");
+        assert_eq!(trace[0].retries, 1);
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].prompt, "What is a flurbo?");
+        assert_eq!(
+            requests[1].prompt,
+            format!("What is a flurbo?\n\n{EMPTY_RESPONSE_NUDGE}")
+        );
+        drop(requests);
+
+        // Without retries, empty completions are returned as is
+        let agent = AgentBuilder::new(MockCompletionModel::new(vec![ModelChoice::Message(
+            String::new(),
+        )]))
+        .build();
+        assert_eq!(agent.prompt("What is a flurbo?").await.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_empty_response_retry_reuses_request() {
+        let retrievals = Arc::new(AtomicUsize::new(0));
+        let agent = |choices: Vec<ModelChoice>| {
+            let model = MockCompletionModel::new(choices);
+            let retrievals = retrievals.clone();
+            let agent = AgentBuilder::new(model.clone())
+                .dynamic_context_fn(move |query: String| {
+                    retrievals.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        vec![RetrievedDoc {
+                            id: "doc_flurbo".to_string(),
+                            text: format!("Search result for: {query}"),
+                            additional_props: HashMap::new(),
+                        }]
+                    }
+                })
+                .retry_empty_responses(RetryPolicy::new(1).backoff(Duration::ZERO))
+                .empty_response_nudge(EMPTY_RESPONSE_NUDGE)
+                .build();
+            (model, agent)
+        };
+
+        // The retry re-sends the request as built, without retrieving its context again
+        let (model, prompt_agent) = agent(vec![
+            ModelChoice::Message(" \n".to_string()),
+            ModelChoice::Message("A made up currency".to_string()),
+        ]);
+        prompt_agent.prompt("What is a flurbo?").await.unwrap();
+        assert_eq!(retrievals.load(Ordering::SeqCst), 1);
+        {
+            let requests = model.requests.lock().unwrap();
+            assert_eq!(requests.len(), 2);
+            assert_eq!(requests[1].documents, requests[0].documents);
+            assert_eq!(
+                requests[1].prompt,
+                format!("What is a flurbo?\n\n{EMPTY_RESPONSE_NUDGE}")
+            );
+        }
+
+        // The whitespace deltas of the empty answer are not streamed before its retry
+        let (model, stream_agent) = agent(vec![
+            ModelChoice::Message(" \n".to_string()),
+            ModelChoice::Message("A made up currency".to_string()),
+        ]);
+        let deltas = stream_agent
+            .stream_prompt("What is a flurbo?")
+            .try_filter_map(|event| async move {
+                Ok(match event {
+                    StreamEvent::Delta(delta) => Some(delta),
+                    _ => None,
+                })
+            })
+            .try_collect::<String>()
+            .await
+            .unwrap();
+        assert_eq!(deltas, "A made up currency");
+        assert_eq!(retrievals.load(Ordering::SeqCst), 2);
+        assert_eq!(model.requests.lock().unwrap().len(), 2);
+
+        // Unless there are no retries left, in which case they are the answer
+        let (_, stream_agent) = agent(vec![
+            ModelChoice::Message(" \n".to_string()),
+            ModelChoice::Message("\t".to_string()),
+        ]);
+        let events = stream_agent
+            .stream_prompt("What is a flurbo?")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Delta("\t".to_string()),
+                StreamEvent::Done { usage: None }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_expansion() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message(
+                "1. flurbo currency\n- glarb value\n\nflurbo value\nexcess query".to_string(),
+            ),
+            ModelChoice::Message("Answer".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model.clone())
+            .dynamic_context_fn(|query: String| async move {
+                let id = if query.contains("flurbo") {
+                    "doc_flurbo"
+                } else if query.contains("glarb") {
+                    "doc_glarb"
+                } else {
+                    "doc_other"
+                };
+                vec![RetrievedDoc {
+                    id: id.to_string(),
+                    text: format!("Search result for: {query}"),
+                    additional_props: HashMap::new(),
+                }]
+            })
+            .query_expansion(3)
+            .build();
+
+        assert_eq!(agent.prompt("What is it worth?").await.unwrap(), "Answer");
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0]
+            .preamble
+            .as_ref()
+            .unwrap()
+            .contains("3 alternative queries"));
+        assert_eq!(requests[0].prompt, "What is it worth?");
+
+        // The documents retrieved for the prompt and its variants are merged by id
+        let documents = &requests[1].documents;
+        assert_eq!(
+            documents
+                .iter()
+                .map(|doc| doc.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["doc_other", "doc_flurbo", "doc_glarb"]
+        );
+        assert_eq!(documents[1].text, "Search result for: flurbo currency");
+        drop(requests);
+
+        // The prompt is expanded once for the whole tool loop, and its variants are also used to
+        // retrieve the dynamic tools
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message("sum of the numbers".to_string()),
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::Message("3".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model.clone())
+            .dynamic_tools(1, SumToolIndex, ToolSet::from_tools(vec![Adder]))
+            .query_expansion(1)
+            .max_turns(2)
+            .build();
+        assert_eq!(agent.prompt("What is 1 + 2?").await.unwrap(), "3");
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1..]
+            .iter()
+            .all(|request| request.tools.len() == 1 && request.tools[0].name == "add"));
+    }
+
+    /// Index of dynamic tools returning the `add` tool for queries about sums only
+    struct SumToolIndex;
+
+    impl VectorStoreIndex for SumToolIndex {
+        async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+            &self,
+            _query: &str,
+            _n: usize,
+        ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+            Ok(vec![])
+        }
+
+        async fn top_n_ids(
+            &self,
+            query: &str,
+            _n: usize,
+        ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+            if query.contains("sum") {
+                Ok(vec![(1.0, "add".to_string())])
+            } else {
+                Ok(vec![])
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_truncate_documents() {
+        let model = MockCompletionModel::new(vec![]);
+        let agent = AgentBuilder::new(model.clone())
+            .context(&"Flurbos are a made up currency. ".repeat(100))
+            .context("Glarbs are ancient artifacts.")
+            .truncate_documents(TruncationPolicy::new(10))
+            .build();
+
+        let request = agent.dry_run("What is a flurbo?").await.unwrap();
+        assert_eq!(
+            request.documents[0].text,
+            "Flurbos are a made up currency. Flu[...]"
+        );
+        assert_eq!(
+            request.documents[0].text.chars().count(),
+            10 * crate::truncation::CHARS_PER_TOKEN
+        );
+        assert_eq!(request.documents[1].text, "Glarbs are ancient artifacts.");
+    }
+
+    #[tokio::test]
+    async fn test_strip_reasoning() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message(
+                "<think>The user wants 1 + 2, which is 3.</think>\nThe answer is 3".to_string(),
+            ),
+            ModelChoice::Message("<think>Still 3.</think>The answer is 3".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model)
+            .strip_reasoning(ReasoningStripper::default())
+            .build();
+
+        let (answer, steps) = agent.prompt_traced("What is 1 + 2?").await.unwrap();
+        assert_eq!(answer, "The answer is 3");
+        assert_eq!(
+            steps[0].reasoning.as_deref(),
+            Some("The user wants 1 + 2, which is 3.")
+        );
+
+        let answer = agent.prompt_with_reasoning("What is 1 + 2?").await.unwrap();
+        assert_eq!(answer.answer(), "The answer is 3");
+        assert_eq!(answer.reasoning(), Some("Still 3."));
+    }
+
+    #[tokio::test]
+    async fn test_context_budgets() {
+        let retrieved = |i: usize| RetrievedDoc {
+            id: format!("doc_{i}"),
+            text: format!("Flurbo fact #{i}. ").repeat(10),
+            additional_props: HashMap::new(),
+        };
+        let tokens = estimated_tokens(&retrieved(0).to_string());
+
+        let model = MockCompletionModel::new(vec![]);
+        let agent = AgentBuilder::new(model.clone())
+            .preamble("You are a dictionary assistant.")
+            .dynamic_context_fn(move |_| async move { (0..5).map(retrieved).collect() })
+            .retrieved_context_budget(2 * tokens + 1)
+            .build();
+
+        let history = (0..20)
+            .map(|i| Message {
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: format!("Message #{i} about glarbs. ").repeat(10),
+            })
+            .collect::<Vec<_>>();
+        agent
+            .chat("What is a flurbo?", history.clone())
+            .await
+            .unwrap();
+
+        {
+            let requests = model.requests.lock().unwrap();
+            let ids = requests[0]
+                .documents
+                .iter()
+                .map(|document| document.id.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(ids, vec!["doc_0", "doc_1"]);
+            // The history is preserved, whatever its size
+            assert_eq!(requests[0].chat_history, history);
+            assert_eq!(
+                requests[0].preamble.as_deref(),
+                Some("You are a dictionary assistant.")
+            );
+        }
+
+        let (_, report) = agent.prompt_with_usage("What is a flurbo?").await.unwrap();
+        assert_eq!(
+            report.dropped_context,
+            DroppedContext {
+                documents: vec!["doc_2".into(), "doc_3".into(), "doc_4".into()],
+                tokens: 3 * tokens,
+            }
+        );
+
+        // The static context is trimmed to the budget of the preamble
+        let agent = AgentBuilder::new(model.clone())
+            .preamble("You are a dictionary assistant.")
+            .context("Flurbos are a made up currency.")
+            .context(&"Glarbs are ancient artifacts. ".repeat(100))
+            .preamble_budget(30)
+            .build();
+        let (_, steps) = agent.prompt_traced("What is a flurbo?").await.unwrap();
+        assert_eq!(steps[0].dropped_context.documents, vec!["static_doc_1"]);
+    }
+
+    #[tokio::test]
+    async fn test_extract_stream() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Answer {
+            name: String,
+            total: Option<i32>,
+        }
+
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("add".to_string(), json!({ "x": 1, "y": 2 })),
+            ModelChoice::Message(r#"{"name": "Flurbo", "total": 3}"#.to_string()),
+        ]);
+        let agent = AgentBuilder::new(model.clone()).tool(Adder).build();
+
+        let extractions = agent
+            .extract_stream::<Answer>("How many flurbos?")
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            extractions,
+            vec![
+                PartialExtraction::Partial(Answer {
+                    name: "Flurbo".to_string(),
+                    total: None,
+                }),
+                PartialExtraction::Partial(Answer {
+                    name: "Flurbo".to_string(),
+                    total: Some(3),
+                }),
+                PartialExtraction::Complete(Answer {
+                    name: "Flurbo".to_string(),
+                    total: Some(3),
+                }),
+            ]
+        );
+
+        // The tool was called before the answer was streamed
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].prompt, "Result of tool `add`: 3");
+    }
+
+    #[tokio::test]
+    async fn test_stream_prompt_turn_logic() {
+        // Empty answers are re-requested and the token budget applies
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message("".to_string()),
+            ModelChoice::Message("A made up currency".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model.clone())
+            .retry_empty_responses(RetryPolicy::new(1).backoff(Duration::ZERO))
+            .empty_response_nudge(EMPTY_RESPONSE_NUDGE)
+            .with_token_budget(100)
+            .build();
+        let events = agent
+            .stream_prompt("What is a flurbo?")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(events.first(), Some(&StreamEvent::Delta("A ".to_string())));
+        {
+            let requests = model.requests.lock().unwrap();
+            assert_eq!(requests.len(), 2);
+            assert!(requests[1].prompt.ends_with(EMPTY_RESPONSE_NUDGE));
+            assert!(requests.iter().all(|request| request.max_tokens.is_some()));
+        }
+
+        // The stop condition ends the loop with the output of the tool call
+        let model = MockCompletionModel::new(vec![ModelChoice::ToolCall(
+            "add".to_string(),
+            json!({ "x": 1, "y": 2 }),
+        )]);
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .max_turns(10)
+            .with_stop_condition(|messages| {
+                messages
+                    .iter()
+                    .any(|message| message.content.starts_with("Calling tool `add`"))
+            })
+            .build();
+        let events = agent
+            .stream_prompt("What is 1 + 2?")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(events.contains(&StreamEvent::Delta("3".to_string())));
+        assert_eq!(model.requests.lock().unwrap().len(), 1);
+
+        // Tools needing input pause the loop, which can be resumed
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::ToolCall("delete".to_string(), json!({ "path": "notes.txt" })),
+            ModelChoice::Message("notes.txt was kept".to_string()),
+        ]);
+        let agent = AgentBuilder::new(model).tool(Deleter).max_turns(5).build();
+        let pending = match agent
+            .stream_prompt("Delete notes.txt")
+            .try_collect::<Vec<_>>()
+            .await
+        {
+            Err(PromptError::NeedsInput(pending)) => pending,
+            result => panic!("Expected the tool to need input, got {result:?}"),
+        };
+        assert_eq!(pending.prompt, "Delete notes.txt?");
+        assert_eq!(pending.chat_history.len(), 2);
+        assert_eq!(
+            agent.resume(*pending, "no").await.unwrap(),
+            "notes.txt was kept"
+        );
+
+        // Stripping the chain-of-thought of streamed answers is not supported
+        let agent = AgentBuilder::new(MockCompletionModel::default())
+            .strip_reasoning(ReasoningStripper::default())
+            .build();
+        let result = agent
+            .stream_prompt("What is a flurbo?")
+            .try_collect::<Vec<_>>()
+            .await;
+        assert!(matches!(result, Err(PromptError::UnsupportedOption(_))));
+        let result = agent
+            .extract_stream::<serde_json::Value>("What is a flurbo?")
+            .await;
+        assert!(matches!(
+            result,
+            Err(ExtractionError::PromptError(
+                PromptError::UnsupportedOption(_)
+            ))
+        ));
+    }
 }
@@ -0,0 +1,573 @@
+//! This module provides caching of prompt responses.
+//!
+//! Two caches are provided:
+//! - [ExactCache]: returns a cached response when the exact same prompt was seen before.
+//! - [SemanticCache]: embeds the prompt and returns a cached response when a previous prompt's
+//!   embedding is similar enough (i.e.: its cosine similarity is above a configurable threshold).
+//!
+//! Caches are attached to any type implementing [Prompt] (e.g.: an [Agent](crate::agent::Agent))
+//! using the [CachedPrompt] wrapper. Both caches can be used alongside each other by passing a
+//! tuple `(ExactCache, SemanticCache<M>)`, in which case the exact cache is checked first.
+//!
+//! Both caches are unbounded by default: long-running applications should bound them with
+//! [ExactCache::capacity] and [SemanticCache::capacity], which evict the oldest responses first.
+//!
+//! # Example
+//! ```rust
+//! use rig::{
+//!     cache::{CachedPrompt, ExactCache, SemanticCache},
+//!     completion::Prompt,
+//!     providers::openai,
+//! };
+//!
+//! let openai = openai::Client::from_env();
+//!
+//! let agent = openai.agent(openai::GPT_4O).build();
+//! let embedding_model = openai.embedding_model(openai::TEXT_EMBEDDING_3_SMALL);
+//!
+//! let agent = CachedPrompt::new(
+//!     agent,
+//!     (
+//!         ExactCache::new().capacity(10_000),
+//!         SemanticCache::new(embedding_model).threshold(0.95).capacity(1_000),
+//!     ),
+//! )
+//! // Never serve cached answers to prompts whose answer changes over time
+//! .bypass_if(|prompt| prompt.contains("today") || prompt.contains("latest"));
+//!
+//! let response = agent.prompt("What is a flurbo?").await?;
+//! // Served from the semantic cache
+//! let response = agent.prompt("Could you tell me what a flurbo is?").await?;
+//! ```
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{PoisonError, RwLock},
+    time::Instant,
+};
+
+use crate::{
+    completion::{CallStats, Prompt, PromptError, PromptWithStats},
+    embeddings::{distance::VectorDistance, Embedding, EmbeddingError, EmbeddingModel},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    /// Error embedding the prompt
+    #[error("EmbeddingError: {0}")]
+    EmbeddingError(#[from] EmbeddingError),
+}
+
+/// Trait for caches of prompt responses.
+pub trait PromptCache: Send + Sync {
+    /// Get the cached response for `prompt`, if any.
+    fn get(&self, prompt: &str) -> impl Future<Output = Result<Option<String>, CacheError>> + Send;
+
+    /// Cache `response` as the response to `prompt`.
+    fn insert(
+        &self,
+        prompt: &str,
+        response: &str,
+    ) -> impl Future<Output = Result<(), CacheError>> + Send;
+
+    /// Release the state kept by [PromptCache::get] for `prompt` after a miss, once the prompt
+    /// completes (whether its response was inserted, it failed or it was cancelled). Does
+    /// nothing by default.
+    fn release(&self, _prompt: &str) {}
+}
+
+/// Cache that only returns a response for prompts that exactly match a previous prompt.
+#[derive(Default)]
+pub struct ExactCache {
+    entries: RwLock<ExactEntries>,
+    /// Maximum number of cached responses (see [ExactCache::capacity])
+    capacity: Option<usize>,
+}
+
+/// Responses of an [ExactCache], by prompt, along with the prompts in insertion order
+#[derive(Default)]
+struct ExactEntries {
+    responses: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+impl ExactCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of cached responses, the oldest responses being evicted first
+    /// (unbounded by default).
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+}
+
+impl PromptCache for ExactCache {
+    async fn get(&self, prompt: &str) -> Result<Option<String>, CacheError> {
+        Ok(self
+            .entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .responses
+            .get(prompt)
+            .cloned())
+    }
+
+    async fn insert(&self, prompt: &str, response: &str) -> Result<(), CacheError> {
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+        if entries
+            .responses
+            .insert(prompt.to_string(), response.to_string())
+            .is_none()
+        {
+            entries.order.push_back(prompt.to_string());
+        }
+        if let Some(capacity) = self.capacity {
+            while entries.order.len() > capacity {
+                if let Some(oldest) = entries.order.pop_front() {
+                    entries.responses.remove(&oldest);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cache that returns the response of the most similar previous prompt, provided that its
+/// cosine similarity with the new prompt is greater or equal to the cache's threshold
+/// (0.95 by default).
+pub struct SemanticCache<M: EmbeddingModel> {
+    model: M,
+    threshold: f64,
+    /// Cached responses, in insertion order
+    entries: RwLock<VecDeque<(Embedding, String)>>,
+    /// Maximum number of cached responses (see [SemanticCache::capacity])
+    capacity: Option<usize>,
+    /// Embeddings of prompts that missed the cache, kept until their response is inserted
+    /// (or the prompt is released) so that prompts are only embedded once.
+    pending: RwLock<HashMap<String, Embedding>>,
+}
+
+impl<M: EmbeddingModel> SemanticCache<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            threshold: 0.95,
+            entries: RwLock::new(VecDeque::new()),
+            capacity: None,
+            pending: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Set the minimum cosine similarity between two prompts for them to be considered
+    /// semantically identical.
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the maximum number of cached responses, the oldest responses being evicted first
+    /// (unbounded by default). Each lookup compares the prompt to every cached response.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+}
+
+impl<M: EmbeddingModel> PromptCache for SemanticCache<M> {
+    async fn get(&self, prompt: &str) -> Result<Option<String>, CacheError> {
+        let embedding = self.model.embed_text(prompt).await?;
+
+        let response = self
+            .entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(cached, response)| (cached.cosine_similarity(&embedding, false), response))
+            .filter(|(similarity, _)| *similarity >= self.threshold)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(similarity, response)| {
+                tracing::debug!(target: "rig", "Semantic cache hit (similarity: {similarity})");
+                response.clone()
+            });
+
+        if response.is_none() {
+            self.pending
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(prompt.to_string(), embedding);
+        }
+
+        Ok(response)
+    }
+
+    async fn insert(&self, prompt: &str, response: &str) -> Result<(), CacheError> {
+        let pending = self
+            .pending
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(prompt);
+
+        let embedding = match pending {
+            Some(embedding) => embedding,
+            None => self.model.embed_text(prompt).await?,
+        };
+
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+        entries.push_back((embedding, response.to_string()));
+        if let Some(capacity) = self.capacity {
+            let evicted = entries.len().saturating_sub(capacity);
+            entries.drain(..evicted);
+        }
+        Ok(())
+    }
+
+    fn release(&self, prompt: &str) {
+        self.pending
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(prompt);
+    }
+}
+
+/// Layered caches: the first cache is checked before the second one and responses are
+/// inserted in both.
+impl<C1: PromptCache, C2: PromptCache> PromptCache for (C1, C2) {
+    async fn get(&self, prompt: &str) -> Result<Option<String>, CacheError> {
+        match self.0.get(prompt).await? {
+            Some(response) => Ok(Some(response)),
+            None => self.1.get(prompt).await,
+        }
+    }
+
+    async fn insert(&self, prompt: &str, response: &str) -> Result<(), CacheError> {
+        self.0.insert(prompt, response).await?;
+        self.1.insert(prompt, response).await
+    }
+
+    fn release(&self, prompt: &str) {
+        self.0.release(prompt);
+        self.1.release(prompt);
+    }
+}
+
+/// Guard releasing the state kept by a cache for a prompt that missed it when dropped, i.e.:
+/// once the prompt completes, fails or is cancelled (see [PromptCache::release]).
+struct Miss<'a, C: PromptCache> {
+    cache: &'a C,
+    prompt: &'a str,
+}
+
+impl<C: PromptCache> Drop for Miss<'_, C> {
+    fn drop(&mut self) {
+        self.cache.release(self.prompt);
+    }
+}
+
+/// Wrapper around a [Prompt] implementation (e.g.: an agent) that serves responses from a
+/// [PromptCache] when possible.
+///
+/// Cache errors never fail the prompt: they are logged and the prompt is sent to the
+/// wrapped model as if the cache had missed.
+pub struct CachedPrompt<P: Prompt, C: PromptCache> {
+    inner: P,
+    cache: C,
+    bypass: Option<Box<dyn Fn(&str) -> bool + Send + Sync>>,
+}
+
+impl<P: Prompt, C: PromptCache> CachedPrompt<P, C> {
+    pub fn new(inner: P, cache: C) -> Self {
+        Self {
+            inner,
+            cache,
+            bypass: None,
+        }
+    }
+
+    /// Opt-out of caching for prompts matching `predicate`. Such prompts (e.g.: time-sensitive
+    /// prompts) are always sent to the wrapped model and their responses are not cached.
+    pub fn bypass_if(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.bypass = Some(Box::new(predicate));
+        self
+    }
+
+    /// Get the cache of this prompt wrapper
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+}
+
+impl<P: Prompt, C: PromptCache> Prompt for CachedPrompt<P, C> {
+    async fn prompt(&self, prompt: &str) -> Result<String, PromptError> {
+        if self.bypass.as_ref().is_some_and(|bypass| bypass(prompt)) {
+            return self.inner.prompt(prompt).await;
+        }
+
+        match self.cache.get(prompt).await {
+            Ok(Some(response)) => return Ok(response),
+            Ok(None) => (),
+            Err(err) => tracing::warn!(target: "rig", "Failed to read from prompt cache: {err}"),
+        }
+        let _miss = Miss {
+            cache: &self.cache,
+            prompt,
+        };
+
+        let response = self.inner.prompt(prompt).await?;
+
+        if let Err(err) = self.cache.insert(prompt, &response).await {
+            tracing::warn!(target: "rig", "Failed to write to prompt cache: {err}");
+        }
+
+        Ok(response)
+    }
+}
+
+impl<P: Prompt + PromptWithStats, C: PromptCache> PromptWithStats for CachedPrompt<P, C> {
+    /// Same as [Prompt::prompt], reporting whether the response was served from the cache
+    /// (in which case no request is sent, hence no retries).
+    async fn prompt_with_stats(&self, prompt: &str) -> Result<(String, CallStats), PromptError> {
+        if self.bypass.as_ref().is_some_and(|bypass| bypass(prompt)) {
+            return self.inner.prompt_with_stats(prompt).await;
+        }
+
+        let start = Instant::now();
+        match self.cache.get(prompt).await {
+            Ok(Some(response)) => {
+                return Ok((
+                    response,
+                    CallStats {
+                        latency: start.elapsed(),
+                        retries: 0,
+                        cached: true,
+                    },
+                ))
+            }
+            Ok(None) => (),
+            Err(err) => tracing::warn!(target: "rig", "Failed to read from prompt cache: {err}"),
+        }
+        let _miss = Miss {
+            cache: &self.cache,
+            prompt,
+        };
+
+        let (response, stats) = self.inner.prompt_with_stats(prompt).await?;
+
+        if let Err(err) = self.cache.insert(prompt, &response).await {
+            tracing::warn!(target: "rig", "Failed to write to prompt cache: {err}");
+        }
+
+        Ok((
+            response,
+            CallStats {
+                latency: start.elapsed(),
+                ..stats
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    /// Prompt mock that counts how many prompts reached it
+    #[derive(Clone, Default)]
+    struct MockPrompt {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Prompt for MockPrompt {
+        async fn prompt(&self, prompt: &str) -> Result<String, PromptError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("Mock response: {}", prompt))
+        }
+    }
+
+    /// Prompt mock that always fails
+    struct FailingPrompt;
+
+    impl Prompt for FailingPrompt {
+        async fn prompt(&self, _prompt: &str) -> Result<String, PromptError> {
+            Err(PromptError::CompletionError(
+                crate::completion::CompletionError::ResponseError("Flurbo outage".to_string()),
+            ))
+        }
+    }
+
+    /// Bag-of-keywords embedding model: texts mentioning the same keywords embed identically
+    #[derive(Clone)]
+    struct MockEmbeddingModel;
+
+    const KEYWORDS: [&str; 3] = ["flurbo", "glarb", "weather"];
+
+    impl EmbeddingModel for MockEmbeddingModel {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            KEYWORDS.len() + 1
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| {
+                    let lowercase = text.to_lowercase();
+                    let mut vec = KEYWORDS
+                        .iter()
+                        .map(|keyword| lowercase.contains(keyword) as u8 as f64)
+                        .collect::<Vec<_>>();
+                    // Bias so that texts without keywords are not zero vectors
+                    vec.push(0.1);
+
+                    Embedding {
+                        document: text,
+                        vec,
+                    }
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache_hit() {
+        let model = MockPrompt::default();
+        let cached = CachedPrompt::new(model.clone(), SemanticCache::new(MockEmbeddingModel));
+
+        let response = cached.prompt("What is a flurbo?").await.unwrap();
+        assert_eq!(response, "Mock response: What is a flurbo?");
+        assert_eq!(model.calls.load(Ordering::SeqCst), 1);
+
+        // Paraphrased prompt is served from the cache
+        let response = cached
+            .prompt("Could you tell me what a Flurbo is?")
+            .await
+            .unwrap();
+        assert_eq!(response, "Mock response: What is a flurbo?");
+        assert_eq!(model.calls.load(Ordering::SeqCst), 1);
+
+        // Unrelated prompt misses the cache
+        let response = cached.prompt("What is a glarb?").await.unwrap();
+        assert_eq!(response, "Mock response: What is a glarb?");
+        assert_eq!(model.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache_threshold() {
+        let model = MockPrompt::default();
+        let cached = CachedPrompt::new(model.clone(), SemanticCache::new(MockEmbeddingModel));
+
+        cached.prompt("What is a flurbo?").await.unwrap();
+        // Related but not similar enough given the default threshold
+        cached
+            .prompt("Is a flurbo related to the weather?")
+            .await
+            .unwrap();
+        assert_eq!(model.calls.load(Ordering::SeqCst), 2);
+
+        let model = MockPrompt::default();
+        let cached = CachedPrompt::new(
+            model.clone(),
+            SemanticCache::new(MockEmbeddingModel).threshold(0.5),
+        );
+
+        cached.prompt("What is a flurbo?").await.unwrap();
+        let response = cached
+            .prompt("Is a flurbo related to the weather?")
+            .await
+            .unwrap();
+        assert_eq!(response, "Mock response: What is a flurbo?");
+        assert_eq!(model.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_exact_and_semantic_cache() {
+        let model = MockPrompt::default();
+        let cached = CachedPrompt::new(
+            model.clone(),
+            (ExactCache::new(), SemanticCache::new(MockEmbeddingModel)),
+        );
+
+        cached.prompt("What is a flurbo?").await.unwrap();
+        cached.prompt("What is a flurbo?").await.unwrap();
+        cached.prompt("Define flurbo").await.unwrap();
+        assert_eq!(model.calls.load(Ordering::SeqCst), 1);
+
+        assert_eq!(
+            cached.cache().0.get("What is a flurbo?").await.unwrap(),
+            Some("Mock response: What is a flurbo?".to_string())
+        );
+        assert_eq!(cached.cache().0.get("Define flurbo").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_cache_bypass() {
+        let model = MockPrompt::default();
+        let cached = CachedPrompt::new(model.clone(), SemanticCache::new(MockEmbeddingModel))
+            .bypass_if(|prompt| prompt.contains("today"));
+
+        cached.prompt("What is the weather today?").await.unwrap();
+        cached.prompt("What is the weather today?").await.unwrap();
+        assert_eq!(model.calls.load(Ordering::SeqCst), 2);
+
+        // Bypassed prompts are not cached either
+        cached.prompt("What is the weather like?").await.unwrap();
+        assert_eq!(model.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache_releases_failed_prompts() {
+        let cached = CachedPrompt::new(FailingPrompt, SemanticCache::new(MockEmbeddingModel));
+
+        assert!(cached.prompt("What is a flurbo?").await.is_err());
+        assert!(cached.prompt("What is a glarb?").await.is_err());
+        assert!(cached
+            .cache()
+            .pending
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cache_capacity() {
+        let model = MockPrompt::default();
+        let cached = CachedPrompt::new(
+            model.clone(),
+            (
+                ExactCache::new().capacity(2),
+                SemanticCache::new(MockEmbeddingModel).capacity(2),
+            ),
+        );
+
+        cached.prompt("What is a flurbo?").await.unwrap();
+        cached.prompt("What is a glarb?").await.unwrap();
+        cached.prompt("What is the weather?").await.unwrap();
+
+        // The oldest response was evicted from both caches
+        let (exact, semantic) = cached.cache();
+        assert_eq!(exact.get("What is a flurbo?").await.unwrap(), None);
+        assert_eq!(semantic.get("Define flurbo").await.unwrap(), None);
+        assert_eq!(
+            exact.get("What is a glarb?").await.unwrap(),
+            Some("Mock response: What is a glarb?".to_string())
+        );
+        assert_eq!(
+            semantic.get("Define glarb").await.unwrap(),
+            Some("Mock response: What is a glarb?".to_string())
+        );
+
+        cached.prompt("What is a flurbo?").await.unwrap();
+        assert_eq!(model.calls.load(Ordering::SeqCst), 4);
+    }
+}
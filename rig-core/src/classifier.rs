@@ -0,0 +1,318 @@
+//! This module provides the [Classifier], which makes a completion model answer with exactly one
+//! label of a fixed set (e.g.: for sentiment analysis or routing).
+//!
+//! The labels are enumerated in the preamble of the requests, along with a strict answer format
+//! (a JSON object with the label and the confidence of the model). Answers are validated against
+//! the labels (ignoring case, whitespace and code fences): when the model answers with anything
+//! else, it is re-prompted with a correction, up to [Classifier::max_attempts] times.
+//!
+//! For providers supporting OpenAI's structured outputs, the answers can also be constrained
+//! with a `response_format` JSON schema enumerating the labels (see
+//! [Classifier::constrain_response_format]).
+//!
+//! # Example
+//! ```rust
+//! use rig::{classifier::Classifier, providers::openai};
+//!
+//! let openai = openai::Client::from_env();
+//! let classifier = Classifier::new(openai.completion_model(openai::GPT_4O))
+//!     .preamble("Classify the sentiment of the reviews.");
+//!
+//! let classification = classifier
+//!     .classify("The battery died after two days.", &["positive", "negative", "neutral"])
+//!     .await?;
+//! assert_eq!(classification.label, "negative");
+//! ```
+use serde_json::json;
+
+use crate::{
+    completion::{CompletionError, CompletionModel, Message, ModelChoice},
+    json_enforcer::strip_code_fences,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClassificationError {
+    #[error("No labels to classify the input with")]
+    NoLabels,
+
+    #[error("Answer `{answer}` is not one of the labels after {attempts} attempts")]
+    InvalidAnswer { answer: String, attempts: usize },
+
+    #[error("CompletionError: {0}")]
+    CompletionError(#[from] CompletionError),
+}
+
+/// Label chosen by a [Classifier]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Classification {
+    /// Chosen label, as given to [Classifier::classify]
+    pub label: String,
+    /// Confidence of the model in the label (between `0.0` and `1.0`), if reported
+    pub confidence: Option<f64>,
+}
+
+/// Classifier of inputs into a fixed set of labels (see the [module](self) docs)
+#[derive(Clone)]
+pub struct Classifier<M: CompletionModel> {
+    model: M,
+    preamble: Option<String>,
+    temperature: Option<f64>,
+    max_attempts: usize,
+    response_format: bool,
+}
+
+impl<M: CompletionModel> Classifier<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            preamble: None,
+            temperature: None,
+            max_attempts: 3,
+            response_format: false,
+        }
+    }
+
+    /// Set instructions describing the classification task (e.g.: the meaning of the labels),
+    /// sent before the enumeration of the labels
+    pub fn preamble(mut self, preamble: &str) -> Self {
+        self.preamble = Some(preamble.to_string());
+        self
+    }
+
+    /// Set the temperature of the requests
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the maximum number of requests (including the re-prompts after invalid answers) made
+    /// to classify an input (3 by default, at least 1)
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Constrain the answers with a `response_format` JSON schema enumerating the labels, sent
+    /// in the additional params of the requests. Only enable it for providers supporting
+    /// OpenAI's structured outputs: other providers may reject the requests.
+    pub fn constrain_response_format(mut self) -> Self {
+        self.response_format = true;
+        self
+    }
+
+    /// Classify `input` into exactly one of `labels`, re-prompting the model when its answer is
+    /// not one of them
+    pub async fn classify(
+        &self,
+        input: &str,
+        labels: &[&str],
+    ) -> Result<Classification, ClassificationError> {
+        if labels.is_empty() {
+            return Err(ClassificationError::NoLabels);
+        }
+
+        let preamble = self.instructions(labels);
+        let mut chat_history = Vec::new();
+        let mut prompt = input.to_string();
+        let mut answer = String::new();
+
+        for _ in 0..self.max_attempts {
+            let mut request = self
+                .model
+                .completion_request(&prompt)
+                .preamble(preamble.clone())
+                .messages(chat_history.clone())
+                .temperature_opt(self.temperature);
+            if self.response_format {
+                request = request.additional_params(response_format(labels));
+            }
+
+            answer = match request.send().await?.choice {
+                ModelChoice::Message(message) => message,
+                ModelChoice::ToolCall(name, _) => format!("<call of the tool `{name}`>"),
+            };
+
+            if let Some(classification) = parse_answer(&answer, labels) {
+                return Ok(classification);
+            }
+
+            tracing::debug!(
+                target: "rig",
+                "Answer `{answer}` is not one of the labels, re-prompting"
+            );
+            chat_history.push(Message {
+                role: "user".to_string(),
+                content: std::mem::take(&mut prompt),
+            });
+            chat_history.push(Message {
+                role: "assistant".to_string(),
+                content: answer.clone(),
+            });
+            prompt = format!(
+                "Your answer `{answer}` is invalid: the label must be exactly one of {}. \
+                Answer again with only the JSON object.",
+                enumerate(labels)
+            );
+        }
+
+        Err(ClassificationError::InvalidAnswer {
+            answer,
+            attempts: self.max_attempts,
+        })
+    }
+
+    /// Preamble of the requests, enumerating the labels and describing the answer format
+    fn instructions(&self, labels: &[&str]) -> String {
+        let instructions = format!(
+            "Classify the input with exactly one of the following labels: {}.\n\
+            Respond with only a JSON object of the form \
+            {{\"label\": \"<label>\", \"confidence\": <number between 0 and 1>}}, \
+            without any explanation, and never with a label which is not in the list.",
+            enumerate(labels)
+        );
+
+        match &self.preamble {
+            Some(preamble) => format!("{preamble}\n\n{instructions}"),
+            None => instructions,
+        }
+    }
+}
+
+fn enumerate(labels: &[&str]) -> String {
+    labels
+        .iter()
+        .map(|label| format!("\"{label}\""))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// OpenAI-compatible `response_format` constraining the answers to the JSON objects of the
+/// answer format, with one of `labels`
+fn response_format(labels: &[&str]) -> serde_json::Value {
+    json!({
+        "response_format": {
+            "type": "json_schema",
+            "json_schema": {
+                "name": "classification",
+                "strict": true,
+                "schema": {
+                    "type": "object",
+                    "properties": {
+                        "label": { "type": "string", "enum": labels },
+                        "confidence": { "type": "number" }
+                    },
+                    "required": ["label", "confidence"],
+                    "additionalProperties": false
+                }
+            }
+        }
+    })
+}
+
+/// Parse an answer in the answer format (or a bare label, e.g.: from models ignoring the
+/// format), returning `None` if its label is not one of `labels`
+fn parse_answer(answer: &str, labels: &[&str]) -> Option<Classification> {
+    let answer = strip_code_fences(answer);
+    let (label, confidence) = match serde_json::from_str::<serde_json::Value>(answer) {
+        Ok(serde_json::Value::Object(object)) => (
+            object.get("label")?.as_str()?.to_string(),
+            object
+                .get("confidence")
+                .and_then(serde_json::Value::as_f64)
+                .filter(|confidence| (0.0..=1.0).contains(confidence)),
+        ),
+        Ok(serde_json::Value::String(label)) => (label, None),
+        _ => (answer.trim_end_matches('.').to_string(), None),
+    };
+
+    let label = label.trim().trim_matches(&['"', '\'', '`'][..]).trim();
+    labels
+        .iter()
+        .find(|candidate| candidate.trim().eq_ignore_ascii_case(label))
+        .map(|candidate| Classification {
+            label: candidate.to_string(),
+            confidence,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::tests::MockCompletionModel;
+
+    const LABELS: &[&str] = &["positive", "negative", "neutral"];
+
+    #[tokio::test]
+    async fn test_classify() {
+        let model = MockCompletionModel::new(vec![ModelChoice::Message(
+            "```json\n{\"label\": \"Negative\", \"confidence\": 0.9}\n```".to_string(),
+        )]);
+
+        let classification = Classifier::new(model.clone())
+            .constrain_response_format()
+            .classify("The battery died after two days.", LABELS)
+            .await
+            .unwrap();
+        assert_eq!(
+            classification,
+            Classification {
+                label: "negative".to_string(),
+                confidence: Some(0.9)
+            }
+        );
+
+        let requests = model.requests.lock().unwrap();
+        assert!(requests[0]
+            .preamble
+            .as_ref()
+            .unwrap()
+            .contains("\"positive\", \"negative\", \"neutral\""));
+        assert_eq!(
+            requests[0].additional_params.as_ref().unwrap()["response_format"]["json_schema"]
+                ["schema"]["properties"]["label"]["enum"],
+            json!(LABELS)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_answer_corrected() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message("{\"label\": \"angry\", \"confidence\": 0.8}".to_string()),
+            ModelChoice::Message("neutral.".to_string()),
+        ]);
+
+        let classification = Classifier::new(model.clone())
+            .classify("It works.", LABELS)
+            .await
+            .unwrap();
+        assert_eq!(classification.label, "neutral");
+        assert_eq!(classification.confidence, None);
+
+        // The model is re-prompted with its invalid answer and a correction
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].chat_history[0].content, "It works.");
+        assert_eq!(
+            requests[1].chat_history[1].content,
+            "{\"label\": \"angry\", \"confidence\": 0.8}"
+        );
+        assert!(requests[1].prompt.contains("is invalid"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_answer_rejected() {
+        let model = MockCompletionModel::new(vec![
+            ModelChoice::Message("angry".to_string()),
+            ModelChoice::Message("very angry".to_string()),
+        ]);
+
+        let result = Classifier::new(model)
+            .max_attempts(2)
+            .classify("I want a refund!", LABELS)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ClassificationError::InvalidAnswer { answer, attempts: 2 }) if answer == "very angry"
+        ));
+    }
+}
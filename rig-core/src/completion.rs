@@ -62,14 +62,31 @@
 //!
 //! For more information on how to use the completion functionality, refer to the documentation of
 //! the individual traits, structs, and enums defined in this module.
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{json_utils, tool::ToolSetError};
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+use crate::{
+    agent::PendingInput,
+    conversation::ConversationStoreError,
+    json_utils,
+    loaders::mime,
+    tool::{ToolError, ToolSetError},
+};
 
 // Errors
+/// Error returned by completion operations.
+///
+/// Variants wrapping an underlying error (e.g.: the transport or serde error) expose it
+/// through [std::error::Error::source], so the full chain can be walked down to the root cause.
 #[derive(Debug, Error)]
 pub enum CompletionError {
     /// Http error (e.g.: connection error, timeout, etc.)
@@ -91,6 +108,67 @@ pub enum CompletionError {
     /// Error returned by the completion model provider
     #[error("ProviderError: {0}")]
     ProviderError(String),
+
+    /// Error of the completion model provider caused by an underlying error (e.g.: the
+    /// websocket error of a realtime connection)
+    #[error("ProviderError: {0}")]
+    ProviderSourceError(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    /// The response was stopped by the content filter of the provider (e.g.: OpenAI's
+    /// `content_filter` finish reason). The prompt can be retried after being rephrased.
+    #[error(
+        "ContentFiltered: the response was filtered by the provider (categories: {categories:?})"
+    )]
+    ContentFiltered {
+        /// Content generated before the response was filtered, if any
+        partial: Option<String>,
+        /// Categories of the filter which were triggered (e.g.: "violence"), where provided
+        categories: Vec<String>,
+    },
+
+    /// The request is too large for the model: its estimated number of tokens (see
+    /// [CompletionRequest::estimated_prompt_tokens]) exceeds the token limit of the model. The
+    /// request is rejected before being sent (see [CompletionRequest::check_size]).
+    #[error(
+        "RequestTooLarge: the request has ~{estimated} tokens, more than the limit of {limit}"
+    )]
+    RequestTooLarge { estimated: u64, limit: u64 },
+
+    /// The response stream was aborted after receiving no chunk for the given idle timeout
+    /// (see [idle_timeout](crate::streaming::idle_timeout)), e.g.: the connection stalled
+    /// without being closed. Unlike a request timeout, it is measured between chunks.
+    #[error("StreamIdleTimeout: no chunk received for {0:?}")]
+    StreamIdleTimeout(std::time::Duration),
+
+    /// Error writing a streamed response to its sink (see
+    /// [StreamingCompletionModel::stream_to](crate::streaming::StreamingCompletionModel::stream_to))
+    #[error("WriteError: {0}")]
+    WriteError(#[source] std::io::Error),
+}
+
+impl CompletionError {
+    /// Whether the request failed because it exceeds the context window of the model, either
+    /// rejected before being sent ([CompletionError::RequestTooLarge]) or by the provider
+    /// (e.g.: OpenAI's `context_length_exceeded` error), in which case it can be retried
+    /// against a model with a larger context window (see
+    /// [ContextFallback](crate::context_fallback::ContextFallback)).
+    pub fn is_context_overflow(&self) -> bool {
+        match self {
+            CompletionError::RequestTooLarge { .. } => true,
+            CompletionError::ProviderError(message) => {
+                let message = message.to_lowercase();
+                [
+                    "context_length_exceeded",
+                    "maximum context length",
+                    "context window",
+                    "prompt is too long",
+                ]
+                .iter()
+                .any(|pattern| message.contains(pattern))
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -100,19 +178,56 @@ pub enum PromptError {
 
     #[error("ToolCallError: {0}")]
     ToolError(#[from] ToolSetError),
+
+    /// The (estimated) size of the prompt exceeds the token budget of the request
+    #[error("Token budget exceeded: prompt is ~{prompt_tokens} tokens, budget is {budget}")]
+    BudgetExceeded { budget: u64, prompt_tokens: u64 },
+
+    /// A tool called by the agent needs input from the user (see [ToolError::NeedsInput]).
+    /// The tool loop is paused and can be resumed with [Agent::resume](crate::agent::Agent::resume).
+    #[error("Tool `{}` needs input: {}", .0.toolname, .0.prompt)]
+    NeedsInput(Box<PendingInput>),
+
+    /// Error loading or saving the conversation history (see
+    /// [Agent::prompt_with_history](crate::agent::Agent::prompt_with_history))
+    #[error("ConversationStoreError: {0}")]
+    ConversationStoreError(#[from] ConversationStoreError),
+
+    /// No answer could be extracted from any of the samples of a self-consistent prompt (see
+    /// [Agent::prompt_self_consistent](crate::agent::Agent::prompt_self_consistent))
+    #[error("No answer in any of the {0} samples")]
+    NoAnswer(usize),
+
+    /// An output transform of the agent failed on its answer (see
+    /// [AgentBuilder::output_transform](crate::agent::AgentBuilder::output_transform))
+    #[error("OutputTransformError: {0}")]
+    OutputTransformError(Box<dyn std::error::Error + Send + Sync>),
+
+    /// An option of the agent is not supported by the call, e.g.: stripping the
+    /// chain-of-thought of streamed answers (see
+    /// [Agent::stream_prompt](crate::agent::Agent::stream_prompt))
+    #[error("Unsupported option: {0}")]
+    UnsupportedOption(String),
 }
 
 // ================================================================
 // Request models
 // ================================================================
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Message {
     /// "system", "user", or "assistant"
     pub role: String,
     pub content: String,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+impl Message {
+    /// Whether the message is a system message
+    pub fn is_system(&self) -> bool {
+        self.role == "system"
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Document {
     pub id: String,
     pub text: String,
@@ -142,7 +257,76 @@ impl std::fmt::Display for Document {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Only the text of a document is embedded, e.g.: for the documents produced by the loaders
+/// (see [FileLoader::into_documents](crate::loaders::file::FileLoader::into_documents)).
+impl crate::embeddings::Embed for Document {
+    fn embed(
+        &self,
+        embedder: &mut crate::embeddings::TextEmbedder,
+    ) -> Result<(), crate::embeddings::EmbedError> {
+        embedder.embed(self.text.clone());
+        Ok(())
+    }
+}
+
+/// An image attached to the prompt of a completion request, encoded in base64
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Image {
+    /// MIME type of the image (e.g.: `image/png`)
+    pub media_type: String,
+    /// Base64-encoded content of the image
+    pub data: String,
+}
+
+impl Image {
+    /// Read the image at `path`, detecting its MIME type from its content.
+    /// Fails with [CompletionError::RequestError] if the file cannot be read or is not an image.
+    ///
+    /// The file is read with blocking IO, which blocks the executor when called from async code:
+    /// prefer [Image::from_path_async] there (with the `tokio-fs` feature).
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, CompletionError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| CompletionError::RequestError(e.into()))?;
+        Self::from_file(path, bytes)
+    }
+
+    /// Same as [Image::from_path], reading the file asynchronously.
+    ///
+    /// Note: This method requires the `tokio-fs` feature to be enabled in the `Cargo.toml` file.
+    #[cfg(feature = "tokio-fs")]
+    pub async fn from_path_async(path: impl AsRef<Path>) -> Result<Self, CompletionError> {
+        let path = path.as_ref();
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(|e| CompletionError::RequestError(e.into()))?;
+        Self::from_file(path, bytes)
+    }
+
+    /// The image of the content `bytes` of the file at `path`
+    fn from_file(path: &Path, bytes: Vec<u8>) -> Result<Self, CompletionError> {
+        match mime::detect(&bytes, Some(path)) {
+            Some(media_type) if media_type.starts_with("image/") => Ok(Self {
+                media_type: media_type.to_string(),
+                data: BASE64_STANDARD.encode(bytes),
+            }),
+            media_type => Err(CompletionError::RequestError(
+                format!(
+                    "{} is not an image (detected type: {})",
+                    path.display(),
+                    media_type.unwrap_or("unknown")
+                )
+                .into(),
+            )),
+        }
+    }
+
+    /// The image as a `data:` URL (e.g.: `data:image/png;base64,...`)
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct ToolDefinition {
     pub name: String,
     pub description: String,
@@ -168,6 +352,28 @@ pub trait Prompt: Send + Sync {
     ) -> impl std::future::Future<Output = Result<String, PromptError>> + Send;
 }
 
+/// Statistics of a prompt (see [PromptWithStats]), e.g.: for performance dashboards.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CallStats {
+    /// Time elapsed between sending the prompt and receiving its response
+    pub latency: Duration,
+    /// Total number of retries of the completion requests sent for the prompt
+    /// (see [RetryPolicy])
+    pub retries: usize,
+    /// Whether the response was served from a cache (see [CachedPrompt](crate::cache::CachedPrompt))
+    pub cached: bool,
+}
+
+/// Trait defining a [Prompt] interface which also returns the [CallStats] of the prompt.
+pub trait PromptWithStats: Send + Sync {
+    /// Same as [Prompt::prompt], but also returns the latency, number of retries and cache
+    /// status of the prompt.
+    fn prompt_with_stats(
+        &self,
+        prompt: &str,
+    ) -> impl std::future::Future<Output = Result<(String, CallStats), PromptError>> + Send;
+}
+
 /// Trait defining a high-level LLM chat interface (i.e.: prompt and chat history in, response out).
 pub trait Chat: Send + Sync {
     /// Send a prompt with optional chat history to the underlying completion model.
@@ -207,7 +413,11 @@ pub trait Completion<M: CompletionModel> {
 
 /// General completion response struct that contains the high-level completion choice
 /// and the raw response.
-#[derive(Debug)]
+///
+/// Responses can be serialized and compared (e.g.: for snapshot testing). Since raw responses
+/// usually contain non-deterministic fields (ids, timestamps, etc.), use
+/// [CompletionResponse::without_raw_response] to only keep the completion choice.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct CompletionResponse<T> {
     /// The completion choice returned by the completion model provider
     pub choice: ModelChoice,
@@ -215,8 +425,18 @@ pub struct CompletionResponse<T> {
     pub raw_response: T,
 }
 
+impl<T> CompletionResponse<T> {
+    /// Drop the raw response (and its non-deterministic fields), keeping only the choice
+    pub fn without_raw_response(self) -> CompletionResponse<()> {
+        CompletionResponse {
+            choice: self.choice,
+            raw_response: (),
+        }
+    }
+}
+
 /// Enum representing the high-level completion choice returned by the completion model provider.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ModelChoice {
     /// Represents a completion response as a message
     Message(String),
@@ -225,6 +445,25 @@ pub enum ModelChoice {
     ToolCall(String, serde_json::Value),
 }
 
+/// Token usage of a completion request, as reported by the provider.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Usage {
+    /// Number of tokens in the prompt (including the preamble, context, etc.)
+    pub prompt_tokens: u64,
+    /// Number of tokens in the generated completion
+    pub completion_tokens: u64,
+    /// Total number of tokens used by the request
+    pub total_tokens: u64,
+    /// Number of prompt tokens read from the prompt cache of the provider (included in
+    /// `prompt_tokens`), if the provider reports it
+    #[serde(default)]
+    pub cached_tokens: u64,
+    /// Number of prompt tokens written to the prompt cache of the provider (included in
+    /// `prompt_tokens`), if the provider reports it
+    #[serde(default)]
+    pub cache_creation_tokens: u64,
+}
+
 /// Trait defining a completion model that can be used to generate completion responses.
 /// This trait is meant to be implemented by the user to define a custom completion model,
 /// either from a third party provider (e.g.: OpenAI) or a local model.
@@ -243,14 +482,271 @@ pub trait CompletionModel: Clone + Send + Sync {
     fn completion_request(&self, prompt: &str) -> CompletionRequestBuilder<Self> {
         CompletionRequestBuilder::new(self.clone(), prompt.to_string())
     }
+
+    /// Send a single completion request made of `messages` with the definitions of `tools`,
+    /// and return the response (i.e.: either a message or a tool call) for manual handling,
+    /// without calling the tool or looping like an [Agent](crate::agent::Agent).
+    ///
+    /// The leading `"system"` messages are joined into the preamble, the last message is the
+    /// prompt and the messages in between are the chat history.
+    ///
+    /// # Example
+    /// ```rust
+    /// let response = model
+    ///     .complete_with_tools(
+    ///         vec![Message { role: "user".into(), content: "What is 2 + 3?".into() }],
+    ///         vec![add_definition],
+    ///     )
+    ///     .await?;
+    ///
+    /// if let ModelChoice::ToolCall(name, args) = response.choice {
+    ///     // Call the tool...
+    /// }
+    /// ```
+    fn complete_with_tools(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolDefinition>,
+    ) -> impl std::future::Future<Output = Result<CompletionResponse<Self::Response>, CompletionError>>
+           + Send {
+        async move {
+            let system = messages
+                .iter()
+                .take_while(|message| message.role == "system")
+                .count();
+            let mut messages = messages;
+            let prompt = match messages.pop() {
+                Some(prompt) if messages.len() >= system => prompt,
+                _ => {
+                    return Err(CompletionError::RequestError(
+                        "The messages should end with a non-system message".into(),
+                    ))
+                }
+            };
+            let chat_history = messages.split_off(system);
+            let preamble = messages
+                .into_iter()
+                .map(|message| message.content)
+                .collect::<Vec<_>>();
+
+            let mut request = self
+                .completion_request(&prompt.content)
+                .messages(chat_history)
+                .tools(tools);
+            if !preamble.is_empty() {
+                request = request.preamble(preamble.join("\n"));
+            }
+            request.send().await
+        }
+    }
+
+    /// Token usage reported in a raw response of the model, if the provider reports it.
+    fn usage(_response: &Self::Response) -> Option<Usage> {
+        None
+    }
+
+    /// Number of times the request which resulted in `response` was retried before succeeding
+    /// (see [RetryPolicy]), for models retrying failed requests. Defaults to 0.
+    fn retries(_response: &Self::Response) -> usize {
+        0
+    }
+
+    /// Calls of native tools (see [NativeTool]) executed by the provider while generating
+    /// `response`, with their results, in order. Defaults to none, for models not supporting
+    /// native tools.
+    fn native_tool_results(_response: &Self::Response) -> Vec<NativeToolResult> {
+        vec![]
+    }
+
+    /// Chain-of-thought of reasoning models reported in a separate field of a raw response
+    /// (e.g.: `reasoning_content`), if any. Chains-of-thought inlined in the text of the
+    /// response are split from the answer by a [ReasoningStripper] instead.
+    fn reasoning(_response: &Self::Response) -> Option<String> {
+        None
+    }
+}
+
+/// Reasoning effort of reasoning models (e.g.: OpenAI's o-series models), trading latency for
+/// the quality of the answers (see [CompletionRequestBuilder::with_reasoning_effort]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+/// Delimiters of the chain-of-thought of reasoning models inlined in their responses (by
+/// default)
+pub const DEFAULT_REASONING_DELIMITERS: (&str, &str) = ("<think>", "</think>");
+
+/// Post-processor separating the chain-of-thought of the responses of reasoning models,
+/// delimited in the text of the responses (e.g.: `<think>...</think>`), from their final
+/// answer, so that the answer can be returned to end users while the chain-of-thought is kept
+/// for logs.
+///
+/// # Example
+/// ```rust
+/// use rig::completion::ReasoningStripper;
+///
+/// let stripped = ReasoningStripper::default()
+///     .strip("<think>Flurbos are a currency.</think>A flurbo is a made up currency.");
+/// assert_eq!(stripped.answer(), "A flurbo is a made up currency.");
+/// assert_eq!(stripped.reasoning(), Some("Flurbos are a currency."));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReasoningStripper {
+    open: String,
+    close: String,
+}
+
+impl Default for ReasoningStripper {
+    fn default() -> Self {
+        let (open, close) = DEFAULT_REASONING_DELIMITERS;
+        Self::new(open, close)
+    }
+}
+
+impl ReasoningStripper {
+    /// Stripper of the chains-of-thought delimited by `open` and `close`
+    pub fn new(open: &str, close: &str) -> Self {
+        Self {
+            open: open.to_string(),
+            close: close.to_string(),
+        }
+    }
+
+    /// Split `text` into its chain-of-thought and its final answer. Every delimited block is
+    /// part of the chain-of-thought, which also includes:
+    /// - the text before a closing delimiter without opening delimiter (for models whose
+    ///   opening delimiter is part of their prompt template)
+    /// - the text after an opening delimiter without closing delimiter (e.g.: when the
+    ///   response was truncated)
+    pub fn strip(&self, text: &str) -> StrippedAnswer {
+        let mut reasoning = vec![];
+        let mut answer = String::new();
+
+        let mut rest = text;
+        // Leading chain-of-thought without opening delimiter
+        if let Some(close) = rest.find(&self.close) {
+            if !rest[..close].contains(&self.open) {
+                reasoning.push(&rest[..close]);
+                rest = &rest[close + self.close.len()..];
+            }
+        }
+        while let Some(open) = rest.find(&self.open) {
+            answer.push_str(&rest[..open]);
+            rest = &rest[open + self.open.len()..];
+            match rest.find(&self.close) {
+                Some(close) => {
+                    reasoning.push(&rest[..close]);
+                    rest = &rest[close + self.close.len()..];
+                }
+                None => {
+                    reasoning.push(rest);
+                    rest = "";
+                }
+            }
+        }
+        answer.push_str(rest);
+
+        let reasoning = reasoning
+            .into_iter()
+            .map(str::trim)
+            .filter(|reasoning| !reasoning.is_empty())
+            .collect::<Vec<_>>();
+        StrippedAnswer {
+            answer: answer.trim().to_string(),
+            reasoning: (!reasoning.is_empty()).then(|| reasoning.join("\n\n")),
+        }
+    }
+}
+
+/// Final answer of a reasoning model separated from its chain-of-thought (see
+/// [ReasoningStripper]).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StrippedAnswer {
+    answer: String,
+    reasoning: Option<String>,
+}
+
+impl From<String> for StrippedAnswer {
+    /// Answer without chain-of-thought
+    fn from(answer: String) -> Self {
+        Self {
+            answer,
+            reasoning: None,
+        }
+    }
+}
+
+impl StrippedAnswer {
+    /// The answer, without its chain-of-thought
+    pub fn answer(&self) -> &str {
+        &self.answer
+    }
+
+    /// The chain-of-thought of the answer, if any
+    pub fn reasoning(&self) -> Option<&str> {
+        self.reasoning.as_deref()
+    }
+
+    pub fn into_answer(self) -> String {
+        self.answer
+    }
+
+    /// Prepend the chain-of-thought reported separately by the model (see
+    /// [CompletionModel::reasoning]) to the chain-of-thought of the answer
+    pub fn with_reasoning(mut self, reasoning: Option<String>) -> Self {
+        let reasoning = reasoning.filter(|reasoning| !reasoning.trim().is_empty());
+        self.reasoning = match (reasoning, self.reasoning) {
+            (Some(reasoning), Some(inlined)) => Some(format!("{}\n\n{inlined}", reasoning.trim())),
+            (reasoning, inlined) => reasoning.map(|r| r.trim().to_string()).or(inlined),
+        };
+        self
+    }
+}
+
+/// Tool executed by the provider itself (e.g.: a web search), enabled in a request (see
+/// [CompletionRequestBuilder::native_tool]) without being implemented. Unlike the calls of the
+/// tools of the request, the calls of native tools are executed on the side of the provider
+/// within the request, and their results are reported separately (see
+/// [CompletionModel::native_tool_results]). Providers not supporting native tools ignore them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NativeTool {
+    /// Search the web (OpenAI's `web_search_preview`, Anthropic's `web_search`), with an
+    /// optional maximum number of searches per request (only supported by Anthropic)
+    WebSearch { max_uses: Option<u32> },
+    /// Run code in a sandbox (OpenAI's `code_interpreter`, Anthropic's `code_execution`)
+    CodeExecution,
+    /// Provider-specific definition of a native tool, sent as is with the tools of the request
+    Custom(serde_json::Value),
+}
+
+/// Call of a [NativeTool] executed by the provider, along with its result (see
+/// [CompletionModel::native_tool_results]).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct NativeToolResult {
+    /// Id of the call
+    pub id: String,
+    /// Name of the tool (e.g.: `web_search`)
+    pub name: String,
+    /// Input of the call (e.g.: the search query), or null if not reported by the provider
+    pub input: serde_json::Value,
+    /// Result of the call, in the format of the provider
+    pub output: serde_json::Value,
 }
 
 /// Struct representing a general completion request that can be sent to a completion model provider.
+#[derive(Clone)]
 pub struct CompletionRequest {
     /// The prompt to be sent to the completion model provider
     pub prompt: String,
     /// The preamble to be sent to the completion model provider
     pub preamble: Option<String>,
+    /// Per-call system instructions, sent after the preamble (see
+    /// [CompletionRequest::system_instructions])
+    pub system: Vec<String>,
     /// The chat history to be sent to the completion model provider
     pub chat_history: Vec<Message>,
     /// The documents to be sent to the completion model provider
@@ -263,9 +759,70 @@ pub struct CompletionRequest {
     pub max_tokens: Option<u64>,
     /// Additional provider-specific parameters to be sent to the completion model provider
     pub additional_params: Option<serde_json::Value>,
+    /// Idempotency key sent to providers supporting it (e.g.: OpenAI's `Idempotency-Key`
+    /// header) so that retries of the request are deduplicated. Generated per request by
+    /// the provider if not set.
+    pub idempotency_key: Option<String>,
+    /// Images attached to the prompt, sent along with it as a mixed content message by
+    /// providers supporting images (e.g.: OpenAI)
+    pub images: Vec<Image>,
+    /// Reasoning effort sent to the reasoning models of providers supporting it (e.g.:
+    /// OpenAI's o-series models), ignored for other models
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Tools executed by the provider (see [NativeTool])
+    pub native_tools: Vec<NativeTool>,
 }
 
 impl CompletionRequest {
+    /// System-level instructions of the request, in order:
+    /// 1. the preamble,
+    /// 2. the system messages of the chat history (e.g.: injected context), in order,
+    /// 3. the per-call system instructions (see [CompletionRequestBuilder::system]), in order.
+    ///
+    /// Empty instructions are skipped. Providers taking a single system prompt (e.g.:
+    /// Anthropic) send them concatenated (see [CompletionRequest::merged_system]), while
+    /// providers taking system messages (e.g.: OpenAI) send them as the leading messages of
+    /// the conversation. Either way, the system messages are removed from the chat history.
+    pub fn system_instructions(&self) -> Vec<&str> {
+        self.preamble
+            .iter()
+            .map(String::as_str)
+            .chain(
+                self.chat_history
+                    .iter()
+                    .filter(|message| message.is_system())
+                    .map(|message| message.content.as_str()),
+            )
+            .chain(self.system.iter().map(String::as_str))
+            .filter(|instruction| !instruction.trim().is_empty())
+            .collect()
+    }
+
+    /// The system instructions of the request (see [CompletionRequest::system_instructions])
+    /// concatenated, separated by blank lines, or `None` if there are none.
+    pub fn merged_system(&self) -> Option<String> {
+        let instructions = self.system_instructions();
+        (!instructions.is_empty()).then(|| instructions.join("\n\n"))
+    }
+
+    /// The chat history led by the system instructions as system messages (see
+    /// [CompletionRequest::system_instructions]), for providers taking system messages.
+    pub(crate) fn history_with_system_messages(&self) -> Vec<Message> {
+        self.system_instructions()
+            .into_iter()
+            .map(|instruction| Message {
+                role: "system".into(),
+                content: instruction.to_string(),
+            })
+            .chain(
+                self.chat_history
+                    .iter()
+                    .filter(|message| !message.is_system())
+                    .cloned(),
+            )
+            .collect()
+    }
+
     pub(crate) fn prompt_with_context(&self) -> String {
         if !self.documents.is_empty() {
             format!(
@@ -281,6 +838,112 @@ impl CompletionRequest {
             self.prompt.clone()
         }
     }
+
+    /// Rough estimate of the number of tokens of the request sent to the model (preamble, chat
+    /// history, documents, tool definitions and prompt), assuming ~4 characters per token.
+    /// Actual token counts depend on the tokenizer of the model.
+    pub fn estimated_prompt_tokens(&self) -> u64 {
+        let chars = self.preamble.as_ref().map_or(0, |preamble| preamble.len())
+            + self.system.iter().map(String::len).sum::<usize>()
+            + self
+                .chat_history
+                .iter()
+                .map(|message| message.role.len() + message.content.len())
+                .sum::<usize>()
+            + self
+                .tools
+                .iter()
+                .map(|tool| {
+                    tool.name.len() + tool.description.len() + tool.parameters.to_string().len()
+                })
+                .sum::<usize>()
+            + self.prompt_with_context().len();
+
+        chars.div_ceil(4) as u64
+    }
+
+    /// Check the size of the request against a token `limit` (e.g.: the context window of the
+    /// model) before sending it, failing with [CompletionError::RequestTooLarge] if its
+    /// estimated number of tokens (see [CompletionRequest::estimated_prompt_tokens]) exceeds the
+    /// limit. As the estimate is rough, only requests clearly exceeding the limit should be
+    /// rejected: the limit should not account for the tokens of the completion.
+    pub fn check_size(&self, limit: u64) -> Result<(), CompletionError> {
+        let estimated = self.estimated_prompt_tokens();
+        if estimated > limit {
+            return Err(CompletionError::RequestTooLarge { estimated, limit });
+        }
+        Ok(())
+    }
+}
+
+/// Policy for retrying the requests to a provider that failed with a transient error
+/// (i.e.: connection errors, rate limits and server errors), with exponential backoff.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rig::{completion::RetryPolicy, providers::openai};
+///
+/// let model = openai::Client::from_env()
+///     .completion_model(openai::GPT_4O)
+///     .retry_policy(RetryPolicy::new(3).backoff(Duration::from_millis(500)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries of a request (0 means no retries)
+    pub max_retries: usize,
+    /// Delay before the first retry, doubled after each retry
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Retry requests up to `max_retries` times, with an initial backoff of 1 second
+    pub fn new(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the delay before the first retry (doubled after each retry)
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Delay before the given retry (starting at 0)
+    pub(crate) fn delay(&self, retry: usize) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry as u32))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// No retries
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// Generate a random idempotency key (see [CompletionRequest::idempotency_key])
+pub(crate) fn generate_idempotency_key() -> String {
+    use std::hash::{BuildHasher, Hasher};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        hasher.finish()
+    };
+
+    format!("rig-{:016x}{:016x}", random(), random())
 }
 
 /// Builder struct for constructing a completion request.
@@ -331,12 +994,17 @@ pub struct CompletionRequestBuilder<M: CompletionModel> {
     model: M,
     prompt: String,
     preamble: Option<String>,
+    system: Vec<String>,
     chat_history: Vec<Message>,
     documents: Vec<Document>,
     tools: Vec<ToolDefinition>,
     temperature: Option<f64>,
     max_tokens: Option<u64>,
     additional_params: Option<serde_json::Value>,
+    idempotency_key: Option<String>,
+    images: Vec<Image>,
+    reasoning_effort: Option<ReasoningEffort>,
+    native_tools: Vec<NativeTool>,
 }
 
 impl<M: CompletionModel> CompletionRequestBuilder<M> {
@@ -345,12 +1013,17 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
             model,
             prompt,
             preamble: None,
+            system: Vec::new(),
             chat_history: Vec::new(),
             documents: Vec::new(),
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
             additional_params: None,
+            idempotency_key: None,
+            images: Vec::new(),
+            reasoning_effort: None,
+            native_tools: Vec::new(),
         }
     }
 
@@ -360,6 +1033,13 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
         self
     }
 
+    /// Adds a per-call system instruction to the completion request, sent after the preamble
+    /// and the system messages of the chat history (see [CompletionRequest::system_instructions]).
+    pub fn system(mut self, system: String) -> Self {
+        self.system.push(system);
+        self
+    }
+
     /// Adds a message to the chat history for the completion request.
     pub fn message(mut self, message: Message) -> Self {
         self.chat_history.push(message);
@@ -386,6 +1066,18 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
             .fold(self, |builder, doc| builder.document(doc))
     }
 
+    /// Attaches an image to the prompt of the completion request.
+    pub fn image(mut self, image: Image) -> Self {
+        self.images.push(image);
+        self
+    }
+
+    /// Attaches a list of images to the prompt of the completion request.
+    pub fn images(mut self, images: Vec<Image>) -> Self {
+        self.images.extend(images);
+        self
+    }
+
     /// Adds a tool to the completion request.
     pub fn tool(mut self, tool: ToolDefinition) -> Self {
         self.tools.push(tool);
@@ -452,17 +1144,49 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
         self
     }
 
+    /// Sets the idempotency key of the completion request, sent with every (re)try of the
+    /// request so that the provider deduplicates them (see [CompletionRequest::idempotency_key]).
+    pub fn with_idempotency_key(mut self, idempotency_key: String) -> Self {
+        self.idempotency_key = Some(idempotency_key);
+        self
+    }
+
+    /// Sets the reasoning effort of the completion request. Only sent to reasoning models
+    /// (e.g.: OpenAI's o-series models), and ignored with a debug log for other models.
+    pub fn with_reasoning_effort(mut self, reasoning_effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(reasoning_effort);
+        self
+    }
+
+    /// Enables a tool executed by the provider (see [NativeTool]) for the completion request.
+    pub fn native_tool(mut self, tool: NativeTool) -> Self {
+        self.native_tools.push(tool);
+        self
+    }
+
+    /// Enables multiple tools executed by the provider (see [NativeTool]) for the completion
+    /// request.
+    pub fn native_tools(mut self, tools: Vec<NativeTool>) -> Self {
+        self.native_tools.extend(tools);
+        self
+    }
+
     /// Builds the completion request.
     pub fn build(self) -> CompletionRequest {
         CompletionRequest {
             prompt: self.prompt,
             preamble: self.preamble,
+            system: self.system,
             chat_history: self.chat_history,
             documents: self.documents,
             tools: self.tools,
             temperature: self.temperature,
             max_tokens: self.max_tokens,
             additional_params: self.additional_params,
+            idempotency_key: self.idempotency_key,
+            images: self.images,
+            reasoning_effort: self.reasoning_effort,
+            native_tools: self.native_tools,
         }
     }
 
@@ -475,8 +1199,150 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
 
 #[cfg(test)]
 mod tests {
+    use std::error::Error as _;
+
     use super::*;
 
+    fn root_cause(err: &(dyn std::error::Error + 'static)) -> &(dyn std::error::Error + 'static) {
+        let mut current = err;
+        while let Some(source) = current.source() {
+            current = source;
+        }
+        current
+    }
+
+    #[test]
+    fn test_strip_reasoning() {
+        let stripper = ReasoningStripper::default();
+
+        let stripped = stripper.strip(
+            "<think>\nThe user asks about flurbos.\n</think>\n\nA flurbo is a made up currency.",
+        );
+        assert_eq!(stripped.answer(), "A flurbo is a made up currency.");
+        assert_eq!(stripped.reasoning(), Some("The user asks about flurbos."));
+
+        // Several blocks, and a truncated one
+        let stripped =
+            stripper.strip("<think>First</think>A flurbo<think>Second</think> is<think>Third");
+        assert_eq!(stripped.answer(), "A flurbo is");
+        assert_eq!(stripped.reasoning(), Some("First\n\nSecond\n\nThird"));
+
+        // The opening delimiter is part of the prompt template
+        let stripped = stripper.strip("Flurbos are a currency.</think>A flurbo is a currency.");
+        assert_eq!(stripped.answer(), "A flurbo is a currency.");
+        assert_eq!(stripped.reasoning(), Some("Flurbos are a currency."));
+
+        let stripped = ReasoningStripper::new("[reasoning]", "[/reasoning]")
+            .strip("A flurbo is a currency.")
+            .with_reasoning(Some("Flurbos are a currency.".to_string()));
+        assert_eq!(stripped.answer(), "A flurbo is a currency.");
+        assert_eq!(stripped.reasoning(), Some("Flurbos are a currency."));
+    }
+
+    #[tokio::test]
+    async fn test_http_error_source_chain() {
+        // The listener is closed before the request is sent, so the connection is refused
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let err: CompletionError = reqwest::Client::new()
+            .get(format!("http://{addr}"))
+            .send()
+            .await
+            .unwrap_err()
+            .into();
+
+        assert!(err
+            .source()
+            .is_some_and(|source| source.downcast_ref::<reqwest::Error>().is_some()));
+
+        let err = PromptError::from(err);
+        let root = root_cause(&err)
+            .downcast_ref::<std::io::Error>()
+            .expect("root cause should be an io error");
+        assert_eq!(root.kind(), std::io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn test_json_error_source_chain() {
+        let err: CompletionError = serde_json::from_str::<serde_json::Value>("{\"choices\": [")
+            .unwrap_err()
+            .into();
+
+        let err = PromptError::from(err);
+        assert!(err
+            .source()
+            .is_some_and(|source| source.downcast_ref::<CompletionError>().is_some()));
+        assert!(root_cause(&err)
+            .downcast_ref::<serde_json::Error>()
+            .is_some_and(|root| root.is_eof()));
+    }
+
+    #[test]
+    fn test_request_error_source_chain() {
+        let err = CompletionError::RequestError(Box::new(ToolSetError::ToolNotFoundError(
+            "missing".to_string(),
+        )));
+
+        assert!(matches!(
+            err.source()
+                .and_then(|source| source.downcast_ref::<ToolSetError>()),
+            Some(ToolSetError::ToolNotFoundError(_))
+        ));
+    }
+
+    #[test]
+    fn test_provider_error_source_chain() {
+        let err = CompletionError::ProviderSourceError(Box::new(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "connection reset by peer",
+        )));
+        assert_eq!(err.to_string(), "ProviderError: connection reset by peer");
+
+        let err = PromptError::from(err);
+        assert_eq!(
+            root_cause(&err)
+                .downcast_ref::<std::io::Error>()
+                .map(std::io::Error::kind),
+            Some(std::io::ErrorKind::ConnectionReset)
+        );
+    }
+
+    #[test]
+    fn test_write_error_source_chain() {
+        let err = CompletionError::WriteError(std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "sink closed",
+        ));
+
+        let err = PromptError::from(err);
+        assert!(err
+            .source()
+            .is_some_and(|source| source.downcast_ref::<CompletionError>().is_some()));
+        assert_eq!(
+            root_cause(&err)
+                .downcast_ref::<std::io::Error>()
+                .map(std::io::Error::kind),
+            Some(std::io::ErrorKind::BrokenPipe)
+        );
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn test_image_from_path_async() {
+        let image = Image::from_path_async("tests/data/pixel.png")
+            .await
+            .unwrap();
+        assert_eq!(image.media_type, "image/png");
+        assert_eq!(image, Image::from_path("tests/data/pixel.png").unwrap());
+
+        assert!(matches!(
+            Image::from_path_async("tests/data/hyphenated.txt").await,
+            Err(CompletionError::RequestError(_))
+        ));
+    }
+
     #[test]
     fn test_document_display_without_metadata() {
         let doc = Document {
@@ -527,12 +1393,17 @@ mod tests {
         let request = CompletionRequest {
             prompt: "What is the capital of France?".to_string(),
             preamble: None,
+            system: Vec::new(),
             chat_history: Vec::new(),
             documents: vec![doc1, doc2],
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
             additional_params: None,
+            idempotency_key: None,
+            images: vec![],
+            reasoning_effort: None,
+            native_tools: vec![],
         };
 
         let expected = concat!(
@@ -0,0 +1,71 @@
+//! This module provides the [ConcurrencyLimiter], a semaphore capping the number of requests
+//! in flight to a provider across all the models sharing it (e.g.: the embedding model used to
+//! index documents and the completion model of a chat agent), so that unrelated paths of a
+//! process do not exceed the concurrency cap of the provider together.
+//!
+//! # Example
+//! ```rust
+//! use rig::{concurrency::ConcurrencyLimiter, providers::openai};
+//!
+//! // At most 8 requests in flight, embeddings and completions combined
+//! let openai = openai::Client::from_env().with_concurrency_limiter(ConcurrencyLimiter::new(8));
+//!
+//! let embedding_model = openai.embedding_model(openai::TEXT_EMBEDDING_3_SMALL);
+//! let agent = openai.agent(openai::GPT_4O).build();
+//! ```
+use std::{future::Future, sync::Arc};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Semaphore capping the number of concurrent requests of the clients and models sharing it
+/// (see the [module](self) documentation). Clones of a limiter share its permits.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimiter {
+    semaphore: Arc<Semaphore>,
+    max_concurrency: usize,
+}
+
+/// Permit of a [ConcurrencyLimiter], released when dropped.
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ConcurrencyLimiter {
+    /// Limiter allowing at most `max_concurrency` (at least 1) concurrent requests
+    pub fn new(max_concurrency: usize) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrency)),
+            max_concurrency,
+        }
+    }
+
+    /// Maximum number of concurrent requests
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// Number of requests which can currently start without waiting
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Wait for a permit to send a request, to be held until the request is done.
+    pub async fn acquire(&self) -> ConcurrencyPermit {
+        ConcurrencyPermit {
+            _permit: self
+                .semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("The semaphore of the limiter is never closed"),
+        }
+    }
+
+    /// Run `request` once a permit is acquired, releasing it when the request is done.
+    pub async fn run<F: Future>(&self, request: F) -> F::Output {
+        let _permit = self.acquire().await;
+        request.await
+    }
+}
@@ -0,0 +1,232 @@
+//! This module provides the [ContextFallback] completion model wrapper, which retries requests
+//! exceeding the context window of a model against models of the same family with larger
+//! context windows, instead of failing.
+//!
+//! The models are tried in order, from the smallest context window to the largest: a request
+//! failing with a context overflow (see [CompletionError::is_context_overflow]) is transparently
+//! sent to the next model, while other errors are returned as is. The name of the model which
+//! served the request is reported in the [FallbackResponse].
+//!
+//! Since [ContextFallback] is itself a [CompletionModel], it composes with agents and extractors.
+//!
+//! # Example
+//! ```rust
+//! use rig::{context_fallback::ContextFallback, providers::openai};
+//!
+//! let openai = openai::Client::from_env();
+//! let model = ContextFallback::new(openai::GPT_4, openai.completion_model(openai::GPT_4))
+//!     .fallback(openai::GPT_4_32K, openai.completion_model(openai::GPT_4_32K));
+//!
+//! let response = model.completion_request(&long_prompt).send().await?;
+//! println!("Served by {}", response.raw_response.model);
+//! ```
+use crate::{
+    completion::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, NativeToolResult,
+        Usage,
+    },
+    streaming::{StreamingCompletionModel, StreamingResult},
+};
+
+/// Raw response of a [ContextFallback], reporting which model served the request
+#[derive(Clone, Debug, PartialEq)]
+pub struct FallbackResponse<T> {
+    /// Name of the model which served the request
+    pub model: String,
+    /// Number of models whose context window was exceeded before the request was served
+    pub fallbacks: usize,
+    /// Raw response of the model which served the request
+    pub response: T,
+}
+
+/// Completion model wrapper falling back to models with larger context windows (see the
+/// [module](self) docs)
+#[derive(Clone)]
+pub struct ContextFallback<M: CompletionModel> {
+    models: Vec<(String, M)>,
+}
+
+impl<M: CompletionModel> ContextFallback<M> {
+    /// Send the requests to `model` (named `name`) first
+    pub fn new(name: &str, model: M) -> Self {
+        Self {
+            models: vec![(name.to_string(), model)],
+        }
+    }
+
+    /// Add `model` (named `name`), with a larger context window than the previous models, to
+    /// which the requests exceeding their context windows are sent
+    pub fn fallback(mut self, name: &str, model: M) -> Self {
+        self.models.push((name.to_string(), model));
+        self
+    }
+
+    /// Send `request` to the models in order until one accepts it, returning the index of the
+    /// model along with its result
+    async fn try_models<T, F>(
+        &self,
+        request: CompletionRequest,
+        send: impl Fn(M, CompletionRequest) -> F,
+    ) -> (usize, Result<T, CompletionError>)
+    where
+        F: std::future::Future<Output = Result<T, CompletionError>>,
+    {
+        let last = self.models.len() - 1;
+        for (index, (name, model)) in self.models[..last].iter().enumerate() {
+            match send(model.clone(), request.clone()).await {
+                Err(e) if e.is_context_overflow() => {
+                    tracing::info!(
+                        target: "rig",
+                        "Request exceeds the context window of {name}, falling back to {}: {e}",
+                        self.models[index + 1].0
+                    );
+                }
+                result => return (index, result),
+            }
+        }
+
+        (last, send(self.models[last].1.clone(), request).await)
+    }
+}
+
+impl<M: CompletionModel> CompletionModel for ContextFallback<M> {
+    type Response = FallbackResponse<M::Response>;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let (index, result) = self
+            .try_models(request, |model, request| async move {
+                model.completion(request).await
+            })
+            .await;
+        let response = result?;
+
+        Ok(CompletionResponse {
+            choice: response.choice,
+            raw_response: FallbackResponse {
+                model: self.models[index].0.clone(),
+                fallbacks: index,
+                response: response.raw_response,
+            },
+        })
+    }
+
+    fn usage(response: &Self::Response) -> Option<Usage> {
+        M::usage(&response.response)
+    }
+
+    fn retries(response: &Self::Response) -> usize {
+        M::retries(&response.response)
+    }
+
+    fn native_tool_results(response: &Self::Response) -> Vec<NativeToolResult> {
+        M::native_tool_results(&response.response)
+    }
+
+    fn reasoning(response: &Self::Response) -> Option<String> {
+        M::reasoning(&response.response)
+    }
+}
+
+/// Streams fall back to the next model only when the request is rejected before the stream
+/// starts (e.g.: with [CompletionError::RequestTooLarge]). The model which served the request is
+/// logged, as streams do not have a raw response.
+impl<M: StreamingCompletionModel> StreamingCompletionModel for ContextFallback<M> {
+    async fn stream(&self, request: CompletionRequest) -> Result<StreamingResult, CompletionError> {
+        let (index, result) = self
+            .try_models(request, |model, request| async move {
+                model.stream(request).await
+            })
+            .await;
+        if result.is_ok() {
+            tracing::debug!(target: "rig", "Request streamed by {}", self.models[index].0);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::completion::ModelChoice;
+
+    /// Model with a context window of `limit` (estimated) tokens, answering with its name
+    #[derive(Clone)]
+    struct Windowed {
+        name: &'static str,
+        limit: u64,
+    }
+
+    impl CompletionModel for Windowed {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            if request.prompt == "fail" {
+                return Err(CompletionError::ProviderError("Rate limited".to_string()));
+            }
+            request.check_size(self.limit)?;
+            Ok(CompletionResponse {
+                choice: ModelChoice::Message(self.name.to_string()),
+                raw_response: (),
+            })
+        }
+    }
+
+    fn model() -> ContextFallback<Windowed> {
+        let small = Windowed {
+            name: "small",
+            limit: 16,
+        };
+        let large = Windowed {
+            name: "large",
+            limit: 1_000,
+        };
+        ContextFallback::new("small", small).fallback("large", large)
+    }
+
+    #[tokio::test]
+    async fn test_context_fallback() {
+        let response = model().completion_request("Hello!").send().await.unwrap();
+        assert_eq!(response.choice, ModelChoice::Message("small".to_string()));
+        assert_eq!(response.raw_response.model, "small");
+        assert_eq!(response.raw_response.fallbacks, 0);
+
+        let prompt = "Summarize this. ".repeat(50);
+        let response = model().completion_request(&prompt).send().await.unwrap();
+        assert_eq!(response.choice, ModelChoice::Message("large".to_string()));
+        assert_eq!(response.raw_response.model, "large");
+        assert_eq!(response.raw_response.fallbacks, 1);
+
+        let prompt = "Summarize this. ".repeat(500);
+        let result = model().completion_request(&prompt).send().await;
+        assert!(matches!(
+            result,
+            Err(CompletionError::RequestTooLarge { limit: 1_000, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_not_retried() {
+        let result = model().completion_request("fail").send().await;
+        assert!(matches!(result, Err(CompletionError::ProviderError(_))));
+    }
+
+    #[test]
+    fn test_is_context_overflow() {
+        assert!(CompletionError::ProviderError(
+            "This model's maximum context length is 8192 tokens.".to_string()
+        )
+        .is_context_overflow());
+        assert!(CompletionError::RequestTooLarge {
+            estimated: 20,
+            limit: 16
+        }
+        .is_context_overflow());
+        assert!(!CompletionError::ProviderError("Rate limited".to_string()).is_context_overflow());
+    }
+}
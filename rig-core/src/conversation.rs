@@ -0,0 +1,280 @@
+//! This module provides the persistence of conversation histories across process restarts.
+//!
+//! Conversations are identified by a session id and stored in a [ConversationStore]:
+//! - [InMemoryConversationStore]: keeps the histories in memory (i.e.: they are lost when the
+//!   process exits). Used by agents by default.
+//! - [FileConversationStore]: stores the history of each session in a JSON file of a directory
+//!   (requires the `tokio-fs` feature and a tokio runtime).
+//!
+//! The history of a session is loaded, extended and saved automatically by
+//! [Agent::prompt_with_history](crate::agent::Agent::prompt_with_history).
+//!
+//! # Example
+//! ```rust
+//! use rig::{conversation::FileConversationStore, providers::openai};
+//!
+//! let openai = openai::Client::from_env();
+//!
+//! let agent = openai.agent(openai::GPT_4O)
+//!     .conversation_store(FileConversationStore::new("conversations"))
+//!     .build();
+//!
+//! agent.prompt_with_history("user-42", "My name is Bob.").await?;
+//! // Possibly after a restart of the process
+//! let response = agent.prompt_with_history("user-42", "What is my name?").await?;
+//! ```
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{PoisonError, RwLock},
+};
+
+#[cfg(feature = "tokio-fs")]
+use std::path::PathBuf;
+
+use futures::future::BoxFuture;
+
+use crate::completion::Message;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConversationStoreError {
+    /// Error reading or writing the stored history
+    #[error("IoError: {0}")]
+    IoError(#[from] std::io::Error),
+
+    /// Error serializing or deserializing the stored history
+    #[error("JsonError: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    /// The session id cannot be used by the store (e.g.: as a file name)
+    #[error("Invalid session id: {0}")]
+    InvalidSessionId(String),
+}
+
+/// Trait for stores of conversation histories, identified by session id.
+pub trait ConversationStore: Send + Sync {
+    /// Save `messages` as the history of the session `session_id`, replacing any previous
+    /// history of the session.
+    fn save(
+        &self,
+        session_id: &str,
+        messages: Vec<Message>,
+    ) -> impl Future<Output = Result<(), ConversationStoreError>> + Send;
+
+    /// Load the history of the session `session_id`. Unknown sessions have an empty history.
+    fn load(
+        &self,
+        session_id: &str,
+    ) -> impl Future<Output = Result<Vec<Message>, ConversationStoreError>> + Send;
+}
+
+/// Object-safe version of [ConversationStore], used by agents to hold their store.
+pub trait ConversationStoreDyn: Send + Sync {
+    fn save<'a>(
+        &'a self,
+        session_id: &'a str,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'a, Result<(), ConversationStoreError>>;
+
+    fn load<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>, ConversationStoreError>>;
+}
+
+impl<S: ConversationStore> ConversationStoreDyn for S {
+    fn save<'a>(
+        &'a self,
+        session_id: &'a str,
+        messages: Vec<Message>,
+    ) -> BoxFuture<'a, Result<(), ConversationStoreError>> {
+        Box::pin(ConversationStore::save(self, session_id, messages))
+    }
+
+    fn load<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>, ConversationStoreError>> {
+        Box::pin(ConversationStore::load(self, session_id))
+    }
+}
+
+/// Store keeping the conversation histories in memory.
+#[derive(Default)]
+pub struct InMemoryConversationStore {
+    sessions: RwLock<HashMap<String, Vec<Message>>>,
+}
+
+impl InMemoryConversationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConversationStore for InMemoryConversationStore {
+    async fn save(
+        &self,
+        session_id: &str,
+        messages: Vec<Message>,
+    ) -> Result<(), ConversationStoreError> {
+        self.sessions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(session_id.to_string(), messages);
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &str) -> Result<Vec<Message>, ConversationStoreError> {
+        Ok(self
+            .sessions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(session_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Store saving the history of each session in the JSON file `<session_id>.json` of a
+/// directory, which is created if needed. Session ids may only contain ASCII letters, digits,
+/// `-` and `_`.
+///
+/// Histories are written to a temporary file first and then renamed, so that a crash while
+/// saving does not corrupt the previous history of the session.
+#[cfg(feature = "tokio-fs")]
+#[derive(Clone, Debug)]
+pub struct FileConversationStore {
+    dir: PathBuf,
+}
+
+#[cfg(feature = "tokio-fs")]
+impl FileConversationStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, session_id: &str) -> Result<PathBuf, ConversationStoreError> {
+        if session_id.is_empty()
+            || !session_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ConversationStoreError::InvalidSessionId(
+                session_id.to_string(),
+            ));
+        }
+        Ok(self.dir.join(format!("{session_id}.json")))
+    }
+}
+
+#[cfg(feature = "tokio-fs")]
+impl ConversationStore for FileConversationStore {
+    async fn save(
+        &self,
+        session_id: &str,
+        messages: Vec<Message>,
+    ) -> Result<(), ConversationStoreError> {
+        let path = self.path(session_id)?;
+        let tmp_path = path.with_extension("json.tmp");
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(&tmp_path, serde_json::to_vec(&messages)?).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &str) -> Result<Vec<Message>, ConversationStoreError> {
+        match tokio::fs::read(self.path(session_id)?).await {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio-fs"))]
+mod tests {
+    use super::{ConversationStore, ConversationStoreError, FileConversationStore};
+    use crate::{
+        agent::{tests::MockCompletionModel, AgentBuilder},
+        completion::{Message, ModelChoice},
+    };
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let store = FileConversationStore::new(temp.path().join("conversations"));
+
+        assert!(store.load("session-1").await.unwrap().is_empty());
+
+        let messages = vec![message("user", "Hello"), message("assistant", "Hi!")];
+        store.save("session-1", messages.clone()).await.unwrap();
+
+        // A new store (e.g.: after a restart) reads the saved history
+        let store = FileConversationStore::new(temp.path().join("conversations"));
+        assert_eq!(store.load("session-1").await.unwrap(), messages);
+        assert!(store.load("session-2").await.unwrap().is_empty());
+
+        assert!(matches!(
+            store.save("../session", vec![]).await,
+            Err(ConversationStoreError::InvalidSessionId(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_with_history() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+
+        let agent = AgentBuilder::new(MockCompletionModel::new(vec![ModelChoice::Message(
+            "Nice to meet you, Bob!".to_string(),
+        )]))
+        .conversation_store(FileConversationStore::new(temp.path()))
+        .build();
+        agent
+            .prompt_with_history("session", "My name is Bob.")
+            .await
+            .unwrap();
+        drop(agent);
+
+        // A new agent reloads the history of the session and continues the conversation
+        let model =
+            MockCompletionModel::new(vec![ModelChoice::Message("Your name is Bob.".to_string())]);
+        let agent = AgentBuilder::new(model.clone())
+            .conversation_store(FileConversationStore::new(temp.path()))
+            .build();
+        let response = agent
+            .prompt_with_history("session", "What is my name?")
+            .await
+            .unwrap();
+        assert_eq!(response, "Your name is Bob.");
+
+        let requests = model.requests.lock().unwrap();
+        assert_eq!(requests[0].prompt, "What is my name?");
+        assert_eq!(
+            requests[0].chat_history,
+            vec![
+                message("user", "My name is Bob."),
+                message("assistant", "Nice to meet you, Bob!"),
+            ]
+        );
+
+        let history = FileConversationStore::new(temp.path())
+            .load("session")
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[3], message("assistant", "Your name is Bob."));
+        drop(requests);
+
+        // Other sessions are independent
+        agent.prompt_with_history("other", "Hi").await.unwrap();
+        assert!(model.requests.lock().unwrap()[1].chat_history.is_empty());
+    }
+}
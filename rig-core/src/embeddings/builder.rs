@@ -2,14 +2,20 @@
 //! and batch generates the embeddings for each object when built.
 //! Only types that implement the [Embed] trait can be added to the [EmbeddingsBuilder].
 
-use std::{cmp::max, collections::HashMap};
+use std::cmp::max;
 
 use futures::{stream, StreamExt};
 
 use crate::{
+    completion::RetryPolicy,
     embeddings::{
-        embed::TextEmbedder, Embed, EmbedError, Embedding, EmbeddingError, EmbeddingModel,
+        embed::TextEmbedder,
+        pooling::{Pooling, SentenceEmbeddings},
+        quantization::{Quantization, QuantizedEmbedding},
+        splitter::{sentences, Chunk, TextSplitter},
+        Embed, EmbedError, Embedding, EmbeddingError, EmbeddingModel, InputTruncation,
     },
+    truncation::TruncationPolicy,
     OneOrMany,
 };
 
@@ -50,6 +56,13 @@ use crate::{
 pub struct EmbeddingsBuilder<M: EmbeddingModel, T: Embed> {
     model: M,
     documents: Vec<(T, Vec<String>)>,
+    retry_policy: RetryPolicy,
+    truncation: Option<TruncationPolicy>,
+    normalizer: Option<Box<dyn Fn(&str) -> String + Send + Sync>>,
+    /// Whether documents failing [Embed::embed] are rejected (see [EmbeddingsBuilder::lenient])
+    lenient: bool,
+    /// Documents rejected in lenient mode, with their error
+    rejected: Vec<(T, EmbedError)>,
 }
 
 impl<M: EmbeddingModel, T: Embed> EmbeddingsBuilder<M, T> {
@@ -58,15 +71,89 @@ impl<M: EmbeddingModel, T: Embed> EmbeddingsBuilder<M, T> {
         Self {
             model,
             documents: vec![],
+            retry_policy: RetryPolicy::default(),
+            truncation: None,
+            normalizer: None,
+            lenient: false,
+            rejected: vec![],
         }
     }
 
+    /// Enable the lenient mode: the documents added afterwards whose texts cannot be extracted
+    /// (i.e.: failing [Embed::embed]) are rejected instead of failing
+    /// [EmbeddingsBuilder::document], and reported as failed by
+    /// [EmbeddingsBuilder::build_lenient], which isolates the documents failing to be embedded.
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Truncate the texts to embed longer than the token limit of `policy` (e.g.: the input
+    /// limit of the embedding model) instead of failing to embed them.
+    pub fn truncation(mut self, policy: TruncationPolicy) -> Self {
+        self.truncation = Some(policy);
+        self
+    }
+
+    /// Normalize the texts of the documents with `normalizer` (e.g.: lowercasing them, or
+    /// cleaning them with a [TextCleanup](crate::loaders::cleanup::TextCleanup)) before
+    /// embedding them. The documents themselves are kept as is, and so is the `document` of
+    /// their embeddings: searches use the normalized texts while the original ones are stored
+    /// and returned (e.g.: by [VectorStoreIndex::top_n](crate::vector_store::VectorStoreIndex::top_n)).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::embeddings::EmbeddingsBuilder;
+    ///
+    /// let embeddings = EmbeddingsBuilder::new(model)
+    ///     .normalizer(|text| text.to_lowercase())
+    ///     .document("Flurbos are a MADE UP currency.")?
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn normalizer(
+        mut self,
+        normalizer: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.normalizer = Some(Box::new(normalizer));
+        self
+    }
+
+    /// Let the provider truncate the texts exceeding the input limit of the model according to
+    /// `truncation` (e.g.: Cohere's `truncate: NONE|START|END`), for providers supporting it
+    /// (see [EmbeddingModel::with_input_truncation]).
+    ///
+    /// Providers which do not support it keep their default behavior (e.g.: OpenAI fails to
+    /// embed over-length texts): prefer truncating the texts before sending them with
+    /// [EmbeddingsBuilder::truncation], which works with any provider. Both can be combined,
+    /// the texts being truncated by the builder first.
+    pub fn input_truncation(mut self, truncation: InputTruncation) -> Self {
+        match self.model.with_input_truncation(truncation) {
+            Some(model) => self.model = model,
+            None => tracing::warn!(target: "rig",
+                "The embedding model does not support input truncation ({:?}), use `EmbeddingsBuilder::truncation` instead",
+                truncation
+            ),
+        }
+        self
+    }
+
+    /// Set the policy for re-embedding texts whose embedding returned by the provider does
+    /// not have the expected number of dimensions (see [EmbeddingModel::ndims]), e.g.: on a
+    /// flaky response. Without retries (the default), building fails on such embeddings.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
     /// Add a document to be embedded to the builder. `document` must implement the [Embed] trait.
     pub fn document(mut self, document: T) -> Result<Self, EmbedError> {
         let mut embedder = TextEmbedder::default();
-        document.embed(&mut embedder)?;
-
-        self.documents.push((document, embedder.texts));
+        match document.embed(&mut embedder) {
+            Ok(()) => self.documents.push((document, embedder.texts)),
+            Err(err) if self.lenient => self.rejected.push((document, err)),
+            Err(err) => return Err(err),
+        }
 
         Ok(self)
     }
@@ -82,69 +169,383 @@ impl<M: EmbeddingModel, T: Embed> EmbeddingsBuilder<M, T> {
     }
 }
 
+impl<M: EmbeddingModel> EmbeddingsBuilder<M, Chunk> {
+    /// Split documents into chunks using `splitter` and add the chunks to be embedded to the
+    /// builder. `documents` must be iteratable with items of the form `(id, text)`. Each chunk
+    /// carries the id of its parent document, its index within it and the ids of its neighbors
+    /// (see [Chunk]).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::embeddings::{splitter::CharacterSplitter, EmbeddingsBuilder};
+    ///
+    /// let embeddings = EmbeddingsBuilder::new(model)
+    ///     .documents_split(
+    ///         vec![("flurbo", "A flurbo is a green alien that lives on cold planets...")],
+    ///         CharacterSplitter::new(500).overlap(50),
+    ///     )?
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn documents_split(
+        self,
+        documents: impl IntoIterator<Item = (impl ToString, impl AsRef<str>)>,
+        splitter: impl TextSplitter,
+    ) -> Result<Self, EmbedError> {
+        let chunks = documents
+            .into_iter()
+            .flat_map(|(id, text)| {
+                let parent_id = id.to_string();
+                let texts = splitter.split(text.as_ref());
+                let count = texts.len();
+                texts
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(chunk_index, text)| {
+                        Chunk::new(&parent_id, chunk_index, count, text)
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        self.documents(chunks)
+    }
+}
+
 impl<M: EmbeddingModel, T: Embed + Send> EmbeddingsBuilder<M, T> {
     /// Generate embeddings for all documents in the builder.
     /// Returns a vector of tuples, where the first element is the document and the second element is the embeddings (either one embedding or many).
-    pub async fn build(self) -> Result<Vec<(T, OneOrMany<Embedding>)>, EmbeddingError> {
-        use stream::TryStreamExt;
+    ///
+    /// Building fails on the first document failing to be embedded, including the documents
+    /// rejected in lenient mode (see [EmbeddingsBuilder::build_lenient]).
+    pub async fn build(mut self) -> Result<Vec<(T, OneOrMany<Embedding>)>, EmbeddingError> {
+        if let Some((_, err)) = std::mem::take(&mut self.rejected).into_iter().next() {
+            return Err(EmbeddingError::DocumentError(Box::new(err)));
+        }
 
-        // Store the documents and their texts in a HashMap for easy access.
-        let mut docs = HashMap::new();
-        let mut texts = HashMap::new();
+        self.embed_documents(false)
+            .await?
+            .into_iter()
+            .map(|(doc, embeddings)| Ok((doc, embeddings?)))
+            .collect()
+    }
 
-        // Iterate over all documents in the builder and insert their docs and texts into the lookup stores.
-        for (i, (doc, doc_texts)) in self.documents.into_iter().enumerate() {
-            docs.insert(i, doc);
-            texts.insert(i, doc_texts);
+    /// Same as [EmbeddingsBuilder::build], but isolating the documents failing to be embedded
+    /// instead of failing: returns the embedded documents and the failed documents along with
+    /// their error, in the order in which the documents were added. The failed documents are
+    /// the documents rejected in lenient mode (see [EmbeddingsBuilder::lenient]), first, and
+    /// the documents with a text failing to be embedded: when a batch fails, its texts are
+    /// embedded one by one so that only the documents of the failing texts fail.
+    ///
+    /// # Example
+    /// ```rust
+    /// let (embeddings, failed) = EmbeddingsBuilder::new(model)
+    ///     .lenient()
+    ///     .documents(documents)?
+    ///     .build_lenient()
+    ///     .await;
+    ///
+    /// for (doc, err) in failed {
+    ///     eprintln!("Failed to embed {doc:?}: {err}");
+    /// }
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub async fn build_lenient(
+        mut self,
+    ) -> (Vec<(T, OneOrMany<Embedding>)>, Vec<(T, EmbeddingError)>) {
+        let mut embedded = vec![];
+        let mut failed = std::mem::take(&mut self.rejected)
+            .into_iter()
+            .map(|(doc, err)| (doc, EmbeddingError::DocumentError(Box::new(err))))
+            .collect::<Vec<_>>();
+        for (doc, embeddings) in self
+            .embed_documents(true)
+            .await
+            .expect("Failures are isolated per document")
+        {
+            match embeddings {
+                Ok(embeddings) => embedded.push((doc, embeddings)),
+                Err(err) => failed.push((doc, err)),
+            }
+        }
+        (embedded, failed)
+    }
+
+    /// Embed the texts of the documents, returning the documents in order along with their
+    /// embeddings (in the order of their texts) or the error of their first failing text.
+    /// Unless `isolate_failures` is set, embedding fails as soon as a batch fails.
+    #[allow(clippy::type_complexity)]
+    async fn embed_documents(
+        mut self,
+        isolate_failures: bool,
+    ) -> Result<Vec<(T, Result<OneOrMany<Embedding>, EmbeddingError>)>, EmbeddingError> {
+        use stream::TryStreamExt;
+
+        // The texts to embed, keyed by the index of their document and their index within it.
+        // The original texts are kept along the normalized ones to be restored as documents
+        // of the embeddings.
+        let mut docs = vec![];
+        let mut texts = vec![];
+        for (i, (doc, doc_texts)) in std::mem::take(&mut self.documents).into_iter().enumerate() {
+            for (j, original) in doc_texts.into_iter().enumerate() {
+                let (original, mut text) = match &self.normalizer {
+                    Some(normalizer) => {
+                        let text = normalizer(&original);
+                        (Some(original), text)
+                    }
+                    None => (None, original),
+                };
+                if let Some(policy) = &self.truncation {
+                    text = policy.truncate(&text).into_owned();
+                }
+                texts.push(((i, j), (original, text)));
+            }
+            docs.push(doc);
         }
 
         // Compute the embeddings.
-        let mut embeddings = stream::iter(texts.into_iter())
-            // Merge the texts of each document into a single list of texts.
-            .flat_map(|(i, texts)| stream::iter(texts.into_iter().map(move |text| (i, text))))
+        let mut embedded = stream::iter(texts)
             // Chunk them into batches. Each batch size is at most the embedding API limit per request.
             .chunks(M::MAX_DOCUMENTS)
             // Generate the embeddings for each batch.
-            .map(|text| async {
-                let (ids, docs): (Vec<_>, Vec<_>) = text.into_iter().unzip();
+            .map(|batch| async {
+                let (keys, texts): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+                let (originals, texts): (Vec<_>, Vec<_>) = texts.into_iter().unzip();
+
+                let embeddings = match self.embed_texts_checked(texts.clone()).await {
+                    Ok(embeddings) => embeddings.into_iter().map(Ok).collect(),
+                    // Embed the texts of the failed batch one by one to find the failing ones
+                    Err(_) if isolate_failures => {
+                        let mut embeddings = vec![];
+                        for text in texts {
+                            embeddings.push(self.embed_texts_checked(vec![text]).await.and_then(
+                                |mut embeddings| {
+                                    embeddings.pop().ok_or_else(|| {
+                                        EmbeddingError::ResponseError(
+                                            "No embedding returned".into(),
+                                        )
+                                    })
+                                },
+                            ));
+                        }
+                        embeddings
+                    }
+                    Err(err) => return Err(err),
+                };
 
-                let embeddings = self.model.embed_texts(docs).await?;
-                Ok::<_, EmbeddingError>(ids.into_iter().zip(embeddings).collect::<Vec<_>>())
+                let embeddings =
+                    embeddings
+                        .into_iter()
+                        .zip(originals)
+                        .map(|(embedding, original)| {
+                            embedding.map(|mut embedding| {
+                                if let Some(original) = original {
+                                    embedding.document = original;
+                                }
+                                embedding
+                            })
+                        });
+                Ok::<_, EmbeddingError>(keys.into_iter().zip(embeddings).collect::<Vec<_>>())
             })
             // Parallelize the embeddings generation over 10 concurrent requests
             .buffer_unordered(max(1, 1024 / M::MAX_DOCUMENTS))
-            // Collect the embeddings into a HashMap.
-            .try_fold(
-                HashMap::new(),
-                |mut acc: HashMap<_, OneOrMany<Embedding>>, embeddings| async move {
-                    embeddings.into_iter().for_each(|(i, embedding)| {
-                        acc.entry(i)
-                            .and_modify(|embeddings| embeddings.push(embedding.clone()))
-                            .or_insert(OneOrMany::one(embedding.clone()));
-                    });
-
-                    Ok(acc)
-                },
-            )
+            .try_concat()
             .await?;
 
-        // Merge the embeddings with their respective documents
+        // The batches complete in any order: sort the embeddings back by document and text
+        embedded.sort_by_key(|(key, _)| *key);
+
+        // Merge the embeddings with their respective documents, keeping the first error
+        let mut results = docs.iter().map(|_| Ok(vec![])).collect::<Vec<_>>();
+        for ((i, _), embedding) in embedded {
+            let result = &mut results[i];
+            match embedding {
+                Ok(embedding) => {
+                    if let Ok(embeddings) = result {
+                        embeddings.push(embedding);
+                    }
+                }
+                Err(err) => {
+                    if result.is_ok() {
+                        *result = Err(err);
+                    }
+                }
+            }
+        }
+
         Ok(docs
             .into_iter()
-            .map(|(i, doc)| {
+            .zip(results)
+            .map(|(doc, embeddings)| {
+                let embeddings = embeddings.and_then(|embeddings| {
+                    OneOrMany::many(embeddings).map_err(|_| {
+                        EmbeddingError::DocumentError("Document has no text to embed".into())
+                    })
+                });
+                (doc, embeddings)
+            })
+            .collect())
+    }
+
+    /// Generate embeddings for all documents in the builder (see [EmbeddingsBuilder::build])
+    /// and quantize them according to `quantization`, e.g.: to store them in a
+    /// [QuantizedVectorStore](crate::vector_store::quantized_store::QuantizedVectorStore).
+    pub async fn build_quantized(
+        self,
+        quantization: Quantization,
+    ) -> Result<Vec<(T, OneOrMany<QuantizedEmbedding>)>, EmbeddingError> {
+        Ok(self
+            .build()
+            .await?
+            .into_iter()
+            .map(|(doc, embeddings)| {
+                let mut quantized = OneOrMany::one(quantization.quantize(&embeddings.first()));
+                embeddings
+                    .rest()
+                    .iter()
+                    .for_each(|embedding| quantized.push(quantization.quantize(embedding)));
+                (doc, quantized)
+            })
+            .collect())
+    }
+
+    /// Generate embeddings for the sentences of all documents in the builder (see
+    /// [EmbeddingsBuilder::build]), the texts of each document being split into sentences
+    /// (ending with `.`, `!` or `?`), along with a [Pooling] of the embeddings of its sentences
+    /// as the embedding of the whole document, enabling coarse-then-fine retrieval: documents
+    /// are retrieved by their pooled embedding, then their sentences by their own embeddings.
+    ///
+    /// The `document` of the pooled embedding is the text of the document, i.e.: its texts
+    /// joined by a space.
+    ///
+    /// # Example
+    /// ```rust
+    /// let embeddings = EmbeddingsBuilder::new(model)
+    ///     .documents(documents)?
+    ///     .build_sentences(Pooling::Mean)
+    ///     .await?;
+    ///
+    /// // Both levels stored together: the pooled embedding first, then the sentences
+    /// let store = InMemoryVectorStore::from_documents(
+    ///     embeddings
+    ///         .into_iter()
+    ///         .map(|(doc, embeddings)| (doc, embeddings.into_embeddings())),
+    /// );
+    /// ```
+    pub async fn build_sentences(
+        mut self,
+        pooling: Pooling,
+    ) -> Result<Vec<(T, SentenceEmbeddings)>, EmbeddingError> {
+        let mut texts = Vec::with_capacity(self.documents.len());
+        for (_, doc_texts) in self.documents.iter_mut() {
+            let split = doc_texts
+                .iter()
+                .flat_map(|text| sentences(text))
+                .collect::<Vec<_>>();
+            texts.push(doc_texts.join(" "));
+            if !split.is_empty() {
+                *doc_texts = split;
+            }
+        }
+
+        Ok(self
+            .build()
+            .await?
+            .into_iter()
+            .zip(texts)
+            .map(|((doc, sentences), document)| {
+                let pooled = Embedding {
+                    document,
+                    vec: pooling.pool(sentences.iter().map(|s| s.vec.clone()).collect()),
+                };
                 (
                     doc,
-                    embeddings.remove(&i).expect("Document should be present"),
+                    SentenceEmbeddings {
+                        document: pooled,
+                        sentences,
+                    },
                 )
             })
             .collect())
     }
+
+    /// Embed `texts`, re-embedding (according to the retry policy of the builder) the texts
+    /// whose embedding does not have the expected number of dimensions. The check is skipped
+    /// for models with an unknown number of dimensions (i.e.: `ndims() == 0`).
+    async fn embed_texts_checked(
+        &self,
+        texts: Vec<String>,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        let ndims = self.model.ndims();
+        let mut embeddings = self.model.embed_texts(texts.clone()).await?;
+        if ndims == 0 {
+            return Ok(embeddings);
+        }
+
+        let mut retry = 0;
+        loop {
+            let invalid = embeddings
+                .iter()
+                .enumerate()
+                .filter(|(_, embedding)| embedding.vec.len() != ndims)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            let Some(&first) = invalid.first() else {
+                return Ok(embeddings);
+            };
+            if retry >= self.retry_policy.max_retries {
+                return Err(EmbeddingError::ResponseError(format!(
+                    "Embedding of {:?} has {} dimensions, expected {ndims}",
+                    texts[first],
+                    embeddings[first].vec.len()
+                )));
+            }
+
+            tracing::warn!(target: "rig",
+                "Re-embedding {} texts with invalid embeddings ({}/{})",
+                invalid.len(),
+                retry + 1,
+                self.retry_policy.max_retries
+            );
+            futures_timer::Delay::new(self.retry_policy.delay(retry)).await;
+            retry += 1;
+
+            let retried = self
+                .model
+                .embed_texts(
+                    invalid
+                        .iter()
+                        .map(|&i| texts[i].clone())
+                        .collect::<Vec<_>>(),
+                )
+                .await?;
+            for (i, embedding) in invalid.into_iter().zip(retried) {
+                embeddings[i] = embedding;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
     use crate::{
-        embeddings::{embed::EmbedError, embed::TextEmbedder, Embedding, EmbeddingModel},
+        completion::RetryPolicy,
+        embeddings::{
+            embed::EmbedError,
+            embed::TextEmbedder,
+            pooling::Pooling,
+            splitter::{CharacterSplitter, Chunk},
+            Embedding, EmbeddingError, EmbeddingModel,
+        },
+        vector_store::in_memory_store::InMemoryVectorStore,
         Embed,
     };
 
@@ -174,6 +575,36 @@ mod tests {
         }
     }
 
+    /// Embedding model recording the texts it embeds, whose embeddings are the number of
+    /// uppercase characters of the texts.
+    #[derive(Clone, Default)]
+    struct RecordingModel {
+        texts: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl EmbeddingModel for RecordingModel {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        async fn embed_texts(
+            &self,
+            documents: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            let documents = documents.into_iter().collect::<Vec<_>>();
+            self.texts.lock().unwrap().extend(documents.clone());
+            Ok(documents
+                .into_iter()
+                .map(|document| Embedding {
+                    vec: vec![document.chars().filter(|c| c.is_uppercase()).count() as f64],
+                    document,
+                })
+                .collect())
+        }
+    }
+
     #[derive(Clone, Debug)]
     struct WordDefinition {
         id: String,
@@ -247,6 +678,82 @@ mod tests {
         ]
     }
 
+    #[tokio::test]
+    async fn test_build_sentences() {
+        let model = RecordingModel::default();
+        let documents = vec![
+            WordDefinitionSingle {
+                id: "doc0".to_string(),
+                definition: "A Green Alien. It lives on Cold Planets!".to_string(),
+            },
+            WordDefinitionSingle {
+                id: "doc1".to_string(),
+                definition: "An Ancient Tool".to_string(),
+            },
+        ];
+
+        let result = EmbeddingsBuilder::new(model.clone())
+            .documents(documents)
+            .unwrap()
+            .build_sentences(Pooling::Mean)
+            .await
+            .unwrap();
+
+        // The sentences are embedded, not the whole documents
+        assert_eq!(
+            *model.texts.lock().unwrap(),
+            vec![
+                "A Green Alien.".to_string(),
+                "It lives on Cold Planets!".to_string(),
+                "An Ancient Tool".to_string()
+            ]
+        );
+
+        let (doc, embeddings) = &result[0];
+        assert_eq!(doc.id, "doc0");
+        assert_eq!(
+            embeddings
+                .sentences
+                .iter()
+                .map(|embedding| (embedding.document.as_str(), embedding.vec.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("A Green Alien.", vec![3.0]),
+                ("It lives on Cold Planets!", vec![3.0])
+            ]
+        );
+        assert_eq!(
+            embeddings.document.document,
+            "A Green Alien. It lives on Cold Planets!"
+        );
+        assert_eq!(embeddings.document.vec, vec![3.0]);
+
+        let (doc, embeddings) = &result[1];
+        assert_eq!(doc.id, "doc1");
+        assert_eq!(embeddings.sentences.len(), 1);
+        assert_eq!(embeddings.document.vec, vec![3.0]);
+
+        // Both levels are stored together and retrievable
+        let store = InMemoryVectorStore::from_documents_with_id_f(
+            result
+                .into_iter()
+                .map(|(doc, embeddings)| (doc.id, embeddings.into_embeddings())),
+            |id| id.clone(),
+        );
+        let (_, (_, stored)) = store.iter_cloned().find(|(id, _)| id == "doc0").unwrap();
+        assert_eq!(
+            stored
+                .iter()
+                .map(|embedding| embedding.document.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "A Green Alien. It lives on Cold Planets!",
+                "A Green Alien.",
+                "It lives on Cold Planets!"
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_build_multiple_text() {
         let fake_definitions = definitions_multiple_text();
@@ -384,4 +891,304 @@ mod tests {
             second_definition.1.rest()[0].document, "A fictional creature found in the distant, swampy marshlands of the planet Glibbo in the Andromeda galaxy.".to_string()
         )
     }
+
+    #[tokio::test]
+    async fn test_build_vec_of_strings() {
+        let documents = vec![
+            "A green alien that lives on cold planets.".to_string(),
+            "An ancient artifact.".to_string(),
+        ];
+
+        let mut result = EmbeddingsBuilder::new(Model)
+            .documents(documents)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        result.sort_by(|(doc_1, _), (doc_2, _)| doc_1.cmp(doc_2));
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, "A green alien that lives on cold planets.");
+        assert_eq!(result[0].1.len(), 1);
+        assert_eq!(
+            result[0].1.first().document,
+            "A green alien that lives on cold planets."
+        );
+        assert_eq!(result[1].1.first().document, "An ancient artifact.");
+    }
+
+    #[tokio::test]
+    async fn test_build_text_with_metadata() {
+        let documents = vec![
+            (
+                "A green alien that lives on cold planets.".to_string(),
+                std::collections::HashMap::from([("source", "flurbo.txt")]),
+            ),
+            (
+                "An ancient artifact.".to_string(),
+                std::collections::HashMap::from([("source", "glarb.txt")]),
+            ),
+        ];
+
+        let mut result = EmbeddingsBuilder::new(Model)
+            .documents(documents)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        result.sort_by(|((doc_1, _), _), ((doc_2, _), _)| doc_1.cmp(doc_2));
+
+        assert_eq!(result.len(), 2);
+        // Only the text is embedded, the metadata is kept alongside the embeddings
+        assert_eq!(result[0].0 .1["source"], "flurbo.txt");
+        assert_eq!(result[0].1.len(), 1);
+        assert_eq!(
+            result[0].1.first().document,
+            "A green alien that lives on cold planets."
+        );
+        assert_eq!(result[1].0 .1["source"], "glarb.txt");
+    }
+
+    #[tokio::test]
+    async fn test_build_documents_split() {
+        let documents = vec![
+            (
+                "flurbo",
+                "A flurbo is a green alien that lives on cold planets. Flurbos are also a made up currency.",
+            ),
+            ("glarb", "An ancient artifact."),
+        ];
+
+        let mut result = EmbeddingsBuilder::new(Model)
+            .documents_split(documents, CharacterSplitter::new(40))
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        result.sort_by(|(a, _), (b, _)| {
+            (&a.parent_id, a.chunk_index).cmp(&(&b.parent_id, b.chunk_index))
+        });
+
+        let chunks = result
+            .iter()
+            .map(|(chunk, embeddings)| {
+                assert_eq!(embeddings.first().document, chunk.text);
+                chunk.clone()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk {
+                    parent_id: "flurbo".to_string(),
+                    chunk_index: 0,
+                    text: "A flurbo is a green alien that lives on".to_string(),
+                    prev_id: None,
+                    next_id: Some("flurbo#1".to_string()),
+                },
+                Chunk {
+                    parent_id: "flurbo".to_string(),
+                    chunk_index: 1,
+                    text: "cold planets. Flurbos are also a made up".to_string(),
+                    prev_id: Some("flurbo#0".to_string()),
+                    next_id: Some("flurbo#2".to_string()),
+                },
+                Chunk {
+                    parent_id: "flurbo".to_string(),
+                    chunk_index: 2,
+                    text: "currency.".to_string(),
+                    prev_id: Some("flurbo#1".to_string()),
+                    next_id: None,
+                },
+                Chunk {
+                    parent_id: "glarb".to_string(),
+                    chunk_index: 0,
+                    text: "An ancient artifact.".to_string(),
+                    prev_id: None,
+                    next_id: None,
+                },
+            ]
+        );
+    }
+
+    /// Embedding model returning a truncated embedding for the first text of its first call
+    #[derive(Clone, Default)]
+    struct FlakyModel {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl EmbeddingModel for FlakyModel {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            3
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+
+            Ok(texts
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| Embedding {
+                    document: text,
+                    vec: if call == 0 && i == 0 {
+                        vec![0.1]
+                    } else {
+                        vec![0.1, 0.2, 0.3]
+                    },
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_retries_invalid_embeddings() {
+        let model = FlakyModel::default();
+
+        let result = EmbeddingsBuilder::new(model.clone())
+            .retry_policy(RetryPolicy::new(2).backoff(Duration::from_millis(1)))
+            .documents(vec!["flurbo".to_string(), "glarb".to_string()])
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert!(result
+            .iter()
+            .all(|(_, embeddings)| embeddings.first().vec == vec![0.1, 0.2, 0.3]));
+        // The invalid embedding was retried once
+        assert_eq!(model.calls.load(Ordering::SeqCst), 2);
+
+        // Without retries, the invalid embedding fails the build instead of being stored
+        let result = EmbeddingsBuilder::new(FlakyModel::default())
+            .documents(vec!["flurbo".to_string(), "glarb".to_string()])
+            .unwrap()
+            .build()
+            .await;
+        assert!(matches!(result, Err(EmbeddingError::ResponseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_build_normalized() {
+        let model = RecordingModel::default();
+        let result = EmbeddingsBuilder::new(model.clone())
+            .normalizer(|text| text.to_lowercase())
+            .documents(definitions_single_text())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        // The normalized texts are embedded
+        let mut texts = model.texts.lock().unwrap().clone();
+        texts.sort();
+        assert_eq!(
+            texts,
+            vec![
+                "a green alien that lives on cold planets.",
+                "an ancient tool used by the ancestors of the inhabitants of planet jiro to farm the land.",
+            ]
+        );
+
+        // While the original documents and texts are stored
+        for (doc, embeddings) in result {
+            assert_eq!(embeddings.first().document, doc.definition);
+            assert_eq!(embeddings.first().vec, vec![0.0]);
+        }
+    }
+
+    /// Embedding model failing to embed the batches containing "glarb"
+    #[derive(Clone)]
+    struct PickyModel;
+
+    impl EmbeddingModel for PickyModel {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            texts
+                .into_iter()
+                .map(|text| {
+                    if text.contains("glarb") {
+                        return Err(EmbeddingError::ProviderError("Invalid input".into()));
+                    }
+                    Ok(Embedding {
+                        vec: vec![text.len() as f64],
+                        document: text,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    /// Document failing to be embedded when empty
+    #[derive(Debug, PartialEq)]
+    struct Note(&'static str);
+
+    impl Embed for Note {
+        fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+            if self.0.is_empty() {
+                return Err(EmbedError::new(std::fmt::Error));
+            }
+            embedder.embed(self.0.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_lenient() {
+        let notes = || vec![Note("flurbo"), Note(""), Note("glarb"), Note("linlingdong")];
+
+        let (embedded, failed) = EmbeddingsBuilder::new(PickyModel)
+            .lenient()
+            .documents(notes())
+            .unwrap()
+            .build_lenient()
+            .await;
+
+        // The other documents of the failed batch are still embedded, in order
+        assert_eq!(
+            embedded
+                .iter()
+                .map(|(note, embeddings)| (note.0, embeddings.first().vec[0]))
+                .collect::<Vec<_>>(),
+            vec![("flurbo", 6.0), ("linlingdong", 11.0)]
+        );
+        assert_eq!(failed.len(), 2);
+        assert!(matches!(
+            failed[0],
+            (Note(""), EmbeddingError::DocumentError(_))
+        ));
+        assert!(matches!(
+            failed[1],
+            (Note("glarb"), EmbeddingError::ProviderError(_))
+        ));
+
+        // Without the lenient mode, the first failure aborts
+        assert!(EmbeddingsBuilder::new(PickyModel)
+            .documents(notes())
+            .is_err());
+        let result = EmbeddingsBuilder::new(PickyModel)
+            .lenient()
+            .documents(notes())
+            .unwrap()
+            .build()
+            .await;
+        assert!(matches!(result, Err(EmbeddingError::DocumentError(_))));
+    }
 }
@@ -1,3 +1,61 @@
+use crate::embeddings::{BinaryEmbedding, Embedding};
+
+/// Metric used to rank embeddings by similarity to a query (e.g.: by the in-memory vector
+/// store, see [InMemoryVectorIndex::metric](crate::vector_store::in_memory_store::InMemoryVectorIndex::metric)).
+///
+/// Distances are converted to similarities so that higher scores always mean more similar
+/// documents: `1 / (1 + distance)` for the euclidean and manhattan distances, and the fraction
+/// of matching bits for the hamming distance.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Cosine similarity
+    #[default]
+    Cosine,
+    /// Euclidean distance
+    Euclidean,
+    /// Manhattan distance, e.g.: for quantized embeddings
+    Manhattan,
+    /// Hamming distance between the [BinaryEmbedding]s of the embeddings, e.g.: for binary
+    /// embeddings, where the cosine similarity is meaningless
+    Hamming,
+}
+
+impl DistanceMetric {
+    /// Name of the metric (e.g.: `"cosine"`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            DistanceMetric::Cosine => "cosine",
+            DistanceMetric::Euclidean => "euclidean",
+            DistanceMetric::Manhattan => "manhattan",
+            DistanceMetric::Hamming => "hamming",
+        }
+    }
+
+    /// Similarity of `embedding` to `query` according to the metric (higher is more similar).
+    /// For the hamming metric, the query should be binarized once with [BinaryEmbedding::from]
+    /// and the embeddings compared with [DistanceMetric::binary_similarity] instead.
+    pub fn similarity(&self, query: &Embedding, embedding: &Embedding) -> f64 {
+        match self {
+            DistanceMetric::Cosine => query.cosine_similarity(embedding, false),
+            DistanceMetric::Euclidean => 1.0 / (1.0 + query.euclidean_distance(embedding)),
+            DistanceMetric::Manhattan => 1.0 / (1.0 + query.manhattan_distance(embedding)),
+            DistanceMetric::Hamming => DistanceMetric::binary_similarity(
+                &BinaryEmbedding::from(query),
+                &BinaryEmbedding::from(embedding),
+            ),
+        }
+    }
+
+    /// Fraction of matching bits of two binary embeddings (1 minus their normalized hamming
+    /// distance).
+    pub fn binary_similarity(query: &BinaryEmbedding, embedding: &BinaryEmbedding) -> f64 {
+        if query.ndims == 0 {
+            return 1.0;
+        }
+        1.0 - query.hamming_distance(embedding) as f64 / query.ndims as f64
+    }
+}
+
 pub trait VectorDistance {
     /// Get dot product of two embedding vectors
     fn dot_product(&self, other: &Self) -> f64;
@@ -20,7 +78,7 @@ pub trait VectorDistance {
 }
 
 #[cfg(not(feature = "rayon"))]
-impl VectorDistance for crate::embeddings::Embedding {
+impl VectorDistance for Embedding {
     fn dot_product(&self, other: &Self) -> f64 {
         self.vec
             .iter()
@@ -134,8 +192,8 @@ mod rayon {
 
 #[cfg(test)]
 mod tests {
-    use super::VectorDistance;
-    use crate::embeddings::Embedding;
+    use super::{DistanceMetric, VectorDistance};
+    use crate::embeddings::{BinaryEmbedding, Embedding};
 
     fn embeddings() -> (Embedding, Embedding) {
         let embedding_1 = Embedding {
@@ -198,4 +256,79 @@ mod tests {
 
         assert_eq!(embedding_1.chebyshev_distance(&embedding_2), 4.0)
     }
+
+    fn binary(vec: Vec<f64>) -> BinaryEmbedding {
+        BinaryEmbedding::from(&Embedding {
+            document: "test".to_string(),
+            vec,
+        })
+    }
+
+    #[test]
+    fn test_binary_embedding() {
+        // Spans two words
+        let mut vec = vec![-1.0; 70];
+        vec[0] = 1.0;
+        vec[65] = 0.5;
+        let embedding = binary(vec.clone());
+        assert_eq!(embedding.bits, vec![1, 1 << 1]);
+        assert_eq!(embedding.ndims, 70);
+        assert!(embedding.bit(0) && embedding.bit(65));
+        assert!(!embedding.bit(1) && !embedding.bit(64) && !embedding.bit(70));
+
+        vec[0] = -1.0;
+        vec[69] = 1.0;
+        assert_eq!(embedding.hamming_distance(&binary(vec)), 2);
+    }
+
+    #[test]
+    fn test_hamming_nearest_neighbors() {
+        let query = binary(vec![1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+        let candidates = [
+            ("far", binary(vec![0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0])),
+            (
+                "exact",
+                binary(vec![1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0]),
+            ),
+            (
+                "close",
+                binary(vec![1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]),
+            ),
+            ("mid", binary(vec![1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0])),
+        ];
+
+        let mut ranked = candidates
+            .iter()
+            .map(|(name, embedding)| (DistanceMetric::binary_similarity(&query, embedding), *name))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap());
+
+        assert_eq!(
+            ranked,
+            vec![
+                (1.0, "exact"),
+                (0.875, "close"),
+                (0.625, "mid"),
+                (0.0, "far")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metric_similarity() {
+        let (embedding_1, embedding_2) = embeddings();
+
+        assert_eq!(
+            DistanceMetric::Manhattan.similarity(&embedding_1, &embedding_2),
+            0.125
+        );
+        assert_eq!(
+            DistanceMetric::Euclidean.similarity(&embedding_1, &embedding_2),
+            1.0 / 6.0
+        );
+        assert_eq!(
+            DistanceMetric::Hamming.similarity(&embedding_1, &embedding_2),
+            1.0
+        );
+    }
 }
@@ -7,7 +7,8 @@
 //! The module also defines the [TextEmbedder] struct which accumulates string values that need to be embedded.
 //! It is used directly with the [Embed] trait.
 //!
-//! Finally, the module implements [Embed] for many common primitive types.
+//! Finally, the module implements [Embed] for many common primitive and std types, as well as
+//! for `(T, M)` tuples where only `T` is embedded and `M` is treated as metadata.
 
 /// Error type used for when the [Embed::embed] method fo the [Embed] trait fails.
 /// Used by default implementations of [Embed] for common types.
@@ -104,6 +105,13 @@ impl Embed for &str {
     }
 }
 
+impl Embed for std::borrow::Cow<'_, str> {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
 impl Embed for i8 {
     fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
         embedder.embed(self.to_string());
@@ -139,6 +147,55 @@ impl Embed for i128 {
     }
 }
 
+impl Embed for isize {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
+impl Embed for u8 {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
+impl Embed for u16 {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
+impl Embed for u32 {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
+impl Embed for u64 {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
+impl Embed for u128 {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
+impl Embed for usize {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.to_string());
+        Ok(())
+    }
+}
+
 impl Embed for f32 {
     fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
         embedder.embed(self.to_string());
@@ -188,3 +245,28 @@ impl<T: Embed> Embed for Vec<T> {
         Ok(())
     }
 }
+
+impl<T: Embed> Embed for Box<T> {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        self.as_ref().embed(embedder)
+    }
+}
+
+/// `None` values do not generate any embedding.
+impl<T: Embed> Embed for Option<T> {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        match self {
+            Some(item) => item.embed(embedder),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Only the first element of the tuple is embedded, the second element can be used to attach
+/// metadata to the embedded value (e.g.: `(String, HashMap<String, String>)`) without having
+/// to define a custom type.
+impl<T: Embed, M> Embed for (T, M) {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        self.0.embed(embedder)
+    }
+}
@@ -1,13 +1,19 @@
 //! The module defines the [EmbeddingModel] trait, which represents an embedding model that can
 //! generate embeddings for documents.
 //!
-//! The module also defines the [Embedding] struct, which represents a single document embedding.
+//! The module also defines the [Embedding] struct, which represents a single document embedding,
+//! and the [BinaryEmbedding] struct, its compact binarized form.
 //!
 //! Finally, the module defines the [EmbeddingError] enum, which represents various errors that
 //! can occur during embedding generation or processing.
 
 use serde::{Deserialize, Serialize};
 
+use super::{
+    pooling::{PooledEmbeddingModel, Pooling},
+    prefix::{EmbeddingPrefixes, PrefixedEmbeddingModel},
+};
+
 #[derive(Debug, thiserror::Error)]
 pub enum EmbeddingError {
     /// Http error (e.g.: connection error, timeout, etc.)
@@ -45,6 +51,13 @@ pub trait EmbeddingModel: Clone + Sync + Send {
         texts: impl IntoIterator<Item = String> + Send,
     ) -> impl std::future::Future<Output = Result<Vec<Embedding>, EmbeddingError>> + Send;
 
+    /// Return a copy of the model letting the provider truncate the texts exceeding its input
+    /// limit according to `truncation`, or `None` if the provider does not support it (the
+    /// default). See [EmbeddingsBuilder::input_truncation](crate::embeddings::EmbeddingsBuilder::input_truncation).
+    fn with_input_truncation(&self, _truncation: InputTruncation) -> Option<Self> {
+        None
+    }
+
     /// Embed a single text document.
     fn embed_text(
         &self,
@@ -58,6 +71,94 @@ pub trait EmbeddingModel: Clone + Sync + Send {
                 .expect("There should be at least one embedding"))
         }
     }
+
+    /// Embed a search query, e.g.: to compare it to embedded documents in a vector store.
+    /// Same as [EmbeddingModel::embed_text] unless the model embeds queries and documents
+    /// differently (see [EmbeddingModel::with_prefixes]).
+    fn embed_query(
+        &self,
+        query: &str,
+    ) -> impl std::future::Future<Output = Result<Embedding, EmbeddingError>> + Send {
+        self.embed_text(query)
+    }
+
+    /// Embed multiple documents in a single request. Same as [EmbeddingModel::embed_texts],
+    /// the counterpart of [EmbeddingModel::embed_query].
+    fn embed_documents(
+        &self,
+        documents: impl IntoIterator<Item = String> + Send,
+    ) -> impl std::future::Future<Output = Result<Vec<Embedding>, EmbeddingError>> + Send {
+        self.embed_texts(documents)
+    }
+
+    /// Embed multiple text documents, resolving the failures per text: the texts are embedded
+    /// in batches of [EmbeddingModel::MAX_DOCUMENTS], and the texts of a rejected batch (e.g.:
+    /// because one of them exceeds the input limit of the provider) are embedded individually to
+    /// isolate the failing ones. Returns the result of each text, in the order of `texts`.
+    ///
+    /// # Example
+    /// ```rust
+    /// let results = model.embed_texts_isolated(texts.clone()).await;
+    /// for (text, result) in texts.iter().zip(results) {
+    ///     if let Err(err) = result {
+    ///         println!("Failed to embed {text:?}: {err}");
+    ///     }
+    /// }
+    /// ```
+    fn embed_texts_isolated(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> impl std::future::Future<Output = Vec<Result<Embedding, EmbeddingError>>> + Send {
+        let texts = texts.into_iter().collect::<Vec<_>>();
+        async move {
+            let mut results = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(Self::MAX_DOCUMENTS.max(1)) {
+                match self.embed_texts(batch.to_vec()).await {
+                    Ok(embeddings) if embeddings.len() == batch.len() => {
+                        results.extend(embeddings.into_iter().map(Ok))
+                    }
+                    Err(err) if batch.len() == 1 => results.push(Err(err)),
+                    result => {
+                        if let Err(err) = result {
+                            tracing::debug!(target: "rig",
+                                "Batch of {} texts rejected, embedding them individually: {err}",
+                                batch.len()
+                            );
+                        }
+                        let embeddings = batch.iter().map(|text| self.embed_text(text));
+                        results.extend(futures::future::join_all(embeddings).await);
+                    }
+                }
+            }
+            results
+        }
+    }
+
+    /// Wrap the model to prepend `prefixes` to the queries and documents it embeds, as
+    /// required by asymmetric models such as the e5 and bge families (see
+    /// [EmbeddingPrefixes::for_model] for the known defaults).
+    fn with_prefixes(self, prefixes: EmbeddingPrefixes) -> PrefixedEmbeddingModel<Self> {
+        PrefixedEmbeddingModel::new(self, prefixes)
+    }
+
+    /// Wrap the model to embed the texts exceeding `max_tokens` (which providers reject) as
+    /// the [Pooling] of the embeddings of their chunks, instead of failing.
+    fn with_pooling(self, max_tokens: usize, pooling: Pooling) -> PooledEmbeddingModel<Self> {
+        PooledEmbeddingModel::new(self, max_tokens, pooling)
+    }
+}
+
+/// Truncation applied by the provider to the texts exceeding the input limit of an embedding
+/// model (e.g.: Cohere's `truncate` parameter).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum InputTruncation {
+    /// Fail to embed over-length texts
+    None,
+    /// Drop the start of over-length texts
+    Start,
+    /// Drop the end of over-length texts
+    End,
 }
 
 /// Struct that holds a single document and its embedding.
@@ -76,3 +177,120 @@ impl PartialEq for Embedding {
 }
 
 impl Eq for Embedding {}
+
+impl Embedding {
+    /// Euclidean norm (i.e.: magnitude) of the embedding vector
+    pub fn norm(&self) -> f64 {
+        self.vec.iter().map(|x| x.powi(2)).sum::<f64>().sqrt()
+    }
+}
+
+/// Compact binary form of an [Embedding], packing one bit per dimension (set if the value of
+/// the dimension is positive) into 64-bit words, e.g.: for binary/quantized embedding models.
+/// Binary embeddings are compared with their hamming distance, computed with popcounts.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BinaryEmbedding {
+    /// The document that was embedded. Used for debugging.
+    pub document: String,
+    /// The packed bits of the embedding, least significant bit first
+    pub bits: Vec<u64>,
+    /// The number of dimensions (i.e.: bits) of the embedding
+    pub ndims: usize,
+}
+
+impl BinaryEmbedding {
+    /// Binarize `embedding`: each dimension is set if its value is positive.
+    pub fn from_embedding(embedding: &Embedding) -> Self {
+        let mut bits = vec![0u64; embedding.vec.len().div_ceil(64)];
+        for (index, value) in embedding.vec.iter().enumerate() {
+            if *value > 0.0 {
+                bits[index / 64] |= 1 << (index % 64);
+            }
+        }
+
+        Self {
+            document: embedding.document.clone(),
+            bits,
+            ndims: embedding.vec.len(),
+        }
+    }
+
+    /// Whether the bit of dimension `index` is set
+    pub fn bit(&self, index: usize) -> bool {
+        index < self.ndims && self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Number of dimensions whose bits differ between the two embeddings
+    pub fn hamming_distance(&self, other: &Self) -> u32 {
+        self.bits
+            .iter()
+            .zip(other.bits.iter())
+            .map(|(x, y)| (x ^ y).count_ones())
+            .sum()
+    }
+}
+
+impl From<&Embedding> for BinaryEmbedding {
+    fn from(embedding: &Embedding) -> Self {
+        Self::from_embedding(embedding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Embedding, EmbeddingError, EmbeddingModel};
+
+    /// Embedding model rejecting the batches with a text longer than 8 characters
+    #[derive(Clone)]
+    struct Model;
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 3;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            texts
+                .into_iter()
+                .map(|document| {
+                    if document.len() > 8 {
+                        return Err(EmbeddingError::ProviderError("Input too long".into()));
+                    }
+                    Ok(Embedding {
+                        vec: vec![document.len() as f64],
+                        document,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_texts_isolated() {
+        let texts = ["flurbo", "glarb", "plumbus-plumbus", "schmeck", "grumbo"]
+            .map(str::to_string)
+            .to_vec();
+        assert!(Model.embed_texts(texts.clone()).await.is_err());
+
+        let results = Model.embed_texts_isolated(texts).await;
+        assert_eq!(results.len(), 5);
+
+        let embedded = results
+            .iter()
+            .map(|result| result.as_ref().ok().map(|embedding| embedding.vec[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            embedded,
+            vec![Some(6.0), Some(5.0), None, Some(7.0), Some(6.0)]
+        );
+        assert!(matches!(
+            results[2],
+            Err(EmbeddingError::ProviderError(ref message)) if message == "Input too long"
+        ));
+    }
+}
@@ -6,10 +6,16 @@
 pub mod builder;
 pub mod embed;
 pub mod embedding;
+pub mod pooling;
+pub mod prefix;
+pub mod quantization;
+pub mod splitter;
 pub mod tool;
 
 pub mod distance;
 pub use builder::EmbeddingsBuilder;
 pub use embed::{to_texts, Embed, EmbedError, TextEmbedder};
-pub use embedding::{Embedding, EmbeddingError, EmbeddingModel};
+pub use embedding::{BinaryEmbedding, Embedding, EmbeddingError, EmbeddingModel, InputTruncation};
+pub use quantization::{Quantization, QuantizedEmbedding};
+pub use splitter::{Chunk, TextSplitter};
 pub use tool::ToolSchema;
@@ -0,0 +1,282 @@
+//! The module defines the [PooledEmbeddingModel] wrapper, embedding the texts exceeding the
+//! input limit of an embedding model (which providers reject) by splitting them into chunks,
+//! embedding each chunk and pooling the embeddings of the chunks into a single embedding of the
+//! text (see [Pooling]).
+//!
+//! # Example
+//! ```rust
+//! use rig::embeddings::{pooling::Pooling, EmbeddingModel};
+//!
+//! let model = openai_client
+//!     .embedding_model(openai::TEXT_EMBEDDING_3_SMALL)
+//!     .with_pooling(8191, Pooling::Mean);
+//!
+//! // A single embedding, the mean of the embeddings of the chunks of the document
+//! let embedding = model.embed_text(&very_long_document).await?;
+//! assert_eq!(model.pooled(), 1);
+//! ```
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    splitter::{CharacterSplitter, TextSplitter},
+    Embedding, EmbeddingError, EmbeddingModel, InputTruncation,
+};
+use crate::OneOrMany;
+
+/// Strategy combining the embeddings of the chunks of an over-limit text into the embedding of
+/// the text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pooling {
+    /// Element-wise mean of the embeddings of the chunks
+    #[default]
+    Mean,
+    /// Embedding of the first chunk
+    First,
+    /// Embedding of the last chunk
+    Last,
+}
+
+impl Pooling {
+    /// Pool the (non-empty) embedding vectors of the chunks of a text
+    pub fn pool(&self, mut vecs: Vec<Vec<f64>>) -> Vec<f64> {
+        match self {
+            Pooling::Mean => {
+                let count = vecs.len() as f64;
+                let mut vecs = vecs.into_iter();
+                let sum = vecs.next().unwrap_or_default();
+                vecs.fold(sum, |mut sum, vec| {
+                    sum.iter_mut().zip(vec).for_each(|(x, y)| *x += y);
+                    sum
+                })
+                .into_iter()
+                .map(|x| x / count)
+                .collect()
+            }
+            Pooling::First => vecs.into_iter().next().unwrap_or_default(),
+            Pooling::Last => vecs.pop().unwrap_or_default(),
+        }
+    }
+}
+
+/// Embeddings of a document at two levels, as built by
+/// [EmbeddingsBuilder::build_sentences](crate::embeddings::EmbeddingsBuilder::build_sentences):
+/// the embeddings of its sentences and their [Pooling], the embedding of the whole document,
+/// e.g.: to retrieve documents by their pooled embedding first, then their best sentences.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SentenceEmbeddings {
+    /// Pooling of the embeddings of the sentences, whose `document` is the text of the document
+    pub document: Embedding,
+    /// Embeddings of the sentences of the document, in order
+    pub sentences: OneOrMany<Embedding>,
+}
+
+impl SentenceEmbeddings {
+    /// Both levels as the embeddings of a single (stored) document: the pooled embedding
+    /// first, followed by the embeddings of the sentences.
+    pub fn into_embeddings(self) -> OneOrMany<Embedding> {
+        let mut embeddings = OneOrMany::one(self.document);
+        self.sentences
+            .into_iter()
+            .for_each(|embedding| embeddings.push(embedding));
+        embeddings
+    }
+}
+
+/// Embedding model splitting the texts exceeding `max_tokens` (estimated, assuming ~4
+/// characters per token) into chunks, embedding the chunks and returning a single [Pooling] of
+/// their embeddings for each text. The `document` of a pooled embedding is the original text.
+///
+/// The number of texts pooled so far is recorded (see [PooledEmbeddingModel::pooled]), along
+/// with an `info` tracing event for each pooled text.
+///
+/// Created with [EmbeddingModel::with_pooling].
+#[derive(Clone, Debug)]
+pub struct PooledEmbeddingModel<M: EmbeddingModel> {
+    model: M,
+    max_tokens: usize,
+    pooling: Pooling,
+    /// Number of pooled texts, shared by the clones of the model
+    pooled: Arc<AtomicUsize>,
+}
+
+impl<M: EmbeddingModel> PooledEmbeddingModel<M> {
+    pub fn new(model: M, max_tokens: usize, pooling: Pooling) -> Self {
+        Self {
+            model,
+            max_tokens: max_tokens.max(1),
+            pooling,
+            pooled: Arc::default(),
+        }
+    }
+
+    /// The pooling strategy of the model
+    pub fn pooling(&self) -> Pooling {
+        self.pooling
+    }
+
+    /// Number of over-limit texts embedded (by the model or its clones) as the pooling of the
+    /// embeddings of their chunks
+    pub fn pooled(&self) -> usize {
+        self.pooled.load(Ordering::Relaxed)
+    }
+
+    /// Chunks of `text` if it exceeds the input limit, `None` otherwise
+    fn chunks(&self, text: &str) -> Option<Vec<String>> {
+        let max_chars = self.max_tokens.saturating_mul(4);
+        if text.len() <= max_chars {
+            return None;
+        }
+
+        tracing::info!(target: "rig",
+            "Pooling the embeddings of an input of {} characters, over the limit of {} tokens",
+            text.len(),
+            self.max_tokens
+        );
+        self.pooled.fetch_add(1, Ordering::Relaxed);
+        Some(CharacterSplitter::new(max_chars).split(text))
+    }
+}
+
+impl<M: EmbeddingModel> EmbeddingModel for PooledEmbeddingModel<M> {
+    const MAX_DOCUMENTS: usize = M::MAX_DOCUMENTS;
+
+    fn ndims(&self) -> usize {
+        self.model.ndims()
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        // The texts along with their chunks (only the text itself if it is within the limit)
+        let texts = texts
+            .into_iter()
+            .map(|text| match self.chunks(&text) {
+                Some(chunks) => (text, chunks),
+                None => (text.clone(), vec![text]),
+            })
+            .collect::<Vec<_>>();
+
+        // The chunks of all the texts are embedded together, in requests of at most
+        // `MAX_DOCUMENTS` chunks
+        let chunks = texts
+            .iter()
+            .flat_map(|(_, chunks)| chunks.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut vecs = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(M::MAX_DOCUMENTS.max(1)) {
+            vecs.extend(
+                self.model
+                    .embed_texts(batch.to_vec())
+                    .await?
+                    .into_iter()
+                    .map(|embedding| embedding.vec),
+            );
+        }
+
+        let mut vecs = vecs.into_iter();
+        Ok(texts
+            .into_iter()
+            .map(|(document, chunks)| {
+                let vecs = vecs.by_ref().take(chunks.len()).collect::<Vec<_>>();
+                let vec = if chunks.len() == 1 {
+                    vecs.into_iter().next().unwrap_or_default()
+                } else {
+                    self.pooling.pool(vecs)
+                };
+                Embedding { document, vec }
+            })
+            .collect())
+    }
+
+    fn with_input_truncation(&self, truncation: InputTruncation) -> Option<Self> {
+        Some(Self {
+            model: self.model.with_input_truncation(truncation)?,
+            max_tokens: self.max_tokens,
+            pooling: self.pooling,
+            pooled: self.pooled.clone(),
+        })
+    }
+
+    async fn embed_query(&self, query: &str) -> Result<Embedding, EmbeddingError> {
+        let Some(chunks) = self.chunks(query) else {
+            return self.model.embed_query(query).await;
+        };
+
+        let mut vecs = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            vecs.push(self.model.embed_query(&chunk).await?.vec);
+        }
+        Ok(Embedding {
+            document: query.to_string(),
+            vec: self.pooling.pool(vecs),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pooling;
+    use crate::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+
+    /// Embedding model rejecting the texts longer than 8 characters, embedding the others as
+    /// `[1, length]`
+    #[derive(Clone)]
+    struct Model;
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 2;
+
+        fn ndims(&self) -> usize {
+            2
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            texts
+                .into_iter()
+                .map(|document| {
+                    if document.len() > 8 {
+                        return Err(EmbeddingError::ProviderError("Input too long".into()));
+                    }
+                    Ok(Embedding {
+                        vec: vec![1.0, document.len() as f64],
+                        document,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pooling() {
+        let text = "flurbo glarb plumbus";
+        assert!(Model.embed_text(text).await.is_err());
+
+        // Split into "flurbo", "glarb" and "plumbus" (2 tokens of ~4 characters each)
+        let model = Model.with_pooling(2, Pooling::Mean);
+        let embeddings = model
+            .embed_texts(vec![text.to_string(), "short".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings[0].document, text);
+        assert_eq!(embeddings[0].vec, vec![1.0, 6.0]);
+        assert_eq!(embeddings[1].vec, vec![1.0, 5.0]);
+        assert_eq!(model.pooled(), 1);
+
+        let first = Model.with_pooling(2, Pooling::First);
+        assert_eq!(first.embed_query(text).await.unwrap().vec, vec![1.0, 6.0]);
+        let last = Model.with_pooling(2, Pooling::Last);
+        assert_eq!(last.embed_text(text).await.unwrap().vec, vec![1.0, 7.0]);
+        assert_eq!(last.pooled(), 1);
+    }
+}
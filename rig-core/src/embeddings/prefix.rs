@@ -0,0 +1,230 @@
+//! The module defines the [EmbeddingPrefixes] of asymmetric embedding models (e.g.: the e5 and
+//! bge families), which expect search queries and documents to be prefixed with different
+//! instructions, and the [PrefixedEmbeddingModel] wrapper prepending them automatically.
+//!
+//! # Example
+//! ```rust
+//! use rig::embeddings::{prefix::EmbeddingPrefixes, EmbeddingModel};
+//!
+//! let model = client
+//!     .embedding_model_with_ndims("intfloat/e5-large-v2", 1024)
+//!     .with_prefixes(EmbeddingPrefixes::e5());
+//!
+//! // Embedded as "passage: Flurbos are a made up currency."
+//! let document = model.embed_text("Flurbos are a made up currency.").await?;
+//! // Embedded as "query: What is a flurbo?"
+//! let query = model.embed_query("What is a flurbo?").await?;
+//! ```
+use super::{Embedding, EmbeddingError, EmbeddingModel, InputTruncation};
+
+/// Prefixes prepended to the texts embedded by an asymmetric embedding model.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmbeddingPrefixes {
+    /// Prefix of search queries (see [EmbeddingModel::embed_query])
+    pub query: String,
+    /// Prefix of documents (see [EmbeddingModel::embed_documents])
+    pub document: String,
+}
+
+impl EmbeddingPrefixes {
+    pub fn new(query: &str, document: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            document: document.to_string(),
+        }
+    }
+
+    /// Prefixes of the e5 family of models (e.g.: `intfloat/e5-large-v2`,
+    /// `intfloat/multilingual-e5-base`).
+    pub fn e5() -> Self {
+        Self::new("query: ", "passage: ")
+    }
+
+    /// Prefixes of the English bge v1.5 family of models (e.g.: `BAAI/bge-large-en-v1.5`),
+    /// whose documents are not prefixed.
+    pub fn bge() -> Self {
+        Self::new(
+            "Represent this sentence for searching relevant passages: ",
+            "",
+        )
+    }
+
+    /// Known prefixes of the model named `model`, if any.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::embeddings::prefix::EmbeddingPrefixes;
+    ///
+    /// assert_eq!(EmbeddingPrefixes::for_model("intfloat/e5-small-v2"), Some(EmbeddingPrefixes::e5()));
+    /// assert_eq!(EmbeddingPrefixes::for_model("text-embedding-3-small"), None);
+    /// ```
+    pub fn for_model(model: &str) -> Option<Self> {
+        let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+
+        if name.starts_with("e5-") || name.starts_with("multilingual-e5-") {
+            Some(Self::e5())
+        } else if name.starts_with("bge-") && name.contains("-en") {
+            Some(Self::bge())
+        } else {
+            None
+        }
+    }
+}
+
+/// Embedding model prepending the [EmbeddingPrefixes] of an asymmetric model to the texts it
+/// embeds: the query prefix for [EmbeddingModel::embed_query] and the document prefix for
+/// every other method (e.g.: documents embedded with an
+/// [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder)). The `document` of the returned
+/// embeddings is the original text, without its prefix.
+///
+/// Created with [EmbeddingModel::with_prefixes].
+#[derive(Clone, Debug)]
+pub struct PrefixedEmbeddingModel<M: EmbeddingModel> {
+    model: M,
+    prefixes: EmbeddingPrefixes,
+}
+
+impl<M: EmbeddingModel> PrefixedEmbeddingModel<M> {
+    pub fn new(model: M, prefixes: EmbeddingPrefixes) -> Self {
+        Self { model, prefixes }
+    }
+
+    /// The prefixes prepended by the model
+    pub fn prefixes(&self) -> &EmbeddingPrefixes {
+        &self.prefixes
+    }
+
+    async fn embed_prefixed(
+        &self,
+        prefix: &str,
+        texts: Vec<String>,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        let embeddings = self
+            .model
+            .embed_texts(texts.iter().map(|text| format!("{prefix}{text}")))
+            .await?;
+
+        Ok(embeddings
+            .into_iter()
+            .zip(texts)
+            .map(|(embedding, document)| Embedding {
+                document,
+                vec: embedding.vec,
+            })
+            .collect())
+    }
+}
+
+impl<M: EmbeddingModel> EmbeddingModel for PrefixedEmbeddingModel<M> {
+    const MAX_DOCUMENTS: usize = M::MAX_DOCUMENTS;
+
+    fn ndims(&self) -> usize {
+        self.model.ndims()
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        self.embed_prefixed(&self.prefixes.document, texts.into_iter().collect())
+            .await
+    }
+
+    fn with_input_truncation(&self, truncation: InputTruncation) -> Option<Self> {
+        Some(Self {
+            model: self.model.with_input_truncation(truncation)?,
+            prefixes: self.prefixes.clone(),
+        })
+    }
+
+    async fn embed_query(&self, query: &str) -> Result<Embedding, EmbeddingError> {
+        Ok(self
+            .embed_prefixed(&self.prefixes.query, vec![query.to_string()])
+            .await?
+            .pop()
+            .expect("There should be at least one embedding"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::EmbeddingPrefixes;
+    use crate::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+
+    /// Embedding model recording the texts it embeds
+    #[derive(Clone, Default)]
+    struct Model {
+        texts: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 10;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            let texts = texts.into_iter().collect::<Vec<_>>();
+            self.texts.lock().unwrap().extend(texts.clone());
+            Ok(texts
+                .into_iter()
+                .map(|document| Embedding {
+                    document,
+                    vec: vec![0.0],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefixes() {
+        let inner = Model::default();
+        let model = inner.clone().with_prefixes(EmbeddingPrefixes::e5());
+
+        let query = model.embed_query("What is a flurbo?").await.unwrap();
+        let document = model
+            .embed_text("Flurbos are a made up currency.")
+            .await
+            .unwrap();
+        model
+            .embed_documents(vec!["Glarbs are ancient artifacts.".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *inner.texts.lock().unwrap(),
+            vec![
+                "query: What is a flurbo?",
+                "passage: Flurbos are a made up currency.",
+                "passage: Glarbs are ancient artifacts.",
+            ]
+        );
+        // The prefixes are not part of the embedded documents
+        assert_eq!(query.document, "What is a flurbo?");
+        assert_eq!(document.document, "Flurbos are a made up currency.");
+
+        // Models without prefixes embed queries as is
+        inner.embed_query("What is a glarb?").await.unwrap();
+        assert_eq!(inner.texts.lock().unwrap()[3], "What is a glarb?");
+    }
+
+    #[test]
+    fn test_known_prefixes() {
+        assert_eq!(
+            EmbeddingPrefixes::for_model("intfloat/multilingual-e5-large"),
+            Some(EmbeddingPrefixes::e5())
+        );
+        assert_eq!(
+            EmbeddingPrefixes::for_model("BAAI/bge-small-en-v1.5"),
+            Some(EmbeddingPrefixes::bge())
+        );
+        assert_eq!(EmbeddingPrefixes::for_model("bge-m3"), None);
+        assert_eq!(EmbeddingPrefixes::for_model("text-embedding-3-small"), None);
+    }
+}
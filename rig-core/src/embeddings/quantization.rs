@@ -0,0 +1,192 @@
+//! The module defines the [Quantization] schemes used to store embeddings in a compact form
+//! (see [EmbeddingsBuilder::build_quantized](crate::embeddings::EmbeddingsBuilder::build_quantized)),
+//! as well as the [QuantizedEmbedding] type holding a quantized embedding. Binary quantized
+//! embeddings are [BinaryEmbedding]s.
+//!
+//! Quantized embeddings are compared directly in quantized space (i.e.: without being
+//! dequantized), e.g.: by the [QuantizedVectorStore](crate::vector_store::quantized_store::QuantizedVectorStore).
+use serde::{Deserialize, Serialize};
+
+use super::{distance::DistanceMetric, BinaryEmbedding, Embedding};
+
+/// Quantization scheme of embeddings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Quantization {
+    /// Scalar quantization of each dimension to an `i8`, scaled by the largest absolute value
+    /// of the embedding. Uses 8 times less memory than `f64` vectors with little loss of
+    /// precision.
+    Int8,
+    /// Binary quantization of each dimension to a single bit (see [BinaryEmbedding]). Uses 64
+    /// times less memory than `f64` vectors, at the cost of a significant loss of precision:
+    /// searches should over-fetch candidates and rerank them.
+    Binary,
+}
+
+impl Quantization {
+    /// Quantize `embedding` according to the scheme.
+    pub fn quantize(&self, embedding: &Embedding) -> QuantizedEmbedding {
+        match self {
+            Quantization::Int8 => {
+                let max = embedding
+                    .vec
+                    .iter()
+                    .fold(0.0, |max: f64, x| max.max(x.abs()));
+                let scale = if max > 0.0 { max / i8::MAX as f64 } else { 1.0 };
+
+                QuantizedEmbedding::Int8 {
+                    document: embedding.document.clone(),
+                    values: embedding
+                        .vec
+                        .iter()
+                        .map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+                        .collect(),
+                    scale,
+                }
+            }
+            Quantization::Binary => {
+                QuantizedEmbedding::Binary(BinaryEmbedding::from_embedding(embedding))
+            }
+        }
+    }
+}
+
+/// Embedding of a single document quantized with a [Quantization] scheme.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum QuantizedEmbedding {
+    /// Int8 quantized embedding: the values of the dimensions, to be multiplied by `scale`
+    Int8 {
+        /// The document that was embedded. Used for debugging.
+        document: String,
+        values: Vec<i8>,
+        scale: f64,
+    },
+    /// Binary quantized embedding
+    Binary(BinaryEmbedding),
+}
+
+impl QuantizedEmbedding {
+    /// The document that was embedded
+    pub fn document(&self) -> &str {
+        match self {
+            QuantizedEmbedding::Int8 { document, .. } => document,
+            QuantizedEmbedding::Binary(embedding) => &embedding.document,
+        }
+    }
+
+    /// The quantization scheme of the embedding
+    pub fn quantization(&self) -> Quantization {
+        match self {
+            QuantizedEmbedding::Int8 { .. } => Quantization::Int8,
+            QuantizedEmbedding::Binary(_) => Quantization::Binary,
+        }
+    }
+
+    /// Approximate the original embedding. Binary embeddings are dequantized to -1.0 and 1.0.
+    pub fn dequantize(&self) -> Embedding {
+        let vec = match self {
+            QuantizedEmbedding::Int8 { values, scale, .. } => {
+                values.iter().map(|x| *x as f64 * scale).collect()
+            }
+            QuantizedEmbedding::Binary(embedding) => (0..embedding.ndims)
+                .map(|index| if embedding.bit(index) { 1.0 } else { -1.0 })
+                .collect(),
+        };
+
+        Embedding {
+            document: self.document().to_string(),
+            vec,
+        }
+    }
+
+    /// Size of the quantized vector in bytes (including its scale factor).
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            QuantizedEmbedding::Int8 { values, .. } => values.len() + std::mem::size_of::<f64>(),
+            QuantizedEmbedding::Binary(embedding) => {
+                embedding.bits.len() * std::mem::size_of::<u64>()
+            }
+        }
+    }
+
+    /// Similarity of two quantized embeddings computed in quantized space (higher is more
+    /// similar): the cosine similarity for int8 embeddings (with integer arithmetic, since the
+    /// scale factors cancel out) and the fraction of matching bits for binary embeddings (see
+    /// [DistanceMetric::binary_similarity]). Embeddings quantized with different schemes are
+    /// compared with the cosine similarity of their dequantized forms.
+    pub fn similarity(&self, other: &Self) -> f64 {
+        match (self, other) {
+            (
+                QuantizedEmbedding::Int8 { values: a, .. },
+                QuantizedEmbedding::Int8 { values: b, .. },
+            ) => {
+                let (dot, norm_a, norm_b) = a.iter().zip(b.iter()).fold(
+                    (0i64, 0i64, 0i64),
+                    |(dot, norm_a, norm_b), (x, y)| {
+                        let (x, y) = (*x as i64, *y as i64);
+                        (dot + x * y, norm_a + x * x, norm_b + y * y)
+                    },
+                );
+
+                if norm_a == 0 || norm_b == 0 {
+                    return 0.0;
+                }
+                dot as f64 / ((norm_a as f64).sqrt() * (norm_b as f64).sqrt())
+            }
+            (QuantizedEmbedding::Binary(a), QuantizedEmbedding::Binary(b)) => {
+                DistanceMetric::binary_similarity(a, b)
+            }
+            _ => DistanceMetric::Cosine.similarity(&self.dequantize(), &other.dequantize()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Quantization, QuantizedEmbedding};
+    use crate::embeddings::{BinaryEmbedding, Embedding};
+
+    fn embedding(vec: Vec<f64>) -> Embedding {
+        Embedding {
+            document: "test".to_string(),
+            vec,
+        }
+    }
+
+    #[test]
+    fn test_int8_quantization() {
+        let quantized = Quantization::Int8.quantize(&embedding(vec![0.5, -1.0, 0.25, 0.0]));
+        assert_eq!(
+            quantized,
+            QuantizedEmbedding::Int8 {
+                document: "test".to_string(),
+                values: vec![64, -127, 32, 0],
+                scale: 1.0 / 127.0,
+            }
+        );
+        assert_eq!(quantized.size_bytes(), 4 + 8);
+
+        let dequantized = quantized.dequantize();
+        assert!(dequantized
+            .vec
+            .iter()
+            .zip([0.5, -1.0, 0.25, 0.0])
+            .all(|(x, y)| (x - y).abs() < 0.01));
+    }
+
+    #[test]
+    fn test_binary_quantization() {
+        let quantized = Quantization::Binary.quantize(&embedding(vec![0.5, -1.0, 0.25, 0.0]));
+        assert_eq!(
+            quantized,
+            QuantizedEmbedding::Binary(BinaryEmbedding {
+                document: "test".to_string(),
+                bits: vec![0b101],
+                ndims: 4,
+            })
+        );
+        assert_eq!(quantized.dequantize().vec, vec![1.0, -1.0, 1.0, -1.0]);
+
+        let other = Quantization::Binary.quantize(&embedding(vec![0.5, 1.0, 0.25, 0.0]));
+        assert_eq!(quantized.similarity(&other), 0.75);
+    }
+}
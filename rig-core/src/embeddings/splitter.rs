@@ -0,0 +1,932 @@
+//! The module defines the [TextSplitter] trait, used to split documents into smaller chunks
+//! before embedding them (see [EmbeddingsBuilder::documents_split](crate::embeddings::EmbeddingsBuilder::documents_split)),
+//! as well as the [Chunk] type holding a chunk of a document along with its parent document id
+//! and position.
+//!
+//! Chunks are linked to the previous and next chunks of their document, so that a retrieved chunk
+//! can be expanded with its neighbors for more context (see [Chunk::with_neighbors]).
+//!
+//! Any closure `Fn(&str) -> Vec<String>` implements [TextSplitter], and the [CharacterSplitter]
+//! provides a simple splitter based on the number of characters of the chunks. The
+//! [MarkdownSplitter] splits Markdown documents along their structure, without breaking their
+//! code blocks, tables and lists. The [HeaderSplitter] prefixes each chunk with a header (e.g.:
+//! the title of the document and the path of the section of the chunk), so that retrieved
+//! chunks are self-describing. The [SentenceSplitter] splits texts into sentences, without
+//! breaking them at abbreviations (e.g.: `Dr.`), decimals or ellipses.
+use serde::{Deserialize, Serialize};
+
+use super::{embed::EmbedError, Embed, TextEmbedder};
+
+/// Trait for splitting a text into chunks.
+pub trait TextSplitter {
+    /// Split `text` into chunks, in order.
+    fn split(&self, text: &str) -> Vec<String>;
+}
+
+impl<F: Fn(&str) -> Vec<String>> TextSplitter for F {
+    fn split(&self, text: &str) -> Vec<String> {
+        self(text)
+    }
+}
+
+/// Splitter producing chunks of at most `chunk_size` characters, splitting on whitespace.
+/// Consecutive chunks can share up to `overlap` characters (whole words only) so that context
+/// is not lost at chunk boundaries. Whitespace between words is normalized to a single space,
+/// and words longer than `chunk_size` are split.
+///
+/// # Example
+/// ```rust
+/// use rig::embeddings::splitter::{CharacterSplitter, TextSplitter};
+///
+/// let splitter = CharacterSplitter::new(16).overlap(6);
+/// assert_eq!(
+///     splitter.split("Flurbos are a made up currency."),
+///     vec!["Flurbos are a", "are a made up", "up currency."]
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct CharacterSplitter {
+    chunk_size: usize,
+    overlap: usize,
+}
+
+impl CharacterSplitter {
+    /// Create a new splitter producing chunks with at most `chunk_size` characters (at least 1).
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1),
+            overlap: 0,
+        }
+    }
+
+    /// Set the maximum number of characters shared by consecutive chunks (0 by default).
+    /// The overlap is clamped below the chunk size.
+    pub fn overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap.min(self.chunk_size - 1);
+        self
+    }
+}
+
+impl TextSplitter for CharacterSplitter {
+    fn split(&self, text: &str) -> Vec<String> {
+        let words = text
+            .split_whitespace()
+            .flat_map(|word| split_chars(word, self.chunk_size));
+
+        let mut chunks = vec![];
+        let mut current: Vec<&str> = vec![];
+        // Number of characters of `current` once joined with spaces
+        let mut len = 0;
+
+        for word in words {
+            let word_len = word.chars().count();
+
+            if !current.is_empty() && len + 1 + word_len > self.chunk_size {
+                chunks.push(current.join(" "));
+
+                // Start the next chunk with the last words of the current one
+                let mut kept = 0;
+                let mut kept_len = 0;
+                for word in current.iter().rev() {
+                    let added = word.chars().count() + usize::from(kept > 0);
+                    if kept_len + added > self.overlap {
+                        break;
+                    }
+                    kept += 1;
+                    kept_len += added;
+                }
+                current.drain(..current.len() - kept);
+                len = kept_len;
+
+                if !current.is_empty() && len + 1 + word_len > self.chunk_size {
+                    current.clear();
+                    len = 0;
+                }
+            }
+
+            len += word_len + usize::from(!current.is_empty());
+            current.push(word);
+        }
+
+        if !current.is_empty() {
+            chunks.push(current.join(" "));
+        }
+
+        chunks
+    }
+}
+
+/// Structure-aware splitter for Markdown documents (e.g.: READMEs, documentation) producing
+/// chunks of at most `chunk_size` characters.
+///
+/// Code fences (` ``` ` or `~~~`), tables and lists are never split: consecutive blocks are
+/// packed into chunks (separated by a blank line) as long as they fit. Oversized paragraphs are
+/// split into sentences (and sentences longer than `chunk_size` are split with a
+/// [CharacterSplitter]), while oversized code blocks, tables and lists are emitted whole as
+/// their own chunk, with a warning.
+///
+/// # Example
+/// ```rust
+/// use rig::embeddings::splitter::{MarkdownSplitter, TextSplitter};
+///
+/// let chunks = MarkdownSplitter::new(512).split(&std::fs::read_to_string("README.md")?);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MarkdownSplitter {
+    chunk_size: usize,
+}
+
+impl MarkdownSplitter {
+    /// Create a new splitter producing chunks with at most `chunk_size` characters (at least 1),
+    /// except for oversized code blocks, tables and lists.
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    /// Pack `piece` into `current`, separated by `separator`, pushing `current` to `chunks`
+    /// first if the piece does not fit.
+    fn pack(&self, chunks: &mut Vec<String>, current: &mut String, piece: &str, separator: &str) {
+        if !current.is_empty()
+            && current.chars().count() + separator.chars().count() + piece.chars().count()
+                > self.chunk_size
+        {
+            chunks.push(std::mem::take(current));
+        }
+        if !current.is_empty() {
+            current.push_str(separator);
+        }
+        current.push_str(piece);
+    }
+}
+
+impl TextSplitter for MarkdownSplitter {
+    fn split(&self, text: &str) -> Vec<String> {
+        let mut chunks = vec![];
+        let mut current = String::new();
+
+        for block in markdown_blocks(text) {
+            if block.text.chars().count() <= self.chunk_size {
+                self.pack(&mut chunks, &mut current, &block.text, "\n\n");
+                continue;
+            }
+
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            match block.kind {
+                Some(kind) => {
+                    tracing::warn!(target: "rig",
+                        "Markdown {kind} of {} characters exceeds the chunk size of {}, emitting it whole",
+                        block.text.chars().count(),
+                        self.chunk_size
+                    );
+                    chunks.push(block.text);
+                }
+                None => {
+                    let mut pieces = String::new();
+                    for sentence in sentences(&block.text) {
+                        if sentence.chars().count() <= self.chunk_size {
+                            self.pack(&mut chunks, &mut pieces, &sentence, " ");
+                        } else {
+                            for piece in CharacterSplitter::new(self.chunk_size).split(&sentence) {
+                                self.pack(&mut chunks, &mut pieces, &piece, " ");
+                            }
+                        }
+                    }
+                    chunks.extend((!pieces.is_empty()).then_some(pieces));
+                }
+            }
+        }
+
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+
+        chunks
+    }
+}
+
+/// Splitter for (Markdown) documents prefixing each chunk with a header made of the title and
+/// metadata of the document and the path of the headings of the section of the chunk (e.g.:
+/// `Terms > Payment > Late fees`), so that each chunk is self-describing once retrieved.
+///
+/// The sections of the document are split with a [MarkdownSplitter] whose chunk size leaves
+/// room for the header: chunks (header included) are at most `chunk_size` characters, except
+/// for oversized code blocks, tables and lists. Headers are truncated to half the chunk size.
+///
+/// # Example
+/// ```rust
+/// use rig::embeddings::splitter::{HeaderSplitter, TextSplitter};
+///
+/// let splitter = HeaderSplitter::new(512)
+///     .title("Master Services Agreement")
+///     .metadata("Version", "2024-03");
+///
+/// // Each chunk starts with e.g.:
+/// // Master Services Agreement
+/// // Version: 2024-03
+/// // Terms > Payment
+/// let chunks = splitter.split(&std::fs::read_to_string("agreement.md")?);
+/// ```
+#[derive(Clone, Debug)]
+pub struct HeaderSplitter {
+    chunk_size: usize,
+    title: Option<String>,
+    metadata: Vec<(String, String)>,
+    separator: String,
+}
+
+impl HeaderSplitter {
+    /// Create a new splitter producing chunks with at most `chunk_size` characters (at least 1),
+    /// header included, except for oversized code blocks, tables and lists.
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1),
+            title: None,
+            metadata: vec![],
+            separator: " > ".to_string(),
+        }
+    }
+
+    /// Set the title of the document, the first line of the headers
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Add a `key: value` line to the headers, after the title, e.g.: the source of the document
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Set the separator of the headings of the section paths (` > ` by default)
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Header of the chunks of the section with the heading path `path`: the title, the
+    /// metadata and the path of the section, one per line (skipping the missing ones), truncated
+    /// to half the chunk size.
+    pub fn header(&self, path: &[String]) -> String {
+        let lines = self
+            .title
+            .iter()
+            .cloned()
+            .chain(
+                self.metadata
+                    .iter()
+                    .map(|(key, value)| format!("{key}: {value}")),
+            )
+            .chain((!path.is_empty()).then(|| path.join(&self.separator)))
+            .collect::<Vec<_>>();
+
+        lines
+            .join("\n")
+            .chars()
+            .take(self.chunk_size / 2)
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+}
+
+impl TextSplitter for HeaderSplitter {
+    fn split(&self, text: &str) -> Vec<String> {
+        let mut chunks = vec![];
+
+        for (path, body) in markdown_sections(text) {
+            let header = self.header(&path);
+            if header.is_empty() {
+                chunks.extend(MarkdownSplitter::new(self.chunk_size).split(&body));
+                continue;
+            }
+
+            // The header and the blank line separating it from the chunk count towards its size
+            let budget = self.chunk_size.saturating_sub(header.chars().count() + 2);
+            chunks.extend(
+                MarkdownSplitter::new(budget)
+                    .split(&body)
+                    .into_iter()
+                    .map(|chunk| format!("{header}\n\n{chunk}")),
+            );
+        }
+
+        chunks
+    }
+}
+
+/// Split a Markdown document into its sections, along with the path of their headings (e.g.:
+/// `["Terms", "Payment"]` for a `## Payment` heading under a `# Terms` heading). Headings
+/// within code blocks are ignored, and sections without content are skipped.
+fn markdown_sections(text: &str) -> Vec<(Vec<String>, String)> {
+    let mut sections = vec![];
+    // Heading path of the current section, with the level of each heading
+    let mut path: Vec<(usize, String)> = vec![];
+    let mut body: Vec<&str> = vec![];
+    let mut fence: Option<(char, usize)> = None;
+
+    let mut push = |path: &[(usize, String)], body: &mut Vec<&str>| {
+        let text = body.join("\n");
+        if !text.trim().is_empty() {
+            sections.push((
+                path.iter().map(|(_, heading)| heading.clone()).collect(),
+                text,
+            ));
+        }
+        body.clear();
+    };
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        match (fence, code_fence(trimmed)) {
+            (None, Some(opening)) => fence = Some(opening),
+            (Some((c, len)), Some((closing, n)))
+                if closing == c && n >= len && n == trimmed.chars().count() =>
+            {
+                fence = None
+            }
+            (None, None) => {
+                if let Some(heading) = heading(trimmed) {
+                    push(&path, &mut body);
+                    while path.last().is_some_and(|(level, _)| *level >= heading.0) {
+                        path.pop();
+                    }
+                    path.push((heading.0, heading.1.to_string()));
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        body.push(line);
+    }
+    push(&path, &mut body);
+
+    sections
+}
+
+/// Level and title of the line if it is an ATX heading (e.g.: `## Payment`)
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let title = line[level..].strip_prefix([' ', '\t'])?;
+    (1..=6)
+        .contains(&level)
+        .then(|| (level, title.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Block of a Markdown document
+struct MarkdownBlock {
+    text: String,
+    /// Kind of the block if it cannot be split (e.g.: "code block"), `None` for paragraphs
+    kind: Option<&'static str>,
+}
+
+/// Split a Markdown document into its code blocks, tables, lists, headings and paragraphs.
+fn markdown_blocks(text: &str) -> Vec<MarkdownBlock> {
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut blocks = vec![];
+
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index].trim_start();
+        let start = index;
+        index += 1;
+
+        let kind = if line.is_empty() {
+            continue;
+        } else if let Some((fence, len)) = code_fence(line) {
+            // The block ends with the closing fence (or at the end of the document)
+            while index < lines.len() {
+                let closing = lines[index].trim();
+                index += 1;
+                if code_fence(closing)
+                    .is_some_and(|(c, n)| c == fence && n >= len && n == closing.chars().count())
+                {
+                    break;
+                }
+            }
+            Some("code block")
+        } else if is_table_row(line) {
+            while index < lines.len() && is_table_row(lines[index].trim_start()) {
+                index += 1;
+            }
+            Some("table")
+        } else if is_list_item(line) {
+            // Items, their indented continuations and the blank lines between them
+            loop {
+                let next = lines[index..]
+                    .iter()
+                    .position(|line| !line.trim().is_empty())
+                    .map(|offset| index + offset);
+                match next {
+                    Some(next)
+                        if is_list_item(lines[next].trim_start())
+                            || lines[next].starts_with([' ', '\t']) =>
+                    {
+                        index = next + 1;
+                    }
+                    _ => break,
+                }
+            }
+            Some("list")
+        } else if line.starts_with('#') {
+            None
+        } else {
+            // The paragraph ends with a blank line or the start of another block
+            while index < lines.len() {
+                let line = lines[index].trim_start();
+                if line.is_empty()
+                    || line.starts_with('#')
+                    || code_fence(line).is_some()
+                    || is_table_row(line)
+                    || is_list_item(line)
+                {
+                    break;
+                }
+                index += 1;
+            }
+            None
+        };
+
+        blocks.push(MarkdownBlock {
+            text: lines[start..index].join("\n").trim_end().to_string(),
+            kind,
+        });
+    }
+
+    blocks
+}
+
+/// Fence character and length of the line if it opens or closes a code block
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let fence = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == fence).count();
+    (len >= 3).then_some((fence, len))
+}
+
+fn is_table_row(line: &str) -> bool {
+    line.starts_with('|')
+}
+
+fn is_list_item(line: &str) -> bool {
+    if let Some(rest) = line.strip_prefix(['-', '*', '+']) {
+        return rest.starts_with([' ', '\t']);
+    }
+    let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    rest.len() < line.len()
+        && rest
+            .strip_prefix(['.', ')'])
+            .is_some_and(|rest| rest.starts_with([' ', '\t']))
+}
+
+/// Common abbreviations (lowercase, without their final period) which do not end sentences.
+/// Abbreviations which are also common words (e.g.: `No.`, `Sec.` or `Mar.`) are left out so
+/// that the words still end sentences: followed by a number (e.g.: `No. 5`), their period does
+/// not end the sentence anyway.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "mt", "gen", "capt", "lt", "sgt", "rev",
+    "hon", "vs", "etc", "e.g", "i.e", "cf", "approx", "fig", "figs", "nos", "vol", "vols", "pp",
+    "eds", "dept", "inc", "ltd", "corp", "jan", "feb", "apr", "jun", "jul", "aug", "sep", "sept",
+    "oct", "nov", "u.s", "u.k", "a.m", "p.m",
+];
+
+/// Splitter of texts into sentences, ending with `.`, `!` or `?` (possibly followed by closing
+/// quotes or brackets) followed by whitespace. Each sentence is a chunk, with its whitespace
+/// normalized.
+///
+/// Periods do not end sentences when they end an abbreviation (see
+/// [SentenceSplitter::abbreviation]) or an initial (e.g.: `J. R. R. Tolkien`), or when the
+/// next word starts with a lowercase letter or a digit. Ellipses (`...` or `…`) only end a
+/// sentence when the next word starts with an uppercase letter, and decimals (e.g.: `3.14`)
+/// never do since their period is not followed by whitespace.
+///
+/// # Example
+/// ```rust
+/// use rig::embeddings::splitter::{SentenceSplitter, TextSplitter};
+///
+/// assert_eq!(
+///     SentenceSplitter::new().split("Dr. Smith paid 3.5 flurbos, i.e. a lot. Wow... what a deal!"),
+///     vec!["Dr. Smith paid 3.5 flurbos, i.e. a lot.", "Wow... what a deal!"]
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct SentenceSplitter {
+    abbreviations: Vec<String>,
+}
+
+impl Default for SentenceSplitter {
+    fn default() -> Self {
+        Self {
+            abbreviations: ABBREVIATIONS
+                .iter()
+                .map(|abbreviation| abbreviation.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl SentenceSplitter {
+    /// Create a new splitter recognizing common English abbreviations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an abbreviation whose period does not end sentences (e.g.: `"Dept."` or `"approx"`),
+    /// matched case-insensitively
+    pub fn abbreviation(mut self, abbreviation: &str) -> Self {
+        self.abbreviations
+            .push(abbreviation.trim_end_matches('.').to_lowercase());
+        self
+    }
+
+    /// Whether the terminator ending the sentence prefix `text` (at byte `index`) followed by
+    /// the word `next` ends the sentence
+    fn is_boundary(&self, text: &str, index: usize, next: &str) -> bool {
+        let next = next.trim_start_matches(['"', '\'', '(', '[', '“', '‘']);
+        let starts_uppercase = next.chars().next().is_some_and(char::is_uppercase);
+        let terminator = text[index..].chars().next();
+
+        if terminator == Some('…') || text[..index].ends_with("..") {
+            return starts_uppercase || next.is_empty();
+        }
+        if terminator != Some('.') {
+            return true;
+        }
+        if next
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_lowercase() || c.is_ascii_digit())
+        {
+            return false;
+        }
+
+        // Last word of the sentence prefix, without its opening punctuation
+        let word = text[..index]
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches(|c: char| !c.is_alphanumeric());
+        let is_initial = word.chars().count() == 1 && word.chars().all(char::is_uppercase);
+
+        !is_initial && !self.abbreviations.contains(&word.to_lowercase())
+    }
+}
+
+impl TextSplitter for SentenceSplitter {
+    fn split(&self, text: &str) -> Vec<String> {
+        let mut sentences = vec![];
+        let mut start = 0;
+
+        let mut chars = text.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            if !matches!(c, '.' | '!' | '?' | '…') {
+                continue;
+            }
+
+            // Closing quotes and brackets following the terminator are part of the sentence
+            let mut end = index + c.len_utf8();
+            while let Some((_, closing)) =
+                chars.next_if(|(_, next)| matches!(next, '"' | '\'' | ')' | ']' | '”' | '’'))
+            {
+                end += closing.len_utf8();
+            }
+
+            if chars.peek().is_some_and(|(_, next)| next.is_whitespace()) {
+                let next = text[end..].split_whitespace().next().unwrap_or_default();
+                if self.is_boundary(&text[start..], index - start, next) {
+                    sentences.push(&text[start..end]);
+                    start = end;
+                }
+            }
+        }
+        sentences.push(&text[start..]);
+
+        sentences
+            .into_iter()
+            .map(|sentence| sentence.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|sentence| !sentence.is_empty())
+            .collect()
+    }
+}
+
+/// Split a paragraph into sentences with a default [SentenceSplitter].
+pub(crate) fn sentences(paragraph: &str) -> Vec<String> {
+    SentenceSplitter::default().split(paragraph)
+}
+
+/// Split `word` into pieces of at most `size` characters.
+fn split_chars(word: &str, size: usize) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut rest = word;
+
+    while !rest.is_empty() {
+        let end = rest
+            .char_indices()
+            .nth(size)
+            .map_or(rest.len(), |(index, _)| index);
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    pieces
+}
+
+/// A chunk of a document, as produced by [EmbeddingsBuilder::documents_split](crate::embeddings::EmbeddingsBuilder::documents_split).
+/// Only the text of the chunk is embedded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Id of the document the chunk was split from
+    pub parent_id: String,
+    /// Position of the chunk in its parent document (0-indexed)
+    pub chunk_index: usize,
+    /// Text of the chunk
+    pub text: String,
+    /// Id of the previous chunk of the parent document (see [Chunk::id]), if any
+    #[serde(default)]
+    pub prev_id: Option<String>,
+    /// Id of the next chunk of the parent document (see [Chunk::id]), if any
+    #[serde(default)]
+    pub next_id: Option<String>,
+}
+
+impl Chunk {
+    /// Create the chunk at position `chunk_index` of the `count` chunks of the document
+    /// `parent_id`, linked to its neighbors.
+    pub fn new(parent_id: &str, chunk_index: usize, count: usize, text: String) -> Self {
+        Self {
+            parent_id: parent_id.to_string(),
+            chunk_index,
+            text,
+            prev_id: chunk_index
+                .checked_sub(1)
+                .map(|index| chunk_id(parent_id, index)),
+            next_id: (chunk_index + 1 < count).then(|| chunk_id(parent_id, chunk_index + 1)),
+        }
+    }
+
+    /// Id of the chunk, of the form `<parent id>#<chunk index>` (e.g.: `flurbo#2`). Chunks
+    /// should be stored under their id for their neighbors to be found (see
+    /// [Chunk::with_neighbors]).
+    pub fn id(&self) -> String {
+        chunk_id(&self.parent_id, self.chunk_index)
+    }
+
+    /// Expand the chunk with up to `window` of its neighbors on each side, fetched by id with
+    /// `get` (e.g.: from the vector store the chunk was retrieved from). The chunks are returned
+    /// in the order of the parent document; neighbors which cannot be fetched end the expansion
+    /// on their side.
+    ///
+    /// # Example
+    /// ```rust
+    /// let vector_store = InMemoryVectorStore::from_documents_with_id_f(chunks, |chunk| chunk.id());
+    /// let index = vector_store.clone().index(model);
+    ///
+    /// for (_, _, chunk) in index.top_n::<Chunk>("What is a flurbo?", 3).await? {
+    ///     let context = chunk
+    ///         .with_neighbors(1, |id| vector_store.get_document(id).ok().flatten())
+    ///         .into_iter()
+    ///         .map(|chunk| chunk.text)
+    ///         .collect::<Vec<_>>()
+    ///         .join(" ");
+    /// }
+    /// ```
+    pub fn with_neighbors(self, window: usize, get: impl Fn(&str) -> Option<Chunk>) -> Vec<Chunk> {
+        let mut previous = vec![];
+        let mut prev_id = self.prev_id.clone();
+        while let Some(chunk) = prev_id
+            .filter(|_| previous.len() < window)
+            .and_then(|id| get(&id))
+        {
+            prev_id = chunk.prev_id.clone();
+            previous.push(chunk);
+        }
+
+        let mut next = vec![];
+        let mut next_id = self.next_id.clone();
+        while let Some(chunk) = next_id
+            .filter(|_| next.len() < window)
+            .and_then(|id| get(&id))
+        {
+            next_id = chunk.next_id.clone();
+            next.push(chunk);
+        }
+
+        previous
+            .into_iter()
+            .rev()
+            .chain(std::iter::once(self))
+            .chain(next)
+            .collect()
+    }
+}
+
+/// Id of the chunk at position `chunk_index` of the document `parent_id` (see [Chunk::id])
+fn chunk_id(parent_id: &str, chunk_index: usize) -> String {
+    format!("{parent_id}#{chunk_index}")
+}
+
+impl Embed for Chunk {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.text.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CharacterSplitter, HeaderSplitter, MarkdownSplitter, SentenceSplitter, TextSplitter,
+    };
+
+    #[test]
+    fn test_character_splitter() {
+        let splitter = CharacterSplitter::new(16);
+        let chunks = splitter.split("Flurbos are a made   up\ncurrency.");
+        assert_eq!(chunks, vec!["Flurbos are a", "made up", "currency."]);
+        assert_eq!(splitter.split(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_character_splitter_overlap() {
+        let splitter = CharacterSplitter::new(16).overlap(6);
+        assert_eq!(
+            splitter.split("Flurbos are a made up currency."),
+            vec!["Flurbos are a", "are a made up", "up currency."]
+        );
+    }
+
+    #[test]
+    fn test_character_splitter_long_words() {
+        let splitter = CharacterSplitter::new(4);
+        assert_eq!(
+            splitter.split("glarb-garb éé"),
+            vec!["glar", "b-ga", "rb", "éé"]
+        );
+    }
+
+    const MARKDOWN: &str = r#"# Flurbos
+
+Flurbos are a made up currency. They are used on planet Glarb.
+
+```rust
+fn main() {
+    let flurbos = 42;
+
+    println!("{flurbos} flurbos");
+}
+```
+
+| Coin   | Value |
+|--------|-------|
+| Flurbo | 1     |
+| Glarb  | 10    |
+
+- Flurbos
+  are round.
+
+- Glarbs are square.
+"#;
+
+    #[test]
+    fn test_markdown_splitter() {
+        let chunks = MarkdownSplitter::new(80).split(MARKDOWN);
+        assert_eq!(
+            chunks,
+            vec![
+                "# Flurbos\n\nFlurbos are a made up currency. They are used on planet Glarb.",
+                // The code block exceeds the chunk size but is emitted whole
+                "```rust\nfn main() {\n    let flurbos = 42;\n\n    println!(\"{flurbos} flurbos\");\n}\n```",
+                "| Coin   | Value |\n|--------|-------|\n| Flurbo | 1     |\n| Glarb  | 10    |",
+                "- Flurbos\n  are round.\n\n- Glarbs are square.",
+            ]
+        );
+        assert!(chunks[1].chars().count() > 80);
+    }
+
+    #[test]
+    fn test_markdown_splitter_paragraphs() {
+        // Small blocks are packed together
+        let chunks = MarkdownSplitter::new(1000).split(MARKDOWN);
+        assert_eq!(chunks, vec![MARKDOWN.trim_end()]);
+
+        // Oversized paragraphs are split into sentences
+        let chunks = MarkdownSplitter::new(50).split(
+            "Flurbos are a made up currency. They are used on planet Glarb to buy glarbs.\n\
+            Nobody knows who invented them! Do you?",
+        );
+        assert_eq!(
+            chunks,
+            vec![
+                "Flurbos are a made up currency.",
+                "They are used on planet Glarb to buy glarbs.",
+                "Nobody knows who invented them! Do you?",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sentence_splitter() {
+        let splitter = SentenceSplitter::new();
+
+        // Abbreviations and initials
+        assert_eq!(
+            splitter.split(
+                "Dr. Smith met Mrs. J. Doe at 5 p.m. yesterday. \
+                They talked about flurbos, e.g. their value."
+            ),
+            vec![
+                "Dr. Smith met Mrs. J. Doe at 5 p.m. yesterday.",
+                "They talked about flurbos, e.g. their value."
+            ]
+        );
+
+        // Decimals and ellipses (ending sentences only before an uppercase word)
+        assert_eq!(
+            splitter
+                .split("A flurbo is worth 3.14 glarbs... or so. Wait… What? \"Really!\" He left."),
+            vec![
+                "A flurbo is worth 3.14 glarbs... or so.",
+                "Wait…",
+                "What?",
+                "\"Really!\"",
+                "He left."
+            ]
+        );
+
+        // Common words which are also abbreviations only continue the sentence before numbers
+        assert_eq!(
+            splitter.split("I said no. Then he left. See No. 5 and Sec. 3 for details."),
+            vec![
+                "I said no.",
+                "Then he left.",
+                "See No. 5 and Sec. 3 for details."
+            ]
+        );
+
+        // Custom abbreviations
+        let text = "A glarb costs 3 flb. Or so they say.";
+        assert_eq!(
+            splitter.split(text),
+            vec!["A glarb costs 3 flb.", "Or so they say."]
+        );
+        assert_eq!(
+            splitter.abbreviation("Flb.").split(text),
+            vec!["A glarb costs 3 flb. Or so they say."]
+        );
+    }
+
+    #[test]
+    fn test_header_splitter() {
+        let document = "\
+# Terms
+
+The services are provided as is. The provider may update them at any time.
+
+## Payment
+
+Invoices are due within thirty days. Late payments accrue interest.
+
+```text
+# Not a heading
+```
+
+# Termination
+
+Either party may terminate the agreement with a notice of ninety days.
+";
+        let splitter = HeaderSplitter::new(120)
+            .title("Master Services Agreement")
+            .metadata("Version", "2024-03");
+        let chunks = splitter.split(document);
+
+        let header =
+            |path: &str| format!("Master Services Agreement\nVersion: 2024-03\n{path}\n\n");
+        let expected = [
+            header("Terms"),
+            header("Terms"),
+            header("Terms > Payment"),
+            header("Terms > Payment"),
+            header("Terms > Payment"),
+            header("Termination"),
+            header("Termination"),
+        ];
+        assert_eq!(chunks.len(), expected.len(), "{chunks:#?}");
+        for (chunk, header) in chunks.iter().zip(expected) {
+            assert!(chunk.starts_with(&header), "{chunk:?}");
+            assert!(chunk.chars().count() <= 120, "{chunk:?}");
+        }
+
+        // The heading within the code block is part of the section of the block
+        assert!(chunks[4].ends_with("```text\n# Not a heading\n```"));
+        assert!(chunks.iter().all(|chunk| !chunk.contains("\n\n# ")));
+
+        // Without headings nor title, the chunks are the chunks of a MarkdownSplitter
+        assert_eq!(
+            HeaderSplitter::new(40).split("Flurbos are a made up currency."),
+            vec!["Flurbos are a made up currency."]
+        );
+    }
+}
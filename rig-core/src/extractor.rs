@@ -28,7 +28,9 @@
 //!     .expect("Failed to extract data from text");
 //! ```
 
-use std::marker::PhantomData;
+use std::{marker::PhantomData, pin::Pin};
+
+use futures::Stream;
 
 use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
@@ -52,6 +54,20 @@ pub enum ExtractionError {
     PromptError(#[from] PromptError),
 }
 
+/// Event of a streaming extraction (see [Agent::extract_stream])
+#[derive(Clone, Debug, PartialEq)]
+pub enum PartialExtraction<T> {
+    /// Value parsed from the part of the response received so far (with its open strings,
+    /// arrays and objects closed), emitted whenever it changes
+    Partial(T),
+    /// Terminal event of the stream, with the value parsed from the full response
+    Complete(T),
+}
+
+/// Boxed stream of [PartialExtraction]s returned by [Agent::extract_stream]
+pub type ExtractionStream<T> =
+    Pin<Box<dyn Stream<Item = Result<PartialExtraction<T>, ExtractionError>> + Send>>;
+
 /// Extractor for structured data from text
 pub struct Extractor<M: CompletionModel, T: JsonSchema + for<'a> Deserialize<'a> + Send + Sync> {
     agent: Agent<M>,
@@ -0,0 +1,159 @@
+//! This module provides the [JsonEnforcer] completion model wrapper, which makes models that
+//! don't support (or ignore) a JSON mode respond with JSON only.
+//!
+//! The wrapper appends a strict JSON-only instruction to the preamble of every request, strips
+//! the markdown code fences models often wrap their JSON in (e.g.: ` ```json ... ``` `) and
+//! validates that the response is valid JSON before returning it. Tool calls are returned as is.
+//!
+//! Since [JsonEnforcer] is itself a [CompletionModel], it composes with agents and extractors.
+//!
+//! # Example
+//! ```rust
+//! use rig::{json_enforcer::JsonEnforcer, providers::openai};
+//!
+//! let openai = openai::Client::from_env();
+//! let model = JsonEnforcer::new(openai.completion_model(openai::GPT_35_TURBO));
+//!
+//! #[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+//! struct Person {
+//!     name: Option<String>,
+//!     age: Option<u8>,
+//! }
+//!
+//! let extractor = rig::extractor::ExtractorBuilder::<Person, _>::new(model).build();
+//! let person = extractor.extract("John Doe is a 30 year old doctor.").await?;
+//! ```
+use crate::completion::{
+    CompletionError, CompletionModel, CompletionRequest, CompletionResponse, ModelChoice,
+};
+
+const DEFAULT_INSTRUCTION: &str = "\
+    IMPORTANT: Unless you are calling a tool, respond with only valid JSON. \
+    Do not include any explanation, comment or markdown formatting (e.g.: code fences) \
+    before or after the JSON.";
+
+/// Completion model wrapper enforcing JSON-only responses (see the [module](self) docs)
+#[derive(Clone)]
+pub struct JsonEnforcer<M: CompletionModel> {
+    model: M,
+    instruction: String,
+}
+
+impl<M: CompletionModel> JsonEnforcer<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            instruction: DEFAULT_INSTRUCTION.to_string(),
+        }
+    }
+
+    /// Override the JSON-only instruction appended to the preamble of every request
+    pub fn instruction(mut self, instruction: &str) -> Self {
+        self.instruction = instruction.to_string();
+        self
+    }
+}
+
+impl<M: CompletionModel> CompletionModel for JsonEnforcer<M> {
+    type Response = M::Response;
+
+    async fn completion(
+        &self,
+        mut request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        request.preamble = Some(match request.preamble.take() {
+            Some(preamble) => format!("{preamble}\n\n{}", self.instruction),
+            None => self.instruction.clone(),
+        });
+
+        let mut response = self.model.completion(request).await?;
+
+        if let ModelChoice::Message(message) = &mut response.choice {
+            let json = strip_code_fences(message);
+            serde_json::from_str::<serde_json::Value>(json).map_err(|e| {
+                CompletionError::ResponseError(format!("Response is not valid JSON: {e}"))
+            })?;
+            *message = json.to_string();
+        }
+
+        Ok(response)
+    }
+}
+
+/// Extract the content of the first markdown code block of `text` (ignoring the language tag
+/// of the fence), or the trimmed `text` if it contains no code block.
+pub(crate) fn strip_code_fences(text: &str) -> &str {
+    let text = text.trim();
+    let Some(start) = text.find("```") else {
+        return text;
+    };
+
+    let block = &text[start + 3..];
+    let block = match block.find('\n') {
+        Some(newline) => &block[newline + 1..],
+        None => block,
+    };
+    let end = block.find("```").unwrap_or(block.len());
+
+    block[..end].trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{agent::tests::MockCompletionModel, extractor::ExtractorBuilder};
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq, schemars::JsonSchema)]
+    struct Person {
+        name: String,
+        age: u8,
+    }
+
+    #[tokio::test]
+    async fn test_fenced_json_response() {
+        let model = MockCompletionModel::new(vec![ModelChoice::Message(
+            "Here is the data:\n```json\n{\"name\": \"John Doe\", \"age\": 30}\n```".to_string(),
+        )]);
+
+        let extractor =
+            ExtractorBuilder::<Person, _>::new(JsonEnforcer::new(model.clone())).build();
+
+        let person = extractor
+            .extract("John Doe is a 30 year old doctor.")
+            .await
+            .unwrap();
+        assert_eq!(
+            person,
+            Person {
+                name: "John Doe".to_string(),
+                age: 30
+            }
+        );
+
+        let requests = model.requests.lock().unwrap();
+        assert!(requests[0]
+            .preamble
+            .as_ref()
+            .unwrap()
+            .ends_with(DEFAULT_INSTRUCTION));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json_response() {
+        let model = JsonEnforcer::new(MockCompletionModel::new(vec![ModelChoice::Message(
+            "```\nNot JSON\n```".to_string(),
+        )]));
+
+        let result = model.completion_request("Who is John Doe?").send().await;
+        assert!(matches!(result, Err(CompletionError::ResponseError(_))));
+    }
+
+    #[test]
+    fn test_strip_code_fences() {
+        assert_eq!(strip_code_fences(" {\"a\": 1} "), "{\"a\": 1}");
+        assert_eq!(strip_code_fences("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+        assert_eq!(strip_code_fences("```\n[1, 2]\n```\nDone."), "[1, 2]");
+    }
+}
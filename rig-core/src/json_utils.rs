@@ -17,3 +17,130 @@ pub fn merge_inplace(a: &mut serde_json::Value, b: serde_json::Value) {
         });
     }
 }
+
+/// Sort the keys of the objects of `value`, recursively. Objects are already sorted unless
+/// serde_json's `preserve_order` feature is enabled (e.g.: by another crate of the dependency
+/// graph), in which case they keep the order of insertion, e.g.: of the iteration of a HashMap.
+pub fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries = map.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// Serialize `value` to a JSON string with sorted object keys (see [sort_keys]), so that
+/// logically identical values (e.g.: with maps iterated in different orders) serialize to
+/// identical bytes.
+pub fn to_sorted_string<T: serde::Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    serde_json::to_string(&sort_keys(serde_json::to_value(value)?))
+}
+
+/// Parse a JSON document truncated at an arbitrary position (e.g.: a partially streamed
+/// response) by closing its open strings, arrays and objects. Incomplete trailing members
+/// (e.g.: a key without its value) are dropped. Any text before the start of the document
+/// (e.g.: a code fence) is skipped. Returns `None` if no object or array has started yet.
+pub fn parse_partial_json(text: &str) -> Option<serde_json::Value> {
+    let start = text.find(['{', '['])?;
+    let text = &text[start..];
+
+    let closers = |stack: &[char]| stack.iter().rev().collect::<String>();
+
+    let mut stack = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    // Position up to which the document is complete, and the containers open at that position
+    let mut safe = (0, vec![]);
+
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => {
+                stack.push('}');
+                safe = (index + 1, stack.clone());
+            }
+            '[' => {
+                stack.push(']');
+                safe = (index + 1, stack.clone());
+            }
+            '}' | ']' => {
+                stack.pop();
+                safe = (index + 1, stack.clone());
+                if stack.is_empty() {
+                    return serde_json::from_str(&text[..=index]).ok();
+                }
+            }
+            ',' => safe = (index, stack.clone()),
+            _ => (),
+        }
+    }
+
+    // Close the document as is, e.g.: to keep the partial value of a string
+    let mut completed = text.to_string();
+    if in_string {
+        if escaped {
+            completed.pop();
+        }
+        completed.push('"');
+    }
+    completed.push_str(&closers(&stack));
+
+    serde_json::from_str(&completed).ok().or_else(|| {
+        let (index, stack) = safe;
+        serde_json::from_str(&format!("{}{}", &text[..index], closers(&stack))).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse_partial_json;
+
+    #[test]
+    fn test_parse_partial_json() {
+        assert_eq!(parse_partial_json("Sure! "), None);
+        assert_eq!(parse_partial_json("```json\n{"), Some(json!({})));
+        assert_eq!(
+            parse_partial_json(r#"{"name": "Flur"#),
+            Some(json!({ "name": "Flur" }))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"name": "Flurbo", "tot"#),
+            Some(json!({ "name": "Flurbo" }))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"tags": ["a", "b\"#),
+            Some(json!({ "tags": ["a", "b"] }))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"nested": {"x": 1}, "y": tr"#),
+            Some(json!({ "nested": { "x": 1 } }))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"x": 1} trailing"#),
+            Some(json!({ "x": 1 }))
+        );
+    }
+}
@@ -79,16 +79,26 @@
 //! implement the [VectorStoreIndex](crate::vector_store::VectorStoreIndex) trait.
 
 pub mod agent;
+pub mod cache;
+pub mod classifier;
 pub mod cli_chatbot;
 pub mod completion;
+pub mod concurrency;
+pub mod context_fallback;
+pub mod conversation;
 pub mod embeddings;
 pub mod extractor;
+pub(crate) mod hash;
+pub mod json_enforcer;
 pub(crate) mod json_utils;
 pub mod loaders;
 pub mod one_or_many;
 pub mod pipeline;
+pub mod prompt_template;
 pub mod providers;
+pub mod streaming;
 pub mod tool;
+pub mod truncation;
 pub mod vector_store;
 
 // Re-export commonly used types and traits
@@ -96,4 +106,12 @@ pub use embeddings::Embed;
 pub use one_or_many::{EmptyListError, OneOrMany};
 
 #[cfg(feature = "derive")]
-pub use rig_derive::Embed;
+pub use rig_derive::{tools, Embed};
+
+/// Dependencies used by the code generated by the derive macros. Not public API.
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+    pub use serde_json;
+}
@@ -0,0 +1,510 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    iter,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use glob::glob;
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+#[cfg(feature = "docx")]
+use super::docx::{DocxDocument, DocxLoaderError};
+#[cfg(feature = "epub")]
+use super::epub::{self, EpubLoaderError};
+#[cfg(feature = "pdf")]
+use super::pdf::{self, PdfLoaderError};
+use super::{email::Email, file::FileLoaderError, html::strip_html, mime};
+use crate::completion::Document;
+
+#[derive(Error, Debug)]
+pub enum ArchiveLoaderError {
+    #[error("{0}")]
+    FileLoaderError(#[from] FileLoaderError),
+
+    #[error("Zip error: {0}")]
+    ZipError(#[from] ZipError),
+
+    #[error("UTF-8 conversion error: {0}")]
+    FromUtf8Error(#[from] std::string::FromUtf8Error),
+
+    #[cfg(feature = "pdf")]
+    #[error("{0}")]
+    PdfLoaderError(#[from] PdfLoaderError),
+
+    #[cfg(feature = "epub")]
+    #[error("{0}")]
+    EpubLoaderError(#[from] EpubLoaderError),
+
+    #[cfg(feature = "docx")]
+    #[error("{0}")]
+    DocxLoaderError(#[from] DocxLoaderError),
+
+    /// The file is neither a zip nor a tar archive
+    #[error("Unsupported archive: {0}")]
+    UnsupportedArchive(String),
+}
+
+impl From<std::io::Error> for ArchiveLoaderError {
+    fn from(err: std::io::Error) -> Self {
+        ArchiveLoaderError::FileLoaderError(FileLoaderError::IoError(err))
+    }
+}
+
+/// A document loaded from an archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveDocument {
+    /// Path of the archive containing the document
+    pub archive: PathBuf,
+    /// Path of the document within the archive
+    pub path: PathBuf,
+    /// MIME type of the document (see [mime::detect])
+    pub mime: &'static str,
+    /// Text content of the document
+    pub text: String,
+}
+
+/// Extract the text of a file of an archive according to its MIME type. Returns `None` for
+///  files that are skipped: nested archives and unsupported formats (e.g.: images, or pdfs
+///  without the `pdf` feature).
+fn extract(
+    path: &Path,
+    bytes: Vec<u8>,
+) -> Option<(&'static str, Result<String, ArchiveLoaderError>)> {
+    let detected = mime::detect(&bytes, Some(path));
+    let text = match detected {
+        Some(mime::ZIP | mime::TAR | mime::GZIP) => {
+            tracing::warn!(target: "rig", "Skipping nested archive {:?}", path);
+            return None;
+        }
+        #[cfg(feature = "pdf")]
+        Some(mime::PDF) => pdf::text_from_bytes(&bytes).map_err(ArchiveLoaderError::from),
+        #[cfg(feature = "epub")]
+        Some(mime::EPUB) => epub::text_from_bytes(bytes).map_err(ArchiveLoaderError::from),
+        #[cfg(feature = "docx")]
+        Some(mime::DOCX) => DocxDocument::from_bytes(bytes)
+            .map(|document| document.text())
+            .map_err(ArchiveLoaderError::from),
+        Some(mime::EML) => Ok(Email::parse(&bytes).body),
+        // The emails of an mbox archive are loaded as a single document
+        Some(mime::MBOX) => Ok(Email::parse_mbox(&bytes, false)
+            .into_iter()
+            .map(|email| email.body)
+            .collect::<Vec<_>>()
+            .join("\n\n")),
+        Some(mime::HTML) => String::from_utf8(bytes)
+            .map(|html| strip_html(&html))
+            .map_err(ArchiveLoaderError::from),
+        Some(mime::PLAIN_TEXT | mime::MARKDOWN | mime::JSON | mime::CSV | mime::XML) => {
+            String::from_utf8(bytes).map_err(ArchiveLoaderError::from)
+        }
+        // Unknown formats are loaded as text if they are valid UTF-8
+        None => match String::from_utf8(bytes) {
+            Ok(text) => Ok(text),
+            Err(_) => {
+                tracing::warn!(target: "rig", "Skipping {:?} of unknown format", path);
+                return None;
+            }
+        },
+        Some(other) => {
+            tracing::warn!(target: "rig", "Skipping {:?} of unsupported format {}", path, other);
+            return None;
+        }
+    };
+
+    Some((detected.unwrap_or(mime::PLAIN_TEXT), text))
+}
+
+/// A file of an archive, as a `(path, bytes)` pair
+type Entry = Result<(PathBuf, Vec<u8>), ArchiveLoaderError>;
+
+/// Iterate over the files of the zip or tar archive at `archive`. Files are read one at a time
+///  as the iterator is advanced, so that only the file being loaded is held in memory.
+fn entries(archive: &Path) -> Result<Box<dyn Iterator<Item = Entry>>, ArchiveLoaderError> {
+    // The magic bytes of tar archives are at offset 257
+    let mut magic = vec![];
+    File::open(archive)?.take(512).read_to_end(&mut magic)?;
+
+    match mime::detect(&magic, Some(archive)) {
+        Some(mime::ZIP) => Ok(Box::new(zip_entries(
+            archive.to_path_buf(),
+            ZipArchive::new(File::open(archive)?)?,
+        ))),
+        Some(mime::TAR) => Ok(Box::new(tar_entries(File::open(archive)?))),
+        _ => Err(ArchiveLoaderError::UnsupportedArchive(
+            archive.display().to_string(),
+        )),
+    }
+}
+
+fn zip_entries(archive: PathBuf, mut zip: ZipArchive<File>) -> impl Iterator<Item = Entry> {
+    (0..zip.len()).filter_map(move |index| {
+        let mut file = match zip.by_index(index) {
+            Ok(file) => file,
+            Err(e) => return Some(Err(e.into())),
+        };
+        if file.is_dir() {
+            return None;
+        }
+        let Some(path) = file.enclosed_name() else {
+            tracing::warn!(target: "rig", "Skipping unsafe path {:?} in {:?}", file.name(), archive);
+            return None;
+        };
+
+        let mut bytes = vec![];
+        Some(match file.read_to_end(&mut bytes) {
+            Ok(_) => Ok((path, bytes)),
+            Err(e) => Err(e.into()),
+        })
+    })
+}
+
+fn tar_entries(file: File) -> impl Iterator<Item = Entry> {
+    // The entries of a tar archive borrow it, so they are read on a separate thread and handed
+    //  over one at a time: the thread blocks until the previous entry is taken, and stops when
+    //  the iterator is dropped.
+    let (sender, receiver) = mpsc::sync_channel(0);
+    thread::spawn(move || {
+        let mut tar = tar::Archive::new(file);
+        let entries = match tar.entries() {
+            Ok(entries) => entries,
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        };
+
+        for entry in entries {
+            let entry = entry.and_then(|mut entry| {
+                if !entry.header().entry_type().is_file() {
+                    return Ok(None);
+                }
+                let path = entry.path()?.into_owned();
+
+                let mut bytes = vec![];
+                entry.read_to_end(&mut bytes)?;
+                Ok(Some((path, bytes)))
+            });
+
+            let entry = match entry {
+                Ok(Some(entry)) => Ok(entry),
+                Ok(None) => continue,
+                Err(e) => Err(e),
+            };
+            // A corrupted tar archive cannot be read past the failing entry
+            let failed = entry.is_err();
+            if sender.send(entry).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    receiver
+        .into_iter()
+        .map(|entry| entry.map_err(ArchiveLoaderError::from))
+}
+
+// ================================================================
+// ArchiveLoader definitions and implementations
+// ================================================================
+
+/// [ArchiveLoader] is a utility for loading the documents contained in zip and tar archives.
+///  Each file of an archive is dispatched according to its MIME type (see [mime::detect]):
+///  text files are loaded as is, html files and emails (`.eml` and `.mbox`) are converted to
+///  text, as are pdfs, epubs and docx documents (with the `pdf`, `epub` and `docx` features
+///  respectively). Nested archives and unsupported formats are skipped with a warning.
+///
+/// The files of an archive are read lazily, one at a time, as the iterator is advanced.
+///
+/// # Example
+/// ```rust
+/// use rig::loaders::ArchiveLoader;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     for document in ArchiveLoader::with_glob("corpora/*.zip")?.read() {
+///         let document = document?;
+///         println!("{:?} ({}): {}", document.path, document.mime, document.text);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub struct ArchiveLoader<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a> ArchiveLoader<'a, Result<PathBuf, ArchiveLoaderError>> {
+    /// Reads the documents of the archives within the iterator returned by
+    ///  [ArchiveLoader::with_glob] or [ArchiveLoader::with_dir], flattened as a single iterator.
+    ///  Errors reading an archive are returned in place of its documents.
+    ///
+    /// # Example
+    /// Read the documents of the archives in directory "corpora".
+    ///
+    /// ```rust
+    /// let documents = ArchiveLoader::with_dir("corpora")?.read().into_iter();
+    /// for result in documents {
+    ///     match result {
+    ///         Ok(document) => println!("{:?}: {}", document.path, document.text),
+    ///         Err(e) => eprintln!("Error reading archive: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read(self) -> ArchiveLoader<'a, Result<ArchiveDocument, ArchiveLoaderError>> {
+        ArchiveLoader {
+            iterator: Box::new(self.iterator.flat_map(
+                |res| -> Box<dyn Iterator<Item = Result<ArchiveDocument, ArchiveLoaderError>>> {
+                    let archive = match res {
+                        Ok(archive) => archive,
+                        Err(e) => return Box::new(iter::once(Err(e))),
+                    };
+                    match entries(&archive) {
+                        Ok(entries) => Box::new(entries.filter_map(move |entry| {
+                            let (path, bytes) = match entry {
+                                Ok(entry) => entry,
+                                Err(e) => return Some(Err(e)),
+                            };
+                            let (mime, text) = extract(&path, bytes)?;
+                            Some(text.map(|text| ArchiveDocument {
+                                archive: archive.clone(),
+                                path,
+                                mime,
+                                text,
+                            }))
+                        })),
+                        Err(e) => Box::new(iter::once(Err(e))),
+                    }
+                },
+            )),
+        }
+    }
+}
+
+impl<'a> ArchiveLoader<'a, Result<ArchiveDocument, ArchiveLoaderError>> {
+    /// Converts the documents read from the archives into [Document]s, which can be embedded
+    ///  directly with an [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder). The id of
+    ///  each document is `<archive path>#<path within the archive>` (e.g.:
+    ///  `corpora/docs.zip#flurbo.txt`) and its MIME type is stored in the `mime` field.
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = ArchiveLoader::with_glob("corpora/*.zip")?
+    ///     .read()
+    ///     .into_documents()
+    ///     .ignore_errors();
+    /// ```
+    pub fn into_documents(self) -> ArchiveLoader<'a, Result<Document, ArchiveLoaderError>> {
+        ArchiveLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let document = res?;
+                Ok(Document {
+                    id: format!("{}#{}", document.archive.display(), document.path.display()),
+                    text: document.text,
+                    additional_props: HashMap::from([(
+                        "mime".to_string(),
+                        document.mime.to_string(),
+                    )]),
+                })
+            })),
+        }
+    }
+}
+
+impl<'a, T: 'a> ArchiveLoader<'a, Result<T, ArchiveLoaderError>> {
+    /// Ignores errors in the iterator, returning only successful results. This can be used on any
+    ///  [ArchiveLoader] state of iterator whose items are results.
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = ArchiveLoader::with_glob("corpora/*.zip")?.read().ignore_errors();
+    /// ```
+    pub fn ignore_errors(self) -> ArchiveLoader<'a, T> {
+        ArchiveLoader {
+            iterator: Box::new(self.iterator.filter_map(|res| res.ok())),
+        }
+    }
+}
+
+impl ArchiveLoader<'_, Result<PathBuf, ArchiveLoaderError>> {
+    /// Creates a new [ArchiveLoader] using a glob pattern to match archives.
+    ///
+    /// # Example
+    /// Create a [ArchiveLoader] for all `.zip` files that match the glob "corpora/*.zip".
+    ///
+    /// ```rust
+    /// let loader = ArchiveLoader::with_glob("corpora/*.zip")?;
+    /// ```
+    pub fn with_glob(
+        pattern: &str,
+    ) -> Result<ArchiveLoader<Result<PathBuf, ArchiveLoaderError>>, ArchiveLoaderError> {
+        let paths = glob(pattern).map_err(FileLoaderError::PatternError)?;
+        Ok(ArchiveLoader {
+            iterator: Box::new(paths.into_iter().map(|path| {
+                path.map_err(FileLoaderError::GlobError)
+                    .map_err(ArchiveLoaderError::FileLoaderError)
+            })),
+        })
+    }
+
+    /// Creates a new [ArchiveLoader] on all files within a directory.
+    ///
+    /// # Example
+    /// ```rust
+    /// let loader = ArchiveLoader::with_dir("corpora")?;
+    /// ```
+    pub fn with_dir(
+        directory: &str,
+    ) -> Result<ArchiveLoader<Result<PathBuf, ArchiveLoaderError>>, ArchiveLoaderError> {
+        Ok(ArchiveLoader {
+            iterator: Box::new(fs::read_dir(directory)?.map(|entry| Ok(entry?.path()))),
+        })
+    }
+}
+
+#[cfg(feature = "language")]
+super::language::language_adaptors!(ArchiveLoader);
+
+// ================================================================
+// ArchiveLoader iterator implementations
+// ================================================================
+
+pub struct IntoIter<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a, T> IntoIterator for ArchiveLoader<'a, T> {
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iterator: self.iterator,
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iterator.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::PathBuf};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::{ArchiveLoader, ArchiveLoaderError};
+    use crate::loaders::mime;
+
+    fn zip(path: &std::path::Path, files: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(std::fs::File::create(path).unwrap());
+        for (name, bytes) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_archive_loader_zip() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let pdf = std::fs::read("tests/data/dummy.pdf").unwrap();
+        zip(&temp.path().join("nested.zip"), &[("inner.txt", b"Inner")]);
+        let nested = std::fs::read(temp.path().join("nested.zip")).unwrap();
+
+        zip(
+            &temp.path().join("corpus.zip"),
+            &[
+                ("notes/flurbos.txt", b"Flurbos are a made up currency."),
+                ("docs/dummy.pdf", &pdf),
+                ("nested.zip", &nested),
+                ("pixel.png", &std::fs::read("tests/data/pixel.png").unwrap()),
+            ],
+        );
+
+        let documents = ArchiveLoader::with_glob(&format!("{}/corpus.zip", temp.path().display()))
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // The nested archive and the image are skipped
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].path, PathBuf::from("notes/flurbos.txt"));
+        assert_eq!(documents[0].mime, mime::PLAIN_TEXT);
+        assert_eq!(documents[0].text, "Flurbos are a made up currency.");
+        assert_eq!(documents[1].path, PathBuf::from("docs/dummy.pdf"));
+        assert_eq!(documents[1].mime, mime::PDF);
+        assert_eq!(documents[1].text, "Test\nPDF\nDocument\n");
+        assert!(documents[1].archive.ends_with("corpus.zip"));
+    }
+
+    #[test]
+    fn test_archive_loader_tar() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let mut tar =
+            tar::Builder::new(std::fs::File::create(temp.path().join("site.tar")).unwrap());
+        let html = b"<html><body><h1>Flurbos</h1><p>Made <b>up</b></p></body></html>";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(html.len() as u64);
+        header.set_cksum();
+        tar.append_data(&mut header, "site/index.html", &html[..])
+            .unwrap();
+        tar.append_path_with_name("tests/data/ticket.eml", "tickets/ticket.eml")
+            .unwrap();
+        #[cfg(feature = "docx")]
+        tar.append_path_with_name("tests/data/paragraphs.docx", "docs/paragraphs.docx")
+            .unwrap();
+        tar.finish().unwrap();
+
+        let documents = ArchiveLoader::with_glob(&format!("{}/site.tar", temp.path().display()))
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // Html markup is stripped and emails are loaded as their body
+        assert_eq!(documents[0].path, PathBuf::from("site/index.html"));
+        assert_eq!(documents[0].mime, mime::HTML);
+        assert_eq!(documents[0].text, "Flurbos\nMade up");
+        assert_eq!(documents[1].path, PathBuf::from("tickets/ticket.eml"));
+        assert_eq!(documents[1].mime, mime::EML);
+        assert_eq!(
+            documents[1].text,
+            "My flurbo order never arrived. Can I get a refund? Costs 5 €."
+        );
+
+        #[cfg(feature = "docx")]
+        {
+            assert_eq!(documents[2].mime, mime::DOCX);
+            assert!(documents[2].text.starts_with("The Flurbo\n"));
+        }
+        #[cfg(not(feature = "docx"))]
+        assert_eq!(documents.len(), 2);
+    }
+
+    #[test]
+    fn test_archive_loader_unsupported() {
+        let results = ArchiveLoader::with_glob("tests/data/hyphenated.txt")
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        assert!(matches!(
+            results[..],
+            [Err(ArchiveLoaderError::UnsupportedArchive(_))]
+        ));
+    }
+}
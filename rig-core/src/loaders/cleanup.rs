@@ -0,0 +1,221 @@
+//! The module defines the [TextCleanup] post-processor, which cleans up the text extracted by
+//! loaders (e.g.: line-break hyphenation and irregular whitespace of PDF-extracted text) before
+//! it is embedded.
+//!
+//! Every loader provides a `clean` method applying a [TextCleanup] to the text of its items:
+//! ```rust
+//! use rig::loaders::{cleanup::TextCleanup, PdfFileLoader};
+//!
+//! let pages = PdfFileLoader::with_glob("tests/data/*.pdf")?
+//!     .read()
+//!     .clean(TextCleanup::default().normalize_unicode(false));
+//! ```
+use unicode_normalization::UnicodeNormalization;
+
+/// Configurable text cleanup pass. All transforms are enabled by default and are applied in the
+/// following order:
+/// 1. Unicode normalization (NFKC), e.g.: ligatures such as `ﬁ` become `fi` and non-breaking
+///    spaces become regular spaces.
+/// 2. De-hyphenation: words hyphenated across a line break (e.g.: `"exam-\nple"`) are rejoined
+///    when the hyphen follows a letter and the next line starts with a lowercase letter. Soft
+///    hyphens (`U+00AD`) are removed.
+/// 3. Whitespace normalization: runs of whitespace are collapsed into a single space, except
+///    runs spanning several line breaks which are collapsed into a paragraph break (`"\n\n"`).
+///    Leading and trailing whitespace is removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextCleanup {
+    normalize_unicode: bool,
+    dehyphenate: bool,
+    collapse_whitespace: bool,
+}
+
+impl Default for TextCleanup {
+    fn default() -> Self {
+        Self {
+            normalize_unicode: true,
+            dehyphenate: true,
+            collapse_whitespace: true,
+        }
+    }
+}
+
+impl TextCleanup {
+    /// Enable or disable the unicode normalization (NFKC) of the text.
+    pub fn normalize_unicode(mut self, enabled: bool) -> Self {
+        self.normalize_unicode = enabled;
+        self
+    }
+
+    /// Enable or disable the rejoining of words hyphenated across line breaks.
+    pub fn dehyphenate(mut self, enabled: bool) -> Self {
+        self.dehyphenate = enabled;
+        self
+    }
+
+    /// Enable or disable the collapsing of runs of whitespace.
+    pub fn collapse_whitespace(mut self, enabled: bool) -> Self {
+        self.collapse_whitespace = enabled;
+        self
+    }
+
+    /// Apply the enabled transforms to `text`.
+    pub fn clean(&self, text: &str) -> String {
+        let mut text = if self.normalize_unicode {
+            text.nfkc().collect()
+        } else {
+            text.to_string()
+        };
+        if self.dehyphenate {
+            text = dehyphenate(&text);
+        }
+        if self.collapse_whitespace {
+            text = collapse_whitespace(&text);
+        }
+        text
+    }
+}
+
+fn dehyphenate(text: &str) -> String {
+    let text = text.replace('\u{ad}', "");
+    let mut result = String::with_capacity(text.len());
+
+    let mut rest = text.as_str();
+    while let Some(index) = rest.find('-') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+
+        // Text of the next line if the hyphen ends the current line
+        let next_line = after
+            .trim_start_matches([' ', '\t', '\r'])
+            .strip_prefix('\n')
+            .map(|line| line.trim_start_matches([' ', '\t']));
+
+        match next_line {
+            Some(next_line)
+                if result.chars().next_back().is_some_and(char::is_alphabetic)
+                    && next_line.starts_with(char::is_lowercase) =>
+            {
+                rest = next_line;
+            }
+            _ => {
+                result.push('-');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+
+    // Number of line breaks of the current run of whitespace, if any
+    let mut line_breaks = None;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            *line_breaks.get_or_insert(0) += (c == '\n') as usize;
+            continue;
+        }
+        if let Some(line_breaks) = line_breaks.take() {
+            if !result.is_empty() {
+                result.push_str(if line_breaks >= 2 { "\n\n" } else { " " });
+            }
+        }
+        result.push(c);
+    }
+
+    result
+}
+
+// ================================================================
+// Implementing Cleanable trait for the items of the loaders
+// ================================================================
+
+/// Items of the loaders whose text can be cleaned with a [TextCleanup]. Only the contents are
+/// cleaned: paths, page numbers, chapter titles and errors are kept as is.
+pub trait Cleanable {
+    fn clean(self, cleanup: &TextCleanup) -> Self;
+}
+
+impl Cleanable for String {
+    fn clean(self, cleanup: &TextCleanup) -> Self {
+        cleanup.clean(&self)
+    }
+}
+
+impl<K, T: Cleanable> Cleanable for (K, T) {
+    fn clean(self, cleanup: &TextCleanup) -> Self {
+        (self.0, self.1.clean(cleanup))
+    }
+}
+
+impl<T: Cleanable> Cleanable for Vec<T> {
+    fn clean(self, cleanup: &TextCleanup) -> Self {
+        self.into_iter().map(|item| item.clean(cleanup)).collect()
+    }
+}
+
+impl<T: Cleanable, E> Cleanable for Result<T, E> {
+    fn clean(self, cleanup: &TextCleanup) -> Self {
+        self.map(|item| item.clean(cleanup))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextCleanup;
+    use crate::loaders::FileLoader;
+
+    const FIXTURE: &str = include_str!("../../tests/data/hyphenated.txt");
+
+    #[test]
+    fn test_clean() {
+        assert_eq!(
+            TextCleanup::default().clean(FIXTURE),
+            "The example shows how PDF text extraction breaks lines.\n\n\
+            A new paragraph with the final demo of well-known hyphenated words, such as \
+            cooperate. A re- Used word."
+        );
+    }
+
+    #[test]
+    fn test_toggle_transforms() {
+        let cleanup = TextCleanup::default();
+
+        let text = cleanup.normalize_unicode(false).clean(FIXTURE);
+        assert!(text.contains("the \u{fb01}nal \u{ff44}\u{ff45}\u{ff4d}\u{ff4f} of"));
+
+        let text = cleanup.dehyphenate(false).clean(FIXTURE);
+        assert!(text.starts_with("The exam- ple shows"));
+        assert!(text.contains("co\u{ad}operate"));
+
+        let text = cleanup.collapse_whitespace(false).clean(FIXTURE);
+        assert!(text.starts_with("  The example shows how   PDF text\textraction\n  breaks"));
+        assert!(text.contains("A new paragraph with"));
+
+        let text = cleanup
+            .normalize_unicode(false)
+            .dehyphenate(false)
+            .collapse_whitespace(false)
+            .clean(FIXTURE);
+        assert_eq!(text, FIXTURE);
+    }
+
+    #[test]
+    fn test_clean_loader() {
+        let contents = FileLoader::with_glob("tests/data/hyphenated.txt")
+            .unwrap()
+            .read_with_path()
+            .clean(TextCleanup::default().dehyphenate(false))
+            .ignore_errors()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        assert_eq!(contents.len(), 1);
+        let (path, content) = &contents[0];
+        assert!(path.ends_with("hyphenated.txt"));
+        assert!(content.starts_with("The exam- ple shows how PDF text extraction"));
+    }
+}
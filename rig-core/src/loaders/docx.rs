@@ -0,0 +1,687 @@
+//! This module provides loading of docx documents (Office Open XML word processing documents).
+//!
+//! Only the text of the body of documents is extracted: the main document part is read from the
+//! archive with the `zip` crate (already used by the epub loader), and its text runs are scanned
+//! by a small WordprocessingML scanner rather than an XML or docx crate. Extracting text needs
+//! neither the styles, numbering nor layout a full docx model parses, and the scanner keeps the
+//! `docx` feature to a single dependency. The scanner handles entities, tabs and line breaks,
+//! tables (nested or not) and text boxes, whose text is only taken from their preferred
+//! (`mc:Choice`) representation.
+
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::PathBuf,
+};
+
+use glob::glob;
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+use super::{
+    cleanup::{Cleanable, TextCleanup},
+    file::{FileLoaderError, WalkDir},
+    html::decode_entities,
+    mime,
+};
+
+#[derive(Error, Debug)]
+pub enum DocxLoaderError {
+    #[error("{0}")]
+    FileLoaderError(#[from] FileLoaderError),
+
+    #[error("UTF-8 conversion error: {0}")]
+    FromUtf8Error(#[from] std::string::FromUtf8Error),
+
+    #[error("Archive error: {0}")]
+    ArchiveError(#[from] ZipError),
+
+    /// The document is password-protected (encrypted documents are not zip archives but OLE
+    /// compound files, like legacy `.doc` documents, which are not supported either)
+    #[error("Password-protected (or legacy .doc) document")]
+    Encrypted,
+
+    /// The document contains macros (e.g.: a `.docm` document)
+    #[error("Macro-enabled document")]
+    MacroEnabled,
+
+    /// The archive has no main document part (i.e.: it is not a docx document)
+    #[error("Missing part: {0}")]
+    MissingPart(String),
+}
+
+/// Path of the main document part (holding the body of the document) within a docx archive
+const DOCUMENT_PART: &str = "word/document.xml";
+
+/// Path of the part holding the VBA macros of a macro-enabled document
+const MACROS_PART: &str = "word/vbaProject.bin";
+
+/// Magic bytes of OLE compound files, the container of password-protected documents
+const OLE_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// A loaded docx document: the text of the paragraphs of its body, in order. Paragraphs
+///  without text (e.g.: blank lines) are skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DocxDocument {
+    pub paragraphs: Vec<String>,
+}
+
+impl DocxDocument {
+    /// Parse a docx document from its bytes (e.g.: a document within an archive).
+    /// Password-protected documents are [DocxLoaderError::Encrypted] errors, and macro-enabled
+    ///  documents [DocxLoaderError::MacroEnabled] errors.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, DocxLoaderError> {
+        if bytes.starts_with(&OLE_MAGIC) {
+            return Err(DocxLoaderError::Encrypted);
+        }
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        if archive.file_names().any(|name| name == MACROS_PART) {
+            return Err(DocxLoaderError::MacroEnabled);
+        }
+
+        let mut xml = Vec::new();
+        match archive.by_name(DOCUMENT_PART) {
+            Ok(mut part) => part
+                .read_to_end(&mut xml)
+                .map_err(FileLoaderError::IoError)?,
+            Err(ZipError::FileNotFound) => {
+                return Err(DocxLoaderError::MissingPart(DOCUMENT_PART.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            paragraphs: paragraphs(&String::from_utf8(xml)?),
+        })
+    }
+
+    /// The text of the document: its paragraphs joined by newlines. A document without
+    ///  paragraphs has an empty text.
+    pub fn text(&self) -> String {
+        self.paragraphs.join("\n")
+    }
+}
+
+// ================================================================
+// Docx text extraction helpers
+// ================================================================
+
+/// Extract the text of the paragraphs (`w:p` elements) of a WordprocessingML document part.
+///  The text of a paragraph is the text of its runs (`w:t` elements, i.e.: excluding deleted
+///  text and field instructions), with tabs and line breaks preserved. Nested paragraphs
+///  (e.g.: of text boxes) are extracted before the paragraph containing them, and fallback
+///  content of markup compatibility blocks (`mc:Fallback` elements, e.g.: the legacy copy of a
+///  text box) is skipped.
+fn paragraphs(xml: &str) -> Vec<String> {
+    let mut paragraphs = vec![];
+    let mut open: Vec<String> = vec![];
+    let mut in_text = false;
+    // Depth of the `mc:Fallback` elements and of the `w:tabs` elements (tab stops definitions,
+    //  whose `w:tab` are not tabs) around the current tag
+    let mut fallback = 0usize;
+    let mut in_tabs = false;
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        if in_text && fallback == 0 {
+            if let Some(paragraph) = open.last_mut() {
+                paragraph.push_str(&decode_entities(&rest[..start]));
+            }
+        }
+
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let self_closing = tag.ends_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+
+        match (name, closing) {
+            ("mc:Fallback", false) if !self_closing => fallback += 1,
+            ("mc:Fallback", true) => fallback = fallback.saturating_sub(1),
+            _ if fallback > 0 => {}
+            ("w:tabs", false) => in_tabs = !self_closing,
+            ("w:tabs", true) => in_tabs = false,
+            ("w:p", false) if !self_closing => open.push(String::new()),
+            ("w:p", true) => {
+                if let Some(paragraph) = open.pop().filter(|p| !p.trim().is_empty()) {
+                    paragraphs.push(paragraph);
+                }
+            }
+            ("w:t", false) => in_text = !self_closing,
+            ("w:t", true) => in_text = false,
+            ("w:tab" | "w:br" | "w:cr", false) if !in_tabs => {
+                if let Some(paragraph) = open.last_mut() {
+                    paragraph.push(if name == "w:tab" { '\t' } else { '\n' });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    paragraphs
+}
+
+fn is_docx(path: &Result<PathBuf, DocxLoaderError>) -> bool {
+    match path {
+        Ok(path) => mime::from_extension(path) == Some(mime::DOCX),
+        Err(_) => true,
+    }
+}
+
+// ================================================================
+// Implementing Loadable trait for loading docx documents
+// ================================================================
+
+pub(crate) trait Loadable {
+    fn load(self) -> Result<DocxDocument, DocxLoaderError>;
+    fn load_with_path(self) -> Result<(PathBuf, DocxDocument), DocxLoaderError>;
+}
+
+impl Loadable for PathBuf {
+    fn load(self) -> Result<DocxDocument, DocxLoaderError> {
+        DocxDocument::from_bytes(fs::read(self).map_err(FileLoaderError::IoError)?)
+    }
+    fn load_with_path(self) -> Result<(PathBuf, DocxDocument), DocxLoaderError> {
+        let contents = self.clone().load()?;
+        Ok((self, contents))
+    }
+}
+
+impl<T: Loadable> Loadable for Result<T, DocxLoaderError> {
+    fn load(self) -> Result<DocxDocument, DocxLoaderError> {
+        self.map(|t| t.load())?
+    }
+    fn load_with_path(self) -> Result<(PathBuf, DocxDocument), DocxLoaderError> {
+        self.map(|t| t.load_with_path())?
+    }
+}
+
+// ================================================================
+// DocxFileLoader definitions and implementations
+// ================================================================
+
+/// [DocxFileLoader] is a utility for loading docx files from the filesystem using glob patterns
+///  or directory paths. It provides methods to read the text of the documents, split them into
+///  paragraphs and handle errors gracefully.
+///
+/// # Errors
+///
+/// This module defines a custom error type [DocxLoaderError] which can represent various errors
+///  that might occur during file loading operations, such as any [FileLoaderError] alongside
+///  specific docx-related errors (e.g.: password-protected or macro-enabled documents).
+///
+/// # Example Usage
+///
+/// ```rust
+/// use rig::loaders::DocxFileLoader;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     // Create a DocxFileLoader using a glob pattern
+///     let loader = DocxFileLoader::with_glob("tests/data/*.docx")?;
+///
+///     // Load the paragraphs of the documents, ignoring any errors
+///     let paragraphs: Vec<String> = loader
+///         .load()
+///         .by_paragraph()
+///         .ignore_errors()
+///         .into_iter()
+///         .collect();
+///
+///     for paragraph in paragraphs {
+///         println!("{}", paragraph);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [DocxFileLoader] uses strict typing between the iterator methods to ensure that transitions
+///  between different implementations of the loaders and it's methods are handled properly by
+///  the compiler.
+pub struct DocxFileLoader<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a> DocxFileLoader<'a, Result<PathBuf, DocxLoaderError>> {
+    /// Directly reads the contents of the docx files within the iterator returned by
+    ///  [DocxFileLoader::with_glob] or [DocxFileLoader::with_dir]: the text of the body of each
+    ///  document, with its paragraphs joined by newlines.
+    ///
+    /// # Example
+    /// Read docx files in directory "tests/data/*.docx" and return the contents of the documents.
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?.read().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(content) => println!("{}", content),
+    ///         Err(e) => eprintln!("Error reading docx: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read(self) -> DocxFileLoader<'a, Result<String, DocxLoaderError>> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.map(|res| Ok(res.load()?.text()))),
+        }
+    }
+
+    /// Directly reads the contents of the docx files within the iterator returned by
+    ///  [DocxFileLoader::with_glob] or [DocxFileLoader::with_dir] and returns the path along
+    ///  with the content (see [DocxFileLoader::read]).
+    ///
+    /// # Example
+    /// Read docx files in directory "tests/data/*.docx" and return the content and paths of the documents.
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?.read_with_path().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, content)) => println!("{:?} {}", path, content),
+    ///         Err(e) => eprintln!("Error reading docx: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_with_path(self) -> DocxFileLoader<'a, Result<(PathBuf, String), DocxLoaderError>> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let (path, doc) = res.load_with_path()?;
+                Ok((path, doc.text()))
+            })),
+        }
+    }
+
+    /// Loads the contents of the docx files within the iterator returned by
+    ///  [DocxFileLoader::with_glob] or [DocxFileLoader::with_dir]. Loaded documents are
+    ///  [DocxDocument]s that can be further processed (by paragraph, etc).
+    ///
+    /// # Example
+    /// Load docx files in directory "tests/data/*.docx" and return the loaded documents
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?.load().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(doc) => println!("{} paragraphs", doc.paragraphs.len()),
+    ///         Err(e) => eprintln!("Error reading docx: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn load(self) -> DocxFileLoader<'a, Result<DocxDocument, DocxLoaderError>> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.map(|res| res.load())),
+        }
+    }
+
+    /// Loads the contents of the docx files within the iterator returned by
+    ///  [DocxFileLoader::with_glob] or [DocxFileLoader::with_dir]. Loaded documents are
+    ///  [DocxDocument]s with their path that can be further processed.
+    ///
+    /// # Example
+    /// Load docx files in directory "tests/data/*.docx" and return the loaded documents
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?.load_with_path().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, doc)) => println!("{:?} {} paragraphs", path, doc.paragraphs.len()),
+    ///         Err(e) => eprintln!("Error reading docx: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn load_with_path(
+        self,
+    ) -> DocxFileLoader<'a, Result<(PathBuf, DocxDocument), DocxLoaderError>> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.map(|res| res.load_with_path())),
+        }
+    }
+}
+
+impl<'a> DocxFileLoader<'a, Result<DocxDocument, DocxLoaderError>> {
+    /// Splits the loaded documents into their paragraphs, flattened as a single iterator.
+    ///
+    /// # Example
+    /// Load docx files in directory "tests/data/*.docx" and chunk them into paragraphs.
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?.load().by_paragraph().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(paragraph) => println!("{}", paragraph),
+    ///         Err(e) => eprintln!("Error reading docx: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn by_paragraph(self) -> DocxFileLoader<'a, Result<String, DocxLoaderError>> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|res| match res {
+                Ok(doc) => doc.paragraphs.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            })),
+        }
+    }
+}
+
+impl<'a> DocxFileLoader<'a, Result<(PathBuf, DocxDocument), DocxLoaderError>> {
+    /// Splits the loaded documents into their paragraphs, flattened as a single iterator, each
+    ///  paragraph along with the path of its document.
+    ///
+    /// # Example
+    /// Load docx files in directory "tests/data/*.docx" and chunk them into paragraphs.
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?
+    ///     .load_with_path()
+    ///     .by_paragraph_with_path()
+    ///     .into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, paragraph)) => println!("{:?} {}", path, paragraph),
+    ///         Err(e) => eprintln!("Error reading docx: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn by_paragraph_with_path(
+        self,
+    ) -> DocxFileLoader<'a, Result<(PathBuf, String), DocxLoaderError>> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|res| {
+                match res {
+                    Ok((path, doc)) => doc
+                        .paragraphs
+                        .into_iter()
+                        .map(|paragraph| Ok((path.clone(), paragraph)))
+                        .collect(),
+                    Err(e) => vec![Err(e)],
+                }
+            })),
+        }
+    }
+}
+
+impl<'a, T: 'a> DocxFileLoader<'a, Result<T, DocxLoaderError>> {
+    /// Ignores errors in the iterator, returning only successful results. This can be used on any
+    ///  [DocxFileLoader] state of iterator whose items are results.
+    ///
+    /// # Example
+    /// Read files in directory "tests/data/*.docx" and ignore errors from unreadable files.
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?.read().ignore_errors().into_iter();
+    /// for result in content {
+    ///     println!("{}", content)
+    /// }
+    /// ```
+    pub fn ignore_errors(self) -> DocxFileLoader<'a, T> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.filter_map(|res| res.ok())),
+        }
+    }
+}
+
+impl<'a, T: Cleanable + 'a> DocxFileLoader<'a, T> {
+    /// Cleans the text of the documents (or of their paragraphs) with the given [TextCleanup]
+    ///  (e.g.: collapsing runs of whitespace and normalizing unicode).
+    ///
+    /// # Example
+    /// Read the paragraphs of the docx files in directory "tests/data/*.docx" and clean their text.
+    ///
+    /// ```rust
+    /// let content = DocxFileLoader::with_glob("tests/data/*.docx")?
+    ///     .load()
+    ///     .by_paragraph()
+    ///     .clean(TextCleanup::default());
+    /// ```
+    pub fn clean(self, cleanup: TextCleanup) -> DocxFileLoader<'a, T> {
+        DocxFileLoader {
+            iterator: Box::new(self.iterator.map(move |item| item.clean(&cleanup))),
+        }
+    }
+}
+
+impl DocxFileLoader<'_, Result<PathBuf, FileLoaderError>> {
+    /// Creates a new [DocxFileLoader] using a glob pattern to match files.
+    ///
+    /// # Example
+    /// Create a [DocxFileLoader] for all `.docx` files that match the glob "tests/data/*.docx".
+    ///
+    /// ```rust
+    /// let loader = DocxFileLoader::with_glob("tests/data/*.docx")?;
+    /// ```
+    pub fn with_glob(
+        pattern: &str,
+    ) -> Result<DocxFileLoader<Result<PathBuf, DocxLoaderError>>, DocxLoaderError> {
+        let paths = glob(pattern).map_err(FileLoaderError::PatternError)?;
+        Ok(DocxFileLoader {
+            iterator: Box::new(paths.into_iter().map(|path| {
+                path.map_err(FileLoaderError::GlobError)
+                    .map_err(DocxLoaderError::FileLoaderError)
+            })),
+        })
+    }
+
+    /// Creates a new [DocxFileLoader] on the docx files (i.e.: with a `.docx` extension) within
+    ///  a directory.
+    ///
+    /// # Example
+    /// Create a [DocxFileLoader] for all docx files that are in the directory "files".
+    ///
+    /// ```rust
+    /// let loader = DocxFileLoader::with_dir("files")?;
+    /// ```
+    pub fn with_dir(
+        directory: &str,
+    ) -> Result<DocxFileLoader<Result<PathBuf, DocxLoaderError>>, DocxLoaderError> {
+        Ok(DocxFileLoader {
+            iterator: Box::new(
+                fs::read_dir(directory)
+                    .map_err(FileLoaderError::IoError)?
+                    .map(|entry| Ok(entry.map_err(FileLoaderError::IoError)?.path()))
+                    .filter(is_docx),
+            ),
+        })
+    }
+
+    /// Creates a new [DocxFileLoader] on the docx files within a directory and its
+    ///  subdirectories, at any depth. Symlinks are followed, and directories that were already
+    ///  visited (i.e.: symlink loops) are skipped.
+    ///
+    /// # Example
+    /// Create a [DocxFileLoader] for all docx files that are in the directory "docs" or its subdirectories.
+    ///
+    /// ```rust
+    /// let loader = DocxFileLoader::with_dir_recursive("docs")?;
+    /// ```
+    pub fn with_dir_recursive(
+        directory: &str,
+    ) -> Result<DocxFileLoader<Result<PathBuf, DocxLoaderError>>, DocxLoaderError> {
+        Ok(DocxFileLoader {
+            iterator: Box::new(
+                WalkDir::new(directory)?
+                    .map(|path| path.map_err(DocxLoaderError::FileLoaderError))
+                    .filter(is_docx),
+            ),
+        })
+    }
+}
+
+// ================================================================
+// DocxFileLoader iterator implementations
+// ================================================================
+
+pub struct IntoIter<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a, T> IntoIterator for DocxFileLoader<'a, T> {
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iterator: self.iterator,
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iterator.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{paragraphs, DocxFileLoader, DocxLoaderError};
+
+    /// A document part with the body `body`
+    fn document(body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>{body}</w:body></w:document>"#
+        )
+    }
+
+    #[test]
+    fn test_docx_loader_read() {
+        let documents = DocxFileLoader::with_glob("tests/data/*.docx")
+            .unwrap()
+            .read_with_path()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let names = documents
+            .iter()
+            .map(|result| match result {
+                Ok((path, _)) => path.file_name().unwrap().to_str().unwrap().to_string(),
+                Err(e) => e.to_string(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "empty.docx",
+                "Password-protected (or legacy .doc) document",
+                "paragraphs.docx"
+            ]
+        );
+
+        // Empty documents have an empty text
+        assert!(matches!(&documents[0], Ok((_, text)) if text.is_empty()));
+        assert!(matches!(&documents[1], Err(DocxLoaderError::Encrypted)));
+
+        // Deleted text and blank paragraphs are left out
+        assert_eq!(
+            documents[2].as_ref().unwrap().1,
+            "The Flurbo\n\
+            Flurbos are a made up currency & never traded.\n\
+            Glarbs\tare ancient.\nVery ancient."
+        );
+    }
+
+    #[test]
+    fn test_docx_loader_by_paragraph() {
+        let paragraphs = DocxFileLoader::with_glob("tests/data/paragraphs.docx")
+            .unwrap()
+            .load()
+            .by_paragraph()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            paragraphs,
+            vec![
+                "The Flurbo",
+                "Flurbos are a made up currency & never traded.",
+                "Glarbs\tare ancient.\nVery ancient.",
+            ]
+        );
+
+        let paragraphs = DocxFileLoader::with_glob("tests/data/*.docx")
+            .unwrap()
+            .load_with_path()
+            .by_paragraph_with_path()
+            .ignore_errors()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(paragraphs.len(), 3);
+        assert!(paragraphs
+            .iter()
+            .all(|(path, _)| *path == PathBuf::from("tests/data/paragraphs.docx")));
+    }
+
+    #[test]
+    fn test_paragraphs_entities() {
+        let xml = document(
+            r#"<w:p><w:r><w:t xml:space="preserve">Flurbos &amp; glarbs &lt;3 &quot;&#233;&#x263A;&quot; </w:t></w:r><w:r><w:t>&apos;ok&apos;</w:t></w:r></w:p>"#,
+        );
+        assert_eq!(paragraphs(&xml), vec!["Flurbos & glarbs <3 \"é☺\" 'ok'"]);
+    }
+
+    #[test]
+    fn test_paragraphs_tabs_and_breaks() {
+        let xml = document(
+            r#"<w:p><w:pPr><w:tabs><w:tab w:val="left" w:pos="720"/></w:tabs></w:pPr><w:r><w:t>Glarbs</w:t><w:tab/><w:t>are</w:t><w:br/><w:t>ancient</w:t><w:br w:type="page"/><w:t>.</w:t><w:cr/></w:r></w:p><w:p><w:pPr><w:tabs/></w:pPr><w:r><w:tab/><w:t>Indented</w:t></w:r></w:p>"#,
+        );
+        // Tab stops definitions are not tabs
+        assert_eq!(
+            paragraphs(&xml),
+            vec!["Glarbs\tare\nancient\n.\n", "\tIndented"]
+        );
+    }
+
+    #[test]
+    fn test_paragraphs_nested_tables() {
+        let xml = document(
+            r#"<w:tbl><w:tr><w:tc><w:p><w:r><w:t>Outer cell</w:t></w:r></w:p><w:tbl><w:tr><w:tc><w:p><w:r><w:t>Inner cell</w:t></w:r></w:p></w:tc><w:tc><w:p/></w:tc></w:tr></w:tbl><w:p/></w:tc><w:tc><w:p><w:r><w:t>Last cell</w:t></w:r></w:p></w:tc></w:tr></w:tbl><w:p><w:r><w:t>After</w:t></w:r></w:p>"#,
+        );
+        // Paragraphs of (nested) tables are in document order, empty cells are skipped
+        assert_eq!(
+            paragraphs(&xml),
+            vec!["Outer cell", "Inner cell", "Last cell", "After"]
+        );
+    }
+
+    #[test]
+    fn test_paragraphs_text_boxes() {
+        let xml = document(
+            r#"<w:p><w:r><w:t>Before</w:t></w:r><w:r><mc:AlternateContent><mc:Choice Requires="wps"><w:drawing><wps:txbx><w:txbxContent><w:p><w:r><w:t>In the box</w:t></w:r></w:p></w:txbxContent></wps:txbx></w:drawing></mc:Choice><mc:Fallback><w:pict><v:textbox><w:txbxContent><w:p><w:r><w:t>In the box</w:t></w:r></w:p></w:txbxContent></v:textbox></w:pict></mc:Fallback></mc:AlternateContent></w:r><w:r><w:t> after</w:t></w:r></w:p>"#,
+        );
+        // The text box is extracted once, before its paragraph
+        assert_eq!(paragraphs(&xml), vec!["In the box", "Before after"]);
+    }
+
+    #[test]
+    fn test_docx_loader_macro_enabled() {
+        let documents = DocxFileLoader::with_glob("tests/data/*.docm")
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            documents.as_slice(),
+            [Err(DocxLoaderError::MacroEnabled)]
+        ));
+
+        // Macro-enabled documents are not docx files
+        let documents = DocxFileLoader::with_dir("tests/data")
+            .unwrap()
+            .read_with_path()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(documents.len(), 3);
+    }
+}
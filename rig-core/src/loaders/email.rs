@@ -0,0 +1,785 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use glob::glob;
+use thiserror::Error;
+
+use super::{
+    cleanup::{Cleanable, TextCleanup},
+    file::{FileLoaderError, WalkDir},
+    html::strip_html,
+    mime,
+};
+use crate::completion::Document;
+
+#[derive(Error, Debug)]
+pub enum EmailLoaderError {
+    #[error("{0}")]
+    FileLoaderError(#[from] FileLoaderError),
+}
+
+// ================================================================
+// Email parsing
+// ================================================================
+
+/// An email parsed from an `.eml` file or an `.mbox` archive.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Email {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    pub date: Option<String>,
+    /// Text of the email: its `text/plain` parts or, if it has none, the text of its
+    ///  `text/html` parts
+    pub body: String,
+    /// Attachments of the email, only extracted by [Email::parse_with_attachments] (see
+    ///  [EmailFileLoader::read_with_attachments])
+    pub attachments: Vec<Attachment>,
+}
+
+/// Attachment of an [Email], with its decoded content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub filename: Option<String>,
+    /// MIME type of the attachment (e.g.: `application/pdf`)
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Email {
+    /// Parse an email (i.e.: the content of an `.eml` file), skipping its attachments.
+    ///  Parsing is best-effort: malformed emails (e.g.: missing boundaries, invalid encodings)
+    ///  are parsed as far as possible instead of failing.
+    pub fn parse(message: &[u8]) -> Self {
+        parse_message(&String::from_utf8_lossy(message), false)
+    }
+
+    /// Same as [Email::parse], also extracting the attachments of the email.
+    pub fn parse_with_attachments(message: &[u8]) -> Self {
+        parse_message(&String::from_utf8_lossy(message), true)
+    }
+
+    /// Parse the emails of an mbox archive (i.e.: emails separated by `From ` lines), in
+    ///  order. Attachments are extracted if `attachments` is set.
+    pub fn parse_mbox(mbox: &[u8], attachments: bool) -> Vec<Self> {
+        mbox_messages(&String::from_utf8_lossy(mbox))
+            .iter()
+            .map(|message| parse_message(message, attachments))
+            .collect()
+    }
+
+    /// Convert the email into a [Document] with the given id, whose text is the body of the
+    ///  email and whose properties are its headers (i.e.: `from`, `to`, `subject` and `date`,
+    ///  when present).
+    pub fn into_document(self, id: String) -> Document {
+        let additional_props = [
+            ("from", self.from),
+            ("to", self.to),
+            ("subject", self.subject),
+            ("date", self.date),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect();
+
+        Document {
+            id,
+            text: self.body,
+            additional_props,
+        }
+    }
+}
+
+impl Cleanable for Email {
+    fn clean(self, cleanup: &TextCleanup) -> Self {
+        Self {
+            body: cleanup.clean(&self.body),
+            ..self
+        }
+    }
+}
+
+/// Text and attachments collected from the MIME parts of an email
+#[derive(Default)]
+struct Parts {
+    plain: Vec<String>,
+    html: Vec<String>,
+    attachments: Vec<Attachment>,
+}
+
+fn parse_message(message: &str, attachments: bool) -> Email {
+    let message = message.replace("\r\n", "\n");
+    let (headers, _) = split_headers(&message);
+
+    let mut parts = Parts::default();
+    collect_parts(&message, attachments, &mut parts);
+
+    let body = if parts.plain.iter().any(|text| !text.is_empty()) {
+        parts.plain
+    } else {
+        parts.html
+    };
+
+    Email {
+        from: header(&headers, "from").map(decode_words),
+        to: header(&headers, "to").map(decode_words),
+        subject: header(&headers, "subject").map(decode_words),
+        date: header(&headers, "date").map(str::to_string),
+        body: body
+            .into_iter()
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        attachments: parts.attachments,
+    }
+}
+
+/// Collect the text and attachments of a MIME part (or of a whole message), recursing into
+///  multipart parts.
+fn collect_parts(part: &str, attachments: bool, parts: &mut Parts) {
+    let (headers, body) = split_headers(part);
+    let (content_type, params) =
+        parameters(header(&headers, "content-type").unwrap_or("text/plain"));
+
+    if content_type.starts_with("multipart/") {
+        if let Some(boundary) = params.get("boundary") {
+            for part in multipart(body, boundary) {
+                collect_parts(part, attachments, parts);
+            }
+            return;
+        }
+    }
+
+    let (disposition, disposition_params) =
+        parameters(header(&headers, "content-disposition").unwrap_or("inline"));
+    let filename = disposition_params
+        .get("filename")
+        .or(params.get("name"))
+        .map(|filename| decode_words(filename));
+    let encoding = header(&headers, "content-transfer-encoding")
+        .unwrap_or("7bit")
+        .to_ascii_lowercase();
+
+    let is_text = content_type == "text/plain" || content_type == "text/html";
+    if disposition == "attachment" || filename.is_some() || !is_text {
+        if attachments {
+            parts.attachments.push(Attachment {
+                filename,
+                content_type,
+                data: decode_transfer(body, &encoding),
+            });
+        }
+        return;
+    }
+
+    let text = decode_charset(
+        &decode_transfer(body, &encoding),
+        params.get("charset").map(String::as_str),
+    );
+    if content_type == "text/html" {
+        parts.html.push(strip_html(&text));
+    } else {
+        parts.plain.push(text.trim().to_string());
+    }
+}
+
+/// Split a message (or a MIME part) into its headers (with lowercase names and unfolded
+///  values) and its body.
+fn split_headers(message: &str) -> (Vec<(String, String)>, &str) {
+    let (head, body) = match message.strip_prefix('\n') {
+        Some(body) => ("", body),
+        None => message.split_once("\n\n").unwrap_or((message, "")),
+    };
+
+    let mut headers: Vec<(String, String)> = vec![];
+    for line in head.lines() {
+        if line.starts_with([' ', '\t']) {
+            // Folded header: continuation of the previous header
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    (headers, body)
+}
+
+/// Value of the first header `name` (lowercase)
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Parse a header value of the form `value; key=value; key="quoted value"` (e.g.:
+///  `Content-Type`), returning the lowercase value and its parameters (with lowercase keys).
+fn parameters(header: &str) -> (String, HashMap<String, String>) {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    for c in header.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    let mut fields = fields.into_iter();
+    let value = fields
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let params = fields
+        .filter_map(|field| {
+            let (key, value) = field.split_once('=')?;
+            Some((key.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect();
+    (value, params)
+}
+
+/// Parts of a multipart body delimited by `boundary`. The preamble and epilogue of the body
+///  are dropped, and an unterminated last part is kept.
+fn multipart<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{boundary}");
+    let closing = format!("{delimiter}--");
+
+    let mut parts = vec![];
+    let mut start = None;
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == delimiter || trimmed == closing {
+            if let Some(start) = start {
+                parts.push(&body[start..offset]);
+            }
+            if trimmed == closing {
+                return parts;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+/// Decode a body according to its `Content-Transfer-Encoding`. Invalid base64 is kept as is.
+fn decode_transfer(body: &str, encoding: &str) -> Vec<u8> {
+    match encoding {
+        "base64" => {
+            let compact = body
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>();
+            BASE64_STANDARD
+                .decode(compact)
+                .unwrap_or_else(|_| body.as_bytes().to_vec())
+        }
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.as_bytes().to_vec(),
+    }
+}
+
+/// Decode quoted-printable text (i.e.: `=XX` escapes and `=` soft line breaks). Underscores
+///  are decoded as spaces if `underscores` is set (i.e.: in encoded words).
+fn decode_quoted_printable(text: &str, underscores: bool) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'=' if bytes.get(i + 1) == Some(&b'\n') => i += 2,
+            b'=' => {
+                let byte = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                    }
+                    None => {
+                        decoded.push(b'=');
+                        i += 1;
+                    }
+                }
+            }
+            b'_' if underscores => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Decode text in the given charset: ISO-8859-1 is decoded as such and any other charset as
+///  UTF-8 (invalid sequences are replaced).
+fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    match charset.map(str::to_ascii_lowercase).as_deref() {
+        Some("iso-8859-1" | "latin1" | "latin-1") => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Decode the encoded words (i.e.: `=?charset?B|Q?text?=`) of a header value. Whitespace
+///  between adjacent encoded words is dropped.
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match encoded_word(candidate) {
+            Some((text, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    decoded.push_str(before);
+                }
+                decoded.push_str(&text);
+                rest = &candidate[len..];
+                after_word = true;
+            }
+            None => {
+                decoded.push_str(before);
+                decoded.push_str("=?");
+                rest = &candidate[2..];
+                after_word = false;
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decode the encoded word at the start of `text`, returning its text and its length
+fn encoded_word(text: &str) -> Option<(String, usize)> {
+    let (charset, rest) = text.strip_prefix("=?")?.split_once('?')?;
+    let (encoding, rest) = rest.split_once('?')?;
+    let end = rest.find("?=")?;
+    let encoded = &rest[..end];
+
+    let bytes = match encoding {
+        "B" | "b" => BASE64_STANDARD.decode(encoded).ok()?,
+        "Q" | "q" => decode_quoted_printable(encoded, true),
+        _ => return None,
+    };
+    let len = "=?".len() + charset.len() + encoding.len() + "??".len() + end + "?=".len();
+    Some((decode_charset(&bytes, Some(charset)), len))
+}
+
+/// Messages of an mbox archive, without their `From ` separator lines. `>From ` lines quoted
+///  within the messages are unquoted.
+fn mbox_messages(mbox: &str) -> Vec<String> {
+    let mut messages = vec![];
+    let mut message: Option<String> = None;
+    let mut after_blank_line = true;
+
+    for line in mbox.lines() {
+        if after_blank_line && line.starts_with("From ") {
+            messages.extend(message.replace(String::new()));
+        } else if let Some(message) = message.as_mut() {
+            let line = match line.strip_prefix('>') {
+                Some(quoted) if quoted.trim_start_matches('>').starts_with("From ") => quoted,
+                _ => line,
+            };
+            message.push_str(line);
+            message.push('\n');
+        }
+        after_blank_line = line.trim().is_empty();
+    }
+
+    messages.extend(message);
+    messages
+}
+
+/// Emails of the file at `path`: a single email for `.eml` files, or the emails of `.mbox`
+///  archives.
+fn load(path: PathBuf, attachments: bool) -> Result<(PathBuf, Vec<Email>), EmailLoaderError> {
+    let bytes = fs::read(&path).map_err(FileLoaderError::IoError)?;
+    let emails = if is_mbox(&path) {
+        Email::parse_mbox(&bytes, attachments)
+    } else {
+        vec![parse_message(&String::from_utf8_lossy(&bytes), attachments)]
+    };
+    Ok((path, emails))
+}
+
+fn is_mbox(path: &std::path::Path) -> bool {
+    mime::from_extension(path) == Some(mime::MBOX)
+}
+
+/// Whether `result` is the path of an email file (i.e.: an `.eml` or `.mbox` file), to filter
+///  the files of a directory. Errors are kept.
+fn is_email<E>(result: &Result<PathBuf, E>) -> bool {
+    match result {
+        Ok(path) => matches!(mime::from_extension(path), Some(mime::EML | mime::MBOX)),
+        Err(_) => true,
+    }
+}
+
+// ================================================================
+// EmailFileLoader definitions and implementations
+// ================================================================
+
+/// [EmailFileLoader] is a utility for loading emails from `.eml` files and `.mbox` archives
+///  (e.g.: exported support tickets) using glob patterns or directory paths.
+///
+/// The headers of the emails (from, to, subject and date) are parsed along with their body:
+///  the text of their `text/plain` parts or, if they have none, the text of their `text/html`
+///  parts (extracted like [HtmlFileLoader](super::HtmlFileLoader) documents). Multipart emails,
+///  quoted-printable and base64 encodings and encoded header words are decoded. Attachments are
+///  skipped, unless read with [EmailFileLoader::read_with_attachments].
+///
+/// # Errors
+///
+/// This module defines a custom error type [EmailLoaderError] which can represent any
+///  [FileLoaderError] that might occur during file loading operations. Malformed emails are
+///  parsed on a best-effort basis.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use rig::loaders::EmailFileLoader;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     // One document per email, with the headers of the email as properties
+///     let documents = EmailFileLoader::with_dir_recursive("tickets")?
+///         .read_with_path()
+///         .into_documents()
+///         .ignore_errors();
+///
+///     for document in documents {
+///         println!("{}: {}", document.id, document.additional_props["subject"]);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [EmailFileLoader] uses strict typing between the iterator methods to ensure that transitions
+///  between different implementations of the loaders and it's methods are handled properly by
+///  the compiler.
+pub struct EmailFileLoader<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a> EmailFileLoader<'a, Result<PathBuf, EmailLoaderError>> {
+    /// Reads the emails of the files within the iterator returned by
+    ///  [EmailFileLoader::with_glob] or [EmailFileLoader::with_dir] (one per `.eml` file, all
+    ///  the emails of `.mbox` archives), skipping their attachments.
+    ///
+    /// # Example
+    /// ```rust
+    /// let emails = EmailFileLoader::with_glob("tickets/*.eml")?.read().into_iter();
+    /// for result in emails {
+    ///     match result {
+    ///         Ok(emails) => println!("{:?}", emails[0].subject),
+    ///         Err(e) => eprintln!("Error reading emails: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read(self) -> EmailFileLoader<'a, Result<Vec<Email>, EmailLoaderError>> {
+        EmailFileLoader {
+            iterator: Box::new(self.iterator.map(|res| Ok(load(res?, false)?.1))),
+        }
+    }
+
+    /// Same as [EmailFileLoader::read], returning the path of the files along with their
+    ///  emails.
+    ///
+    /// # Example
+    /// ```rust
+    /// let emails = EmailFileLoader::with_glob("tickets/*.mbox")?.read_with_path().into_iter();
+    /// for result in emails {
+    ///     match result {
+    ///         Ok((path, emails)) => println!("{:?}: {} emails", path, emails.len()),
+    ///         Err(e) => eprintln!("Error reading emails: {}", e),
+    ///     }
+    /// }
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn read_with_path(
+        self,
+    ) -> EmailFileLoader<'a, Result<(PathBuf, Vec<Email>), EmailLoaderError>> {
+        EmailFileLoader {
+            iterator: Box::new(self.iterator.map(|res| load(res?, false))),
+        }
+    }
+
+    /// Same as [EmailFileLoader::read_with_path], also extracting the attachments of the
+    ///  emails (see [Email::attachments]).
+    ///
+    /// # Example
+    /// ```rust
+    /// for result in EmailFileLoader::with_glob("tickets/*.eml")?.read_with_attachments() {
+    ///     let (path, emails) = result?;
+    ///     for attachment in &emails[0].attachments {
+    ///         println!("{:?}: {:?} ({})", path, attachment.filename, attachment.content_type);
+    ///     }
+    /// }
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn read_with_attachments(
+        self,
+    ) -> EmailFileLoader<'a, Result<(PathBuf, Vec<Email>), EmailLoaderError>> {
+        EmailFileLoader {
+            iterator: Box::new(self.iterator.map(|res| load(res?, true))),
+        }
+    }
+}
+
+impl<'a> EmailFileLoader<'a, Result<(PathBuf, Vec<Email>), EmailLoaderError>> {
+    /// Converts the emails read by [EmailFileLoader::read_with_path] into [Document]s (see
+    ///  [Email::into_document]), which can be embedded directly with an
+    ///  [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder). The id of the documents is
+    ///  the path of their `.eml` file, or `<path>#<index>` for the emails of `.mbox` archives.
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = EmailFileLoader::with_dir("tickets")?
+    ///     .read_with_path()
+    ///     .into_documents()
+    ///     .ignore_errors();
+    /// ```
+    pub fn into_documents(self) -> EmailFileLoader<'a, Result<Document, EmailLoaderError>> {
+        EmailFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|res| match res {
+                Ok((path, emails)) => {
+                    let mbox = is_mbox(&path);
+                    emails
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, email)| {
+                            let id = if mbox {
+                                format!("{}#{index}", path.display())
+                            } else {
+                                path.display().to_string()
+                            };
+                            Ok(email.into_document(id))
+                        })
+                        .collect()
+                }
+                Err(err) => vec![Err(err)],
+            })),
+        }
+    }
+}
+
+impl<'a, T: 'a> EmailFileLoader<'a, Result<T, EmailLoaderError>> {
+    /// Ignores errors in the iterator, returning only successful results. This can be used on any
+    ///  [EmailFileLoader] state of iterator whose items are results.
+    ///
+    /// # Example
+    /// ```rust
+    /// let emails = EmailFileLoader::with_glob("tickets/*.eml")?.read().ignore_errors().into_iter();
+    /// ```
+    pub fn ignore_errors(self) -> EmailFileLoader<'a, T> {
+        EmailFileLoader {
+            iterator: Box::new(self.iterator.filter_map(|res| res.ok())),
+        }
+    }
+}
+
+impl<'a, T: Cleanable + 'a> EmailFileLoader<'a, T> {
+    /// Cleans the bodies of the emails with the given [TextCleanup] (e.g.: collapsing runs of
+    ///  whitespace and normalizing unicode).
+    ///
+    /// # Example
+    /// ```rust
+    /// let emails = EmailFileLoader::with_glob("tickets/*.eml")?
+    ///     .read_with_path()
+    ///     .clean(TextCleanup::default());
+    /// ```
+    pub fn clean(self, cleanup: TextCleanup) -> EmailFileLoader<'a, T> {
+        EmailFileLoader {
+            iterator: Box::new(self.iterator.map(move |item| item.clean(&cleanup))),
+        }
+    }
+}
+
+impl EmailFileLoader<'_, Result<PathBuf, FileLoaderError>> {
+    /// Creates a new [EmailFileLoader] using a glob pattern to match files.
+    ///
+    /// # Example
+    /// Create a [EmailFileLoader] for all `.eml` files that match the glob "tickets/*.eml".
+    ///
+    /// ```rust
+    /// let loader = EmailFileLoader::with_glob("tickets/*.eml")?;
+    /// ```
+    pub fn with_glob(
+        pattern: &str,
+    ) -> Result<EmailFileLoader<Result<PathBuf, EmailLoaderError>>, EmailLoaderError> {
+        let paths = glob(pattern).map_err(FileLoaderError::PatternError)?;
+        Ok(EmailFileLoader {
+            iterator: Box::new(paths.into_iter().map(|path| {
+                path.map_err(FileLoaderError::GlobError)
+                    .map_err(EmailLoaderError::FileLoaderError)
+            })),
+        })
+    }
+
+    /// Creates a new [EmailFileLoader] on the email files (i.e.: `.eml` and `.mbox` files)
+    ///  within a directory.
+    ///
+    /// # Example
+    /// ```rust
+    /// let loader = EmailFileLoader::with_dir("tickets")?;
+    /// ```
+    pub fn with_dir(
+        directory: &str,
+    ) -> Result<EmailFileLoader<Result<PathBuf, EmailLoaderError>>, EmailLoaderError> {
+        Ok(EmailFileLoader {
+            iterator: Box::new(
+                fs::read_dir(directory)
+                    .map_err(FileLoaderError::IoError)?
+                    .map(|entry| Ok(entry.map_err(FileLoaderError::IoError)?.path()))
+                    .filter(is_email),
+            ),
+        })
+    }
+
+    /// Creates a new [EmailFileLoader] on the email files within a directory and its
+    ///  subdirectories, at any depth. Symlinks are followed, and directories that were already
+    ///  visited (i.e.: symlink loops) are skipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// let loader = EmailFileLoader::with_dir_recursive("tickets")?;
+    /// ```
+    pub fn with_dir_recursive(
+        directory: &str,
+    ) -> Result<EmailFileLoader<Result<PathBuf, EmailLoaderError>>, EmailLoaderError> {
+        Ok(EmailFileLoader {
+            iterator: Box::new(
+                WalkDir::new(directory)?
+                    .map(|path| path.map_err(EmailLoaderError::FileLoaderError))
+                    .filter(is_email),
+            ),
+        })
+    }
+}
+
+#[cfg(feature = "language")]
+super::language::language_adaptors!(EmailFileLoader);
+
+// ================================================================
+// EmailFileLoader iterator implementations
+// ================================================================
+
+pub struct IntoIter<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a, T> IntoIterator for EmailFileLoader<'a, T> {
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iterator: self.iterator,
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iterator.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_words, Attachment, Email, EmailFileLoader};
+
+    #[test]
+    fn test_eml() {
+        let emails = EmailFileLoader::with_glob("tests/data/ticket.eml")
+            .unwrap()
+            .read_with_attachments()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(emails.len(), 1);
+
+        let (path, emails) = &emails[0];
+        assert!(path.ends_with("ticket.eml"));
+        assert_eq!(
+            emails,
+            &vec![Email {
+                from: Some("Renée Support <renee@example.com>".into()),
+                to: Some("support@example.com".into()),
+                subject: Some("Flurbo refund".into()),
+                date: Some("Mon, 14 Oct 2024 10:00:00 +0000".into()),
+                // The text/plain alternative is preferred over the html one
+                body: "My flurbo order never arrived. Can I get a refund? Costs 5 €.".into(),
+                attachments: vec![Attachment {
+                    filename: Some("receipt.txt".into()),
+                    content_type: "text/plain".into(),
+                    data: b"Order #42".to_vec(),
+                }],
+            }]
+        );
+
+        // Attachments are skipped by default
+        let emails = EmailFileLoader::with_glob("tests/data/ticket.eml")
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(emails[0][0].attachments.is_empty());
+    }
+
+    #[test]
+    fn test_mbox() {
+        let documents = EmailFileLoader::with_glob("tests/data/tickets.mbox")
+            .unwrap()
+            .read_with_path()
+            .into_documents()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(documents.len(), 2);
+
+        assert!(documents[0].id.ends_with("tickets.mbox#0"));
+        assert_eq!(
+            documents[0].text,
+            "Is my glarb still under warranty?\nFrom the manual, it should be."
+        );
+        assert_eq!(documents[0].additional_props["subject"], "Glarb warranty");
+        assert_eq!(documents[0].additional_props["from"], "renee@example.com");
+
+        // Html-only emails fall back to the text of their html
+        assert!(documents[1].id.ends_with("tickets.mbox#1"));
+        assert_eq!(documents[1].text, "Your invoice is ready.");
+        assert_eq!(documents[1].additional_props["subject"], "Invoice");
+        assert!(!documents[1].additional_props.contains_key("date"));
+    }
+
+    #[test]
+    fn test_decode_words() {
+        assert_eq!(
+            decode_words("=?utf-8?B?Rmx1cmJv?= =?ISO-8859-1?Q?r=E9sum=E9?= and =?x?"),
+            "Flurbor\u{e9}sum\u{e9} and =?x?"
+        );
+    }
+}
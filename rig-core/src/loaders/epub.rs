@@ -0,0 +1,1619 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use epub::doc::{DocError, EpubDoc, NavPoint};
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zip::{result::ZipError, write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use super::{
+    cleanup::{Cleanable, TextCleanup},
+    file::{FileLoaderError, WalkDir},
+    html::strip_html,
+    mime,
+};
+use crate::completion::Document;
+
+#[derive(Error, Debug)]
+pub enum EpubLoaderError {
+    #[error("{0}")]
+    FileLoaderError(#[from] FileLoaderError),
+
+    #[error("UTF-8 conversion error: {0}")]
+    FromUtf8Error(#[from] std::string::FromUtf8Error),
+
+    #[error("IO error: {0}")]
+    EpubError(#[from] DocError),
+
+    #[error("Missing resource: {0}")]
+    MissingResource(String),
+
+    /// The overlap of [EpubFileLoader::by_chunk] is not smaller than its chunk size
+    #[error("Chunk overlap ({overlap}) should be smaller than the chunk size ({max_tokens})")]
+    InvalidOverlap { max_tokens: usize, overlap: usize },
+
+    /// The thread pool of [EpubFileLoader::read_parallel] could not be created
+    #[cfg(feature = "rayon")]
+    #[error("Thread pool error: {0}")]
+    ThreadPoolError(#[from] rayon::ThreadPoolBuildError),
+
+    #[error("Archive error: {0}")]
+    ArchiveError(#[from] ZipError),
+
+    /// The password of an encrypted epub is wrong
+    #[error("Decryption error: {0}")]
+    Decryption(String),
+}
+
+/// Reader of the archive of a loaded epub: epubs are read from their file, except for
+///  password-protected epubs which are decrypted into memory (see [EpubFileLoader::with_password]).
+pub enum EpubReader {
+    File(BufReader<File>),
+    Decrypted(Cursor<Vec<u8>>),
+}
+
+impl Read for EpubReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            EpubReader::File(reader) => reader.read(buf),
+            EpubReader::Decrypted(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for EpubReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            EpubReader::File(reader) => reader.seek(pos),
+            EpubReader::Decrypted(reader) => reader.seek(pos),
+        }
+    }
+}
+
+type EpubDocument = EpubDoc<EpubReader>;
+
+// ================================================================
+// Implementing Loadable trait for loading epubs
+// ================================================================
+
+pub(crate) trait Loadable {
+    fn load(self, password: Option<&str>) -> Result<EpubDocument, EpubLoaderError>;
+    fn load_with_path(
+        self,
+        password: Option<&str>,
+    ) -> Result<(PathBuf, EpubDocument), EpubLoaderError>;
+}
+
+impl Loadable for PathBuf {
+    fn load(self, password: Option<&str>) -> Result<EpubDocument, EpubLoaderError> {
+        let reader = match password {
+            Some(password) => {
+                let bytes = fs::read(&self).map_err(FileLoaderError::IoError)?;
+                EpubReader::Decrypted(Cursor::new(decrypt(bytes, password)?))
+            }
+            None => EpubReader::File(BufReader::new(
+                File::open(&self).map_err(FileLoaderError::IoError)?,
+            )),
+        };
+        EpubDoc::from_reader(reader).map_err(EpubLoaderError::EpubError)
+    }
+    fn load_with_path(
+        self,
+        password: Option<&str>,
+    ) -> Result<(PathBuf, EpubDocument), EpubLoaderError> {
+        let contents = self.clone().load(password);
+        Ok((self, contents?))
+    }
+}
+impl<T: Loadable> Loadable for Result<T, EpubLoaderError> {
+    fn load(self, password: Option<&str>) -> Result<EpubDocument, EpubLoaderError> {
+        self.map(|t| t.load(password))?
+    }
+    fn load_with_path(
+        self,
+        password: Option<&str>,
+    ) -> Result<(PathBuf, EpubDocument), EpubLoaderError> {
+        self.map(|t| t.load_with_path(password))?
+    }
+}
+
+/// Decrypt a (ZipCrypto) password-protected epub archive, returning the bytes of the equivalent
+///  unencrypted archive. Unencrypted entries are copied as is.
+fn decrypt(bytes: Vec<u8>, password: &str) -> Result<Vec<u8>, EpubLoaderError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for index in 0..archive.len() {
+        let mut file = match archive.by_index_decrypt(index, password.as_bytes()) {
+            Ok(file) => file,
+            Err(ZipError::InvalidPassword) => {
+                return Err(EpubLoaderError::Decryption(
+                    "Invalid password for encrypted epub".to_string(),
+                ))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let options = SimpleFileOptions::default().compression_method(file.compression());
+        if file.is_dir() {
+            writer.add_directory(file.name().to_string(), options)?;
+            continue;
+        }
+        writer.start_file(file.name().to_string(), options)?;
+
+        let encrypted = file.encrypted();
+        io::copy(&mut file, &mut writer).map_err(|e| match encrypted {
+            // The password check of ZipCrypto has false positives, in which case decryption
+            //  fails on the checksum of the decrypted entry instead
+            true => {
+                EpubLoaderError::Decryption(format!("Failed to decrypt `{}`: {e}", file.name()))
+            }
+            false => FileLoaderError::IoError(e).into(),
+        })?;
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
+
+// ================================================================
+// Epub text extraction helpers
+// ================================================================
+
+/// Map the content path of every TOC entry (flattening nested entries) to its label.
+/// Fragments (e.g.: `chapter1.xhtml#section2`) are ignored and the first entry pointing to a
+///  given file wins, so that the title of a chapter is not overridden by one of its sections.
+fn toc_titles(toc: &[NavPoint]) -> HashMap<String, String> {
+    fn visit(points: &[NavPoint], titles: &mut HashMap<String, String>) {
+        for point in points {
+            let content = point.content.to_string_lossy();
+            let path = content.split('#').next().unwrap_or_default().to_string();
+            titles
+                .entry(path)
+                .or_insert_with(|| point.label.trim().to_string());
+            visit(&point.children, titles);
+        }
+    }
+
+    let mut titles = HashMap::new();
+    visit(toc, &mut titles);
+    titles
+}
+
+/// Dublin Core metadata of an epub. Fields missing from the epub are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    /// The (first) author of the epub
+    pub creator: Option<String>,
+    pub language: Option<String>,
+    /// Unique identifier of the epub (e.g.: ISBN, UUID)
+    pub identifier: Option<String>,
+    /// Publication date
+    pub date: Option<String>,
+}
+
+impl EpubMetadata {
+    /// The metadata fields which are set, by name
+    fn fields(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        [
+            ("title", &self.title),
+            ("creator", &self.creator),
+            ("language", &self.language),
+            ("identifier", &self.identifier),
+            ("date", &self.date),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.clone()?)))
+    }
+}
+
+impl From<&EpubDocument> for EpubMetadata {
+    fn from(doc: &EpubDocument) -> Self {
+        Self {
+            title: doc.mdata("title"),
+            creator: doc.mdata("creator"),
+            language: doc.mdata("language"),
+            identifier: doc.mdata("identifier"),
+            date: doc.mdata("date"),
+        }
+    }
+}
+
+/// Extract the text of every spine item of the document along with its title, skipping the
+///  items without text (e.g.: cover pages). Spine items missing from the TOC are given a
+///  synthetic title of the form `"Section {n}"` where `n` is the (1-indexed) position of the
+///  item in the spine.
+fn chapters(doc: &mut EpubDocument) -> Vec<Result<(String, String), EpubLoaderError>> {
+    let titles = toc_titles(&doc.toc);
+
+    (0..doc.get_num_pages())
+        .map(|page| {
+            doc.set_current_page(page);
+
+            let title = doc
+                .get_current_path()
+                .and_then(|path| titles.get(path.to_string_lossy().as_ref()).cloned())
+                .unwrap_or_else(|| format!("Section {}", page + 1));
+
+            let (content, _mime) = doc.get_current().ok_or_else(|| {
+                EpubLoaderError::MissingResource(doc.get_current_id().unwrap_or_default())
+            })?;
+
+            Ok((title, strip_html(&String::from_utf8(content)?)))
+        })
+        .filter(|chapter| !matches!(chapter, Ok((_, text)) if text.is_empty()))
+        .collect()
+}
+
+/// Extract the text of the whole document: the text of every spine item, in reading order,
+///  separated by blank lines. A document with an empty spine has an empty text.
+fn full_text(doc: &mut EpubDocument) -> Result<String, EpubLoaderError> {
+    Ok(chapters(doc)
+        .into_iter()
+        .map(|chapter| chapter.map(|(_title, text)| text))
+        .collect::<Result<Vec<_>, _>>()?
+        .join("\n\n"))
+}
+
+/// Extract the text of the whole document like [full_text], skipping the spine items that fail
+///  to be extracted (e.g.: chapters with invalid UTF-8) and returning their errors, in spine
+///  order, along with the text of the other items.
+fn lossy_text(doc: &mut EpubDocument) -> (String, Vec<EpubLoaderError>) {
+    let mut texts = vec![];
+    let mut errors = vec![];
+    for chapter in chapters(doc) {
+        match chapter {
+            Ok((_title, text)) => texts.push(text),
+            Err(e) => errors.push(e),
+        }
+    }
+    (texts.join("\n\n"), errors)
+}
+
+/// Extract the text of an (unencrypted) epub from its bytes (e.g.: an epub within an archive),
+///  see [EpubFileLoader::read]
+#[cfg(feature = "archive")]
+pub(crate) fn text_from_bytes(bytes: Vec<u8>) -> Result<String, EpubLoaderError> {
+    full_text(&mut EpubDoc::from_reader(Cursor::new(bytes))?)
+}
+
+fn is_epub<E>(result: &Result<PathBuf, E>) -> bool {
+    match result {
+        Ok(path) => mime::from_extension(path) == Some(mime::EPUB),
+        Err(_) => true,
+    }
+}
+
+/// Raw bytes of the image resources of `doc` (i.e.: manifest entries with an `image/*` MIME
+///  type), paired with their resource id and sorted by id. Entries pointing to a resource
+///  missing from the epub are [EpubLoaderError::MissingResource] errors.
+fn images(doc: &mut EpubDocument) -> Vec<Result<(String, Vec<u8>), EpubLoaderError>> {
+    let mut ids = doc
+        .resources
+        .iter()
+        .filter(|(_, (_, mime))| mime.starts_with("image/"))
+        .map(|(id, _)| id.clone())
+        .collect::<Vec<_>>();
+    ids.sort();
+
+    ids.into_iter()
+        .map(|id| match doc.get_resource(&id) {
+            Some((bytes, _mime)) => Ok((id, bytes)),
+            None => Err(EpubLoaderError::MissingResource(id)),
+        })
+        .collect()
+}
+
+/// Approximate number of tokens of `text` used by [EpubFileLoader::by_chunk]: one token per
+///  whitespace-separated word.
+fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// Split `text` into windows of at most `max_tokens` tokens (according to `count`, applied to
+///  each whitespace-separated word), each window repeating the trailing words of the previous
+///  one totalling at most `overlap` tokens. Words longer than `max_tokens` make up a window of
+///  their own, and the whitespace of the text is preserved within windows.
+fn chunk_by_tokens(
+    text: &str,
+    max_tokens: usize,
+    overlap: usize,
+    count: &dyn Fn(&str) -> usize,
+) -> Vec<String> {
+    // Byte range and number of tokens of each word
+    let mut words = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        let offset = text.len() - rest.len() + start;
+        let word = rest[start..]
+            .split(char::is_whitespace)
+            .next()
+            .unwrap_or_default();
+        words.push((offset, offset + word.len(), count(word)));
+        rest = &rest[start + word.len()..];
+    }
+
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < words.len() {
+        let mut end = start;
+        let mut tokens = 0;
+        while end < words.len() && (end == start || tokens + words[end].2 <= max_tokens) {
+            tokens += words[end].2;
+            end += 1;
+        }
+        chunks.push(text[words[start].0..words[end - 1].1].to_string());
+
+        if end == words.len() {
+            break;
+        }
+
+        // Repeat the trailing words of the chunk, always moving forward by at least one word
+        let mut next = end;
+        let mut overlapped = 0;
+        while next > start + 1 && overlapped + words[next - 1].2 <= overlap {
+            overlapped += words[next - 1].2;
+            next -= 1;
+        }
+        start = next;
+    }
+
+    chunks
+}
+
+// ================================================================
+// EpubFileLoader definitions and implementations
+// ================================================================
+
+/// [EpubFileLoader] is a utility for loading epub files from the filesystem using glob patterns
+///  or directory paths. It provides methods to load the epubs and chunk them by chapter.
+///
+/// # Errors
+///
+/// This module defines a custom error type [EpubLoaderError] which can represent various errors
+///  that might occur during file loading operations, such as any [FileLoaderError] alongside
+///  specific epub-related errors.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use rig::loaders::EpubFileLoader;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     // Create a EpubFileLoader using a glob pattern
+///     let loader = EpubFileLoader::with_glob("tests/data/*.epub")?;
+///
+///     // Load epub files and chunk them by chapter
+///     for chapter in loader.load().by_chapter() {
+///         let (title, text) = chapter?;
+///         println!("{title}: {text}");
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [EpubFileLoader] uses strict typing between the iterator methods to ensure that transitions
+///  between different implementations of the loaders and it's methods are handled properly by
+///  the compiler.
+pub struct EpubFileLoader<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+    password: Option<String>,
+}
+
+impl<'a> EpubFileLoader<'a, Result<PathBuf, EpubLoaderError>> {
+    /// Sets the password used to decrypt password-protected epubs when loading them. Only
+    ///  DRM-free (ZipCrypto) encryption of the archive is supported, and epubs that are not
+    ///  encrypted are loaded as usual. A wrong password results in a
+    ///  [EpubLoaderError::Decryption] error for the epubs it fails to decrypt.
+    ///
+    /// # Example
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .with_password("secret")
+    ///     .load()
+    ///     .by_chapter();
+    /// ```
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Directly reads the contents of the epubs within the iterator returned by
+    ///  [EpubFileLoader::with_glob] or [EpubFileLoader::with_dir]. The text of a book is the
+    ///  plain text of its chapters in reading order (i.e.: following its spine), separated by
+    ///  blank lines.
+    ///
+    /// # Example
+    /// Read epubs in directory "tests/data/*.epub" and return the contents of the books.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.read().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(content) => println!("{}", content),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read(self) -> EpubFileLoader<'a, Result<String, EpubLoaderError>> {
+        let password = self.password;
+        EpubFileLoader {
+            iterator: Box::new(
+                self.iterator
+                    .map(move |res| full_text(&mut res.load(password.as_deref())?)),
+            ),
+            password: None,
+        }
+    }
+
+    /// Directly reads the contents of the epubs within the iterator returned by
+    ///  [EpubFileLoader::with_glob] or [EpubFileLoader::with_dir] and returns the path along with
+    ///  the content (see [EpubFileLoader::read]).
+    ///
+    /// # Example
+    /// Read epubs in directory "tests/data/*.epub" and return the content and paths of the books.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.read_with_path().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, content)) => println!("{:?} {}", path, content),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_with_path(self) -> EpubFileLoader<'a, Result<(PathBuf, String), EpubLoaderError>> {
+        let password = self.password;
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.map(move |res| {
+                let (path, mut doc) = res.load_with_path(password.as_deref())?;
+                Ok((path, full_text(&mut doc)?))
+            })),
+            password: None,
+        }
+    }
+
+    /// Directly reads the contents of the epubs within the iterator returned by
+    ///  [EpubFileLoader::with_glob] or [EpubFileLoader::with_dir] like [EpubFileLoader::read],
+    ///  but on a best-effort basis: the spine entries that fail to be extracted (e.g.: chapters
+    ///  with invalid UTF-8 or missing resources) are left out of the text, and their errors are
+    ///  returned along with it. Only the epubs that cannot be opened at all are errors.
+    ///
+    /// # Example
+    /// Read the epubs in directory "library", logging the damaged chapters.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_dir("library")?.read_lossy().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((content, errors)) => {
+    ///             errors.iter().for_each(|e| eprintln!("Skipped a chapter: {}", e));
+    ///             println!("{}", content);
+    ///         }
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_lossy(
+        self,
+    ) -> EpubFileLoader<'a, Result<(String, Vec<EpubLoaderError>), EpubLoaderError>> {
+        let password = self.password;
+        EpubFileLoader {
+            iterator: Box::new(
+                self.iterator
+                    .map(move |res| Ok(lossy_text(&mut res.load(password.as_deref())?))),
+            ),
+            password: None,
+        }
+    }
+
+    /// Directly reads the contents of the epubs within the iterator returned by
+    ///  [EpubFileLoader::with_glob] or [EpubFileLoader::with_dir] along with their
+    ///  [EpubMetadata], in a single pass (see [EpubFileLoader::read]).
+    ///
+    /// # Example
+    /// Read epubs in directory "tests/data/*.epub" and return the metadata and content of the books.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.read_with_metadata().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((metadata, content)) => println!("{:?} {}", metadata.title, content),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_with_metadata(
+        self,
+    ) -> EpubFileLoader<'a, Result<(EpubMetadata, String), EpubLoaderError>> {
+        let password = self.password;
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.map(move |res| {
+                let mut doc = res.load(password.as_deref())?;
+                Ok((EpubMetadata::from(&doc), full_text(&mut doc)?))
+            })),
+            password: None,
+        }
+    }
+
+    /// Reads the contents of the epubs within the iterator returned by
+    ///  [EpubFileLoader::with_glob] or [EpubFileLoader::with_dir] like
+    ///  [EpubFileLoader::read_with_path], but opening and parsing the epubs concurrently on a
+    ///  pool of `threads` threads (the number of CPUs if `threads` is 0). The paths are collected
+    ///  upfront, and every epub appears exactly once in the results. Requires the `rayon` feature.
+    ///
+    /// # Example
+    /// Read the epubs in directory "library" with 8 threads.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_dir("library")?.read_parallel(8).into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, content)) => println!("{:?} {}", path, content),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn read_parallel(
+        self,
+        threads: usize,
+    ) -> EpubFileLoader<'a, Result<(PathBuf, String), EpubLoaderError>> {
+        use rayon::prelude::*;
+
+        let password = self.password;
+        let paths = self.iterator.collect::<Vec<_>>();
+
+        let results = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => pool.install(|| {
+                paths
+                    .into_par_iter()
+                    .map(|res| -> Result<_, EpubLoaderError> {
+                        let (path, mut doc) = res.load_with_path(password.as_deref())?;
+                        Ok((path, full_text(&mut doc)?))
+                    })
+                    .collect::<Vec<_>>()
+            }),
+            Err(e) => vec![Err(e.into())],
+        };
+
+        EpubFileLoader {
+            iterator: Box::new(results.into_iter()),
+            password: None,
+        }
+    }
+
+    /// Loads the contents of the epubs within the iterator returned by
+    ///  [EpubFileLoader::with_glob] or [EpubFileLoader::with_dir]. Loaded epub documents are raw
+    ///  epub instances that can be further processed (by chapter, etc).
+    ///
+    /// # Example
+    /// Load epubs in directory "tests/data/*.epub" and return the loaded documents
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.load().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(doc) => println!("{:?}", doc.mdata("title")),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn load(self) -> EpubFileLoader<'a, Result<EpubDocument, EpubLoaderError>> {
+        let password = self.password;
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.map(move |res| res.load(password.as_deref()))),
+            password: None,
+        }
+    }
+
+    /// Loads the contents of the epubs within the iterator returned by
+    ///  [EpubFileLoader::with_glob] or [EpubFileLoader::with_dir]. Loaded epub documents are raw
+    ///  epub instances with their path that can be further processed.
+    ///
+    /// # Example
+    /// Load epubs in directory "tests/data/*.epub" and return the loaded documents
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.load_with_path().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, doc)) => println!("{:?} {:?}", path, doc.mdata("title")),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn load_with_path(
+        self,
+    ) -> EpubFileLoader<'a, Result<(PathBuf, EpubDocument), EpubLoaderError>> {
+        let password = self.password;
+        EpubFileLoader {
+            iterator: Box::new(
+                self.iterator
+                    .map(move |res| res.load_with_path(password.as_deref())),
+            ),
+            password: None,
+        }
+    }
+}
+
+impl<'a> EpubFileLoader<'a, Result<EpubDocument, EpubLoaderError>> {
+    /// Chunks the text of the loaded documents (see [EpubFileLoader::read]) into overlapping
+    ///  windows of at most `max_tokens` tokens, flattened as a single iterator. Each window
+    ///  starts with the trailing `overlap` tokens of the previous one, to preserve context across
+    ///  boundaries. Tokens are approximated by whitespace-separated words, see
+    ///  [EpubFileLoader::by_chunk_with_tokenizer] to count them with an actual tokenizer.
+    ///
+    /// A text shorter than `max_tokens` makes up a single chunk. If `overlap` is not smaller
+    ///  than `max_tokens`, the iterator yields a single [EpubLoaderError::InvalidOverlap] error.
+    ///
+    /// # Example
+    /// Load epubs in directory "tests/data/*.epub" and chunk their text into windows of about
+    ///  500 words.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .load()
+    ///     .by_chunk(500, 50)
+    ///     .into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(chunk) => println!("{}", chunk),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn by_chunk(
+        self,
+        max_tokens: usize,
+        overlap: usize,
+    ) -> EpubFileLoader<'a, Result<String, EpubLoaderError>> {
+        self.by_chunk_with_tokenizer(max_tokens, overlap, count_words)
+    }
+
+    /// Same as [EpubFileLoader::by_chunk], with the number of tokens of each word of the text
+    ///  counted by `count` (e.g.: using the tokenizer of the embedding model).
+    ///
+    /// # Example
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .load()
+    ///     .by_chunk_with_tokenizer(8000, 200, |word| tokenizer.encode(word).len());
+    /// ```
+    pub fn by_chunk_with_tokenizer(
+        self,
+        max_tokens: usize,
+        overlap: usize,
+        count: impl Fn(&str) -> usize + 'a,
+    ) -> EpubFileLoader<'a, Result<String, EpubLoaderError>> {
+        if overlap >= max_tokens {
+            return EpubFileLoader {
+                iterator: Box::new(std::iter::once(Err(EpubLoaderError::InvalidOverlap {
+                    max_tokens,
+                    overlap,
+                }))),
+                password: None,
+            };
+        }
+
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.flat_map(move |res| {
+                match res.and_then(|mut doc| full_text(&mut doc)) {
+                    Ok(text) => chunk_by_tokens(&text, max_tokens, overlap, &count)
+                        .into_iter()
+                        .map(Ok)
+                        .collect(),
+                    Err(e) => vec![Err(e)],
+                }
+            })),
+            password: None,
+        }
+    }
+
+    /// Chunks the loaded documents by chapter, flattened as a single iterator of
+    ///  `(title, text)` pairs. Each spine item of a document is paired with its title from the
+    ///  table of contents, and its text is converted from XHTML to plain text. Spine items
+    ///  missing from the table of contents are given a synthetic `"Section {n}"` title, and
+    ///  spine items without text (e.g.: cover pages, navigation documents) are skipped.
+    ///
+    /// # Example
+    /// Load epubs in directory "tests/data/*.epub" and chunk all documents by chapter.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.load().by_chapter().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((title, text)) => println!("{}\n{}", title, text),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn by_chapter(self) -> EpubFileLoader<'a, Result<(String, String), EpubLoaderError>> {
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|res| match res {
+                Ok(mut doc) => chapters(&mut doc),
+                Err(e) => vec![Err(e)],
+            })),
+            password: None,
+        }
+    }
+
+    /// Extracts the images (e.g.: cover, illustrations) of the loaded documents as
+    ///  `(resource id, bytes)` pairs, flattened as a single iterator. Images declared in the
+    ///  manifest of an epub but missing from it are [EpubLoaderError::MissingResource] errors,
+    ///  which can be skipped with [EpubFileLoader::ignore_errors].
+    ///
+    /// # Example
+    /// Load epubs in directory "tests/data/*.epub" and save their images.
+    ///
+    /// ```rust
+    /// let images = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .load()
+    ///     .images()
+    ///     .ignore_errors()
+    ///     .into_iter();
+    /// for (id, bytes) in images {
+    ///     std::fs::write(format!("images/{id}"), bytes)?;
+    /// }
+    /// ```
+    pub fn images(self) -> EpubFileLoader<'a, Result<(String, Vec<u8>), EpubLoaderError>> {
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|res| match res {
+                Ok(mut doc) => images(&mut doc),
+                Err(e) => vec![Err(e)],
+            })),
+            password: None,
+        }
+    }
+
+    /// Extracts the bytes of the cover image of the loaded documents, or `None` for epubs
+    ///  declaring no cover.
+    ///
+    /// # Example
+    /// ```rust
+    /// let covers = EpubFileLoader::with_glob("tests/data/*.epub")?.load().cover().into_iter();
+    /// for cover in covers {
+    ///     match cover {
+    ///         Ok(Some(bytes)) => println!("Cover of {} bytes", bytes.len()),
+    ///         Ok(None) => println!("No cover"),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn cover(self) -> EpubFileLoader<'a, Result<Option<Vec<u8>>, EpubLoaderError>> {
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let mut doc = res?;
+                match doc.get_cover_id() {
+                    Some(id) => match doc.get_resource(&id) {
+                        Some((bytes, _mime)) => Ok(Some(bytes)),
+                        None => Err(EpubLoaderError::MissingResource(id)),
+                    },
+                    None => Ok(None),
+                }
+            })),
+            password: None,
+        }
+    }
+
+    /// Extracts the [EpubMetadata] (title, author, language, etc.) of the loaded documents.
+    ///
+    /// # Example
+    /// Load epubs in directory "tests/data/*.epub" and return their metadata.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.load().metadata().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(metadata) => println!("{:?} by {:?}", metadata.title, metadata.creator),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn metadata(self) -> EpubFileLoader<'a, Result<EpubMetadata, EpubLoaderError>> {
+        EpubFileLoader {
+            iterator: Box::new(
+                self.iterator
+                    .map(|res| res.map(|doc| EpubMetadata::from(&doc))),
+            ),
+            password: None,
+        }
+    }
+}
+
+/// Document of the chapter `index` of the epub at `path`
+fn chapter_document(path: &std::path::Path, index: usize, title: String, text: String) -> Document {
+    Document {
+        id: format!("{}#{index}", path.display()),
+        text,
+        additional_props: HashMap::from([("chapter_title".to_string(), title)]),
+    }
+}
+
+impl<'a> EpubFileLoader<'a, Result<(PathBuf, EpubDocument), EpubLoaderError>> {
+    /// Chunks the loaded documents by chapter and converts the chapters into [Document]s,
+    ///  flattened as a single iterator, which can be embedded directly with an
+    ///  [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder).
+    ///
+    /// The id of each chapter is `<path>#<chapter index>` (e.g.: `tests/data/chapters.epub#1`),
+    ///  which is stable across loads so that re-indexing an epub replaces its chapters. The
+    ///  title of the chapter is stored in the `chapter_title` field, along with the
+    ///  [EpubMetadata] of the epub which is set (`title`, `creator`, `language`, `identifier`
+    ///  and `date`).
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .load_with_path()
+    ///     .into_documents()
+    ///     .ignore_errors();
+    ///
+    /// let embeddings = EmbeddingsBuilder::new(model).documents(documents)?.build().await?;
+    /// ```
+    pub fn into_documents(self) -> EpubFileLoader<'a, Result<Document, EpubLoaderError>> {
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|res| {
+                let (path, mut doc) = match res {
+                    Ok(loaded) => loaded,
+                    Err(e) => return vec![Err(e)],
+                };
+                let metadata = EpubMetadata::from(&doc);
+
+                chapters(&mut doc)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, chapter)| {
+                        let (title, text) = chapter?;
+                        let mut document = chapter_document(&path, index, title, text);
+                        document.additional_props.extend(metadata.fields());
+                        Ok(document)
+                    })
+                    .collect()
+            })),
+            password: None,
+        }
+    }
+
+    /// Chunks the loaded documents by chapter like [EpubFileLoader::by_chapter], pairing each
+    ///  `(title, text)` chapter with the path of its epub.
+    ///
+    /// # Example
+    /// Load epubs in directory "tests/data/*.epub" and chunk all documents by chapter.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .load_with_path()
+    ///     .by_chapter_with_path()
+    ///     .into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, (title, text))) => println!("{:?} {}\n{}", path, title, text),
+    ///         Err(e) => eprintln!("Error reading epub: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn by_chapter_with_path(
+        self,
+    ) -> EpubFileLoader<'a, Result<(PathBuf, (String, String)), EpubLoaderError>> {
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|res| {
+                match res {
+                    Ok((path, mut doc)) => chapters(&mut doc)
+                        .into_iter()
+                        .map(|chapter| Ok((path.clone(), chapter?)))
+                        .collect(),
+                    Err(e) => vec![Err(e)],
+                }
+            })),
+            password: None,
+        }
+    }
+}
+
+impl<'a> EpubFileLoader<'a, Result<(PathBuf, (String, String)), EpubLoaderError>> {
+    /// Converts the chapters of [EpubFileLoader::by_chapter_with_path] into [Document]s, with
+    ///  the same ids (`<path>#<chapter index>`) as [EpubFileLoader::into_documents] and the
+    ///  title of the chapter in the `chapter_title` field. Chapters are numbered in order within
+    ///  each epub, so a chapter failing to load shifts the index of the following ones: use
+    ///  [EpubFileLoader::into_documents] on the loaded epubs for ids which are stable in that
+    ///  case, and to include the [EpubMetadata] of the epubs.
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .load_with_path()
+    ///     .by_chapter_with_path()
+    ///     .into_documents();
+    /// ```
+    pub fn into_documents(self) -> EpubFileLoader<'a, Result<Document, EpubLoaderError>> {
+        let mut previous: Option<(PathBuf, usize)> = None;
+
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.map(move |res| {
+                let (path, (title, text)) = res?;
+                let index = match &previous {
+                    Some((previous_path, index)) if *previous_path == path => index + 1,
+                    _ => 0,
+                };
+                let document = chapter_document(&path, index, title, text);
+                previous = Some((path, index));
+                Ok(document)
+            })),
+            password: None,
+        }
+    }
+}
+
+impl<'a, T: 'a> EpubFileLoader<'a, Result<T, EpubLoaderError>> {
+    /// Ignores errors in the iterator, returning only successful results (e.g.: skipping
+    ///  corrupted epubs). This can be used on any [EpubFileLoader] state of iterator whose items
+    ///  are results, and the items are still processed lazily.
+    ///
+    /// # Example
+    /// Read epubs in directory "tests/data/*.epub" and ignore errors from unreadable epubs.
+    ///
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?.read().ignore_errors().into_iter();
+    /// for content in content {
+    ///     println!("{}", content)
+    /// }
+    /// ```
+    pub fn ignore_errors(self) -> EpubFileLoader<'a, T> {
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.filter_map(|res| res.ok())),
+            password: self.password,
+        }
+    }
+}
+
+impl<'a, T: Cleanable + 'a> EpubFileLoader<'a, T> {
+    /// Cleans the text of the chapters with the given [TextCleanup] (e.g.: collapsing runs of
+    ///  whitespace). Chapter titles are kept as is.
+    ///
+    /// # Example
+    /// ```rust
+    /// let content = EpubFileLoader::with_glob("tests/data/*.epub")?
+    ///     .load()
+    ///     .by_chapter()
+    ///     .clean(TextCleanup::default());
+    /// ```
+    pub fn clean(self, cleanup: TextCleanup) -> EpubFileLoader<'a, T> {
+        EpubFileLoader {
+            iterator: Box::new(self.iterator.map(move |item| item.clean(&cleanup))),
+            password: self.password,
+        }
+    }
+}
+
+impl EpubFileLoader<'_, Result<PathBuf, FileLoaderError>> {
+    /// Creates a new [EpubFileLoader] using a glob pattern to match files.
+    ///
+    /// # Example
+    /// Create a [EpubFileLoader] for all `.epub` files that match the glob "tests/data/*.epub".
+    ///
+    /// ```rust
+    /// let loader = EpubFileLoader::with_glob("tests/data/*.epub")?;
+    /// ```
+    pub fn with_glob(
+        pattern: &str,
+    ) -> Result<EpubFileLoader<Result<PathBuf, EpubLoaderError>>, EpubLoaderError> {
+        let paths = glob(pattern).map_err(FileLoaderError::PatternError)?;
+        Ok(EpubFileLoader {
+            iterator: Box::new(paths.into_iter().map(|path| {
+                path.map_err(FileLoaderError::GlobError)
+                    .map_err(EpubLoaderError::FileLoaderError)
+            })),
+            password: None,
+        })
+    }
+
+    /// Creates a new [EpubFileLoader] using a glob pattern to match files, like
+    ///  [EpubFileLoader::with_glob], but omitting the matched files without an `.epub` extension
+    ///  (case-insensitive), which are not opened at all. Errors of actual epub files (e.g.:
+    ///  corrupt epubs) are still returned.
+    ///
+    /// # Example
+    /// Create a [EpubFileLoader] for the epubs of a directory of mixed files.
+    ///
+    /// ```rust
+    /// let loader = EpubFileLoader::with_glob_filtered("books/*")?;
+    /// ```
+    pub fn with_glob_filtered(
+        pattern: &str,
+    ) -> Result<EpubFileLoader<Result<PathBuf, EpubLoaderError>>, EpubLoaderError> {
+        let loader = Self::with_glob(pattern)?;
+        Ok(EpubFileLoader {
+            iterator: Box::new(loader.iterator.filter(is_epub)),
+            password: None,
+        })
+    }
+
+    /// Creates a new [EpubFileLoader] on all files within a directory.
+    ///
+    /// # Example
+    /// Create a [EpubFileLoader] for all files that are in the directory "files".
+    ///
+    /// ```rust
+    /// let loader = EpubFileLoader::with_dir("files")?;
+    /// ```
+    pub fn with_dir(
+        directory: &str,
+    ) -> Result<EpubFileLoader<Result<PathBuf, EpubLoaderError>>, EpubLoaderError> {
+        Ok(EpubFileLoader {
+            iterator: Box::new(
+                fs::read_dir(directory)
+                    .map_err(FileLoaderError::IoError)?
+                    .map(|entry| Ok(entry.map_err(FileLoaderError::IoError)?.path())),
+            ),
+            password: None,
+        })
+    }
+
+    /// Creates a new [EpubFileLoader] on all files within a directory and its subdirectories, at
+    ///  any depth. Symlinks are followed, and directories that were already visited (i.e.:
+    ///  symlink loops) are skipped.
+    ///
+    /// # Example
+    /// Create a [EpubFileLoader] for all files that are in the directory "library" or its subdirectories.
+    ///
+    /// ```rust
+    /// let loader = EpubFileLoader::with_dir_recursive("library")?;
+    /// ```
+    pub fn with_dir_recursive(
+        directory: &str,
+    ) -> Result<EpubFileLoader<Result<PathBuf, EpubLoaderError>>, EpubLoaderError> {
+        Ok(EpubFileLoader {
+            iterator: Box::new(
+                WalkDir::new(directory)?.map(|path| path.map_err(EpubLoaderError::FileLoaderError)),
+            ),
+            password: None,
+        })
+    }
+}
+
+#[cfg(feature = "language")]
+super::language::language_adaptors!(EpubFileLoader { password });
+
+// ================================================================
+// EpubFileLoader iterator implementations
+// ================================================================
+
+pub struct IntoIter<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a, T> IntoIterator for EpubFileLoader<'a, T> {
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iterator: self.iterator,
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iterator.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::{FileTouch, FileWriteBin, FileWriteStr, PathChild};
+
+    use super::{EpubFileLoader, EpubLoaderError, EpubMetadata};
+
+    #[test]
+    fn test_epub_loader_by_chapter() {
+        let loader = EpubFileLoader::with_glob("tests/data/chapters.epub").unwrap();
+        let actual = loader
+            .load()
+            .by_chapter()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // The trailing colophon has no text and is skipped
+        assert_eq!(
+            actual,
+            vec![
+                (
+                    "The Flurbo".to_string(),
+                    "The Flurbo\nFlurbos are a made up currency.".to_string()
+                ),
+                (
+                    "The Glarb".to_string(),
+                    "The Glarb\nGlarbs are & always were ancient creatures.".to_string()
+                ),
+                (
+                    "Section 3".to_string(),
+                    "This appendix is not in the table of contents.".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_epub_loader_by_chapter_with_path() {
+        let chapters = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .with_password("glarb")
+            .load_with_path()
+            .by_chapter_with_path()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // The empty epub has no chapters
+        assert_eq!(chapters.len(), 6);
+
+        let mut paths = chapters
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        paths.dedup();
+        assert_eq!(paths, vec!["chapters.epub", "chapters_encrypted.epub"]);
+        assert_eq!(
+            chapters[2].1,
+            (
+                "Section 3".to_string(),
+                "This appendix is not in the table of contents.".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_epub_loader_into_documents() {
+        let documents = EpubFileLoader::with_glob("tests/data/chapters.epub")
+            .unwrap()
+            .load_with_path()
+            .into_documents()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            documents
+                .iter()
+                .map(|doc| doc.id.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "tests/data/chapters.epub#0",
+                "tests/data/chapters.epub#1",
+                "tests/data/chapters.epub#2",
+            ]
+        );
+        assert_eq!(
+            documents[2].text,
+            "This appendix is not in the table of contents."
+        );
+        let props = &documents[2].additional_props;
+        assert_eq!(props["chapter_title"], "Section 3");
+        assert_eq!(props["title"], "Flurbos and Glarbs");
+        assert_eq!(props["creator"], "Rig Tester");
+        assert_eq!(props["date"], "2024-01-01");
+        assert_eq!(
+            crate::embeddings::to_texts(documents[2].clone()).unwrap(),
+            vec![documents[2].text.clone()]
+        );
+
+        // Chapters are numbered within each epub
+        let chapters = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .with_password("glarb")
+            .load_with_path()
+            .by_chapter_with_path()
+            .into_documents()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(chapters.len(), 6);
+        assert_eq!(chapters[3].id, "tests/data/chapters_encrypted.epub#0");
+        for (chapter, document) in chapters.iter().zip(&documents) {
+            assert_eq!(chapter.id, document.id);
+            assert_eq!(chapter.text, document.text);
+            assert_eq!(
+                chapter.additional_props["chapter_title"],
+                document.additional_props["chapter_title"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_epub_loader_ignore_errors() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let epub = std::fs::read("tests/data/chapters.epub").unwrap();
+        temp.child("valid.epub").write_binary(&epub).unwrap();
+        temp.child("truncated.epub")
+            .write_binary(&epub[..epub.len() / 2])
+            .unwrap();
+        temp.child("empty.epub").touch().unwrap();
+
+        let books = EpubFileLoader::with_glob(&format!("{}/*.epub", temp.path().display()))
+            .unwrap()
+            .read_with_path()
+            .ignore_errors()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        assert_eq!(books.len(), 1);
+        assert!(books[0].0.ends_with("valid.epub"));
+        assert!(books[0].1.starts_with("The Flurbo"));
+
+        // Errors are filtered lazily
+        let loader = EpubFileLoader {
+            iterator: Box::new((0..).map(|i| {
+                if i % 2 == 0 {
+                    Ok(i)
+                } else {
+                    Err(EpubLoaderError::MissingResource(i.to_string()))
+                }
+            })),
+            password: None,
+        };
+        assert_eq!(
+            loader
+                .ignore_errors()
+                .into_iter()
+                .take(3)
+                .collect::<Vec<_>>(),
+            vec![0, 2, 4]
+        );
+    }
+
+    #[test]
+    fn test_epub_loader_metadata() {
+        let metadata = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .with_password("glarb")
+            .load()
+            .metadata()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let chapters = EpubMetadata {
+            title: Some("Flurbos and Glarbs".to_string()),
+            creator: Some("Rig Tester".to_string()),
+            language: Some("en".to_string()),
+            identifier: Some("urn:uuid:0c2b1c5e-7d3a-4b8e-9f1a-3c2d7e5f9a10".to_string()),
+            date: Some("2024-01-01".to_string()),
+        };
+        assert_eq!(metadata[0], chapters);
+        // Missing fields are `None`
+        assert_eq!(
+            metadata[2],
+            EpubMetadata {
+                title: Some("Nothing".to_string()),
+                language: Some("en".to_string()),
+                identifier: Some("urn:uuid:5f0e7c1a-2b4d-4e6f-8a9b-1c2d3e4f5a6b".to_string()),
+                ..Default::default()
+            }
+        );
+
+        let books = EpubFileLoader::with_glob("tests/data/chapters.epub")
+            .unwrap()
+            .read_with_metadata()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(books[0].0, chapters);
+        assert!(books[0]
+            .1
+            .starts_with("The Flurbo\nFlurbos are a made up currency."));
+    }
+
+    #[test]
+    fn test_epub_loader_images() {
+        let pixel = std::fs::read("tests/data/pixel.png").unwrap();
+
+        let images = EpubFileLoader::with_glob("tests/data/chapters.epub")
+            .unwrap()
+            .load()
+            .images()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        assert_eq!(images.len(), 3);
+        assert_eq!(
+            images[0].as_ref().unwrap(),
+            &("cover".to_string(), pixel.clone())
+        );
+        // The manifest entry of "flurbo" points to a missing file
+        assert!(matches!(
+            &images[1],
+            Err(EpubLoaderError::MissingResource(id)) if id == "flurbo"
+        ));
+        let (id, bytes) = images[2].as_ref().unwrap();
+        assert_eq!(id, "glarb");
+        assert!(bytes.starts_with(b"\x89PNG"));
+
+        // Missing images are skipped without failing the other ones
+        let ids = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .load()
+            .images()
+            .ignore_errors()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["cover", "glarb"]);
+
+        let covers = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .with_password("glarb")
+            .load()
+            .cover()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(covers.len(), 3);
+        assert_eq!(covers[0].as_ref().unwrap(), &Some(pixel));
+        // empty.epub declares no cover
+        assert_eq!(covers[2].as_ref().unwrap(), &None);
+    }
+
+    #[test]
+    fn test_epub_loader_with_dir_recursive() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let epub = std::fs::read("tests/data/chapters.epub").unwrap();
+        temp.child("fiction/flurbos.epub")
+            .write_binary(&epub)
+            .unwrap();
+        temp.child("fiction/ancient/glarbs.epub")
+            .write_binary(&epub)
+            .unwrap();
+        temp.child("top.epub").write_binary(&epub).unwrap();
+
+        let books = EpubFileLoader::with_dir_recursive(temp.path().to_str().unwrap())
+            .unwrap()
+            .read_with_path()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let names = books
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["glarbs.epub", "flurbos.epub", "top.epub"]);
+        assert!(books.iter().all(|(_, text)| text.starts_with("The Flurbo")));
+    }
+
+    #[test]
+    fn test_epub_loader_with_glob_filtered() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let epub = std::fs::read("tests/data/chapters.epub").unwrap();
+        temp.child("flurbos.epub").write_binary(&epub).unwrap();
+        temp.child("GLARBS.EPUB").write_binary(&epub).unwrap();
+        temp.child("notes.txt").write_str("Not an epub").unwrap();
+        temp.child("cover.jpg")
+            .write_binary(&std::fs::read("tests/data/pixel.png").unwrap())
+            .unwrap();
+
+        let pattern = format!("{}/*", temp.path().display());
+        let paths = EpubFileLoader::with_glob_filtered(&pattern)
+            .unwrap()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let mut names = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["GLARBS.EPUB", "flurbos.epub"]);
+
+        let books = EpubFileLoader::with_glob_filtered(&pattern)
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(books.len(), 2);
+
+        // Other files are errors with the unfiltered glob
+        let errors = EpubFileLoader::with_glob(&pattern)
+            .unwrap()
+            .read()
+            .into_iter()
+            .filter(Result::is_err)
+            .count();
+        assert_eq!(errors, 2);
+
+        // Corrupt epubs are still attempted
+        temp.child("broken.epub").write_str("Not an epub").unwrap();
+        let results = EpubFileLoader::with_glob_filtered(&pattern)
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_epub_loader_read_parallel() {
+        let mut books = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .with_password("glarb")
+            .read_parallel(2)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        books.sort();
+
+        let sequential = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .with_password("glarb")
+            .read_with_path()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(books, sequential);
+    }
+
+    #[test]
+    fn test_epub_loader_by_chunk() {
+        let chunks = EpubFileLoader::with_glob("tests/data/chapters.epub")
+            .unwrap()
+            .load()
+            .by_chunk(8, 2)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            chunks,
+            vec![
+                "The Flurbo\nFlurbos are a made up currency.",
+                "up currency.\n\nThe Glarb\nGlarbs are & always",
+                "& always were ancient creatures.\n\nThis appendix is",
+                "appendix is not in the table of contents.",
+            ]
+        );
+
+        // Shorter texts make up a single chunk
+        let chunks = EpubFileLoader::with_glob("tests/data/chapters.epub")
+            .unwrap()
+            .load()
+            .by_chunk_with_tokenizer(100, 10, |word| word.len())
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].ends_with("table of contents."));
+
+        let result = EpubFileLoader::with_glob("tests/data/chapters.epub")
+            .unwrap()
+            .load()
+            .by_chunk(8, 8)
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            result[..],
+            [Err(EpubLoaderError::InvalidOverlap {
+                max_tokens: 8,
+                overlap: 8
+            })]
+        ));
+    }
+
+    #[test]
+    fn test_epub_loader_read() {
+        let books = EpubFileLoader::with_glob("tests/data/chapters.epub")
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // The chapters follow the order of the spine
+        assert_eq!(
+            books,
+            vec![
+                "The Flurbo\nFlurbos are a made up currency.\n\n\
+                The Glarb\nGlarbs are & always were ancient creatures.\n\n\
+                This appendix is not in the table of contents."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_epub_loader_read_lossy() {
+        // The second chapter of the book contains invalid UTF-8
+        let strict = EpubFileLoader::with_glob("tests/data/damaged/corrupt_chapter.epub")
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            strict.as_slice(),
+            [Err(EpubLoaderError::FromUtf8Error(_))]
+        ));
+
+        let books = EpubFileLoader::with_glob("tests/data/damaged/corrupt_chapter.epub")
+            .unwrap()
+            .read_lossy()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(books.len(), 1);
+
+        let (text, errors) = &books[0];
+        assert_eq!(
+            text,
+            "The Flurbo\nFlurbos are a made up currency.\n\n\
+            This appendix is not in the table of contents."
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], EpubLoaderError::FromUtf8Error(_)));
+    }
+
+    #[test]
+    fn test_epub_loader_read_with_path() {
+        let mut books = EpubFileLoader::with_glob("tests/data/*.epub")
+            .unwrap()
+            .with_password("glarb")
+            .read_with_path()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        books.sort();
+
+        let names = books
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["chapters.epub", "chapters_encrypted.epub", "empty.epub"]
+        );
+        assert!(books[0]
+            .1
+            .starts_with("The Flurbo\nFlurbos are a made up currency."));
+        assert_eq!(books[0].1, books[1].1);
+        assert_eq!(books[2].1, "");
+    }
+
+    #[test]
+    fn test_epub_loader_with_password() {
+        let chapters = EpubFileLoader::with_glob("tests/data/chapters_encrypted.epub")
+            .unwrap()
+            .with_password("glarb")
+            .load()
+            .by_chapter()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(chapters.len(), 3);
+        assert_eq!(
+            chapters[0],
+            (
+                "The Flurbo".to_string(),
+                "The Flurbo\nFlurbos are a made up currency.".to_string()
+            )
+        );
+
+        let result = EpubFileLoader::with_glob("tests/data/chapters_encrypted.epub")
+            .unwrap()
+            .with_password("flurbo")
+            .load()
+            .into_iter()
+            .next()
+            .unwrap();
+
+        assert!(matches!(result, Err(EpubLoaderError::Decryption(_))));
+    }
+}
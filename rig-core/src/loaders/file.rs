@@ -1,7 +1,18 @@
-use std::{fs, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
 
+#[cfg(feature = "tokio-fs")]
+use futures::{stream, Stream, StreamExt};
 use glob::glob;
 use thiserror::Error;
+#[cfg(feature = "tokio-fs")]
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use super::cleanup::{Cleanable, TextCleanup};
+use crate::completion::Document;
 
 #[derive(Error, Debug)]
 pub enum FileLoaderError {
@@ -140,6 +151,123 @@ impl<'a> FileLoader<'a, Result<PathBuf, FileLoaderError>> {
             iterator: Box::new(self.iterator.map(|res| res.read_with_path())),
         }
     }
+
+    /// Streams the contents of the files within the iterator returned by [FileLoader::with_glob]
+    ///  or [FileLoader::with_dir] as documents of `lines_per_document` lines (at least 1), one file
+    ///  after the other. Files are read incrementally, so only one document is held in memory at
+    ///  a time no matter the size of the files. Line terminators are removed and the lines of a
+    ///  document are joined with `\n`. A file that is not valid UTF-8 yields an error and is not
+    ///  read further. Requires the `tokio-fs` feature (and a tokio runtime).
+    ///
+    /// # Example
+    /// Embed a very large log file 100 lines at a time.
+    ///
+    /// ```rust
+    /// use futures::StreamExt;
+    ///
+    /// let mut documents = FileLoader::with_glob("logs/*.log")?.stream_lines(100);
+    /// while let Some(document) = documents.next().await {
+    ///     let embedding = model.embed_text(&document?).await?;
+    /// }
+    /// ```
+    #[cfg(feature = "tokio-fs")]
+    pub fn stream_lines(
+        self,
+        lines_per_document: usize,
+    ) -> impl Stream<Item = Result<String, FileLoaderError>> + Send + 'static {
+        let lines_per_document = lines_per_document.max(1);
+
+        stream::iter(self.iterator.collect::<Vec<_>>()).flat_map(move |path| match path {
+            Ok(path) => stream_file_lines(path, lines_per_document).boxed(),
+            Err(e) => stream::once(async { Err(e) }).boxed(),
+        })
+    }
+}
+
+/// Stream the lines of the file at `path`, grouped by `lines_per_document`
+#[cfg(feature = "tokio-fs")]
+fn stream_file_lines(
+    path: PathBuf,
+    lines_per_document: usize,
+) -> impl Stream<Item = Result<String, FileLoaderError>> + Send {
+    enum State {
+        Closed(PathBuf),
+        Reading(BufReader<tokio::fs::File>),
+        Done,
+    }
+
+    stream::unfold(State::Closed(path), move |state| async move {
+        let mut reader = match state {
+            State::Closed(path) => match tokio::fs::File::open(path).await {
+                Ok(file) => BufReader::new(file),
+                Err(e) => return Some((Err(e.into()), State::Done)),
+            },
+            State::Reading(reader) => reader,
+            State::Done => return None,
+        };
+
+        let mut document = String::new();
+        let mut line = vec![];
+        for i in 0..lines_per_document {
+            line.clear();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) if i == 0 => return None,
+                Ok(0) => return Some((Ok(document), State::Done)),
+                Ok(_) => {
+                    if line.last() == Some(&b'\n') {
+                        line.pop();
+                        if line.last() == Some(&b'\r') {
+                            line.pop();
+                        }
+                    }
+                    // Lines are complete, so multi-byte characters are never split
+                    match std::str::from_utf8(&line) {
+                        Ok(line) => {
+                            if i > 0 {
+                                document.push('\n');
+                            }
+                            document.push_str(line);
+                        }
+                        Err(e) => {
+                            let e = std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+                            return Some((Err(e.into()), State::Done));
+                        }
+                    }
+                }
+                Err(e) => return Some((Err(e.into()), State::Done)),
+            }
+        }
+
+        Some((Ok(document), State::Reading(reader)))
+    })
+}
+
+impl<'a> FileLoader<'a, Result<(PathBuf, String), FileLoaderError>> {
+    /// Converts the files read by [FileLoader::read_with_path] into [Document]s whose id is
+    ///  the path of their file, which can be embedded directly with an
+    ///  [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder).
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = FileLoader::with_glob("files/*.txt")?
+    ///     .read_with_path()
+    ///     .into_documents()
+    ///     .ignore_errors();
+    ///
+    /// let embeddings = EmbeddingsBuilder::new(model).documents(documents)?.build().await?;
+    /// ```
+    pub fn into_documents(self) -> FileLoader<'a, Result<Document, FileLoaderError>> {
+        FileLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let (path, text) = res?;
+                Ok(Document {
+                    id: path.display().to_string(),
+                    text,
+                    additional_props: HashMap::new(),
+                })
+            })),
+        }
+    }
 }
 
 impl<'a, T: 'a> FileLoader<'a, Result<T, FileLoaderError>> {
@@ -162,6 +290,25 @@ impl<'a, T: 'a> FileLoader<'a, Result<T, FileLoaderError>> {
     }
 }
 
+impl<'a, T: Cleanable + 'a> FileLoader<'a, T> {
+    /// Cleans the contents of the files with the given [TextCleanup] (e.g.: rejoining words
+    ///  hyphenated across line breaks and collapsing runs of whitespace).
+    ///
+    /// # Example
+    /// Read files in directory "files/*.txt" and clean their contents.
+    ///
+    /// ```rust
+    /// let content = FileLoader::with_glob("files/*.txt")?
+    ///     .read_with_path()
+    ///     .clean(TextCleanup::default());
+    /// ```
+    pub fn clean(self, cleanup: TextCleanup) -> FileLoader<'a, T> {
+        FileLoader {
+            iterator: Box::new(self.iterator.map(move |item| item.clean(&cleanup))),
+        }
+    }
+}
+
 impl FileLoader<'_, Result<PathBuf, FileLoaderError>> {
     /// Creates a new [FileLoader] using a glob pattern to match files.
     ///
@@ -206,8 +353,85 @@ impl FileLoader<'_, Result<PathBuf, FileLoaderError>> {
             })),
         })
     }
+
+    /// Creates a new [FileLoader] on all files within a directory and its subdirectories, at
+    ///  any depth. Symlinks are followed, and directories that were already visited (i.e.:
+    ///  symlink loops) are skipped.
+    ///
+    /// # Example
+    /// Create a [FileLoader] for all files that are in the directory "files" or its subdirectories.
+    ///
+    /// ```rust
+    /// let loader = FileLoader::with_dir_recursive("files")?;
+    /// ```
+    pub fn with_dir_recursive(
+        directory: &str,
+    ) -> Result<FileLoader<Result<PathBuf, FileLoaderError>>, FileLoaderError> {
+        Ok(FileLoader {
+            iterator: Box::new(WalkDir::new(directory)?),
+        })
+    }
 }
 
+// ================================================================
+// Recursive directory traversal
+// ================================================================
+
+/// Lazy iterator over the files of a directory and of its subdirectories (depth first, in
+///  lexicographic order), shared by the `with_dir_recursive` constructors of the loaders.
+///  Symlinks are followed, but directories that were already visited (e.g.: through a symlink
+///  loop) are skipped.
+pub(crate) struct WalkDir {
+    /// Paths left to visit, in reverse order
+    stack: Vec<PathBuf>,
+    visited: HashSet<PathBuf>,
+}
+
+impl WalkDir {
+    pub(crate) fn new(directory: impl AsRef<Path>) -> Result<Self, FileLoaderError> {
+        let mut walk = Self {
+            stack: vec![],
+            visited: HashSet::new(),
+        };
+        walk.visit(directory.as_ref())?;
+        Ok(walk)
+    }
+
+    /// Push the entries of `directory` on the stack, unless it was already visited
+    fn visit(&mut self, directory: &Path) -> Result<(), FileLoaderError> {
+        if !self.visited.insert(fs::canonicalize(directory)?) {
+            return Ok(());
+        }
+
+        let mut entries = fs::read_dir(directory)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        entries.sort_by(|a, b| b.cmp(a));
+        self.stack.extend(entries);
+        Ok(())
+    }
+}
+
+impl Iterator for WalkDir {
+    type Item = Result<PathBuf, FileLoaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(path) = self.stack.pop() {
+            if path.is_dir() {
+                if let Err(e) = self.visit(&path) {
+                    return Some(Err(e));
+                }
+            } else if path.is_file() {
+                return Some(Ok(path));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(feature = "language")]
+super::language::language_adaptors!(FileLoader);
+
 // ================================================================
 // Iterators for FileLoader
 // ================================================================
@@ -237,7 +461,12 @@ impl<T> Iterator for IntoIter<'_, T> {
 
 #[cfg(test)]
 mod tests {
-    use assert_fs::prelude::{FileTouch, FileWriteStr, PathChild};
+    #[cfg(feature = "tokio-fs")]
+    use std::io::Write;
+
+    use assert_fs::prelude::{FileTouch, FileWriteStr, PathChild, PathCreateDir};
+    #[cfg(feature = "tokio-fs")]
+    use futures::StreamExt;
 
     use super::FileLoader;
 
@@ -270,4 +499,118 @@ mod tests {
         assert!(!actual.is_empty());
         assert!(expected == actual)
     }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn test_stream_lines() {
+        const LINES: usize = 200_000;
+        const LINES_PER_DOCUMENT: usize = 1000;
+
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let log_file = temp.child("large.log");
+        {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(log_file.path()).unwrap());
+            for i in 0..LINES {
+                write!(file, "{i:06} Paid 3 flurbos (≈ 6 glarbs)\r\n").unwrap();
+            }
+        }
+        let line_len = "000000 Paid 3 flurbos (≈ 6 glarbs)".len();
+
+        let glob = temp.path().to_string_lossy().to_string() + "/*.log";
+        let (documents, lines, max_len) = FileLoader::with_glob(&glob)
+            .unwrap()
+            .stream_lines(LINES_PER_DOCUMENT)
+            .fold(
+                (0, 0, 0),
+                |(documents, lines, max_len), document| async move {
+                    let document = document.unwrap();
+                    assert!(document.lines().all(|line| line.ends_with("(≈ 6 glarbs)")));
+                    (
+                        documents + 1,
+                        lines + document.lines().count(),
+                        max_len.max(document.len()),
+                    )
+                },
+            )
+            .await;
+
+        assert_eq!(lines, LINES);
+        assert_eq!(documents, LINES / LINES_PER_DOCUMENT);
+        // Only one document is buffered at a time
+        assert_eq!(max_len, LINES_PER_DOCUMENT * (line_len + 1) - 1);
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn test_stream_lines_partial_document() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let file = temp.child("flurbos.txt");
+        file.write_str("one\ntwo\nthree").unwrap();
+
+        let documents = FileLoader::with_glob(&file.path().to_string_lossy())
+            .unwrap()
+            .stream_lines(2)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(documents, vec!["one\ntwo", "three"]);
+    }
+
+    #[test]
+    fn test_file_loader_into_documents() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        temp.child("flurbo.txt").write_str("Flurbos").unwrap();
+
+        let documents = FileLoader::with_dir(temp.path().to_str().unwrap())
+            .unwrap()
+            .read_with_path()
+            .into_documents()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(documents.len(), 1);
+        assert_eq!(
+            documents[0].id,
+            temp.child("flurbo.txt").path().display().to_string()
+        );
+        assert_eq!(documents[0].text, "Flurbos");
+        assert!(documents[0].additional_props.is_empty());
+    }
+
+    #[test]
+    fn test_file_loader_recursive() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        temp.child("a.txt").write_str("a").unwrap();
+        temp.child("sub/b.txt").write_str("b").unwrap();
+        temp.child("sub/deep/c.txt").write_str("c").unwrap();
+        temp.child("sub/empty").create_dir_all().unwrap();
+
+        // Symlink loop back to the root of the tree
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(temp.path(), temp.child("sub/deep/loop").path()).unwrap();
+
+        let files = FileLoader::with_dir_recursive(temp.path().to_str().unwrap())
+            .unwrap()
+            .read_with_path()
+            .ignore_errors()
+            .into_iter()
+            .map(|(path, content)| {
+                let path = path.strip_prefix(temp.path()).unwrap().to_path_buf();
+                (path.to_str().unwrap().replace('\\', "/"), content)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            files,
+            vec![
+                ("a.txt".to_string(), "a".to_string()),
+                ("sub/b.txt".to_string(), "b".to_string()),
+                ("sub/deep/c.txt".to_string(), "c".to_string()),
+            ]
+        );
+
+        assert!(FileLoader::with_dir_recursive("does/not/exist").is_err());
+    }
 }
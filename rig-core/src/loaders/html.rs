@@ -0,0 +1,671 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use glob::glob;
+use thiserror::Error;
+
+use super::{
+    cleanup::{Cleanable, TextCleanup},
+    file::{FileLoaderError, WalkDir},
+    mime,
+};
+use crate::completion::Document;
+
+#[derive(Error, Debug)]
+pub enum HtmlLoaderError {
+    #[error("{0}")]
+    FileLoaderError(#[from] FileLoaderError),
+
+    #[error("UTF-8 conversion error: {0}")]
+    FromUtf8Error(#[from] std::string::FromUtf8Error),
+}
+
+// ================================================================
+// Implementing Loadable trait for loading html files
+// ================================================================
+
+pub(crate) trait Loadable {
+    fn load(self) -> Result<String, HtmlLoaderError>;
+    fn load_with_path(self) -> Result<(PathBuf, String), HtmlLoaderError>;
+}
+
+impl Loadable for PathBuf {
+    fn load(self) -> Result<String, HtmlLoaderError> {
+        let bytes = fs::read(self).map_err(FileLoaderError::IoError)?;
+        Ok(String::from_utf8(bytes)?)
+    }
+    fn load_with_path(self) -> Result<(PathBuf, String), HtmlLoaderError> {
+        let contents = self.clone().load()?;
+        Ok((self, contents))
+    }
+}
+
+impl<T: Loadable> Loadable for Result<T, HtmlLoaderError> {
+    fn load(self) -> Result<String, HtmlLoaderError> {
+        self.map(|t| t.load())?
+    }
+    fn load_with_path(self) -> Result<(PathBuf, String), HtmlLoaderError> {
+        self.map(|t| t.load_with_path())?
+    }
+}
+
+// ================================================================
+// HTML text extraction helpers
+// ================================================================
+
+/// Convert (X)HTML to plain text: tags are removed, the contents of `head`, `script` and
+///  `style` elements are dropped, block elements are separated by newlines and common
+///  character entities are decoded.
+pub(crate) fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut skipped_element: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if skipped_element.is_none() {
+            text.push_str(&decode_entities(&rest[..start]));
+        }
+
+        // Unclosed tag: drop the remainder of the document
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if let Some(skipped) = &skipped_element {
+            if closing && *skipped == name {
+                skipped_element = None;
+            }
+            continue;
+        }
+
+        if !closing && !tag.ends_with('/') && matches!(name.as_str(), "head" | "script" | "style") {
+            skipped_element = Some(name);
+        } else if is_block_element(&name) {
+            text.push('\n');
+        }
+    }
+
+    if skipped_element.is_none() {
+        text.push_str(&decode_entities(rest));
+    }
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_block_element(name: &str) -> bool {
+    matches!(
+        name,
+        "p" | "div"
+            | "br"
+            | "hr"
+            | "li"
+            | "tr"
+            | "dt"
+            | "dd"
+            | "pre"
+            | "blockquote"
+            | "section"
+            | "article"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+    )
+}
+
+/// Decode the predefined XML entities, `&nbsp;` and numeric character references.
+/// Unknown entities are left as is.
+pub(crate) fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .map(|end| &rest[1..end + 1])
+            .and_then(|entity| {
+                let c = match entity {
+                    "amp" => '&',
+                    "lt" => '<',
+                    "gt" => '>',
+                    "quot" => '"',
+                    "apos" => '\'',
+                    "nbsp" => ' ',
+                    _ => {
+                        let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                            None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                        };
+                        code.and_then(char::from_u32)?
+                    }
+                };
+                Some((c, entity.len() + 2))
+            });
+
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Targets of the `href` attributes of the tags of `html` (e.g.: links, stylesheets), in
+///  document order and without duplicates. Character entities of the targets are decoded.
+fn links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = vec![];
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        // Unclosed tag: its attributes are ignored
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        if tag.starts_with(['/', '!', '?']) {
+            continue;
+        }
+
+        if let Some(href) = attribute(tag, "href") {
+            let href = decode_entities(href.trim());
+            if !href.is_empty() && !links.contains(&href) {
+                links.push(href);
+            }
+        }
+    }
+
+    links
+}
+
+/// Value of the attribute `name` of `tag` (the content of a tag, without its angle brackets).
+///  Values may be double-quoted, single-quoted or unquoted.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    // Skip the name of the tag
+    let mut rest = tag.trim_start_matches(|c: char| !c.is_whitespace() && c != '/');
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let attribute = &rest[..name_end];
+        rest = rest[name_end..].trim_start();
+
+        // Attribute without value (e.g.: `<input disabled>`)
+        let Some(value) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+
+        let (value, remainder) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &value[1..];
+                // Unclosed quote: the value extends to the end of the tag
+                match value.find(quote) {
+                    Some(end) => (&value[..end], &value[end + 1..]),
+                    None => (value, ""),
+                }
+            }
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+
+        if attribute.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = remainder;
+    }
+}
+
+/// Whether `result` is not the path of an html file (i.e.: to be skipped when loading all the
+///  files of a directory). Errors are kept.
+fn is_html<E>(result: &Result<PathBuf, E>) -> bool {
+    match result {
+        Ok(path) => mime::from_extension(path) == Some(mime::HTML),
+        Err(_) => true,
+    }
+}
+
+// ================================================================
+// HtmlFileLoader definitions and implementations
+// ================================================================
+
+/// [HtmlFileLoader] is a utility for loading html and xhtml files from the filesystem using
+///  glob patterns or directory paths (e.g.: a local dump of a website). It provides methods to
+///  extract the plain text of the documents and the targets of their links.
+///
+/// Text is extracted the same way as for [EpubFileLoader](super::EpubFileLoader) chapters:
+///  tags are removed, the contents of `head`, `script` and `style` elements are dropped, block
+///  elements are separated by newlines and common character entities are decoded. Malformed
+///  documents (e.g.: unclosed tags) are extracted on a best-effort basis.
+///
+/// # Errors
+///
+/// This module defines a custom error type [HtmlLoaderError] which can represent any
+///  [FileLoaderError] that might occur during file loading operations, as well as documents
+///  which are not valid UTF-8.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use rig::loaders::HtmlFileLoader;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     // Create a HtmlFileLoader using a glob pattern
+///     let loader = HtmlFileLoader::with_glob("site/**/*.html")?;
+///
+///     // Read the text of the html files, ignoring any errors
+///     let contents: Vec<String> = loader
+///         .read()
+///         .ignore_errors()
+///         .into_iter()
+///         .collect();
+///
+///     for content in contents {
+///         println!("{}", content);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [HtmlFileLoader] uses strict typing between the iterator methods to ensure that transitions
+///  between different implementations of the loaders and it's methods are handled properly by
+///  the compiler.
+pub struct HtmlFileLoader<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a> HtmlFileLoader<'a, Result<PathBuf, HtmlLoaderError>> {
+    /// Reads the plain text of the html files within the iterator returned by
+    ///  [HtmlFileLoader::with_glob] or [HtmlFileLoader::with_dir].
+    ///
+    /// # Example
+    /// Read html files in directory "site/*.html" and return their text.
+    ///
+    /// ```rust
+    /// let content = HtmlFileLoader::with_glob("site/*.html")?.read().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok(content) => println!("{}", content),
+    ///         Err(e) => eprintln!("Error reading html: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read(self) -> HtmlFileLoader<'a, Result<String, HtmlLoaderError>> {
+        HtmlFileLoader {
+            iterator: Box::new(
+                self.iterator
+                    .map(|res| res.load().map(|html| strip_html(&html))),
+            ),
+        }
+    }
+
+    /// Reads the plain text of the html files within the iterator returned by
+    ///  [HtmlFileLoader::with_glob] or [HtmlFileLoader::with_dir] and returns the path along
+    ///  with the text.
+    ///
+    /// # Example
+    /// Read html files in directory "site/*.html" and return their text and paths.
+    ///
+    /// ```rust
+    /// let content = HtmlFileLoader::with_glob("site/*.html")?.read_with_path().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, content)) => println!("{:?} {}", path, content),
+    ///         Err(e) => eprintln!("Error reading html: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_with_path(self) -> HtmlFileLoader<'a, Result<(PathBuf, String), HtmlLoaderError>> {
+        HtmlFileLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let (path, html) = res.load_with_path()?;
+                Ok((path, strip_html(&html)))
+            })),
+        }
+    }
+
+    /// Reads the plain text of the html files like [HtmlFileLoader::read_with_path], along with
+    ///  the targets of the `href` attributes found in each document (in document order and
+    ///  without duplicates), e.g.: to crawl a local dump of a website. Targets are returned as
+    ///  is (i.e.: relative targets are not resolved against the path of the document).
+    ///
+    /// # Example
+    /// Read html files in directory "site/*.html" and print their links.
+    ///
+    /// ```rust
+    /// let content = HtmlFileLoader::with_glob("site/*.html")?.links().into_iter();
+    /// for result in content {
+    ///     match result {
+    ///         Ok((path, _text, links)) => println!("{:?} links to {:?}", path, links),
+    ///         Err(e) => eprintln!("Error reading html: {}", e),
+    ///     }
+    /// }
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn links(
+        self,
+    ) -> HtmlFileLoader<'a, Result<(PathBuf, String, Vec<String>), HtmlLoaderError>> {
+        HtmlFileLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let (path, html) = res.load_with_path()?;
+                Ok((path, strip_html(&html), links(&html)))
+            })),
+        }
+    }
+}
+
+impl<'a> HtmlFileLoader<'a, Result<(PathBuf, String), HtmlLoaderError>> {
+    /// Converts the html files read by [HtmlFileLoader::read_with_path] into [Document]s whose
+    ///  id is the path of their file, which can be embedded directly with an
+    ///  [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder).
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = HtmlFileLoader::with_dir_recursive("site")?
+    ///     .read_with_path()
+    ///     .into_documents()
+    ///     .ignore_errors();
+    /// ```
+    pub fn into_documents(self) -> HtmlFileLoader<'a, Result<Document, HtmlLoaderError>> {
+        HtmlFileLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let (path, text) = res?;
+                Ok(Document {
+                    id: path.display().to_string(),
+                    text,
+                    additional_props: HashMap::new(),
+                })
+            })),
+        }
+    }
+}
+
+impl<'a, T: 'a> HtmlFileLoader<'a, Result<T, HtmlLoaderError>> {
+    /// Ignores errors in the iterator, returning only successful results. This can be used on any
+    ///  [HtmlFileLoader] state of iterator whose items are results.
+    ///
+    /// # Example
+    /// Read files in directory "site/*.html" and ignore errors from unreadable files.
+    ///
+    /// ```rust
+    /// let content = HtmlFileLoader::with_glob("site/*.html")?.read().ignore_errors().into_iter();
+    /// for content in content {
+    ///     println!("{}", content)
+    /// }
+    /// ```
+    pub fn ignore_errors(self) -> HtmlFileLoader<'a, T> {
+        HtmlFileLoader {
+            iterator: Box::new(self.iterator.filter_map(|res| res.ok())),
+        }
+    }
+}
+
+impl<'a, T: Cleanable + 'a> HtmlFileLoader<'a, T> {
+    /// Cleans the text of the html files with the given [TextCleanup] (e.g.: collapsing runs of
+    ///  whitespace and normalizing unicode).
+    ///
+    /// # Example
+    /// ```rust
+    /// let content = HtmlFileLoader::with_glob("site/*.html")?
+    ///     .read_with_path()
+    ///     .clean(TextCleanup::default());
+    /// ```
+    pub fn clean(self, cleanup: TextCleanup) -> HtmlFileLoader<'a, T> {
+        HtmlFileLoader {
+            iterator: Box::new(self.iterator.map(move |item| item.clean(&cleanup))),
+        }
+    }
+}
+
+impl HtmlFileLoader<'_, Result<PathBuf, FileLoaderError>> {
+    /// Creates a new [HtmlFileLoader] using a glob pattern to match files.
+    ///
+    /// # Example
+    /// Create a [HtmlFileLoader] for all `.html` files that match the glob "site/*.html".
+    ///
+    /// ```rust
+    /// let loader = HtmlFileLoader::with_glob("site/*.html")?;
+    /// ```
+    pub fn with_glob(
+        pattern: &str,
+    ) -> Result<HtmlFileLoader<Result<PathBuf, HtmlLoaderError>>, HtmlLoaderError> {
+        let paths = glob(pattern).map_err(FileLoaderError::PatternError)?;
+        Ok(HtmlFileLoader {
+            iterator: Box::new(paths.into_iter().map(|path| {
+                path.map_err(FileLoaderError::GlobError)
+                    .map_err(HtmlLoaderError::FileLoaderError)
+            })),
+        })
+    }
+
+    /// Creates a new [HtmlFileLoader] on the html files (i.e.: `.html`, `.htm` and `.xhtml`
+    ///  files) within a directory.
+    ///
+    /// # Example
+    /// Create a [HtmlFileLoader] for all html files that are in the directory "site".
+    ///
+    /// ```rust
+    /// let loader = HtmlFileLoader::with_dir("site")?;
+    /// ```
+    pub fn with_dir(
+        directory: &str,
+    ) -> Result<HtmlFileLoader<Result<PathBuf, HtmlLoaderError>>, HtmlLoaderError> {
+        Ok(HtmlFileLoader {
+            iterator: Box::new(
+                fs::read_dir(directory)
+                    .map_err(FileLoaderError::IoError)?
+                    .map(|entry| Ok(entry.map_err(FileLoaderError::IoError)?.path()))
+                    .filter(is_html),
+            ),
+        })
+    }
+
+    /// Creates a new [HtmlFileLoader] on the html files within a directory and its
+    ///  subdirectories, at any depth. Symlinks are followed, and directories that were already
+    ///  visited (i.e.: symlink loops) are skipped.
+    ///
+    /// # Example
+    /// Create a [HtmlFileLoader] for all html files that are in the directory "site" or its subdirectories.
+    ///
+    /// ```rust
+    /// let loader = HtmlFileLoader::with_dir_recursive("site")?;
+    /// ```
+    pub fn with_dir_recursive(
+        directory: &str,
+    ) -> Result<HtmlFileLoader<Result<PathBuf, HtmlLoaderError>>, HtmlLoaderError> {
+        Ok(HtmlFileLoader {
+            iterator: Box::new(
+                WalkDir::new(directory)?
+                    .map(|path| path.map_err(HtmlLoaderError::FileLoaderError))
+                    .filter(is_html),
+            ),
+        })
+    }
+}
+
+#[cfg(feature = "language")]
+super::language::language_adaptors!(HtmlFileLoader);
+
+// ================================================================
+// HtmlFileLoader iterator implementations
+// ================================================================
+
+pub struct IntoIter<'a, T> {
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<'a, T> IntoIterator for HtmlFileLoader<'a, T> {
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iterator: self.iterator,
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iterator.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::{FileWriteBin, FileWriteStr, PathChild};
+
+    use super::{links, strip_html, HtmlFileLoader, HtmlLoaderError};
+
+    fn site() -> assert_fs::TempDir {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        temp.child("index.html")
+            .write_str(
+                r#"<!DOCTYPE html>
+<html><head><title>Index</title><link rel="stylesheet" href="style.css"></head>
+<body><h1>Flurbos</h1><p>See <a href="glarbs.xhtml">glarbs</a> and <a class=ext href='https://example.com/?a=1&amp;b=2'>elsewhere</a>.</p>
+<a href="glarbs.xhtml">Again</a></body></html>"#,
+            )
+            .unwrap();
+        temp.child("docs/glarbs.xhtml")
+            .write_str("<html><body><p>Glarbs are ancient creatures.</p></body></html>")
+            .unwrap();
+        temp.child("style.css")
+            .write_str("p { margin: 0; }")
+            .unwrap();
+        temp
+    }
+
+    #[test]
+    fn test_html_loader_read() {
+        let temp = site();
+
+        let mut docs = HtmlFileLoader::with_dir_recursive(temp.path().to_str().unwrap())
+            .unwrap()
+            .read_with_path()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        docs.sort();
+
+        // style.css is not an html file
+        assert_eq!(docs.len(), 2);
+        assert!(docs[0].0.ends_with("docs/glarbs.xhtml"));
+        assert_eq!(docs[0].1, "Glarbs are ancient creatures.");
+        assert!(docs[1].0.ends_with("index.html"));
+        assert_eq!(docs[1].1, "Flurbos\nSee glarbs and elsewhere.\nAgain");
+
+        let docs = HtmlFileLoader::with_dir(temp.path().to_str().unwrap())
+            .unwrap()
+            .read()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(docs, vec!["Flurbos\nSee glarbs and elsewhere.\nAgain"]);
+    }
+
+    #[test]
+    fn test_html_loader_links() {
+        let temp = site();
+
+        let docs = HtmlFileLoader::with_glob(&format!("{}/*.html", temp.path().display()))
+            .unwrap()
+            .links()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(
+            docs[0].2,
+            vec!["style.css", "glarbs.xhtml", "https://example.com/?a=1&b=2"]
+        );
+    }
+
+    #[test]
+    fn test_malformed_html() {
+        // Unclosed elements and tags
+        let html = "<html><body><p>Flurbos <b>are<p>made up<div class=\"unclosed";
+        assert_eq!(strip_html(html), "Flurbos are\nmade up");
+        assert_eq!(
+            links("<a href=\"one.html\">One</a><a href=\"two.html"),
+            vec!["one.html"]
+        );
+
+        // Unclosed quotes, attributes without values and stray brackets
+        let html = "<a download href=\"one.html>One</a> 1 < 2 <a href=two.html>Two</a> > <a href>";
+        assert_eq!(links(html), vec!["one.html", "two.html"]);
+        assert_eq!(strip_html(html), "One 1 Two >");
+
+        assert!(strip_html("").is_empty());
+        assert!(links("<<<>>>").is_empty());
+    }
+
+    #[test]
+    fn test_html_loader_invalid_utf8() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        temp.child("invalid.html")
+            .write_binary(&[0x3c, 0x70, 0x3e, 0xff, 0xfe])
+            .unwrap();
+        temp.child("valid.html").write_str("<p>Flurbo</p>").unwrap();
+
+        let mut results = HtmlFileLoader::with_dir(temp.path().to_str().unwrap())
+            .unwrap()
+            .read_with_path()
+            .into_iter()
+            .collect::<Vec<_>>();
+        results.sort_by_key(|result| result.is_ok());
+
+        assert!(matches!(results[0], Err(HtmlLoaderError::FromUtf8Error(_))));
+        assert_eq!(results[1].as_ref().unwrap().1, "Flurbo");
+    }
+
+    #[test]
+    fn test_strip_html() {
+        let html = r#"<?xml version="1.0"?>
+<html><head><title>Ignored</title><style>p { color: red; }</style></head>
+<body><h1>Title</h1><p>Some&nbsp;<b>bold</b>   text &#x26; &#38; &unknown;</p><br/>End"#;
+
+        assert_eq!(strip_html(html), "Title\nSome bold text & & &unknown;\nEnd");
+    }
+}
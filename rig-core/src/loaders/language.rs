@@ -0,0 +1,235 @@
+//! The module defines the [LanguageDetection] step, which tags loaded documents with the
+//! language of their text (detected with [whatlang]), so that they can be filtered by language
+//! before they are embedded (e.g.: to only embed the english documents of a multilingual corpus).
+//!
+//! Every loader producing [Document]s provides a `detect_language` method tagging its documents
+//! and a `filter_language` method keeping the documents tagged with one of the given languages:
+//! ```rust
+//! use rig::loaders::{language::LanguageDetection, FileLoader};
+//!
+//! let documents = FileLoader::with_glob("tests/data/languages/*.txt")?
+//!     .read_with_path()
+//!     .into_documents()
+//!     .detect_language(LanguageDetection::default())
+//!     .filter_language(&["eng", "fra"])
+//!     .ignore_errors();
+//! ```
+//!
+//! Note: This module requires the `language` feature to be enabled in the `Cargo.toml` file.
+use crate::completion::Document;
+
+/// Key of the [Document::additional_props] holding the detected language (distinct from the
+/// `language` declared in the metadata of epubs, which is kept)
+pub const LANGUAGE_KEY: &str = "detected_language";
+
+/// Language code of the documents whose language could not be detected reliably
+pub const UNKNOWN_LANGUAGE: &str = "unknown";
+
+/// Configurable language detection step. Languages are identified by their ISO 639-3 code
+/// (e.g.: `"eng"`, `"fra"`, `"deu"`), and documents whose language cannot be detected with a
+/// confidence of at least [LanguageDetection::min_confidence] (`0.5` by default, e.g.: short or
+/// mostly numeric texts) are tagged [UNKNOWN_LANGUAGE].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LanguageDetection {
+    min_confidence: f64,
+}
+
+impl Default for LanguageDetection {
+    fn default() -> Self {
+        Self {
+            min_confidence: 0.5,
+        }
+    }
+}
+
+impl LanguageDetection {
+    /// Set the minimum confidence (between `0.0` and `1.0`) of the detections, below which the
+    /// documents are tagged [UNKNOWN_LANGUAGE].
+    pub fn min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    /// ISO 639-3 code of the language of `text`, or [UNKNOWN_LANGUAGE].
+    pub fn detect(&self, text: &str) -> &'static str {
+        match whatlang::detect(text) {
+            Some(info) if info.confidence() >= self.min_confidence => info.lang().code(),
+            _ => UNKNOWN_LANGUAGE,
+        }
+    }
+}
+
+// ================================================================
+// Implementing Detectable trait for the items of the loaders
+// ================================================================
+
+/// Items of the loaders which can be tagged with the language of their text. Errors are kept as
+/// is by both the tagging and the filtering, so that they can still be handled (e.g.: with
+/// `ignore_errors`).
+pub trait Detectable {
+    /// Tag the item with the language of its text.
+    fn detect_language(self, detection: &LanguageDetection) -> Self;
+
+    /// Whether the item is tagged with one of `languages`.
+    fn has_language(&self, languages: &[String]) -> bool;
+}
+
+impl Detectable for Document {
+    fn detect_language(mut self, detection: &LanguageDetection) -> Self {
+        let language = detection.detect(&self.text);
+        self.additional_props
+            .insert(LANGUAGE_KEY.to_string(), language.to_string());
+        self
+    }
+
+    fn has_language(&self, languages: &[String]) -> bool {
+        self.additional_props
+            .get(LANGUAGE_KEY)
+            .is_some_and(|language| languages.contains(language))
+    }
+}
+
+impl<T: Detectable, E> Detectable for Result<T, E> {
+    fn detect_language(self, detection: &LanguageDetection) -> Self {
+        self.map(|item| item.detect_language(detection))
+    }
+
+    fn has_language(&self, languages: &[String]) -> bool {
+        match self {
+            Ok(item) => item.has_language(languages),
+            Err(_) => true,
+        }
+    }
+}
+
+// ================================================================
+// Language adaptors of the loaders
+// ================================================================
+
+/// Implements `detect_language` and `filter_language` for a loader wrapping a boxed `iterator` of
+/// [Detectable] items, carrying its other fields through (e.g.: `language_adaptors!(EpubFileLoader
+/// { password })`).
+macro_rules! language_adaptors {
+    ($loader:ident $({ $($field:ident),* })?) => {
+        impl<'a, T: $crate::loaders::language::Detectable + 'a> $loader<'a, T> {
+            /// Tags the documents with the language of their text, detected with the given
+            ///  [LanguageDetection](crate::loaders::LanguageDetection) (see the
+            ///  [language](crate::loaders::language) module).
+            pub fn detect_language(
+                self,
+                detection: $crate::loaders::language::LanguageDetection,
+            ) -> $loader<'a, T> {
+                $loader {
+                    iterator: Box::new(self.iterator.map(move |item| {
+                        $crate::loaders::language::Detectable::detect_language(item, &detection)
+                    })),
+                    $($($field: self.$field,)*)?
+                }
+            }
+
+            /// Keeps the documents tagged by `detect_language` with one of the given ISO 639-3
+            ///  language codes (e.g.: `"eng"`, `"unknown"`). Errors are kept.
+            pub fn filter_language(self, languages: &[&str]) -> $loader<'a, T> {
+                let languages = languages
+                    .iter()
+                    .map(|language| language.to_string())
+                    .collect::<Vec<_>>();
+                $loader {
+                    iterator: Box::new(self.iterator.filter(move |item| {
+                        $crate::loaders::language::Detectable::has_language(item, &languages)
+                    })),
+                    $($($field: self.$field,)*)?
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use language_adaptors;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{LanguageDetection, LANGUAGE_KEY, UNKNOWN_LANGUAGE};
+    use crate::loaders::FileLoader;
+
+    fn languages(loader: FileLoader<'_, crate::completion::Document>) -> HashMap<String, String> {
+        loader
+            .into_iter()
+            .map(|document| {
+                let name = document.id.rsplit('/').next().unwrap().to_string();
+                (name, document.additional_props[LANGUAGE_KEY].clone())
+            })
+            .collect()
+    }
+
+    fn load() -> FileLoader<'static, crate::completion::Document> {
+        FileLoader::with_glob("tests/data/languages/*.txt")
+            .unwrap()
+            .read_with_path()
+            .into_documents()
+            .detect_language(LanguageDetection::default())
+            .ignore_errors()
+    }
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            languages(load()),
+            HashMap::from([
+                ("english.txt".to_string(), "eng".to_string()),
+                ("french.txt".to_string(), "fra".to_string()),
+                ("german.txt".to_string(), "deu".to_string()),
+                ("numbers.txt".to_string(), UNKNOWN_LANGUAGE.to_string()),
+            ])
+        );
+
+        let detection = LanguageDetection::default().min_confidence(1.1);
+        assert_eq!(
+            detection.detect(include_str!("../../tests/data/languages/english.txt")),
+            UNKNOWN_LANGUAGE
+        );
+    }
+
+    #[test]
+    fn test_filter_language() {
+        assert_eq!(
+            languages(load().filter_language(&["fra", "deu"])),
+            HashMap::from([
+                ("french.txt".to_string(), "fra".to_string()),
+                ("german.txt".to_string(), "deu".to_string()),
+            ])
+        );
+        assert_eq!(
+            languages(load().filter_language(&[UNKNOWN_LANGUAGE])),
+            HashMap::from([("numbers.txt".to_string(), UNKNOWN_LANGUAGE.to_string())])
+        );
+        assert!(languages(load().filter_language(&["spa"])).is_empty());
+    }
+
+    #[cfg(feature = "epub")]
+    #[test]
+    fn test_epub_language() {
+        use crate::loaders::EpubFileLoader;
+
+        let load = || {
+            EpubFileLoader::with_glob("tests/data/chapters*.epub")
+                .unwrap()
+                .with_password("glarb")
+                .load_with_path()
+                .by_chapter_with_path()
+                .into_documents()
+                .detect_language(LanguageDetection::default())
+        };
+        let documents = load().into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(documents.len(), 6);
+        let detected = documents
+            .iter()
+            .map(|document| document.additional_props[LANGUAGE_KEY].as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(load().filter_language(&detected).into_iter().count(), 6);
+        assert_eq!(load().filter_language(&["spa"]).into_iter().count(), 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! MIME type detection utilities, used to dispatch loaded content to the right parser.
+//!
+//! The MIME type of some content is inferred from its magic bytes (using the [infer] crate)
+//! and, when the bytes are not recognized, from the extension of its path.
+//!
+//! # Example
+//! ```rust
+//! use rig::loaders::mime;
+//!
+//! let bytes = std::fs::read("tests/data/dummy.pdf")?;
+//! assert_eq!(mime::detect(&bytes, Some("tests/data/dummy.pdf".as_ref())), Some(mime::PDF));
+//! ```
+use std::path::Path;
+
+pub const PDF: &str = "application/pdf";
+pub const EPUB: &str = "application/epub+zip";
+pub const HTML: &str = "text/html";
+pub const XML: &str = "text/xml";
+pub const PLAIN_TEXT: &str = "text/plain";
+pub const MARKDOWN: &str = "text/markdown";
+pub const JSON: &str = "application/json";
+pub const CSV: &str = "text/csv";
+pub const DOCX: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+pub const ZIP: &str = "application/zip";
+pub const TAR: &str = "application/x-tar";
+pub const GZIP: &str = "application/gzip";
+pub const EML: &str = "message/rfc822";
+pub const MBOX: &str = "application/mbox";
+
+/// Detect the MIME type of `bytes`, first from their magic bytes and then, if they are not
+/// recognized (or only recognized as a generic zip archive or XML document), from the
+/// extension of `path`. Returns `None` if neither is conclusive.
+pub fn detect(bytes: &[u8], path: Option<&Path>) -> Option<&'static str> {
+    let inferred = from_bytes(bytes);
+
+    match inferred {
+        Some(ZIP) | Some(XML) | None => path.and_then(from_extension).or(inferred),
+        _ => inferred,
+    }
+}
+
+/// Detect the MIME type of `bytes` from their magic bytes only.
+pub fn from_bytes(bytes: &[u8]) -> Option<&'static str> {
+    infer::get(bytes).map(|kind| kind.mime_type())
+}
+
+/// Detect the MIME type of a file from the extension of its path only.
+pub fn from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    Some(match extension.as_str() {
+        "pdf" => PDF,
+        "epub" => EPUB,
+        "html" | "htm" | "xhtml" => HTML,
+        "xml" => XML,
+        "txt" => PLAIN_TEXT,
+        "md" | "markdown" => MARKDOWN,
+        "json" => JSON,
+        "csv" => CSV,
+        "docx" => DOCX,
+        "zip" => ZIP,
+        "tar" => TAR,
+        "gz" | "tgz" => GZIP,
+        "eml" => EML,
+        "mbox" => MBOX,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{detect, from_bytes, EPUB, HTML, MARKDOWN, PDF};
+
+    #[test]
+    fn test_detect_pdf() {
+        let bytes = std::fs::read("tests/data/dummy.pdf").unwrap();
+        assert_eq!(from_bytes(&bytes), Some(PDF));
+        assert_eq!(detect(&bytes, None), Some(PDF));
+    }
+
+    #[test]
+    fn test_detect_epub() {
+        let bytes = std::fs::read("tests/data/chapters.epub").unwrap();
+        assert_eq!(detect(&bytes, None), Some(EPUB));
+        assert_eq!(detect(&bytes, Some(Path::new("book.zip"))), Some(EPUB));
+    }
+
+    #[test]
+    fn test_detect_html() {
+        let bytes =
+            b"<!DOCTYPE html>\n<html><body><p>Flurbos are a made up currency.</p></body></html>";
+        assert_eq!(detect(bytes, None), Some(HTML));
+    }
+
+    #[test]
+    fn test_detect_from_extension() {
+        let bytes = b"# Flurbos\n\nFlurbos are a made up currency.";
+        assert_eq!(detect(bytes, None), None);
+        assert_eq!(
+            detect(bytes, Some(Path::new("notes/flurbos.MD"))),
+            Some(MARKDOWN)
+        );
+    }
+}
@@ -6,16 +6,85 @@
 //!
 //! The [PdfFileLoader] works similarly to the [FileLoader], but is specifically designed to load PDF
 //! files. This loader also provides PDF-specific preprocessing methods for splitting the PDF into pages
-//! and keeping track of the page numbers along with their contents.
+//! and keeping track of the page numbers along with their contents, optionally extracting the tables of
+//! the pages as rows of cells separately from their flowing text.
+//!
+//! The [EpubFileLoader] works similarly to the [PdfFileLoader], but is specifically designed to load
+//! epub files. This loader provides epub-specific preprocessing methods for splitting the epub into
+//! chapters and keeping track of the chapter titles along with their contents.
+//!
+//! The [DocxFileLoader] works similarly to the [EpubFileLoader], but is specifically designed to load
+//! docx files, providing a preprocessing method for splitting the documents into paragraphs.
+//!
+//! The [HtmlFileLoader] loads html and xhtml files (e.g.: a local dump of a website), extracting their
+//! plain text the same way as epub chapters, as well as the targets of their links.
+//!
+//! The [EmailFileLoader] loads emails from `.eml` files and `.mbox` archives (e.g.: exported
+//! support tickets), extracting their headers and their text, and optionally their attachments.
+//!
+//! The [ArchiveLoader] loads the documents contained in zip and tar archives, dispatching each
+//! document to the right loader based on its MIME type.
+//!
+//! The [mime] module provides MIME type detection (from magic bytes and file extensions) to
+//! classify loaded content and dispatch it to the right loader.
+//!
+//! The [cleanup] module provides the [TextCleanup] post-processor, usable with any loader to clean
+//! up extracted text (e.g.: rejoining words hyphenated across line breaks, collapsing runs of
+//! whitespace and normalizing unicode).
+//!
+//! The [language] module provides the [LanguageDetection] step, usable with any loader producing
+//! documents to tag them with the language of their text and filter them by language.
 //!
 //! Note: The [PdfFileLoader] requires the `pdf` feature to be enabled in the `Cargo.toml` file.
+//! Likewise, the [EpubFileLoader] requires the `epub` feature, the [DocxFileLoader] the `docx`
+//! feature, the [ArchiveLoader] the `archive` feature and the [language] module the `language`
+//! feature (pdfs, epubs and docx documents within archives are only loaded with the `pdf`, `epub`
+//! and `docx` features).
+
+pub mod cleanup;
+
+pub use cleanup::TextCleanup;
+
+#[cfg(feature = "language")]
+pub mod language;
+
+#[cfg(feature = "language")]
+pub use language::LanguageDetection;
 
 pub mod file;
 
 pub use file::FileLoader;
 
+pub mod html;
+
+pub use html::HtmlFileLoader;
+
+pub mod email;
+
+pub use email::EmailFileLoader;
+
+pub mod mime;
+
 #[cfg(feature = "pdf")]
 pub mod pdf;
 
 #[cfg(feature = "pdf")]
 pub use pdf::PdfFileLoader;
+
+#[cfg(feature = "epub")]
+pub mod epub;
+
+#[cfg(feature = "epub")]
+pub use epub::EpubFileLoader;
+
+#[cfg(feature = "docx")]
+pub mod docx;
+
+#[cfg(feature = "docx")]
+pub use docx::DocxFileLoader;
+
+#[cfg(feature = "archive")]
+pub mod archive;
+
+#[cfg(feature = "archive")]
+pub use archive::ArchiveLoader;
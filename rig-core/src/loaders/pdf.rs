@@ -1,10 +1,14 @@
-use std::{fs, path::PathBuf};
+use std::{collections::HashMap, fs, path::PathBuf};
 
 use glob::glob;
-use lopdf::{Document, Error as LopdfError};
+use lopdf::{content::Content, Document, Error as LopdfError, Object, ObjectId};
 use thiserror::Error;
 
-use super::file::FileLoaderError;
+use super::{
+    cleanup::{Cleanable, TextCleanup},
+    file::{FileLoaderError, WalkDir},
+};
+use crate::completion;
 
 #[derive(Error, Debug)]
 pub enum PdfLoaderError {
@@ -18,6 +22,295 @@ pub enum PdfLoaderError {
     PdfError(#[from] LopdfError),
 }
 
+// ================================================================
+// Table extraction
+// ================================================================
+
+/// Table detected on a page of a pdf by [PdfFileLoader::by_page_with_tables], as rows of cells.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PdfTable {
+    pub rows: Vec<Vec<String>>,
+}
+
+impl PdfTable {
+    /// Text of the table, one row per line with its cells separated by ` | `.
+    pub fn to_text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| row.join(" | "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Page of a pdf read by [PdfFileLoader::by_page_with_tables]: its tables and its flowing text,
+///  i.e.: the lines of the page which are not part of a table.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PdfPage {
+    pub text: String,
+    pub tables: Vec<PdfTable>,
+}
+
+impl Cleanable for PdfPage {
+    fn clean(self, cleanup: &TextCleanup) -> Self {
+        Self {
+            text: self.text.clean(cleanup),
+            tables: self
+                .tables
+                .into_iter()
+                .map(|table| PdfTable {
+                    rows: table.rows.clean(cleanup),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Run of text shown at a position of a page
+struct Span {
+    x: f64,
+    y: f64,
+    /// Estimated from the font size, as the widths of the glyphs are not read
+    width: f64,
+    size: f64,
+    text: String,
+}
+
+/// Run of spans of a line close enough to each other to be read as one cell
+struct Cell {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+/// Average width of a glyph, relative to the font size
+const GLYPH_WIDTH: f64 = 0.5;
+
+/// Text of a pdf string: UTF-16BE if it starts with a byte order mark, one byte per character
+///  otherwise (which holds for the standard encodings of latin text).
+fn decode_text(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFE, 0xFF, rest @ ..] => String::from_utf16_lossy(
+            &rest
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+                .collect::<Vec<_>>(),
+        ),
+        _ => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// Move to the start of the next line, offset by (`tx`, `ty`) from the start of the current one
+fn next_line(line: &mut (f64, f64), x: &mut f64, scale: f64, tx: f64, ty: f64) {
+    *line = (line.0 + tx * scale, line.1 + ty * scale);
+    *x = line.0;
+}
+
+/// Positioned runs of text of a page, read by interpreting the text operators of its content
+///  stream (the graphics state, e.g.: transformations of the page, is not tracked).
+fn page_spans(doc: &Document, page_id: ObjectId) -> Result<Vec<Span>, PdfLoaderError> {
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+
+    let mut spans = vec![];
+    // Origin of the current line, position of the next glyph and scale of the text matrix
+    let (mut line, mut x, mut scale) = ((0.0, 0.0), 0.0, 1.0);
+    let (mut size, mut leading) = (0.0, 0.0);
+
+    for operation in content.operations {
+        let number = |i: usize| {
+            operation
+                .operands
+                .get(i)
+                .and_then(|operand| operand.as_float().ok())
+                .map(f64::from)
+                .unwrap_or(0.0)
+        };
+        let text = |i: usize| match operation.operands.get(i) {
+            Some(Object::String(bytes, _)) => Some(decode_text(bytes)),
+            _ => None,
+        };
+
+        let shown = match operation.operator.as_str() {
+            "BT" => {
+                (line, x, scale) = ((0.0, 0.0), 0.0, 1.0);
+                None
+            }
+            "Tf" => {
+                size = number(1);
+                None
+            }
+            "TL" => {
+                leading = number(0);
+                None
+            }
+            "Tm" => {
+                scale = number(3).abs().max(f64::EPSILON);
+                line = (number(4), number(5));
+                x = line.0;
+                None
+            }
+            "Td" => {
+                next_line(&mut line, &mut x, scale, number(0), number(1));
+                None
+            }
+            "TD" => {
+                leading = -number(1);
+                next_line(&mut line, &mut x, scale, number(0), number(1));
+                None
+            }
+            "T*" => {
+                next_line(&mut line, &mut x, scale, 0.0, -leading);
+                None
+            }
+            "Tj" => text(0).map(|text| {
+                let glyphs = text.chars().count() as f64;
+                (text, glyphs * GLYPH_WIDTH * size)
+            }),
+            "'" | "\"" => {
+                next_line(&mut line, &mut x, scale, 0.0, -leading);
+                text(if operation.operator == "'" { 0 } else { 2 }).map(|text| {
+                    let glyphs = text.chars().count() as f64;
+                    (text, glyphs * GLYPH_WIDTH * size)
+                })
+            }
+            "TJ" => match operation.operands.first() {
+                Some(Object::Array(elements)) => {
+                    let (mut shown, mut width) = (String::new(), 0.0);
+                    for element in elements {
+                        match element {
+                            Object::String(bytes, _) => {
+                                let text = decode_text(bytes);
+                                width += text.chars().count() as f64 * GLYPH_WIDTH * size;
+                                shown.push_str(&text);
+                            }
+                            adjustment => {
+                                // Adjustments are in thousandths of the font size, large
+                                //  negative ones separating words
+                                let adjustment =
+                                    adjustment.as_float().map(f64::from).unwrap_or(0.0);
+                                if adjustment < -200.0 && !shown.ends_with(' ') {
+                                    shown.push(' ');
+                                }
+                                width -= adjustment / 1000.0 * size;
+                            }
+                        }
+                    }
+                    Some((shown, width))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some((text, width)) = shown {
+            spans.push(Span {
+                x,
+                y: line.1,
+                width: width * scale,
+                size: size * scale,
+                text,
+            });
+            x += width * scale;
+        }
+    }
+    Ok(spans)
+}
+
+/// Lines of a page, from top to bottom, as their cells from left to right.
+fn page_lines(mut spans: Vec<Span>) -> Vec<Vec<Cell>> {
+    spans.retain(|span| !span.text.trim().is_empty());
+    spans.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    // Spans whose baselines are within half a font size of each other are on the same line
+    let mut lines: Vec<Vec<Span>> = vec![];
+    for span in spans {
+        match lines.last_mut() {
+            Some(line) if (line[0].y - span.y).abs() <= line[0].size.max(span.size) / 2.0 => {
+                line.push(span)
+            }
+            _ => lines.push(vec![span]),
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|mut line| {
+            line.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+            // Spans closer than a font size to each other are in the same cell
+            let mut cells: Vec<Cell> = vec![];
+            for span in line {
+                match cells.last_mut() {
+                    Some(cell) if span.x - cell.end < span.size => {
+                        if span.x - cell.end > span.size / 4.0
+                            && !cell.text.ends_with(' ')
+                            && !span.text.starts_with(' ')
+                        {
+                            cell.text.push(' ');
+                        }
+                        cell.text.push_str(&span.text);
+                        cell.end = cell.end.max(span.x + span.width);
+                    }
+                    _ => cells.push(Cell {
+                        start: span.x,
+                        end: span.x + span.width,
+                        text: span.text,
+                    }),
+                }
+            }
+            cells
+        })
+        .collect()
+}
+
+/// Whether the cells of `line` are in the columns of the cells of `header`, i.e.: they have as
+///  many cells each overlapping the cell of the same column of the header.
+fn same_columns(header: &[Cell], line: &[Cell]) -> bool {
+    header.len() == line.len()
+        && header
+            .iter()
+            .zip(line)
+            .all(|(a, b)| a.start <= b.end && b.start <= a.end)
+}
+
+/// Tables and flowing text of a page. Tables are detected from the positions of the text: a
+///  table is a run of at least two consecutive lines of at least two cells each, all aligned in
+///  the same columns.
+fn page_layout(doc: &Document, page_id: ObjectId) -> Result<PdfPage, PdfLoaderError> {
+    let lines = page_lines(page_spans(doc, page_id)?);
+
+    let cells = |line: &[Cell]| {
+        line.iter()
+            .map(|cell| cell.text.trim().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let (mut text, mut tables) = (String::new(), vec![]);
+    let mut i = 0;
+    while i < lines.len() {
+        let header = &lines[i];
+        let rows = if header.len() >= 2 {
+            lines[i..]
+                .iter()
+                .take_while(|line| same_columns(header, line))
+                .count()
+        } else {
+            1
+        };
+
+        if rows >= 2 {
+            tables.push(PdfTable {
+                rows: lines[i..i + rows].iter().map(|line| cells(line)).collect(),
+            });
+        } else {
+            text.push_str(&cells(header).join(" "));
+            text.push('\n');
+        }
+        i += rows.max(1);
+    }
+    Ok(PdfPage { text, tables })
+}
+
 // ================================================================
 // Implementing Loadable trait for loading pdfs
 // ================================================================
@@ -45,6 +338,22 @@ impl<T: Loadable> Loadable for Result<T, PdfLoaderError> {
     }
 }
 
+/// Extract the text of all the pages of a pdf from its bytes (e.g.: a pdf within an archive)
+#[cfg(feature = "archive")]
+pub(crate) fn text_from_bytes(bytes: &[u8]) -> Result<String, PdfLoaderError> {
+    let doc = Document::load_mem(bytes)?;
+    Ok(doc
+        .page_iter()
+        .enumerate()
+        .map(|(page_no, _)| {
+            doc.extract_text(&[page_no as u32 + 1])
+                .map_err(PdfLoaderError::PdfError)
+        })
+        .collect::<Result<Vec<String>, PdfLoaderError>>()?
+        .into_iter()
+        .collect::<String>())
+}
+
 // ================================================================
 // PdfFileLoader definitions and implementations
 // ================================================================
@@ -210,6 +519,32 @@ impl<'a> PdfFileLoader<'a, Result<PathBuf, PdfLoaderError>> {
     }
 }
 
+impl<'a> PdfFileLoader<'a, Result<(PathBuf, String), PdfLoaderError>> {
+    /// Converts the pdfs read by [PdfFileLoader::read_with_path] into
+    ///  [Document](completion::Document)s whose id is the path of their pdf, which can be
+    ///  embedded directly with an [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder).
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = PdfFileLoader::with_glob("tests/data/*.pdf")?
+    ///     .read_with_path()
+    ///     .into_documents()
+    ///     .ignore_errors();
+    /// ```
+    pub fn into_documents(self) -> PdfFileLoader<'a, Result<completion::Document, PdfLoaderError>> {
+        PdfFileLoader {
+            iterator: Box::new(self.iterator.map(|res| {
+                let (path, text) = res?;
+                Ok(completion::Document {
+                    id: path.display().to_string(),
+                    text,
+                    additional_props: HashMap::new(),
+                })
+            })),
+        }
+    }
+}
+
 impl<'a> PdfFileLoader<'a, Document> {
     /// Chunks the pages of a loaded document by page, flattened as a single vector.
     ///
@@ -289,6 +624,118 @@ impl<'a> PdfFileLoader<'a, (PathBuf, Document)> {
     }
 }
 
+type ByPageWithTables = (PathBuf, Vec<(usize, Result<PdfPage, PdfLoaderError>)>);
+impl<'a> PdfFileLoader<'a, (PathBuf, Document)> {
+    /// Chunks the pages of a loaded document by page like [PdfFileLoader::by_page], separating
+    ///  the tables of each page, as rows of cells, from its flowing text (see [PdfPage]).
+    ///
+    /// Tables are detected with a heuristic on the positions of the text: runs of consecutive
+    ///  lines whose text is split in the same columns. Tables drawn without aligned columns (or
+    ///  pdfs whose text is in images) are read as flowing text.
+    ///
+    /// # Example
+    /// ```rust
+    /// let pages = PdfFileLoader::with_glob("tests/data/tables/*.pdf")?
+    ///     .load_with_path()
+    ///     .ignore_errors()
+    ///     .by_page_with_tables()
+    ///     .ignore_errors();
+    ///
+    /// for (path, pages) in pages {
+    ///     for (page_no, page) in pages {
+    ///         for table in page.tables {
+    ///             println!("{:?} page {}: {:?}", path, page_no, table.rows);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn by_page_with_tables(self) -> PdfFileLoader<'a, ByPageWithTables> {
+        PdfFileLoader {
+            iterator: Box::new(self.iterator.map(|(path, doc)| {
+                let pages = doc
+                    .get_pages()
+                    .into_values()
+                    .enumerate()
+                    .map(|(page_no, page_id)| (page_no, page_layout(&doc, page_id)))
+                    .collect::<Vec<_>>();
+                (path, pages)
+            })),
+        }
+    }
+}
+
+impl<'a> PdfFileLoader<'a, ByPageWithTables> {
+    /// Ignores the pages which could not be read, like [PdfFileLoader::ignore_errors] for pages.
+    pub fn ignore_errors(self) -> PdfFileLoader<'a, (PathBuf, Vec<(usize, PdfPage)>)> {
+        PdfFileLoader {
+            iterator: Box::new(self.iterator.map(|(path, pages)| {
+                let pages = pages
+                    .into_iter()
+                    .filter_map(|(page_no, res)| res.ok().map(|page| (page_no, page)))
+                    .collect::<Vec<_>>();
+                (path, pages)
+            })),
+        }
+    }
+}
+
+impl<'a> PdfFileLoader<'a, (PathBuf, Vec<(usize, PdfPage)>)> {
+    /// Converts the pages of the pdfs into [Document](completion::Document)s, flattened as a
+    ///  single iterator: one document per page for its flowing text, with the same id and
+    ///  `page` field as the pages converted by [PdfFileLoader::into_documents], followed by one
+    ///  document per table of the page, whose id is `<path>#<page number>/table<table number>`
+    ///  (e.g.: `tests/data/tables/rates.pdf#0/table0`). The text of a table document is its rows
+    ///  (see [PdfTable::to_text]), and its `rows` field holds the rows as a JSON array of arrays.
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = PdfFileLoader::with_glob("tests/data/tables/*.pdf")?
+    ///     .load_with_path()
+    ///     .ignore_errors()
+    ///     .by_page_with_tables()
+    ///     .ignore_errors()
+    ///     .into_documents();
+    /// ```
+    pub fn into_documents(self) -> PdfFileLoader<'a, completion::Document> {
+        PdfFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|(path, pages)| {
+                let path = path.display().to_string();
+                pages.into_iter().flat_map(move |(page_no, page)| {
+                    let id = format!("{path}#{page_no}");
+                    let tables = page
+                        .tables
+                        .iter()
+                        .enumerate()
+                        .map(|(table_no, table)| completion::Document {
+                            id: format!("{id}/table{table_no}"),
+                            text: table.to_text(),
+                            additional_props: HashMap::from([
+                                ("page".to_string(), page_no.to_string()),
+                                ("table".to_string(), table_no.to_string()),
+                                (
+                                    "rows".to_string(),
+                                    serde_json::to_string(&table.rows)
+                                        .expect("Rows of strings are serializable"),
+                                ),
+                            ]),
+                        })
+                        .collect::<Vec<_>>();
+
+                    std::iter::once(completion::Document {
+                        id,
+                        text: page.text,
+                        additional_props: HashMap::from([(
+                            "page".to_string(),
+                            page_no.to_string(),
+                        )]),
+                    })
+                    .chain(tables)
+                })
+            })),
+        }
+    }
+}
+
 impl<'a> PdfFileLoader<'a, ByPage> {
     /// Ignores errors in the iterator, returning only successful results. This can be used on any
     ///  [PdfFileLoader] state of iterator whose items are results.
@@ -315,6 +762,39 @@ impl<'a> PdfFileLoader<'a, ByPage> {
     }
 }
 
+impl<'a> PdfFileLoader<'a, (PathBuf, Vec<(usize, String)>)> {
+    /// Converts the pages of the pdfs into [Document](completion::Document)s, flattened as a
+    ///  single iterator. The id of each page is `<path>#<page number>` (e.g.:
+    ///  `tests/data/pages.pdf#2`), which is stable across loads so that re-indexing a pdf
+    ///  replaces its pages, and its page number (0-indexed) is stored in the `page` field.
+    ///
+    /// # Example
+    /// ```rust
+    /// let documents = PdfFileLoader::with_glob("tests/data/*.pdf")?
+    ///     .load_with_path()
+    ///     .ignore_errors()
+    ///     .by_page()
+    ///     .ignore_errors()
+    ///     .into_documents();
+    /// ```
+    pub fn into_documents(self) -> PdfFileLoader<'a, completion::Document> {
+        PdfFileLoader {
+            iterator: Box::new(self.iterator.flat_map(|(path, pages)| {
+                pages
+                    .into_iter()
+                    .map(move |(page_no, text)| completion::Document {
+                        id: format!("{}#{page_no}", path.display()),
+                        text,
+                        additional_props: HashMap::from([(
+                            "page".to_string(),
+                            page_no.to_string(),
+                        )]),
+                    })
+            })),
+        }
+    }
+}
+
 impl<'a, T: 'a> PdfFileLoader<'a, Result<T, PdfLoaderError>> {
     /// Ignores errors in the iterator, returning only successful results. This can be used on any
     ///  [PdfFileLoader] state of iterator whose items are results.
@@ -335,6 +815,27 @@ impl<'a, T: 'a> PdfFileLoader<'a, Result<T, PdfLoaderError>> {
     }
 }
 
+impl<'a, T: Cleanable + 'a> PdfFileLoader<'a, T> {
+    /// Cleans the text of the pdfs (or of their pages) with the given [TextCleanup] (e.g.:
+    ///  rejoining words hyphenated across line breaks and collapsing runs of whitespace).
+    ///
+    /// # Example
+    /// Read the pages of the pdfs in directory "tests/data/*.pdf" and clean their text.
+    ///
+    /// ```rust
+    /// let content = PdfFileLoader::with_glob("tests/data/*.pdf")?
+    ///     .load_with_path()
+    ///     .ignore_errors()
+    ///     .by_page()
+    ///     .clean(TextCleanup::default());
+    /// ```
+    pub fn clean(self, cleanup: TextCleanup) -> PdfFileLoader<'a, T> {
+        PdfFileLoader {
+            iterator: Box::new(self.iterator.map(move |item| item.clean(&cleanup))),
+        }
+    }
+}
+
 impl PdfFileLoader<'_, Result<PathBuf, FileLoaderError>> {
     /// Creates a new [PdfFileLoader] using a glob pattern to match files.
     ///
@@ -375,8 +876,31 @@ impl PdfFileLoader<'_, Result<PathBuf, FileLoaderError>> {
             ),
         })
     }
+
+    /// Creates a new [PdfFileLoader] on all files within a directory and its subdirectories, at
+    ///  any depth. Symlinks are followed, and directories that were already visited (i.e.:
+    ///  symlink loops) are skipped.
+    ///
+    /// # Example
+    /// Create a [PdfFileLoader] for all files that are in the directory "library" or its subdirectories.
+    ///
+    /// ```rust
+    /// let loader = PdfFileLoader::with_dir_recursive("library")?;
+    /// ```
+    pub fn with_dir_recursive(
+        directory: &str,
+    ) -> Result<PdfFileLoader<Result<PathBuf, PdfLoaderError>>, PdfLoaderError> {
+        Ok(PdfFileLoader {
+            iterator: Box::new(
+                WalkDir::new(directory)?.map(|path| path.map_err(PdfLoaderError::FileLoaderError)),
+            ),
+        })
+    }
 }
 
+#[cfg(feature = "language")]
+super::language::language_adaptors!(PdfFileLoader);
+
 // ================================================================
 // PDFFileLoader iterator implementations
 // ================================================================
@@ -408,7 +932,7 @@ impl<T> Iterator for IntoIter<'_, T> {
 mod tests {
     use std::path::PathBuf;
 
-    use super::PdfFileLoader;
+    use super::{PdfFileLoader, PdfTable};
 
     #[test]
     fn test_pdf_loader() {
@@ -453,4 +977,74 @@ mod tests {
         assert!(!actual.is_empty());
         assert!(expected == actual)
     }
+
+    #[test]
+    fn test_pdf_tables() {
+        let pages = PdfFileLoader::with_glob("tests/data/tables/rates.pdf")
+            .unwrap()
+            .load_with_path()
+            .ignore_errors()
+            .by_page_with_tables()
+            .ignore_errors()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(pages.len(), 1);
+
+        let (_, pages) = &pages[0];
+        assert_eq!(pages.len(), 1);
+        let (page_no, page) = &pages[0];
+        assert_eq!(*page_no, 0);
+
+        let rows = |rows: &[&[&str]]| {
+            rows.iter()
+                .map(|row| row.iter().map(|cell| cell.to_string()).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            page.tables,
+            vec![PdfTable {
+                rows: rows(&[
+                    &["Currency", "Code", "Rate"],
+                    &["Glarb dollar", "GLD", "1.25"],
+                    &["Schmeckle", "SCH", "0.80"],
+                ]),
+            }]
+        );
+
+        // The table is not part of the flowing text of the page
+        assert_eq!(
+            page.text,
+            "Flurbo exchange rates\n\
+             Rates of the flurbo against other currencies, as of last week.\n\
+             Rates are updated every Monday.\n"
+        );
+
+        let documents = PdfFileLoader::with_glob("tests/data/tables/rates.pdf")
+            .unwrap()
+            .load_with_path()
+            .ignore_errors()
+            .by_page_with_tables()
+            .ignore_errors()
+            .into_documents()
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            documents
+                .iter()
+                .map(|doc| doc.id.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "tests/data/tables/rates.pdf#0",
+                "tests/data/tables/rates.pdf#0/table0"
+            ]
+        );
+        assert_eq!(
+            documents[1].text,
+            "Currency | Code | Rate\nGlarb dollar | GLD | 1.25\nSchmeckle | SCH | 0.80"
+        );
+        assert_eq!(
+            documents[1].additional_props["rows"],
+            r#"[["Currency","Code","Rate"],["Glarb dollar","GLD","1.25"],["Schmeckle","SCH","0.80"]]"#
+        );
+    }
 }
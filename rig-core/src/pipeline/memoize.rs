@@ -0,0 +1,191 @@
+//! This module provides the [Memoize] op, caching the outputs of an op on disk (in a
+//! [DiskCache]) keyed by its inputs, so that re-running a pipeline (e.g.: while iterating on its
+//! later stages) skips recomputing its expensive early stages.
+//!
+//! # Example
+//! ```rust
+//! use rig::pipeline::{self, memoize::DiskCache, Op};
+//!
+//! let summarize = pipeline::memoize(
+//!     pipeline::new().then(|text: String| async move { expensive_summary(&text).await }),
+//!     |text: &String| text.clone(),
+//!     DiskCache::new(".cache/summaries").version("v2"),
+//! );
+//!
+//! let pipeline = pipeline::new().chain(summarize).map(|summary| format!("Summary: {summary}"));
+//! ```
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::Op;
+use crate::hash::fnv1a;
+
+/// Disk cache of a [Memoize] op, storing each output as a json file in a directory.
+///
+/// Cached outputs are tagged with the version of the cache (empty by default): bumping the
+/// version (e.g.: after changing the prompt of the memoized stage) invalidates the outputs
+/// cached with previous versions, which are recomputed and overwritten.
+#[derive(Clone, Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    version: String,
+}
+
+/// Entry of a [DiskCache], stored with its key and version so that colliding file names and
+/// stale entries are detected
+#[derive(Deserialize, Serialize)]
+struct Entry<T> {
+    version: String,
+    key: String,
+    output: T,
+}
+
+impl DiskCache {
+    /// Cache the outputs in the directory `dir` (created when the first output is cached)
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            version: String::new(),
+        }
+    }
+
+    /// Set the version of the cached outputs, invalidating the outputs cached with other
+    /// versions
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// Directory of the cache
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file caching `key`, named after its (stable) hash so that the file names
+    /// of the cached outputs do not change between runs
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", fnv1a(key.bytes())))
+    }
+
+    /// Output cached for `key` with the current version, if any
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> std::io::Result<Option<T>> {
+        let content = match tokio::fs::read(self.path(key)).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let entry = serde_json::from_slice::<Entry<T>>(&content)?;
+
+        Ok((entry.key == key && entry.version == self.version).then_some(entry.output))
+    }
+
+    /// Cache `output` for `key`, writing to a temporary file first so that the cache is not
+    /// corrupted if the write is interrupted
+    async fn set<T: Serialize>(&self, key: &str, output: &T) -> std::io::Result<()> {
+        let path = self.path(key);
+        let tmp_path = path.with_extension("json.tmp");
+        let entry = Entry {
+            version: self.version.clone(),
+            key: key.to_string(),
+            output,
+        };
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(&tmp_path, serde_json::to_vec(&entry)?).await?;
+        tokio::fs::rename(&tmp_path, &path).await
+    }
+}
+
+pub struct Memoize<O, F> {
+    op: O,
+    key: F,
+    cache: DiskCache,
+}
+
+impl<O, F> Memoize<O, F> {
+    pub(crate) fn new(op: O, key: F, cache: DiskCache) -> Self {
+        Self { op, key, cache }
+    }
+}
+
+impl<O, F> Op for Memoize<O, F>
+where
+    O: Op,
+    O::Output: Serialize + DeserializeOwned,
+    F: Fn(&O::Input) -> String + Send + Sync,
+{
+    type Input = O::Input;
+    type Output = O::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let key = (self.key)(&input);
+
+        match self.cache.get(&key).await {
+            Ok(Some(output)) => return output,
+            Ok(None) => (),
+            Err(err) => {
+                tracing::warn!(target: "rig", "Failed to read the cached output of {key}: {err}")
+            }
+        }
+
+        let output = self.op.call(input).await;
+        if let Err(err) = self.cache.set(&key, &output).await {
+            tracing::warn!(target: "rig", "Failed to cache the output of {key}: {err}");
+        }
+        output
+    }
+}
+
+/// Create a [Memoize] op, caching the outputs of `op` in `cache` keyed by `key(input)` (see the
+/// [module](self) docs). On a cache hit, `op` is not called. Cache failures (e.g.: an
+/// unwritable directory) are logged and the output is computed as if it was not cached.
+pub fn memoize<O, F>(op: O, key: F, cache: DiskCache) -> Memoize<O, F>
+where
+    O: Op,
+    O::Output: Serialize + DeserializeOwned,
+    F: Fn(&O::Input) -> String + Send + Sync,
+{
+    Memoize::new(op, key, cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::pipeline::{self, map};
+
+    #[tokio::test]
+    async fn test_memoize() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let calls = AtomicUsize::new(0);
+
+        let run = |cache: DiskCache| {
+            let calls = &calls;
+            let stage = memoize(
+                map(move |x: u32| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    x * 2
+                }),
+                |x: &u32| x.to_string(),
+                cache,
+            );
+            pipeline::new().chain(stage).map(|x| format!("Result: {x}"))
+        };
+
+        let cache = DiskCache::new(temp.path());
+        assert_eq!(run(cache.clone()).call(21).await, "Result: 42");
+        assert_eq!(run(cache.clone()).call(21).await, "Result: 42");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        assert_eq!(run(cache.clone()).call(1).await, "Result: 2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Bumping the version invalidates the cached outputs
+        let cache = cache.version("v2");
+        assert_eq!(run(cache.clone()).call(21).await, "Result: 42");
+        assert_eq!(run(cache).call(21).await, "Result: 42");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -88,6 +88,8 @@
 //! ```
 
 pub mod agent_ops;
+#[cfg(feature = "tokio-fs")]
+pub mod memoize;
 pub mod op;
 pub mod try_op;
 #[macro_use]
@@ -95,8 +97,10 @@ pub mod parallel;
 
 use std::future::Future;
 
-pub use op::{map, passthrough, then, Op};
-pub use try_op::TryOp;
+#[cfg(feature = "tokio-fs")]
+pub use memoize::memoize;
+pub use op::{branch, map, passthrough, then, Op};
+pub use try_op::{fallback, retry, TryOp};
 
 use crate::{completion, extractor::Extractor, vector_store};
 
@@ -187,6 +191,34 @@ impl<E> PipelineBuilder<E> {
         op
     }
 
+    /// Add a conditional branch to the current pipeline: the input is routed to `if_true` if
+    /// `predicate` returns true for it, and to `if_false` otherwise.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::pipeline::{self, map, Op};
+    ///
+    /// let pipeline = pipeline::new()
+    ///     .branch(|x: &i32| x % 2 == 0, map(|x| x / 2), map(|x| x * 3 + 1));
+    ///
+    /// assert_eq!(pipeline.call(4).await, 2);
+    /// assert_eq!(pipeline.call(3).await, 10);
+    /// ```
+    pub fn branch<F, Op1, Op2>(
+        self,
+        predicate: F,
+        if_true: Op1,
+        if_false: Op2,
+    ) -> op::Branch<F, Op1, Op2>
+    where
+        F: Fn(&Op1::Input) -> bool + Send + Sync,
+        Op1: Op,
+        Op2: Op<Input = Op1::Input, Output = Op1::Output>,
+        Self: Sized,
+    {
+        op::Branch::new(predicate, if_true, if_false)
+    }
+
     /// Chain a lookup operation to the current chain. The lookup operation expects the
     /// current chain to output a query string. The lookup operation will use the query to
     /// retrieve the top `n` documents from the index and return them with the query string.
@@ -167,6 +167,40 @@ pub trait Op: Send + Sync {
     {
         Sequential::new(self, Prompt::new(prompt))
     }
+
+    /// Chain a conditional branch to the current op: the output of the current op is routed
+    /// to `if_true` if `predicate` returns true for it, and to `if_false` otherwise. Both
+    /// branches must share the same input and output types. Branches can be nested to route
+    /// between more than two sub-pipelines.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::pipeline::{self, Op};
+    ///
+    /// let pipeline = pipeline::new()
+    ///     .map(|question: String| question.to_lowercase())
+    ///     .branch(
+    ///         |question| question.contains("rust"),
+    ///         pipeline::new().prompt(code_agent),
+    ///         pipeline::new().prompt(general_agent),
+    ///     );
+    ///
+    /// let result = pipeline.call("How do I write a Rust macro?".to_string()).await?;
+    /// ```
+    fn branch<F, Op1, Op2>(
+        self,
+        predicate: F,
+        if_true: Op1,
+        if_false: Op2,
+    ) -> Sequential<Self, Branch<F, Op1, Op2>>
+    where
+        F: Fn(&Self::Output) -> bool + Send + Sync,
+        Op1: Op<Input = Self::Output>,
+        Op2: Op<Input = Self::Output, Output = Op1::Output>,
+        Self: Sized,
+    {
+        Sequential::new(self, Branch::new(predicate, if_true, if_false))
+    }
 }
 
 impl<T: Op> Op for &T {
@@ -208,6 +242,52 @@ where
     }
 }
 
+pub struct Branch<F, Op1, Op2> {
+    predicate: F,
+    if_true: Op1,
+    if_false: Op2,
+}
+
+impl<F, Op1, Op2> Branch<F, Op1, Op2> {
+    pub(crate) fn new(predicate: F, if_true: Op1, if_false: Op2) -> Self {
+        Self {
+            predicate,
+            if_true,
+            if_false,
+        }
+    }
+}
+
+impl<F, Op1, Op2> Op for Branch<F, Op1, Op2>
+where
+    F: Fn(&Op1::Input) -> bool + Send + Sync,
+    Op1: Op,
+    Op2: Op<Input = Op1::Input, Output = Op1::Output>,
+{
+    type Input = Op1::Input;
+    type Output = Op1::Output;
+
+    #[inline]
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        if (self.predicate)(&input) {
+            self.if_true.call(input).await
+        } else {
+            self.if_false.call(input).await
+        }
+    }
+}
+
+/// Create a standalone [Branch] op, routing its input to `if_true` if `predicate` returns true
+/// for it, and to `if_false` otherwise (see [Op::branch]).
+pub fn branch<F, Op1, Op2>(predicate: F, if_true: Op1, if_false: Op2) -> Branch<F, Op1, Op2>
+where
+    F: Fn(&Op1::Input) -> bool + Send + Sync,
+    Op1: Op,
+    Op2: Op<Input = Op1::Input, Output = Op1::Output>,
+{
+    Branch::new(predicate, if_true, if_false)
+}
+
 use crate::{completion, vector_store};
 
 use super::agent_ops::{Lookup, Prompt};
@@ -350,6 +430,57 @@ mod tests {
         assert_eq!(result, 12);
     }
 
+    #[tokio::test]
+    async fn test_branch() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let code_calls = AtomicUsize::new(0);
+        let general_calls = AtomicUsize::new(0);
+
+        let pipeline = map(|question: &str| question.to_lowercase()).branch(
+            |question| question.contains("rust"),
+            map(|question: String| {
+                code_calls.fetch_add(1, Ordering::SeqCst);
+                format!("code: {question}")
+            }),
+            map(|question: String| {
+                general_calls.fetch_add(1, Ordering::SeqCst);
+                format!("general: {question}")
+            }),
+        );
+
+        assert_eq!(
+            pipeline.call("How do I write a Rust macro?").await,
+            "code: how do i write a rust macro?"
+        );
+        assert_eq!(code_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(general_calls.load(Ordering::SeqCst), 0);
+
+        assert_eq!(
+            pipeline.call("What is a flurbo?").await,
+            "general: what is a flurbo?"
+        );
+        assert_eq!(code_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(general_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_nested_branch() {
+        let pipeline = branch(
+            |x: &i32| *x < 0,
+            map(|_: i32| "negative"),
+            branch(
+                |x: &i32| *x == 0,
+                map(|_: i32| "zero"),
+                map(|_: i32| "positive"),
+            ),
+        );
+
+        assert_eq!(pipeline.call(-3).await, "negative");
+        assert_eq!(pipeline.call(0).await, "zero");
+        assert_eq!(pipeline.call(3).await, "positive");
+    }
+
     // #[tokio::test]
     // async fn test_flatten() {
     //     let op = Parallel::new(
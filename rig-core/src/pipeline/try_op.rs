@@ -5,6 +5,7 @@ use futures::stream;
 use futures::try_join;
 
 use super::op::{self};
+use crate::completion::RetryPolicy;
 
 // ================================================================
 // Core TryOp trait
@@ -345,6 +346,116 @@ where
     }
 }
 
+pub struct Retry<Op> {
+    op: Op,
+    policy: RetryPolicy,
+}
+
+impl<Op> Retry<Op> {
+    pub(crate) fn new(op: Op, policy: RetryPolicy) -> Self {
+        Self { op, policy }
+    }
+}
+
+impl<Op> op::Op for Retry<Op>
+where
+    Op: TryOp,
+    Op::Input: Clone,
+{
+    type Input = Op::Input;
+    type Output = Result<Op::Output, Op::Error>;
+
+    #[inline]
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let mut retry = 0;
+        loop {
+            match self.op.try_call(input.clone()).await {
+                Err(_) if retry < self.policy.max_retries => {
+                    futures_timer::Delay::new(self.policy.delay(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Create a [Retry] op, calling `op` again with the same input when it fails, up to
+/// `policy.max_retries` times and with the exponential backoff of the policy. The error of the
+/// last attempt is returned once the retries are exhausted.
+///
+/// # Example
+/// ```rust
+/// use rig::{completion::RetryPolicy, pipeline::{self, TryOp}};
+///
+/// let op = pipeline::retry(
+///     pipeline::new().then(|url: String| async move { fetch(&url).await }),
+///     RetryPolicy::new(3),
+/// );
+///
+/// let page = op.try_call("https://example.com".to_string()).await?;
+/// ```
+pub fn retry<Op>(op: Op, policy: RetryPolicy) -> Retry<Op>
+where
+    Op: TryOp,
+    Op::Input: Clone,
+{
+    Retry::new(op, policy)
+}
+
+pub struct Fallback<Op1, Op2> {
+    primary: Op1,
+    secondary: Op2,
+}
+
+impl<Op1, Op2> Fallback<Op1, Op2> {
+    pub(crate) fn new(primary: Op1, secondary: Op2) -> Self {
+        Self { primary, secondary }
+    }
+}
+
+impl<Op1, Op2> op::Op for Fallback<Op1, Op2>
+where
+    Op1: TryOp,
+    Op1::Input: Clone,
+    Op2: TryOp<Input = Op1::Input, Output = Op1::Output>,
+{
+    type Input = Op1::Input;
+    type Output = Result<Op1::Output, Op2::Error>;
+
+    #[inline]
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        match self.primary.try_call(input.clone()).await {
+            Ok(output) => Ok(output),
+            Err(_) => self.secondary.try_call(input).await,
+        }
+    }
+}
+
+/// Create a [Fallback] op, calling `secondary` with the same input when `primary` fails (unlike
+/// [TryOp::or_else], which is called with the error). The error of `secondary` is returned if
+/// both ops fail.
+///
+/// # Example
+/// ```rust
+/// use rig::pipeline::{self, TryOp};
+///
+/// let op = pipeline::fallback(
+///     pipeline::new().prompt(gpt4o_agent).map_err(|err| err.to_string()),
+///     pipeline::new().prompt(claude_agent).map_err(|err| err.to_string()),
+/// );
+///
+/// let answer = op.try_call("What is a flurbo?".to_string()).await?;
+/// ```
+pub fn fallback<Op1, Op2>(primary: Op1, secondary: Op2) -> Fallback<Op1, Op2>
+where
+    Op1: TryOp,
+    Op1::Input: Clone,
+    Op2: TryOp<Input = Op1::Input, Output = Op1::Output>,
+{
+    Fallback::new(primary, secondary)
+}
+
 // TODO: Implement TryParallel
 // pub struct TryParallel<Op1, Op2> {
 //     op1: Op1,
@@ -375,6 +486,14 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
     use super::*;
     use crate::pipeline::op::{map, then};
 
@@ -472,4 +591,50 @@ mod tests {
         let result = pipeline.try_call(1).await.unwrap();
         assert_eq!(result, 15);
     }
+
+    #[tokio::test]
+    async fn test_retry() {
+        // Fails on its first 2 calls
+        let flaky = |calls: Arc<AtomicUsize>| {
+            map(move |x: i32| {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err("flaky")
+                } else {
+                    Ok(x * 2)
+                }
+            })
+        };
+        let policy = RetryPolicy::new(2).backoff(Duration::ZERO);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pipeline = retry(flaky(calls.clone()), policy);
+        assert_eq!(pipeline.try_call(2).await, Ok(4));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // The error is returned once the retries are exhausted
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pipeline = retry(
+            flaky(calls.clone()),
+            RetryPolicy::new(1).backoff(Duration::ZERO),
+        );
+        assert_eq!(pipeline.try_call(2).await, Err("flaky"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let primary = map(|x: i32| if x % 2 == 0 { Ok(x) } else { Err("x is odd") });
+        let secondary = map(|x: i32| {
+            if x > 0 {
+                Ok(x * 10)
+            } else {
+                Err(format!("{x} <= 0"))
+            }
+        });
+        let pipeline = fallback(primary, secondary);
+
+        assert_eq!(pipeline.try_call(2).await, Ok(2));
+        assert_eq!(pipeline.try_call(3).await, Ok(30));
+        assert_eq!(pipeline.try_call(-1).await, Err("-1 <= 0".to_string()));
+    }
 }
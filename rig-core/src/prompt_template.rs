@@ -0,0 +1,216 @@
+//! This module provides the [PromptTemplate] struct, a prompt with `{variable}` placeholders
+//! which can be bound in several steps: variables fixed for an agent (e.g.: its role) are bound
+//! once with [PromptTemplate::with], returning a new template, and the variables varying per
+//! call are filled when rendering it with [PromptTemplate::render].
+//!
+//! Literal braces are written `{{` and `}}`.
+//!
+//! # Example
+//! ```rust
+//! use rig::prompt_template::PromptTemplate;
+//!
+//! let template = PromptTemplate::new("As a {role}, answer in {language}: {question}")?;
+//!
+//! // Bound once per agent
+//! let assistant = template.with("role", "assistant").with("language", "French");
+//!
+//! // Filled on each call
+//! let prompt = assistant.render([("question", "What is a flurbo?")])?;
+//! assert_eq!(prompt, "As a assistant, answer in French: What is a flurbo?");
+//! ```
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PromptTemplateError {
+    /// A `{` at the given byte offset of the template is never closed
+    #[error("Unclosed variable at offset {0} of the template")]
+    UnclosedVariable(usize),
+
+    /// A `}` at the given byte offset of the template was never opened
+    #[error("Unopened variable at offset {0} of the template")]
+    UnopenedVariable(usize),
+
+    /// Variables of the template left unbound when rendering it
+    #[error("Missing variables of the template: {}", .0.join(", "))]
+    MissingVariables(Vec<String>),
+}
+
+/// Part of a parsed template
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// Prompt template with `{variable}` placeholders (see the [module](self) documentation).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PromptTemplate {
+    segments: Vec<Segment>,
+    /// Values of the variables bound with [PromptTemplate::with]
+    bound: HashMap<String, String>,
+}
+
+impl PromptTemplate {
+    /// Parse `template`. Fails if it has unbalanced braces (literal braces are written `{{`
+    /// and `}}`).
+    pub fn new(template: &str) -> Result<Self, PromptTemplateError> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = template.char_indices().peekable();
+
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|(_, c)| *c == '{').is_some() => text.push('{'),
+                '}' if chars.next_if(|(_, c)| *c == '}').is_some() => text.push('}'),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(PromptTemplateError::UnclosedVariable(offset)),
+                        }
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Variable(name.trim().to_string()));
+                }
+                '}' => return Err(PromptTemplateError::UnopenedVariable(offset)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Self {
+            segments,
+            bound: HashMap::new(),
+        })
+    }
+
+    /// New template with the variable `name` bound to `value`, the template itself being left
+    /// unchanged. Binding a variable again overrides its value, and binding a variable which is
+    /// not part of the template has no effect.
+    pub fn with(&self, name: &str, value: impl Into<String>) -> Self {
+        let mut template = self.clone();
+        template.bound.insert(name.to_string(), value.into());
+        template
+    }
+
+    /// Names of the variables of the template, in order of first appearance.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables: Vec<&str> = vec![];
+        for segment in &self.segments {
+            if let Segment::Variable(name) = segment {
+                if !variables.contains(&name.as_str()) {
+                    variables.push(name);
+                }
+            }
+        }
+        variables
+    }
+
+    /// Names of the variables of the template not bound yet, in order of first appearance.
+    pub fn unbound_variables(&self) -> Vec<&str> {
+        self.variables()
+            .into_iter()
+            .filter(|name| !self.bound.contains_key(*name))
+            .collect()
+    }
+
+    /// Render the template, filling its unbound variables with `values` (which take precedence
+    /// over the bound values). Fails with [PromptTemplateError::MissingVariables] listing every
+    /// variable left unbound.
+    pub fn render<K: AsRef<str>, V: Into<String>>(
+        &self,
+        values: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<String, PromptTemplateError> {
+        let values = values
+            .into_iter()
+            .map(|(name, value)| (name.as_ref().to_string(), value.into()))
+            .collect::<HashMap<_, _>>();
+        let value = |name: &str| values.get(name).or_else(|| self.bound.get(name));
+
+        let missing = self
+            .variables()
+            .into_iter()
+            .filter(|name| value(name).is_none())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(PromptTemplateError::MissingVariables(missing));
+        }
+
+        Ok(self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.as_str(),
+                Segment::Variable(name) => value(name).map(String::as_str).unwrap_or_default(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PromptTemplate, PromptTemplateError};
+
+    #[test]
+    fn test_partial_application() {
+        let template =
+            PromptTemplate::new("You are a {role}. {{Answer}} in {language}: {question}").unwrap();
+        assert_eq!(template.variables(), vec!["role", "language", "question"]);
+
+        let assistant = template.with("role", "assistant");
+        assert_eq!(assistant.unbound_variables(), vec!["language", "question"]);
+        // The original template is left unchanged
+        assert_eq!(template.unbound_variables().len(), 3);
+
+        let french = assistant.with("language", "French");
+        assert_eq!(
+            french.render([("question", "What is a flurbo?")]).unwrap(),
+            "You are a assistant. {Answer} in French: What is a flurbo?"
+        );
+
+        // Values given when rendering take precedence over the bound ones
+        assert_eq!(
+            french
+                .render([("question", "What is a glarb?"), ("language", "German")])
+                .unwrap(),
+            "You are a assistant. {Answer} in German: What is a glarb?"
+        );
+
+        // Missing variables are all reported
+        assert_eq!(
+            assistant.render(Vec::<(&str, &str)>::new()),
+            Err(PromptTemplateError::MissingVariables(vec![
+                "language".to_string(),
+                "question".to_string()
+            ]))
+        );
+        assert_eq!(
+            assistant
+                .render([("question", "What is a flurbo?")])
+                .unwrap_err()
+                .to_string(),
+            "Missing variables of the template: language"
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_braces() {
+        assert_eq!(
+            PromptTemplate::new("Answer {question"),
+            Err(PromptTemplateError::UnclosedVariable(7))
+        );
+        assert_eq!(
+            PromptTemplate::new("Answer} {question}"),
+            Err(PromptTemplateError::UnopenedVariable(6))
+        );
+    }
+}
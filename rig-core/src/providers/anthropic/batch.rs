@@ -0,0 +1,390 @@
+//! Anthropic Message Batches API implementation
+//!
+//! Batches process many Messages API requests asynchronously, at a lower cost than individual
+//! requests (e.g.: for offline jobs). Each request of a batch is identified by a custom id,
+//! which is used to map the results of the batch back to their requests.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use rig::{completion::CompletionModel as _, providers::anthropic};
+//!
+//! let client = anthropic::Client::from_env();
+//! let model = client.completion_model(anthropic::CLAUDE_3_5_HAIKU);
+//!
+//! let batch = model
+//!     .create_batch(vec![
+//!         ("flurbo", model.completion_request("What is a flurbo?").build()),
+//!         ("glarb", model.completion_request("What is a glarb?").build()),
+//!     ])
+//!     .await?;
+//!
+//! let batch = client.wait_for_batch(&batch.id, Duration::from_secs(60)).await?;
+//! for (custom_id, result) in client.batch_results(&batch.id).await? {
+//!     match result.into_completion() {
+//!         Ok(response) => println!("{custom_id}: {:?}", response.choice),
+//!         Err(e) => eprintln!("{custom_id} failed: {e}"),
+//!     }
+//! }
+//! ```
+
+use std::{collections::HashMap, time::Duration};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::completion::{self, CompletionError};
+
+use super::{
+    client::Client,
+    completion::{CompletionModel, CompletionResponse},
+};
+
+/// Processing status of a [Batch]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingStatus {
+    InProgress,
+    Canceling,
+    Ended,
+}
+
+/// Number of requests of a [Batch] by status
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct RequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// Message batch, as returned when creating or polling a batch
+#[derive(Clone, Debug, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    pub processing_status: ProcessingStatus,
+    pub request_counts: RequestCounts,
+    pub created_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub expires_at: Option<String>,
+    /// URL of the results of the batch, available once it has ended
+    pub results_url: Option<String>,
+}
+
+/// Error of an errored request of a batch. Errors may be nested (i.e.: an `error` typed error
+/// wrapping the actual error), see [BatchError::message].
+#[derive(Clone, Debug, Deserialize)]
+pub struct BatchError {
+    pub r#type: String,
+    pub message: Option<String>,
+    pub error: Option<Box<BatchError>>,
+}
+
+impl BatchError {
+    /// Message of the innermost error
+    pub fn message(&self) -> String {
+        match (&self.error, &self.message) {
+            (Some(error), _) => error.message(),
+            (None, Some(message)) => message.clone(),
+            (None, None) => self.r#type.clone(),
+        }
+    }
+}
+
+/// Result of a request of a batch
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchResult {
+    Succeeded {
+        message: CompletionResponse,
+    },
+    Errored {
+        error: BatchError,
+    },
+    Canceled,
+    /// The batch expired before the request was processed
+    Expired,
+}
+
+impl BatchResult {
+    /// Convert the result into the response of its request, failing with a
+    /// [CompletionError::ProviderError] for requests which did not succeed.
+    pub fn into_completion(
+        self,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        match self {
+            BatchResult::Succeeded { message } => message.try_into(),
+            BatchResult::Errored { error } => Err(CompletionError::ProviderError(error.message())),
+            BatchResult::Canceled => Err(CompletionError::ProviderError(
+                "The request was canceled".into(),
+            )),
+            BatchResult::Expired => Err(CompletionError::ProviderError(
+                "The request expired before being processed".into(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchResultLine {
+    custom_id: String,
+    result: BatchResult,
+}
+
+async fn parse_response<T: for<'a> Deserialize<'a>>(
+    response: reqwest::Response,
+) -> Result<T, CompletionError> {
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        Err(CompletionError::ProviderError(response.text().await?))
+    }
+}
+
+impl CompletionModel {
+    /// Submit a batch of completion requests, each paired with its custom id (which must be
+    /// unique within the batch). The batch is processed asynchronously: poll it with
+    /// [Client::retrieve_batch] or [Client::wait_for_batch], then fetch its results with
+    /// [Client::batch_results].
+    pub async fn create_batch(
+        &self,
+        requests: impl IntoIterator<Item = (impl Into<String>, completion::CompletionRequest)>,
+    ) -> Result<Batch, CompletionError> {
+        let requests = requests
+            .into_iter()
+            .map(|(custom_id, request)| {
+                Ok(json!({
+                    "custom_id": custom_id.into(),
+                    "params": self.create_request_body(request)?,
+                }))
+            })
+            .collect::<Result<Vec<_>, CompletionError>>()?;
+
+        let response = self
+            .client
+            .post("/v1/messages/batches")
+            .json(&json!({ "requests": requests }))
+            .send()
+            .await?;
+
+        parse_response(response).await
+    }
+}
+
+impl Client {
+    /// Retrieve the current state of the batch `id`.
+    pub async fn retrieve_batch(&self, id: &str) -> Result<Batch, CompletionError> {
+        let response = self
+            .get(&format!("/v1/messages/batches/{id}"))
+            .send()
+            .await?;
+
+        parse_response(response).await
+    }
+
+    /// Poll the batch `id` every `poll_interval` until it has ended.
+    pub async fn wait_for_batch(
+        &self,
+        id: &str,
+        poll_interval: Duration,
+    ) -> Result<Batch, CompletionError> {
+        loop {
+            let batch = self.retrieve_batch(id).await?;
+            if batch.processing_status == ProcessingStatus::Ended {
+                return Ok(batch);
+            }
+
+            tracing::debug!(target: "rig",
+                "Anthropic batch {id} is still processing ({} requests left)",
+                batch.request_counts.processing
+            );
+            futures_timer::Delay::new(poll_interval).await;
+        }
+    }
+
+    /// Retrieve the results of the ended batch `id`, by custom id of their request.
+    pub async fn batch_results(
+        &self,
+        id: &str,
+    ) -> Result<HashMap<String, BatchResult>, CompletionError> {
+        let response = self
+            .get(&format!("/v1/messages/batches/{id}/results"))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(CompletionError::ProviderError(response.text().await?));
+        }
+
+        // The results are returned as JSON lines
+        response
+            .text()
+            .await?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let line = serde_json::from_str::<BatchResultLine>(line)?;
+                Ok((line.custom_id, line.result))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{BatchResult, ProcessingStatus};
+    use crate::{
+        completion::{CompletionError, CompletionModel as _, ModelChoice},
+        providers::anthropic::{ClientBuilder, CLAUDE_3_5_HAIKU},
+    };
+
+    /// Serve the given responses (status and body), one per connection, on a local port and
+    /// record the raw requests. Returns the base URL of the server.
+    async fn mock_server(responses: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+
+                // Read the headers and the body of the request
+                let mut request = vec![];
+                let mut buffer = [0; 4096];
+                loop {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some(end) = text.find("\r\n\r\n") else {
+                        if read == 0 {
+                            break;
+                        }
+                        continue;
+                    };
+                    let content_length = text[..end]
+                        .lines()
+                        .find_map(|line| {
+                            let (key, value) = line.split_once(':')?;
+                            key.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if read == 0 || request.len() >= end + 4 + content_length {
+                        recorded.lock().unwrap().push(text);
+                        break;
+                    }
+                }
+
+                let response = format!(
+                    "HTTP/1.1 {status} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    const IN_PROGRESS: &str = r#"{
+        "id": "msgbatch_123",
+        "type": "message_batch",
+        "processing_status": "in_progress",
+        "request_counts": {"processing": 3, "succeeded": 0, "errored": 0, "canceled": 0, "expired": 0},
+        "created_at": "2024-09-24T18:37:24.100435Z",
+        "ended_at": null,
+        "expires_at": "2024-09-25T18:37:24.100435Z",
+        "results_url": null
+    }"#;
+
+    const ENDED: &str = r#"{
+        "id": "msgbatch_123",
+        "type": "message_batch",
+        "processing_status": "ended",
+        "request_counts": {"processing": 0, "succeeded": 1, "errored": 1, "canceled": 0, "expired": 1},
+        "created_at": "2024-09-24T18:37:24.100435Z",
+        "ended_at": "2024-09-24T18:39:03.114500Z",
+        "expires_at": "2024-09-25T18:37:24.100435Z",
+        "results_url": "https://api.anthropic.com/v1/messages/batches/msgbatch_123/results"
+    }"#;
+
+    const RESULTS: &str = r#"{"custom_id":"flurbo","result":{"type":"succeeded","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-3-5-haiku-latest","content":[{"type":"text","text":"A made up currency."}],"stop_reason":"end_turn","stop_sequence":null,"usage":{"input_tokens":10,"output_tokens":5}}}}
+{"custom_id":"glarb","result":{"type":"errored","error":{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens: too large"}}}}
+{"custom_id":"linlingdong","result":{"type":"expired"}}
+"#;
+
+    #[tokio::test]
+    async fn test_batch() {
+        let (url, requests) = mock_server(vec![
+            (200, IN_PROGRESS),
+            (200, IN_PROGRESS),
+            (200, ENDED),
+            (200, RESULTS),
+        ])
+        .await;
+        let client = ClientBuilder::new("test").base_url(&url).build();
+        let model = client.completion_model(CLAUDE_3_5_HAIKU);
+
+        let batch = model
+            .create_batch(
+                ["flurbo", "glarb", "linlingdong"]
+                    .map(|word| (word, model.completion_request(word).build())),
+            )
+            .await
+            .unwrap();
+        assert_eq!(batch.id, "msgbatch_123");
+        assert_eq!(batch.processing_status, ProcessingStatus::InProgress);
+
+        let batch = client
+            .wait_for_batch(&batch.id, Duration::from_millis(1))
+            .await
+            .unwrap();
+        assert_eq!(batch.processing_status, ProcessingStatus::Ended);
+        assert_eq!(batch.request_counts.succeeded, 1);
+
+        let mut results = client.batch_results(&batch.id).await.unwrap();
+        assert_eq!(results.len(), 3);
+        let response = results.remove("flurbo").unwrap().into_completion().unwrap();
+        assert_eq!(
+            response.choice,
+            ModelChoice::Message("A made up currency.".to_string())
+        );
+        assert!(matches!(
+            results.remove("glarb").unwrap().into_completion(),
+            Err(CompletionError::ProviderError(message)) if message == "max_tokens: too large"
+        ));
+        assert!(matches!(
+            results.remove("linlingdong").unwrap(),
+            BatchResult::Expired
+        ));
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("POST /v1/messages/batches "));
+        let body = &requests[0][requests[0].find("\r\n\r\n").unwrap() + 4..];
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["requests"][1]["custom_id"], "glarb");
+        assert_eq!(body["requests"][1]["params"]["model"], CLAUDE_3_5_HAIKU);
+        assert_eq!(body["requests"][1]["params"]["max_tokens"], 8192);
+        assert_eq!(
+            body["requests"][1]["params"]["messages"][0]["content"],
+            "glarb"
+        );
+        assert!(requests[1].starts_with("GET /v1/messages/batches/msgbatch_123 "));
+        assert!(requests[3].starts_with("GET /v1/messages/batches/msgbatch_123/results "));
+    }
+}
@@ -1,6 +1,6 @@
 //! Anthropic client api implementation
 
-use crate::{agent::AgentBuilder, extractor::ExtractorBuilder};
+use crate::{agent::AgentBuilder, extractor::ExtractorBuilder, providers::Interceptors};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -76,6 +76,7 @@ impl<'a> ClientBuilder<'a> {
 pub struct Client {
     base_url: String,
     http_client: reqwest::Client,
+    interceptors: Interceptors,
 }
 
 impl Client {
@@ -110,9 +111,21 @@ impl Client {
                 })
                 .build()
                 .expect("Anthropic reqwest client should build"),
+            interceptors: Interceptors::default(),
         }
     }
 
+    /// Patch the raw JSON bodies of the requests and responses of all the models created from
+    /// the client with `interceptors` (see [Interceptors]), e.g.: to adapt to a change of the API
+    pub fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    pub(crate) fn interceptors(&self) -> &Interceptors {
+        &self.interceptors
+    }
+
     /// Create a new Anthropic client from the `ANTHROPIC_API_KEY` environment variable.
     /// Panics if the environment variable is not set.
     pub fn from_env() -> Self {
@@ -125,6 +138,11 @@ impl Client {
         self.http_client.post(url)
     }
 
+    pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{}", self.base_url, path).replace("//", "/");
+        self.http_client.get(url)
+    }
+
     pub fn completion_model(&self, model: &str) -> CompletionModel {
         CompletionModel::new(self.clone(), model)
     }
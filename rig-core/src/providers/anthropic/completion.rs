@@ -5,6 +5,7 @@ use std::iter;
 use crate::{
     completion::{self, CompletionError},
     json_utils,
+    tool::{ToolNameMap, ToolNameRules},
 };
 
 use serde::{Deserialize, Serialize};
@@ -53,12 +54,66 @@ pub enum Content {
         r#type: String,
         text: String,
     },
+    /// Call of a tool, either of the request (`tool_use`) or of a server tool executed by
+    /// Anthropic (`server_tool_use`)
     ToolUse {
         r#type: String,
         id: String,
         name: String,
         input: serde_json::Value,
     },
+    /// Result of a call of a server tool (e.g.: `web_search_tool_result`)
+    ToolResult {
+        r#type: String,
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
+}
+
+impl Content {
+    /// Whether the content is a call of a server tool or its result, executed by Anthropic
+    fn is_server_side(&self) -> bool {
+        match self {
+            Content::ToolUse { r#type, .. } => r#type == "server_tool_use",
+            Content::ToolResult { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl CompletionResponse {
+    /// Calls of the server tools executed by Anthropic (i.e.: the `server_tool_use` blocks of
+    /// the content), each with the content of its result block (or null if there is none).
+    pub fn native_tool_results(&self) -> Vec<completion::NativeToolResult> {
+        self.content
+            .iter()
+            .filter_map(|content| match content {
+                Content::ToolUse {
+                    r#type,
+                    id,
+                    name,
+                    input,
+                } if r#type == "server_tool_use" => Some(completion::NativeToolResult {
+                    id: id.clone(),
+                    name: name.clone(),
+                    input: input.clone(),
+                    output: self
+                        .content
+                        .iter()
+                        .find_map(|content| match content {
+                            Content::ToolResult {
+                                tool_use_id,
+                                content,
+                                ..
+                            } if tool_use_id == id => Some(content.clone()),
+                            _ => None,
+                        })
+                        .unwrap_or_default(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -69,6 +124,24 @@ pub struct Usage {
     pub output_tokens: u64,
 }
 
+impl From<&Usage> for completion::Usage {
+    fn from(usage: &Usage) -> Self {
+        // The input tokens reported by Anthropic exclude the tokens read from and written to
+        // the prompt cache
+        let cached_tokens = usage.cache_read_input_tokens.unwrap_or(0);
+        let cache_creation_tokens = usage.cache_creation_input_tokens.unwrap_or(0);
+        let prompt_tokens = usage.input_tokens + cached_tokens + cache_creation_tokens;
+
+        Self {
+            prompt_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: prompt_tokens + usage.output_tokens,
+            cached_tokens,
+            cache_creation_tokens,
+        }
+    }
+}
+
 impl std::fmt::Display for Usage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -105,21 +178,38 @@ impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionRe
     type Error = CompletionError;
 
     fn try_from(response: CompletionResponse) -> std::prelude::v1::Result<Self, Self::Error> {
-        match response.content.as_slice() {
-            [Content::String(text) | Content::Text { text, .. }, ..] => {
-                Ok(completion::CompletionResponse {
-                    choice: completion::ModelChoice::Message(text.to_string()),
-                    raw_response: response,
-                })
+        // The calls of server tools are executed by Anthropic, and reported separately (see
+        // [CompletionResponse::native_tool_results])
+        let choice = match response
+            .content
+            .iter()
+            .find(|content| !content.is_server_side())
+        {
+            // The text may be split into several blocks (e.g.: around the results of a search)
+            Some(Content::String(_) | Content::Text { .. }) => completion::ModelChoice::Message(
+                response
+                    .content
+                    .iter()
+                    .filter_map(|content| match content {
+                        Content::String(text) | Content::Text { text, .. } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            Some(Content::ToolUse { name, input, .. }) => {
+                completion::ModelChoice::ToolCall(name.clone(), input.clone())
             }
-            [Content::ToolUse { name, input, .. }, ..] => Ok(completion::CompletionResponse {
-                choice: completion::ModelChoice::ToolCall(name.clone(), input.clone()),
-                raw_response: response,
-            }),
-            _ => Err(CompletionError::ResponseError(
-                "Response did not contain a message or tool call".into(),
-            )),
-        }
+            _ => {
+                return Err(CompletionError::ResponseError(
+                    "Response did not contain a message or tool call".into(),
+                ))
+            }
+        };
+
+        Ok(completion::CompletionResponse {
+            choice,
+            raw_response: response,
+        })
     }
 }
 
@@ -140,9 +230,13 @@ impl From<completion::Message> for Message {
 
 #[derive(Clone)]
 pub struct CompletionModel {
-    client: Client,
+    pub(super) client: Client,
     pub model: String,
     default_max_tokens: Option<u64>,
+    /// Restrictions on the names of the tools (see [CompletionModel::tool_name_rules])
+    tool_name_rules: ToolNameRules,
+    /// Token limit of the requests (see [CompletionModel::request_limit])
+    request_limit: Option<u64>,
 }
 
 impl CompletionModel {
@@ -151,8 +245,29 @@ impl CompletionModel {
             client,
             model: model.to_string(),
             default_max_tokens: calculate_max_tokens(model),
+            tool_name_rules: ToolNameRules::ALPHANUMERIC,
+            request_limit: context_window(model),
         }
     }
+
+    /// Set the restrictions on the names of the tools ([ToolNameRules::ALPHANUMERIC] by
+    /// default). Tools with invalid names (e.g.: `math.add`) are sent under sanitized names
+    /// (e.g.: `math_add`), and their calls are resolved to the original names (see
+    /// [ToolNameMap]).
+    pub fn tool_name_rules(mut self, rules: ToolNameRules) -> Self {
+        self.tool_name_rules = rules;
+        self
+    }
+
+    /// Set the token limit of the requests: requests whose estimated number of tokens exceeds
+    /// it fail with [CompletionError::RequestTooLarge] without being sent (see
+    /// [CompletionRequest::check_size](completion::CompletionRequest::check_size)). Defaults to
+    /// the context window of the Claude 3 models, and to no limit for other models. `None`
+    /// disables the check.
+    pub fn request_limit(mut self, limit: Option<u64>) -> Self {
+        self.request_limit = limit;
+        self
+    }
 }
 
 /// Anthropic requires a `max_tokens` parameter to be set, which is dependant on the model. If not
@@ -173,6 +288,29 @@ fn calculate_max_tokens(model: &str) -> Option<u64> {
     }
 }
 
+/// Definition of a server tool. Use [NativeTool::Custom](completion::NativeTool::Custom) for
+/// other versions of the tools (code execution also requires its beta header).
+fn server_tool(tool: completion::NativeTool) -> serde_json::Value {
+    match tool {
+        completion::NativeTool::WebSearch { max_uses } => {
+            let mut tool = json!({ "type": "web_search_20250305", "name": "web_search" });
+            if let Some(max_uses) = max_uses {
+                tool["max_uses"] = json!(max_uses);
+            }
+            tool
+        }
+        completion::NativeTool::CodeExecution => {
+            json!({ "type": "code_execution_20250522", "name": "code_execution" })
+        }
+        completion::NativeTool::Custom(tool) => tool,
+    }
+}
+
+/// Context window of the model, for the models available at the time of writing
+fn context_window(model: &str) -> Option<u64> {
+    model.starts_with("claude-3").then_some(200_000)
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct Metadata {
     user_id: Option<String>,
@@ -186,19 +324,23 @@ enum ToolChoice {
     Tool { name: String },
 }
 
-impl completion::CompletionModel for CompletionModel {
-    type Response = CompletionResponse;
-
-    async fn completion(
+impl CompletionModel {
+    /// Build the body of a Messages API request from `completion_request` (also used as the
+    /// `params` of the requests of a message batch, see [super::batch]).
+    pub(super) fn create_request_body(
         &self,
         completion_request: completion::CompletionRequest,
-    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+    ) -> Result<serde_json::Value, CompletionError> {
         // Note: Ideally we'd introduce provider-specific Request models to handle the
         // specific requirements of each provider. For now, we just manually check while
         // building the request as a raw JSON document.
 
         let prompt_with_context = completion_request.prompt_with_context();
 
+        // The system instructions are sent concatenated as the top-level system prompt
+        // (see [CompletionRequest::system_instructions])
+        let system = completion_request.merged_system().unwrap_or_default();
+
         // Check if max_tokens is set, required for Anthropic
         let max_tokens = if let Some(tokens) = completion_request.max_tokens {
             tokens
@@ -215,6 +357,7 @@ impl completion::CompletionModel for CompletionModel {
             "messages": completion_request
                 .chat_history
                 .into_iter()
+                .filter(|message| !message.is_system())
                 .map(Message::from)
                 .chain(iter::once(Message {
                     role: "user".to_owned(),
@@ -222,25 +365,28 @@ impl completion::CompletionModel for CompletionModel {
                 }))
                 .collect::<Vec<_>>(),
             "max_tokens": max_tokens,
-            "system": completion_request.preamble.unwrap_or("".to_string()),
+            "system": system,
         });
 
         if let Some(temperature) = completion_request.temperature {
             json_utils::merge_inplace(&mut request, json!({ "temperature": temperature }));
         }
 
-        if !completion_request.tools.is_empty() {
+        if !completion_request.tools.is_empty() || !completion_request.native_tools.is_empty() {
             json_utils::merge_inplace(
                 &mut request,
                 json!({
                     "tools": completion_request
                         .tools
                         .into_iter()
-                        .map(|tool| ToolDefinition {
-                            name: tool.name,
-                            description: Some(tool.description),
-                            input_schema: tool.parameters,
+                        .map(|tool| {
+                            json!(ToolDefinition {
+                                name: tool.name,
+                                description: Some(tool.description),
+                                input_schema: tool.parameters,
+                            })
                         })
+                        .chain(completion_request.native_tools.into_iter().map(server_tool))
                         .collect::<Vec<_>>(),
                     "tool_choice": ToolChoice::Auto,
                 }),
@@ -251,21 +397,54 @@ impl completion::CompletionModel for CompletionModel {
             json_utils::merge_inplace(&mut request, params.clone())
         }
 
+        Ok(request)
+    }
+}
+
+impl completion::CompletionModel for CompletionModel {
+    type Response = CompletionResponse;
+
+    fn usage(response: &CompletionResponse) -> Option<completion::Usage> {
+        Some((&response.usage).into())
+    }
+
+    fn native_tool_results(response: &CompletionResponse) -> Vec<completion::NativeToolResult> {
+        response.native_tool_results()
+    }
+
+    async fn completion(
+        &self,
+        mut completion_request: completion::CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        if let Some(limit) = self.request_limit {
+            completion_request.check_size(limit)?;
+        }
+        let names = ToolNameMap::of(&completion_request.tools, self.tool_name_rules);
+        completion_request.tools = names.rename(std::mem::take(&mut completion_request.tools));
+        let request = self.create_request_body(completion_request)?;
+
         let response = self
             .client
             .post("/v1/messages")
-            .json(&request)
+            .json(&self.client.interceptors().intercept_request(&request)?)
             .send()
             .await?;
 
         if response.status().is_success() {
-            match response.json::<ApiResponse<CompletionResponse>>().await? {
+            match self
+                .client
+                .interceptors()
+                .parse::<ApiResponse<CompletionResponse>, CompletionError>(response)
+                .await?
+            {
                 ApiResponse::Message(completion) => {
                     tracing::info!(target: "rig",
                         "Anthropic completion token usage: {}",
                         completion.usage
                     );
-                    completion.try_into()
+                    let mut response: completion::CompletionResponse<_> = completion.try_into()?;
+                    response.choice = names.resolve(response.choice);
+                    Ok(response)
                 }
                 ApiResponse::Error(error) => Err(CompletionError::ProviderError(error.message)),
             }
@@ -286,3 +465,118 @@ enum ApiResponse<T> {
     Message(T),
     Error(ApiErrorResponse),
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::CompletionResponse;
+    use crate::{
+        completion::{self, CompletionModel as _, Message, ModelChoice, NativeTool},
+        providers::anthropic::{ClientBuilder, CLAUDE_3_5_HAIKU},
+    };
+
+    #[test]
+    fn test_system_instructions() {
+        let model = ClientBuilder::new("test")
+            .build()
+            .completion_model(CLAUDE_3_5_HAIKU);
+        let request = model
+            .completion_request("What is a flurbo?")
+            .preamble("You are a dictionary.".to_string())
+            .messages(vec![
+                Message {
+                    role: "user".into(),
+                    content: "Hello".into(),
+                },
+                Message {
+                    role: "system".into(),
+                    content: "Flurbos are a currency.".into(),
+                },
+            ])
+            .system("Answer in one sentence.".to_string())
+            .max_tokens(100)
+            .build();
+
+        let body = model.create_request_body(request).unwrap();
+        assert_eq!(
+            body["system"],
+            "You are a dictionary.\n\nFlurbos are a currency.\n\nAnswer in one sentence."
+        );
+        assert_eq!(
+            body["messages"],
+            json!([
+                { "role": "user", "content": "Hello" },
+                { "role": "user", "content": "What is a flurbo?" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_server_tools() {
+        let model = ClientBuilder::new("test")
+            .build()
+            .completion_model(CLAUDE_3_5_HAIKU);
+        let request = model
+            .completion_request("What is the price of a flurbo today?")
+            .native_tool(NativeTool::WebSearch { max_uses: Some(2) })
+            .build();
+
+        let body = model.create_request_body(request).unwrap();
+        assert_eq!(
+            body["tools"],
+            json!([{ "type": "web_search_20250305", "name": "web_search", "max_uses": 2 }])
+        );
+
+        let response = r#"{
+            "id": "msg_1",
+            "model": "claude-3-5-haiku-latest",
+            "role": "assistant",
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "content": [
+                { "type": "text", "text": "Let me search for that. " },
+                {
+                    "type": "server_tool_use",
+                    "id": "srvtoolu_1",
+                    "name": "web_search",
+                    "input": { "query": "flurbo price" }
+                },
+                {
+                    "type": "web_search_tool_result",
+                    "tool_use_id": "srvtoolu_1",
+                    "content": [{
+                        "type": "web_search_result",
+                        "url": "https://flurbo.example/price",
+                        "title": "Flurbo price"
+                    }]
+                },
+                { "type": "text", "text": "A flurbo is worth 2 glarbs." }
+            ],
+            "usage": { "input_tokens": 100, "output_tokens": 20 }
+        }"#;
+        let response = serde_json::from_str::<CompletionResponse>(response).unwrap();
+
+        // The server tool call is not reported as a call of a tool of the request
+        let results = super::CompletionModel::native_tool_results(&response);
+        let response = completion::CompletionResponse::try_from(response).unwrap();
+        assert_eq!(
+            response.choice,
+            ModelChoice::Message("Let me search for that. A flurbo is worth 2 glarbs.".into())
+        );
+
+        assert_eq!(
+            results,
+            vec![completion::NativeToolResult {
+                id: "srvtoolu_1".into(),
+                name: "web_search".into(),
+                input: json!({ "query": "flurbo price" }),
+                output: json!([{
+                    "type": "web_search_result",
+                    "url": "https://flurbo.example/price",
+                    "title": "Flurbo price"
+                }]),
+            }]
+        );
+    }
+}
@@ -8,16 +8,23 @@
 //!
 //! let command_r = client.completion_model(cohere::COMMAND_R);
 //! ```
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
 
 use crate::{
     agent::AgentBuilder,
     completion::{self, CompletionError},
-    embeddings::{self, EmbeddingError, EmbeddingsBuilder},
+    embeddings::{self, EmbeddingError, EmbeddingsBuilder, InputTruncation},
     extractor::ExtractorBuilder,
-    json_utils, Embed,
+    json_utils,
+    providers::Interceptors,
+    streaming::{self, DeltaTracing, StreamEvent, StreamingResult},
+    Embed,
 };
 
+use futures::{Stream, StreamExt};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -31,6 +38,7 @@ const COHERE_API_BASE_URL: &str = "https://api.cohere.ai";
 pub struct Client {
     base_url: String,
     http_client: reqwest::Client,
+    interceptors: Interceptors,
 }
 
 impl Client {
@@ -54,9 +62,17 @@ impl Client {
                 })
                 .build()
                 .expect("Cohere reqwest client should build"),
+            interceptors: Interceptors::default(),
         }
     }
 
+    /// Patch the raw JSON bodies of the requests and responses of all the models created from
+    /// the client with `interceptors` (see [Interceptors]), e.g.: to adapt to a change of the API
+    pub fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
     /// Create a new Cohere client from the `COHERE_API_KEY` environment variable.
     /// Panics if the environment variable is not set.
     pub fn from_env() -> Self {
@@ -157,7 +173,7 @@ pub struct EmbeddingResponse {
     pub meta: Option<Meta>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct Meta {
     pub api_version: ApiVersion,
     pub billed_units: BilledUnits,
@@ -165,7 +181,7 @@ pub struct Meta {
     pub warnings: Vec<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct ApiVersion {
     pub version: String,
     #[serde(default)]
@@ -186,6 +202,17 @@ pub struct BilledUnits {
     pub classifications: u32,
 }
 
+impl From<&BilledUnits> for completion::Usage {
+    fn from(units: &BilledUnits) -> Self {
+        Self {
+            prompt_tokens: units.input_tokens as u64,
+            completion_tokens: units.output_tokens as u64,
+            total_tokens: (units.input_tokens + units.output_tokens) as u64,
+            ..Default::default()
+        }
+    }
+}
+
 impl std::fmt::Display for BilledUnits {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -201,6 +228,9 @@ pub struct EmbeddingModel {
     client: Client,
     pub model: String,
     pub input_type: String,
+    /// Truncation of over-length texts (see [embeddings::EmbeddingModel::with_input_truncation]). Cohere
+    /// truncates the end of the texts if not set.
+    pub truncate: Option<InputTruncation>,
     ndims: usize,
 }
 
@@ -211,6 +241,13 @@ impl embeddings::EmbeddingModel for EmbeddingModel {
         self.ndims
     }
 
+    fn with_input_truncation(&self, truncation: InputTruncation) -> Option<Self> {
+        Some(Self {
+            truncate: Some(truncation),
+            ..self.clone()
+        })
+    }
+
     async fn embed_texts(
         &self,
         documents: impl IntoIterator<Item = String>,
@@ -220,16 +257,22 @@ impl embeddings::EmbeddingModel for EmbeddingModel {
         let response = self
             .client
             .post("/v1/embed")
-            .json(&json!({
-                "model": self.model,
-                "texts": documents,
-                "input_type": self.input_type,
-            }))
+            .json(
+                &self
+                    .client
+                    .interceptors
+                    .intercept_request(&self.create_embedding_request(&documents))?,
+            )
             .send()
             .await?;
 
         if response.status().is_success() {
-            match response.json::<ApiResponse<EmbeddingResponse>>().await? {
+            match self
+                .client
+                .interceptors
+                .parse::<ApiResponse<EmbeddingResponse>, EmbeddingError>(response)
+                .await?
+            {
                 ApiResponse::Ok(response) => {
                     match response.meta {
                         Some(meta) => tracing::info!(target: "rig",
@@ -276,16 +319,30 @@ impl EmbeddingModel {
             client,
             model: model.to_string(),
             input_type: input_type.to_string(),
+            truncate: None,
             ndims,
         }
     }
+
+    fn create_embedding_request(&self, documents: &[String]) -> serde_json::Value {
+        let request = json!({
+            "model": self.model,
+            "texts": documents,
+            "input_type": self.input_type,
+        });
+
+        match self.truncate {
+            Some(truncate) => json_utils::merge(request, json!({ "truncate": truncate })),
+            None => request,
+        }
+    }
 }
 
 // ================================================================
 // Cohere Completion API
 // ================================================================
 /// `command-r-plus` completion model
-pub const COMMAND_R_PLUS: &str = "comman-r-plus";
+pub const COMMAND_R_PLUS: &str = "command-r-plus";
 /// `command-r` completion model
 pub const COMMAND_R: &str = "command-r";
 /// `command` completion model
@@ -316,6 +373,8 @@ pub struct CompletionResponse {
     pub tool_calls: Vec<ToolCall>,
     #[serde(default)]
     pub chat_history: Vec<ChatHistory>,
+    #[serde(default)]
+    pub meta: Option<Meta>,
 }
 
 impl From<CompletionResponse> for completion::CompletionResponse<CompletionResponse> {
@@ -400,7 +459,8 @@ pub struct Parameter {
 pub struct ToolDefinition {
     pub name: String,
     pub description: String,
-    pub parameter_definitions: HashMap<String, Parameter>,
+    /// Ordered by name, so that identical requests serialize to identical payloads
+    pub parameter_definitions: BTreeMap<String, Parameter>,
 }
 
 impl From<completion::ToolDefinition> for ToolDefinition {
@@ -469,7 +529,7 @@ impl From<completion::ToolDefinition> for ToolDefinition {
                         },
                     )
                 })
-                .collect::<HashMap<_, _>>(),
+                .collect::<BTreeMap<_, _>>(),
         }
     }
 }
@@ -498,6 +558,10 @@ impl From<completion::Message> for Message {
 pub struct CompletionModel {
     client: Client,
     pub model: String,
+    /// Debug mode of the streamed deltas (see [CompletionModel::trace_deltas])
+    trace_deltas: DeltaTracing,
+    /// Idle timeout of the streamed responses (see [CompletionModel::stream_idle_timeout])
+    stream_idle_timeout: Option<Duration>,
 }
 
 impl CompletionModel {
@@ -505,18 +569,31 @@ impl CompletionModel {
         Self {
             client,
             model: model.to_string(),
+            trace_deltas: DeltaTracing::Off,
+            stream_idle_timeout: None,
         }
     }
-}
 
-impl completion::CompletionModel for CompletionModel {
-    type Response = CompletionResponse;
+    /// Set the debug mode logging each streamed delta as a `tracing::trace!` event
+    /// (see [DeltaTracing]). Off by default.
+    pub fn trace_deltas(mut self, mode: DeltaTracing) -> Self {
+        self.trace_deltas = mode;
+        self
+    }
 
-    async fn completion(
+    /// Set the idle timeout of the streamed responses: a stream receiving no event within
+    /// `timeout` of the previous one fails with [CompletionError::StreamIdleTimeout] instead
+    /// of hanging (see [streaming::idle_timeout]). Unset by default.
+    pub fn stream_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_idle_timeout = Some(timeout);
+        self
+    }
+
+    fn create_completion_request(
         &self,
         completion_request: completion::CompletionRequest,
-    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
-        let request = json!({
+    ) -> serde_json::Value {
+        let mut request = json!({
             "model": self.model,
             "preamble": completion_request.preamble,
             "message": completion_request.prompt,
@@ -526,21 +603,71 @@ impl completion::CompletionModel for CompletionModel {
             "tools": completion_request.tools.into_iter().map(ToolDefinition::from).collect::<Vec<_>>(),
         });
 
-        let response = self
-            .client
-            .post("/v1/chat")
-            .json(
-                &if let Some(ref params) = completion_request.additional_params {
-                    json_utils::merge(request.clone(), params.clone())
-                } else {
-                    request.clone()
-                },
-            )
-            .send()
-            .await?;
+        if let Some(max_tokens) = completion_request.max_tokens {
+            request = json_utils::merge(request, json!({ "max_tokens": max_tokens }));
+        }
+
+        if let Some(params) = completion_request.additional_params {
+            json_utils::merge(request, params)
+        } else {
+            request
+        }
+    }
+
+    /// Send the completion request and stream the response as [StreamEvent]s.
+    pub async fn stream(
+        &self,
+        completion_request: completion::CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        let request = json_utils::merge(
+            self.create_completion_request(completion_request),
+            json!({ "stream": true }),
+        );
+
+        let request = self.client.interceptors.intercept_request(&request)?;
+        let response = self.client.post("/v1/chat").json(&request).send().await?;
+
+        if response.status().is_success() {
+            let stream =
+                streaming::trace_deltas(stream_events(response.bytes_stream()), self.trace_deltas);
+            Ok(match self.stream_idle_timeout {
+                Some(timeout) => streaming::idle_timeout(stream, timeout),
+                None => stream,
+            })
+        } else {
+            Err(CompletionError::ProviderError(response.text().await?))
+        }
+    }
+}
+
+impl streaming::StreamingCompletionModel for CompletionModel {
+    async fn stream(
+        &self,
+        request: completion::CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        CompletionModel::stream(self, request).await
+    }
+}
+
+impl completion::CompletionModel for CompletionModel {
+    type Response = CompletionResponse;
+
+    async fn completion(
+        &self,
+        completion_request: completion::CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let request = self.create_completion_request(completion_request);
+
+        let request = self.client.interceptors.intercept_request(&request)?;
+        let response = self.client.post("/v1/chat").json(&request).send().await?;
 
         if response.status().is_success() {
-            match response.json::<ApiResponse<CompletionResponse>>().await? {
+            match self
+                .client
+                .interceptors
+                .parse::<ApiResponse<CompletionResponse>, CompletionError>(response)
+                .await?
+            {
                 ApiResponse::Ok(completion) => Ok(completion.into()),
                 ApiResponse::Err(error) => Err(CompletionError::ProviderError(error.message)),
             }
@@ -548,4 +675,229 @@ impl completion::CompletionModel for CompletionModel {
             Err(CompletionError::ProviderError(response.text().await?))
         }
     }
+
+    fn usage(response: &CompletionResponse) -> Option<completion::Usage> {
+        response
+            .meta
+            .as_ref()
+            .map(|meta| (&meta.billed_units).into())
+    }
+}
+
+// ================================================================
+// Cohere Streaming API
+// ================================================================
+#[derive(Debug, Deserialize)]
+#[serde(tag = "event_type", rename_all = "kebab-case")]
+enum StreamingEvent {
+    TextGeneration {
+        text: String,
+    },
+    ToolCallsGeneration {
+        #[serde(default)]
+        tool_calls: Vec<ToolCall>,
+    },
+    StreamEnd {
+        #[serde(default)]
+        response: Option<StreamEndResponse>,
+    },
+    /// Other events (e.g.: `stream-start`, `citation-generation`, etc.) are ignored
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamEndResponse {
+    #[serde(default)]
+    meta: Option<Meta>,
+}
+
+/// Convert the raw events of a Cohere streaming response (newline-delimited JSON objects)
+/// to [StreamEvent]s. The `stream-end` event is converted to [StreamEvent::Done], carrying the
+/// billed units of the request as its usage.
+fn stream_events<S, B, E>(stream: S) -> StreamingResult
+where
+    S: Stream<Item = Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]> + Send,
+    E: Into<CompletionError> + Send,
+{
+    Box::pin(streaming::decode_lines(stream).flat_map(|line| {
+        let events = match line {
+            Ok(line) if line.trim().is_empty() => vec![],
+            Ok(line) => match serde_json::from_str::<StreamingEvent>(&line) {
+                Ok(StreamingEvent::TextGeneration { text }) => vec![Ok(StreamEvent::Delta(text))],
+                Ok(StreamingEvent::ToolCallsGeneration { tool_calls }) => tool_calls
+                    .into_iter()
+                    .map(|call| {
+                        Ok(StreamEvent::ToolCall {
+                            name: call.name,
+                            arguments: call.parameters,
+                        })
+                    })
+                    .collect(),
+                Ok(StreamingEvent::StreamEnd { response }) => vec![Ok(StreamEvent::Done {
+                    usage: response
+                        .and_then(|response| response.meta)
+                        .map(|meta| (&meta.billed_units).into()),
+                })],
+                Ok(StreamingEvent::Other) => vec![],
+                Err(err) => vec![Err(CompletionError::JsonError(err))],
+            },
+            Err(err) => vec![Err(err)],
+        };
+
+        futures::stream::iter(events)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{stream, StreamExt};
+    use serde_json::json;
+
+    use super::*;
+    use crate::completion::{CompletionRequest, Usage};
+
+    #[test]
+    fn test_embedding_truncation() {
+        let model = Client::new("test").embedding_model(EMBED_ENGLISH_V3, "search_document");
+        let documents = vec!["Flurbos are a made up currency.".to_string()];
+
+        let request = model.create_embedding_request(&documents);
+        assert!(request.get("truncate").is_none());
+
+        let model =
+            embeddings::EmbeddingModel::with_input_truncation(&model, InputTruncation::Start)
+                .unwrap();
+        let request = model.create_embedding_request(&documents);
+        assert_eq!(request["truncate"], "START");
+        assert_eq!(request["texts"], json!(documents));
+
+        let model =
+            embeddings::EmbeddingModel::with_input_truncation(&model, InputTruncation::None)
+                .unwrap();
+        assert_eq!(
+            model.create_embedding_request(&documents)["truncate"],
+            "NONE"
+        );
+    }
+
+    #[test]
+    fn test_multi_turn_request() {
+        let model = Client::new("test").completion_model(COMMAND_R);
+
+        let request = model.create_completion_request(CompletionRequest {
+            prompt: "And in dollars?".to_string(),
+            preamble: Some("You are a helpful assistant.".to_string()),
+            system: vec![],
+            chat_history: vec![
+                completion::Message {
+                    role: "user".to_string(),
+                    content: "How much is 3 flurbos?".to_string(),
+                },
+                completion::Message {
+                    role: "assistant".to_string(),
+                    content: "3 flurbos are 6 glarbs.".to_string(),
+                },
+            ],
+            documents: vec![],
+            tools: vec![completion::ToolDefinition {
+                name: "convert".to_string(),
+                description: "Convert an amount to another currency".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "amount": { "type": "number", "description": "The amount to convert" },
+                        "currency": { "type": ["string", "null"], "description": "The target currency" }
+                    },
+                    "required": ["amount"]
+                }),
+            }],
+            temperature: Some(0.5),
+            max_tokens: Some(256),
+            additional_params: None,
+            idempotency_key: None,
+            images: vec![],
+            reasoning_effort: None,
+            native_tools: vec![],
+        });
+
+        assert_eq!(
+            request,
+            json!({
+                "model": "command-r",
+                "preamble": "You are a helpful assistant.",
+                "message": "And in dollars?",
+                "documents": [],
+                "chat_history": [
+                    { "role": "USER", "message": "How much is 3 flurbos?" },
+                    { "role": "CHATBOT", "message": "3 flurbos are 6 glarbs." }
+                ],
+                "temperature": 0.5,
+                "max_tokens": 256,
+                "tools": [{
+                    "name": "convert",
+                    "description": "Convert an amount to another currency",
+                    "parameter_definitions": {
+                        "amount": {
+                            "description": "The amount to convert",
+                            "type": "number",
+                            "required": true
+                        },
+                        "currency": {
+                            "description": "The target currency",
+                            "type": "string",
+                            "required": false
+                        }
+                    }
+                }]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_events() {
+        let chunks = vec![
+            Ok::<_, CompletionError>(
+                r#"{"is_finished":false,"event_type":"stream-start","generation_id":"1"}
+{"is_finished":false,"event_type":"text-generation","text":"Let me"}
+{"is_fi"#
+                    .as_bytes()
+                    .to_vec(),
+            ),
+            Ok(r#"nished":false,"event_type":"text-generation","text":" convert"}
+{"is_finished":false,"event_type":"tool-calls-generation","tool_calls":[{"name":"convert","parameters":{"amount":6}}]}
+{"is_finished":true,"event_type":"stream-end","finish_reason":"COMPLETE","response":{"meta":{"api_version":{"version":"1"},"billed_units":{"input_tokens":20,"output_tokens":5}}}}
+"#
+                .as_bytes()
+                .to_vec()),
+        ];
+
+        let events = stream_events(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Delta("Let me".to_string()),
+                StreamEvent::Delta(" convert".to_string()),
+                StreamEvent::ToolCall {
+                    name: "convert".to_string(),
+                    arguments: json!({ "amount": 6 }),
+                },
+                StreamEvent::Done {
+                    usage: Some(Usage {
+                        prompt_tokens: 20,
+                        completion_tokens: 5,
+                        total_tokens: 25,
+                        ..Default::default()
+                    })
+                },
+            ]
+        );
+    }
 }
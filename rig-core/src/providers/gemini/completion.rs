@@ -115,11 +115,14 @@ impl completion::CompletionModel for CompletionModel {
         let response = self
             .client
             .post(&format!("/v1beta/models/{}:generateContent", self.model))
-            .json(&request)
+            .json(&self.client.interceptors().intercept_request(&request)?)
             .send()
             .await?
-            .error_for_status()?
-            .json::<GenerateContentResponse>()
+            .error_for_status()?;
+        let response = self
+            .client
+            .interceptors()
+            .parse::<GenerateContentResponse, CompletionError>(response)
             .await?;
 
         match response.usage_metadata {
@@ -186,7 +189,8 @@ impl TryFrom<GenerateContentResponse> for completion::CompletionResponse<Generat
 }
 
 pub mod gemini_api_types {
-    use std::collections::HashMap;
+    // Ordered maps, so that identical requests serialize to identical payloads
+    use std::collections::BTreeMap;
 
     // =================================================================
     // Gemini API Types
@@ -314,7 +318,7 @@ pub mod gemini_api_types {
         /// with a maximum length of 63.
         pub name: String,
         /// The function response in JSON object format.
-        pub response: Option<HashMap<String, Value>>,
+        pub response: Option<BTreeMap<String, Value>>,
     }
 
     /// URI based data.
@@ -566,7 +570,7 @@ pub mod gemini_api_types {
         pub r#enum: Option<Vec<String>>,
         pub max_items: Option<i32>,
         pub min_items: Option<i32>,
-        pub properties: Option<HashMap<String, Schema>>,
+        pub properties: Option<BTreeMap<String, Schema>>,
         pub required: Option<Vec<String>>,
         pub items: Option<Box<Schema>>,
     }
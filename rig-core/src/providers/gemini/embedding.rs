@@ -60,11 +60,19 @@ impl embeddings::EmbeddingModel for EmbeddingModel {
         let response = self
             .client
             .post(&format!("/v1beta/models/{}:embedContent", self.model))
-            .json(&request_body)
+            .json(
+                &self
+                    .client
+                    .interceptors()
+                    .intercept_request(&request_body)?,
+            )
             .send()
             .await?
-            .error_for_status()?
-            .json::<ApiResponse<gemini_api_types::EmbeddingResponse>>()
+            .error_for_status()?;
+        let response = self
+            .client
+            .interceptors()
+            .parse::<ApiResponse<gemini_api_types::EmbeddingResponse>, EmbeddingError>(response)
             .await?;
 
         match response {
@@ -0,0 +1,162 @@
+//! This module provides the [Interceptors] of the provider clients, hooks receiving the raw JSON
+//! bodies of the requests before they are sent and of the responses before they are parsed into
+//! the typed responses of the providers.
+//!
+//! Interceptors let users adapt to changes of the API of a provider without waiting for a
+//! release of the crate (or forking it), e.g.: by renaming a field which was moved in the
+//! responses, or by adding a new required parameter to the requests.
+//!
+//! # Example
+//! ```rust
+//! use rig::providers::{openai, Interceptors};
+//!
+//! let interceptors = Interceptors::new().response(|body| {
+//!     // The provider renamed `choices` to `outputs`
+//!     if let Some(outputs) = body.as_object_mut().and_then(|body| body.remove("outputs")) {
+//!         body["choices"] = outputs;
+//!     }
+//! });
+//!
+//! let openai = openai::Client::from_env().with_interceptors(interceptors);
+//! ```
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Hook patching a raw JSON body in place (see [Interceptors])
+pub type Interceptor = Arc<dyn Fn(&mut serde_json::Value) + Send + Sync>;
+
+/// Request and response interceptors of a provider client (see the [module](self) docs).
+/// Interceptors are called in the order they were added, and apply to the completion and
+/// embedding requests of all the models created from the client. Streamed responses are not
+/// intercepted.
+#[derive(Clone, Default)]
+pub struct Interceptors {
+    requests: Vec<Interceptor>,
+    responses: Vec<Interceptor>,
+}
+
+impl std::fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interceptors")
+            .field("requests", &self.requests.len())
+            .field("responses", &self.responses.len())
+            .finish()
+    }
+}
+
+impl Interceptors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an interceptor of the bodies of the requests, called before they are sent
+    pub fn request(
+        mut self,
+        interceptor: impl Fn(&mut serde_json::Value) + Send + Sync + 'static,
+    ) -> Self {
+        self.requests.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Add an interceptor of the bodies of the responses, called before they are parsed
+    pub fn response(
+        mut self,
+        interceptor: impl Fn(&mut serde_json::Value) + Send + Sync + 'static,
+    ) -> Self {
+        self.responses.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Whether no interceptors were added
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty() && self.responses.is_empty()
+    }
+
+    /// Serialize the body of a request and pass it through the request interceptors
+    pub fn intercept_request<T: Serialize>(
+        &self,
+        body: &T,
+    ) -> Result<serde_json::Value, serde_json::Error> {
+        let mut body = serde_json::to_value(body)?;
+        for interceptor in &self.requests {
+            interceptor(&mut body);
+        }
+        Ok(body)
+    }
+
+    /// Pass the raw body of a response through the response interceptors and parse it
+    pub fn intercept_response<T: DeserializeOwned>(
+        &self,
+        mut body: serde_json::Value,
+    ) -> Result<T, serde_json::Error> {
+        for interceptor in &self.responses {
+            interceptor(&mut body);
+        }
+        serde_json::from_value(body)
+    }
+
+    /// Parse the body of an HTTP response, through the response interceptors if any
+    pub(crate) async fn parse<T, E>(&self, response: reqwest::Response) -> Result<T, E>
+    where
+        T: DeserializeOwned,
+        E: From<reqwest::Error> + From<serde_json::Error>,
+    {
+        if self.responses.is_empty() {
+            return Ok(response.json().await?);
+        }
+
+        let body = response.json::<serde_json::Value>().await?;
+        Ok(self.intercept_response(body)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::Interceptors;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Response {
+        text: String,
+    }
+
+    #[test]
+    fn test_response_interceptor() {
+        let body = json!({ "output_text": "Hello!" });
+        assert!(Interceptors::new()
+            .intercept_response::<Response>(body.clone())
+            .is_err());
+
+        let interceptors = Interceptors::new().response(|body| {
+            if let Some(text) = body
+                .as_object_mut()
+                .and_then(|body| body.remove("output_text"))
+            {
+                body["text"] = text;
+            }
+        });
+        assert_eq!(
+            interceptors.intercept_response::<Response>(body).unwrap(),
+            Response {
+                text: "Hello!".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_request_interceptor() {
+        let interceptors = Interceptors::new()
+            .request(|body| body["store"] = json!(false))
+            .request(|body| body["store"] = json!(true));
+
+        assert_eq!(
+            interceptors
+                .intercept_request(&json!({ "model": "gpt-4o" }))
+                .unwrap(),
+            json!({ "model": "gpt-4o", "store": true })
+        );
+    }
+}
@@ -40,9 +40,74 @@
 //! ```
 //! Note: The example above uses the OpenAI provider client, but the same pattern can
 //! be used with the Cohere provider client.
+//!
+//! Clients of providers exposing their available models implement the [ListModels] trait.
+//!
+//! The raw JSON bodies of the requests and responses of the clients can be patched with
+//! [Interceptors] (e.g.: to adapt to a change of the API of a provider before a release).
+use serde::{Deserialize, Serialize};
+
 pub mod anthropic;
 pub mod cohere;
 pub mod gemini;
+pub mod interceptor;
 pub mod openai;
 pub mod perplexity;
 pub mod xai;
+
+pub use interceptor::Interceptors;
+
+#[cfg(feature = "vcr")]
+pub mod vcr;
+
+/// A model available from a provider (see [ListModels])
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ModelInfo {
+    /// Id of the model, as used to create completion or embedding models
+    pub id: String,
+    /// Organization owning the model, if reported by the provider
+    pub owned_by: Option<String>,
+    /// Creation time of the model (as a Unix timestamp), if reported by the provider
+    pub created: Option<u64>,
+    /// Raw, provider-specific metadata of the model
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ListModelsError {
+    /// Http error (e.g.: connection error, timeout, etc.)
+    #[error("HttpError: {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    /// Json error (e.g.: serialization, deserialization)
+    #[error("JsonError: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    /// Error returned by the provider
+    #[error("ProviderError: {0}")]
+    ProviderError(String),
+
+    /// The provider does not support listing its models (e.g.: an OpenAI-compatible API
+    /// without a `/models` endpoint)
+    #[error("Listing models is not supported by this provider")]
+    NotSupported,
+}
+
+/// Trait for provider clients that can list the models available to the user, e.g.: to
+/// present them in a configuration UI.
+///
+/// # Example
+/// ```rust
+/// use rig::providers::{openai, ListModels};
+///
+/// let openai = openai::Client::from_env();
+/// for model in openai.list_models().await? {
+///     println!("{} (owned by {:?})", model.id, model.owned_by);
+/// }
+/// ```
+pub trait ListModels {
+    /// List the models available from the provider
+    fn list_models(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<ModelInfo>, ListModelsError>> + Send;
+}
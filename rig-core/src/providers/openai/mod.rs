@@ -0,0 +1,2539 @@
+//! OpenAI API client and Rig integration
+//!
+//! # Example
+//! ```
+//! use rig::providers::openai;
+//!
+//! let client = openai::Client::new("YOUR_API_KEY");
+//!
+//! let gpt4o = client.completion_model(openai::GPT_4O);
+//! ```
+use std::time::Duration;
+
+use crate::{
+    agent::AgentBuilder,
+    completion::{self, CompletionError, CompletionRequest, RetryPolicy},
+    concurrency::{ConcurrencyLimiter, ConcurrencyPermit},
+    embeddings::{self, EmbeddingError, EmbeddingsBuilder},
+    extractor::ExtractorBuilder,
+    json_utils,
+    providers::{Interceptors, ListModels, ListModelsError, ModelInfo},
+    streaming::{self, DeltaTracing, StreamEvent, StreamingResult},
+    tool::{ToolNameMap, ToolNameRules},
+    Embed,
+};
+use futures::{Stream, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[cfg(feature = "realtime")]
+pub mod realtime;
+pub mod responses;
+pub mod strict;
+
+// ================================================================
+// Main OpenAI Client
+// ================================================================
+const OPENAI_API_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Clone)]
+pub struct Client {
+    base_url: String,
+    /// API key, sent as a bearer token (only kept for the websocket handshake of
+    /// [realtime::RealtimeModel], other requests use the default headers of `http_client`)
+    #[cfg_attr(not(feature = "realtime"), allow(dead_code))]
+    api_key: String,
+    http_client: reqwest::Client,
+    /// Limiter of the concurrent requests of the models of the client
+    limiter: Option<ConcurrencyLimiter>,
+    interceptors: Interceptors,
+}
+
+impl Client {
+    /// Create a new OpenAI client with the given API key.
+    pub fn new(api_key: &str) -> Self {
+        Self::from_url(api_key, OPENAI_API_BASE_URL)
+    }
+
+    /// Create a new OpenAI client with the given API key and base API URL.
+    pub fn from_url(api_key: &str, base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            api_key: api_key.to_string(),
+            http_client: reqwest::Client::builder()
+                .default_headers({
+                    let mut headers = reqwest::header::HeaderMap::new();
+                    headers.insert(
+                        "Authorization",
+                        format!("Bearer {}", api_key)
+                            .parse()
+                            .expect("Bearer token should parse"),
+                    );
+                    headers
+                })
+                .build()
+                .expect("OpenAI reqwest client should build"),
+            limiter: None,
+            interceptors: Interceptors::default(),
+        }
+    }
+
+    /// Patch the raw JSON bodies of the requests and responses of all the models created from
+    /// the client with `interceptors` (see [Interceptors]), e.g.: to adapt to a change of the API
+    pub fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Cap the number of concurrent requests of all the models created from the client
+    /// (embeddings and completions combined) with `limiter`, which can also be shared with
+    /// other clients (see [ConcurrencyLimiter]). The permit of a request is held until its
+    /// response is fully received (including retries, or the end of a streamed response).
+    ///
+    /// # Example
+    /// ```
+    /// use rig::{concurrency::ConcurrencyLimiter, providers::openai::Client};
+    ///
+    /// let openai = Client::new("your-open-ai-api-key")
+    ///     .with_concurrency_limiter(ConcurrencyLimiter::new(8));
+    /// ```
+    pub fn with_concurrency_limiter(mut self, limiter: ConcurrencyLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Wait for a permit of the concurrency limiter of the client, if any
+    async fn permit(&self) -> Option<ConcurrencyPermit> {
+        match &self.limiter {
+            Some(limiter) => Some(limiter.acquire().await),
+            None => None,
+        }
+    }
+
+    /// Create a new OpenAI client from the `OPENAI_API_KEY` environment variable.
+    /// Panics if the environment variable is not set.
+    pub fn from_env() -> Self {
+        let api_key = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set");
+        Self::new(&api_key)
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{}", self.base_url, path).replace("//", "/");
+        self.http_client.post(url)
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{}", self.base_url, path).replace("//", "/");
+        self.http_client.get(url)
+    }
+
+    /// Create an embedding model with the given name.
+    /// Note: default embedding dimension of 0 will be used if model is not known.
+    /// If this is the case, it's better to use function `embedding_model_with_ndims`
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::openai::{Client, self};
+    ///
+    /// // Initialize the OpenAI client
+    /// let openai = Client::new("your-open-ai-api-key");
+    ///
+    /// let embedding_model = openai.embedding_model(openai::TEXT_EMBEDDING_3_LARGE);
+    /// ```
+    pub fn embedding_model(&self, model: &str) -> EmbeddingModel {
+        let ndims = match model {
+            TEXT_EMBEDDING_3_LARGE => 3072,
+            TEXT_EMBEDDING_3_SMALL | TEXT_EMBEDDING_ADA_002 => 1536,
+            _ => 0,
+        };
+        EmbeddingModel::new(self.clone(), model, ndims)
+    }
+
+    /// Create an embedding model with the given name and the number of dimensions in the embedding generated by the model.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::openai::{Client, self};
+    ///
+    /// // Initialize the OpenAI client
+    /// let openai = Client::new("your-open-ai-api-key");
+    ///
+    /// let embedding_model = openai.embedding_model("model-unknown-to-rig", 3072);
+    /// ```
+    pub fn embedding_model_with_ndims(&self, model: &str, ndims: usize) -> EmbeddingModel {
+        EmbeddingModel::new(self.clone(), model, ndims)
+    }
+
+    /// Create an embedding builder with the given embedding model.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::openai::{Client, self};
+    ///
+    /// // Initialize the OpenAI client
+    /// let openai = Client::new("your-open-ai-api-key");
+    ///
+    /// let embeddings = openai.embeddings(openai::TEXT_EMBEDDING_3_LARGE)
+    ///     .simple_document("doc0", "Hello, world!")
+    ///     .simple_document("doc1", "Goodbye, world!")
+    ///     .build()
+    ///     .await
+    ///     .expect("Failed to embed documents");
+    /// ```
+    pub fn embeddings<D: Embed>(&self, model: &str) -> EmbeddingsBuilder<EmbeddingModel, D> {
+        EmbeddingsBuilder::new(self.embedding_model(model))
+    }
+
+    /// Create a completion model with the given name.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::openai::{Client, self};
+    ///
+    /// // Initialize the OpenAI client
+    /// let openai = Client::new("your-open-ai-api-key");
+    ///
+    /// let gpt4 = openai.completion_model(openai::GPT_4);
+    /// ```
+    pub fn completion_model(&self, model: &str) -> CompletionModel {
+        CompletionModel::new(self.clone(), model)
+    }
+
+    /// Create a completion model with the given name, using the Responses API (see
+    /// [responses::CompletionModel]) instead of the chat completions API.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::openai::{Client, self};
+    ///
+    /// // Initialize the OpenAI client
+    /// let openai = Client::new("your-open-ai-api-key");
+    ///
+    /// let gpt4o = openai.responses_model(openai::GPT_4O);
+    /// ```
+    pub fn responses_model(&self, model: &str) -> responses::CompletionModel {
+        responses::CompletionModel::new(self.clone(), model)
+    }
+
+    /// Create a model with the given name streaming its responses over a websocket connection
+    /// to the Realtime API (see [realtime::RealtimeModel]), e.g.: for low-latency interactive
+    /// apps. Requires the `realtime` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::openai::{realtime, Client};
+    ///
+    /// // Initialize the OpenAI client
+    /// let openai = Client::new("your-open-ai-api-key");
+    ///
+    /// let model = openai.realtime_model(realtime::GPT_4O_REALTIME_PREVIEW);
+    /// ```
+    #[cfg(feature = "realtime")]
+    pub fn realtime_model(&self, model: &str) -> realtime::RealtimeModel {
+        realtime::RealtimeModel::new(self.clone(), model)
+    }
+
+    /// Create an agent builder with the given completion model.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::openai::{Client, self};
+    ///
+    /// // Initialize the OpenAI client
+    /// let openai = Client::new("your-open-ai-api-key");
+    ///
+    /// let agent = openai.agent(openai::GPT_4)
+    ///    .preamble("You are comedian AI with a mission to make people laugh.")
+    ///    .temperature(0.0)
+    ///    .build();
+    /// ```
+    pub fn agent(&self, model: &str) -> AgentBuilder<CompletionModel> {
+        AgentBuilder::new(self.completion_model(model))
+    }
+
+    /// Create an extractor builder with the given completion model.
+    ///
+    /// Models supporting structured outputs (see [ModelCapabilities::structured_outputs])
+    /// extract the data in strict mode (see [CompletionModel::strict_tools]), guaranteeing
+    /// that it adheres to the schema of `T`, unless the schema of `T` cannot be represented in
+    /// strict mode (see [strict::to_strict_schema]).
+    pub fn extractor<T: JsonSchema + for<'a> Deserialize<'a> + Serialize + Send + Sync>(
+        &self,
+        model: &str,
+    ) -> ExtractorBuilder<T, CompletionModel> {
+        let mut completion_model = self.completion_model(model);
+        if completion_model.capabilities().structured_outputs {
+            match strict::strict_schema::<T>() {
+                Ok(_) => completion_model = completion_model.strict_tools(true),
+                Err(err) => tracing::warn!(target: "rig",
+                    "Extracting with {model} without strict mode: {err}"
+                ),
+            }
+        }
+        ExtractorBuilder::new(completion_model)
+    }
+}
+
+/// Lists the models of the `/models` endpoint, falling back to the `/api/tags` endpoint of
+/// Ollama servers (e.g.: with a client created with
+/// `Client::from_url("ollama", "http://localhost:11434")`).
+impl ListModels for Client {
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ListModelsError> {
+        let response = self.get("/models").send().await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => self.list_ollama_models().await,
+            status if status.is_success() => {
+                match self
+                    .interceptors
+                    .parse::<ApiResponse<ModelList>, ListModelsError>(response)
+                    .await?
+                {
+                    ApiResponse::Ok(models) => Ok(models.into()),
+                    ApiResponse::Err(err) => Err(ListModelsError::ProviderError(err.message)),
+                }
+            }
+            _ => Err(ListModelsError::ProviderError(response.text().await?)),
+        }
+    }
+}
+
+impl Client {
+    /// List the models of the `/api/tags` endpoint of Ollama servers
+    async fn list_ollama_models(&self) -> Result<Vec<ModelInfo>, ListModelsError> {
+        let response = self.get("/api/tags").send().await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Err(ListModelsError::NotSupported),
+            status if status.is_success() => Ok(self
+                .interceptors
+                .parse::<OllamaModelList, ListModelsError>(response)
+                .await?
+                .into()),
+            _ => Err(ListModelsError::ProviderError(response.text().await?)),
+        }
+    }
+}
+
+/// Response of the `/api/tags` endpoint of Ollama servers
+#[derive(Debug, Deserialize)]
+struct OllamaModelList {
+    models: Vec<serde_json::Value>,
+}
+
+impl From<OllamaModelList> for Vec<ModelInfo> {
+    fn from(models: OllamaModelList) -> Self {
+        models
+            .models
+            .into_iter()
+            .filter_map(|model| {
+                Some(ModelInfo {
+                    id: model.get("name")?.as_str()?.to_string(),
+                    owned_by: None,
+                    created: model
+                        .get("modified_at")
+                        .and_then(|modified_at| modified_at.as_str())
+                        .and_then(|modified_at| {
+                            chrono::DateTime::parse_from_rfc3339(modified_at).ok()
+                        })
+                        .and_then(|modified_at| u64::try_from(modified_at.timestamp()).ok()),
+                    metadata: model,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Response of the `/models` endpoint
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<serde_json::Value>,
+}
+
+impl From<ModelList> for Vec<ModelInfo> {
+    fn from(models: ModelList) -> Self {
+        models
+            .data
+            .into_iter()
+            .filter_map(|model| {
+                Some(ModelInfo {
+                    id: model.get("id")?.as_str()?.to_string(),
+                    owned_by: model
+                        .get("owned_by")
+                        .and_then(|owned_by| owned_by.as_str())
+                        .map(str::to_string),
+                    created: model.get("created").and_then(|created| created.as_u64()),
+                    metadata: model,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiErrorResponse {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ApiResponse<T> {
+    Ok(T),
+    Err(ApiErrorResponse),
+}
+
+// ================================================================
+// OpenAI Embedding API
+// ================================================================
+/// `text-embedding-3-large` embedding model
+pub const TEXT_EMBEDDING_3_LARGE: &str = "text-embedding-3-large";
+/// `text-embedding-3-small` embedding model
+pub const TEXT_EMBEDDING_3_SMALL: &str = "text-embedding-3-small";
+/// `text-embedding-ada-002` embedding model
+pub const TEXT_EMBEDDING_ADA_002: &str = "text-embedding-ada-002";
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingResponse {
+    pub object: String,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+impl From<ApiErrorResponse> for EmbeddingError {
+    fn from(err: ApiErrorResponse) -> Self {
+        EmbeddingError::ProviderError(err.message)
+    }
+}
+
+impl From<ApiResponse<EmbeddingResponse>> for Result<EmbeddingResponse, EmbeddingError> {
+    fn from(value: ApiResponse<EmbeddingResponse>) -> Self {
+        match value {
+            ApiResponse::Ok(response) => Ok(response),
+            ApiResponse::Err(err) => Err(EmbeddingError::ProviderError(err.message)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingData {
+    pub object: String,
+    #[serde(deserialize_with = "deserialize_embedding")]
+    pub embedding: Vec<f64>,
+    pub index: usize,
+}
+
+/// Format of the embeddings returned by the API (see [EmbeddingModel::encoding_format])
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodingFormat {
+    /// Embeddings returned as arrays of numbers
+    #[default]
+    Float,
+    /// Embeddings returned as base64-encoded little-endian `f32` arrays, about 4 times
+    /// smaller on the wire
+    Base64,
+}
+
+/// Deserialize an embedding returned either as an array of numbers or as a base64-encoded
+/// array of little-endian `f32`s
+fn deserialize_embedding<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use base64::{prelude::BASE64_STANDARD, Engine};
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Embedding {
+        Float(Vec<f64>),
+        Base64(String),
+    }
+
+    match Embedding::deserialize(deserializer)? {
+        Embedding::Float(vec) => Ok(vec),
+        Embedding::Base64(data) => {
+            let bytes = BASE64_STANDARD.decode(data).map_err(D::Error::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(D::Error::custom(format!(
+                    "Invalid base64 embedding length: {} bytes",
+                    bytes.len()
+                )));
+            }
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as f64)
+                .collect())
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    #[serde(default)]
+    pub completion_tokens: Option<usize>,
+    pub total_tokens: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PromptTokensDetails {
+    /// Number of prompt tokens read from the prompt cache
+    #[serde(default)]
+    pub cached_tokens: usize,
+}
+
+impl From<Usage> for completion::Usage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens as u64,
+            completion_tokens: usage
+                .completion_tokens
+                .unwrap_or(usage.total_tokens.saturating_sub(usage.prompt_tokens))
+                as u64,
+            total_tokens: usage.total_tokens as u64,
+            cached_tokens: usage
+                .prompt_tokens_details
+                .map_or(0, |details| details.cached_tokens) as u64,
+            cache_creation_tokens: 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Usage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Prompt tokens: {} Total tokens: {}",
+            self.prompt_tokens, self.total_tokens
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct EmbeddingModel {
+    client: Client,
+    pub model: String,
+    ndims: usize,
+    encoding_format: EncodingFormat,
+}
+
+impl embeddings::EmbeddingModel for EmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    async fn embed_texts(
+        &self,
+        documents: impl IntoIterator<Item = String>,
+    ) -> Result<Vec<embeddings::Embedding>, EmbeddingError> {
+        let documents = documents.into_iter().collect::<Vec<_>>();
+
+        let mut request = json!({
+            "model": self.model,
+            "input": documents,
+        });
+        if self.encoding_format != EncodingFormat::Float {
+            request["encoding_format"] = json!(self.encoding_format);
+        }
+
+        let _permit = self.client.permit().await;
+        let response = self
+            .client
+            .post("/embeddings")
+            .json(&self.client.interceptors.intercept_request(&request)?)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            match self
+                .client
+                .interceptors
+                .parse::<ApiResponse<EmbeddingResponse>, EmbeddingError>(response)
+                .await?
+            {
+                ApiResponse::Ok(response) => {
+                    tracing::info!(target: "rig",
+                        "OpenAI embedding token usage: {}",
+                        response.usage
+                    );
+
+                    if response.data.len() != documents.len() {
+                        return Err(EmbeddingError::ResponseError(
+                            "Response data length does not match input length".into(),
+                        ));
+                    }
+
+                    Ok(response
+                        .data
+                        .into_iter()
+                        .zip(documents.into_iter())
+                        .map(|(embedding, document)| embeddings::Embedding {
+                            document,
+                            vec: embedding.embedding,
+                        })
+                        .collect())
+                }
+                ApiResponse::Err(err) => Err(EmbeddingError::ProviderError(err.message)),
+            }
+        } else {
+            Err(EmbeddingError::ProviderError(response.text().await?))
+        }
+    }
+}
+
+impl EmbeddingModel {
+    pub fn new(client: Client, model: &str, ndims: usize) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+            ndims,
+            encoding_format: EncodingFormat::default(),
+        }
+    }
+
+    /// Set the format in which the API returns the embeddings ([EncodingFormat::Float] by
+    /// default). [EncodingFormat::Base64] reduces the size of the responses, and is decoded
+    /// transparently into the same embeddings (up to `f32` precision).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::providers::openai::{self, EncodingFormat};
+    ///
+    /// let model = openai::Client::from_env()
+    ///     .embedding_model(openai::TEXT_EMBEDDING_3_SMALL)
+    ///     .encoding_format(EncodingFormat::Base64);
+    /// ```
+    pub fn encoding_format(mut self, format: EncodingFormat) -> Self {
+        self.encoding_format = format;
+        self
+    }
+}
+
+// ================================================================
+// OpenAI Completion API
+// ================================================================
+/// `o1-preview` completion model
+pub const O1_PREVIEW: &str = "o1-preview";
+/// `o1-preview-2024-09-12` completion model
+pub const O1_PREVIEW_2024_09_12: &str = "o1-preview-2024-09-12";
+/// `o1-mini completion model
+pub const O1_MINI: &str = "o1-mini";
+/// `o1-mini-2024-09-12` completion model
+pub const O1_MINI_2024_09_12: &str = "o1-mini-2024-09-12";
+/// `gpt-4o` completion model
+pub const GPT_4O: &str = "gpt-4o";
+/// `gpt-4o-2024-05-13` completion model
+pub const GPT_4O_2024_05_13: &str = "gpt-4o-2024-05-13";
+/// `gpt-4-turbo` completion model
+pub const GPT_4_TURBO: &str = "gpt-4-turbo";
+/// `gpt-4-turbo-2024-04-09` completion model
+pub const GPT_4_TURBO_2024_04_09: &str = "gpt-4-turbo-2024-04-09";
+/// `gpt-4-turbo-preview` completion model
+pub const GPT_4_TURBO_PREVIEW: &str = "gpt-4-turbo-preview";
+/// `gpt-4-0125-preview` completion model
+pub const GPT_4_0125_PREVIEW: &str = "gpt-4-0125-preview";
+/// `gpt-4-1106-preview` completion model
+pub const GPT_4_1106_PREVIEW: &str = "gpt-4-1106-preview";
+/// `gpt-4-vision-preview` completion model
+pub const GPT_4_VISION_PREVIEW: &str = "gpt-4-vision-preview";
+/// `gpt-4-1106-vision-preview` completion model
+pub const GPT_4_1106_VISION_PREVIEW: &str = "gpt-4-1106-vision-preview";
+/// `gpt-4` completion model
+pub const GPT_4: &str = "gpt-4";
+/// `gpt-4-0613` completion model
+pub const GPT_4_0613: &str = "gpt-4-0613";
+/// `gpt-4-32k` completion model
+pub const GPT_4_32K: &str = "gpt-4-32k";
+/// `gpt-4-32k-0613` completion model
+pub const GPT_4_32K_0613: &str = "gpt-4-32k-0613";
+/// `gpt-3.5-turbo` completion model
+pub const GPT_35_TURBO: &str = "gpt-3.5-turbo";
+/// `gpt-3.5-turbo-0125` completion model
+pub const GPT_35_TURBO_0125: &str = "gpt-3.5-turbo-0125";
+/// `gpt-3.5-turbo-1106` completion model
+pub const GPT_35_TURBO_1106: &str = "gpt-3.5-turbo-1106";
+/// `gpt-3.5-turbo-instruct` completion model
+pub const GPT_35_TURBO_INSTRUCT: &str = "gpt-3.5-turbo-instruct";
+
+/// Capabilities of an OpenAI completion model (see [model_capabilities])
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// Maximum number of tokens of the prompt and the completion
+    pub context_window: usize,
+    /// Whether the model supports tool calls
+    pub tools: bool,
+    /// Whether the model accepts images
+    pub vision: bool,
+    /// Whether the model is a reasoning model (e.g.: `o1`)
+    pub reasoning: bool,
+    /// Whether the model supports structured outputs, i.e.: strict schemas (see [strict])
+    pub structured_outputs: bool,
+}
+
+impl ModelCapabilities {
+    /// Capabilities assumed for unknown models: tool calls with a conservative context window.
+    pub const UNKNOWN: Self = Self {
+        context_window: 8_192,
+        tools: true,
+        vision: false,
+        reasoning: false,
+        structured_outputs: false,
+    };
+
+    const fn new(context_window: usize, tools: bool, vision: bool, reasoning: bool) -> Self {
+        Self {
+            context_window,
+            tools,
+            vision,
+            reasoning,
+            structured_outputs: false,
+        }
+    }
+
+    const fn with_structured_outputs(mut self) -> Self {
+        self.structured_outputs = true;
+        self
+    }
+}
+
+/// Capabilities of the known model families (see [model_capabilities]). More specific families
+/// come first.
+const MODEL_FAMILIES: &[(&str, ModelCapabilities)] = &[
+    (
+        "o1-pro",
+        ModelCapabilities::new(200_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "o1-mini",
+        ModelCapabilities::new(128_000, false, false, true),
+    ),
+    (
+        "o1-preview",
+        ModelCapabilities::new(128_000, false, false, true),
+    ),
+    (
+        "o1",
+        ModelCapabilities::new(200_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "o3-mini",
+        ModelCapabilities::new(200_000, true, false, true).with_structured_outputs(),
+    ),
+    (
+        "o3",
+        ModelCapabilities::new(200_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "o4-mini",
+        ModelCapabilities::new(200_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "o4",
+        ModelCapabilities::new(200_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "gpt-5-mini",
+        ModelCapabilities::new(400_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "gpt-5-nano",
+        ModelCapabilities::new(400_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "gpt-5",
+        ModelCapabilities::new(400_000, true, true, true).with_structured_outputs(),
+    ),
+    (
+        "gpt-4.5-preview",
+        ModelCapabilities::new(128_000, true, true, false).with_structured_outputs(),
+    ),
+    (
+        "gpt-4.1-mini",
+        ModelCapabilities::new(1_047_576, true, true, false).with_structured_outputs(),
+    ),
+    (
+        "gpt-4.1-nano",
+        ModelCapabilities::new(1_047_576, true, true, false).with_structured_outputs(),
+    ),
+    (
+        "gpt-4.1",
+        ModelCapabilities::new(1_047_576, true, true, false).with_structured_outputs(),
+    ),
+    (
+        "gpt-4o-mini",
+        ModelCapabilities::new(128_000, true, true, false).with_structured_outputs(),
+    ),
+    (
+        "gpt-4o-2024-05-13",
+        ModelCapabilities::new(128_000, true, true, false),
+    ),
+    (
+        "gpt-4o",
+        ModelCapabilities::new(128_000, true, true, false).with_structured_outputs(),
+    ),
+    (
+        "gpt-4-turbo",
+        ModelCapabilities::new(128_000, true, true, false),
+    ),
+    (
+        "gpt-4-vision-preview",
+        ModelCapabilities::new(128_000, false, true, false),
+    ),
+    (
+        "gpt-4-1106-vision-preview",
+        ModelCapabilities::new(128_000, false, true, false),
+    ),
+    (
+        "gpt-4-0125-preview",
+        ModelCapabilities::new(128_000, true, false, false),
+    ),
+    (
+        "gpt-4-1106-preview",
+        ModelCapabilities::new(128_000, true, false, false),
+    ),
+    (
+        "gpt-4-32k",
+        ModelCapabilities::new(32_768, true, false, false),
+    ),
+    ("gpt-4", ModelCapabilities::new(8_192, true, false, false)),
+    (
+        "gpt-3.5-turbo-instruct",
+        ModelCapabilities::new(4_096, false, false, false),
+    ),
+    (
+        "gpt-3.5-turbo",
+        ModelCapabilities::new(16_385, true, false, false),
+    ),
+];
+
+/// Base model of a fine-tuned model id of the form `ft:<base model>:<organization>:<suffix>:<id>`
+/// (e.g.: `gpt-4o-2024-08-06` for `ft:gpt-4o-2024-08-06:flurbo-corp::abc123`). Other model
+/// ids are returned as is.
+pub fn base_model(model: &str) -> &str {
+    match model.strip_prefix("ft:") {
+        Some(fine_tuned) => fine_tuned.split(':').next().unwrap_or(fine_tuned),
+        None => model,
+    }
+}
+
+/// Capabilities of `model`. A model belongs to a known family if its id is the name of the
+/// family, or the name followed by a `-` and a variant or date suffix (e.g.: `gpt-4o-mini` and
+/// `gpt-4o-2024-08-06` belong to `gpt-4o`, but `gpt-4.1` does not belong to `gpt-4`).
+/// Fine-tuned models inherit the capabilities of their base model (see [base_model]), and
+/// unknown models fall back to [ModelCapabilities::UNKNOWN].
+pub fn model_capabilities(model: &str) -> ModelCapabilities {
+    model_family(model)
+        .map(|(capabilities, _)| capabilities)
+        .unwrap_or(ModelCapabilities::UNKNOWN)
+}
+
+/// Capabilities of the known family of `model`, along with the suffix of `model` after the
+/// name of the family (e.g.: `-2024-08-06` for `gpt-4o-2024-08-06`)
+fn model_family(model: &str) -> Option<(ModelCapabilities, &str)> {
+    let base_model = base_model(model);
+
+    MODEL_FAMILIES.iter().find_map(|(family, capabilities)| {
+        let suffix = base_model.strip_prefix(family)?;
+        (suffix.is_empty() || suffix.starts_with('-')).then_some((*capabilities, suffix))
+    })
+}
+
+/// Context window of `model`, if it is a known family or one of its dated snapshots (e.g.:
+/// `gpt-4-0613`). Other variants of a family (e.g.: `gpt-4-flurbo`) may have a different
+/// context window, so that requests to them are not limited.
+fn known_context_window(model: &str) -> Option<u64> {
+    let (capabilities, suffix) = model_family(model)?;
+    suffix
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '-')
+        .then_some(capabilities.context_window as u64)
+}
+
+/// Name of the request field limiting the number of generated tokens for `model`. Reasoning
+/// models (e.g.: `o1`) reject the deprecated `max_tokens` in favor of `max_completion_tokens`.
+fn max_tokens_field(model: &str) -> &'static str {
+    if model_capabilities(model).reasoning {
+        "max_completion_tokens"
+    } else {
+        "max_tokens"
+    }
+}
+
+/// Reasoning effort of a request to `model`, if set and supported by the model (i.e.: a
+/// reasoning model). Unsupported reasoning efforts are ignored with a debug log.
+fn reasoning_effort(
+    model: &str,
+    reasoning_effort: Option<completion::ReasoningEffort>,
+) -> Option<completion::ReasoningEffort> {
+    let reasoning_effort = reasoning_effort?;
+    if model_capabilities(model).reasoning {
+        Some(reasoning_effort)
+    } else {
+        tracing::debug!(target: "rig",
+            "Ignoring the reasoning effort of a request to {model}, which is not a reasoning model"
+        );
+        None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub system_fingerprint: Option<String>,
+    pub choices: Vec<Choice>,
+    pub usage: Option<Usage>,
+    /// Number of retries of the request before this response was received
+    /// (see [CompletionModel::retry_policy]). Not part of the API response.
+    #[serde(skip)]
+    pub retries: usize,
+}
+
+impl From<ApiErrorResponse> for CompletionError {
+    fn from(err: ApiErrorResponse) -> Self {
+        CompletionError::ProviderError(err.message)
+    }
+}
+
+impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionResponse> {
+    type Error = CompletionError;
+
+    fn try_from(value: CompletionResponse) -> std::prelude::v1::Result<Self, Self::Error> {
+        if let Some(choice) = value
+            .choices
+            .first()
+            .filter(|choice| choice.finish_reason == "content_filter")
+        {
+            return Err(CompletionError::ContentFiltered {
+                partial: choice
+                    .message
+                    .content
+                    .clone()
+                    .filter(|content| !content.is_empty()),
+                categories: choice.filtered_categories(),
+            });
+        }
+
+        match value.choices.as_slice() {
+            [Choice {
+                message:
+                    Message {
+                        tool_calls: Some(calls),
+                        ..
+                    },
+                ..
+            }, ..] => {
+                let call = calls.first().ok_or(CompletionError::ResponseError(
+                    "Tool selection is empty".into(),
+                ))?;
+
+                Ok(completion::CompletionResponse {
+                    choice: completion::ModelChoice::ToolCall(
+                        call.function.name.clone(),
+                        serde_json::from_str(&call.function.arguments)?,
+                    ),
+                    raw_response: value,
+                })
+            }
+            [Choice {
+                message:
+                    Message {
+                        content: Some(content),
+                        ..
+                    },
+                ..
+            }, ..] => Ok(completion::CompletionResponse {
+                choice: completion::ModelChoice::Message(content.to_string()),
+                raw_response: value,
+            }),
+            _ => Err(CompletionError::ResponseError(
+                "Response did not contain a message or tool call".into(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Choice {
+    pub index: usize,
+    pub message: Message,
+    pub logprobs: Option<serde_json::Value>,
+    pub finish_reason: String,
+    /// Results of the content filter by category (e.g.: `{"violence": {"filtered": true}}`),
+    /// reported by Azure OpenAI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_filter_results: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl Choice {
+    /// Categories of the content filter which filtered the choice
+    fn filtered_categories(&self) -> Vec<String> {
+        self.content_filter_results
+            .iter()
+            .flatten()
+            .filter(|(_, result)| result["filtered"] == true)
+            .map(|(category, _)| category.clone())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Message {
+    pub role: String,
+    pub content: Option<String>,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Chain-of-thought of the message, reported by some OpenAI-compatible APIs serving
+    /// reasoning models (e.g.: DeepSeek)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub r#type: String,
+    pub function: Function,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ToolDefinition {
+    pub r#type: String,
+    pub function: completion::ToolDefinition,
+}
+
+impl From<completion::ToolDefinition> for ToolDefinition {
+    fn from(tool: completion::ToolDefinition) -> Self {
+        Self {
+            r#type: "function".into(),
+            function: tool,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Function {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Clone)]
+pub struct CompletionModel {
+    client: Client,
+    /// Name of the model (e.g.: gpt-3.5-turbo-1106)
+    pub model: String,
+    /// Whether to request the token usage when streaming (see [CompletionModel::stream_usage])
+    stream_usage: bool,
+    /// Debug mode of the streamed deltas (see [CompletionModel::trace_deltas])
+    trace_deltas: DeltaTracing,
+    /// Idle timeout of the streamed responses (see [CompletionModel::stream_idle_timeout])
+    stream_idle_timeout: Option<Duration>,
+    /// Policy for retrying failed requests (see [CompletionModel::retry_policy])
+    retry_policy: RetryPolicy,
+    /// Restrictions on the names of the tools (see [CompletionModel::tool_name_rules])
+    tool_name_rules: ToolNameRules,
+    /// Token limit of the requests (see [CompletionModel::request_limit])
+    request_limit: Option<u64>,
+    /// Whether the tools are sent in strict mode (see [CompletionModel::strict_tools])
+    strict_tools: bool,
+}
+
+impl CompletionModel {
+    pub fn new(client: Client, model: &str) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+            stream_usage: true,
+            trace_deltas: DeltaTracing::Off,
+            stream_idle_timeout: None,
+            retry_policy: RetryPolicy::default(),
+            tool_name_rules: ToolNameRules::ALPHANUMERIC,
+            request_limit: known_context_window(model),
+            strict_tools: false,
+        }
+    }
+
+    /// Capabilities of the model (see [model_capabilities]). Fine-tuned models (i.e.: with an
+    /// id of the form `ft:<base model>:...`) are sent to the chat completions endpoint like
+    /// any other model, and inherit the capabilities of their base model.
+    pub fn capabilities(&self) -> ModelCapabilities {
+        model_capabilities(&self.model)
+    }
+
+    /// Set whether streaming requests should ask for the token usage of the request
+    /// (i.e.: `stream_options: {"include_usage": true}`), which is then reported on the
+    /// [StreamEvent::Done] event. Enabled by default.
+    ///
+    /// Disable it for OpenAI-compatible APIs that do not support `stream_options`, or
+    /// which bill for it differently.
+    pub fn stream_usage(mut self, include_usage: bool) -> Self {
+        self.stream_usage = include_usage;
+        self
+    }
+
+    /// Set the debug mode logging each streamed delta as a `tracing::trace!` event
+    /// (see [DeltaTracing]). Off by default.
+    pub fn trace_deltas(mut self, mode: DeltaTracing) -> Self {
+        self.trace_deltas = mode;
+        self
+    }
+
+    /// Set the idle timeout of the streamed responses: a stream receiving no event within
+    /// `timeout` of the previous one fails with [CompletionError::StreamIdleTimeout] instead
+    /// of hanging (see [streaming::idle_timeout]). Unset by default.
+    pub fn stream_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the policy for retrying requests failing with a transient error (no retries by
+    /// default). Every retry of a request is sent with the same `Idempotency-Key` header
+    /// (see [CompletionRequest::idempotency_key]) so that OpenAI deduplicates them.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set the restrictions on the names of the tools ([ToolNameRules::ALPHANUMERIC] by
+    /// default). Tools with invalid names (e.g.: `math.add`) are sent under sanitized names
+    /// (e.g.: `math_add`), and their calls are resolved to the original names (see
+    /// [ToolNameMap]).
+    ///
+    /// Use [ToolNameRules::ANY] for OpenAI-compatible APIs accepting any name.
+    pub fn tool_name_rules(mut self, rules: ToolNameRules) -> Self {
+        self.tool_name_rules = rules;
+        self
+    }
+
+    /// Set the token limit of the requests: requests whose estimated number of tokens exceeds
+    /// it fail with [CompletionError::RequestTooLarge] without being sent (see
+    /// [CompletionRequest::check_size]). Defaults to the context window of known model families and
+    /// their dated snapshots (see [model_capabilities]), and to no limit for other models.
+    /// `None` disables the check.
+    pub fn request_limit(mut self, limit: Option<u64>) -> Self {
+        self.request_limit = limit;
+        self
+    }
+
+    /// Set whether the tools are sent in strict mode (`strict: true`), guaranteeing that the
+    /// arguments of their calls adhere to their schema. Their parameters are converted to
+    /// strict-compatible schemas (see [strict::to_strict_schema]), and requests with a tool
+    /// whose schema cannot be represented in strict mode fail with
+    /// [CompletionError::RequestError]. Only supported by the models with structured outputs
+    /// (see [ModelCapabilities::structured_outputs]). Disabled by default.
+    pub fn strict_tools(mut self, strict: bool) -> Self {
+        self.strict_tools = strict;
+        self
+    }
+
+    /// Convert the parameters of the tools of `completion_request` to strict-compatible
+    /// schemas, if the tools are sent in strict mode
+    fn strict_parameters(
+        &self,
+        completion_request: &mut CompletionRequest,
+    ) -> Result<(), CompletionError> {
+        if self.strict_tools {
+            for tool in completion_request.tools.iter_mut() {
+                tool.parameters = strict::to_strict_schema(std::mem::take(&mut tool.parameters))
+                    .map_err(|err| CompletionError::RequestError(Box::new(err)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the size of `completion_request` against the token limit of the requests
+    fn check_size(&self, completion_request: &CompletionRequest) -> Result<(), CompletionError> {
+        match self.request_limit {
+            Some(limit) => completion_request.check_size(limit),
+            None => Ok(()),
+        }
+    }
+
+    /// Rename the tools of `completion_request` to valid names, returning the mapping to
+    /// resolve the tool calls of the response.
+    fn rename_tools(&self, completion_request: &mut CompletionRequest) -> ToolNameMap {
+        let names = ToolNameMap::of(&completion_request.tools, self.tool_name_rules);
+        completion_request.tools = names.rename(std::mem::take(&mut completion_request.tools));
+        names
+    }
+
+    /// Post `request` to the chat completions endpoint with the given idempotency key,
+    /// retrying it according to the retry policy of the model. Returns the response along
+    /// with the number of retries.
+    async fn send(
+        &self,
+        request: &serde_json::Value,
+        idempotency_key: &str,
+    ) -> Result<(reqwest::Response, usize), CompletionError> {
+        let request = self.client.interceptors.intercept_request(request)?;
+        let mut retry = 0;
+        loop {
+            let result = self
+                .client
+                .post("/chat/completions")
+                .header("Idempotency-Key", idempotency_key)
+                .json(&request)
+                .send()
+                .await;
+
+            let transient = match &result {
+                Ok(response) => {
+                    response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        || response.status().is_server_error()
+                }
+                Err(err) => err.is_connect() || err.is_timeout(),
+            };
+            if !transient || retry >= self.retry_policy.max_retries {
+                return Ok((result?, retry));
+            }
+
+            tracing::warn!(target: "rig",
+                "OpenAI request failed, retrying ({}/{})",
+                retry + 1,
+                self.retry_policy.max_retries
+            );
+            futures_timer::Delay::new(self.retry_policy.delay(retry)).await;
+            retry += 1;
+        }
+    }
+
+    fn create_completion_request(
+        &self,
+        completion_request: CompletionRequest,
+    ) -> serde_json::Value {
+        // Lead the chat history with the system instructions as system messages
+        // (see [CompletionRequest::system_instructions])
+        let full_history = completion_request.history_with_system_messages();
+
+        // Add context documents to chat history
+        let prompt_with_context = completion_request.prompt_with_context();
+
+        // Add the prompt (with its images as a mixed content message, if any) to chat history
+        let prompt_message = if completion_request.images.is_empty() {
+            json!({ "role": "user", "content": prompt_with_context })
+        } else {
+            let content = std::iter::once(json!({ "type": "text", "text": prompt_with_context }))
+                .chain(completion_request.images.iter().map(|image| {
+                    json!({ "type": "image_url", "image_url": { "url": image.data_url() } })
+                }))
+                .collect::<Vec<_>>();
+            json!({ "role": "user", "content": content })
+        };
+        let full_history = full_history
+            .iter()
+            .map(|message| json!(message))
+            .chain(std::iter::once(prompt_message))
+            .collect::<Vec<_>>();
+
+        let mut request = if completion_request.tools.is_empty() {
+            json!({
+                "model": self.model,
+                "messages": full_history,
+                "temperature": completion_request.temperature,
+            })
+        } else {
+            json!({
+                "model": self.model,
+                "messages": full_history,
+                "temperature": completion_request.temperature,
+                "tools": completion_request.tools.into_iter().map(|tool| {
+                    let mut tool = json!(ToolDefinition::from(tool));
+                    if self.strict_tools {
+                        tool["function"]["strict"] = json!(true);
+                    }
+                    tool
+                }).collect::<Vec<_>>(),
+                "tool_choice": "auto",
+            })
+        };
+
+        if let Some(max_tokens) = completion_request.max_tokens {
+            request = json_utils::merge(
+                request,
+                json!({ max_tokens_field(&self.model): max_tokens }),
+            );
+        }
+
+        if let Some(effort) = reasoning_effort(&self.model, completion_request.reasoning_effort) {
+            request = json_utils::merge(request, json!({ "reasoning_effort": effort }));
+        }
+
+        if let Some(params) = completion_request.additional_params {
+            json_utils::merge(request, params)
+        } else {
+            request
+        }
+    }
+
+    /// Send the completion request and stream the response as [StreamEvent]s.
+    pub async fn stream(
+        &self,
+        mut completion_request: CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        self.check_size(&completion_request)?;
+        let idempotency_key = completion_request
+            .idempotency_key
+            .clone()
+            .unwrap_or_else(completion::generate_idempotency_key);
+        let names = self.rename_tools(&mut completion_request);
+        self.strict_parameters(&mut completion_request)?;
+        let mut request = json_utils::merge(
+            self.create_completion_request(completion_request),
+            json!({ "stream": true }),
+        );
+        if self.stream_usage {
+            request = json_utils::merge(
+                request,
+                json!({ "stream_options": { "include_usage": true } }),
+            );
+        }
+
+        let permit = self.client.permit().await;
+        let (response, _) = self.send(&request, &idempotency_key).await?;
+
+        if response.status().is_success() {
+            let events = stream_events(response.bytes_stream()).map(move |event| {
+                // The permit is released once the stream is dropped
+                let _permit = &permit;
+                match event {
+                    Ok(StreamEvent::ToolCall { name, arguments }) => Ok(StreamEvent::ToolCall {
+                        name: names.original_name(&name).to_string(),
+                        arguments,
+                    }),
+                    event => event,
+                }
+            });
+            let stream = streaming::trace_deltas(Box::pin(events), self.trace_deltas);
+            Ok(match self.stream_idle_timeout {
+                Some(timeout) => streaming::idle_timeout(stream, timeout),
+                None => stream,
+            })
+        } else {
+            Err(CompletionError::ProviderError(response.text().await?))
+        }
+    }
+}
+
+// ================================================================
+// OpenAI Streaming API
+// ================================================================
+#[derive(Debug, Deserialize)]
+struct StreamingChunk {
+    choices: Vec<StreamingChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamingChoice {
+    delta: StreamingDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamingDelta {
+    content: Option<String>,
+    tool_calls: Option<Vec<StreamingToolCall>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamingToolCall {
+    index: usize,
+    function: StreamingFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamingFunction {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// Convert the raw server-sent events of an OpenAI streaming response to [StreamEvent]s (also
+/// used by OpenAI-compatible providers, e.g.: xAI and Perplexity).
+/// Tool calls are accumulated (their arguments are streamed in chunks) and emitted once
+/// complete. The [StreamEvent::Done] event carries the usage reported in the last chunk
+/// of the stream (only sent when `stream_options.include_usage` is set).
+pub(crate) fn stream_events<S, B, E>(stream: S) -> StreamingResult
+where
+    S: Stream<Item = Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]> + Send,
+    E: Into<CompletionError> + Send,
+{
+    struct State<S> {
+        events: std::pin::Pin<Box<S>>,
+        /// Partial tool calls by index: (name, arguments)
+        tool_calls: std::collections::BTreeMap<usize, (String, String)>,
+        usage: Option<completion::Usage>,
+        pending: std::collections::VecDeque<Result<StreamEvent, CompletionError>>,
+        finished: bool,
+    }
+
+    impl<S> State<S> {
+        fn flush_tool_calls(&mut self) {
+            for (_, (name, arguments)) in std::mem::take(&mut self.tool_calls) {
+                let arguments = if arguments.trim().is_empty() {
+                    Ok(json!({}))
+                } else {
+                    serde_json::from_str(&arguments)
+                };
+
+                self.pending.push_back(
+                    arguments
+                        .map(|arguments| StreamEvent::ToolCall { name, arguments })
+                        .map_err(CompletionError::JsonError),
+                );
+            }
+        }
+
+        fn finish(&mut self) {
+            self.flush_tool_calls();
+            self.pending
+                .push_back(Ok(StreamEvent::Done { usage: self.usage }));
+            self.finished = true;
+        }
+    }
+
+    Box::pin(futures::stream::unfold(
+        State {
+            events: Box::pin(streaming::decode_sse(stream)),
+            tool_calls: Default::default(),
+            usage: None,
+            pending: Default::default(),
+            finished: false,
+        },
+        |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((event, state));
+                }
+
+                if state.finished {
+                    return None;
+                }
+
+                let data = match state.events.next().await {
+                    Some(Ok(data)) if data == "[DONE]" => {
+                        state.finish();
+                        continue;
+                    }
+                    Some(Ok(data)) => data,
+                    Some(Err(err)) => {
+                        state.finished = true;
+                        return Some((Err(err), state));
+                    }
+                    None => {
+                        state.finish();
+                        continue;
+                    }
+                };
+
+                let chunk = match serde_json::from_str::<ApiResponse<StreamingChunk>>(&data) {
+                    Ok(ApiResponse::Ok(chunk)) => chunk,
+                    Ok(ApiResponse::Err(err)) => {
+                        state.finished = true;
+                        return Some((Err(err.into()), state));
+                    }
+                    Err(err) => return Some((Err(err.into()), state)),
+                };
+
+                if let Some(usage) = chunk.usage {
+                    state.usage = Some(usage.into());
+                }
+
+                for choice in chunk.choices {
+                    if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                        state.pending.push_back(Ok(StreamEvent::Delta(content)));
+                    }
+
+                    for call in choice.delta.tool_calls.unwrap_or_default() {
+                        let (name, arguments) = state.tool_calls.entry(call.index).or_default();
+                        if let Some(chunk) = call.function.name {
+                            name.push_str(&chunk);
+                        }
+                        if let Some(chunk) = call.function.arguments {
+                            arguments.push_str(&chunk);
+                        }
+                    }
+
+                    match choice.finish_reason.as_deref() {
+                        Some("tool_calls") => state.flush_tool_calls(),
+                        Some("content_filter") => {
+                            // The content streamed so far was already emitted as deltas
+                            state
+                                .pending
+                                .push_back(Err(CompletionError::ContentFiltered {
+                                    partial: None,
+                                    categories: vec![],
+                                }));
+                            state.finished = true;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        },
+    ))
+}
+
+impl streaming::StreamingCompletionModel for CompletionModel {
+    async fn stream(
+        &self,
+        request: completion::CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        CompletionModel::stream(self, request).await
+    }
+}
+
+impl completion::CompletionModel for CompletionModel {
+    type Response = CompletionResponse;
+
+    fn usage(response: &CompletionResponse) -> Option<completion::Usage> {
+        response.usage.clone().map(Into::into)
+    }
+
+    fn retries(response: &CompletionResponse) -> usize {
+        response.retries
+    }
+
+    fn reasoning(response: &CompletionResponse) -> Option<String> {
+        response
+            .choices
+            .first()
+            .and_then(|choice| choice.message.reasoning_content.clone())
+    }
+
+    async fn completion(
+        &self,
+        mut completion_request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        self.check_size(&completion_request)?;
+        let idempotency_key = completion_request
+            .idempotency_key
+            .clone()
+            .unwrap_or_else(completion::generate_idempotency_key);
+        let names = self.rename_tools(&mut completion_request);
+        self.strict_parameters(&mut completion_request)?;
+        let request = self.create_completion_request(completion_request);
+
+        let _permit = self.client.permit().await;
+        let (response, retries) = self.send(&request, &idempotency_key).await?;
+
+        if response.status().is_success() {
+            match self
+                .client
+                .interceptors
+                .parse::<ApiResponse<CompletionResponse>, CompletionError>(response)
+                .await?
+            {
+                ApiResponse::Ok(mut response) => {
+                    tracing::info!(target: "rig",
+                        "OpenAI completion token usage: {:?}",
+                        response.usage.clone().map(|usage| format!("{usage}")).unwrap_or("N/A".to_string())
+                    );
+                    response.retries = retries;
+                    let mut response: completion::CompletionResponse<_> = response.try_into()?;
+                    response.choice = names.resolve(response.choice);
+                    Ok(response)
+                }
+                ApiResponse::Err(err) => Err(CompletionError::ProviderError(err.message)),
+            }
+        } else {
+            Err(CompletionError::ProviderError(response.text().await?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use futures::{stream, StreamExt};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::completion::{CompletionModel as _, ModelChoice};
+
+    /// Serve the given responses (status and body), one per connection, on a local port and
+    /// record the `Idempotency-Key` header of the requests. Returns the base URL of the server.
+    async fn mock_server(responses: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        recording_mock_server(responses, |request| header(request, "idempotency-key")).await
+    }
+
+    /// Value of the header `name` of a raw HTTP request
+    fn header(request: &str, name: &str) -> Option<String> {
+        let end = request.find("\r\n\r\n").unwrap_or(request.len());
+        request[..end].lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name)
+                .then(|| value.trim().to_string())
+        })
+    }
+
+    /// Same as [mock_server], but records `record(request)` for each raw HTTP request
+    async fn recording_mock_server(
+        responses: Vec<(u16, &'static str)>,
+        record: fn(&str) -> Option<String>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let records = Arc::new(Mutex::new(vec![]));
+
+        let recorded = records.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+
+                // Read the headers and the body of the request
+                let mut request = vec![];
+                let mut buffer = [0; 4096];
+                let text = loop {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let content_length = header(&text, "content-length")
+                            .map_or(0, |length| length.parse::<usize>().unwrap());
+
+                        if request.len() >= end + 4 + content_length {
+                            break Some(text);
+                        }
+                    }
+                };
+                recorded
+                    .lock()
+                    .unwrap()
+                    .extend(text.as_deref().and_then(record));
+
+                let response = format!(
+                    "HTTP/1.1 {status} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, records)
+    }
+
+    const SERVER_ERROR: &str = r#"{"error": {"message": "The server had an error"}}"#;
+
+    const COMPLETION: &str = r#"{
+        "id": "chatcmpl-123",
+        "object": "chat.completion",
+        "created": 1728000000,
+        "model": "gpt-4o",
+        "system_fingerprint": null,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hello!" },
+            "logprobs": null,
+            "finish_reason": "stop"
+        }],
+        "usage": null
+    }"#;
+
+    #[test]
+    fn test_deterministic_request() {
+        let model = Client::new("test").completion_model(GPT_4O);
+        // Request whose metadata and tool parameters are inserted in the order of `keys`
+        let request = |keys: &[&str]| {
+            let document = completion::Document {
+                id: "flurbo".to_string(),
+                text: "Flurbos are a made up currency.".to_string(),
+                additional_props: keys
+                    .iter()
+                    .map(|key| (key.to_string(), key.to_uppercase()))
+                    .collect(),
+            };
+            let properties = keys
+                .iter()
+                .map(|key| (key.to_string(), json!({ "type": "string" })))
+                .collect::<serde_json::Map<_, _>>();
+            let tool = completion::ToolDefinition {
+                name: "lookup".to_string(),
+                description: "Look up a currency".to_string(),
+                parameters: json!({ "type": "object", "properties": properties }),
+            };
+
+            serde_json::to_vec(
+                &model.create_completion_request(
+                    model
+                        .completion_request("What is a flurbo?")
+                        .document(document)
+                        .tool(tool)
+                        .build(),
+                ),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            request(&["author", "source", "lang", "year"]),
+            request(&["year", "lang", "source", "author"])
+        );
+    }
+
+    #[test]
+    fn test_max_tokens_field() {
+        let request = |model: &str| {
+            let model = Client::new("test").completion_model(model);
+            model
+                .create_completion_request(model.completion_request("Hello").max_tokens(64).build())
+        };
+
+        let reasoning = request(O1_MINI);
+        assert_eq!(reasoning["max_completion_tokens"], 64);
+        assert!(reasoning.get("max_tokens").is_none());
+
+        let legacy = request(GPT_35_TURBO);
+        assert_eq!(legacy["max_tokens"], 64);
+        assert!(legacy.get("max_completion_tokens").is_none());
+
+        assert_eq!(
+            max_tokens_field("ft:o1-mini:flurbo-corp::abc123"),
+            "max_completion_tokens"
+        );
+        assert_eq!(max_tokens_field(GPT_4O), "max_tokens");
+    }
+
+    #[test]
+    fn test_reasoning_effort() {
+        let request = |model: &str| {
+            let model = Client::new("test").completion_model(model);
+            model.create_completion_request(
+                model
+                    .completion_request("Hello")
+                    .with_reasoning_effort(completion::ReasoningEffort::High)
+                    .build(),
+            )
+        };
+
+        assert_eq!(request("o3-mini")["reasoning_effort"], "high");
+        assert_eq!(
+            request("ft:o1-2024-12-17:flurbo-corp::abc123")["reasoning_effort"],
+            "high"
+        );
+
+        // Chat models do not support it
+        assert!(request(GPT_4O).get("reasoning_effort").is_none());
+
+        let model = Client::new("test").completion_model(O1_MINI);
+        let body = model.create_completion_request(model.completion_request("Hello").build());
+        assert!(body.get("reasoning_effort").is_none());
+    }
+
+    #[test]
+    fn test_system_instructions() {
+        let message = |role: &str, content: &str| completion::Message {
+            role: role.into(),
+            content: content.into(),
+        };
+        let model = Client::new("test").completion_model(GPT_4O);
+        let request = model
+            .completion_request("What is a flurbo?")
+            .preamble("You are a dictionary.".to_string())
+            .messages(vec![
+                message("user", "Hello"),
+                message("system", "Flurbos are a currency."),
+            ])
+            .system("Answer in one sentence.".to_string())
+            .build();
+
+        let body = model.create_completion_request(request);
+        assert_eq!(
+            body["messages"],
+            json!([
+                { "role": "system", "content": "You are a dictionary." },
+                { "role": "system", "content": "Flurbos are a currency." },
+                { "role": "system", "content": "Answer in one sentence." },
+                { "role": "user", "content": "Hello" },
+                { "role": "user", "content": "What is a flurbo?" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_image_request() {
+        let model = Client::new("test").completion_model(GPT_4O);
+        let image = completion::Image::from_path("tests/data/pixel.png").unwrap();
+
+        let request = model.create_completion_request(
+            model
+                .completion_request("Describe this")
+                .image(image.clone())
+                .build(),
+        );
+
+        assert_eq!(
+            request["messages"],
+            json!([{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "Describe this" },
+                    { "type": "image_url", "image_url": { "url": image.data_url() } },
+                ],
+            }])
+        );
+        assert!(image
+            .data_url()
+            .starts_with("data:image/png;base64,iVBORw0KGgo"));
+    }
+
+    #[test]
+    fn test_model_list() {
+        let response = r#"{
+            "object": "list",
+            "data": [
+                { "id": "gpt-4o", "object": "model", "created": 1715367049, "owned_by": "system" },
+                { "id": "ft:gpt-4o-mini:flurbo-corp::abc123", "object": "model", "created": 1728000000, "owned_by": "flurbo-corp" }
+            ]
+        }"#;
+
+        let models: Vec<ModelInfo> = match serde_json::from_str(response).unwrap() {
+            ApiResponse::<ModelList>::Ok(models) => models.into(),
+            ApiResponse::Err(err) => panic!("Unexpected error: {}", err.message),
+        };
+
+        assert_eq!(models.len(), 2);
+        assert_eq!(
+            models[0],
+            ModelInfo {
+                id: "gpt-4o".to_string(),
+                owned_by: Some("system".to_string()),
+                created: Some(1715367049),
+                metadata: json!({
+                    "id": "gpt-4o",
+                    "object": "model",
+                    "created": 1715367049,
+                    "owned_by": "system"
+                }),
+            }
+        );
+        assert_eq!(models[1].owned_by.as_deref(), Some("flurbo-corp"));
+    }
+
+    #[test]
+    fn test_ollama_model_list() {
+        let response = r#"{
+            "models": [
+                {
+                    "name": "llama3.2:latest",
+                    "model": "llama3.2:latest",
+                    "modified_at": "2024-10-01T12:00:00.000000+02:00",
+                    "size": 2019393189,
+                    "digest": "a80c4f17acd55265feec403c7aef86be0c25983ab279d83f3bcd3abbcb5b8b72",
+                    "details": { "family": "llama", "parameter_size": "3.2B" }
+                },
+                { "model": "nameless" }
+            ]
+        }"#;
+
+        let models: Vec<ModelInfo> = serde_json::from_str::<OllamaModelList>(response)
+            .unwrap()
+            .into();
+
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "llama3.2:latest");
+        assert_eq!(models[0].owned_by, None);
+        assert_eq!(models[0].created, Some(1727776800));
+        assert_eq!(models[0].metadata["details"]["parameter_size"], "3.2B");
+    }
+
+    #[test]
+    fn test_response_serde_round_trip() {
+        let response: completion::CompletionResponse<CompletionResponse> =
+            serde_json::from_str::<CompletionResponse>(COMPLETION)
+                .unwrap()
+                .try_into()
+                .unwrap();
+
+        let json = serde_json::to_string(&response).unwrap();
+        let deserialized: completion::CompletionResponse<CompletionResponse> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, response);
+
+        // Non-deterministic fields of the raw response can be excluded
+        let mut other = response.clone();
+        other.raw_response.id = "chatcmpl-456".to_string();
+        other.raw_response.created += 60;
+        assert_ne!(other, response);
+        assert_eq!(
+            other.without_raw_response(),
+            response.without_raw_response()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_retries() {
+        let retry_policy = RetryPolicy::new(2).backoff(Duration::from_millis(1));
+
+        let (url, keys) = mock_server(vec![(500, SERVER_ERROR), (200, COMPLETION)]).await;
+        let model = Client::from_url("test", &url)
+            .completion_model(GPT_4O)
+            .retry_policy(retry_policy);
+
+        let response = model
+            .completion_request("Hi")
+            .with_idempotency_key("flurbo-1".to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.choice, ModelChoice::Message("Hello!".to_string()));
+        assert_eq!(*keys.lock().unwrap(), vec!["flurbo-1", "flurbo-1"]);
+
+        // A key is generated per request and reused across its retries
+        let (url, keys) = mock_server(vec![(429, SERVER_ERROR), (200, COMPLETION)]).await;
+        let model = Client::from_url("test", &url)
+            .completion_model(GPT_4O)
+            .retry_policy(retry_policy);
+
+        model.completion_request("Hi").send().await.unwrap();
+        let keys = keys.lock().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys[0].starts_with("rig-"));
+        assert_eq!(keys[0], keys[1]);
+    }
+
+    #[tokio::test]
+    async fn test_interceptors() {
+        // Completion whose `choices` were renamed to `outputs` by the provider
+        let renamed: &'static str = Box::leak(
+            COMPLETION
+                .replace("\"choices\"", "\"outputs\"")
+                .into_boxed_str(),
+        );
+
+        let (url, _) = mock_server(vec![(200, renamed)]).await;
+        let model = Client::from_url("test", &url).completion_model(GPT_4O);
+        assert!(matches!(
+            model.completion_request("Hi").send().await,
+            Err(CompletionError::HttpError(_))
+        ));
+
+        let (url, requests) = recording_mock_server(vec![(200, renamed)], |request| {
+            Some(request[request.find("\r\n\r\n").unwrap() + 4..].to_string())
+        })
+        .await;
+        let interceptors = Interceptors::new()
+            .request(|body| body["store"] = json!(false))
+            .response(|body| {
+                if let Some(outputs) = body.as_object_mut().and_then(|body| body.remove("outputs"))
+                {
+                    body["choices"] = outputs;
+                }
+            });
+        let model = Client::from_url("test", &url)
+            .with_interceptors(interceptors)
+            .completion_model(GPT_4O);
+
+        let response = model.completion_request("Hi").send().await.unwrap();
+        assert_eq!(response.choice, ModelChoice::Message("Hello!".to_string()));
+
+        let request: serde_json::Value =
+            serde_json::from_str(&requests.lock().unwrap()[0]).unwrap();
+        assert_eq!(request["store"], json!(false));
+    }
+
+    #[test]
+    fn test_strict_tools() {
+        let tool = |parameters: serde_json::Value| completion::ToolDefinition {
+            name: "lookup".to_string(),
+            description: "Look up a word".to_string(),
+            parameters,
+        };
+        let model = Client::new("test")
+            .completion_model(GPT_4O)
+            .strict_tools(true);
+
+        let mut request = model
+            .completion_request("What is a flurbo?")
+            .tool(tool(json!({
+                "type": "object",
+                "properties": {
+                    "word": { "type": "string" },
+                    "limit": { "type": ["integer", "null"], "format": "uint8" },
+                },
+                "required": ["word"],
+            })))
+            .build();
+        model.strict_parameters(&mut request).unwrap();
+        let body = model.create_completion_request(request);
+
+        assert_eq!(
+            body["tools"][0]["function"],
+            json!({
+                "name": "lookup",
+                "description": "Look up a word",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "word": { "type": "string" },
+                        "limit": { "type": ["integer", "null"] },
+                    },
+                    "required": ["limit", "word"],
+                    "additionalProperties": false,
+                },
+                "strict": true,
+            })
+        );
+
+        // Tools whose schema cannot be represented in strict mode are rejected
+        let mut request = model
+            .completion_request("What is a flurbo?")
+            .tool(tool(json!({
+                "type": "object",
+                "properties": {
+                    "words": { "type": "object", "additionalProperties": { "type": "string" } },
+                },
+            })))
+            .build();
+        assert!(matches!(
+            model.strict_parameters(&mut request),
+            Err(CompletionError::RequestError(_))
+        ));
+
+        // Tools are not sent in strict mode by default
+        let model = Client::new("test").completion_model(GPT_4O);
+        let body = model.create_completion_request(
+            model
+                .completion_request("What is a flurbo?")
+                .tool(tool(json!({ "type": "object", "properties": {} })))
+                .build(),
+        );
+        assert!(body["tools"][0]["function"].get("strict").is_none());
+    }
+
+    #[test]
+    fn test_model_capabilities() {
+        let fine_tuned = "ft:gpt-4o-2024-08-06:flurbo-corp:support:9gH7aB2c";
+        assert_eq!(base_model(fine_tuned), "gpt-4o-2024-08-06");
+        assert_eq!(base_model(GPT_4O), GPT_4O);
+        assert_eq!(model_capabilities(fine_tuned), model_capabilities(GPT_4O));
+        assert!(model_capabilities(fine_tuned).vision);
+
+        assert!(model_capabilities(O1_MINI).reasoning);
+        assert!(!model_capabilities(O1_MINI).tools);
+        assert_eq!(model_capabilities(GPT_4_32K).context_window, 32_768);
+        assert_eq!(model_capabilities(GPT_4).context_window, 8_192);
+
+        assert!(model_capabilities(GPT_4O).structured_outputs);
+        assert!(model_capabilities("o3-mini").structured_outputs);
+        assert!(!model_capabilities(GPT_4O_2024_05_13).structured_outputs);
+        assert!(!model_capabilities(GPT_4_TURBO).structured_outputs);
+
+        // Models only belong to the families whose name is followed by a `-` in their id
+        assert_eq!(model_capabilities("gpt-4.1-mini").context_window, 1_047_576);
+        assert_eq!(
+            model_capabilities("gpt-4.5-preview").context_window,
+            128_000
+        );
+        assert_eq!(model_capabilities("gpt-4-0613").context_window, 8_192);
+        assert_eq!(
+            model_capabilities("gpt-4o-mini-2024-07-18"),
+            model_capabilities(GPT_4O)
+        );
+        assert_eq!(model_capabilities("gpt-4x"), ModelCapabilities::UNKNOWN);
+        assert_eq!(model_capabilities("o10"), ModelCapabilities::UNKNOWN);
+
+        // Requests are only limited for known families and their dated snapshots
+        assert_eq!(known_context_window(GPT_4_32K_0613), Some(32_768));
+        assert_eq!(known_context_window("gpt-4.1-2025-04-14"), Some(1_047_576));
+        assert_eq!(known_context_window("gpt-4-flurbo"), None);
+        assert_eq!(known_context_window("flurbo-1"), None);
+
+        // Unknown (fine-tuned) models fall back to the default capabilities
+        assert_eq!(
+            model_capabilities("ft:flurbo-1:flurbo-corp::abc123"),
+            ModelCapabilities::UNKNOWN
+        );
+        assert_eq!(model_capabilities("ft:"), ModelCapabilities::UNKNOWN);
+    }
+
+    const FLOAT_EMBEDDINGS: &str = r#"{
+        "object": "list",
+        "data": [{ "object": "embedding", "embedding": [0.5, -1.25, 3.0], "index": 0 }],
+        "model": "text-embedding-3-small",
+        "usage": { "prompt_tokens": 4, "total_tokens": 4 }
+    }"#;
+
+    const BASE64_EMBEDDINGS: &str = r#"{
+        "object": "list",
+        "data": [{ "object": "embedding", "embedding": "AAAAPwAAoL8AAEBA", "index": 0 }],
+        "model": "text-embedding-3-small",
+        "usage": { "prompt_tokens": 4, "total_tokens": 4 }
+    }"#;
+
+    #[tokio::test]
+    async fn test_concurrency_limiter() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use crate::{concurrency::ConcurrencyLimiter, embeddings::EmbeddingModel as _};
+
+        // Server handling the connections concurrently, recording the maximum number of
+        // requests in flight
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        {
+            let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+            tokio::spawn(async move {
+                loop {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+                    let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+                    tokio::spawn(async move {
+                        let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_in_flight.fetch_max(current, Ordering::SeqCst);
+
+                        let mut buffer = [0; 4096];
+                        let read = socket.read(&mut buffer).await.unwrap();
+                        let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+
+                        let body = if request.starts_with("POST /embeddings") {
+                            FLOAT_EMBEDDINGS
+                        } else {
+                            COMPLETION
+                        };
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                            body.len()
+                        );
+                        socket.write_all(response.as_bytes()).await.unwrap();
+                    });
+                }
+            });
+        }
+
+        let limiter = ConcurrencyLimiter::new(2);
+        let client = Client::from_url("test", &url).with_concurrency_limiter(limiter.clone());
+        let embedding_model = client.embedding_model(TEXT_EMBEDDING_3_SMALL);
+        let completion_model = client.completion_model(GPT_4O);
+
+        let embeddings =
+            futures::future::join_all((0..4).map(|_| embedding_model.embed_text("Flurbo")));
+        let completions = futures::future::join_all(
+            (0..4).map(|_| completion_model.completion_request("Hi").send()),
+        );
+        let (embeddings, completions) = futures::join!(embeddings, completions);
+        assert!(embeddings.iter().all(Result::is_ok));
+        assert!(completions.iter().all(Result::is_ok));
+
+        let max_in_flight = max_in_flight.load(Ordering::SeqCst);
+        assert!(max_in_flight <= 2, "{max_in_flight} requests in flight");
+        assert_eq!(limiter.available(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_base64_embeddings() {
+        use crate::embeddings::EmbeddingModel as _;
+
+        let (url, requests) = recording_mock_server(
+            vec![(200, FLOAT_EMBEDDINGS), (200, BASE64_EMBEDDINGS)],
+            |request| Some(request[request.find("\r\n\r\n").unwrap() + 4..].to_string()),
+        )
+        .await;
+        let client = Client::from_url("test", &url);
+
+        let float = client
+            .embedding_model(TEXT_EMBEDDING_3_SMALL)
+            .embed_text("Flurbo")
+            .await
+            .unwrap();
+        let base64 = client
+            .embedding_model(TEXT_EMBEDDING_3_SMALL)
+            .encoding_format(EncodingFormat::Base64)
+            .embed_text("Flurbo")
+            .await
+            .unwrap();
+
+        assert_eq!(base64.vec, vec![0.5, -1.25, 3.0]);
+        assert_eq!(base64.vec, float.vec);
+
+        let requests = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|body| serde_json::from_str::<serde_json::Value>(body).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(requests[0].get("encoding_format"), None);
+        assert_eq!(requests[1]["encoding_format"], "base64");
+    }
+
+    #[tokio::test]
+    async fn test_fine_tuned_model_routing() {
+        let fine_tuned = "ft:gpt-4o-2024-08-06:flurbo-corp:support:9gH7aB2c";
+
+        let (url, requests) =
+            recording_mock_server(vec![(200, COMPLETION)], |request| Some(request.to_string()))
+                .await;
+        let model = Client::from_url("test", &url).completion_model(fine_tuned);
+        assert_eq!(model.capabilities(), model_capabilities(GPT_4O));
+
+        let response = model.completion_request("Hi").send().await.unwrap();
+        assert_eq!(response.choice, ModelChoice::Message("Hello!".to_string()));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("POST /chat/completions "));
+        let body = &requests[0][requests[0].find("\r\n\r\n").unwrap() + 4..];
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["model"], fine_tuned);
+    }
+
+    const TOOL_CALL: &str = r#"{
+        "id": "chatcmpl-456",
+        "object": "chat.completion",
+        "created": 1728000000,
+        "model": "gpt-4o",
+        "system_fingerprint": null,
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "add", "arguments": "{\"x\": 2, \"y\": 3}" }
+                }]
+            },
+            "logprobs": null,
+            "finish_reason": "tool_calls"
+        }],
+        "usage": null
+    }"#;
+
+    #[tokio::test]
+    async fn test_complete_with_tools() {
+        let (url, requests) =
+            recording_mock_server(vec![(200, TOOL_CALL)], |request| Some(request.to_string()))
+                .await;
+        let model = Client::from_url("test", &url).completion_model(GPT_4O);
+
+        let message = |role: &str, content: &str| completion::Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        let add = completion::ToolDefinition {
+            name: "add".to_string(),
+            description: "Add x and y together".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "x": { "type": "number" },
+                    "y": { "type": "number" }
+                }
+            }),
+        };
+
+        let response = model
+            .complete_with_tools(
+                vec![
+                    message("system", "You are a calculator."),
+                    message("user", "What is 1 + 1?"),
+                    message("assistant", "2"),
+                    message("user", "What is 2 + 3?"),
+                ],
+                vec![add.clone()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.choice,
+            ModelChoice::ToolCall("add".to_string(), json!({"x": 2, "y": 3}))
+        );
+
+        let requests = requests.lock().unwrap();
+        let body = &requests[0][requests[0].find("\r\n\r\n").unwrap() + 4..];
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            body["tools"],
+            json!([{ "type": "function", "function": serde_json::to_value(&add).unwrap() }])
+        );
+        assert_eq!(
+            body["messages"],
+            json!([
+                { "role": "system", "content": "You are a calculator." },
+                { "role": "user", "content": "What is 1 + 1?" },
+                { "role": "assistant", "content": "2" },
+                { "role": "user", "content": "What is 2 + 3?" },
+            ])
+        );
+
+        // The messages should end with a prompt
+        assert!(matches!(
+            model
+                .complete_with_tools(vec![message("system", "You are a calculator.")], vec![])
+                .await,
+            Err(CompletionError::RequestError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tool_name_mapping() {
+        let response = TOOL_CALL.replace(r#""name": "add""#, r#""name": "math_add""#);
+        let (url, requests) = recording_mock_server(vec![(200, response.leak())], |request| {
+            Some(request.to_string())
+        })
+        .await;
+        let model = Client::from_url("test", &url).completion_model(GPT_4O);
+
+        let add = completion::ToolDefinition {
+            name: "math.add".to_string(),
+            description: "Add x and y together".to_string(),
+            parameters: json!({ "type": "object" }),
+        };
+        let response = model
+            .complete_with_tools(
+                vec![completion::Message {
+                    role: "user".to_string(),
+                    content: "What is 2 + 3?".to_string(),
+                }],
+                vec![add],
+            )
+            .await
+            .unwrap();
+
+        // The tool is sent under a valid name and its call resolved to the original name
+        let requests = requests.lock().unwrap();
+        let body = &requests[0][requests[0].find("\r\n\r\n").unwrap() + 4..];
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["tools"][0]["function"]["name"], "math_add");
+        assert_eq!(
+            response.choice,
+            ModelChoice::ToolCall("math.add".to_string(), json!({"x": 2, "y": 3}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_too_large() {
+        let (url, requests) = mock_server(vec![(200, COMPLETION), (200, COMPLETION)]).await;
+        let model = Client::from_url("test", &url).completion_model(GPT_4);
+
+        // ~10k tokens, more than the context window of gpt-4
+        let prompt = "Flurbos are a made up currency. ".repeat(1250);
+        let result = model.completion_request(&prompt).send().await;
+        assert!(matches!(
+            result,
+            Err(CompletionError::RequestTooLarge {
+                estimated: 10_000,
+                limit: 8_192
+            })
+        ));
+        assert!(model
+            .stream(model.completion_request(&prompt).build())
+            .await
+            .is_err());
+
+        // The request was rejected without being sent
+        assert!(requests.lock().unwrap().is_empty());
+
+        // The limit can be raised (or disabled)
+        let model = model.request_limit(Some(16_000));
+        assert!(model.completion_request(&prompt).send().await.is_ok());
+        let model = model.request_limit(None);
+        assert!(model.completion_request(&prompt).send().await.is_ok());
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_request_size_of_family_prefix() {
+        let (url, requests) = mock_server(vec![(200, COMPLETION), (200, COMPLETION)]).await;
+        let client = Client::from_url("test", &url);
+
+        // ~10k tokens, more than the context window of gpt-4 but not of gpt-4.1-mini, which
+        // shares its prefix
+        let prompt = "Flurbos are a made up currency. ".repeat(1250);
+        let model = client.completion_model("gpt-4.1-mini");
+        assert!(model.completion_request(&prompt).send().await.is_ok());
+
+        // Unknown variants of a family are not limited
+        let model = client.completion_model("gpt-4-flurbo");
+        assert!(model.completion_request(&prompt).send().await.is_ok());
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    fn sse(events: &[&str]) -> Vec<Result<Vec<u8>, CompletionError>> {
+        events
+            .iter()
+            .map(|event| Ok(format!("data: {event}\n\n").into_bytes()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_call_stats() {
+        use crate::{
+            cache::{CachedPrompt, ExactCache},
+            completion::PromptWithStats,
+        };
+
+        let (url, _) = mock_server(vec![(500, SERVER_ERROR), (200, COMPLETION)]).await;
+        let model = Client::from_url("test", &url)
+            .completion_model(GPT_4O)
+            .retry_policy(RetryPolicy::new(2).backoff(Duration::from_millis(1)));
+        let agent = AgentBuilder::new(model).build();
+        let agent = CachedPrompt::new(agent, ExactCache::new());
+
+        // Flaky request, retried once
+        let (response, stats) = agent.prompt_with_stats("Hi").await.unwrap();
+        assert_eq!(response, "Hello!");
+        assert_eq!(stats.retries, 1);
+        assert!(!stats.cached);
+
+        // Served from the cache: the mock server would fail any further request
+        let (response, stats) = agent.prompt_with_stats("Hi").await.unwrap();
+        assert_eq!(response, "Hello!");
+        assert_eq!(stats.retries, 0);
+        assert!(stats.cached);
+    }
+
+    #[tokio::test]
+    async fn test_stream_usage() {
+        let chunks = sse(&[
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}],"usage":null}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}],"usage":null}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":" world!"},"finish_reason":null}],"usage":null}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"stop"}],"usage":null}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#,
+            "[DONE]",
+        ]);
+
+        let events = stream_events(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Delta("Hello".to_string()),
+                StreamEvent::Delta(" world!".to_string()),
+                StreamEvent::Done {
+                    usage: Some(completion::Usage {
+                        prompt_tokens: 12,
+                        completion_tokens: 3,
+                        total_tokens: 15,
+                        ..Default::default()
+                    })
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_tool_call() {
+        let chunks = sse(&[
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"add","arguments":""}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"x\": 1,"}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":" \"y\": 2}"}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+            "[DONE]",
+        ]);
+
+        let events = stream_events(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // Usage was not requested
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::ToolCall {
+                    name: "add".to_string(),
+                    arguments: json!({"x": 1, "y": 2}),
+                },
+                StreamEvent::Done { usage: None },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_content_filtered() {
+        let response = serde_json::from_str::<CompletionResponse>(
+            r#"{
+                "id": "chatcmpl-789",
+                "object": "chat.completion",
+                "created": 1728000000,
+                "model": "gpt-4o",
+                "system_fingerprint": null,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "The duel ended when" },
+                    "logprobs": null,
+                    "finish_reason": "content_filter",
+                    "content_filter_results": {
+                        "hate": { "filtered": false, "severity": "safe" },
+                        "violence": { "filtered": true, "severity": "high" }
+                    }
+                }],
+                "usage": null
+            }"#,
+        )
+        .unwrap();
+
+        match completion::CompletionResponse::try_from(response) {
+            Err(CompletionError::ContentFiltered {
+                partial,
+                categories,
+            }) => {
+                assert_eq!(partial.as_deref(), Some("The duel ended when"));
+                assert_eq!(categories, vec!["violence"]);
+            }
+            response => panic!("Unexpected response: {response:?}"),
+        }
+
+        let chunks = sse(&[
+            r#"{"choices":[{"index":0,"delta":{"content":"The duel"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"content_filter"}]}"#,
+            "[DONE]",
+        ]);
+        let events = stream_events(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            *events[0].as_ref().unwrap(),
+            StreamEvent::Delta("The duel".to_string())
+        );
+        assert!(matches!(
+            events[1],
+            Err(CompletionError::ContentFiltered { .. })
+        ));
+    }
+
+    #[test]
+    fn test_stream_options() {
+        let model = Client::new("test").completion_model(GPT_4O);
+        let request = model.completion_request("Hello").build();
+        let body = model.create_completion_request(request);
+        assert_eq!(body["model"], GPT_4O);
+        assert!(body.get("stream_options").is_none());
+
+        assert!(model.stream_usage);
+        assert!(!model.stream_usage(false).stream_usage);
+    }
+}
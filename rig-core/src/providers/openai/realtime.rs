@@ -0,0 +1,535 @@
+//! OpenAI Realtime API (`/v1/realtime`) integration, over a websocket connection
+//!
+//! The Realtime API is meant for low-latency interactive (e.g.: voice) apps: instead of one
+//! HTTP request per completion, the client and OpenAI exchange JSON events over a websocket.
+//! [RealtimeModel] opens a connection per request, configures the session (instructions, tools,
+//! temperature) and the conversation from the [CompletionRequest], and streams the response
+//! as the same [StreamEvent]s as the HTTP streaming models (see [crate::streaming]).
+//!
+//! Requires the `realtime` feature.
+//!
+//! # Example
+//! ```
+//! use futures::StreamExt;
+//! use rig::{
+//!     completion::CompletionModel,
+//!     providers::openai::{self, realtime},
+//!     streaming::{StreamEvent, StreamingCompletionModel},
+//! };
+//!
+//! let client = openai::Client::from_env();
+//! let model = client.realtime_model(realtime::GPT_4O_REALTIME_PREVIEW);
+//!
+//! let mut stream = model.stream(model.completion_request("Tell me a joke").build()).await?;
+//! while let Some(event) = stream.next().await {
+//!     if let StreamEvent::Delta(text) = event? {
+//!         print!("{text}");
+//!     }
+//! }
+//! ```
+use futures::{stream, SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, client::IntoClientRequest, Message as WsMessage},
+};
+
+use super::Client;
+use crate::{
+    completion::{self, CompletionError, CompletionRequest},
+    streaming::{StreamEvent, StreamingCompletionModel, StreamingResult},
+};
+
+/// `gpt-4o-realtime-preview` realtime model
+pub const GPT_4O_REALTIME_PREVIEW: &str = "gpt-4o-realtime-preview";
+/// `gpt-4o-mini-realtime-preview` realtime model
+pub const GPT_4O_MINI_REALTIME_PREVIEW: &str = "gpt-4o-mini-realtime-preview";
+
+/// Model of the Realtime API, streaming its responses over a websocket connection (see the
+/// [module](self) documentation). Responses are text-only.
+#[derive(Clone)]
+pub struct RealtimeModel {
+    client: Client,
+    /// Name of the model (e.g.: gpt-4o-realtime-preview)
+    pub model: String,
+}
+
+impl RealtimeModel {
+    pub fn new(client: Client, model: &str) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+        }
+    }
+
+    /// Websocket URL of the realtime endpoint, derived from the base URL of the client
+    fn url(&self) -> String {
+        let base_url = self.client.base_url.trim_end_matches('/');
+        let base_url = match base_url.split_once("://") {
+            Some(("https", rest)) => format!("wss://{rest}"),
+            Some(("http", rest)) => format!("ws://{rest}"),
+            _ => base_url.to_string(),
+        };
+        format!("{base_url}/realtime?model={}", self.model)
+    }
+
+    /// Client events sent to generate the response to `completion_request`: the update of the
+    /// session, the items of the conversation and the creation of the response.
+    fn client_events(&self, completion_request: CompletionRequest) -> Vec<serde_json::Value> {
+        let mut session = json!({ "modalities": ["text"] });
+        // The system instructions are sent concatenated as the instructions of the session
+        // (see [CompletionRequest::system_instructions])
+        if let Some(instructions) = completion_request.merged_system() {
+            session["instructions"] = json!(instructions);
+        }
+        if let Some(temperature) = completion_request.temperature {
+            session["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = completion_request.max_tokens {
+            session["max_response_output_tokens"] = json!(max_tokens);
+        }
+        if !completion_request.tools.is_empty() {
+            session["tools"] = completion_request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    })
+                })
+                .collect();
+            session["tool_choice"] = json!("auto");
+        }
+
+        let prompt = completion::Message {
+            role: "user".to_string(),
+            content: completion_request.prompt_with_context(),
+        };
+        let items = completion_request
+            .chat_history
+            .iter()
+            .filter(|message| !message.is_system())
+            .chain(std::iter::once(&prompt))
+            .map(|message| {
+                // Assistant messages hold output text, other messages input text
+                let content_type = if message.role == "assistant" {
+                    "text"
+                } else {
+                    "input_text"
+                };
+                json!({
+                    "type": "conversation.item.create",
+                    "item": {
+                        "type": "message",
+                        "role": message.role,
+                        "content": [{ "type": content_type, "text": message.content }],
+                    },
+                })
+            });
+
+        std::iter::once(json!({ "type": "session.update", "session": session }))
+            .chain(items)
+            .chain(std::iter::once(json!({ "type": "response.create" })))
+            .collect()
+    }
+
+    /// Open a websocket connection to the realtime endpoint, send the completion request and
+    /// stream the response as [StreamEvent]s. The connection is closed once the response is
+    /// done (i.e.: after the [StreamEvent::Done] event).
+    pub async fn stream(
+        &self,
+        completion_request: CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        let mut request = self.url().into_client_request().map_err(ws_error)?;
+        let headers = request.headers_mut();
+        headers.insert(
+            "Authorization",
+            format!("Bearer {}", self.client.api_key)
+                .parse()
+                .expect("Bearer token should parse"),
+        );
+        headers.insert(
+            "OpenAI-Beta",
+            "realtime=v1".parse().expect("Header should parse"),
+        );
+
+        let permit = self.client.permit().await;
+        let (mut socket, _) = connect_async(request).await.map_err(ws_error)?;
+        for event in self.client_events(completion_request) {
+            socket
+                .send(WsMessage::Text(event.to_string()))
+                .await
+                .map_err(ws_error)?;
+        }
+
+        Ok(Box::pin(stream::unfold(Some(socket), move |socket| {
+            // The permit is released once the stream is dropped
+            let _permit = &permit;
+            async move {
+                let mut socket = socket?;
+                loop {
+                    let message = match socket.next().await {
+                        Some(Ok(message)) => message,
+                        Some(Err(err)) => return Some((Err(ws_error(err)), None)),
+                        None => {
+                            return Some((
+                                Err(CompletionError::ResponseError(
+                                    "The connection was closed before the response was done".into(),
+                                )),
+                                None,
+                            ))
+                        }
+                    };
+                    // Pings are answered by the websocket itself
+                    let WsMessage::Text(event) = message else {
+                        continue;
+                    };
+
+                    match stream_event(&event) {
+                        Ok(Some(event @ StreamEvent::Done { .. })) => {
+                            let _ = socket.close(None).await;
+                            return Some((Ok(event), None));
+                        }
+                        Ok(Some(event)) => return Some((Ok(event), Some(socket))),
+                        Ok(None) => continue,
+                        Err(err) => return Some((Err(err), None)),
+                    }
+                }
+            }
+        })))
+    }
+}
+
+fn ws_error(err: tungstenite::Error) -> CompletionError {
+    CompletionError::ProviderSourceError(Box::new(err))
+}
+
+/// Server event of the Realtime API. Only the events of the text of the responses, of their
+/// function calls and of their completion are handled.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ServerEvent {
+    #[serde(rename = "response.text.delta")]
+    TextDelta { delta: String },
+    #[serde(rename = "response.audio_transcript.delta")]
+    AudioTranscriptDelta { delta: String },
+    #[serde(rename = "response.function_call_arguments.done")]
+    FunctionCallArgumentsDone { name: String, arguments: String },
+    #[serde(rename = "response.done")]
+    ResponseDone { response: Response },
+    #[serde(rename = "error")]
+    Error { error: ErrorDetails },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    status: String,
+    #[serde(default)]
+    status_details: Option<serde_json::Value>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetails {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    input_tokens: u64,
+    output_tokens: u64,
+    total_tokens: u64,
+    #[serde(default)]
+    input_token_details: Option<InputTokenDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputTokenDetails {
+    #[serde(default)]
+    cached_tokens: u64,
+}
+
+impl From<Usage> for completion::Usage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+            cached_tokens: usage
+                .input_token_details
+                .map_or(0, |details| details.cached_tokens),
+            cache_creation_tokens: 0,
+        }
+    }
+}
+
+/// [StreamEvent] of a (JSON) server event, if any
+fn stream_event(event: &str) -> Result<Option<StreamEvent>, CompletionError> {
+    Ok(match serde_json::from_str(event)? {
+        ServerEvent::TextDelta { delta } | ServerEvent::AudioTranscriptDelta { delta } => {
+            Some(StreamEvent::Delta(delta))
+        }
+        ServerEvent::FunctionCallArgumentsDone { name, arguments } => Some(StreamEvent::ToolCall {
+            name,
+            arguments: serde_json::from_str(&arguments)?,
+        }),
+        ServerEvent::ResponseDone { response } if response.status == "failed" => {
+            return Err(CompletionError::ProviderError(format!(
+                "The response failed: {}",
+                response.status_details.unwrap_or_default()
+            )))
+        }
+        ServerEvent::ResponseDone { response } => Some(StreamEvent::Done {
+            usage: response.usage.map(Into::into),
+        }),
+        ServerEvent::Error { error } => return Err(CompletionError::ProviderError(error.message)),
+        ServerEvent::Other => None,
+    })
+}
+
+impl completion::CompletionModel for RealtimeModel {
+    /// The events of the streamed response
+    type Response = Vec<StreamEvent>;
+
+    fn usage(response: &Vec<StreamEvent>) -> Option<completion::Usage> {
+        response.iter().find_map(|event| match event {
+            StreamEvent::Done { usage } => *usage,
+            _ => None,
+        })
+    }
+
+    /// Stream the response (see [RealtimeModel::stream]) and collect it: tool calls take
+    /// precedence over the text of the response, as for the chat completions API.
+    async fn completion(
+        &self,
+        completion_request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<Vec<StreamEvent>>, CompletionError> {
+        let events = self
+            .stream(completion_request)
+            .await?
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let call = events.iter().find_map(|event| match event {
+            StreamEvent::ToolCall { name, arguments } => Some((name.clone(), arguments.clone())),
+            _ => None,
+        });
+        let choice = match call {
+            Some((name, arguments)) => completion::ModelChoice::ToolCall(name, arguments),
+            None => completion::ModelChoice::Message(
+                events
+                    .iter()
+                    .filter_map(|event| match event {
+                        StreamEvent::Delta(delta) => Some(delta.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        };
+
+        Ok(completion::CompletionResponse {
+            choice,
+            raw_response: events,
+        })
+    }
+}
+
+impl StreamingCompletionModel for RealtimeModel {
+    async fn stream(&self, request: CompletionRequest) -> Result<StreamingResult, CompletionError> {
+        RealtimeModel::stream(self, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::{SinkExt, StreamExt};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{
+        accept_hdr_async,
+        tungstenite::{
+            handshake::server::{Request, Response},
+            Message as WsMessage,
+        },
+    };
+
+    use super::GPT_4O_REALTIME_PREVIEW;
+    use crate::{
+        completion::{self, CompletionModel as _, Usage},
+        providers::openai::Client,
+        streaming::StreamEvent,
+    };
+
+    /// Accept a websocket connection, record the `OpenAI-Beta` header of the handshake and the
+    /// client events until `response.create`, then send `events`. Returns the base URL of the
+    /// server.
+    async fn mock_server(events: Vec<serde_json::Value>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let recorded = received.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let header = recorded.clone();
+            let mut socket = accept_hdr_async(stream, move |request: &Request, response| {
+                let beta = request.headers()["OpenAI-Beta"].to_str().unwrap();
+                header.lock().unwrap().push(beta.to_string());
+                Ok::<Response, _>(response)
+            })
+            .await
+            .unwrap();
+
+            while let Some(Ok(WsMessage::Text(event))) = socket.next().await {
+                let done = event.contains(r#""type":"response.create""#);
+                recorded.lock().unwrap().push(event);
+                if done {
+                    break;
+                }
+            }
+            for event in events {
+                socket
+                    .send(WsMessage::Text(event.to_string()))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let (url, received) = mock_server(vec![
+            json!({ "type": "session.updated", "session": {} }),
+            json!({ "type": "response.created", "response": {} }),
+            json!({ "type": "response.text.delta", "delta": "Flurbos are" }),
+            json!({ "type": "response.text.delta", "delta": " a currency." }),
+            json!({
+                "type": "response.done",
+                "response": {
+                    "status": "completed",
+                    "usage": { "input_tokens": 20, "output_tokens": 5, "total_tokens": 25 }
+                }
+            }),
+        ])
+        .await;
+        let model = Client::from_url("test", &url).realtime_model(GPT_4O_REALTIME_PREVIEW);
+
+        let request = model
+            .completion_request("What is a flurbo?")
+            .preamble("You are a dictionary.".to_string())
+            .messages(vec![completion::Message {
+                role: "assistant".to_string(),
+                content: "Hello!".to_string(),
+            }])
+            .build();
+        let events = model
+            .stream(request)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Delta("Flurbos are".to_string()),
+                StreamEvent::Delta(" a currency.".to_string()),
+                StreamEvent::Done {
+                    usage: Some(Usage {
+                        prompt_tokens: 20,
+                        completion_tokens: 5,
+                        total_tokens: 25,
+                        ..Default::default()
+                    })
+                },
+            ]
+        );
+
+        let received = received.lock().unwrap();
+        assert_eq!(received[0], "realtime=v1");
+        let events = received[1..]
+            .iter()
+            .map(|event| serde_json::from_str::<serde_json::Value>(event).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                json!({
+                    "type": "session.update",
+                    "session": { "modalities": ["text"], "instructions": "You are a dictionary." }
+                }),
+                json!({
+                    "type": "conversation.item.create",
+                    "item": {
+                        "type": "message",
+                        "role": "assistant",
+                        "content": [{ "type": "text", "text": "Hello!" }]
+                    }
+                }),
+                json!({
+                    "type": "conversation.item.create",
+                    "item": {
+                        "type": "message",
+                        "role": "user",
+                        "content": [{ "type": "input_text", "text": "What is a flurbo?" }]
+                    }
+                }),
+                json!({ "type": "response.create" }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_tool_call() {
+        let (url, _) = mock_server(vec![
+            json!({
+                "type": "response.function_call_arguments.done",
+                "call_id": "call_1",
+                "name": "add",
+                "arguments": "{\"x\":2,\"y\":3}"
+            }),
+            json!({ "type": "response.done", "response": { "status": "completed" } }),
+        ])
+        .await;
+        let model = Client::from_url("test", &url).realtime_model(GPT_4O_REALTIME_PREVIEW);
+
+        let response = model
+            .completion_request("What is 2 + 3?")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.choice,
+            completion::ModelChoice::ToolCall("add".to_string(), json!({ "x": 2, "y": 3 }))
+        );
+
+        // Errors of the server are surfaced as provider errors
+        let (url, _) = mock_server(vec![json!({
+            "type": "error",
+            "error": { "type": "invalid_request_error", "message": "Invalid model" }
+        })])
+        .await;
+        let model = Client::from_url("test", &url).realtime_model(GPT_4O_REALTIME_PREVIEW);
+        let result = model.completion_request("What is 2 + 3?").send().await;
+        assert!(matches!(
+            result,
+            Err(completion::CompletionError::ProviderError(message)) if message == "Invalid model"
+        ));
+    }
+}
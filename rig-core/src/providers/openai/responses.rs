@@ -0,0 +1,541 @@
+//! OpenAI Responses API (`/v1/responses`) integration
+//!
+//! The Responses API supersedes the chat completions API: the preamble is sent as the
+//! `instructions` of the request, the chat history and the prompt as its `input` items, and
+//! the generated messages and tool calls are returned as `output` items.
+//!
+//! Responses are stored by OpenAI, so that a conversation can be continued on the server side
+//! by referencing the previous response (see [CompletionModel::previous_response_id]) instead
+//! of sending the whole chat history again.
+//!
+//! # Example
+//! ```
+//! use rig::{completion::CompletionModel, providers::openai};
+//!
+//! let client = openai::Client::new("YOUR_API_KEY");
+//!
+//! let model = client.responses_model(openai::GPT_4O);
+//! let response = model.completion_request("Hello!").send().await?;
+//!
+//! // Continue the conversation from the previous response
+//! let model = model.previous_response_id(&response.raw_response.id);
+//! let response = model.completion_request("What did I just say?").send().await?;
+//! ```
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{reasoning_effort, ApiResponse, Client};
+use crate::{
+    completion::{self, CompletionError, CompletionRequest},
+    json_utils,
+};
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub model: String,
+    pub status: String,
+    pub output: Vec<OutputItem>,
+    pub previous_response_id: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+/// Item of the `output` of a response
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputItem {
+    Message {
+        id: String,
+        role: String,
+        content: Vec<OutputContent>,
+    },
+    FunctionCall {
+        id: String,
+        call_id: String,
+        name: String,
+        /// JSON-encoded arguments of the call
+        arguments: String,
+    },
+    /// Other items (e.g.: reasoning summaries, built-in tool calls), kept as is
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+impl CompletionResponse {
+    /// Calls of the built-in tools executed by OpenAI (i.e.: the `*_call` output items other
+    /// than function calls, such as `web_search_call`), each item being the output of its call.
+    pub fn native_tool_results(&self) -> Vec<completion::NativeToolResult> {
+        self.output
+            .iter()
+            .filter_map(|item| match item {
+                OutputItem::Other(item) => {
+                    let name = item.get("type")?.as_str()?.strip_suffix("_call")?;
+                    Some(completion::NativeToolResult {
+                        id: item.get("id")?.as_str()?.to_string(),
+                        name: name.to_string(),
+                        input: item.get("action").cloned().unwrap_or_default(),
+                        output: item.clone(),
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Content of an output [OutputItem::Message]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputContent {
+    OutputText { text: String },
+    Refusal { refusal: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Usage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub total_tokens: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens_details: Option<InputTokensDetails>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct InputTokensDetails {
+    /// Number of input tokens read from the prompt cache
+    #[serde(default)]
+    pub cached_tokens: usize,
+}
+
+impl From<Usage> for completion::Usage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            prompt_tokens: usage.input_tokens as u64,
+            completion_tokens: usage.output_tokens as u64,
+            total_tokens: usage.total_tokens as u64,
+            cached_tokens: usage
+                .input_tokens_details
+                .map_or(0, |details| details.cached_tokens) as u64,
+            cache_creation_tokens: 0,
+        }
+    }
+}
+
+impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionResponse> {
+    type Error = CompletionError;
+
+    fn try_from(value: CompletionResponse) -> Result<Self, Self::Error> {
+        // Tool calls take precedence over messages, as for the chat completions API
+        let call = value.output.iter().find_map(|item| match item {
+            OutputItem::FunctionCall {
+                name, arguments, ..
+            } => Some((name, arguments)),
+            _ => None,
+        });
+        if let Some((name, arguments)) = call {
+            return Ok(completion::CompletionResponse {
+                choice: completion::ModelChoice::ToolCall(
+                    name.clone(),
+                    serde_json::from_str(arguments)?,
+                ),
+                raw_response: value,
+            });
+        }
+
+        let texts = value
+            .output
+            .iter()
+            .filter_map(|item| match item {
+                OutputItem::Message { content, .. } => Some(content),
+                _ => None,
+            })
+            .flatten()
+            .map(|content| match content {
+                OutputContent::OutputText { text } => text.as_str(),
+                OutputContent::Refusal { refusal } => refusal.as_str(),
+            })
+            .collect::<Vec<_>>();
+        if texts.is_empty() {
+            return Err(CompletionError::ResponseError(
+                "Response did not contain a message or tool call".into(),
+            ));
+        }
+
+        Ok(completion::CompletionResponse {
+            choice: completion::ModelChoice::Message(texts.concat()),
+            raw_response: value,
+        })
+    }
+}
+
+/// Definition of a built-in tool of the Responses API
+fn native_tool(tool: completion::NativeTool) -> serde_json::Value {
+    match tool {
+        completion::NativeTool::WebSearch { .. } => json!({ "type": "web_search_preview" }),
+        completion::NativeTool::CodeExecution => {
+            json!({ "type": "code_interpreter", "container": { "type": "auto" } })
+        }
+        completion::NativeTool::Custom(tool) => tool,
+    }
+}
+
+/// Completion model using the OpenAI Responses API (see the [module](self) documentation).
+#[derive(Clone)]
+pub struct CompletionModel {
+    client: Client,
+    /// Name of the model (e.g.: gpt-4o)
+    pub model: String,
+    /// Id of the response continued by the requests of the model
+    previous_response_id: Option<String>,
+}
+
+impl CompletionModel {
+    pub fn new(client: Client, model: &str) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+            previous_response_id: None,
+        }
+    }
+
+    /// Continue the conversation of the stored response `response_id` (i.e.: the `id` of a
+    /// previous [CompletionResponse]). The conversation state is then held by OpenAI, so the
+    /// chat history of the requests is not sent (only their preamble, context and prompt).
+    ///
+    /// The id can also be set per request with the `previous_response_id` additional parameter.
+    pub fn previous_response_id(mut self, response_id: &str) -> Self {
+        self.previous_response_id = Some(response_id.to_string());
+        self
+    }
+
+    fn create_completion_request(
+        &self,
+        completion_request: CompletionRequest,
+    ) -> serde_json::Value {
+        let previous_response_id = completion_request
+            .additional_params
+            .as_ref()
+            .and_then(|params| params.get("previous_response_id"))
+            .and_then(|id| id.as_str())
+            .map(str::to_string)
+            .or_else(|| self.previous_response_id.clone());
+
+        // The chat history is already stored by OpenAI when continuing a previous response
+        let history = if previous_response_id.is_some() {
+            vec![]
+        } else {
+            completion_request
+                .chat_history
+                .iter()
+                .filter(|message| !message.is_system())
+                .map(|message| json!({ "role": message.role, "content": message.content }))
+                .collect()
+        };
+
+        // Add the prompt with its context documents (and images, if any) to the input
+        let prompt_with_context = completion_request.prompt_with_context();
+        let prompt_message = if completion_request.images.is_empty() {
+            json!({ "role": "user", "content": prompt_with_context })
+        } else {
+            let content =
+                std::iter::once(json!({ "type": "input_text", "text": prompt_with_context }))
+                    .chain(completion_request.images.iter().map(
+                        |image| json!({ "type": "input_image", "image_url": image.data_url() }),
+                    ))
+                    .collect::<Vec<_>>();
+            json!({ "role": "user", "content": content })
+        };
+        let input = history
+            .into_iter()
+            .chain(std::iter::once(prompt_message))
+            .collect::<Vec<_>>();
+
+        let mut request = json!({
+            "model": self.model,
+            "input": input,
+            "temperature": completion_request.temperature,
+        });
+
+        // The system instructions are sent concatenated as the instructions of the response
+        // (see [CompletionRequest::system_instructions])
+        if let Some(instructions) = completion_request.merged_system() {
+            request = json_utils::merge(request, json!({ "instructions": instructions }));
+        }
+
+        if !completion_request.tools.is_empty() || !completion_request.native_tools.is_empty() {
+            // Function tools are not nested in a `function` object, unlike chat completions
+            let tools = completion_request
+                .tools
+                .into_iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    })
+                })
+                .chain(completion_request.native_tools.into_iter().map(native_tool))
+                .collect::<Vec<_>>();
+            request = json_utils::merge(request, json!({ "tools": tools, "tool_choice": "auto" }));
+        }
+
+        if let Some(max_tokens) = completion_request.max_tokens {
+            request = json_utils::merge(request, json!({ "max_output_tokens": max_tokens }));
+        }
+
+        if let Some(effort) = reasoning_effort(&self.model, completion_request.reasoning_effort) {
+            request = json_utils::merge(request, json!({ "reasoning": { "effort": effort } }));
+        }
+
+        if let Some(previous_response_id) = previous_response_id {
+            request = json_utils::merge(
+                request,
+                json!({ "previous_response_id": previous_response_id }),
+            );
+        }
+
+        if let Some(params) = completion_request.additional_params {
+            json_utils::merge(request, params)
+        } else {
+            request
+        }
+    }
+}
+
+impl completion::CompletionModel for CompletionModel {
+    type Response = CompletionResponse;
+
+    fn usage(response: &CompletionResponse) -> Option<completion::Usage> {
+        response.usage.clone().map(Into::into)
+    }
+
+    fn native_tool_results(response: &CompletionResponse) -> Vec<completion::NativeToolResult> {
+        response.native_tool_results()
+    }
+
+    async fn completion(
+        &self,
+        completion_request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let idempotency_key = completion_request
+            .idempotency_key
+            .clone()
+            .unwrap_or_else(completion::generate_idempotency_key);
+        let request = self.create_completion_request(completion_request);
+
+        let _permit = self.client.permit().await;
+        let response = self
+            .client
+            .post("/responses")
+            .header("Idempotency-Key", idempotency_key)
+            .json(&self.client.interceptors.intercept_request(&request)?)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            match self
+                .client
+                .interceptors
+                .parse::<ApiResponse<CompletionResponse>, CompletionError>(response)
+                .await?
+            {
+                ApiResponse::Ok(response) => {
+                    tracing::info!(target: "rig",
+                        "OpenAI responses token usage: {:?}",
+                        response.usage
+                    );
+                    response.try_into()
+                }
+                ApiResponse::Err(err) => Err(CompletionError::ProviderError(err.message)),
+            }
+        } else {
+            Err(CompletionError::ProviderError(response.text().await?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{CompletionResponse, OutputItem};
+    use crate::{
+        completion::{self, CompletionModel as _, Message, ModelChoice},
+        providers::openai::{Client, GPT_4O},
+    };
+
+    #[test]
+    fn test_request() {
+        let model = Client::new("test").responses_model(GPT_4O);
+        let request = |model: &super::CompletionModel| {
+            model.create_completion_request(
+                model
+                    .completion_request("What is 2 + 3?")
+                    .preamble("You are a calculator.".to_string())
+                    .messages(vec![
+                        Message {
+                            role: "user".into(),
+                            content: "Hello".into(),
+                        },
+                        Message {
+                            role: "assistant".into(),
+                            content: "Hi!".into(),
+                        },
+                    ])
+                    .tool(completion::ToolDefinition {
+                        name: "add".into(),
+                        description: "Add x and y together".into(),
+                        parameters: json!({ "type": "object" }),
+                    })
+                    .max_tokens(64)
+                    .build(),
+            )
+        };
+
+        assert_eq!(
+            request(&model),
+            json!({
+                "model": "gpt-4o",
+                "instructions": "You are a calculator.",
+                "input": [
+                    { "role": "user", "content": "Hello" },
+                    { "role": "assistant", "content": "Hi!" },
+                    { "role": "user", "content": "What is 2 + 3?" },
+                ],
+                "temperature": null,
+                "tools": [{
+                    "type": "function",
+                    "name": "add",
+                    "description": "Add x and y together",
+                    "parameters": { "type": "object" },
+                }],
+                "tool_choice": "auto",
+                "max_output_tokens": 64,
+            })
+        );
+
+        // The history is held by OpenAI when continuing a previous response
+        let request = request(&model.previous_response_id("resp_123"));
+        assert_eq!(request["previous_response_id"], "resp_123");
+        assert_eq!(
+            request["input"],
+            json!([{ "role": "user", "content": "What is 2 + 3?" }])
+        );
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let response = r#"{
+            "id": "resp_456",
+            "object": "response",
+            "created_at": 1741476542,
+            "model": "gpt-4o-2024-08-06",
+            "status": "completed",
+            "output": [
+                { "type": "reasoning", "id": "rs_1", "summary": [] },
+                {
+                    "type": "message",
+                    "id": "msg_1",
+                    "status": "completed",
+                    "role": "assistant",
+                    "content": [{ "type": "output_text", "text": "2 + 3 = 5", "annotations": [] }]
+                }
+            ],
+            "previous_response_id": "resp_123",
+            "usage": { "input_tokens": 36, "output_tokens": 8, "total_tokens": 44 }
+        }"#;
+        let response = serde_json::from_str::<CompletionResponse>(response).unwrap();
+        assert!(matches!(response.output[0], OutputItem::Other(_)));
+        assert_eq!(
+            super::CompletionModel::usage(&response)
+                .unwrap()
+                .completion_tokens,
+            8
+        );
+
+        let response = completion::CompletionResponse::try_from(response).unwrap();
+        assert_eq!(response.choice, ModelChoice::Message("2 + 3 = 5".into()));
+        assert!(super::CompletionModel::native_tool_results(&response.raw_response).is_empty());
+
+        let response = r#"{
+            "id": "resp_789",
+            "object": "response",
+            "created_at": 1741476542,
+            "model": "gpt-4o-2024-08-06",
+            "status": "completed",
+            "output": [{
+                "type": "function_call",
+                "id": "fc_1",
+                "call_id": "call_1",
+                "name": "add",
+                "arguments": "{\"x\":2,\"y\":3}",
+                "status": "completed"
+            }],
+            "previous_response_id": null,
+            "usage": null
+        }"#;
+        let response = completion::CompletionResponse::try_from(
+            serde_json::from_str::<CompletionResponse>(response).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            response.choice,
+            ModelChoice::ToolCall("add".into(), json!({ "x": 2, "y": 3 }))
+        );
+    }
+
+    #[test]
+    fn test_native_tools() {
+        let model = Client::new("test").responses_model(GPT_4O);
+        let request = model.create_completion_request(
+            model
+                .completion_request("What is the price of a flurbo today?")
+                .native_tool(completion::NativeTool::WebSearch { max_uses: None })
+                .build(),
+        );
+        assert_eq!(request["tools"], json!([{ "type": "web_search_preview" }]));
+
+        let response = r#"{
+            "id": "resp_1",
+            "object": "response",
+            "created_at": 1741476542,
+            "model": "gpt-4o-2024-08-06",
+            "status": "completed",
+            "output": [
+                {
+                    "type": "web_search_call",
+                    "id": "ws_1",
+                    "status": "completed",
+                    "action": { "type": "search", "query": "flurbo price" }
+                },
+                {
+                    "type": "message",
+                    "id": "msg_1",
+                    "status": "completed",
+                    "role": "assistant",
+                    "content": [{ "type": "output_text", "text": "A flurbo is worth 2 glarbs." }]
+                }
+            ],
+            "previous_response_id": null,
+            "usage": null
+        }"#;
+        let response = completion::CompletionResponse::try_from(
+            serde_json::from_str::<CompletionResponse>(response).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            response.choice,
+            ModelChoice::Message("A flurbo is worth 2 glarbs.".into())
+        );
+
+        let results = super::CompletionModel::native_tool_results(&response.raw_response);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "ws_1");
+        assert_eq!(results[0].name, "web_search");
+        assert_eq!(results[0].input["query"], "flurbo price");
+        assert_eq!(results[0].output["status"], "completed");
+    }
+}
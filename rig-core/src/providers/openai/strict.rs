@@ -0,0 +1,334 @@
+//! Schemas compatible with OpenAI's strict structured outputs.
+//!
+//! In strict mode (e.g.: tools sent with `strict: true`, see
+//! [CompletionModel::strict_tools](super::CompletionModel::strict_tools)), OpenAI guarantees
+//! that the generated arguments adhere to the JSON schema, provided that the schema only uses
+//! the supported subset of JSON schema: every object must list all of its properties as
+//! `required` and set `additionalProperties: false`, and the root must be an object.
+//!
+//! [to_strict_schema] converts a schema (e.g.: generated with `schemars`) to this subset:
+//! - every property of the objects is marked as required, optional fields (i.e.: `Option<T>`)
+//!   being represented as nullable types, and `additionalProperties: false` is set,
+//! - `oneOf` (e.g.: enums with data) is converted to `anyOf`, as is an `allOf` with a single
+//!   schema (e.g.: a documented field referencing another type),
+//! - validation keywords which strict mode does not support (e.g.: `format`, `minimum`) are
+//!   removed: the values are still validated when deserialized,
+//! - `definitions` are moved to `$defs`.
+//!
+//! Schemas which cannot be represented in strict mode are rejected with a
+//! [StrictSchemaError]: maps with arbitrary keys (e.g.: `HashMap<String, T>`), values of any
+//! type (e.g.: `serde_json::Value`), tuples and non-object roots.
+//!
+//! # Example
+//! ```rust
+//! use rig::providers::openai::strict::strict_schema;
+//!
+//! #[derive(schemars::JsonSchema)]
+//! struct Person {
+//!     name: String,
+//!     age: Option<u8>,
+//! }
+//!
+//! let schema = strict_schema::<Person>()?;
+//! assert_eq!(schema["required"], serde_json::json!(["age", "name"]));
+//! assert_eq!(schema["additionalProperties"], false);
+//! ```
+use schemars::{schema_for, JsonSchema};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+/// Validation keywords not supported in strict mode, removed from the schemas
+const UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "$schema",
+    "format",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+    "minProperties",
+    "maxProperties",
+    "default",
+    "examples",
+    "readOnly",
+    "writeOnly",
+];
+
+/// Keywords of schemas which cannot be represented in strict mode
+const UNREPRESENTABLE_KEYWORDS: &[&str] = &[
+    "not",
+    "if",
+    "then",
+    "else",
+    "patternProperties",
+    "propertyNames",
+    "dependencies",
+    "contains",
+];
+
+/// Error returned when a schema cannot be represented in strict mode
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[error("Schema not supported in strict mode at `{path}`: {reason}")]
+pub struct StrictSchemaError {
+    /// JSON pointer of the unsupported part of the schema (e.g.: `/properties/scores`)
+    pub path: String,
+    /// Why it is not supported
+    pub reason: String,
+}
+
+/// Strict-compatible JSON schema of `T` (see the [module](self) documentation).
+pub fn strict_schema<T: JsonSchema>() -> Result<Value, StrictSchemaError> {
+    to_strict_schema(json!(schema_for!(T)))
+}
+
+/// Convert `schema` to a strict-compatible JSON schema (see the [module](self) documentation).
+pub fn to_strict_schema(mut schema: Value) -> Result<Value, StrictSchemaError> {
+    let Some(root) = schema.as_object_mut() else {
+        return Err(unsupported("", "the root of the schema must be an object"));
+    };
+    if root.get("type") != Some(&json!("object")) {
+        return Err(unsupported("", "the root of the schema must be an object"));
+    }
+    if let Some(definitions) = root.remove("definitions") {
+        root.insert("$defs".to_string(), definitions);
+    }
+
+    strictify(&mut schema, "")?;
+    Ok(schema)
+}
+
+fn unsupported(path: &str, reason: &str) -> StrictSchemaError {
+    StrictSchemaError {
+        path: if path.is_empty() { "/" } else { path }.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Whether `object` is the schema of an object
+fn is_object(object: &Map<String, Value>) -> bool {
+    object.contains_key("properties")
+        || match object.get("type") {
+            Some(Value::String(ty)) => ty == "object",
+            Some(Value::Array(types)) => types.iter().any(|ty| ty == "object"),
+            _ => false,
+        }
+}
+
+/// Convert the schema at `path` (and its subschemas) in place
+fn strictify(schema: &mut Value, path: &str) -> Result<(), StrictSchemaError> {
+    let object = match schema {
+        Value::Object(object) => object,
+        Value::Bool(true) => {
+            return Err(unsupported(path, "values of any type are not supported"));
+        }
+        _ => return Err(unsupported(path, "invalid schema")),
+    };
+
+    for keyword in UNSUPPORTED_KEYWORDS {
+        object.remove(*keyword);
+    }
+    if let Some(keyword) = UNREPRESENTABLE_KEYWORDS
+        .iter()
+        .find(|keyword| object.contains_key(**keyword))
+    {
+        return Err(unsupported(path, &format!("`{keyword}` is not supported")));
+    }
+    if !["type", "$ref", "anyOf", "oneOf", "allOf", "enum", "const"]
+        .iter()
+        .any(|keyword| object.contains_key(*keyword))
+    {
+        return Err(unsupported(path, "values of any type are not supported"));
+    }
+
+    if let Some(Value::String(reference)) = object.get_mut("$ref") {
+        if let Some(name) = reference.strip_prefix("#/definitions/") {
+            *reference = format!("#/$defs/{name}");
+        }
+    }
+    if let Some(one_of) = object.remove("oneOf") {
+        object.insert("anyOf".to_string(), one_of);
+    }
+    match object.remove("allOf") {
+        Some(Value::Array(schemas)) if schemas.len() == 1 => {
+            object.insert("anyOf".to_string(), Value::Array(schemas));
+        }
+        Some(_) => {
+            return Err(unsupported(
+                path,
+                "`allOf` with several schemas is not supported",
+            ));
+        }
+        None => (),
+    }
+
+    if is_object(object) {
+        match object.get("additionalProperties") {
+            None | Some(Value::Bool(false)) => (),
+            Some(_) => {
+                return Err(unsupported(
+                    path,
+                    "maps with arbitrary keys are not supported",
+                ));
+            }
+        }
+        object.insert("additionalProperties".to_string(), Value::Bool(false));
+
+        let properties = object
+            .entry("properties")
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .ok_or_else(|| unsupported(path, "`properties` must be an object"))?;
+        for (name, property) in properties.iter_mut() {
+            strictify(property, &format!("{path}/properties/{name}"))?;
+        }
+        let required = properties.keys().cloned().collect::<Vec<_>>();
+        object.insert("required".to_string(), json!(required));
+    }
+
+    match object.get_mut("items") {
+        Some(Value::Array(_)) => return Err(unsupported(path, "tuples are not supported")),
+        Some(items) => strictify(items, &format!("{path}/items"))?,
+        None => (),
+    }
+
+    if let Some(Value::Array(schemas)) = object.get_mut("anyOf") {
+        for (i, schema) in schemas.iter_mut().enumerate() {
+            strictify(schema, &format!("{path}/anyOf/{i}"))?;
+        }
+    }
+
+    if let Some(Value::Object(definitions)) = object.get_mut("$defs") {
+        for (name, definition) in definitions.iter_mut() {
+            strictify(definition, &format!("{path}/$defs/{name}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use schemars::JsonSchema;
+    use serde_json::json;
+
+    use super::{strict_schema, StrictSchemaError};
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Address {
+        city: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    enum Role {
+        Admin,
+        Guest { until: String },
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Person {
+        name: String,
+        age: Option<u8>,
+        tags: Vec<String>,
+        /// Work address
+        work: Address,
+        home: Option<Address>,
+        role: Role,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Scores {
+        name: String,
+        scores: HashMap<String, u32>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Payload {
+        data: serde_json::Value,
+    }
+
+    #[test]
+    fn test_strict_schema() {
+        let address = json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"],
+            "additionalProperties": false,
+        });
+
+        assert_eq!(
+            strict_schema::<Person>().unwrap(),
+            json!({
+                "title": "Person",
+                "type": "object",
+                "properties": {
+                    "age": { "type": ["integer", "null"] },
+                    "home": {
+                        "anyOf": [{ "$ref": "#/$defs/Address" }, { "type": "null" }]
+                    },
+                    "name": { "type": "string" },
+                    "role": { "$ref": "#/$defs/Role" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                    "work": {
+                        "description": "Work address",
+                        "anyOf": [{ "$ref": "#/$defs/Address" }]
+                    },
+                },
+                "required": ["age", "home", "name", "role", "tags", "work"],
+                "additionalProperties": false,
+                "$defs": {
+                    "Address": address,
+                    "Role": {
+                        "anyOf": [
+                            { "type": "string", "enum": ["Admin"] },
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "Guest": {
+                                        "type": "object",
+                                        "properties": { "until": { "type": "string" } },
+                                        "required": ["until"],
+                                        "additionalProperties": false,
+                                    }
+                                },
+                                "required": ["Guest"],
+                                "additionalProperties": false,
+                            }
+                        ]
+                    },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_unsupported_schemas() {
+        assert_eq!(
+            strict_schema::<Scores>(),
+            Err(StrictSchemaError {
+                path: "/properties/scores".to_string(),
+                reason: "maps with arbitrary keys are not supported".to_string(),
+            })
+        );
+        assert_eq!(
+            strict_schema::<Payload>().unwrap_err().to_string(),
+            "Schema not supported in strict mode at `/properties/data`: values of any type are not supported"
+        );
+        assert_eq!(
+            strict_schema::<Role>().unwrap_err().to_string(),
+            "Schema not supported in strict mode at `/`: the root of the schema must be an object"
+        );
+    }
+}
@@ -14,6 +14,8 @@ use crate::{
     completion::{self, CompletionError},
     extractor::ExtractorBuilder,
     json_utils,
+    providers::{openai, Interceptors, ListModels, ListModelsError, ModelInfo},
+    streaming::{self, StreamingResult},
 };
 
 use schemars::JsonSchema;
@@ -29,6 +31,7 @@ const PERPLEXITY_API_BASE_URL: &str = "https://api.perplexity.ai";
 pub struct Client {
     base_url: String,
     http_client: reqwest::Client,
+    interceptors: Interceptors,
 }
 
 impl Client {
@@ -59,9 +62,17 @@ impl Client {
                 })
                 .build()
                 .expect("Perplexity reqwest client should build"),
+            interceptors: Interceptors::default(),
         }
     }
 
+    /// Patch the raw JSON bodies of the requests and responses of all the models created from
+    /// the client with `interceptors` (see [Interceptors]), e.g.: to adapt to a change of the API
+    pub fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
     pub fn post(&self, path: &str) -> reqwest::RequestBuilder {
         let url = format!("{}/{}", self.base_url, path).replace("//", "/");
         self.http_client.post(url)
@@ -113,6 +124,32 @@ pub const LLAMA_3_1_8B_INSTRUCT: &str = "llama-3.1-8b-instruct";
 /// `llama-3.1-70b-instruct` completion model
 pub const LLAMA_3_1_70B_INSTRUCT: &str = "llama-3.1-70b-instruct";
 
+/// Models known to be available from Perplexity (which has no endpoint listing them)
+const KNOWN_MODELS: [&str; 7] = [
+    LLAMA_3_1_SONAR_SMALL_ONLINE,
+    LLAMA_3_1_SONAR_LARGE_ONLINE,
+    LLAMA_3_1_SONAR_HUGE_ONLINE,
+    LLAMA_3_1_SONAR_SMALL_CHAT,
+    LLAMA_3_1_SONAR_LARGE_CHAT,
+    LLAMA_3_1_8B_INSTRUCT,
+    LLAMA_3_1_70B_INSTRUCT,
+];
+
+impl ListModels for Client {
+    /// Perplexity has no endpoint listing its models, so the known models are returned
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ListModelsError> {
+        Ok(KNOWN_MODELS
+            .iter()
+            .map(|model| ModelInfo {
+                id: model.to_string(),
+                owned_by: None,
+                created: None,
+                metadata: serde_json::Value::Null,
+            })
+            .collect())
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CompletionResponse {
     pub id: String,
@@ -193,31 +230,17 @@ impl CompletionModel {
             model: model.to_string(),
         }
     }
-}
 
-impl completion::CompletionModel for CompletionModel {
-    type Response = CompletionResponse;
-
-    async fn completion(
+    fn create_completion_request(
         &self,
         completion_request: completion::CompletionRequest,
-    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
-        // Add preamble to messages (if available)
-        let mut messages = if let Some(preamble) = &completion_request.preamble {
-            vec![completion::Message {
-                role: "system".into(),
-                content: preamble.clone(),
-            }]
-        } else {
-            vec![]
-        };
+    ) -> serde_json::Value {
+        // Add the system instructions and chat history to messages
+        let mut messages = completion_request.history_with_system_messages();
 
         // Add context documents to chat history
         let prompt_with_context = completion_request.prompt_with_context();
 
-        // Add chat history to messages
-        messages.extend(completion_request.chat_history);
-
         // Add user prompt to messages
         messages.push(completion::Message {
             role: "user".to_string(),
@@ -230,21 +253,72 @@ impl completion::CompletionModel for CompletionModel {
             "temperature": completion_request.temperature,
         });
 
+        if let Some(params) = completion_request.additional_params {
+            json_utils::merge(request, params)
+        } else {
+            request
+        }
+    }
+
+    /// Send the completion request and stream the response as
+    /// [StreamEvent](crate::streaming::StreamEvent)s. The Perplexity API streams
+    /// OpenAI-compatible server-sent events.
+    pub async fn stream(
+        &self,
+        completion_request: completion::CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        let request = json_utils::merge(
+            self.create_completion_request(completion_request),
+            json!({ "stream": true }),
+        );
+
+        let response = self
+            .client
+            .post("/chat/completions")
+            .json(&self.client.interceptors.intercept_request(&request)?)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(openai::stream_events(response.bytes_stream()))
+        } else {
+            Err(CompletionError::ProviderError(response.text().await?))
+        }
+    }
+}
+
+impl streaming::StreamingCompletionModel for CompletionModel {
+    async fn stream(
+        &self,
+        request: completion::CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        CompletionModel::stream(self, request).await
+    }
+}
+
+impl completion::CompletionModel for CompletionModel {
+    type Response = CompletionResponse;
+
+    async fn completion(
+        &self,
+        completion_request: completion::CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let request = self.create_completion_request(completion_request);
+
         let response = self
             .client
             .post("/chat/completions")
-            .json(
-                &if let Some(ref params) = completion_request.additional_params {
-                    json_utils::merge(request.clone(), params.clone())
-                } else {
-                    request.clone()
-                },
-            )
+            .json(&self.client.interceptors.intercept_request(&request)?)
             .send()
             .await?;
 
         if response.status().is_success() {
-            match response.json::<ApiResponse<CompletionResponse>>().await? {
+            match self
+                .client
+                .interceptors
+                .parse::<ApiResponse<CompletionResponse>, CompletionError>(response)
+                .await?
+            {
                 ApiResponse::Ok(completion) => {
                     tracing::info!(target: "rig",
                         "Perplexity completion token usage: {}",
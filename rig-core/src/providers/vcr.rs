@@ -0,0 +1,511 @@
+//! This module provides the [Vcr], a local HTTP proxy recording the interactions of provider
+//! clients with a provider to a cassette file, and replaying them deterministically from the
+//! cassette, so that provider integration tests are reproducible (e.g.: in CI, without API
+//! keys or network access).
+//!
+//! The clients are pointed at the proxy with their `from_url` constructor. Interactions are keyed
+//! by a hash of their request (method, path and body): headers, such as the API key, are neither
+//! hashed nor recorded. Identical requests recorded several times are replayed in the order they
+//! were recorded.
+//!
+//! Note: The [Vcr] requires the `vcr` feature to be enabled in the `Cargo.toml` file.
+//!
+//! # Example
+//! ```rust
+//! use rig::{completion::Prompt, providers::{openai, vcr::Vcr}};
+//!
+//! // Record the interactions with OpenAI (once, with a real API key)
+//! let vcr = Vcr::record("tests/cassettes/flurbo.json", "https://api.openai.com").await?;
+//! // Replay them (e.g.: in CI)
+//! let vcr = Vcr::replay("tests/cassettes/flurbo.json").await?;
+//!
+//! let openai = openai::Client::from_url(&api_key, &format!("{}/v1", vcr.url()));
+//! let answer = openai.agent(openai::GPT_4O).build().prompt("What is a flurbo?").await?;
+//! ```
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// Version of the format of the cassettes
+const CASSETTE_FORMAT_VERSION: u32 = 1;
+
+/// Headers of the requests which are not forwarded to the provider when recording (the
+/// responses are recorded uncompressed)
+const SKIPPED_HEADERS: [&str; 4] = ["host", "content-length", "connection", "accept-encoding"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum VcrError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    /// Json error (e.g.: malformed cassette)
+    #[error("Json error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    /// The cassette has an unsupported format version
+    #[error("Unsupported cassette format version: {0}")]
+    FormatVersionError(u32),
+}
+
+/// Recorded request and response (see [Cassette])
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Interaction {
+    /// Hash of the request (see [request_key])
+    pub key: String,
+    pub method: String,
+    pub path: String,
+    pub request_body: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub response_body: String,
+}
+
+/// Interactions recorded by a [Vcr], as stored in a cassette file
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Cassette {
+    pub format_version: u32,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self {
+            format_version: CASSETTE_FORMAT_VERSION,
+            interactions: vec![],
+        }
+    }
+}
+
+/// Key of a request in a cassette: the FNV-1a hash of its method, path and body, which is stable
+/// across platforms and releases.
+pub fn request_key(method: &str, path: &str, body: &str) -> String {
+    let hash = crate::hash::fnv1a(
+        [method, path, body]
+            .iter()
+            .flat_map(|part| part.bytes().chain([0])),
+    );
+    format!("{hash:016x}")
+}
+
+enum Mode {
+    /// Forward the requests to the upstream url, recording the interactions to the cassette file
+    Record {
+        upstream: String,
+        http_client: reqwest::Client,
+        cassette: Cassette,
+    },
+    /// Serve the interactions of the cassette, by request key, in order of recording
+    Replay {
+        interactions: HashMap<String, VecDeque<Interaction>>,
+    },
+}
+
+struct State {
+    path: PathBuf,
+    mode: Mode,
+    /// Keys of the requests which were not found in the cassette when replaying
+    misses: Vec<String>,
+}
+
+/// Local HTTP proxy recording or replaying the interactions of provider clients with a provider
+/// (see the [module](self) documentation). The proxy stops when the [Vcr] is dropped.
+pub struct Vcr {
+    url: String,
+    state: Arc<Mutex<State>>,
+    server: JoinHandle<()>,
+}
+
+impl Vcr {
+    /// Proxy forwarding the requests to `upstream` (e.g.: `https://api.openai.com`), recording
+    /// each interaction to the cassette file at `path` as soon as it completes. An existing
+    /// cassette is overwritten.
+    pub async fn record(path: impl Into<PathBuf>, upstream: &str) -> Result<Self, VcrError> {
+        let path = path.into();
+        let cassette = Cassette::default();
+        write_cassette(&path, &cassette)?;
+
+        Self::start(
+            path,
+            Mode::Record {
+                upstream: upstream.trim_end_matches('/').to_string(),
+                http_client: reqwest::Client::new(),
+                cassette,
+            },
+        )
+        .await
+    }
+
+    /// Proxy serving the interactions of the cassette file at `path`. Requests not found in the
+    /// cassette are answered with a 404 (see [Vcr::misses]).
+    pub async fn replay(path: impl Into<PathBuf>) -> Result<Self, VcrError> {
+        let path = path.into();
+        let cassette: Cassette = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+        if cassette.format_version != CASSETTE_FORMAT_VERSION {
+            return Err(VcrError::FormatVersionError(cassette.format_version));
+        }
+
+        let mut interactions: HashMap<String, VecDeque<Interaction>> = HashMap::new();
+        for interaction in cassette.interactions {
+            interactions
+                .entry(interaction.key.clone())
+                .or_default()
+                .push_back(interaction);
+        }
+        Self::start(path, Mode::Replay { interactions }).await
+    }
+
+    /// Url of the proxy, to be used as the base url of the provider clients (with the path
+    /// prefix of the provider, if any, e.g.: `/v1` for OpenAI)
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Keys of the requests which were not found in the cassette while replaying it
+    pub fn misses(&self) -> Vec<String> {
+        self.lock().misses.clone()
+    }
+
+    async fn start(path: PathBuf, mode: Mode) -> Result<Self, VcrError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State {
+            path,
+            mode,
+            misses: vec![],
+        }));
+
+        let server = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = handle(socket, state).await {
+                            tracing::warn!(target: "rig", "VCR connection failed: {err}");
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(Self { url, state, server })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Vcr {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// Request read from a client of the proxy
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// Read a request from `socket`, or `None` if the connection was closed before a full request
+async fn read_request(socket: &mut TcpStream) -> Result<Option<Request>, VcrError> {
+    let mut request = vec![];
+    let mut buffer = [0; 4096];
+    loop {
+        let read = socket.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buffer[..read]);
+
+        let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") else {
+            continue;
+        };
+        let head = String::from_utf8_lossy(&request[..end]).to_string();
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let (method, path) = (
+            request_line.next().unwrap_or_default().to_string(),
+            request_line.next().unwrap_or_default().to_string(),
+        );
+        let headers = lines
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_lowercase(), value.trim().to_string()))
+            })
+            .collect::<Vec<_>>();
+        let content_length = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .and_then(|(_, length)| length.parse::<usize>().ok())
+            .unwrap_or(0);
+
+        if request.len() >= end + 4 + content_length {
+            let body = &request[end + 4..end + 4 + content_length];
+            return Ok(Some(Request {
+                method,
+                path,
+                headers,
+                body: String::from_utf8_lossy(body).to_string(),
+            }));
+        }
+    }
+}
+
+/// Serve a request of a client of the proxy, recording or replaying its response
+async fn handle(mut socket: TcpStream, state: Arc<Mutex<State>>) -> Result<(), VcrError> {
+    let Some(request) = read_request(&mut socket).await? else {
+        return Ok(());
+    };
+    let key = request_key(&request.method, &request.path, &request.body);
+
+    // The lock is not held across the request to the provider
+    let route = {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        let route = match &mut state.mode {
+            Mode::Record {
+                upstream,
+                http_client,
+                ..
+            } => Ok((upstream.clone(), http_client.clone())),
+            Mode::Replay { interactions } => Err(interactions
+                .get_mut(&key)
+                .and_then(|interactions| interactions.pop_front())),
+        };
+        if matches!(route, Err(None)) {
+            state.misses.push(key.clone());
+        }
+        route
+    };
+
+    let (upstream, http_client) = match route {
+        Ok(upstream) => upstream,
+        Err(Some(interaction)) => {
+            return write_response(
+                &mut socket,
+                interaction.status,
+                interaction.content_type.as_deref(),
+                &interaction.response_body,
+            )
+            .await
+        }
+        Err(None) => {
+            let body = serde_json::json!({
+                "error": {
+                    "message": format!(
+                        "No interaction recorded for {} {} (request key {key})",
+                        request.method, request.path
+                    )
+                }
+            });
+            return write_response(
+                &mut socket,
+                404,
+                Some("application/json"),
+                &body.to_string(),
+            )
+            .await;
+        }
+    };
+
+    let method =
+        reqwest::Method::from_bytes(request.method.as_bytes()).unwrap_or(reqwest::Method::GET);
+    let mut builder = http_client
+        .request(method, format!("{upstream}{}", request.path))
+        .body(request.body.clone());
+    for (name, value) in &request.headers {
+        if !SKIPPED_HEADERS.contains(&name.as_str()) {
+            builder = builder.header(name, value);
+        }
+    }
+
+    let response = async {
+        let response = builder.send().await?;
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        Ok::<_, reqwest::Error>((status, content_type, response.text().await?))
+    };
+    let (status, content_type, body) = match response.await {
+        Ok(response) => response,
+        // Failed requests are not recorded
+        Err(err) => return write_response(&mut socket, 502, None, &err.to_string()).await,
+    };
+
+    {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        let State { path, mode, .. } = &mut *state;
+        let Mode::Record { cassette, .. } = mode else {
+            unreachable!("The mode of a VCR never changes")
+        };
+        cassette.interactions.push(Interaction {
+            key,
+            method: request.method,
+            path: request.path,
+            request_body: request.body,
+            status,
+            content_type: content_type.clone(),
+            response_body: body.clone(),
+        });
+        // Written with the lock held, so that concurrent interactions are written in order
+        write_cassette(path, cassette)?;
+    }
+
+    write_response(&mut socket, status, content_type.as_deref(), &body).await
+}
+
+async fn write_response(
+    socket: &mut TcpStream,
+    status: u16,
+    content_type: Option<&str>,
+    body: &str,
+) -> Result<(), VcrError> {
+    let content_type = content_type
+        .map(|content_type| format!("content-type: {content_type}\r\n"))
+        .unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 {status} VCR\r\n{content_type}content-length: {}\r\nconnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    socket.write_all(response.as_bytes()).await?;
+    Ok(socket.shutdown().await?)
+}
+
+fn write_cassette(path: &Path, cassette: &Cassette) -> Result<(), VcrError> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::write(path, serde_json::to_vec_pretty(cassette)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{request_key, Vcr};
+    use crate::{
+        completion::{CompletionModel, ModelChoice},
+        providers::openai,
+    };
+
+    const COMPLETION: &str = r#"{
+        "id": "chatcmpl-123",
+        "object": "chat.completion",
+        "created": 1728000000,
+        "model": "gpt-4o",
+        "system_fingerprint": null,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "A flurbo is a currency." },
+            "logprobs": null,
+            "finish_reason": "stop"
+        }],
+        "usage": null
+    }"#;
+
+    /// Provider answering every request with [COMPLETION], counting the requests
+    async fn provider() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                // The requests of the test fit in a single read
+                let mut buffer = [0; 8192];
+                let _ = socket.read(&mut buffer).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{COMPLETION}",
+                    COMPLETION.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    async fn complete(vcr: &Vcr, prompt: &str) -> Result<String, String> {
+        let model = openai::Client::from_url("sk-test", &format!("{}/v1", vcr.url()))
+            .completion_model(openai::GPT_4O);
+        match model.completion_request(prompt).send().await {
+            Ok(response) => match response.choice {
+                ModelChoice::Message(message) => Ok(message),
+                ModelChoice::ToolCall(name, _) => Err(name),
+            },
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_request_key() {
+        let key = request_key("POST", "/v1/chat/completions", "{}");
+        assert_eq!(key, request_key("POST", "/v1/chat/completions", "{}"));
+        assert_eq!(key.len(), 16);
+        assert_ne!(key, request_key("POST", "/v1/chat/completions", "{ }"));
+        assert_ne!(key, request_key("POST", "/v1/embeddings", "{}"));
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let (upstream, requests) = provider().await;
+        let dir = std::env::temp_dir().join(format!("rig-vcr-{}", std::process::id()));
+        let cassette = dir.join("flurbo.json");
+
+        // Recording forwards the requests to the provider
+        let vcr = Vcr::record(&cassette, &upstream).await.unwrap();
+        assert_eq!(
+            complete(&vcr, "What is a flurbo?").await.unwrap(),
+            "A flurbo is a currency."
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        drop(vcr);
+
+        // Replaying serves the recorded responses without reaching the provider
+        let vcr = Vcr::replay(&cassette).await.unwrap();
+        assert_eq!(
+            complete(&vcr, "What is a flurbo?").await.unwrap(),
+            "A flurbo is a currency."
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(vcr.misses().is_empty());
+
+        // Requests which were not recorded (or were already replayed) fail
+        assert!(complete(&vcr, "What is a glarb?").await.is_err());
+        assert!(complete(&vcr, "What is a flurbo?").await.is_err());
+        assert_eq!(vcr.misses().len(), 2);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,6 +2,7 @@ use crate::{
     agent::AgentBuilder,
     embeddings::{self},
     extractor::ExtractorBuilder,
+    providers::Interceptors,
     Embed,
 };
 use schemars::JsonSchema;
@@ -18,6 +19,7 @@ const XAI_BASE_URL: &str = "https://api.x.ai";
 pub struct Client {
     base_url: String,
     http_client: reqwest::Client,
+    interceptors: Interceptors,
 }
 
 impl Client {
@@ -44,9 +46,21 @@ impl Client {
                 })
                 .build()
                 .expect("xAI reqwest client should build"),
+            interceptors: Interceptors::default(),
         }
     }
 
+    /// Patch the raw JSON bodies of the requests and responses of all the models created from
+    /// the client with `interceptors` (see [Interceptors]), e.g.: to adapt to a change of the API
+    pub fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    pub(crate) fn interceptors(&self) -> &Interceptors {
+        &self.interceptors
+    }
+
     /// Create a new xAI client from the `XAI_API_KEY` environment variable.
     /// Panics if the environment variable is not set.
     pub fn from_env() -> Self {
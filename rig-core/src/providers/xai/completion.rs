@@ -6,6 +6,8 @@
 use crate::{
     completion::{self, CompletionError},
     json_utils,
+    providers::openai,
+    streaming::{self, StreamingResult},
 };
 
 use serde_json::json;
@@ -33,24 +35,12 @@ impl CompletionModel {
             model: model.to_string(),
         }
     }
-}
 
-impl completion::CompletionModel for CompletionModel {
-    type Response = CompletionResponse;
-
-    async fn completion(
+    fn create_completion_request(
         &self,
-        mut completion_request: completion::CompletionRequest,
-    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
-        let mut messages = if let Some(preamble) = &completion_request.preamble {
-            vec![completion::Message {
-                role: "system".into(),
-                content: preamble.clone(),
-            }]
-        } else {
-            vec![]
-        };
-        messages.append(&mut completion_request.chat_history);
+        completion_request: completion::CompletionRequest,
+    ) -> serde_json::Value {
+        let mut messages = completion_request.history_with_system_messages();
 
         let prompt_with_context = completion_request.prompt_with_context();
 
@@ -59,7 +49,7 @@ impl completion::CompletionModel for CompletionModel {
             content: prompt_with_context,
         });
 
-        let mut request = if completion_request.tools.is_empty() {
+        let request = if completion_request.tools.is_empty() {
             json!({
                 "model": self.model,
                 "messages": messages,
@@ -75,21 +65,72 @@ impl completion::CompletionModel for CompletionModel {
             })
         };
 
-        request = if let Some(params) = completion_request.additional_params {
+        if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
             request
-        };
+        }
+    }
+
+    /// Send the completion request and stream the response as
+    /// [StreamEvent](crate::streaming::StreamEvent)s. The xAI API streams OpenAI-compatible
+    /// server-sent events.
+    pub async fn stream(
+        &self,
+        completion_request: completion::CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        let request = json_utils::merge(
+            self.create_completion_request(completion_request),
+            json!({ "stream": true }),
+        );
+
+        let response = self
+            .client
+            .post("/v1/chat/completions")
+            .json(&self.client.interceptors().intercept_request(&request)?)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(openai::stream_events(response.bytes_stream()))
+        } else {
+            Err(CompletionError::ProviderError(response.text().await?))
+        }
+    }
+}
+
+impl streaming::StreamingCompletionModel for CompletionModel {
+    async fn stream(
+        &self,
+        request: completion::CompletionRequest,
+    ) -> Result<StreamingResult, CompletionError> {
+        CompletionModel::stream(self, request).await
+    }
+}
+
+impl completion::CompletionModel for CompletionModel {
+    type Response = CompletionResponse;
+
+    async fn completion(
+        &self,
+        completion_request: completion::CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let request = self.create_completion_request(completion_request);
 
         let response = self
             .client
             .post("/v1/chat/completions")
-            .json(&request)
+            .json(&self.client.interceptors().intercept_request(&request)?)
             .send()
             .await?;
 
         if response.status().is_success() {
-            match response.json::<ApiResponse<CompletionResponse>>().await? {
+            match self
+                .client
+                .interceptors()
+                .parse::<ApiResponse<CompletionResponse>, CompletionError>(response)
+                .await?
+            {
                 ApiResponse::Ok(completion) => completion.try_into(),
                 ApiResponse::Error(error) => Err(CompletionError::ProviderError(error.message())),
             }
@@ -78,15 +78,20 @@ impl embeddings::EmbeddingModel for EmbeddingModel {
         let response = self
             .client
             .post("/v1/embeddings")
-            .json(&json!({
+            .json(&self.client.interceptors().intercept_request(&json!({
                 "model": self.model,
                 "input": documents,
-            }))
+            }))?)
             .send()
             .await?;
 
         if response.status().is_success() {
-            match response.json::<ApiResponse<EmbeddingResponse>>().await? {
+            match self
+                .client
+                .interceptors()
+                .parse::<ApiResponse<EmbeddingResponse>, EmbeddingError>(response)
+                .await?
+            {
                 ApiResponse::Ok(response) => {
                     if response.data.len() != documents.len() {
                         return Err(EmbeddingError::ResponseError(
@@ -0,0 +1,745 @@
+//! This module provides the building blocks for streaming completions.
+//!
+//! Streaming completion responses are represented as a stream of [StreamEvent]s: the text of the
+//! response is received as a sequence of [StreamEvent::Delta]s, tool calls are emitted once fully
+//! received as [StreamEvent::ToolCall] and the stream ends with a [StreamEvent::Done] event
+//! holding the token usage of the request (if reported by the provider). Streaming agents (see
+//! [Agent::stream_prompt](crate::agent::Agent::stream_prompt)) also emit the progress of the
+//! tools they call as [StreamEvent::ToolProgress] events.
+//!
+//! Every streaming-capable provider (OpenAI, Cohere, xAI and Perplexity) implements the
+//! [StreamingCompletionModel] trait, returning the same [StreamingResult] stream, so that
+//! streaming code can be written once for any of them:
+//! ```rust
+//! use futures::StreamExt;
+//! use rig::streaming::{StreamEvent, StreamingCompletionModel};
+//!
+//! async fn print_answer<M: StreamingCompletionModel>(model: &M, prompt: &str) -> Result<(), CompletionError> {
+//!     let mut stream = model.stream(model.completion_request(prompt).build()).await?;
+//!     while let Some(event) = stream.next().await {
+//!         if let StreamEvent::Delta(text) = event? {
+//!             print!("{text}");
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Example
+//! ```rust
+//! use futures::StreamExt;
+//! use rig::{providers::openai, streaming::StreamEvent};
+//!
+//! let openai = openai::Client::from_env();
+//! let model = openai.completion_model(openai::GPT_4O);
+//!
+//! let request = model.completion_request("Tell me a joke").build();
+//! let mut stream = model.stream(request).await?;
+//!
+//! while let Some(event) = stream.next().await {
+//!     match event? {
+//!         StreamEvent::Delta(text) => print!("{text}"),
+//!         StreamEvent::ToolCall { name, arguments } => println!("Tool call: {name}({arguments})"),
+//!         StreamEvent::ToolProgress { tool, delta } => println!("[{tool}] {delta}"),
+//!         StreamEvent::Done { usage } => println!("\nUsage: {usage:?}"),
+//!     }
+//! }
+//! ```
+//!
+//! To forward the text of a response to a sink (e.g.: stdout, a file or a socket) while keeping
+//! the final response, use [StreamingCompletionModel::stream_to]:
+//! ```rust
+//! let response = model.stream_to(&mut tokio::io::stdout(), request).await?;
+//! ```
+//!
+//! # Debugging
+//! To see where a model goes off-rails mid-stream, streams can emit each delta as a
+//! `tracing::trace!` event (target `rig::streaming`) by wrapping them with [trace_deltas] or
+//! by setting [DeltaTracing] on the streaming models (e.g.: `openai::CompletionModel::trace_deltas`).
+//! It is off by default, and [DeltaTracing::Redacted] only logs the length of the deltas,
+//! never their content.
+//!
+//! # Stalled streams
+//! A stream which stops sending data without being closed would otherwise hang forever:
+//! [idle_timeout] aborts it with [CompletionError::StreamIdleTimeout] when no event is
+//! received for a given duration (e.g.: `openai::CompletionModel::stream_idle_timeout`).
+use std::{pin::Pin, time::Duration};
+
+use futures::{
+    future::{self, Either},
+    Stream, StreamExt,
+};
+use futures_timer::Delay;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    completion::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, ModelChoice, Usage,
+    },
+    tool::Source,
+};
+
+/// Event of a streaming completion response
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamEvent {
+    /// A chunk of the text of the response
+    Delta(String),
+    /// A tool call requested by the model, emitted once its arguments are fully received
+    ToolCall {
+        name: String,
+        arguments: serde_json::Value,
+    },
+    /// Progress of the call of the tool `tool` by a streaming agent (see
+    /// [ToolProgress](crate::tool::ToolProgress)), e.g.: a delta of the answer of a sub-agent
+    /// (see [AgentTool::streaming](crate::agent::AgentTool::streaming))
+    ToolProgress { tool: String, delta: String },
+    /// A source cited by the answer of a streaming agent, emitted once per source as soon as
+    /// the streamed text references its id (see
+    /// [Agent::stream_prompt_with_citations](crate::agent::Agent::stream_prompt_with_citations))
+    Citation(Source),
+    /// Terminal event of the stream, with the token usage of the request if available
+    Done { usage: Option<Usage> },
+}
+
+/// Boxed stream of [StreamEvent]s returned by streaming completion models
+pub type StreamingResult = Pin<Box<dyn Stream<Item = Result<StreamEvent, CompletionError>> + Send>>;
+
+/// Trait defining a completion model which can stream its responses.
+pub trait StreamingCompletionModel: CompletionModel {
+    /// Send the completion request and stream the response as [StreamEvent]s.
+    fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl std::future::Future<Output = Result<StreamingResult, CompletionError>> + Send;
+
+    /// Stream the response to the completion request into `writer`, writing each
+    /// [StreamEvent::Delta] to it as soon as it is received, and return the final response
+    /// once the stream ends, with the token usage of the request (if reported) as raw
+    /// response. As for non-streaming completions, the choice is the first tool call of the
+    /// response if there is one, its full text otherwise.
+    ///
+    /// If the stream fails midway, what was already written is flushed before the error is
+    /// returned. Errors of the writer are returned as [CompletionError::WriteError].
+    fn stream_to<W>(
+        &self,
+        writer: &mut W,
+        request: CompletionRequest,
+    ) -> impl std::future::Future<Output = Result<CompletionResponse<Option<Usage>>, CompletionError>>
+           + Send
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        async move {
+            let mut stream = self.stream(request).await?;
+            let (mut text, mut tool_call, mut usage) = (String::new(), None, None);
+
+            while let Some(event) = stream.next().await {
+                match event {
+                    Ok(StreamEvent::Delta(delta)) => {
+                        writer
+                            .write_all(delta.as_bytes())
+                            .await
+                            .map_err(CompletionError::WriteError)?;
+                        text.push_str(&delta);
+                    }
+                    Ok(StreamEvent::ToolCall { name, arguments }) => {
+                        tool_call.get_or_insert(ModelChoice::ToolCall(name, arguments));
+                    }
+                    Ok(StreamEvent::ToolProgress { .. } | StreamEvent::Citation(_)) => (),
+                    Ok(StreamEvent::Done { usage: done }) => usage = done,
+                    Err(err) => {
+                        if let Err(flush_err) = writer.flush().await {
+                            tracing::warn!(
+                                target: "rig::streaming",
+                                "Failed to flush the streamed response: {flush_err}"
+                            );
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+            writer.flush().await.map_err(CompletionError::WriteError)?;
+
+            Ok(CompletionResponse {
+                choice: tool_call.unwrap_or(ModelChoice::Message(text)),
+                raw_response: usage,
+            })
+        }
+    }
+}
+
+/// Debug mode logging each [StreamEvent::Delta] of a stream as a `tracing::trace!` event
+/// (see [trace_deltas]).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeltaTracing {
+    /// Deltas are not logged
+    #[default]
+    Off,
+    /// Only the index and length (in characters) of the deltas are logged, so that the content
+    /// of the responses never ends up in the logs
+    Redacted,
+    /// The index, length and content of the deltas are logged
+    Full,
+}
+
+/// Wrap `stream` so that each of its [StreamEvent::Delta]s is logged as a `tracing::trace!`
+/// event with target `rig::streaming`, according to `mode`. The events of the stream are
+/// passed through unchanged.
+pub fn trace_deltas(stream: StreamingResult, mode: DeltaTracing) -> StreamingResult {
+    if mode == DeltaTracing::Off {
+        return stream;
+    }
+
+    let mut index = 0usize;
+    Box::pin(stream.inspect(move |event| {
+        if let Ok(StreamEvent::Delta(text)) = event {
+            let len = text.chars().count();
+            match mode {
+                DeltaTracing::Full => {
+                    tracing::trace!(target: "rig::streaming", index, len, delta = %text, "Stream delta")
+                }
+                _ => tracing::trace!(target: "rig::streaming", index, len, "Stream delta"),
+            }
+            index += 1;
+        }
+    }))
+}
+
+/// Wrap `stream` so that it fails with [CompletionError::StreamIdleTimeout] (and ends) if no
+/// event is received within `timeout` of the previous one (or of the start of the stream).
+/// The timeout is measured between events, independently of the total duration of the stream.
+pub fn idle_timeout(stream: StreamingResult, timeout: Duration) -> StreamingResult {
+    Box::pin(futures::stream::unfold(
+        Some(stream),
+        move |stream| async move {
+            let mut stream = stream?;
+            match future::select(stream.next(), Delay::new(timeout)).await {
+                Either::Left((Some(event), _)) => Some((event, Some(stream))),
+                Either::Left((None, _)) => None,
+                Either::Right(_) => {
+                    tracing::warn!(target: "rig::streaming", "Stream idle for {timeout:?}, aborting");
+                    Some((Err(CompletionError::StreamIdleTimeout(timeout)), None))
+                }
+            }
+        },
+    ))
+}
+
+/// Decode a stream of bytes into its lines (with the line terminator, `\n` or `\r\n`,
+/// removed). Lines are only decoded once complete, so chunks may split lines (and multi-byte
+/// characters) arbitrarily. The stream ends after the first error of the underlying stream.
+pub(crate) fn decode_lines<S, B, E>(
+    stream: S,
+) -> impl Stream<Item = Result<String, CompletionError>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]> + Send,
+    E: Into<CompletionError> + Send,
+{
+    struct State<S> {
+        stream: Pin<Box<S>>,
+        buffer: Vec<u8>,
+        ended: bool,
+    }
+
+    futures::stream::unfold(
+        State {
+            stream: Box::pin(stream),
+            buffer: vec![],
+            ended: false,
+        },
+        |mut state| async move {
+            loop {
+                if let Some(pos) = state.buffer.iter().position(|byte| *byte == b'\n') {
+                    let mut line = state.buffer.drain(..=pos).collect::<Vec<_>>();
+                    line.pop();
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+
+                    let line = String::from_utf8(line).map_err(|err| {
+                        CompletionError::ResponseError(format!(
+                            "Invalid UTF-8 in event stream: {err}"
+                        ))
+                    });
+                    return Some((line, state));
+                }
+
+                if state.ended {
+                    if !state.buffer.is_empty() {
+                        // Terminate the last line of the stream
+                        state.buffer.push(b'\n');
+                        continue;
+                    }
+                    return None;
+                }
+
+                match state.stream.next().await {
+                    Some(Ok(chunk)) => state.buffer.extend_from_slice(chunk.as_ref()),
+                    Some(Err(err)) => {
+                        state.ended = true;
+                        state.buffer.clear();
+                        return Some((Err(err.into()), state));
+                    }
+                    None => state.ended = true,
+                }
+            }
+        },
+    )
+}
+
+/// Decode a stream of bytes formatted as server-sent events into the `data` payloads of
+/// the events. Multi-line payloads are joined with `\n`, while other fields and comments
+/// are ignored. Lines are decoded with [decode_lines], so chunks may split lines (and
+/// multi-byte characters) arbitrarily.
+pub(crate) fn decode_sse<S, B, E>(
+    stream: S,
+) -> impl Stream<Item = Result<String, CompletionError>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]> + Send,
+    E: Into<CompletionError> + Send,
+{
+    struct State<L> {
+        lines: Pin<Box<L>>,
+        data: Vec<String>,
+    }
+
+    futures::stream::unfold(
+        State {
+            lines: Box::pin(decode_lines(stream)),
+            data: vec![],
+        },
+        |mut state| async move {
+            loop {
+                match state.lines.next().await {
+                    // Blank line: dispatch the event
+                    Some(Ok(line)) if line.is_empty() => {
+                        if !state.data.is_empty() {
+                            let data = state.data.join("\n");
+                            state.data.clear();
+                            return Some((Ok(data), state));
+                        }
+                    }
+                    Some(Ok(line)) => {
+                        if let Some(data) = line.strip_prefix("data:") {
+                            state
+                                .data
+                                .push(data.strip_prefix(' ').unwrap_or(data).to_string());
+                        }
+                    }
+                    Some(Err(err)) => {
+                        state.data.clear();
+                        return Some((Err(err), state));
+                    }
+                    None => {
+                        if !state.data.is_empty() {
+                            let data = state.data.join("\n");
+                            state.data.clear();
+                            return Some((Ok(data), state));
+                        }
+                        return None;
+                    }
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::{stream, StreamExt};
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+    use super::*;
+    use crate::completion::{self, ModelChoice};
+
+    /// Provider streaming the words of the prompt
+    #[derive(Clone)]
+    struct Echo;
+
+    /// Provider streaming a tool call followed by a fixed answer, with its usage
+    #[derive(Clone)]
+    struct Scripted;
+
+    impl CompletionModel for Echo {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<completion::CompletionResponse<()>, CompletionError> {
+            Ok(completion::CompletionResponse {
+                choice: ModelChoice::Message(request.prompt),
+                raw_response: (),
+            })
+        }
+    }
+
+    impl StreamingCompletionModel for Echo {
+        async fn stream(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<StreamingResult, CompletionError> {
+            let events = request
+                .prompt
+                .split_inclusive(' ')
+                .map(|word| Ok(StreamEvent::Delta(word.to_string())))
+                .chain(std::iter::once(Ok(StreamEvent::Done { usage: None })))
+                .collect::<Vec<_>>();
+            Ok(Box::pin(stream::iter(events)))
+        }
+    }
+
+    impl CompletionModel for Scripted {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<completion::CompletionResponse<()>, CompletionError> {
+            Ok(completion::CompletionResponse {
+                choice: ModelChoice::Message("Flurbos are a currency.".to_string()),
+                raw_response: (),
+            })
+        }
+    }
+
+    impl StreamingCompletionModel for Scripted {
+        async fn stream(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<StreamingResult, CompletionError> {
+            Ok(Box::pin(stream::iter(vec![
+                Ok(StreamEvent::ToolCall {
+                    name: "lookup".to_string(),
+                    arguments: serde_json::json!({ "word": "flurbo" }),
+                }),
+                Ok(StreamEvent::Delta("Flurbos are".to_string())),
+                Ok(StreamEvent::Delta(" a currency.".to_string())),
+                Ok(StreamEvent::Done {
+                    usage: Some(Usage {
+                        prompt_tokens: 5,
+                        completion_tokens: 4,
+                        total_tokens: 9,
+                        ..Default::default()
+                    }),
+                }),
+            ])))
+        }
+    }
+
+    /// Text, tool calls and usage of the streamed response of any provider to `prompt`
+    async fn collect<M: StreamingCompletionModel>(
+        model: &M,
+        prompt: &str,
+    ) -> (String, Vec<String>, Option<Usage>) {
+        let mut stream = model
+            .stream(model.completion_request(prompt).build())
+            .await
+            .unwrap();
+
+        let (mut text, mut tools, mut usage) = (String::new(), vec![], None);
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                StreamEvent::Delta(delta) => text.push_str(&delta),
+                StreamEvent::ToolCall { name, .. } => tools.push(name),
+                StreamEvent::ToolProgress { .. } | StreamEvent::Citation(_) => (),
+                StreamEvent::Done { usage: done } => usage = done,
+            }
+        }
+        (text, tools, usage)
+    }
+
+    #[tokio::test]
+    async fn test_streaming_providers() {
+        assert_eq!(
+            collect(&Echo, "What is a flurbo?").await,
+            ("What is a flurbo?".to_string(), vec![], None)
+        );
+
+        let (text, tools, usage) = collect(&Scripted, "What is a flurbo?").await;
+        assert_eq!(text, "Flurbos are a currency.");
+        assert_eq!(tools, vec!["lookup"]);
+        assert_eq!(usage.map(|usage| usage.total_tokens), Some(9));
+    }
+
+    /// Provider streaming the words of the prompt, then failing before the end of the response
+    #[derive(Clone)]
+    struct Interrupted;
+
+    impl CompletionModel for Interrupted {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<completion::CompletionResponse<()>, CompletionError> {
+            Echo.completion(request).await
+        }
+    }
+
+    impl StreamingCompletionModel for Interrupted {
+        async fn stream(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<StreamingResult, CompletionError> {
+            let events = Echo.stream(request).await?.filter(|event| {
+                futures::future::ready(!matches!(event, Ok(StreamEvent::Done { .. })))
+            });
+            Ok(Box::pin(events.chain(stream::once(async {
+                Err(CompletionError::ProviderError(
+                    "Connection reset".to_string(),
+                ))
+            }))))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_to() {
+        let mut buffer = Vec::new();
+        let response = Echo
+            .stream_to(
+                &mut buffer,
+                Echo.completion_request("What is a flurbo?").build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "What is a flurbo?");
+        assert_eq!(
+            response.choice,
+            ModelChoice::Message("What is a flurbo?".to_string())
+        );
+        assert_eq!(response.raw_response, None);
+
+        let mut buffer = Vec::new();
+        let response = Scripted
+            .stream_to(&mut buffer, Scripted.completion_request("").build())
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "Flurbos are a currency."
+        );
+        assert!(matches!(response.choice, ModelChoice::ToolCall(name, _) if name == "lookup"));
+        assert_eq!(
+            response.raw_response.map(|usage| usage.total_tokens),
+            Some(9)
+        );
+
+        // The deltas received before the error are flushed through the buffered writer
+        let mut writer = tokio::io::BufWriter::new(Vec::new());
+        let result = Interrupted
+            .stream_to(
+                &mut writer,
+                Interrupted.completion_request("Flurbos are").build(),
+            )
+            .await;
+        assert!(matches!(result, Err(CompletionError::ProviderError(_))));
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "Flurbos are"
+        );
+    }
+
+    /// Layer capturing the fields of the events logged with target `rig::streaming`
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<Vec<(String, String)>>>>);
+
+    impl<S: tracing::Subscriber> Layer<S> for Capture {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            struct Visitor(Vec<(String, String)>);
+
+            impl tracing::field::Visit for Visitor {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    self.0
+                        .push((field.name().to_string(), format!("{value:?}")));
+                }
+            }
+
+            if event.metadata().target() == "rig::streaming"
+                && *event.metadata().level() == tracing::Level::TRACE
+            {
+                let mut visitor = Visitor(vec![]);
+                event.record(&mut visitor);
+                self.0.lock().unwrap().push(visitor.0);
+            }
+        }
+    }
+
+    fn events() -> StreamingResult {
+        Box::pin(stream::iter(vec![
+            Ok(StreamEvent::Delta("Flurbos".to_string())),
+            Ok(StreamEvent::Delta(" are".to_string())),
+            Ok(StreamEvent::Done { usage: None }),
+        ]))
+    }
+
+    async fn capture(mode: DeltaTracing) -> Vec<Vec<(String, String)>> {
+        let capture = Capture::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+        let events = trace_deltas(events(), mode).collect::<Vec<_>>().await;
+        assert_eq!(events.len(), 3);
+
+        let captured = capture.0.lock().unwrap().clone();
+        captured
+    }
+
+    fn field(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_trace_deltas() {
+        assert_eq!(
+            capture(DeltaTracing::Full).await,
+            vec![
+                vec![
+                    field("message", "Stream delta"),
+                    field("index", "0"),
+                    field("len", "7"),
+                    field("delta", "Flurbos"),
+                ],
+                vec![
+                    field("message", "Stream delta"),
+                    field("index", "1"),
+                    field("len", "4"),
+                    field("delta", " are"),
+                ],
+            ]
+        );
+
+        assert_eq!(
+            capture(DeltaTracing::Redacted).await,
+            vec![
+                vec![
+                    field("message", "Stream delta"),
+                    field("index", "0"),
+                    field("len", "7"),
+                ],
+                vec![
+                    field("message", "Stream delta"),
+                    field("index", "1"),
+                    field("len", "4"),
+                ],
+            ]
+        );
+
+        assert!(capture(DeltaTracing::Off).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        // Stream sending a delta, then stalling without being closed
+        let stalled: StreamingResult = Box::pin(
+            stream::iter(vec![Ok(StreamEvent::Delta("Flurbos".to_string()))])
+                .chain(stream::pending()),
+        );
+
+        let stalled = idle_timeout(stalled, Duration::from_millis(50))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(stalled.len(), 2);
+        assert_eq!(
+            stalled[0].as_ref().unwrap(),
+            &StreamEvent::Delta("Flurbos".to_string())
+        );
+        assert!(matches!(
+            stalled[1],
+            Err(CompletionError::StreamIdleTimeout(timeout)) if timeout == Duration::from_millis(50)
+        ));
+
+        // Streams sending events within the timeout are passed through unchanged
+        let events = idle_timeout(events(), Duration::from_millis(50))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn test_decode_sse() {
+        let chunks = vec![
+            Ok::<_, CompletionError>(": comment\ndata: {\"a\"".as_bytes().to_vec()),
+            Ok(": 1}\n\nevent: message\r\ndata: line 1\r\n"
+                .as_bytes()
+                .to_vec()),
+            Ok("data: line 2\r\n\r\ndata: [DONE]".as_bytes().to_vec()),
+        ];
+
+        let events = decode_sse(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(events, vec!["{\"a\": 1}", "line 1\nline 2", "[DONE]"]);
+    }
+
+    #[tokio::test]
+    async fn test_decode_sse_error() {
+        let chunks = vec![
+            Ok("data: first\n\ndata: incomplete".as_bytes().to_vec()),
+            Err(CompletionError::ProviderError(
+                "connection reset".to_string(),
+            )),
+            Ok("data: never reached\n\n".as_bytes().to_vec()),
+        ];
+
+        let events = decode_sse(stream::iter(chunks)).collect::<Vec<_>>().await;
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].as_ref().unwrap(), "first");
+        assert!(matches!(events[1], Err(CompletionError::ProviderError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_decode_sse_split_character() {
+        // The 4 bytes of the emoji are split across two frames
+        let event = "data: {\"delta\": \"Hi 🦀!\"}\n\n".as_bytes();
+        let split = event.iter().position(|byte| *byte == 0xF0).unwrap() + 2;
+        let chunks = vec![
+            Ok::<_, CompletionError>(event[..split].to_vec()),
+            Ok(event[split..].to_vec()),
+        ];
+
+        let events = decode_sse(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let delta: serde_json::Value = serde_json::from_str(&events[0]).unwrap();
+
+        assert_eq!(delta["delta"], "Hi 🦀!");
+        assert!(!events[0].contains(char::REPLACEMENT_CHARACTER));
+    }
+
+    #[tokio::test]
+    async fn test_decode_lines() {
+        let chunks = vec![
+            Ok::<_, CompletionError>("{\"a\": 1}\r\n{\"b\"".as_bytes().to_vec()),
+            Ok(": 2}\n\n".as_bytes().to_vec()),
+            Ok("{\"c\": 3}".as_bytes().to_vec()),
+        ];
+
+        let lines = decode_lines(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(lines, vec!["{\"a\": 1}", "{\"b\": 2}", "", "{\"c\": 3}"]);
+    }
+}
@@ -8,15 +8,39 @@
 //!
 //! The [ToolSet] struct is a collection of tools that can be used by an [Agent](crate::agent::Agent)
 //! and optionally RAGged.
+//!
+//! With the `derive` feature, the `#[rig::tools]` attribute macro turns the methods of an impl
+//! block tagged with `#[tool]` into tools, collected in a [ToolSet] by the generated `toolset`
+//! method. The doc comment of each method becomes the description of its tool.
+//!
+//! Tools can illustrate their usage with [ToolExample]s (see [Tool::examples]), which are
+//! appended to the description of their definition.
+//!
+//! Several tool calls can be executed together with [ToolSet::call_all], which runs
+//! independent calls concurrently and sequences the calls of tools depending on the output of
+//! other tools (see [Tool::depends_on]).
+//!
+//! Tools can attach the [Source]s their results are based on (see [Tool::sources]), which
+//! [Agents](crate::agent::Agent) surface as citations of their answers (see
+//! [Agent::prompt_with_citations](crate::agent::Agent::prompt_with_citations)).
+//!
+//! Tools can report the progress of their calls (see [Tool::call_with_progress]), which
+//! streaming [Agents](crate::agent::Agent) forward as
+//! [StreamEvent::ToolProgress](crate::streaming::StreamEvent::ToolProgress) events (see
+//! [Agent::stream_prompt](crate::agent::Agent::stream_prompt)).
+//!
+//! Providers restricting the names of tools (e.g.: no dots) send the tools under sanitized
+//! names, mapped back to the original names when resolving the tool calls (see [ToolNameMap]).
 
-use std::{collections::HashMap, pin::Pin};
+use std::{collections::HashMap, pin::Pin, sync::Arc};
 
-use futures::Future;
+use futures::{future, Future};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     completion::{self, ToolDefinition},
     embeddings::{embed::EmbedError, tool::ToolSchema},
+    json_utils,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -27,6 +51,288 @@ pub enum ToolError {
 
     #[error("JsonError: {0}")]
     JsonError(#[from] serde_json::Error),
+
+    /// The tool needs input from the user (e.g.: to confirm a destructive action) before it
+    /// can proceed. An [Agent](crate::agent::Agent) pauses its tool loop with a
+    /// [PromptError::NeedsInput](crate::completion::PromptError::NeedsInput) error, holding the
+    /// state to resume it once the input is provided (see [Agent::resume](crate::agent::Agent::resume)).
+    ///
+    /// Tools request input by returning this variant from [Tool::call] (i.e.: with
+    /// `type Error = ToolError`).
+    #[error("NeedsInput: {0}")]
+    NeedsInput(String),
+}
+
+/// Sink of the progress of a tool call (see [Tool::call_with_progress]), e.g.: forwarded as
+/// [StreamEvent::ToolProgress](crate::streaming::StreamEvent::ToolProgress) events by
+/// [Agent::stream_prompt](crate::agent::Agent::stream_prompt). The default sink ignores the
+/// progress.
+#[derive(Clone, Default)]
+pub struct ToolProgress(Option<Arc<dyn Fn(String) + Send + Sync>>);
+
+impl ToolProgress {
+    /// Sink calling `report` with each progress update
+    pub fn new(report: impl Fn(String) + Send + Sync + 'static) -> Self {
+        Self(Some(Arc::new(report)))
+    }
+
+    /// Report a progress update (e.g.: a delta of the answer of a sub-agent)
+    pub fn send(&self, progress: impl Into<String>) {
+        if let Some(report) = &self.0 {
+            report(progress.into())
+        }
+    }
+
+    /// Whether the progress is ignored, e.g.: for tools to skip producing it
+    pub fn is_ignored(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+impl std::fmt::Debug for ToolProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ToolProgress")
+            .field(&if self.is_ignored() {
+                "ignored"
+            } else {
+                "reported"
+            })
+            .finish()
+    }
+}
+
+/// Restrictions of a provider on the names of tools (e.g.: no dots, limited length), see
+/// [ToolNameMap].
+#[derive(Clone, Copy, Debug)]
+pub struct ToolNameRules {
+    /// Maximum length (in characters) of a name
+    pub max_len: usize,
+    /// Whether a character is allowed in a name
+    pub allowed: fn(char) -> bool,
+}
+
+impl ToolNameRules {
+    /// Names matching `^[a-zA-Z0-9_-]{1,64}$` (e.g.: OpenAI and Anthropic)
+    pub const ALPHANUMERIC: Self = Self {
+        max_len: 64,
+        allowed: |c| c.is_ascii_alphanumeric() || c == '_' || c == '-',
+    };
+
+    /// No restriction on the names
+    pub const ANY: Self = Self {
+        max_len: usize::MAX,
+        allowed: |_| true,
+    };
+
+    /// Whether `name` is a valid name
+    pub fn is_valid(&self, name: &str) -> bool {
+        !name.is_empty() && name.chars().count() <= self.max_len && name.chars().all(self.allowed)
+    }
+
+    /// `name` with its invalid characters replaced by `_` and truncated to `max_len`
+    fn sanitize(&self, name: &str, max_len: usize) -> String {
+        let name = name
+            .chars()
+            .map(|c| if (self.allowed)(c) { c } else { '_' })
+            .take(max_len)
+            .collect::<String>();
+        if name.is_empty() {
+            "_".to_string()
+        } else {
+            name
+        }
+    }
+}
+
+impl Default for ToolNameRules {
+    fn default() -> Self {
+        Self::ALPHANUMERIC
+    }
+}
+
+/// Reversible mapping of the names of tools (e.g.: namespaced names such as `math.add`) to
+/// names valid for a provider (e.g.: `math_add`), so that the tools are sent under their
+/// provider names and the tool calls of the model are resolved to the original names.
+///
+/// Valid names are kept as is. Invalid names have their invalid characters replaced by `_` and
+/// are truncated, names colliding with another tool after sanitization get a numeric suffix
+/// (e.g.: `math_add_2`).
+///
+/// # Example
+/// ```rust
+/// use rig::tool::{ToolNameMap, ToolNameRules};
+///
+/// let names = ToolNameMap::new(["math.add", "math_add"], ToolNameRules::ALPHANUMERIC);
+/// assert_eq!(names.provider_name("math.add"), "math_add_2");
+/// assert_eq!(names.original_name("math_add_2"), "math.add");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ToolNameMap {
+    /// Provider names by original name, for the renamed tools only
+    provider: HashMap<String, String>,
+    /// Original names by provider name, for the renamed tools only
+    original: HashMap<String, String>,
+}
+
+impl ToolNameMap {
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>, rules: ToolNameRules) -> Self {
+        let names = names.into_iter().collect::<Vec<_>>();
+
+        // Valid names are reserved first so that renamed tools never shadow them
+        let mut taken = names
+            .iter()
+            .filter(|name| rules.is_valid(name))
+            .map(|name| name.to_string())
+            .collect::<std::collections::HashSet<_>>();
+
+        let mut map = Self::default();
+        for name in names {
+            if rules.is_valid(name) || map.provider.contains_key(name) {
+                continue;
+            }
+
+            let mut provider_name = rules.sanitize(name, rules.max_len);
+            let mut n = 2;
+            while taken.contains(&provider_name) {
+                let suffix = format!("_{n}");
+                let max_len = rules.max_len.saturating_sub(suffix.len()).max(1);
+                provider_name = rules.sanitize(name, max_len) + &suffix;
+                n += 1;
+            }
+
+            taken.insert(provider_name.clone());
+            map.original.insert(provider_name.clone(), name.to_string());
+            map.provider.insert(name.to_string(), provider_name);
+        }
+        map
+    }
+
+    /// Mapping of the names of `tools`
+    pub fn of(tools: &[ToolDefinition], rules: ToolNameRules) -> Self {
+        Self::new(tools.iter().map(|tool| tool.name.as_str()), rules)
+    }
+
+    /// Name under which the tool `name` is sent to the provider
+    pub fn provider_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.provider.get(name).map_or(name, String::as_str)
+    }
+
+    /// Original name of the tool sent to the provider as `name`
+    pub fn original_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.original.get(name).map_or(name, String::as_str)
+    }
+
+    /// `tools` renamed to their provider names
+    pub fn rename(&self, tools: Vec<ToolDefinition>) -> Vec<ToolDefinition> {
+        tools
+            .into_iter()
+            .map(|tool| ToolDefinition {
+                name: self.provider_name(&tool.name).to_string(),
+                ..tool
+            })
+            .collect()
+    }
+
+    /// Resolve the tool call of `choice` (if any) to the original name of its tool
+    pub fn resolve(&self, choice: completion::ModelChoice) -> completion::ModelChoice {
+        match choice {
+            completion::ModelChoice::ToolCall(name, args) => {
+                completion::ModelChoice::ToolCall(self.original_name(&name).to_string(), args)
+            }
+            choice => choice,
+        }
+    }
+}
+
+/// Maximum number of examples included in the definition of a tool
+pub const MAX_TOOL_EXAMPLES: usize = 3;
+
+/// Maximum length (in characters) of a rendered example, longer examples are left out of the
+/// definition of their tool
+pub const MAX_TOOL_EXAMPLE_LEN: usize = 500;
+
+/// Example invocation of a tool: the arguments of the call and the output of the tool.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolExample {
+    pub input: serde_json::Value,
+    pub output: serde_json::Value,
+}
+
+impl ToolExample {
+    pub fn new(input: serde_json::Value, output: serde_json::Value) -> Self {
+        Self { input, output }
+    }
+}
+
+/// Source a tool result is based on (e.g.: a search result or a retrieved document), cited by
+/// the answers of the [Agents](crate::agent::Agent) calling the tool.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    /// Identifier of the source, used to deduplicate citations
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Excerpt of the source relevant to the tool result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+impl Source {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            title: None,
+            url: None,
+            snippet: None,
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn snippet(mut self, snippet: impl Into<String>) -> Self {
+        self.snippet = Some(snippet.into());
+        self
+    }
+}
+
+/// Append the examples of a tool to the description of its definition. At most
+///  [MAX_TOOL_EXAMPLES] examples are included, skipping those longer than
+///  [MAX_TOOL_EXAMPLE_LEN] characters.
+fn with_examples(mut definition: ToolDefinition, examples: Vec<ToolExample>) -> ToolDefinition {
+    let examples = examples
+        .into_iter()
+        .map(|example| format!("- Input: {}\n  Output: {}", example.input, example.output))
+        .filter(|example| {
+            let fits = example.chars().count() <= MAX_TOOL_EXAMPLE_LEN;
+            if !fits {
+                tracing::warn!(target: "rig",
+                    "Example of tool {} exceeds {MAX_TOOL_EXAMPLE_LEN} characters, skipping it",
+                    definition.name
+                );
+            }
+            fits
+        })
+        .take(MAX_TOOL_EXAMPLES)
+        .collect::<Vec<_>>();
+
+    if !examples.is_empty() {
+        definition.description = format!(
+            "{}\n\nExamples:\n{}",
+            definition.description,
+            examples.join("\n")
+        );
+    }
+    definition
 }
 
 /// Trait that represents a simple LLM tool
@@ -104,6 +410,27 @@ pub trait Tool: Sized + Send + Sync {
     /// tailor the definition to the specific use case.
     fn definition(&self, _prompt: String) -> impl Future<Output = ToolDefinition> + Send + Sync;
 
+    /// A method returning example invocations of the tool, appended to the description of its
+    /// definition to help models call it correctly (see [MAX_TOOL_EXAMPLES] and
+    /// [MAX_TOOL_EXAMPLE_LEN]). Tools have no examples by default.
+    fn examples(&self) -> Vec<ToolExample> {
+        vec![]
+    }
+
+    /// A method returning the names of the tools whose calls must complete before the calls of
+    /// this tool, when several tool calls are executed together (see [ToolSet::call_all]).
+    /// Tools have no dependencies by default.
+    fn depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// A method returning the sources the output of a call of the tool is based on, cited by
+    /// the answers of the agents calling the tool (see [Source]). Tools have no sources by
+    /// default.
+    fn sources(&self, _output: &Self::Output) -> Vec<Source> {
+        vec![]
+    }
+
     /// The tool execution method.
     /// Both the arguments and return value are a String since these values are meant to
     /// be the output and input of LLM models (respectively)
@@ -111,6 +438,17 @@ pub trait Tool: Sized + Send + Sync {
         &self,
         args: Self::Args,
     ) -> impl Future<Output = Result<Self::Output, Self::Error>> + Send + Sync;
+
+    /// Same as [Tool::call], reporting the progress of the call to `progress` (e.g.: the
+    /// stream of a sub-agent, see [AgentTool::streaming](crate::agent::AgentTool::streaming)).
+    /// Tools do not report progress by default.
+    fn call_with_progress(
+        &self,
+        args: Self::Args,
+        _progress: ToolProgress,
+    ) -> impl Future<Output = Result<Self::Output, Self::Error>> + Send {
+        self.call(args)
+    }
 }
 
 /// Trait that represents an LLM tool that can be stored in a vector store and RAGged
@@ -149,10 +487,63 @@ pub trait ToolDyn: Send + Sync {
         prompt: String,
     ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + Sync + '_>>;
 
+    fn examples(&self) -> Vec<ToolExample> {
+        vec![]
+    }
+
+    fn depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+
     fn call(
         &self,
         args: String,
     ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + Sync + '_>>;
+
+    /// Same as [ToolDyn::call] but also returns the sources of the result (see [Tool::sources])
+    fn call_with_sources(
+        &self,
+        args: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(String, Vec<Source>), ToolError>> + Send + '_>> {
+        Box::pin(async move { Ok((self.call(args).await?, vec![])) })
+    }
+
+    /// Same as [ToolDyn::call_with_sources], reporting the progress of the call to `progress`
+    /// (see [Tool::call_with_progress])
+    fn call_with_progress(
+        &self,
+        args: String,
+        _progress: ToolProgress,
+    ) -> Pin<Box<dyn Future<Output = Result<(String, Vec<Source>), ToolError>> + Send + '_>> {
+        self.call_with_sources(args)
+    }
+}
+
+/// Wrapper making a `Send` future `Sync`, for the futures of [Tool::call] and [ToolDyn::call]
+/// built on futures which are only `Send` (e.g.: completion requests). The future is only ever
+/// accessed mutably (when polled), so the mutex is never locked.
+pub(crate) struct SyncFuture<F>(std::sync::Mutex<Pin<Box<F>>>);
+
+impl<F: Future + Send> SyncFuture<F> {
+    pub(crate) fn new(future: F) -> Self {
+        Self(std::sync::Mutex::new(Box::pin(future)))
+    }
+}
+
+impl<F: Future> Future for SyncFuture<F> {
+    type Output = F::Output;
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        self.get_mut()
+            .0
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .as_mut()
+            .poll(cx)
+    }
 }
 
 impl<T: Tool> ToolDyn for T {
@@ -167,17 +558,58 @@ impl<T: Tool> ToolDyn for T {
         Box::pin(<Self as Tool>::definition(self, prompt))
     }
 
+    fn examples(&self) -> Vec<ToolExample> {
+        <Self as Tool>::examples(self)
+    }
+
+    fn depends_on(&self) -> Vec<String> {
+        <Self as Tool>::depends_on(self)
+    }
+
     fn call(
         &self,
         args: String,
     ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + Sync + '_>> {
+        Box::pin(SyncFuture::new(async move {
+            <Self as ToolDyn>::call_with_sources(self, args)
+                .await
+                .map(|(output, _)| output)
+        }))
+    }
+
+    fn call_with_sources(
+        &self,
+        args: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(String, Vec<Source>), ToolError>> + Send + '_>> {
+        <Self as ToolDyn>::call_with_progress(self, args, ToolProgress::default())
+    }
+
+    fn call_with_progress(
+        &self,
+        args: String,
+        progress: ToolProgress,
+    ) -> Pin<Box<dyn Future<Output = Result<(String, Vec<Source>), ToolError>> + Send + '_>> {
         Box::pin(async move {
             match serde_json::from_str(&args) {
-                Ok(args) => <Self as Tool>::call(self, args)
+                Ok(args) => <Self as Tool>::call_with_progress(self, args, progress)
                     .await
-                    .map_err(|e| ToolError::ToolCallError(Box::new(e)))
+                    .map_err(|e| {
+                        // Forward requests for input of tools returning a `ToolError`
+                        let e: Box<dyn std::error::Error + Send + Sync> = Box::new(e);
+                        match e.downcast::<ToolError>() {
+                            Ok(e) => match *e {
+                                ToolError::NeedsInput(prompt) => ToolError::NeedsInput(prompt),
+                                e => ToolError::ToolCallError(Box::new(e)),
+                            },
+                            Err(e) => ToolError::ToolCallError(e),
+                        }
+                    })
                     .and_then(|output| {
-                        serde_json::to_string(&output).map_err(ToolError::JsonError)
+                        let sources = <Self as Tool>::sources(self, &output);
+                        // Sorted, so that identical outputs (e.g.: maps) are identical results
+                        json_utils::to_sorted_string(&output)
+                            .map(|output| (output, sources))
+                            .map_err(ToolError::JsonError)
                     }),
                 Err(e) => Err(ToolError::JsonError(e)),
             }
@@ -202,9 +634,10 @@ impl<T: ToolEmbedding> ToolEmbeddingDyn for T {
     }
 }
 
+#[derive(Clone)]
 pub(crate) enum ToolType {
-    Simple(Box<dyn ToolDyn>),
-    Embedding(Box<dyn ToolEmbeddingDyn>),
+    Simple(Arc<dyn ToolDyn>),
+    Embedding(Arc<dyn ToolEmbeddingDyn>),
 }
 
 impl ToolType {
@@ -215,10 +648,20 @@ impl ToolType {
         }
     }
 
+    /// The definition of the tool, including its examples
     pub async fn definition(&self, prompt: String) -> ToolDefinition {
         match self {
-            ToolType::Simple(tool) => tool.definition(prompt).await,
-            ToolType::Embedding(tool) => tool.definition(prompt).await,
+            ToolType::Simple(tool) => with_examples(tool.definition(prompt).await, tool.examples()),
+            ToolType::Embedding(tool) => {
+                with_examples(tool.definition(prompt).await, tool.examples())
+            }
+        }
+    }
+
+    pub fn depends_on(&self) -> Vec<String> {
+        match self {
+            ToolType::Simple(tool) => tool.depends_on(),
+            ToolType::Embedding(tool) => tool.depends_on(),
         }
     }
 
@@ -228,6 +671,27 @@ impl ToolType {
             ToolType::Embedding(tool) => tool.call(args).await,
         }
     }
+
+    pub async fn call_with_sources(
+        &self,
+        args: String,
+    ) -> Result<(String, Vec<Source>), ToolError> {
+        match self {
+            ToolType::Simple(tool) => tool.call_with_sources(args).await,
+            ToolType::Embedding(tool) => tool.call_with_sources(args).await,
+        }
+    }
+
+    pub async fn call_with_progress(
+        &self,
+        args: String,
+        progress: ToolProgress,
+    ) -> Result<(String, Vec<Source>), ToolError> {
+        match self {
+            ToolType::Simple(tool) => tool.call_with_progress(args, progress).await,
+            ToolType::Embedding(tool) => tool.call_with_progress(args, progress).await,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -244,8 +708,8 @@ pub enum ToolSetError {
     JsonError(#[from] serde_json::Error),
 }
 
-/// A struct that holds a set of tools
-#[derive(Default)]
+/// A struct that holds a set of tools. Cloning a toolset is cheap: the tools are shared.
+#[derive(Clone, Default)]
 pub struct ToolSet {
     pub(crate) tools: HashMap<String, ToolType>,
 }
@@ -273,7 +737,12 @@ impl ToolSet {
     /// Add a tool to the toolset
     pub fn add_tool(&mut self, tool: impl ToolDyn + 'static) {
         self.tools
-            .insert(tool.name(), ToolType::Simple(Box::new(tool)));
+            .insert(tool.name(), ToolType::Simple(Arc::new(tool)));
+    }
+
+    /// Remove the tool with the given name from the toolset, returning whether it was present
+    pub fn remove_tool(&mut self, toolname: &str) -> bool {
+        self.tools.remove(toolname).is_some()
     }
 
     /// Merge another toolset into this one
@@ -287,17 +756,109 @@ impl ToolSet {
 
     /// Call a tool with the given name and arguments
     pub async fn call(&self, toolname: &str, args: String) -> Result<String, ToolSetError> {
+        self.call_with_sources(toolname, args)
+            .await
+            .map(|(output, _)| output)
+    }
+
+    /// Same as [ToolSet::call] but also returns the sources of the result (see [Tool::sources])
+    pub async fn call_with_sources(
+        &self,
+        toolname: &str,
+        args: String,
+    ) -> Result<(String, Vec<Source>), ToolSetError> {
+        self.call_with_progress(toolname, args, ToolProgress::default())
+            .await
+    }
+
+    /// Same as [ToolSet::call_with_sources], reporting the progress of the call to `progress`
+    /// (see [Tool::call_with_progress])
+    pub async fn call_with_progress(
+        &self,
+        toolname: &str,
+        args: String,
+        progress: ToolProgress,
+    ) -> Result<(String, Vec<Source>), ToolSetError> {
         if let Some(tool) = self.tools.get(toolname) {
             tracing::info!(target: "rig",
                 "Calling tool {toolname} with args:\n{}",
                 serde_json::to_string_pretty(&args).unwrap_or_else(|_| args.clone())
             );
-            Ok(tool.call(args).await?)
+            Ok(tool.call_with_progress(args, progress).await?)
         } else {
             Err(ToolSetError::ToolNotFoundError(toolname.to_string()))
         }
     }
 
+    /// Call several tools with the given names and arguments, returning their results in the
+    /// order of `calls`. Calls are executed concurrently, except for calls of tools depending
+    /// on other tools of the batch (see [Tool::depends_on]), which are executed once the calls
+    /// of their dependencies have completed. Once only calls with circular dependencies remain,
+    /// they are executed sequentially, in the order of `calls`.
+    ///
+    /// # Example
+    /// ```rust
+    /// // `summarize` depends on `search`, which is called first
+    /// let results = toolset
+    ///     .call_all(vec![
+    ///         ("summarize".to_string(), r#"{"length": 100}"#.to_string()),
+    ///         ("search".to_string(), r#"{"query": "flurbos"}"#.to_string()),
+    ///     ])
+    ///     .await;
+    /// ```
+    pub async fn call_all(
+        &self,
+        calls: Vec<(String, String)>,
+    ) -> Vec<Result<String, ToolSetError>> {
+        let dependencies = calls
+            .iter()
+            .map(|(toolname, _)| {
+                self.tools
+                    .get(toolname)
+                    .map(|tool| tool.depends_on())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let mut results = calls.iter().map(|_| None).collect::<Vec<_>>();
+        let mut pending = (0..calls.len()).collect::<Vec<_>>();
+
+        while !pending.is_empty() {
+            // Calls whose dependencies are not called by any other pending call
+            let (ready, blocked): (Vec<_>, Vec<_>) = pending.iter().copied().partition(|&i| {
+                !pending.iter().any(|&j| {
+                    j != i && calls[j].0 != calls[i].0 && dependencies[i].contains(&calls[j].0)
+                })
+            });
+            if ready.is_empty() {
+                tracing::warn!(target: "rig",
+                    "Circular dependencies between tools {:?}, calling them sequentially",
+                    blocked.iter().map(|&i| &calls[i].0).collect::<Vec<_>>()
+                );
+                for i in blocked {
+                    let (toolname, args) = &calls[i];
+                    results[i] = Some(self.call(toolname, args.clone()).await);
+                }
+                break;
+            }
+
+            let outputs = future::join_all(ready.iter().map(|&i| {
+                let (toolname, args) = &calls[i];
+                self.call(toolname, args.clone())
+            }))
+            .await;
+            for (i, output) in ready.into_iter().zip(outputs) {
+                results[i] = Some(output);
+            }
+            pending = blocked;
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("Every call should have been executed"))
+            .collect()
+    }
+
     /// Get the documents of all the tools in the toolset
     pub async fn documents(&self) -> Result<Vec<completion::Document>, ToolSetError> {
         let mut docs = Vec::new();
@@ -362,12 +923,12 @@ pub struct ToolSetBuilder {
 
 impl ToolSetBuilder {
     pub fn static_tool(mut self, tool: impl ToolDyn + 'static) -> Self {
-        self.tools.push(ToolType::Simple(Box::new(tool)));
+        self.tools.push(ToolType::Simple(Arc::new(tool)));
         self
     }
 
     pub fn dynamic_tool(mut self, tool: impl ToolEmbeddingDyn + 'static) -> Self {
-        self.tools.push(ToolType::Embedding(Box::new(tool)));
+        self.tools.push(ToolType::Embedding(Arc::new(tool)));
         self
     }
 
@@ -381,3 +942,178 @@ impl ToolSetBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use serde_json::json;
+
+    use super::{Tool, ToolNameMap, ToolNameRules, ToolSet, ToolSetError};
+    use crate::completion::ToolDefinition;
+
+    /// Tool recording its calls in a shared log once they complete
+    struct Logger {
+        name: &'static str,
+        delay: Duration,
+        depends_on: Vec<String>,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Tool for Logger {
+        const NAME: &'static str = "logger";
+
+        type Error = std::convert::Infallible;
+        type Args = serde_json::Value;
+        type Output = String;
+
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: self.name.to_string(),
+                description: "Log the call".to_string(),
+                parameters: json!({ "type": "object" }),
+            }
+        }
+
+        fn depends_on(&self) -> Vec<String> {
+            self.depends_on.clone()
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            if !self.delay.is_zero() {
+                tokio::time::sleep(self.delay).await;
+            }
+            self.log.lock().unwrap().push(self.name);
+            Ok(self.name.to_string())
+        }
+    }
+
+    /// Tool returning the stock of each of its items, as a map
+    struct Inventory;
+
+    impl Tool for Inventory {
+        const NAME: &'static str = "inventory";
+
+        type Error = std::convert::Infallible;
+        type Args = serde_json::Value;
+        type Output = HashMap<String, usize>;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "List the stock".to_string(),
+                parameters: json!({ "type": "object" }),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            // A new map (with new random keys for its hasher) on each call
+            Ok((0..32)
+                .map(|item| (format!("item{item:02}"), item))
+                .collect())
+        }
+    }
+
+    fn toolset(depends_on: Vec<String>, log: &Arc<Mutex<Vec<&'static str>>>) -> ToolSet {
+        ToolSet::from_tools(vec![
+            Logger {
+                name: "search",
+                delay: Duration::from_millis(50),
+                depends_on: vec![],
+                log: log.clone(),
+            },
+            Logger {
+                name: "summarize",
+                delay: Duration::ZERO,
+                depends_on,
+                log: log.clone(),
+            },
+            Logger {
+                name: "translate",
+                delay: Duration::ZERO,
+                depends_on: vec![],
+                log: log.clone(),
+            },
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_call_all_dependencies() {
+        let calls = vec![
+            ("summarize".to_string(), "{}".to_string()),
+            ("search".to_string(), "{}".to_string()),
+            ("translate".to_string(), "{}".to_string()),
+            ("unknown".to_string(), "{}".to_string()),
+        ];
+
+        // Without dependencies, the calls are concurrent and the slow search completes last
+        let log = Arc::new(Mutex::new(vec![]));
+        toolset(vec![], &log).call_all(calls.clone()).await;
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["summarize", "translate", "search"]
+        );
+
+        // The summary waits for the search, while the translation does not
+        let log = Arc::new(Mutex::new(vec![]));
+        let results = toolset(vec!["search".to_string()], &log)
+            .call_all(calls)
+            .await;
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["translate", "search", "summarize"]
+        );
+
+        // Results are in the order of the calls
+        assert_eq!(results[0].as_ref().unwrap(), "\"summarize\"");
+        assert_eq!(results[1].as_ref().unwrap(), "\"search\"");
+        assert!(matches!(
+            results[3],
+            Err(ToolSetError::ToolNotFoundError(_))
+        ));
+    }
+
+    #[test]
+    fn test_tool_name_map() {
+        let rules = ToolNameRules {
+            max_len: 8,
+            ..ToolNameRules::ALPHANUMERIC
+        };
+        let names = ToolNameMap::new(["math.add", "math_add", "search", "web.search.v2"], rules);
+
+        // Valid names are kept, colliding names get a suffix
+        assert_eq!(names.provider_name("math_add"), "math_add");
+        assert_eq!(names.provider_name("search"), "search");
+        assert_eq!(names.provider_name("math.add"), "math_a_2");
+        assert_eq!(names.provider_name("web.search.v2"), "web_sear");
+
+        for name in ["math.add", "math_add", "search", "web.search.v2"] {
+            assert!(rules.is_valid(names.provider_name(name)));
+            assert_eq!(names.original_name(names.provider_name(name)), name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_tool_results() {
+        let toolset = ToolSet::from_tools(vec![Inventory]);
+
+        let first = toolset.call("inventory", "{}".to_string()).await.unwrap();
+        let second = toolset.call("inventory", "{}".to_string()).await.unwrap();
+        assert_eq!(first, second);
+
+        // The entries of the map are sorted by key
+        let expected = (0..32)
+            .map(|item| format!("\"item{item:02}\":{item}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(first, format!("{{{expected}}}"));
+    }
+}
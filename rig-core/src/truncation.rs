@@ -0,0 +1,143 @@
+//! This module provides the [TruncationPolicy] struct, used to trim individual oversized
+//! documents to a token limit before embedding them (see
+//! [EmbeddingsBuilder::truncation](crate::embeddings::EmbeddingsBuilder::truncation)) or
+//! prompting a model with them (see
+//! [AgentBuilder::truncate_documents](crate::agent::AgentBuilder::truncate_documents)).
+//!
+//! Token counts are estimated assuming ~[CHARS_PER_TOKEN] characters per token, as actual
+//! token counts depend on the tokenizer of the model.
+//!
+//! # Example
+//! ```rust
+//! use rig::truncation::{TruncationPolicy, TruncationStrategy};
+//!
+//! let policy = TruncationPolicy::new(2).strategy(TruncationStrategy::MiddleOut);
+//! assert_eq!(policy.truncate("Flurbos are a made up currency."), "F[...]y.");
+//! ```
+use std::borrow::Cow;
+
+/// Estimated number of characters per token
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Marker inserted where a document was truncated (by default)
+pub const DEFAULT_MARKER: &str = "[...]";
+
+/// Which part of an oversized document is kept
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    /// Keep the beginning of the document
+    #[default]
+    Head,
+    /// Keep the end of the document
+    Tail,
+    /// Keep the beginning and the end of the document, dropping its middle
+    MiddleOut,
+}
+
+/// Policy for truncating documents longer than a token limit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TruncationPolicy {
+    max_tokens: usize,
+    strategy: TruncationStrategy,
+    marker: String,
+}
+
+impl TruncationPolicy {
+    /// Truncate documents longer than `max_tokens` (estimated) tokens, keeping their beginning
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            strategy: TruncationStrategy::default(),
+            marker: DEFAULT_MARKER.to_string(),
+        }
+    }
+
+    /// Set which part of oversized documents is kept
+    pub fn strategy(mut self, strategy: TruncationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set the marker inserted where documents are truncated. The marker counts towards the
+    /// token limit.
+    pub fn marker(mut self, marker: &str) -> Self {
+        self.marker = marker.to_string();
+        self
+    }
+
+    /// Truncate `text` to the token limit of the policy, inserting the marker where it was
+    /// truncated. Texts within the limit are returned unchanged.
+    pub fn truncate<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let limit = self.max_tokens.saturating_mul(CHARS_PER_TOKEN);
+        let len = text.chars().count();
+        if len <= limit {
+            return Cow::Borrowed(text);
+        }
+
+        let keep = limit.saturating_sub(self.marker.chars().count());
+        let head = |n: usize| &text[..byte_offset(text, n)];
+        let tail = |n: usize| &text[byte_offset(text, len - n)..];
+
+        Cow::Owned(match self.strategy {
+            TruncationStrategy::Head => format!("{}{}", head(keep), self.marker),
+            TruncationStrategy::Tail => format!("{}{}", self.marker, tail(keep)),
+            TruncationStrategy::MiddleOut => {
+                format!("{}{}{}", head(keep / 2), self.marker, tail(keep - keep / 2))
+            }
+        })
+    }
+}
+
+/// Byte offset of the `n`-th character of `text`
+fn byte_offset(text: &str, n: usize) -> usize {
+    text.char_indices()
+        .nth(n)
+        .map_or(text.len(), |(offset, _)| offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TruncationPolicy, TruncationStrategy, CHARS_PER_TOKEN};
+
+    const DOCUMENT: &str = "Flurbos are a made up currency used on the planet Glarb.";
+
+    #[test]
+    fn test_truncate() {
+        let policy = TruncationPolicy::new(5);
+
+        let truncated = policy.truncate(DOCUMENT);
+        assert_eq!(truncated, "Flurbos are a m[...]");
+        assert_eq!(truncated.chars().count(), 5 * CHARS_PER_TOKEN);
+
+        assert_eq!(
+            policy
+                .clone()
+                .strategy(TruncationStrategy::Tail)
+                .truncate(DOCUMENT),
+            "[...]e planet Glarb."
+        );
+        assert_eq!(
+            policy
+                .strategy(TruncationStrategy::MiddleOut)
+                .marker(" … ")
+                .truncate(DOCUMENT),
+            "Flurbos  … et Glarb."
+        );
+    }
+
+    #[test]
+    fn test_truncate_within_limit() {
+        let policy = TruncationPolicy::new(100);
+        assert_eq!(policy.truncate(DOCUMENT), DOCUMENT);
+        assert!(matches!(
+            policy.truncate(DOCUMENT),
+            std::borrow::Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_truncate_multi_byte() {
+        let policy = TruncationPolicy::new(1).marker("…");
+        assert_eq!(policy.truncate("ééééé"), "ééé…");
+    }
+}
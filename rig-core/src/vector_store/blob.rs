@@ -0,0 +1,133 @@
+//! The module defines the [Blob] type, a raw bytes payload which documents stored in vector
+//! stores can carry alongside their text and metadata (e.g.: the original bytes of an embedded
+//! image or binary), returned with the documents by
+//! [VectorStoreIndex::top_n](super::VectorStoreIndex::top_n).
+//!
+//! # Example
+//! ```rust
+//! use rig::vector_store::{Blob, VectorStoreIndex};
+//!
+//! #[derive(serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+//! struct Picture {
+//!     caption: String,
+//!     image: Option<Blob>,
+//! }
+//!
+//! let results = index.top_n::<Picture>("A cat on a sofa", 1).await?;
+//! let (_, _, picture) = &results[0];
+//! std::fs::write("cat.png", picture.image.as_ref().unwrap())?;
+//! ```
+use std::fmt;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Raw bytes payload of a document. Blobs are serialized as base64 strings, so that they can be
+/// stored by any vector store (instead of JSON arrays of numbers, ~4 times larger), and can be
+/// deserialized from base64 strings, byte strings (e.g.: the `BLOB` columns of SQL backends) or
+/// sequences of bytes.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Blob(pub Vec<u8>);
+
+impl Blob {
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Self(bytes.into())
+    }
+
+    /// Size of the blob, in bytes
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl fmt::Debug for Blob {
+    /// Only the size of the blob is shown, to keep the logs of documents readable
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Blob({} bytes)", self.0.len())
+    }
+}
+
+impl From<Vec<u8>> for Blob {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Blob {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BlobVisitor;
+
+        impl<'de> de::Visitor<'de> for BlobVisitor {
+            type Value = Blob;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a base64 string or a sequence of bytes")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Blob, E> {
+                BASE64_STANDARD.decode(value).map(Blob).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Blob, E> {
+                Ok(Blob(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Blob, E> {
+                Ok(Blob(value))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Blob, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(Blob(bytes))
+            }
+        }
+
+        deserializer.deserialize_any(BlobVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Blob;
+
+    #[test]
+    fn test_serde() {
+        let blob = Blob::new(vec![0u8, 159, 146, 150, 255]);
+
+        let value = serde_json::to_value(&blob).unwrap();
+        assert_eq!(value, json!("AJ+Slv8="));
+        assert_eq!(serde_json::from_value::<Blob>(value).unwrap(), blob);
+
+        assert_eq!(
+            serde_json::from_value::<Blob>(json!([0, 159, 146, 150, 255])).unwrap(),
+            blob
+        );
+        assert!(serde_json::from_value::<Blob>(json!("not base64!")).is_err());
+        assert_eq!(format!("{blob:?}"), "Blob(5 bytes)");
+    }
+}
@@ -0,0 +1,302 @@
+//! The module defines the [CachingIndex] adaptor, which caches the search results of a
+//! [VectorStoreIndex] so that repeated queries are not embedded and searched again.
+use std::{
+    collections::HashMap,
+    sync::{PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{ScoreExplanation, VectorStoreError, VectorStoreIndex};
+use crate::embeddings::EmbeddingModel;
+
+/// Default time to live of the cached results (see [CachingIndex::ttl])
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// Search results cached by a [CachingIndex]
+#[derive(Clone)]
+enum CachedResults {
+    Documents(Vec<(f64, String, Value)>),
+    Ids(Vec<(f64, String)>),
+}
+
+struct CacheEntry {
+    inserted: Instant,
+    /// Generation of the index when the search started (see [VectorStoreIndex::generation])
+    generation: Option<u64>,
+    results: CachedResults,
+}
+
+/// Adaptor of a [VectorStoreIndex] caching the results of its searches by query and number of
+/// results. Since the filters of an index (e.g.: [InMemoryVectorIndex::after](super::in_memory_store::InMemoryVectorIndex::after))
+/// are part of its configuration, indexes with different filters should be wrapped separately.
+///
+/// Cached results expire after their time to live (see [CachingIndex::ttl]) and are
+/// invalidated when the documents of the index are modified, for indexes tracking their
+/// modifications (see [VectorStoreIndex::generation], e.g.: the
+/// [InMemoryVectorIndex](super::in_memory_store::InMemoryVectorIndex)). The cache of other
+/// indexes should be cleared with [CachingIndex::invalidate] after modifying them.
+///
+/// # Example
+/// ```rust
+/// use rig::vector_store::CachingIndex;
+///
+/// let index = CachingIndex::new(vector_store.clone().index(model)).ttl(Duration::from_secs(300));
+///
+/// let results = index.top_n::<String>("What is a flurbo?", 3).await?;
+/// // Served from the cache
+/// let results = index.top_n::<String>("What is a flurbo?", 3).await?;
+///
+/// // Invalidates the cached results
+/// vector_store.add_documents(documents);
+/// ```
+pub struct CachingIndex<I: VectorStoreIndex> {
+    index: I,
+    ttl: Duration,
+    entries: RwLock<HashMap<(String, usize), CacheEntry>>,
+}
+
+impl<I: VectorStoreIndex> CachingIndex<I> {
+    pub fn new(index: I) -> Self {
+        Self {
+            index,
+            ttl: DEFAULT_TTL,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Set the time to live of the cached results (60 seconds by default).
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Clear the cached results, e.g.: after modifying an index which does not track its
+    /// modifications.
+    pub fn invalidate(&self) {
+        self.entries
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// The wrapped index
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    fn is_valid(&self, entry: &CacheEntry, generation: Option<u64>) -> bool {
+        entry.inserted.elapsed() < self.ttl && entry.generation == generation
+    }
+
+    fn get(&self, query: &str, n: usize, generation: Option<u64>) -> Option<CachedResults> {
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&(query.to_string(), n))
+            .filter(|entry| self.is_valid(entry, generation))
+            .map(|entry| entry.results.clone())
+    }
+
+    fn insert(&self, query: &str, n: usize, generation: Option<u64>, results: CachedResults) {
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+
+        // Drop the stale entries so that the cache does not grow unbounded
+        let current = self.index.generation();
+        entries.retain(|_, entry| self.is_valid(entry, current));
+
+        entries.insert(
+            (query.to_string(), n),
+            CacheEntry {
+                inserted: Instant::now(),
+                generation,
+                results,
+            },
+        );
+    }
+}
+
+impl<I: VectorStoreIndex> VectorStoreIndex for CachingIndex<I> {
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        // The generation is read before searching, so that results racing with a modification
+        // of the index are invalidated
+        let generation = self.index.generation();
+
+        let results = match self.get(query, n, generation) {
+            Some(CachedResults::Documents(results)) => results,
+            _ => {
+                let results = self.index.top_n::<Value>(query, n).await?;
+                self.insert(
+                    query,
+                    n,
+                    generation,
+                    CachedResults::Documents(results.clone()),
+                );
+                results
+            }
+        };
+
+        results
+            .into_iter()
+            .map(|(score, id, doc)| Ok((score, id, serde_json::from_value(doc)?)))
+            .collect()
+    }
+
+    async fn top_n_ids(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        let generation = self.index.generation();
+
+        match self.get(query, n, generation) {
+            Some(CachedResults::Documents(results)) => Ok(results
+                .into_iter()
+                .map(|(score, id, _)| (score, id))
+                .collect()),
+            Some(CachedResults::Ids(results)) => Ok(results),
+            None => {
+                let results = self.index.top_n_ids(query, n).await?;
+                self.insert(query, n, generation, CachedResults::Ids(results.clone()));
+                Ok(results)
+            }
+        }
+    }
+
+    /// Explanations are not cached: the query is always forwarded to the inner index.
+    async fn top_n_explain<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(ScoreExplanation, String, T)>, VectorStoreError> {
+        self.index.top_n_explain(query, n).await
+    }
+
+    fn generation(&self) -> Option<u64> {
+        self.index.generation()
+    }
+
+    async fn reembed<M: EmbeddingModel>(&self, model: &M) -> Result<(), VectorStoreError> {
+        self.index.reembed(model).await?;
+        // The cached results were computed with the previous embeddings
+        self.invalidate();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::CachingIndex;
+    use crate::{
+        embeddings::{Embedding, EmbeddingError, EmbeddingModel},
+        vector_store::{in_memory_store::InMemoryVectorStore, VectorStoreIndex},
+        OneOrMany,
+    };
+
+    /// Embedding model counting the texts it embeds
+    #[derive(Clone, Default)]
+    struct Model {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 10;
+
+        fn ndims(&self) -> usize {
+            2
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|document| {
+                    self.calls.fetch_add(1, Ordering::SeqCst);
+                    Embedding {
+                        document,
+                        vec: vec![1.0, 0.0],
+                    }
+                })
+                .collect())
+        }
+    }
+
+    fn embedding(vec: Vec<f64>) -> OneOrMany<Embedding> {
+        OneOrMany::one(Embedding {
+            document: "doc".to_string(),
+            vec,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_cached_query() {
+        let model = Model::default();
+        let store = InMemoryVectorStore::from_documents_with_ids(vec![(
+            "glarb",
+            "Glarbs are ancient artifacts.".to_string(),
+            embedding(vec![0.0, 1.0]),
+        )]);
+        let index = CachingIndex::new(store.clone().index(model.clone()));
+
+        let results = index.top_n::<String>("flurbo", 1).await.unwrap();
+        assert_eq!(results[0].1, "glarb");
+        assert_eq!(model.calls.load(Ordering::SeqCst), 1);
+
+        // Repeated queries are served from the cache
+        assert_eq!(index.top_n::<String>("flurbo", 1).await.unwrap(), results);
+        assert_eq!(
+            index.top_n_ids("flurbo", 1).await.unwrap(),
+            vec![(results[0].0, "glarb".to_string())]
+        );
+        assert_eq!(model.calls.load(Ordering::SeqCst), 1);
+
+        // Other queries and numbers of results are not
+        index.top_n::<String>("flurbo", 2).await.unwrap();
+        index.top_n_ids("glarb", 1).await.unwrap();
+        assert_eq!(model.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_insert_invalidates_cache() {
+        let model = Model::default();
+        let store = InMemoryVectorStore::from_documents_with_ids(vec![(
+            "glarb",
+            "Glarbs are ancient artifacts.".to_string(),
+            embedding(vec![0.0, 1.0]),
+        )]);
+        let index = CachingIndex::new(store.clone().index(model.clone()));
+
+        assert_eq!(index.top_n_ids("flurbo", 1).await.unwrap()[0].1, "glarb");
+
+        store.add_documents_with_ids(vec![(
+            "flurbo",
+            "Flurbos are a made up currency.".to_string(),
+            embedding(vec![1.0, 0.0]),
+        )]);
+
+        // The new document is found by the repeated query
+        assert_eq!(index.top_n_ids("flurbo", 1).await.unwrap()[0].1, "flurbo");
+        assert_eq!(model.calls.load(Ordering::SeqCst), 2);
+
+        // Expired results are not served either
+        let index = index.ttl(Duration::ZERO);
+        index.top_n_ids("flurbo", 1).await.unwrap();
+        assert_eq!(model.calls.load(Ordering::SeqCst), 3);
+    }
+}
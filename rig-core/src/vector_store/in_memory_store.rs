@@ -2,25 +2,98 @@
 use std::{
     cmp::Reverse,
     collections::{BinaryHeap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{Duration, SystemTime},
 };
 
 use ordered_float::OrderedFloat;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use super::{VectorStoreError, VectorStoreIndex};
+use super::{
+    CancellationToken, ScoreAdjustment, ScoreExplanation, Snapshot, SnapshotDocument,
+    VectorStoreError, VectorStoreIndex, VectorStoreSnapshot, SNAPSHOT_FORMAT_VERSION,
+};
 use crate::{
-    embeddings::{distance::VectorDistance, Embedding, EmbeddingModel},
+    embeddings::{
+        distance::{DistanceMetric, VectorDistance},
+        BinaryEmbedding, Embedding, EmbeddingError, EmbeddingModel,
+    },
     OneOrMany,
 };
 
+type Embeddings<D> = HashMap<String, (D, OneOrMany<Embedding>)>;
+type Timestamps = HashMap<String, SystemTime>;
+/// Precomputed values of the embeddings of each document, in the order of its embeddings
+type Cache = HashMap<String, Vec<CachedEmbedding>>;
+/// Values of the tracked top-level metadata fields of the documents, by field then by document
+/// id (see [InMemoryVectorIndex::with_metadata_boost])
+type Fields = HashMap<String, HashMap<String, serde_json::Value>>;
+
+/// Number of documents scanned by a search between two checks of its [CancellationToken]
+pub const CANCELLATION_CHECK_INTERVAL: usize = 1024;
+
 /// [InMemoryVectorStore] is a simple in-memory vector store that stores embeddings
 /// in-memory using a HashMap.
-#[derive(Clone, Default)]
+///
+/// The store is internally synchronized with an `Arc<RwLock<...>>`, meaning that it can be
+/// shared between threads and tasks without any external locking. Cloning the store is cheap
+/// and returns a handle to the same underlying data: documents added through a clone are
+/// visible from the original store (and vice versa). Unlike in previous versions, a clone is
+/// not a deep copy; to get an independent copy of the documents, build a new store from
+/// [InMemoryVectorStore::iter_cloned].
+///
+/// # Consistency model
+/// - Searches (e.g.: [VectorStoreIndex::top_n]) and other reads hold a shared lock for their
+///   whole duration, so they always see a consistent snapshot of the store. Any number of
+///   reads can run concurrently.
+/// - Writes (e.g.: [InMemoryVectorStore::add_documents]) hold an exclusive lock while the
+///   documents are inserted, so each call is atomic: concurrent readers either see all of the
+///   documents of a given call or none of them.
+/// - The lock is never held across `.await` points (the query embedding is computed before the
+///   lock is acquired).
+/// - Document timestamps (see [InMemoryVectorStore::add_documents_with_timestamps]) are stored
+///   behind a second lock, always acquired after the lock of the documents.
+/// - The norms and the binarized bits of the embeddings are computed when the documents are
+///   inserted (and recomputed when they are replaced), so that cosine and hamming searches only
+///   compute the norm and the bits of the query. They are stored behind a third lock, always acquired right after the lock of the documents.
+/// - The values of the metadata fields boosted by indexes (see
+///   [InMemoryVectorIndex::with_metadata_boost]) are extracted when the documents are inserted
+///   (or when a field is first boosted), so that searches do not serialize the documents. They
+///   are stored behind a fourth lock, always acquired after the other locks.
 pub struct InMemoryVectorStore<D: Serialize> {
     /// The embeddings are stored in a HashMap.
     /// Hashmap key is the document id.
     /// Hashmap value is a tuple of the serializable document and its corresponding embeddings.
-    embeddings: HashMap<String, (D, OneOrMany<Embedding>)>,
+    embeddings: Arc<RwLock<Embeddings<D>>>,
+    /// Timestamps of the documents, by document id.
+    timestamps: Arc<RwLock<Timestamps>>,
+    /// Norms and bits of the embeddings of the documents, by document id.
+    cache: Arc<RwLock<Cache>>,
+    /// Values of the boosted metadata fields of the documents.
+    fields: Arc<RwLock<Fields>>,
+    /// Number of writes to the store (see [VectorStoreIndex::generation])
+    generation: Arc<AtomicU64>,
+}
+
+impl<D: Serialize> Clone for InMemoryVectorStore<D> {
+    fn clone(&self) -> Self {
+        Self {
+            embeddings: self.embeddings.clone(),
+            timestamps: self.timestamps.clone(),
+            cache: self.cache.clone(),
+            fields: self.fields.clone(),
+            generation: self.generation.clone(),
+        }
+    }
+}
+
+impl<D: Serialize> Default for InMemoryVectorStore<D> {
+    fn default() -> Self {
+        Self::from_map(HashMap::new())
+    }
 }
 
 impl<D: Serialize + Eq> InMemoryVectorStore<D> {
@@ -36,7 +109,7 @@ impl<D: Serialize + Eq> InMemoryVectorStore<D> {
                 store.insert(format!("doc{i}"), (doc, embeddings));
             });
 
-        Self { embeddings: store }
+        Self::from_map(store)
     }
 
     /// Create a new [InMemoryVectorStore] from documents and and their corresponding embeddings with ids.
@@ -48,7 +121,7 @@ impl<D: Serialize + Eq> InMemoryVectorStore<D> {
             store.insert(i.to_string(), (doc, embeddings));
         });
 
-        Self { embeddings: store }
+        Self::from_map(store)
     }
 
     /// Create a new [InMemoryVectorStore] from documents and their corresponding embeddings.
@@ -62,108 +135,217 @@ impl<D: Serialize + Eq> InMemoryVectorStore<D> {
             store.insert(f(&doc), (doc, embeddings));
         });
 
-        Self { embeddings: store }
-    }
-
-    /// Implement vector search on [InMemoryVectorStore].
-    /// To be used by implementations of [VectorStoreIndex::top_n] and [VectorStoreIndex::top_n_ids] methods.
-    fn vector_search(&self, prompt_embedding: &Embedding, n: usize) -> EmbeddingRanking<D> {
-        // Sort documents by best embedding distance
-        let mut docs = BinaryHeap::new();
-
-        for (id, (doc, embeddings)) in self.embeddings.iter() {
-            // Get the best context for the document given the prompt
-            if let Some((distance, embed_doc)) = embeddings
-                .iter()
-                .map(|embedding| {
-                    (
-                        OrderedFloat(embedding.cosine_similarity(prompt_embedding, false)),
-                        &embedding.document,
-                    )
-                })
-                .max_by(|a, b| a.0.cmp(&b.0))
-            {
-                docs.push(Reverse(RankingItem(distance, id, doc, embed_doc)));
-            };
-
-            // If the heap size exceeds n, pop the least old element.
-            if docs.len() > n {
-                docs.pop();
-            }
-        }
-
-        // Log selected tools with their distances
-        tracing::info!(target: "rig",
-            "Selected documents: {}",
-            docs.iter()
-                .map(|Reverse(RankingItem(distance, id, _, _))| format!("{} ({})", id, distance))
-                .collect::<Vec<String>>()
-                .join(", ")
-        );
-
-        docs
+        Self::from_map(store)
     }
 
     /// Add documents and their corresponding embeddings to the store.
     /// Ids are automatically generated have will have the form `"doc{n}"` where `n`
     /// is the index of the document.
-    pub fn add_documents(
-        &mut self,
-        documents: impl IntoIterator<Item = (D, OneOrMany<Embedding>)>,
-    ) {
-        let current_index = self.embeddings.len();
+    pub fn add_documents(&self, documents: impl IntoIterator<Item = (D, OneOrMany<Embedding>)>) {
+        let mut store = self.write();
+        let mut cache = self.write_cache();
+        let mut fields = self.write_fields();
+        let current_index = store.len();
         documents
             .into_iter()
             .enumerate()
             .for_each(|(index, (doc, embeddings))| {
-                self.embeddings
-                    .insert(format!("doc{}", index + current_index), (doc, embeddings));
+                let id = format!("doc{}", index + current_index);
+                insert(&mut store, &mut cache, &mut fields, id, doc, embeddings);
             });
     }
 
     /// Add documents and their corresponding embeddings to the store with ids.
     pub fn add_documents_with_ids(
-        &mut self,
+        &self,
         documents: impl IntoIterator<Item = (impl ToString, D, OneOrMany<Embedding>)>,
     ) {
+        let mut store = self.write();
+        let mut cache = self.write_cache();
+        let mut fields = self.write_fields();
         documents.into_iter().for_each(|(id, doc, embeddings)| {
-            self.embeddings.insert(id.to_string(), (doc, embeddings));
+            insert(
+                &mut store,
+                &mut cache,
+                &mut fields,
+                id.to_string(),
+                doc,
+                embeddings,
+            );
         });
     }
 
     /// Add documents and their corresponding embeddings to the store.
     /// Document ids are generated using the provided function.
     pub fn add_documents_with_id_f(
-        &mut self,
+        &self,
         documents: Vec<(D, OneOrMany<Embedding>)>,
         f: fn(&D) -> String,
     ) {
+        let mut store = self.write();
+        let mut cache = self.write_cache();
+        let mut fields = self.write_fields();
         for (doc, embeddings) in documents {
             let id = f(&doc);
-            self.embeddings.insert(id, (doc, embeddings));
+            insert(&mut store, &mut cache, &mut fields, id, doc, embeddings);
+        }
+    }
+
+    /// Add documents with ids, their corresponding embeddings and their timestamps (e.g.: the
+    /// creation time of the documents) to the store. Timestamps can be used to filter and
+    /// weight search results by recency (see [InMemoryVectorIndex::after],
+    /// [InMemoryVectorIndex::before] and [InMemoryVectorIndex::recency_decay]).
+    pub fn add_documents_with_timestamps(
+        &self,
+        documents: impl IntoIterator<Item = (impl ToString, D, OneOrMany<Embedding>, SystemTime)>,
+    ) {
+        let mut store = self.write();
+        let mut cache = self.write_cache();
+        let mut timestamps = self.write_timestamps();
+        let mut fields = self.write_fields();
+        documents
+            .into_iter()
+            .for_each(|(id, doc, embeddings, timestamp)| {
+                insert(
+                    &mut store,
+                    &mut cache,
+                    &mut fields,
+                    id.to_string(),
+                    doc,
+                    embeddings,
+                );
+                timestamps.insert(id.to_string(), timestamp);
+            });
+    }
+
+    /// Remove the documents with the given ids (along with their timestamps). Ids missing from
+    /// the store are ignored.
+    pub fn remove_documents(&self, ids: impl IntoIterator<Item = impl AsRef<str>>) {
+        let mut store = self.write();
+        let mut cache = self.write_cache();
+        let mut timestamps = self.write_timestamps();
+        let mut fields = self.write_fields();
+        for id in ids {
+            let id = id.as_ref();
+            store.remove(id);
+            cache.remove(id);
+            timestamps.remove(id);
+            for values in fields.values_mut() {
+                values.remove(id);
+            }
         }
     }
 
+    /// Get the timestamp of a document by its id, if it was added with one.
+    pub fn get_timestamp(&self, id: &str) -> Option<SystemTime> {
+        self.read_timestamps().get(id).copied()
+    }
+
     /// Get the document by its id and deserialize it into the given type.
     pub fn get_document<T: for<'a> Deserialize<'a>>(
         &self,
         id: &str,
     ) -> Result<Option<T>, VectorStoreError> {
         Ok(self
-            .embeddings
+            .read()
             .get(id)
             .map(|(doc, _)| serde_json::from_str(&serde_json::to_string(doc)?))
             .transpose()?)
     }
+
+    /// Number of dimensions of the embeddings of the store, or `None` if the store is empty.
+    pub fn ndims(&self) -> Option<usize> {
+        self.read()
+            .values()
+            .find_map(|(_, embeddings)| embeddings.iter().next())
+            .map(|embedding| embedding.vec.len())
+    }
+
+    /// Re-embed all the documents of the store with `model` from the texts of their embeddings
+    /// (see [VectorStoreIndex::reembed]). The texts are embedded without holding the lock of the
+    /// store, then the embeddings of all the documents are replaced at once, so that the store
+    /// is left unchanged on error. Documents replaced meanwhile by documents with different
+    /// texts keep their new embeddings.
+    pub async fn reembed<M: EmbeddingModel>(&self, model: &M) -> Result<(), VectorStoreError> {
+        // Ids of the documents with the texts of their embeddings
+        let documents = self
+            .read()
+            .iter()
+            .map(|(id, (_, embeddings))| {
+                let texts = embeddings
+                    .iter()
+                    .map(|embedding| embedding.document.clone())
+                    .collect::<Vec<_>>();
+                (id.clone(), texts)
+            })
+            .collect::<Vec<_>>();
+
+        let texts = documents
+            .iter()
+            .flat_map(|(_, texts)| texts.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(M::MAX_DOCUMENTS.max(1)) {
+            let batch_embeddings = model.embed_texts(batch.to_vec()).await?;
+            if batch_embeddings.len() != batch.len() {
+                return Err(EmbeddingError::ResponseError(format!(
+                    "Expected {} embeddings, got {}",
+                    batch.len(),
+                    batch_embeddings.len()
+                ))
+                .into());
+            }
+            embeddings.extend(batch_embeddings);
+        }
+        if let Some(embedding) = embeddings
+            .iter()
+            .find(|embedding| embedding.vec.len() != model.ndims())
+        {
+            return Err(EmbeddingError::ResponseError(format!(
+                "Embedding of {:?} does not have {} dimensions",
+                embedding.document,
+                model.ndims()
+            ))
+            .into());
+        }
+
+        let mut embeddings = embeddings.into_iter();
+        let mut store = self.write();
+        let mut cache = self.write_cache();
+        for (id, texts) in documents {
+            let reembedded = OneOrMany::many(embeddings.by_ref().take(texts.len()).collect())
+                .expect("Documents should have at least one embedding");
+            let current = store.get_mut(&id).filter(|(_, current)| {
+                current
+                    .iter()
+                    .map(|embedding| &embedding.document)
+                    .eq(texts.iter())
+            });
+            if let Some((_, current)) = current {
+                cache.insert(id, reembedded.iter().map(CachedEmbedding::of).collect());
+                *current = reembedded;
+            }
+        }
+
+        Ok(())
+    }
 }
 
-/// RankingItem(distance, document_id, serializable document, embeddings document)
+/// RankingItem(distance, document_id, serializable document, embeddings document, similarity
+/// before weighting). Items are ordered by distance, ties being broken by id (the smaller id
+/// ranking higher), so that rankings are deterministic.
 #[derive(Eq, PartialEq)]
-struct RankingItem<'a, D: Serialize>(OrderedFloat<f64>, &'a String, &'a D, &'a String);
+struct RankingItem<'a, D: Serialize>(
+    OrderedFloat<f64>,
+    &'a String,
+    &'a D,
+    &'a String,
+    OrderedFloat<f64>,
+);
 
 impl<D: Serialize + Eq> Ord for RankingItem<'_, D> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.cmp(&other.0)
+        self.0.cmp(&other.0).then_with(|| other.1.cmp(self.1))
     }
 }
 
@@ -175,36 +357,576 @@ impl<D: Serialize + Eq> PartialOrd for RankingItem<'_, D> {
 
 type EmbeddingRanking<'a, D> = BinaryHeap<Reverse<RankingItem<'a, D>>>;
 
+/// Insert a document in `store`, replacing the document with the same id (if any), and cache
+/// the norms and bits of its embeddings in `cache` and the values of its tracked metadata
+/// `fields`.
+fn insert<D: Serialize>(
+    store: &mut Embeddings<D>,
+    cache: &mut Cache,
+    fields: &mut Fields,
+    id: String,
+    doc: D,
+    embeddings: OneOrMany<Embedding>,
+) {
+    cache.insert(
+        id.clone(),
+        embeddings.iter().map(CachedEmbedding::of).collect(),
+    );
+    cache_fields(fields, &id, &doc);
+    store.insert(id, (doc, embeddings));
+}
+
+/// Cache the values of the tracked metadata `fields` of the document `id`, serializing it once
+/// (and only if fields are tracked).
+fn cache_fields<D: Serialize>(fields: &mut Fields, id: &str, doc: &D) {
+    if fields.is_empty() {
+        return;
+    }
+
+    let metadata = serde_json::to_value(doc).ok();
+    for (field, values) in fields.iter_mut() {
+        match metadata.as_ref().and_then(|metadata| metadata.get(field)) {
+            Some(value) => {
+                values.insert(id.to_string(), value.clone());
+            }
+            None => {
+                values.remove(id);
+            }
+        }
+    }
+}
+
+/// Norm and bits of an embedding, cached when its document is inserted
+struct CachedEmbedding {
+    norm: f64,
+    /// The bits of the embedding (see [BinaryEmbedding::from_embedding]), without its document
+    binary: BinaryEmbedding,
+}
+
+impl CachedEmbedding {
+    fn of(embedding: &Embedding) -> Self {
+        Self {
+            norm: embedding.norm(),
+            binary: BinaryEmbedding {
+                document: String::new(),
+                ..BinaryEmbedding::from_embedding(embedding)
+            },
+        }
+    }
+}
+
+/// Norms and bits of the embeddings of the documents of `store`
+fn cache_of<D>(store: &Embeddings<D>) -> Cache {
+    store
+        .iter()
+        .map(|(id, (_, embeddings))| {
+            (
+                id.clone(),
+                embeddings.iter().map(CachedEmbedding::of).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Implement vector search on the embeddings of an [InMemoryVectorStore].
+/// To be used by implementations of [VectorStoreIndex::top_n] and [VectorStoreIndex::top_n_ids] methods.
+fn vector_search<'a, D: Serialize + Eq>(
+    store: &'a Embeddings<D>,
+    prompt_embedding: &Embedding,
+    n: usize,
+) -> EmbeddingRanking<'a, D> {
+    weighted_vector_search(
+        store,
+        &cache_of(store),
+        std::slice::from_ref(prompt_embedding),
+        n,
+        DistanceMetric::Cosine,
+        |_, _| Some(1.0),
+        &CancellationToken::new(),
+    )
+    // The search is never cancelled
+    .unwrap_or_default()
+}
+
+/// Same as [vector_search] with the given distance `metric` and one or more query embeddings:
+/// the score of a document is the sum, over the query embeddings, of their best similarity with
+/// an embedding of the document (i.e.: its best similarity for a single query embedding). The
+/// score of each document is then multiplied by `weight(id, doc)`. Documents for which `weight`
+/// returns `None` are excluded from the search.
+///
+/// Cosine similarities and hamming distances use the norms and bits of the embeddings of the
+/// documents cached in `cache` (see [InMemoryVectorStore]), falling back to computing them for
+/// the documents missing from `cache`.
+///
+/// Only the top `n` documents are kept during the scan, in a bounded min-heap (i.e.: in
+/// O(N log n) for N documents), ranked as a full sort of the scores would (see [RankingItem]).
+///
+/// The search fails with [VectorStoreError::Cancelled] once `cancel` is cancelled, checked every
+/// [CANCELLATION_CHECK_INTERVAL] documents.
+fn weighted_vector_search<'a, D: Serialize + Eq>(
+    store: &'a Embeddings<D>,
+    cache: &Cache,
+    prompt_embeddings: &[Embedding],
+    n: usize,
+    metric: DistanceMetric,
+    weight: impl Fn(&str, &D) -> Option<f64>,
+    cancel: &CancellationToken,
+) -> Result<EmbeddingRanking<'a, D>, VectorStoreError> {
+    // Binarize the queries once for hamming distances
+    let binary_prompts = (metric == DistanceMetric::Hamming).then(|| {
+        prompt_embeddings
+            .iter()
+            .map(BinaryEmbedding::from_embedding)
+            .collect::<Vec<_>>()
+    });
+    // Compute the norms of the queries once for cosine similarities
+    let prompt_norms = prompt_embeddings
+        .iter()
+        .map(Embedding::norm)
+        .collect::<Vec<_>>();
+    let similarity = |query: usize, embedding: &Embedding, cached: Option<&CachedEmbedding>| {
+        match &binary_prompts {
+            Some(binary_prompts) => match cached {
+                Some(cached) => {
+                    DistanceMetric::binary_similarity(&binary_prompts[query], &cached.binary)
+                }
+                None => DistanceMetric::binary_similarity(
+                    &binary_prompts[query],
+                    &BinaryEmbedding::from_embedding(embedding),
+                ),
+            },
+            None if metric == DistanceMetric::Cosine => {
+                let norm = cached.map_or_else(|| embedding.norm(), |cached| cached.norm);
+                prompt_embeddings[query].dot_product(embedding) / (prompt_norms[query] * norm)
+            }
+            None => metric.similarity(&prompt_embeddings[query], embedding),
+        }
+    };
+
+    // Sort documents by best embedding distance
+    let mut docs = BinaryHeap::new();
+
+    for (scanned, (id, (doc, embeddings))) in store.iter().enumerate() {
+        if scanned % CANCELLATION_CHECK_INTERVAL == 0 {
+            cancel.check()?;
+        }
+
+        let Some(weight) = weight(id, doc) else {
+            continue;
+        };
+
+        // Get the best context for the document given each query embedding
+        let doc_cache = cache.get(id);
+        let mut score = 0.0;
+        let mut best: Option<(OrderedFloat<f64>, &String)> = None;
+        for query in 0..prompt_embeddings.len() {
+            if let Some((similarity, embed_doc)) = embeddings
+                .iter()
+                .enumerate()
+                .map(|(index, embedding)| {
+                    let cached = doc_cache.and_then(|cache| cache.get(index));
+                    (
+                        OrderedFloat(similarity(query, embedding, cached)),
+                        &embedding.document,
+                    )
+                })
+                .max_by(|a, b| a.0.cmp(&b.0))
+            {
+                score += similarity.0;
+                if best.map_or(true, |(best, _)| similarity > best) {
+                    best = Some((similarity, embed_doc));
+                }
+            }
+        }
+
+        if let Some((_, embed_doc)) = best {
+            let item = RankingItem(
+                OrderedFloat(score * weight),
+                id,
+                doc,
+                embed_doc,
+                OrderedFloat(score),
+            );
+            if docs.len() < n {
+                docs.push(Reverse(item));
+            } else if let Some(mut worst) = docs.peek_mut() {
+                // Replace the worst of the top n documents if the document ranks higher
+                if item > worst.0 {
+                    *worst = Reverse(item);
+                }
+            }
+        };
+    }
+
+    // Log selected tools with their distances
+    tracing::info!(target: "rig",
+        "Selected documents: {}",
+        docs.iter()
+            .map(|Reverse(RankingItem(distance, id, _, _, _))| format!("{} ({})", id, distance))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+
+    Ok(docs)
+}
+
 impl<D: Serialize> InMemoryVectorStore<D> {
+    fn from_map(embeddings: Embeddings<D>) -> Self {
+        Self {
+            cache: Arc::new(RwLock::new(cache_of(&embeddings))),
+            embeddings: Arc::new(RwLock::new(embeddings)),
+            timestamps: Arc::new(RwLock::new(HashMap::new())),
+            fields: Arc::new(RwLock::new(HashMap::new())),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Acquire the shared lock of the store.
+    /// A poisoned lock is recovered since writes never leave the map in an invalid state.
+    fn read(&self) -> RwLockReadGuard<'_, Embeddings<D>> {
+        self.embeddings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquire the exclusive lock of the store, counting a new write.
+    fn write(&self) -> RwLockWriteGuard<'_, Embeddings<D>> {
+        let guard = self
+            .embeddings
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        guard
+    }
+
+    /// Number of writes to the store so far (i.e.: calls adding, replacing or restoring
+    /// documents), shared by all the clones of the store.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Acquire the shared lock of the cached norms and bits. Must be acquired right after the
+    /// lock of the store.
+    fn read_cache(&self) -> RwLockReadGuard<'_, Cache> {
+        self.cache.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquire the exclusive lock of the cached norms and bits. Must be acquired right after the
+    /// lock of the store.
+    fn write_cache(&self) -> RwLockWriteGuard<'_, Cache> {
+        self.cache.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquire the shared lock of the timestamps. Must be acquired after the lock of the store.
+    fn read_timestamps(&self) -> RwLockReadGuard<'_, Timestamps> {
+        self.timestamps
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquire the exclusive lock of the timestamps. Must be acquired after the lock of the store.
+    fn write_timestamps(&self) -> RwLockWriteGuard<'_, Timestamps> {
+        self.timestamps
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquire the shared lock of the boosted metadata fields. Must be acquired after the other
+    /// locks of the store.
+    fn read_fields(&self) -> RwLockReadGuard<'_, Fields> {
+        self.fields.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquire the exclusive lock of the boosted metadata fields. Must be acquired after the
+    /// other locks of the store.
+    fn write_fields(&self) -> RwLockWriteGuard<'_, Fields> {
+        self.fields.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Track the metadata `field` of the documents: its value is extracted from the documents
+    /// now and whenever documents are inserted, so that searches boosting it never serialize
+    /// the documents.
+    fn track_field(&self, field: &str) {
+        let store = self.read();
+        let mut fields = self.write_fields();
+        if fields.contains_key(field) {
+            return;
+        }
+
+        let values = store
+            .iter()
+            .filter_map(|(id, (doc, _))| {
+                let mut metadata = serde_json::to_value(doc).ok()?;
+                Some((id.clone(), metadata.get_mut(field)?.take()))
+            })
+            .collect();
+        fields.insert(field.to_string(), values);
+    }
+
     pub fn index<M: EmbeddingModel>(self, model: M) -> InMemoryVectorIndex<M, D> {
         InMemoryVectorIndex::new(model, self)
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&String, &(D, OneOrMany<Embedding>))> {
-        self.embeddings.iter()
+    /// Acquire the shared lock of the store to access its documents (with their embeddings, by
+    /// id) without cloning them, e.g.: `store.documents().iter()`. Writes to the store (through
+    /// any of its clones) are blocked until the guard is dropped, so it should not be held
+    /// across `.await` points.
+    pub fn documents(&self) -> RwLockReadGuard<'_, HashMap<String, (D, OneOrMany<Embedding>)>> {
+        self.read()
+    }
+
+    /// Iterate over a snapshot of the documents of the store taken when this method is called.
+    /// The documents are cloned, so the lock of the store is not held during the iteration
+    /// (see [InMemoryVectorStore::documents] to borrow them instead).
+    pub fn iter_cloned(&self) -> impl Iterator<Item = (String, (D, OneOrMany<Embedding>))>
+    where
+        D: Clone,
+    {
+        self.read()
+            .iter()
+            .map(|(id, entry)| (id.clone(), entry.clone()))
+            .collect::<Vec<_>>()
+            .into_iter()
     }
 
     pub fn len(&self) -> usize {
-        self.embeddings.len()
+        self.read().len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.embeddings.is_empty()
+        self.read().is_empty()
     }
 }
 
 pub struct InMemoryVectorIndex<M: EmbeddingModel, D: Serialize> {
     model: M,
     pub store: InMemoryVectorStore<D>,
+    /// Only documents with a timestamp after this time are returned
+    after: Option<SystemTime>,
+    /// Only documents with a timestamp before this time are returned
+    before: Option<SystemTime>,
+    /// Half-life of the recency decay applied to the scores of the documents
+    half_life: Option<Duration>,
+    /// Boosts applied to the scores of the documents with matching metadata
+    boosts: Vec<MetadataBoost>,
+    /// Metric used to compare the embeddings of the documents to the query
+    metric: DistanceMetric,
+    /// Whether documents are scored by late interaction (see [InMemoryVectorIndex::max_sim])
+    max_sim: bool,
+}
+
+/// Boost of the score of documents whose `field` equals `value`
+struct MetadataBoost {
+    field: String,
+    value: serde_json::Value,
+    factor: f64,
+}
+
+impl MetadataBoost {
+    /// Whether the document `id` matches the boost, given the cached values of the tracked
+    /// metadata `fields` of the store
+    fn matches(&self, fields: &Fields, id: &str) -> bool {
+        fields.get(&self.field).and_then(|values| values.get(id)) == Some(&self.value)
+    }
 }
 
 impl<M: EmbeddingModel, D: Serialize> InMemoryVectorIndex<M, D> {
     pub fn new(model: M, store: InMemoryVectorStore<D>) -> Self {
-        Self { model, store }
+        Self {
+            model,
+            store,
+            after: None,
+            before: None,
+            half_life: None,
+            boosts: vec![],
+            metric: DistanceMetric::default(),
+            max_sim: false,
+        }
+    }
+
+    /// Score documents by late interaction (ColBERT-style max-sim) instead of the best
+    /// similarity of their embeddings: the query is split into whitespace-separated tokens,
+    /// each embedded separately, and the score of a document is the sum over the query tokens
+    /// of their best similarity with an embedding of the document. Documents should hold one
+    /// embedding per token (or per small span of text).
+    ///
+    /// # Example
+    /// ```rust
+    /// let index = vector_store.index(model).max_sim();
+    /// ```
+    pub fn max_sim(mut self) -> Self {
+        self.max_sim = true;
+        self
+    }
+
+    /// Set the metric used to compare the embeddings of the documents to the query (cosine
+    /// similarity by default), e.g.: [DistanceMetric::Hamming] for binary embeddings.
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Only return documents with a timestamp after `time` (inclusive).
+    /// Documents without a timestamp are excluded.
+    pub fn after(mut self, time: SystemTime) -> Self {
+        self.after = Some(time);
+        self
+    }
+
+    /// Only return documents with a timestamp before `time` (exclusive).
+    /// Documents without a timestamp are excluded.
+    pub fn before(mut self, time: SystemTime) -> Self {
+        self.before = Some(time);
+        self
+    }
+
+    /// Weight the score of documents by their recency: the score of a document is multiplied
+    /// by `0.5^(age / half_life)`, where `age` is the time elapsed since its timestamp at query
+    /// time. Documents without a timestamp (or with a timestamp in the future) are not decayed.
+    ///
+    /// # Example
+    /// ```rust
+    /// // The score of a week old document is halved
+    /// let index = vector_store
+    ///     .index(model)
+    ///     .recency_decay(Duration::from_secs(7 * 24 * 60 * 60));
+    /// ```
+    pub fn recency_decay(mut self, half_life: Duration) -> Self {
+        self.half_life = Some(half_life);
+        self
+    }
+
+    /// Multiply the score of documents whose (top-level) metadata `field` equals `value` by
+    /// `factor` before ranking them, e.g.: to rank official documentation above community
+    /// content. Boosts compose: the score of a document matching several boosts is multiplied
+    /// by each of their factors.
+    ///
+    /// # Example
+    /// ```rust
+    /// // Official documents are ranked as if they were 50% more similar to the query
+    /// let index = vector_store
+    ///     .index(model)
+    ///     .with_metadata_boost("source", "official", 1.5);
+    /// ```
+    pub fn with_metadata_boost(
+        mut self,
+        field: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+        factor: f64,
+    ) -> Self {
+        let field = field.into();
+        self.store.track_field(&field);
+        self.boosts.push(MetadataBoost {
+            field,
+            value: value.into(),
+            factor,
+        });
+        self
+    }
+
+    /// Weight of the document `id` in searches given the timestamp filters, recency decay and
+    /// metadata boosts of the index, or `None` if the document is filtered out.
+    fn weight(
+        &self,
+        timestamps: &Timestamps,
+        fields: &Fields,
+        now: SystemTime,
+        id: &str,
+    ) -> Option<f64> {
+        let timestamp = timestamps.get(id);
+
+        if self.after.is_some() || self.before.is_some() {
+            let timestamp = timestamp?;
+            if self.after.is_some_and(|after| *timestamp < after)
+                || self.before.is_some_and(|before| *timestamp >= before)
+            {
+                return None;
+            }
+        }
+
+        Some(self.decay(now, timestamp).unwrap_or(1.0) * self.boost(fields, id))
+    }
+
+    /// Recency decay of a document with the given timestamp, or `None` if it is not decayed.
+    fn decay(&self, now: SystemTime, timestamp: Option<&SystemTime>) -> Option<f64> {
+        let (half_life, timestamp) = (self.half_life?, timestamp?);
+        let age = now.duration_since(*timestamp).unwrap_or_default();
+        Some(0.5_f64.powf(age.as_secs_f64() / half_life.as_secs_f64()))
+    }
+
+    /// Adjustments of the score of the document `id` making up its weight (see
+    /// [InMemoryVectorIndex::weight]): its recency decay and the metadata boosts it matches.
+    fn adjustments(
+        &self,
+        timestamps: &Timestamps,
+        fields: &Fields,
+        now: SystemTime,
+        id: &str,
+    ) -> Vec<ScoreAdjustment> {
+        let decay = self
+            .decay(now, timestamps.get(id))
+            .map(|factor| ScoreAdjustment {
+                name: "recency_decay".to_string(),
+                factor,
+            });
+
+        let boosts = self.boosts.iter().filter_map(|boost| {
+            boost.matches(fields, id).then(|| ScoreAdjustment {
+                name: format!("metadata_boost({}={})", boost.field, boost.value),
+                factor: boost.factor,
+            })
+        });
+
+        decay.into_iter().chain(boosts).collect()
+    }
+
+    /// Embeddings of `query`: one per token for late interaction (see
+    /// [InMemoryVectorIndex::max_sim]), or a single one otherwise.
+    pub(super) async fn query_embeddings(
+        &self,
+        query: &str,
+    ) -> Result<Vec<Embedding>, VectorStoreError> {
+        let tokens = query.split_whitespace().collect::<Vec<_>>();
+        if !self.max_sim || tokens.is_empty() {
+            return Ok(vec![self.model.embed_query(query).await?]);
+        }
+
+        let mut embeddings = Vec::with_capacity(tokens.len());
+        for batch in tokens.chunks(M::MAX_DOCUMENTS.max(1)) {
+            embeddings.extend(
+                self.model
+                    .embed_texts(
+                        batch
+                            .iter()
+                            .map(|token| token.to_string())
+                            .collect::<Vec<_>>(),
+                    )
+                    .await?,
+            );
+        }
+        Ok(embeddings)
+    }
+
+    /// Product of the factors of the metadata boosts matching the document `id`.
+    fn boost(&self, fields: &Fields, id: &str) -> f64 {
+        self.boosts
+            .iter()
+            .filter(|boost| boost.matches(fields, id))
+            .map(|boost| boost.factor)
+            .product()
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&String, &(D, OneOrMany<Embedding>))> {
-        self.store.iter()
+    /// See [InMemoryVectorStore::documents]
+    pub fn documents(&self) -> RwLockReadGuard<'_, HashMap<String, (D, OneOrMany<Embedding>)>> {
+        self.store.documents()
+    }
+
+    /// See [InMemoryVectorStore::iter_cloned]
+    pub fn iter_cloned(&self) -> impl Iterator<Item = (String, (D, OneOrMany<Embedding>))>
+    where
+        D: Clone,
+    {
+        self.store.iter_cloned()
     }
 
     pub fn len(&self) -> usize {
@@ -216,21 +938,34 @@ impl<M: EmbeddingModel, D: Serialize> InMemoryVectorIndex<M, D> {
     }
 }
 
-impl<M: EmbeddingModel + Sync, D: Serialize + Sync + Send + Eq> VectorStoreIndex
-    for InMemoryVectorIndex<M, D>
-{
-    async fn top_n<T: for<'a> Deserialize<'a>>(
+impl<M: EmbeddingModel, D: Serialize + Eq> InMemoryVectorIndex<M, D> {
+    /// Top `n` documents of the index for the given query embeddings (see
+    /// [InMemoryVectorIndex::query_embeddings]), unless the search is cancelled by `cancel`.
+    pub(super) fn search<T: for<'a> Deserialize<'a>>(
         &self,
-        query: &str,
+        prompt_embeddings: &[Embedding],
         n: usize,
+        cancel: &CancellationToken,
     ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
-        let prompt_embedding = &self.model.embed_text(query).await?;
-
-        let docs = self.store.vector_search(prompt_embedding, n);
+        let store = self.store.read();
+        let cache = self.store.read_cache();
+        let timestamps = self.store.read_timestamps();
+        let fields = self.store.read_fields();
+        let now = SystemTime::now();
+        let docs = weighted_vector_search(
+            &store,
+            &cache,
+            prompt_embeddings,
+            n,
+            self.metric,
+            |id, _| self.weight(&timestamps, &fields, now, id),
+            cancel,
+        )?;
 
         // Return n best
-        docs.into_iter()
-            .map(|Reverse(RankingItem(distance, id, doc, _))| {
+        docs.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(RankingItem(distance, id, doc, _, _))| {
                 Ok((
                     distance.0,
                     id.clone(),
@@ -243,33 +978,288 @@ impl<M: EmbeddingModel + Sync, D: Serialize + Sync + Send + Eq> VectorStoreIndex
             .collect::<Result<Vec<_>, _>>()
     }
 
+    /// Same as [InMemoryVectorIndex::search] but the scores are explained (see
+    /// [VectorStoreIndex::top_n_explain]).
+    pub(super) fn search_explain<T: for<'a> Deserialize<'a>>(
+        &self,
+        prompt_embeddings: &[Embedding],
+        n: usize,
+    ) -> Result<Vec<(ScoreExplanation, String, T)>, VectorStoreError> {
+        let store = self.store.read();
+        let cache = self.store.read_cache();
+        let timestamps = self.store.read_timestamps();
+        let fields = self.store.read_fields();
+        let now = SystemTime::now();
+        let docs = weighted_vector_search(
+            &store,
+            &cache,
+            prompt_embeddings,
+            n,
+            self.metric,
+            |id, _| self.weight(&timestamps, &fields, now, id),
+            &CancellationToken::new(),
+        )?;
+
+        docs.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(RankingItem(score, id, doc, _, similarity))| {
+                let explanation = ScoreExplanation {
+                    similarity: similarity.0,
+                    adjustments: self.adjustments(&timestamps, &fields, now, id),
+                    score: score.0,
+                };
+                Ok((
+                    explanation,
+                    id.clone(),
+                    serde_json::from_str(
+                        &serde_json::to_string(doc).map_err(VectorStoreError::JsonError)?,
+                    )
+                    .map_err(VectorStoreError::JsonError)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    }
+
+    /// Same as [InMemoryVectorIndex::search] but returns the document ids only.
+    pub(super) fn search_ids(
+        &self,
+        prompt_embeddings: &[Embedding],
+        n: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        let store = self.store.read();
+        let cache = self.store.read_cache();
+        let timestamps = self.store.read_timestamps();
+        let fields = self.store.read_fields();
+        let now = SystemTime::now();
+        let docs = weighted_vector_search(
+            &store,
+            &cache,
+            prompt_embeddings,
+            n,
+            self.metric,
+            |id, _| self.weight(&timestamps, &fields, now, id),
+            cancel,
+        )?;
+
+        // Return n best
+        Ok(docs
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(RankingItem(distance, id, _, _, _))| (distance.0, id.clone()))
+            .collect())
+    }
+}
+
+impl<M: EmbeddingModel + Sync, D: Serialize + Sync + Send + Eq> VectorStoreIndex
+    for InMemoryVectorIndex<M, D>
+{
+    async fn top_n<T: for<'a> Deserialize<'a>>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.top_n_cancellable(query, n, &CancellationToken::new())
+            .await
+    }
+
     async fn top_n_ids(
         &self,
         query: &str,
         n: usize,
     ) -> Result<Vec<(f64, String)>, VectorStoreError> {
-        let prompt_embedding = &self.model.embed_text(query).await?;
+        self.top_n_ids_cancellable(query, n, &CancellationToken::new())
+            .await
+    }
 
-        let docs = self.store.vector_search(prompt_embedding, n);
+    async fn top_n_cancellable<T: for<'a> Deserialize<'a>>(
+        &self,
+        query: &str,
+        n: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        let prompt_embeddings = self.query_embeddings(query).await?;
+        self.search(&prompt_embeddings, n, cancel)
+    }
 
-        // Return n best
-        docs.into_iter()
-            .map(|Reverse(RankingItem(distance, id, _, _))| Ok((distance.0, id.clone())))
-            .collect::<Result<Vec<_>, _>>()
+    async fn top_n_ids_cancellable(
+        &self,
+        query: &str,
+        n: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        let prompt_embeddings = self.query_embeddings(query).await?;
+        self.search_ids(&prompt_embeddings, n, cancel)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::cmp::Reverse;
+    async fn top_n_explain<T: for<'a> Deserialize<'a>>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(ScoreExplanation, String, T)>, VectorStoreError> {
+        let prompt_embeddings = self.query_embeddings(query).await?;
+        self.search_explain(&prompt_embeddings, n)
+    }
 
-    use crate::{embeddings::embedding::Embedding, OneOrMany};
+    fn generation(&self) -> Option<u64> {
+        Some(self.store.generation())
+    }
 
-    use super::{InMemoryVectorStore, RankingItem};
+    async fn reembed<N: EmbeddingModel>(&self, model: &N) -> Result<(), VectorStoreError> {
+        self.store.reembed(model).await
+    }
+}
 
-    #[test]
-    fn test_auto_ids() {
-        let mut vector_store = InMemoryVectorStore::from_documents(vec![
+impl<M: EmbeddingModel + Sync, D: Serialize + DeserializeOwned + Sync + Send + Eq>
+    VectorStoreSnapshot for InMemoryVectorIndex<M, D>
+{
+    async fn snapshot(&self, writer: impl std::io::Write + Send) -> Result<(), VectorStoreError> {
+        let snapshot = {
+            let store = self.store.read();
+            let timestamps = self.store.read_timestamps();
+
+            Snapshot {
+                format_version: SNAPSHOT_FORMAT_VERSION,
+                ndims: self.model.ndims(),
+                metric: self.metric.name().to_string(),
+                documents: store
+                    .iter()
+                    .map(|(id, (doc, embeddings))| {
+                        Ok(SnapshotDocument {
+                            id: id.clone(),
+                            document: serde_json::to_value(doc)?,
+                            embeddings: embeddings.iter().cloned().collect(),
+                            timestamp: timestamps.get(id).copied(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, VectorStoreError>>()?,
+            }
+        };
+
+        serde_json::to_writer(writer, &snapshot)?;
+        Ok(())
+    }
+
+    async fn restore(&self, reader: impl std::io::Read + Send) -> Result<(), VectorStoreError> {
+        let snapshot: Snapshot = serde_json::from_reader(reader)?;
+
+        if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(VectorStoreError::SnapshotError(format!(
+                "Unsupported format version {} (expected {SNAPSHOT_FORMAT_VERSION})",
+                snapshot.format_version
+            )));
+        }
+        if snapshot.metric != self.metric.name() {
+            return Err(VectorStoreError::SnapshotError(format!(
+                "Snapshot metric {} does not match index metric {}",
+                snapshot.metric,
+                self.metric.name()
+            )));
+        }
+        if snapshot.ndims != self.model.ndims() {
+            return Err(VectorStoreError::SnapshotError(format!(
+                "Snapshot has {} dimensions but the index has {}",
+                snapshot.ndims,
+                self.model.ndims()
+            )));
+        }
+
+        let documents = snapshot
+            .documents
+            .into_iter()
+            .map(|document| {
+                if document
+                    .embeddings
+                    .iter()
+                    .any(|embedding| embedding.vec.len() != snapshot.ndims)
+                {
+                    return Err(VectorStoreError::SnapshotError(format!(
+                        "Embedding of document {} does not have {} dimensions",
+                        document.id, snapshot.ndims
+                    )));
+                }
+                let embeddings = OneOrMany::many(document.embeddings).map_err(|_| {
+                    VectorStoreError::SnapshotError(format!(
+                        "Document {} has no embeddings",
+                        document.id
+                    ))
+                })?;
+
+                Ok((
+                    document.id,
+                    serde_json::from_value::<D>(document.document)?,
+                    embeddings,
+                    document.timestamp,
+                ))
+            })
+            .collect::<Result<Vec<_>, VectorStoreError>>()?;
+
+        let mut store = self.store.write();
+        let mut cache = self.store.write_cache();
+        let mut timestamps = self.store.write_timestamps();
+        let mut fields = self.store.write_fields();
+        store.clear();
+        cache.clear();
+        timestamps.clear();
+        fields.values_mut().for_each(HashMap::clear);
+        for (id, doc, embeddings, timestamp) in documents {
+            if let Some(timestamp) = timestamp {
+                timestamps.insert(id.clone(), timestamp);
+            }
+            insert(&mut store, &mut cache, &mut fields, id, doc, embeddings);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Reverse, collections::HashMap};
+
+    use crate::{embeddings::embedding::Embedding, OneOrMany};
+
+    use std::time::{Duration, SystemTime};
+
+    use super::{
+        cache_of, vector_search, weighted_vector_search, InMemoryVectorIndex, InMemoryVectorStore,
+        RankingItem, CANCELLATION_CHECK_INTERVAL,
+    };
+    use crate::{
+        embeddings::{distance::DistanceMetric, Chunk, EmbeddingError, EmbeddingModel},
+        vector_store::{
+            Blob, CancellationToken, VectorStoreError, VectorStoreIndex, VectorStoreSnapshot,
+        },
+    };
+
+    /// Embedding model returning the same embedding for any text
+    #[derive(Clone)]
+    struct Model;
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            3
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| Embedding {
+                    document: text,
+                    vec: vec![0.0, 0.1, 0.6],
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_auto_ids() {
+        let vector_store = InMemoryVectorStore::from_documents(vec![
             (
                 "glarb-garb",
                 OneOrMany::one(Embedding {
@@ -310,7 +1300,7 @@ mod tests {
             ),
         ]);
 
-        let mut store = vector_store.embeddings.into_iter().collect::<Vec<_>>();
+        let mut store = vector_store.iter_cloned().collect::<Vec<_>>();
         store.sort_by_key(|(id, _)| id.clone());
 
         assert_eq!(
@@ -399,7 +1389,9 @@ mod tests {
             ),
         ]);
 
-        let ranking = vector_store.vector_search(
+        let store = vector_store.read();
+        let ranking = vector_search(
+            &store,
             &Embedding {
                 document: "glarby-glarble".to_string(),
                 vec: vec![0.0, 0.1, 0.6],
@@ -410,7 +1402,7 @@ mod tests {
         assert_eq!(
             ranking
                 .into_iter()
-                .map(|Reverse(RankingItem(distance, id, doc, _))| {
+                .map(|Reverse(RankingItem(distance, id, doc, _, _))| {
                     (
                         distance.0,
                         id.clone(),
@@ -476,7 +1468,9 @@ mod tests {
             ),
         ]);
 
-        let ranking = vector_store.vector_search(
+        let store = vector_store.read();
+        let ranking = vector_search(
+            &store,
             &Embedding {
                 document: "glarby-glarble".to_string(),
                 vec: vec![0.0, 0.1, 0.6],
@@ -487,7 +1481,7 @@ mod tests {
         assert_eq!(
             ranking
                 .into_iter()
-                .map(|Reverse(RankingItem(distance, id, doc, _))| {
+                .map(|Reverse(RankingItem(distance, id, doc, _, _))| {
                     (
                         distance.0,
                         id.clone(),
@@ -502,4 +1496,1014 @@ mod tests {
             )]
         )
     }
+
+    #[test]
+    fn test_concurrent_reads_and_writes() {
+        let vector_store = InMemoryVectorStore::from_documents_with_ids(vec![(
+            "doc0",
+            "glarb-garb".to_string(),
+            OneOrMany::one(Embedding {
+                document: "glarb-garb".to_string(),
+                vec: vec![0.1, 0.1, 0.5],
+            }),
+        )]);
+
+        let query = Embedding {
+            document: "glarby-glarble".to_string(),
+            vec: vec![0.0, 0.1, 0.6],
+        };
+
+        std::thread::scope(|s| {
+            // Writer: each call inserts a pair of documents which must be observed atomically
+            let writer_store = vector_store.clone();
+            s.spawn(move || {
+                for i in 0..100 {
+                    writer_store.add_documents_with_ids(vec![
+                        (
+                            format!("a{i}"),
+                            format!("a{i}"),
+                            OneOrMany::one(Embedding {
+                                document: format!("a{i}"),
+                                vec: vec![0.7, -0.3, 0.0],
+                            }),
+                        ),
+                        (
+                            format!("b{i}"),
+                            format!("b{i}"),
+                            OneOrMany::one(Embedding {
+                                document: format!("b{i}"),
+                                vec: vec![0.3, 0.7, 0.1],
+                            }),
+                        ),
+                    ]);
+                }
+            });
+
+            for _ in 0..4 {
+                let reader_store = vector_store.clone();
+                let query = &query;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        let store = reader_store.read();
+                        assert_eq!(store.len() % 2, 1);
+
+                        let ranking = vector_search(&store, query, 1);
+                        let ids = ranking
+                            .into_iter()
+                            .map(|Reverse(RankingItem(_, id, _, _, _))| id.clone())
+                            .collect::<Vec<_>>();
+                        assert_eq!(ids, vec!["doc0".to_string()]);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(vector_store.len(), 201);
+    }
+
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Definition {
+        word: String,
+        definition: String,
+    }
+
+    #[tokio::test]
+    async fn test_blob_payload() {
+        #[derive(Debug, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+        struct Picture {
+            caption: String,
+            image: Option<Blob>,
+        }
+
+        let image = Blob::new(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR".to_vec());
+        let index = InMemoryVectorStore::from_documents_with_ids(vec![
+            (
+                "cat",
+                Picture {
+                    caption: "A cat on a sofa".to_string(),
+                    image: Some(image.clone()),
+                },
+                OneOrMany::one(Embedding {
+                    document: "A cat on a sofa".to_string(),
+                    vec: vec![0.0, 0.1, 0.6],
+                }),
+            ),
+            (
+                "text",
+                Picture {
+                    caption: "No picture".to_string(),
+                    image: None,
+                },
+                OneOrMany::one(Embedding {
+                    document: "No picture".to_string(),
+                    vec: vec![0.9, 0.1, 0.0],
+                }),
+            ),
+        ])
+        .index(Model);
+
+        let results = index.top_n::<Picture>("A cat", 2).await.unwrap();
+        let ids = results
+            .iter()
+            .map(|(_, id, _)| id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["cat", "text"]);
+
+        let (_, _, picture) = &results[0];
+        assert_eq!(picture.image.as_ref(), Some(&image));
+        assert_eq!(picture.image.as_ref().unwrap().as_ref(), image.as_ref());
+        assert_eq!(results[1].2.image, None);
+    }
+
+    #[tokio::test]
+    async fn test_top_n_lenient() {
+        let index = InMemoryVectorStore::from_documents_with_ids(vec![
+            (
+                "doc1",
+                serde_json::json!({
+                    "word": "glarb-garb",
+                    "definition": "An ancient artifact.",
+                    "source": "glarb.txt",
+                }),
+                OneOrMany::one(Embedding {
+                    document: "An ancient artifact.".to_string(),
+                    vec: vec![0.1, 0.1, 0.5],
+                }),
+            ),
+            (
+                "doc2",
+                serde_json::json!({ "word": "flumb-flumb" }),
+                OneOrMany::one(Embedding {
+                    document: "flumb-flumb".to_string(),
+                    vec: vec![0.3, 0.7, 0.1],
+                }),
+            ),
+        ])
+        .index(Model);
+
+        let mut results = index
+            .top_n_lenient::<Definition>("What is a glarb-garb?", 2)
+            .await
+            .unwrap();
+        results.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+        assert_eq!(results.len(), 2);
+
+        let (_, id, definition) = &results[0];
+        assert_eq!(id, "doc1");
+        assert_eq!(
+            definition.as_ref().unwrap(),
+            &Definition {
+                word: "glarb-garb".to_string(),
+                definition: "An ancient artifact.".to_string(),
+            }
+        );
+
+        // Missing field only fails its own result
+        let (_, id, definition) = &results[1];
+        assert_eq!(id, "doc2");
+        assert!(matches!(definition, Err(VectorStoreError::JsonError(_))));
+
+        // Whereas it fails the whole query with `top_n`
+        assert!(index
+            .top_n::<Definition>("What is a glarb-garb?", 2)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_top_n_grouped() {
+        let chunk = |parent_id: &str, chunk_index, text: &str| Chunk {
+            parent_id: parent_id.to_string(),
+            chunk_index,
+            text: text.to_string(),
+            prev_id: None,
+            next_id: None,
+        };
+        let embedding = |text: &str, vec| {
+            OneOrMany::one(Embedding {
+                document: text.to_string(),
+                vec,
+            })
+        };
+
+        let index = InMemoryVectorStore::from_documents_with_ids(vec![
+            (
+                "flurbo-0",
+                chunk("flurbo", 0, "A flurbo is a green alien"),
+                embedding("A flurbo is a green alien", vec![0.0, 0.1, 0.6]),
+            ),
+            (
+                "flurbo-1",
+                chunk("flurbo", 1, "that lives on cold planets."),
+                embedding("that lives on cold planets.", vec![0.0, 0.2, 0.6]),
+            ),
+            (
+                "glarb-0",
+                chunk("glarb", 0, "An ancient artifact."),
+                embedding("An ancient artifact.", vec![0.1, 0.1, 0.5]),
+            ),
+            (
+                "marble-0",
+                chunk("marble", 0, "A marble."),
+                embedding("A marble.", vec![0.7, -0.3, 0.0]),
+            ),
+        ])
+        .index(Model);
+
+        // Both chunks of the flurbo document rank higher than the glarb document
+        let mut chunks = index
+            .top_n::<Chunk>("What is a flurbo?", 2)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, id, _)| id)
+            .collect::<Vec<_>>();
+        chunks.sort();
+        assert_eq!(chunks, vec!["flurbo-0", "flurbo-1"]);
+
+        let results = index
+            .top_n_grouped::<Chunk>("What is a flurbo?", 2)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results
+                .into_iter()
+                .map(|(_, parent_id, chunk)| (parent_id, chunk))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "flurbo".to_string(),
+                    chunk("flurbo", 0, "A flurbo is a green alien")
+                ),
+                (
+                    "glarb".to_string(),
+                    chunk("glarb", 0, "An ancient artifact.")
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chunk_neighbors() {
+        let texts = [
+            "A flurbo is",
+            "a green alien",
+            "that lives on",
+            "cold planets.",
+        ];
+        let vecs = [
+            vec![0.7, -0.3, 0.0],
+            vec![0.1, 0.1, 0.5],
+            vec![0.0, 0.1, 0.6],
+            vec![0.7, -0.3, 0.1],
+        ];
+        let chunks = texts.iter().zip(vecs).enumerate().map(|(i, (text, vec))| {
+            (
+                Chunk::new("flurbo", i, texts.len(), text.to_string()),
+                OneOrMany::one(Embedding {
+                    document: text.to_string(),
+                    vec,
+                }),
+            )
+        });
+
+        let vector_store =
+            InMemoryVectorStore::from_documents_with_id_f(chunks, |chunk| chunk.id());
+        let index = vector_store.clone().index(Model);
+
+        let (_, id, chunk) = index
+            .top_n::<Chunk>("What is a flurbo?", 1)
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(id, "flurbo#2");
+        assert_eq!(chunk.prev_id.as_deref(), Some("flurbo#1"));
+        assert_eq!(chunk.next_id.as_deref(), Some("flurbo#3"));
+
+        let get = |id: &str| vector_store.get_document::<Chunk>(id).ok().flatten();
+
+        // Expanded with its immediate neighbors, in document order
+        let expanded = chunk.clone().with_neighbors(1, get);
+        assert_eq!(
+            expanded
+                .iter()
+                .map(|chunk| chunk.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a green alien", "that lives on", "cold planets."]
+        );
+
+        // The window is bounded by the ends of the document
+        let expanded = chunk.clone().with_neighbors(5, get);
+        assert_eq!(
+            expanded.iter().map(Chunk::id).collect::<Vec<_>>(),
+            vec!["flurbo#0", "flurbo#1", "flurbo#2", "flurbo#3"]
+        );
+
+        assert_eq!(chunk.clone().with_neighbors(0, get), vec![chunk]);
+    }
+
+    #[tokio::test]
+    async fn test_cancellation() {
+        let vector_store = InMemoryVectorStore::from_documents_with_ids((0..20_000).map(|i| {
+            (
+                i,
+                i.to_string(),
+                OneOrMany::one(Embedding {
+                    document: i.to_string(),
+                    vec: vec![0.1, 0.2, i as f64],
+                }),
+            )
+        }));
+
+        // Cancelled while scanning the documents: the scan stops at the next check
+        let store = vector_store.read();
+        let cancel = CancellationToken::new();
+        let scanned = std::cell::Cell::new(0);
+        let result = weighted_vector_search(
+            &store,
+            &cache_of(&store),
+            &[Embedding {
+                document: "query".to_string(),
+                vec: vec![0.0, 0.1, 0.6],
+            }],
+            3,
+            DistanceMetric::Cosine,
+            |_, _| {
+                scanned.set(scanned.get() + 1);
+                if scanned.get() == 10 {
+                    cancel.cancel();
+                }
+                Some(1.0)
+            },
+            &cancel,
+        );
+        assert!(matches!(result, Err(VectorStoreError::Cancelled)));
+        assert_eq!(scanned.get(), CANCELLATION_CHECK_INTERVAL);
+        drop(store);
+
+        let index = vector_store.index(Model);
+        let cancel = CancellationToken::new();
+        assert_eq!(
+            index
+                .top_n_ids_cancellable("flurbo", 3, &cancel)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+
+        cancel.clone().cancel();
+        assert!(matches!(
+            index
+                .top_n_cancellable::<String>("flurbo", 3, &cancel)
+                .await,
+            Err(VectorStoreError::Cancelled)
+        ));
+        assert!(matches!(
+            index.top_n_ids_cancellable("flurbo", 3, &cancel).await,
+            Err(VectorStoreError::Cancelled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cached_norms() {
+        let embedding = |vec: Vec<f64>| Embedding {
+            document: "doc".to_string(),
+            vec,
+        };
+        let documents = [
+            ("flurbo", vec![0.1, 0.1, 0.5]),
+            ("glarb", vec![0.7, -0.3, 0.0]),
+            ("linglingdong", vec![3.0, 2.0, 1.0]),
+        ];
+        let vector_store = InMemoryVectorStore::from_documents_with_ids(
+            documents
+                .clone()
+                .map(|(id, vec)| (id, id.to_string(), OneOrMany::one(embedding(vec)))),
+        );
+        let index = vector_store.clone().index(Model);
+        let hamming = vector_store
+            .clone()
+            .index(Model)
+            .metric(DistanceMetric::Hamming);
+
+        // The query embeds as [0.0, 0.1, 0.6]
+        let query = embedding(vec![0.0, 0.1, 0.6]);
+        let scores = |results: Vec<(f64, String)>| {
+            results
+                .into_iter()
+                .map(|(score, id)| (id, score))
+                .collect::<HashMap<_, _>>()
+        };
+
+        // Same scores as binarizing the documents on every query
+        let results = scores(hamming.top_n_ids("flurbo", 3).await.unwrap());
+        for (id, vec) in documents.clone() {
+            let expected = DistanceMetric::Hamming.similarity(&query, &embedding(vec));
+            assert!((results[id] - expected).abs() < 1e-12);
+        }
+        assert_eq!(results["glarb"], 0.0);
+
+        // Same scores as computing the norms of the documents on every query
+        let results = scores(index.top_n_ids("flurbo", 3).await.unwrap());
+        for (id, vec) in documents {
+            let expected = DistanceMetric::Cosine.similarity(&query, &embedding(vec));
+            assert!((results[id] - expected).abs() < 1e-12);
+        }
+
+        // Replacing a document recomputes the norms and bits of its embeddings
+        vector_store.add_documents_with_ids(vec![(
+            "glarb",
+            "glarb".to_string(),
+            OneOrMany::one(embedding(vec![0.0, 0.2, 1.2])),
+        )]);
+        let results = scores(index.top_n_ids("flurbo", 3).await.unwrap());
+        assert!((results["glarb"] - 1.0).abs() < 1e-12);
+        let results = scores(hamming.top_n_ids("flurbo", 3).await.unwrap());
+        assert_eq!(results["glarb"], 1.0);
+    }
+
+    /// Embedding model with a configurable number of dimensions
+    #[derive(Clone)]
+    struct SizedModel(usize);
+
+    impl EmbeddingModel for SizedModel {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            self.0
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| Embedding {
+                    document: text,
+                    vec: vec![0.1; self.0],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore() {
+        let now = SystemTime::now();
+        let vector_store = InMemoryVectorStore::default();
+        vector_store.add_documents_with_timestamps(vec![
+            (
+                "doc1",
+                "glarb-garb".to_string(),
+                OneOrMany::one(Embedding {
+                    document: "glarb-garb".to_string(),
+                    vec: vec![0.1, 0.1, 0.5],
+                }),
+                now,
+            ),
+            (
+                "doc2",
+                "marble-marble".to_string(),
+                OneOrMany::one(Embedding {
+                    document: "marble-marble".to_string(),
+                    vec: vec![0.7, -0.3, 0.0],
+                }),
+                now - Duration::from_secs(60),
+            ),
+        ]);
+        vector_store.add_documents_with_ids(vec![(
+            "doc3",
+            "flumb-flumb".to_string(),
+            OneOrMany::many(vec![
+                Embedding {
+                    document: "flumb".to_string(),
+                    vec: vec![0.3, 0.7, 0.1],
+                },
+                Embedding {
+                    document: "flumb-flumb".to_string(),
+                    vec: vec![0.0, 0.1, 0.6],
+                },
+            ])
+            .unwrap(),
+        )]);
+        let index = vector_store.index(Model);
+
+        let mut snapshot = vec![];
+        index.snapshot(&mut snapshot).await.unwrap();
+
+        let restored = InMemoryVectorStore::<String>::default().index(Model);
+        restored.restore(snapshot.as_slice()).await.unwrap();
+
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.store.get_timestamp("doc1"), Some(now));
+        assert_eq!(restored.store.get_timestamp("doc3"), None);
+
+        let mut expected = index.top_n::<String>("glarb-garb", 3).await.unwrap();
+        let mut results = restored.top_n::<String>("glarb-garb", 3).await.unwrap();
+        expected.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+        results.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+        assert_eq!(results, expected);
+
+        // Restoring into an index with a different number of dimensions fails
+        let mismatched = InMemoryVectorStore::<String>::default().index(SizedModel(4));
+        assert!(matches!(
+            mismatched.restore(snapshot.as_slice()).await,
+            Err(VectorStoreError::SnapshotError(_))
+        ));
+        assert!(mismatched.is_empty());
+    }
+
+    /// Embedding model embedding "flurbo" and "glarb" along distinct axes, in 4 dimensions
+    #[derive(Clone)]
+    struct AxisModel;
+
+    impl EmbeddingModel for AxisModel {
+        const MAX_DOCUMENTS: usize = 2;
+
+        fn ndims(&self) -> usize {
+            4
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| {
+                    let vec = match text.as_str() {
+                        "flurbo" => vec![1.0, 0.0, 0.0, 0.0],
+                        "glarb" => vec![0.0, 1.0, 0.0, 0.0],
+                        _ => vec![0.0, 0.0, 0.0, 1.0],
+                    };
+                    Embedding {
+                        document: text,
+                        vec,
+                    }
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reembed() {
+        let embedding = |document: &str| Embedding {
+            document: document.to_string(),
+            vec: vec![0.0, 0.1, 0.6],
+        };
+        let vector_store = InMemoryVectorStore::from_documents_with_ids(vec![
+            ("doc0", "flurbo", OneOrMany::one(embedding("flurbo"))),
+            (
+                "doc1",
+                "glarb",
+                OneOrMany::many(vec![embedding("glarb"), embedding("glarb-glarb")]).unwrap(),
+            ),
+            ("doc2", "marble", OneOrMany::one(embedding("marble"))),
+        ]);
+        let generation = vector_store.generation();
+        assert_eq!(vector_store.ndims(), Some(3));
+
+        vector_store
+            .clone()
+            .index(Model)
+            .reembed(&AxisModel)
+            .await
+            .unwrap();
+        assert_eq!(vector_store.ndims(), Some(4));
+        assert!(vector_store.generation() > generation);
+
+        let documents = vector_store.documents();
+        let (doc, embeddings) = documents.get("doc1").unwrap();
+        assert_eq!(*doc, "glarb");
+        assert_eq!(
+            embeddings
+                .iter()
+                .map(|embedding| (embedding.document.as_str(), embedding.vec.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("glarb", vec![0.0, 1.0, 0.0, 0.0]),
+                ("glarb-glarb", vec![0.0, 0.0, 0.0, 1.0]),
+            ]
+        );
+
+        // The documents are now searched with the new model
+        let index = vector_store.index(AxisModel);
+        let results = index.top_n_ids("glarb", 1).await.unwrap();
+        assert_eq!(results[0].1, "doc1");
+        let results = index.top_n::<String>("flurbo", 1).await.unwrap();
+        assert_eq!(
+            (results[0].1.as_str(), results[0].2.as_str()),
+            ("doc0", "flurbo")
+        );
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Store of three documents with the same embedding, created 1, 10 and 100 days ago
+    fn timestamped_store() -> InMemoryVectorStore<&'static str> {
+        let now = SystemTime::now();
+        let vector_store = InMemoryVectorStore::default();
+        vector_store.add_documents_with_timestamps(
+            [("recent", 1), ("old", 10), ("ancient", 100)].map(|(id, days)| {
+                (
+                    id,
+                    id,
+                    OneOrMany::one(Embedding {
+                        document: id.to_string(),
+                        vec: vec![0.1, 0.1, 0.5],
+                    }),
+                    now - DAY * days,
+                )
+            }),
+        );
+        vector_store
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_filters() {
+        let now = SystemTime::now();
+        let vector_store = timestamped_store();
+        vector_store.add_documents_with_ids(vec![(
+            "undated",
+            "undated",
+            OneOrMany::one(Embedding {
+                document: "undated".to_string(),
+                vec: vec![0.1, 0.1, 0.5],
+            }),
+        )]);
+
+        let index = vector_store.clone().index(Model).after(now - DAY * 50);
+        let mut ids = index.top_n_ids("glarb-garb", 10).await.unwrap();
+        ids.sort_by(|(_, a), (_, b)| a.cmp(b));
+        assert_eq!(
+            ids.into_iter().map(|(_, id)| id).collect::<Vec<_>>(),
+            vec!["old", "recent"]
+        );
+
+        let index = vector_store
+            .clone()
+            .index(Model)
+            .after(now - DAY * 50)
+            .before(now - DAY * 5);
+        let docs = index.top_n::<String>("glarb-garb", 10).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].2, "old");
+
+        // Without filters, undated documents are returned
+        let index = vector_store.index(Model);
+        assert_eq!(index.top_n_ids("glarb-garb", 10).await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_boost() {
+        let vector_store = InMemoryVectorStore::from_documents_with_ids(vec![
+            (
+                "community",
+                serde_json::json!({ "source": "community", "lang": "en" }),
+                OneOrMany::one(Embedding {
+                    document: "community".to_string(),
+                    vec: vec![0.0, 0.1, 0.6],
+                }),
+            ),
+            (
+                "official",
+                serde_json::json!({ "source": "official", "lang": "en" }),
+                OneOrMany::one(Embedding {
+                    document: "official".to_string(),
+                    vec: vec![0.3, 0.2, 0.5],
+                }),
+            ),
+        ]);
+
+        // Without boost, the community document is the closest match
+        let top = vector_store
+            .clone()
+            .index(Model)
+            .top_n_ids("glarb-garb", 1)
+            .await
+            .unwrap();
+        assert_eq!(top[0].1, "community");
+
+        let index = vector_store
+            .clone()
+            .index(Model)
+            .with_metadata_boost("source", "official", 1.5);
+        let top = index.top_n_ids("glarb-garb", 1).await.unwrap();
+        assert_eq!(top[0].1, "official");
+
+        // Boosts compose
+        let index = vector_store
+            .index(Model)
+            .with_metadata_boost("source", "official", 1.5)
+            .with_metadata_boost("lang", "en", 2.0)
+            .with_metadata_boost("lang", "fr", 10.0);
+        let mut scores = index.top_n_ids("glarb-garb", 2).await.unwrap();
+        scores.sort_by(|(_, a), (_, b)| a.cmp(b));
+        assert!((scores[0].0 - 2.0).abs() < 1e-6);
+        assert_eq!(scores[1].1, "official");
+        assert!(scores[1].0 > 2.0);
+
+        // The boosted fields of documents inserted (or replaced) after the boosts were
+        // configured are extracted as well
+        index.store.add_documents_with_ids(vec![(
+            "official",
+            serde_json::json!({ "source": "community", "lang": "de" }),
+            OneOrMany::one(Embedding {
+                document: "official".to_string(),
+                vec: vec![0.3, 0.2, 0.5],
+            }),
+        )]);
+        let top = index.top_n_ids("glarb-garb", 1).await.unwrap();
+        assert_eq!(top[0].1, "community");
+        assert!((top[0].0 - 2.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_distance_metrics() {
+        let vector_store = InMemoryVectorStore::from_documents_with_ids(
+            [
+                ("all", vec![1.0, 1.0, 1.0]),
+                ("binary_match", vec![-1.0, 0.5, 0.2]),
+                ("opposite", vec![1.0, -1.0, -1.0]),
+            ]
+            .map(|(id, vec)| {
+                (
+                    id,
+                    id.to_string(),
+                    OneOrMany::one(Embedding {
+                        document: id.to_string(),
+                        vec,
+                    }),
+                )
+            }),
+        );
+
+        let ranking = |metric| {
+            let index = vector_store.clone().index(Model).metric(metric);
+            async move {
+                let mut results = index.top_n_ids("glarb-garb", 3).await.unwrap();
+                results.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap());
+                results
+            }
+        };
+
+        // The query embeds as [0.0, 0.1, 0.6]
+        let cosine = ranking(DistanceMetric::Cosine).await;
+        assert_eq!(cosine[0].1, "all");
+
+        let hamming = ranking(DistanceMetric::Hamming).await;
+        assert_eq!(
+            hamming,
+            vec![
+                (1.0, "binary_match".to_string()),
+                (1.0 - 1.0 / 3.0, "all".to_string()),
+                (0.0, "opposite".to_string()),
+            ]
+        );
+
+        let manhattan = ranking(DistanceMetric::Manhattan).await;
+        assert_eq!(
+            manhattan
+                .iter()
+                .map(|(_, id)| id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["binary_match", "all", "opposite"]
+        );
+    }
+
+    /// Embedding model embedding "red", "car" and "red car" along distinct axes
+    #[derive(Clone)]
+    struct TokenModel;
+
+    impl EmbeddingModel for TokenModel {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            3
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| {
+                    let vec = match text.as_str() {
+                        "red" => vec![1.0, 0.0, 0.0],
+                        "car" => vec![0.0, 1.0, 0.0],
+                        "red car" => vec![1.0, 1.0, 0.0],
+                        _ => vec![0.0, 0.0, 1.0],
+                    };
+                    Embedding {
+                        document: text,
+                        vec,
+                    }
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_sim() {
+        let embedding = |document: &str, vec: Vec<f64>| Embedding {
+            document: document.to_string(),
+            vec,
+        };
+        let vector_store = InMemoryVectorStore::from_documents_with_ids(vec![
+            // One embedding per token, matching each token of the query
+            (
+                "tokens",
+                "A red car".to_string(),
+                OneOrMany::many(vec![
+                    embedding("A", vec![0.0, 0.0, 1.0]),
+                    embedding("red", vec![1.0, 0.0, 0.0]),
+                    embedding("car", vec![0.0, 1.0, 0.0]),
+                ])
+                .unwrap(),
+            ),
+            // A single embedding close to the whole query
+            (
+                "sentence",
+                "Crimson automobile".to_string(),
+                OneOrMany::one(embedding("Crimson automobile", vec![0.7, 0.7, 0.1])),
+            ),
+        ]);
+
+        let ranking = |index: InMemoryVectorIndex<_, String>| async move {
+            let mut results = index.top_n_ids("red car", 2).await.unwrap();
+            results.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap());
+            results
+        };
+
+        // Single-vector: the best embedding of each document against the query embedding
+        let single = ranking(vector_store.clone().index(TokenModel)).await;
+        assert_eq!(single[0].1, "sentence");
+        assert!((single[0].0 - 1.4 / (2.0_f64.sqrt() * 0.99_f64.sqrt())).abs() < 1e-9);
+        assert!((single[1].0 - 1.0 / 2.0_f64.sqrt()).abs() < 1e-9);
+
+        // Max-sim: sum over the query tokens of their best similarity with the document
+        let max_sim = ranking(vector_store.index(TokenModel).max_sim()).await;
+        assert_eq!(max_sim[0].1, "tokens");
+        assert!((max_sim[0].0 - 2.0).abs() < 1e-9);
+        assert!((max_sim[1].0 - 1.4 / 0.99_f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_recency_decay() {
+        let vector_store = timestamped_store();
+
+        // Equal matches without decay
+        let index = vector_store.clone().index(Model);
+        let scores = index.top_n_ids("glarb-garb", 3).await.unwrap();
+        assert!(scores.iter().all(|(score, _)| *score == scores[0].0));
+
+        let index = vector_store.index(Model).recency_decay(DAY * 10);
+        let top = index.top_n_ids("glarb-garb", 1).await.unwrap();
+        assert_eq!(top[0].1, "recent");
+
+        let mut scores = index.top_n_ids("glarb-garb", 3).await.unwrap();
+        scores.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap());
+        assert_eq!(
+            scores.iter().map(|(_, id)| id.as_str()).collect::<Vec<_>>(),
+            vec!["recent", "old", "ancient"]
+        );
+        // The score of the 10 days old document is about halved
+        assert!((scores[1].0 / scores[0].0 - 0.5_f64.powf(0.9)).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_top_n_full_sort_order() {
+        // Documents with many tied scores
+        let vector_store = InMemoryVectorStore::from_documents_with_ids((0..60).map(|i| {
+            let embedding = Embedding {
+                document: format!("doc{i:02}"),
+                vec: vec![(i % 7) as f64, ((i * 3) % 5) as f64 + 1.0, 0.5],
+            };
+            (format!("doc{i:02}"), i, OneOrMany::one(embedding))
+        }));
+        let index = vector_store.index(Model);
+
+        // Full sort of the scores of every document, by descending score then id
+        let mut sorted = index.top_n_ids("glarb-garb", 1_000).await.unwrap();
+        assert_eq!(sorted.len(), 60);
+        sorted.sort_by(|(a, a_id), (b, b_id)| b.total_cmp(a).then_with(|| a_id.cmp(b_id)));
+
+        for n in [0, 1, 3, 10, 59, 60, 100] {
+            let expected = sorted.iter().take(n).cloned().collect::<Vec<_>>();
+            assert_eq!(index.top_n_ids("glarb-garb", n).await.unwrap(), expected);
+            assert_eq!(
+                index
+                    .top_n::<usize>("glarb-garb", n)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(score, id, _)| (score, id))
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_top_n_explain() {
+        let now = SystemTime::now();
+        let vector_store = InMemoryVectorStore::default();
+        vector_store.add_documents_with_timestamps([
+            (
+                "official",
+                serde_json::json!({ "source": "official", "lang": "en" }),
+                OneOrMany::one(Embedding {
+                    document: "official".to_string(),
+                    vec: vec![0.3, 0.2, 0.5],
+                }),
+                now - DAY * 10,
+            ),
+            (
+                "community",
+                serde_json::json!({ "source": "community", "lang": "fr" }),
+                OneOrMany::one(Embedding {
+                    document: "community".to_string(),
+                    vec: vec![0.0, 0.1, 0.6],
+                }),
+                now,
+            ),
+        ]);
+        vector_store.add_documents_with_ids(vec![(
+            "undated",
+            serde_json::json!({ "source": "community", "lang": "en" }),
+            OneOrMany::one(Embedding {
+                document: "undated".to_string(),
+                vec: vec![0.1, 0.1, 0.5],
+            }),
+        )]);
+
+        let index = vector_store
+            .index(Model)
+            .recency_decay(DAY * 10)
+            .with_metadata_boost("source", "official", 1.5)
+            .with_metadata_boost("lang", "en", 2.0);
+
+        let explained = index
+            .top_n_explain::<serde_json::Value>("glarb-garb", 3)
+            .await
+            .unwrap();
+        assert_eq!(explained.len(), 3);
+
+        // The components of the explanations combine to the final scores, which are the scores
+        // of top_n
+        for (explanation, _, _) in &explained {
+            let factor = explanation
+                .adjustments
+                .iter()
+                .map(|adjustment| adjustment.factor)
+                .product::<f64>();
+            assert!((explanation.similarity * factor - explanation.score).abs() < 1e-9);
+        }
+        let mut scores = index.top_n_ids("glarb-garb", 3).await.unwrap();
+        scores.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        let mut explained_scores = explained
+            .iter()
+            .map(|(explanation, id, _)| (explanation.score, id.clone()))
+            .collect::<Vec<_>>();
+        explained_scores.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        assert_eq!(explained_scores, scores);
+
+        let explanation = |id: &str| {
+            explained
+                .iter()
+                .find(|(_, doc_id, _)| doc_id == id)
+                .map(|(explanation, _, _)| explanation.clone())
+                .unwrap()
+        };
+
+        let official = explanation("official");
+        assert_eq!(
+            official
+                .adjustments
+                .iter()
+                .map(|adjustment| adjustment.name.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "recency_decay",
+                "metadata_boost(source=\"official\")",
+                "metadata_boost(lang=\"en\")"
+            ]
+        );
+        assert!((official.adjustments[0].factor - 0.5).abs() < 1e-6);
+        assert_eq!(official.adjustments[1].factor, 1.5);
+        assert_eq!(official.adjustments[2].factor, 2.0);
+
+        // Documents without a timestamp are not decayed
+        let undated = explanation("undated");
+        assert_eq!(undated.adjustments.len(), 1);
+        assert!((undated.score - undated.similarity * 2.0).abs() < 1e-9);
+
+        // A document neither boosted nor meaningfully decayed keeps its similarity
+        let community = explanation("community");
+        assert_eq!(community.adjustments.len(), 1);
+        assert!((community.score - community.similarity).abs() < 1e-6);
+    }
 }
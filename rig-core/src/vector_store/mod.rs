@@ -1,10 +1,26 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
 use futures::future::BoxFuture;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::embeddings::EmbeddingError;
+use crate::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
 
+pub mod blob;
+pub mod caching_index;
 pub mod in_memory_store;
+pub mod quantized_store;
+pub mod sharded_index;
+
+pub use blob::Blob;
+pub use caching_index::CachingIndex;
+pub use sharded_index::ShardedIndex;
 
 #[derive(Debug, thiserror::Error)]
 pub enum VectorStoreError {
@@ -20,6 +36,83 @@ pub enum VectorStoreError {
 
     #[error("Missing Id: {0}")]
     MissingIdError(String),
+
+    /// A document of a [ShardedIndex] has no shard key
+    #[error("Missing shard key for document: {0}")]
+    MissingShardKeyError(String),
+
+    /// Error restoring a snapshot (e.g.: unsupported format version, mismatched configuration)
+    #[error("Snapshot error: {0}")]
+    SnapshotError(String),
+
+    /// The search was cancelled (see [VectorStoreIndex::top_n_cancellable])
+    #[error("Search cancelled")]
+    Cancelled,
+
+    /// The operation is not supported by the vector store (e.g.: [VectorStoreIndex::reembed])
+    #[error("Unsupported operation: {0}")]
+    UnsupportedError(String),
+}
+
+/// Token cancelling the searches it is passed to (see [VectorStoreIndex::top_n_cancellable]),
+/// e.g.: when the caller gave up on the results. Clones of a token share its state.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the searches using the token (or its clones)
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail with [VectorStoreError::Cancelled] if the token is cancelled
+    pub fn check(&self) -> Result<(), VectorStoreError> {
+        if self.is_cancelled() {
+            Err(VectorStoreError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Components of the score of a search result (see [VectorStoreIndex::top_n_explain]): the final
+/// score is the similarity of the document to the query multiplied by the factors of the
+/// adjustments applied by the index.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScoreExplanation {
+    /// Similarity of the document to the query, as computed by the metric of the index
+    pub similarity: f64,
+    /// Adjustments of the similarity (e.g.: boosts and decays), in order of application
+    pub adjustments: Vec<ScoreAdjustment>,
+    /// Score of the document, by which the results are ranked
+    pub score: f64,
+}
+
+/// Factor applied to the similarity of a document by an index (see [ScoreExplanation])
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScoreAdjustment {
+    /// Name of the adjustment, e.g.: `recency_decay` or `metadata_boost(source="official")`
+    pub name: String,
+    pub factor: f64,
+}
+
+impl ScoreExplanation {
+    /// Explanation of a score without any adjustment
+    pub fn unadjusted(score: f64) -> Self {
+        Self {
+            similarity: score,
+            adjustments: vec![],
+            score,
+        }
+    }
 }
 
 /// Trait for vector store indexes
@@ -38,8 +131,292 @@ pub trait VectorStoreIndex: Send + Sync {
         query: &str,
         n: usize,
     ) -> impl std::future::Future<Output = Result<Vec<(f64, String)>, VectorStoreError>> + Send;
+
+    /// Same as `top_n`, failing with [VectorStoreError::Cancelled] once `cancel` is cancelled.
+    /// By default, the token is checked before and after the search. Exhaustive in-memory
+    /// searches also check it periodically while scanning the documents (see
+    /// [InMemoryVectorIndex](in_memory_store::InMemoryVectorIndex)), so that they return
+    /// promptly.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::vector_store::{CancellationToken, VectorStoreError};
+    ///
+    /// let cancel = CancellationToken::new();
+    /// // e.g.: cancelled by the handler of a closed client connection
+    /// on_disconnect({ let cancel = cancel.clone(); move || cancel.cancel() });
+    ///
+    /// match index.top_n_cancellable::<Document>("What is a flurbo?", 5, &cancel).await {
+    ///     Err(VectorStoreError::Cancelled) => println!("The client gave up"),
+    ///     results => println!("{:?}", results?),
+    /// }
+    /// ```
+    fn top_n_cancellable<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+        cancel: &CancellationToken,
+    ) -> impl std::future::Future<Output = Result<Vec<(f64, String, T)>, VectorStoreError>> + Send
+    {
+        async move {
+            cancel.check()?;
+            let results = self.top_n(query, n).await?;
+            cancel.check()?;
+            Ok(results)
+        }
+    }
+
+    /// Same as `top_n_cancellable` but returns the document ids only.
+    fn top_n_ids_cancellable(
+        &self,
+        query: &str,
+        n: usize,
+        cancel: &CancellationToken,
+    ) -> impl std::future::Future<Output = Result<Vec<(f64, String)>, VectorStoreError>> + Send
+    {
+        async move {
+            cancel.check()?;
+            let results = self.top_n_ids(query, n).await?;
+            cancel.check()?;
+            Ok(results)
+        }
+    }
+
+    /// Same as `top_n` but each result comes with the explanation of its score (the similarity
+    /// of the document to the query and the boosts or decays applied to it), to debug the
+    /// ranking of the results. The result is a list of tuples of the form (explanation, id,
+    /// document).
+    ///
+    /// By default, the scores of `top_n` are returned without adjustments (see
+    /// [ScoreExplanation::unadjusted]).
+    ///
+    /// # Example
+    /// ```rust
+    /// for (explanation, id, _) in index.top_n_explain::<Document>("What is a flurbo?", 5).await? {
+    ///     println!("{id}: {} = {}", explanation.score, explanation.similarity);
+    ///     for adjustment in explanation.adjustments {
+    ///         println!("    x {} ({})", adjustment.factor, adjustment.name);
+    ///     }
+    /// }
+    /// ```
+    fn top_n_explain<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> impl std::future::Future<
+        Output = Result<Vec<(ScoreExplanation, String, T)>, VectorStoreError>,
+    > + Send {
+        async move {
+            Ok(self
+                .top_n::<T>(query, n)
+                .await?
+                .into_iter()
+                .map(|(score, id, doc)| (ScoreExplanation::unadjusted(score), id, doc))
+                .collect())
+        }
+    }
+
+    /// Counter of the modifications of the documents of the index (e.g.: incremented on every
+    /// insert, upsert or delete), or `None` if the index does not track them (the default).
+    /// Used to invalidate cached search results (see [CachingIndex]).
+    fn generation(&self) -> Option<u64> {
+        None
+    }
+
+    /// Re-embed all the documents of the index with `model` (e.g.: after upgrading the
+    /// embedding model) from the texts of their stored embeddings, replacing their vectors and
+    /// the number of dimensions recorded by the store, if any. Stores supporting it replace the
+    /// embeddings transactionally, leaving the documents unchanged on error.
+    ///
+    /// The index should then be queried with `model`, since queries must be embedded by the
+    /// same model as the documents. Fails with [VectorStoreError::UnsupportedError] by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::vector_store::VectorStoreIndex;
+    ///
+    /// vector_store.clone().index(old_model).reembed(&new_model).await?;
+    /// let index = vector_store.index(new_model);
+    /// ```
+    fn reembed<M: EmbeddingModel>(
+        &self,
+        _model: &M,
+    ) -> impl std::future::Future<Output = Result<(), VectorStoreError>> + Send {
+        async {
+            Err(VectorStoreError::UnsupportedError(
+                "re-embedding the documents".to_string(),
+            ))
+        }
+    }
+
+    /// Same as `top_n` but the stored documents are deserialized into `T` one by one, so that
+    /// a document which cannot be deserialized into `T` does not fail the whole query.
+    /// The result is a list of tuples of the form (score, id, document or deserialization error)
+    ///
+    /// # Example
+    /// ```rust
+    /// #[derive(serde::Deserialize)]
+    /// struct Metadata {
+    ///     title: String,
+    /// }
+    ///
+    /// for (score, id, metadata) in index.top_n_lenient::<Metadata>("What is a flurbo?", 5).await? {
+    ///     match metadata {
+    ///         Ok(metadata) => println!("{score} {id}: {}", metadata.title),
+    ///         Err(err) => eprintln!("Skipping {id}: {err}"),
+    ///     }
+    /// }
+    /// ```
+    fn top_n_lenient<T: DeserializeOwned + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> impl std::future::Future<Output = TopNLenientResults<T>> + Send {
+        async move {
+            Ok(self
+                .top_n::<Value>(query, n)
+                .await?
+                .into_iter()
+                .map(|(score, id, doc)| {
+                    (
+                        score,
+                        id,
+                        serde_json::from_value(doc).map_err(VectorStoreError::JsonError),
+                    )
+                })
+                .collect())
+        }
+    }
+
+    /// Same as `top_n` but the results are grouped by parent document: the result is a list
+    /// of tuples of the form (score, parent id, best chunk) for the top n distinct parent
+    /// documents, ranked by the score of their best-scoring chunk.
+    ///
+    /// The parent of a document is read from its `parent_id` field (as set by
+    /// [Chunk](crate::embeddings::Chunk), see
+    /// [EmbeddingsBuilder::documents_split](crate::embeddings::EmbeddingsBuilder::documents_split)).
+    /// Documents without a `parent_id` field are their own parent.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rig::embeddings::Chunk;
+    ///
+    /// for (score, parent_id, chunk) in index.top_n_grouped::<Chunk>("What is a flurbo?", 3).await? {
+    ///     println!("{score} {parent_id} (chunk {}): {}", chunk.chunk_index, chunk.text);
+    /// }
+    /// ```
+    fn top_n_grouped<T: DeserializeOwned + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> impl std::future::Future<Output = Result<Vec<(f64, String, T)>, VectorStoreError>> + Send
+    {
+        async move {
+            // Over-fetch chunks until enough distinct parents are found or the index is exhausted
+            let mut fetched = n.saturating_mul(GROUPED_OVERFETCH);
+            loop {
+                let mut results = self.top_n::<Value>(query, fetched).await?;
+                let exhausted = results.len() < fetched;
+
+                results.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+
+                let mut parents = std::collections::HashSet::new();
+                let grouped = results
+                    .into_iter()
+                    .filter_map(|(score, id, doc)| {
+                        let parent_id = match doc.get("parent_id") {
+                            Some(Value::String(parent_id)) => parent_id.clone(),
+                            Some(parent_id) if !parent_id.is_null() => parent_id.to_string(),
+                            _ => id,
+                        };
+                        parents
+                            .insert(parent_id.clone())
+                            .then_some((score, parent_id, doc))
+                    })
+                    .take(n)
+                    .collect::<Vec<_>>();
+
+                if grouped.len() == n || exhausted {
+                    return grouped
+                        .into_iter()
+                        .map(|(score, parent_id, doc)| {
+                            serde_json::from_value(doc)
+                                .map(|doc| (score, parent_id, doc))
+                                .map_err(VectorStoreError::JsonError)
+                        })
+                        .collect();
+                }
+
+                fetched = fetched.saturating_mul(2);
+            }
+        }
+    }
 }
 
+/// Number of chunks initially fetched per requested parent by [VectorStoreIndex::top_n_grouped]
+const GROUPED_OVERFETCH: usize = 4;
+
+/// Version of the snapshot format written by [VectorStoreSnapshot::snapshot]
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Self-describing point-in-time archive of a vector store (see [VectorStoreSnapshot]),
+/// serialized as JSON.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// Version of the snapshot format (see [SNAPSHOT_FORMAT_VERSION])
+    pub format_version: u32,
+    /// Number of dimensions of the embeddings
+    pub ndims: usize,
+    /// Distance metric of the index (e.g.: `"cosine"`)
+    pub metric: String,
+    pub documents: Vec<SnapshotDocument>,
+}
+
+/// A document of a [Snapshot], with its embeddings
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SnapshotDocument {
+    pub id: String,
+    pub document: Value,
+    pub embeddings: Vec<Embedding>,
+    /// Timestamp of the document, if the store supports it
+    #[serde(default)]
+    pub timestamp: Option<SystemTime>,
+}
+
+/// Trait for vector store indexes supporting point-in-time backups.
+///
+/// This is a separate trait from [VectorStoreIndex] since restoring documents requires them
+/// to be deserializable, which is not required to query an index.
+///
+/// # Example
+/// ```rust
+/// use rig::vector_store::VectorStoreSnapshot;
+///
+/// index.snapshot(std::fs::File::create("backup.json")?).await?;
+///
+/// // Later, into an index with the same configuration
+/// index.restore(std::fs::File::open("backup.json")?).await?;
+/// ```
+pub trait VectorStoreSnapshot: VectorStoreIndex {
+    /// Write a [Snapshot] of all the documents of the index to `writer`.
+    fn snapshot(
+        &self,
+        writer: impl std::io::Write + Send,
+    ) -> impl std::future::Future<Output = Result<(), VectorStoreError>> + Send;
+
+    /// Replace the documents of the index with the ones of the [Snapshot] read from `reader`.
+    /// Fails with [VectorStoreError::SnapshotError], leaving the index unchanged, if the
+    /// format version, number of dimensions or distance metric of the snapshot do not match
+    /// the ones of the index.
+    fn restore(
+        &self,
+        reader: impl std::io::Read + Send,
+    ) -> impl std::future::Future<Output = Result<(), VectorStoreError>> + Send;
+}
+
+pub type TopNLenientResults<T> =
+    Result<Vec<(f64, String, Result<T, VectorStoreError>)>, VectorStoreError>;
+
 pub type TopNResults = Result<Vec<(f64, String, Value)>, VectorStoreError>;
 
 pub trait VectorStoreIndexDyn: Send + Sync {
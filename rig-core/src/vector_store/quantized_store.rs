@@ -0,0 +1,333 @@
+//! In-memory implementation of a vector store holding quantized embeddings
+//! (see [Quantization]).
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+use super::{VectorStoreError, VectorStoreIndex};
+use crate::{
+    embeddings::{
+        quantization::{Quantization, QuantizedEmbedding},
+        Embedding, EmbeddingModel,
+    },
+    OneOrMany,
+};
+
+type QuantizedEmbeddings<D> = HashMap<String, (D, OneOrMany<QuantizedEmbedding>)>;
+
+/// [QuantizedVectorStore] is an in-memory vector store similar to the
+/// [InMemoryVectorStore](super::in_memory_store::InMemoryVectorStore), but which stores
+/// quantized embeddings (e.g.: as generated by
+/// [EmbeddingsBuilder::build_quantized](crate::embeddings::EmbeddingsBuilder::build_quantized))
+/// to reduce its memory usage.
+///
+/// Searches quantize the query embedding with the scheme of each document embedding and
+/// compute the scores in quantized space (see [QuantizedEmbedding::similarity]).
+#[derive(Clone)]
+pub struct QuantizedVectorStore<D: Serialize> {
+    /// The embeddings are stored in a HashMap.
+    /// Hashmap key is the document id.
+    /// Hashmap value is a tuple of the serializable document and its quantized embeddings.
+    embeddings: QuantizedEmbeddings<D>,
+}
+
+impl<D: Serialize> QuantizedVectorStore<D> {
+    /// Create a new [QuantizedVectorStore] from documents and their corresponding quantized
+    /// embeddings with ids.
+    pub fn from_documents_with_ids(
+        documents: impl IntoIterator<Item = (impl ToString, D, OneOrMany<QuantizedEmbedding>)>,
+    ) -> Self {
+        let mut store = Self {
+            embeddings: HashMap::new(),
+        };
+        store.add_documents_with_ids(documents);
+        store
+    }
+
+    /// Add documents and their corresponding quantized embeddings to the store with ids.
+    pub fn add_documents_with_ids(
+        &mut self,
+        documents: impl IntoIterator<Item = (impl ToString, D, OneOrMany<QuantizedEmbedding>)>,
+    ) {
+        documents.into_iter().for_each(|(id, doc, embeddings)| {
+            self.embeddings.insert(id.to_string(), (doc, embeddings));
+        });
+    }
+
+    /// Get the document with the given id, if it exists.
+    pub fn get_document(&self, id: &str) -> Option<&D> {
+        self.embeddings.get(id).map(|(doc, _)| doc)
+    }
+
+    /// Total size in bytes of the quantized vectors of the store (see
+    /// [QuantizedEmbedding::size_bytes]).
+    pub fn size_bytes(&self) -> usize {
+        self.embeddings
+            .values()
+            .flat_map(|(_, embeddings)| embeddings.iter().map(QuantizedEmbedding::size_bytes))
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.embeddings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.embeddings.is_empty()
+    }
+
+    /// Create an index from the store using the given embedding model to embed the queries.
+    pub fn index<M: EmbeddingModel>(self, model: M) -> QuantizedVectorIndex<M, D> {
+        QuantizedVectorIndex { model, store: self }
+    }
+
+    /// Get the top `n` documents by score against `query`, computed in quantized space.
+    fn search(&self, query: &Embedding, n: usize) -> Vec<(f64, &String, &D)> {
+        // Quantize the query once per scheme
+        let int8_query = Quantization::Int8.quantize(query);
+        let binary_query = Quantization::Binary.quantize(query);
+
+        let mut docs = BinaryHeap::new();
+        for (id, (_, embeddings)) in self.embeddings.iter() {
+            // Get the best embedding of the document given the query
+            if let Some(score) = embeddings
+                .iter()
+                .map(|embedding| {
+                    let query = match embedding.quantization() {
+                        Quantization::Int8 => &int8_query,
+                        Quantization::Binary => &binary_query,
+                    };
+                    OrderedFloat(query.similarity(embedding))
+                })
+                .max()
+            {
+                docs.push(Reverse((score, id)));
+            }
+
+            // If the heap size exceeds n, pop the worst document.
+            if docs.len() > n {
+                docs.pop();
+            }
+        }
+
+        docs.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((score, id))| (score.0, id, &self.embeddings[id].0))
+            .collect()
+    }
+}
+
+/// Index of a [QuantizedVectorStore], computing the scores of the documents in quantized space.
+pub struct QuantizedVectorIndex<M: EmbeddingModel, D: Serialize> {
+    model: M,
+    pub store: QuantizedVectorStore<D>,
+}
+
+impl<M: EmbeddingModel + Sync, D: Serialize + Sync + Send> VectorStoreIndex
+    for QuantizedVectorIndex<M, D>
+{
+    async fn top_n<T: for<'a> Deserialize<'a>>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        let query_embedding = self.model.embed_query(query).await?;
+
+        self.store
+            .search(&query_embedding, n)
+            .into_iter()
+            .map(|(score, id, doc)| {
+                Ok((
+                    score,
+                    id.clone(),
+                    serde_json::from_value(serde_json::to_value(doc)?)?,
+                ))
+            })
+            .collect()
+    }
+
+    async fn top_n_ids(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        let query_embedding = self.model.embed_query(query).await?;
+
+        Ok(self
+            .store
+            .search(&query_embedding, n)
+            .into_iter()
+            .map(|(score, id, _)| (score, id.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::QuantizedVectorStore;
+    use crate::{
+        embeddings::{Embedding, EmbeddingError, EmbeddingModel, EmbeddingsBuilder, Quantization},
+        vector_store::{in_memory_store::InMemoryVectorStore, VectorStoreIndex},
+        OneOrMany,
+    };
+
+    const NDIMS: usize = 64;
+
+    /// Embedding model returning pseudo-random embeddings seeded by the hash of the text
+    #[derive(Clone)]
+    struct Model;
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 100;
+
+        fn ndims(&self) -> usize {
+            NDIMS
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| {
+                    let mut state = crate::hash::fnv1a(text.bytes());
+                    let vec = (0..NDIMS)
+                        .map(|_| {
+                            state = state
+                                .wrapping_mul(6364136223846793005)
+                                .wrapping_add(1442695040888963407);
+                            (state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+                        })
+                        .collect();
+                    Embedding {
+                        document: text,
+                        vec,
+                    }
+                })
+                .collect())
+        }
+    }
+
+    fn documents() -> Vec<String> {
+        (0..200).map(|i| format!("doc {i}")).collect()
+    }
+
+    /// Average fraction of the top 10 documents of the float index found in the top `n`
+    /// documents of the quantized index, over 10 queries.
+    async fn recall(quantization: Quantization, n: usize) -> f64 {
+        let embeddings = EmbeddingsBuilder::new(Model)
+            .documents(documents())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let quantized = EmbeddingsBuilder::new(Model)
+            .documents(documents())
+            .unwrap()
+            .build_quantized(quantization)
+            .await
+            .unwrap();
+
+        let float_index = InMemoryVectorStore::from_documents_with_ids(
+            embeddings
+                .into_iter()
+                .map(|(doc, embeddings)| (doc.clone(), doc, embeddings)),
+        )
+        .index(Model);
+        let quantized_index = QuantizedVectorStore::from_documents_with_ids(
+            quantized
+                .into_iter()
+                .map(|(doc, embeddings)| (doc.clone(), doc, embeddings)),
+        )
+        .index(Model);
+
+        let mut total = 0.0;
+        for i in 0..10 {
+            let query = format!("query {i}");
+            let expected = float_index
+                .top_n_ids(&query, 10)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(_, id)| id)
+                .collect::<HashSet<_>>();
+            let found = quantized_index
+                .top_n_ids(&query, n)
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|(_, id)| expected.contains(id))
+                .count();
+            total += found as f64 / 10.0;
+        }
+        total / 10.0
+    }
+
+    #[tokio::test]
+    async fn test_storage_size() {
+        let embeddings = EmbeddingsBuilder::new(Model)
+            .documents(documents())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let float_size = embeddings.len() * NDIMS * std::mem::size_of::<f64>();
+
+        for (quantization, ratio) in [(Quantization::Int8, 7), (Quantization::Binary, 64)] {
+            let store = QuantizedVectorStore::from_documents_with_ids(embeddings.iter().map(
+                |(doc, embeddings)| {
+                    (
+                        doc.clone(),
+                        doc.clone(),
+                        OneOrMany::one(quantization.quantize(&embeddings.first())),
+                    )
+                },
+            ));
+
+            assert_eq!(store.len(), 200);
+            assert!(store.size_bytes() * ratio <= float_size);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_int8_recall() {
+        assert!(recall(Quantization::Int8, 10).await >= 0.95);
+    }
+
+    #[tokio::test]
+    async fn test_binary_recall() {
+        // Binary quantization loses too much precision to rank documents exactly, but the
+        // best documents should be found among a larger set of candidates
+        assert!(recall(Quantization::Binary, 60).await >= 0.8);
+    }
+
+    #[tokio::test]
+    async fn test_top_n() {
+        let store = QuantizedVectorStore::from_documents_with_ids(
+            EmbeddingsBuilder::new(Model)
+                .documents(documents())
+                .unwrap()
+                .build_quantized(Quantization::Int8)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(doc, embeddings)| (doc.clone(), doc, embeddings)),
+        );
+        let index = store.index(Model);
+
+        // A document is its own best match
+        let results = index.top_n::<String>("doc 42", 3).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].1, "doc 42");
+        assert_eq!(results[0].2, "doc 42");
+        assert!(results[0].0 > 0.99);
+        assert!(results.windows(2).all(|pair| pair[0].0 >= pair[1].0));
+    }
+}
@@ -0,0 +1,472 @@
+//! The module defines the [ShardedIndex], an in-memory vector index partitioning its documents
+//! into shards by the value of a metadata key (e.g.: a tenant id).
+use std::{
+    collections::HashMap,
+    sync::{PoisonError, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    in_memory_store::{InMemoryVectorIndex, InMemoryVectorStore},
+    CancellationToken, ScoreExplanation, VectorStoreError, VectorStoreIndex,
+};
+use crate::{
+    embeddings::{distance::DistanceMetric, Embedding, EmbeddingModel},
+    OneOrMany,
+};
+
+/// In-memory vector index routing its documents to a shard (an [InMemoryVectorStore]) chosen by
+/// the value of their (top-level) metadata field `key`, e.g.: `tenant_id`.
+///
+/// Queries of a single shard (see [ShardedIndex::shard]) only search the documents of that
+/// shard, which isolates the documents of the shards (e.g.: of different tenants) from each other
+/// and bounds the cost of the searches. Queries of the [ShardedIndex] itself fan out across all
+/// the shards, the query being embedded only once.
+///
+/// # Example
+/// ```rust
+/// use rig::vector_store::ShardedIndex;
+///
+/// let index = ShardedIndex::new(model, "tenant_id");
+/// index.add_documents_with_ids(documents)?;
+///
+/// // Only the documents of tenant "acme"
+/// let results = index.shard("acme").top_n::<Document>("What is a flurbo?", 3).await?;
+///
+/// // The documents of every tenant
+/// let results = index.top_n::<Document>("What is a flurbo?", 3).await?;
+/// ```
+pub struct ShardedIndex<M: EmbeddingModel, D: Serialize> {
+    model: M,
+    /// Metadata field of the documents holding their shard
+    key: String,
+    /// Metric of the indexes of the shards
+    metric: DistanceMetric,
+    shards: RwLock<HashMap<String, InMemoryVectorStore<D>>>,
+}
+
+impl<M: EmbeddingModel, D: Serialize + Eq> ShardedIndex<M, D> {
+    pub fn new(model: M, key: impl Into<String>) -> Self {
+        Self {
+            model,
+            key: key.into(),
+            metric: DistanceMetric::default(),
+            shards: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Set the metric used to compare the embeddings of the documents to the query (cosine
+    /// similarity by default).
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Add documents with ids and their corresponding embeddings to the shard given by their
+    /// metadata `key`. String values are used as is, other values are serialized to JSON.
+    /// Fails with [VectorStoreError::MissingShardKeyError], without adding any document, if a
+    /// document has no (or a null) `key`.
+    ///
+    /// A document replacing a document of another shard (i.e.: with the same id but another
+    /// `key`, e.g.: moved to another tenant) is removed from that shard.
+    pub fn add_documents_with_ids(
+        &self,
+        documents: impl IntoIterator<Item = (impl ToString, D, OneOrMany<Embedding>)>,
+    ) -> Result<(), VectorStoreError> {
+        // The last document with a given id wins, as when adding documents to a store
+        let mut latest = HashMap::new();
+        for (id, doc, embeddings) in documents {
+            let id = id.to_string();
+            let shard = self.shard_of(&id, &doc)?;
+            latest.insert(id, (shard, doc, embeddings));
+        }
+        let mut routed: HashMap<String, Vec<_>> = HashMap::new();
+        for (id, (shard, doc, embeddings)) in latest {
+            routed.entry(shard).or_default().push((id, doc, embeddings));
+        }
+
+        let mut shards = self.shards.write().unwrap_or_else(PoisonError::into_inner);
+        for (shard, documents) in routed {
+            for (_, store) in shards.iter().filter(|(name, _)| **name != shard) {
+                let moved = {
+                    let stored = store.documents();
+                    documents
+                        .iter()
+                        .filter(|(id, _, _)| stored.contains_key(id))
+                        .map(|(id, _, _)| id.clone())
+                        .collect::<Vec<_>>()
+                };
+                if !moved.is_empty() {
+                    store.remove_documents(moved);
+                }
+            }
+            shards
+                .entry(shard)
+                .or_default()
+                .add_documents_with_ids(documents);
+        }
+        Ok(())
+    }
+
+    /// Index of the documents of `shard`, seeing the documents added to the shard later on.
+    /// Shards are only created when documents are added to them: the index of a shard without
+    /// documents is empty, and does not see the documents added to the shard later on.
+    pub fn shard(&self, shard: &str) -> InMemoryVectorIndex<M, D> {
+        let store = self
+            .shards
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(shard)
+            .cloned()
+            .unwrap_or_default();
+        self.index(store)
+    }
+
+    /// Names of the shards, sorted.
+    pub fn shard_names(&self) -> Vec<String> {
+        let mut names = self
+            .shards
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Total number of documents of the shards.
+    pub fn len(&self) -> usize {
+        self.stores().iter().map(InMemoryVectorStore::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn index(&self, store: InMemoryVectorStore<D>) -> InMemoryVectorIndex<M, D> {
+        InMemoryVectorIndex::new(self.model.clone(), store).metric(self.metric)
+    }
+
+    /// Handles of the stores of the shards, so that the lock of the shards is not held while
+    /// searching them.
+    fn stores(&self) -> Vec<InMemoryVectorStore<D>> {
+        self.shards
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Shard of the document `id`, given by its metadata `key`.
+    fn shard_of(&self, id: &str, doc: &D) -> Result<String, VectorStoreError> {
+        match serde_json::to_value(doc)?.get(&self.key) {
+            Some(Value::String(shard)) => Ok(shard.clone()),
+            Some(shard) if !shard.is_null() => Ok(shard.to_string()),
+            _ => Err(VectorStoreError::MissingShardKeyError(id.to_string())),
+        }
+    }
+}
+
+impl<M: EmbeddingModel + Sync, D: Serialize + Sync + Send + Eq> VectorStoreIndex
+    for ShardedIndex<M, D>
+{
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.top_n_cancellable(query, n, &CancellationToken::new())
+            .await
+    }
+
+    async fn top_n_ids(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        self.top_n_ids_cancellable(query, n, &CancellationToken::new())
+            .await
+    }
+
+    async fn top_n_cancellable<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        let prompt_embedding = [self.model.embed_query(query).await?];
+
+        let mut results = vec![];
+        for store in self.stores() {
+            results.extend(
+                self.index(store)
+                    .search::<T>(&prompt_embedding, n, cancel)?,
+            );
+        }
+        results.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+        results.truncate(n);
+        Ok(results)
+    }
+
+    async fn top_n_ids_cancellable(
+        &self,
+        query: &str,
+        n: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        let prompt_embedding = [self.model.embed_query(query).await?];
+
+        let mut results = vec![];
+        for store in self.stores() {
+            results.extend(self.index(store).search_ids(&prompt_embedding, n, cancel)?);
+        }
+        results.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        results.truncate(n);
+        Ok(results)
+    }
+
+    async fn top_n_explain<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(ScoreExplanation, String, T)>, VectorStoreError> {
+        let prompt_embedding = [self.model.embed_query(query).await?];
+
+        let mut results = vec![];
+        for store in self.stores() {
+            results.extend(
+                self.index(store)
+                    .search_explain::<T>(&prompt_embedding, n)?,
+            );
+        }
+        results.sort_by(|(a, _, _), (b, _, _)| b.score.total_cmp(&a.score));
+        results.truncate(n);
+        Ok(results)
+    }
+
+    fn generation(&self) -> Option<u64> {
+        Some(
+            self.stores()
+                .iter()
+                .map(InMemoryVectorStore::generation)
+                .sum(),
+        )
+    }
+
+    /// Re-embed the documents of each shard in turn (see [InMemoryVectorStore::reembed]): the
+    /// shards re-embedded before an error keep their new embeddings.
+    async fn reembed<N: EmbeddingModel>(&self, model: &N) -> Result<(), VectorStoreError> {
+        for store in self.stores() {
+            store.reembed(model).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::ShardedIndex;
+    use crate::{
+        embeddings::{Embedding, EmbeddingError, EmbeddingModel},
+        vector_store::{VectorStoreError, VectorStoreIndex},
+        OneOrMany,
+    };
+
+    /// Embedding model embedding every text as `[1, 0]`
+    #[derive(Clone)]
+    struct Model;
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 10;
+
+        fn ndims(&self) -> usize {
+            2
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|document| Embedding {
+                    document,
+                    vec: vec![1.0, 0.0],
+                })
+                .collect())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+    struct Doc {
+        tenant_id: String,
+        text: String,
+    }
+
+    fn document(id: &str, tenant_id: &str, vec: Vec<f64>) -> (String, Doc, OneOrMany<Embedding>) {
+        (
+            id.to_string(),
+            Doc {
+                tenant_id: tenant_id.to_string(),
+                text: id.to_string(),
+            },
+            OneOrMany::one(Embedding {
+                document: id.to_string(),
+                vec,
+            }),
+        )
+    }
+
+    fn index() -> ShardedIndex<Model, Doc> {
+        let index = ShardedIndex::new(Model, "tenant_id");
+        index
+            .add_documents_with_ids(vec![
+                document("acme-far", "acme", vec![0.0, 1.0]),
+                document("acme-near", "acme", vec![0.6, 0.8]),
+                document("globex-best", "globex", vec![1.0, 0.0]),
+                document("globex-good", "globex", vec![0.8, 0.6]),
+            ])
+            .unwrap();
+        index
+    }
+
+    #[tokio::test]
+    async fn test_shard_isolation() {
+        let index = index();
+        assert_eq!(index.shard_names(), vec!["acme", "globex"]);
+        assert_eq!(index.len(), 4);
+
+        // The documents of globex are closer to the query but never returned for acme
+        let results = index
+            .shard("acme")
+            .top_n::<Doc>("flurbo", 10)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|(_, _, doc)| doc.tenant_id == "acme"));
+
+        let ids = index.shard("acme").top_n_ids("flurbo", 10).await.unwrap();
+        let mut ids = ids.into_iter().map(|(_, id)| id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec!["acme-far", "acme-near"]);
+
+        // Unknown tenants have no documents, and querying them does not create their shard
+        assert!(index
+            .shard("initech")
+            .top_n::<Doc>("flurbo", 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(index.shard_names(), vec!["acme", "globex"]);
+
+        // Documents added later are routed to their shard
+        let acme = index.shard("acme");
+        index
+            .add_documents_with_ids(vec![document("acme-best", "acme", vec![1.0, 0.0])])
+            .unwrap();
+        let results = acme.top_n_ids("flurbo", 1).await.unwrap();
+        assert_eq!(results[0].1, "acme-best");
+        assert!(index
+            .shard("globex")
+            .top_n_ids("flurbo", 10)
+            .await
+            .unwrap()
+            .iter()
+            .all(|(_, id)| id.starts_with("globex")));
+    }
+
+    #[test]
+    fn test_moved_document() {
+        let index = index();
+
+        // Moving a document to another tenant removes it from its previous shard
+        index
+            .add_documents_with_ids(vec![document("acme-near", "globex", vec![0.6, 0.8])])
+            .unwrap();
+        assert_eq!(index.len(), 4);
+        let ids = |shard: &str| {
+            let mut ids = index
+                .shard(shard)
+                .store
+                .documents()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids("acme"), vec!["acme-far"]);
+        assert_eq!(
+            ids("globex"),
+            vec!["acme-near", "globex-best", "globex-good"]
+        );
+
+        // Within a batch, the last document with a given id wins
+        index
+            .add_documents_with_ids(vec![
+                document("acme-far", "globex", vec![0.0, 1.0]),
+                document("acme-far", "initech", vec![0.0, 1.0]),
+            ])
+            .unwrap();
+        assert_eq!(index.len(), 4);
+        assert!(ids("acme").is_empty());
+        assert_eq!(ids("initech"), vec!["acme-far"]);
+        assert!(!ids("globex").contains(&"acme-far".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_fan_out() {
+        let index = index();
+
+        let ids = index
+            .top_n_ids("flurbo", 3)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["globex-best", "globex-good", "acme-near"]);
+
+        let results = index.top_n::<Doc>("flurbo", 10).await.unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[3].1, "acme-far");
+    }
+
+    #[test]
+    fn test_missing_shard_key() {
+        let index: ShardedIndex<Model, serde_json::Value> = ShardedIndex::new(Model, "tenant_id");
+        let embedding = || {
+            OneOrMany::one(Embedding {
+                document: "doc".to_string(),
+                vec: vec![1.0, 0.0],
+            })
+        };
+
+        let result = index.add_documents_with_ids(vec![
+            (
+                "flurbo",
+                serde_json::json!({ "tenant_id": 42 }),
+                embedding(),
+            ),
+            (
+                "glarb",
+                serde_json::json!({ "text": "Glarbs" }),
+                embedding(),
+            ),
+        ]);
+        assert!(matches!(
+            result,
+            Err(VectorStoreError::MissingShardKeyError(id)) if id == "glarb"
+        ));
+
+        // No document of the batch was added
+        assert!(index.is_empty());
+        assert!(index.shard_names().is_empty());
+    }
+}
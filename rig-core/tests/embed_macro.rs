@@ -215,3 +215,49 @@ fn test_multiple_embed_tags() {
         ]
     );
 }
+
+#[test]
+fn test_weighted_embed() {
+    #[derive(Embed)]
+    struct Article {
+        #[allow(dead_code)]
+        id: String,
+        #[embed(weight = 3)]
+        title: String,
+        #[embed]
+        body: String,
+        #[embed(embed_with = "custom_embedding_function", weight = 2)]
+        tags: Vec<String>,
+    }
+
+    fn custom_embedding_function(
+        embedder: &mut TextEmbedder,
+        tags: Vec<String>,
+    ) -> Result<(), EmbedError> {
+        embedder.embed(tags.join(", "));
+
+        Ok(())
+    }
+
+    let article = Article {
+        id: "doc1".to_string(),
+        title: "Flurbos".to_string(),
+        body: "Flurbos are a made up currency.".to_string(),
+        tags: vec!["fiction".to_string(), "currency".to_string()],
+    };
+
+    let texts = embeddings::to_texts(article).unwrap();
+
+    assert_eq!(texts.iter().filter(|text| *text == "Flurbos").count(), 3);
+    assert_eq!(
+        texts,
+        vec![
+            "Flurbos".to_string(),
+            "Flurbos".to_string(),
+            "Flurbos".to_string(),
+            "Flurbos are a made up currency.".to_string(),
+            "fiction, currency".to_string(),
+            "fiction, currency".to_string(),
+        ]
+    );
+}
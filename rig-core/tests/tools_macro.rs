@@ -0,0 +1,125 @@
+use rig::{completion::ToolDefinition, tools};
+use serde_json::json;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Math error: {0}")]
+struct MathError(String);
+
+struct Calculator {
+    precision: u32,
+}
+
+#[tools]
+impl Calculator {
+    /// Add x and y together
+    #[tool]
+    fn add(&self, x: i32, y: i32) -> Result<i32, MathError> {
+        Ok(x + y)
+    }
+
+    /// Divide x by y, rounding the result
+    /// to the calculator's precision
+    #[tool]
+    async fn divide(&self, x: f64, y: f64, label: Option<&str>) -> Result<String, MathError> {
+        if y == 0.0 {
+            return Err(MathError("Division by zero".to_string()));
+        }
+        let result = format!("{:.*}", self.precision as usize, x / y);
+        Ok(match label {
+            Some(label) => format!("{label}: {result}"),
+            None => result,
+        })
+    }
+
+    #[allow(dead_code)]
+    fn not_a_tool(&self) -> u32 {
+        self.precision
+    }
+}
+
+#[tokio::test]
+async fn test_tools_macro_dispatch() {
+    let toolset = Calculator { precision: 2 }.toolset();
+
+    assert!(toolset.contains("add"));
+    assert!(toolset.contains("divide"));
+    assert!(!toolset.contains("not_a_tool"));
+
+    assert_eq!(
+        toolset
+            .call("add", json!({"x": 2, "y": 3}).to_string())
+            .await
+            .unwrap(),
+        "5"
+    );
+    assert_eq!(
+        toolset
+            .call("divide", json!({"x": 1.0, "y": 3.0}).to_string())
+            .await
+            .unwrap(),
+        "\"0.33\""
+    );
+    assert_eq!(
+        toolset
+            .call(
+                "divide",
+                json!({"x": 1.0, "y": 4.0, "label": "quarter"}).to_string()
+            )
+            .await
+            .unwrap(),
+        "\"quarter: 0.25\""
+    );
+    assert!(toolset
+        .call("divide", json!({"x": 1.0, "y": 0.0}).to_string())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_tools_macro_definitions() {
+    let toolset = Calculator { precision: 2 }.toolset();
+
+    let mut definitions = toolset
+        .documents()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|document| {
+            let definition = document.text.split_once("Definition: \n").unwrap().1;
+            serde_json::from_str::<ToolDefinition>(definition).unwrap()
+        })
+        .collect::<Vec<_>>();
+    definitions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    assert_eq!(
+        definitions,
+        vec![
+            ToolDefinition {
+                name: "add".to_string(),
+                description: "Add x and y together".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "x": { "type": "integer" },
+                        "y": { "type": "integer" }
+                    },
+                    "required": ["x", "y"]
+                }),
+            },
+            ToolDefinition {
+                name: "divide".to_string(),
+                description: "Divide x by y, rounding the result\nto the calculator's precision"
+                    .to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "x": { "type": "number" },
+                        "y": { "type": "number" },
+                        "label": { "type": "string" }
+                    },
+                    "required": ["x", "y"]
+                }),
+            },
+        ]
+    );
+}
@@ -225,7 +225,7 @@ impl<M: EmbeddingModel + std::marker::Sync + Send> VectorStoreIndex for Neo4jVec
         query: &str,
         n: usize,
     ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
-        let prompt_embedding = self.embedding_model.embed_text(query).await?;
+        let prompt_embedding = self.embedding_model.embed_query(query).await?;
         let query = self.build_vector_search_query(prompt_embedding, true, n);
 
         let rows = Neo4jClient::execute_and_collect::<RowResultNode<T>>(&self.graph, query).await?;
@@ -245,7 +245,7 @@ impl<M: EmbeddingModel + std::marker::Sync + Send> VectorStoreIndex for Neo4jVec
         query: &str,
         n: usize,
     ) -> Result<Vec<(f64, String)>, VectorStoreError> {
-        let prompt_embedding = self.embedding_model.embed_text(query).await?;
+        let prompt_embedding = self.embedding_model.embed_query(query).await?;
 
         let query = self.build_vector_search_query(prompt_embedding, false, n);
 
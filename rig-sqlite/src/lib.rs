@@ -1,6 +1,7 @@
 use rig::embeddings::{Embedding, EmbeddingModel};
-use rig::vector_store::{VectorStoreError, VectorStoreIndex};
+use rig::vector_store::{Blob, VectorStoreError, VectorStoreIndex};
 use rig::OneOrMany;
+use rusqlite::types::{Value, ValueRef};
 use serde::Deserialize;
 use std::marker::PhantomData;
 use tokio_rusqlite::Connection;
@@ -17,6 +18,12 @@ pub enum SqliteError {
 pub trait ColumnValue: Send + Sync {
     fn to_sql_string(&self) -> String;
     fn column_type(&self) -> &'static str;
+
+    /// Value bound to the column when inserting the row. Defaults to the text of
+    /// [ColumnValue::to_sql_string], overridden by binary values (e.g.: [Blob]) to be stored as is
+    fn to_sql_value(&self) -> Value {
+        Value::Text(self.to_sql_string())
+    }
 }
 
 pub struct Column {
@@ -84,9 +91,14 @@ pub trait SqliteVectorStoreTable: Send + Sync + Clone {
     fn column_values(&self) -> Vec<(&'static str, Box<dyn ColumnValue>)>;
 }
 
+/// Default maximum size of the blobs of the documents, in bytes: the default maximum size of a
+/// value in SQLite (`SQLITE_MAX_LENGTH`)
+pub const DEFAULT_MAX_BLOB_SIZE: usize = 1_000_000_000;
+
 #[derive(Clone)]
 pub struct SqliteVectorStore<E: EmbeddingModel + 'static, T: SqliteVectorStoreTable + 'static> {
     conn: Connection,
+    max_blob_size: usize,
     _phantom: PhantomData<(E, T)>,
 }
 
@@ -152,10 +164,21 @@ impl<E: EmbeddingModel + 'static, T: SqliteVectorStoreTable + 'static> SqliteVec
 
         Ok(Self {
             conn,
+            max_blob_size: DEFAULT_MAX_BLOB_SIZE,
             _phantom: PhantomData,
         })
     }
 
+    /// Set the maximum size (in bytes) of the blobs of the documents (see [Blob]). Documents with
+    /// larger blobs are rejected before being inserted, failing the whole batch of rows, instead
+    /// of being truncated or failing deep inside SQLite. Since the blobs are returned with the
+    /// documents by [VectorStoreIndex::top_n], large blobs also make queries slower: use
+    /// [VectorStoreIndex::top_n_ids] to query the ids only.
+    pub fn max_blob_size(mut self, max_blob_size: usize) -> Self {
+        self.max_blob_size = max_blob_size;
+        self
+    }
+
     pub fn index(self, model: E) -> SqliteVectorIndex<E, T> {
         SqliteVectorIndex::new(model, self)
     }
@@ -174,6 +197,25 @@ impl<E: EmbeddingModel + 'static, T: SqliteVectorStoreTable + 'static> SqliteVec
 
             let values = doc.column_values();
             let columns = values.iter().map(|(col, _)| *col).collect::<Vec<_>>();
+            let values = values
+                .iter()
+                .map(|(col, val)| match val.to_sql_value() {
+                    Value::Blob(blob) if blob.len() > self.max_blob_size => {
+                        Err(rusqlite::Error::ToSqlConversionFailure(
+                            format!(
+                                "Blob of column `{}` of document {} is too large: {} bytes, \
+                                more than the maximum of {} bytes",
+                                col,
+                                doc.id(),
+                                blob.len(),
+                                self.max_blob_size
+                            )
+                            .into(),
+                        ))
+                    }
+                    value => Ok(value),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
 
             let placeholders = (1..=values.len())
                 .map(|i| format!("?{}", i))
@@ -186,10 +228,7 @@ impl<E: EmbeddingModel + 'static, T: SqliteVectorStoreTable + 'static> SqliteVec
                 placeholders.join(", ")
             );
 
-            txn.execute(
-                &insert_sql,
-                rusqlite::params_from_iter(values.iter().map(|(_, val)| val.to_sql_string())),
-            )?;
+            txn.execute(&insert_sql, rusqlite::params_from_iter(values))?;
             last_id = txn.last_insert_rowid();
 
             let embeddings_sql = format!(
@@ -338,7 +377,7 @@ impl<E: EmbeddingModel + std::marker::Sync, T: SqliteVectorStoreTable> VectorSto
         n: usize,
     ) -> Result<Vec<(f64, String, D)>, VectorStoreError> {
         debug!("Finding top {} matches for query", n);
-        let embedding = self.embedding_model.embed_text(query).await?;
+        let embedding = self.embedding_model.embed_query(query).await?;
         let query_vec: Vec<f32> = serialize_embedding(&embedding);
         let table_name = T::name();
 
@@ -366,8 +405,7 @@ impl<E: EmbeddingModel + std::marker::Sync, T: SqliteVectorStoreTable> VectorSto
                         // Create a map of column names to values
                         let mut map = serde_json::Map::new();
                         for (i, col_name) in column_names.iter().enumerate() {
-                            let value: String = row.get(i)?;
-                            map.insert(col_name.to_string(), serde_json::Value::String(value));
+                            map.insert(col_name.to_string(), json_value(row.get_ref(i)?));
                         }
                         let distance: f64 = row.get(column_names.len())?;
                         let id: String = row.get(0)?; // Assuming id is always first column
@@ -404,7 +442,7 @@ impl<E: EmbeddingModel + std::marker::Sync, T: SqliteVectorStoreTable> VectorSto
         n: usize,
     ) -> Result<Vec<(f64, String)>, VectorStoreError> {
         debug!("Finding top {} document IDs for query", n);
-        let embedding = self.embedding_model.embed_text(query).await?;
+        let embedding = self.embedding_model.embed_query(query).await?;
         let query_vec = serialize_embedding(&embedding);
         let table_name = T::name();
 
@@ -447,6 +485,20 @@ fn serialize_embedding(embedding: &Embedding) -> Vec<f32> {
     embedding.vec.iter().map(|x| *x as f32).collect()
 }
 
+/// JSON value of a column, from which the documents are deserialized. Blobs are converted to
+/// their serialization as a [Blob] (i.e.: base64 strings).
+fn json_value(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned().into(),
+        ValueRef::Blob(blob) => {
+            serde_json::to_value(Blob::new(blob)).unwrap_or(serde_json::Value::Null)
+        }
+    }
+}
+
 impl ColumnValue for String {
     fn to_sql_string(&self) -> String {
         self.clone()
@@ -457,6 +509,26 @@ impl ColumnValue for String {
     }
 }
 
+/// Blobs are stored as is in `BLOB` columns (see [SqliteVectorStore::max_blob_size]). Optional
+/// blobs are stored by omitting their column from the [SqliteVectorStoreTable::column_values]
+/// of the documents without a blob, which is then `NULL`.
+impl ColumnValue for Blob {
+    fn to_sql_string(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    fn column_type(&self) -> &'static str {
+        "BLOB"
+    }
+
+    fn to_sql_value(&self) -> Value {
+        Value::Blob(self.0.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -554,4 +626,128 @@ mod tests {
 
         Ok(())
     }
+
+    /// Embedding model embedding every text to the same vector
+    #[derive(Clone)]
+    struct Model;
+
+    impl EmbeddingModel for Model {
+        const MAX_DOCUMENTS: usize = 5;
+
+        fn ndims(&self) -> usize {
+            3
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, rig::embeddings::EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| Embedding {
+                    document: text,
+                    vec: vec![0.0, 0.1, 0.6],
+                })
+                .collect())
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, PartialEq)]
+    struct Picture {
+        id: String,
+        caption: String,
+        image: Option<Blob>,
+    }
+
+    impl SqliteVectorStoreTable for Picture {
+        fn name() -> &'static str {
+            "pictures"
+        }
+
+        fn schema() -> Vec<Column> {
+            vec![
+                Column::new("id", "TEXT PRIMARY KEY"),
+                Column::new("caption", "TEXT"),
+                Column::new("image", "BLOB"),
+            ]
+        }
+
+        fn id(&self) -> String {
+            self.id.clone()
+        }
+
+        fn column_values(&self) -> Vec<(&'static str, Box<dyn ColumnValue>)> {
+            let mut values: Vec<(&'static str, Box<dyn ColumnValue>)> = vec![
+                ("id", Box::new(self.id.clone())),
+                ("caption", Box::new(self.caption.clone())),
+            ];
+            if let Some(image) = &self.image {
+                values.push(("image", Box::new(image.clone())));
+            }
+            values
+        }
+    }
+
+    fn embedded(picture: Picture) -> (Picture, OneOrMany<Embedding>) {
+        let embedding = Embedding {
+            document: picture.caption.clone(),
+            vec: vec![0.0, 0.1, 0.6],
+        };
+        (picture, OneOrMany::one(embedding))
+    }
+
+    #[tokio::test]
+    async fn test_blob_payload() -> Result<(), anyhow::Error> {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_vec_init as *const ())));
+        }
+        let conn = Connection::open(":memory:").await?;
+
+        let picture = Picture {
+            id: "cat".to_string(),
+            caption: "A cat on a sofa".to_string(),
+            image: Some(Blob::new(vec![0x89, b'P', b'N', b'G', 0, 255])),
+        };
+        let vector_store = SqliteVectorStore::new(conn, &Model).await?.max_blob_size(6);
+        vector_store
+            .add_rows(vec![embedded(picture.clone())])
+            .await?;
+
+        let results = vector_store
+            .clone()
+            .index(Model)
+            .top_n::<Picture>("A cat", 1)
+            .await?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].2, picture);
+
+        // Documents without a blob are stored with a NULL column
+        let caption_only = Picture {
+            id: "dog".to_string(),
+            caption: "A dog".to_string(),
+            image: None,
+        };
+        vector_store
+            .add_rows(vec![embedded(caption_only.clone())])
+            .await?;
+        let results = vector_store
+            .clone()
+            .index(Model)
+            .top_n::<Picture>("A dog", 2)
+            .await?;
+        assert!(results.iter().any(|(_, _, doc)| *doc == caption_only));
+
+        // Blobs larger than the maximum size are rejected
+        let too_large = Picture {
+            id: "large".to_string(),
+            caption: "A large picture".to_string(),
+            image: Some(Blob::new(vec![0; 7])),
+        };
+        assert!(vector_store
+            .add_rows(vec![embedded(too_large)])
+            .await
+            .is_err());
+
+        Ok(())
+    }
 }